@@ -0,0 +1,66 @@
+//! 流式响应 SSE 编码基准测试
+//!
+//! 对比两种增量事件的构建 + 编码路径：
+//! - `json_format`：优化前的方式，每个增量构建 `serde_json::Value`，再格式化为 `String`
+//! - `typed_delta`：`SseEvent::delta` 直接写出 JSON，由 `SseEncoder` 复用缓冲区
+//!
+//! ```bash
+//! cargo bench --bench streaming
+//! ```
+
+use std::hint::black_box;
+
+use bytes::Bytes;
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use serde_json::json;
+
+use kiro_rs::anthropic::sse::{DeltaKind, SseEncoder, SseEvent};
+
+/// 模拟一次长回复的文本增量（中英文混合，每个增量十余个字符）
+fn text_chunks(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|i| format!("token {} 流式输出\n", i))
+        .collect()
+}
+
+/// 优化前的编码方式：构建 JSON 值后格式化为 String 再转换为 Bytes
+fn json_format(index: i32, text: &str) -> Bytes {
+    let data = json!({
+        "type": "content_block_delta",
+        "index": index,
+        "delta": {"type": "text_delta", "text": text}
+    });
+    Bytes::from(format!(
+        "event: {}\ndata: {}\n\n",
+        "content_block_delta",
+        serde_json::to_string(&data).unwrap_or_default()
+    ))
+}
+
+fn bench_text_delta(c: &mut Criterion) {
+    let chunks = text_chunks(1000);
+    let mut group = c.benchmark_group("text_delta");
+    group.throughput(Throughput::Elements(chunks.len() as u64));
+
+    group.bench_function("json_format", |b| {
+        b.iter(|| {
+            for chunk in &chunks {
+                black_box(json_format(0, chunk));
+            }
+        })
+    });
+    group.bench_function("typed_delta", |b| {
+        b.iter(|| {
+            let mut encoder = SseEncoder::default();
+            for chunk in &chunks {
+                let event = SseEvent::delta(0, DeltaKind::Text, chunk.as_str());
+                black_box(encoder.encode(&event));
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_text_delta);
+criterion_main!(benches);
@@ -0,0 +1,90 @@
+//! 批量刷新凭据余额
+//!
+//! 凭据列表接口只返回数据库中缓存的余额；需要最新余额时通过该任务以有限并发批量查询上游，
+//! 在后台进行，通过任务 ID 轮询进度
+
+use chrono::Utc;
+
+use super::jobs::{Job, JobTable};
+use super::types::{BalanceRefreshFailure, BalanceRefreshJob, BalanceRefreshState};
+
+impl Job for BalanceRefreshJob {
+    fn finished_at(&self) -> Option<&str> {
+        self.finished_at.as_deref()
+    }
+}
+
+/// 批量刷新余额任务表（仅内存）
+#[derive(Default)]
+pub struct BalanceRefreshJobs {
+    jobs: JobTable<BalanceRefreshJob>,
+}
+
+impl BalanceRefreshJobs {
+    /// 创建刷新任务（同时清理过期的已结束任务）
+    pub fn create(&self, total: usize) -> BalanceRefreshJob {
+        self.jobs.create(|id| BalanceRefreshJob {
+            id,
+            state: BalanceRefreshState::Running,
+            total,
+            succeeded: 0,
+            failed: 0,
+            failures: Vec::new(),
+            started_at: Utc::now().to_rfc3339(),
+            finished_at: None,
+        })
+    }
+
+    /// 记录单个凭据的刷新结果
+    pub fn record(&self, id: u64, credential_id: u64, result: Result<(), String>) {
+        self.jobs.update(id, |job| match result {
+            Ok(()) => job.succeeded += 1,
+            Err(error) => {
+                job.failed += 1;
+                job.failures.push(BalanceRefreshFailure {
+                    credential_id,
+                    error,
+                });
+            }
+        });
+    }
+
+    /// 结束刷新任务
+    pub fn finish(&self, id: u64) {
+        self.jobs.update(id, |job| {
+            job.state = BalanceRefreshState::Completed;
+            job.finished_at = Some(Utc::now().to_rfc3339());
+        });
+    }
+
+    /// 获取刷新任务
+    pub fn get(&self, id: u64) -> Option<BalanceRefreshJob> {
+        self.jobs.get(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_progress() {
+        let jobs = BalanceRefreshJobs::default();
+        let job = jobs.create(3);
+        assert_eq!(job.state, BalanceRefreshState::Running);
+
+        jobs.record(job.id, 1, Ok(()));
+        jobs.record(job.id, 2, Err("凭据已失效".to_string()));
+        let running = jobs.get(job.id).unwrap();
+        assert_eq!((running.succeeded, running.failed), (1, 1));
+        assert_eq!(running.failures[0].credential_id, 2);
+        assert!(running.finished_at.is_none());
+
+        jobs.record(job.id, 3, Ok(()));
+        jobs.finish(job.id);
+        let finished = jobs.get(job.id).unwrap();
+        assert_eq!(finished.state, BalanceRefreshState::Completed);
+        assert_eq!(finished.succeeded, 2);
+        assert!(jobs.get(999).is_none());
+    }
+}
@@ -0,0 +1,256 @@
+//! Admin API 类型化客户端（`client` feature）
+//!
+//! 复用 [`super::types`] 中的请求/响应类型，供 Rust 编写的自动化工具管理凭据
+//!
+//! ```ignore
+//! let client = AdminClient::new("http://127.0.0.1:8990/api/admin", "your-admin-api-key");
+//! for cred in client.list_credentials().await?.credentials {
+//!     if cred.failure_count > 0 {
+//!         client.reset_failure_count(cred.id).await?;
+//!     }
+//! }
+//! ```
+
+use std::fmt;
+
+use reqwest::{Method, StatusCode};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use super::types::{
+    AddCredentialRequest, AddCredentialResponse, AdminErrorResponse, BalanceResponse,
+    BulkCredentialUpdate, CredentialsStatusResponse, SetDisabledRequest, SetPriorityRequest,
+    SuccessResponse,
+};
+
+/// Admin API 客户端错误
+#[derive(Debug)]
+pub enum ClientError {
+    /// 网络错误或响应体无法解析
+    Http(reqwest::Error),
+
+    /// Admin API 返回的错误响应
+    Api {
+        status: StatusCode,
+        response: AdminErrorResponse,
+    },
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "Admin API 请求失败: {}", e),
+            ClientError::Api { status, response } => write!(
+                f,
+                "Admin API 返回错误 {}（{}）: {}",
+                status, response.error.error_type, response.error.message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Http(e) => Some(e),
+            ClientError::Api { .. } => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+/// Admin API 客户端
+#[derive(Debug, Clone)]
+pub struct AdminClient {
+    http: reqwest::Client,
+    /// Admin API 根地址（如 `http://127.0.0.1:8990/api/admin`）
+    base_url: String,
+    /// Admin API Key 或受限 Admin Token
+    api_key: String,
+}
+
+impl AdminClient {
+    /// 创建客户端
+    ///
+    /// `base_url` 为 Admin API 根地址（包含 `adminPath`，如 `http://127.0.0.1:8990/api/admin`）
+    pub fn new(base_url: impl Into<String>, api_key: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: api_key.into(),
+        }
+    }
+
+    /// 使用自定义的 HTTP 客户端（代理、超时、TLS 等）
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// 获取所有凭据状态
+    pub async fn list_credentials(&self) -> Result<CredentialsStatusResponse, ClientError> {
+        self.send(Method::GET, "/credentials", None::<&()>).await
+    }
+
+    /// 添加凭据
+    pub async fn add_credential(
+        &self,
+        request: &AddCredentialRequest,
+    ) -> Result<AddCredentialResponse, ClientError> {
+        self.send(Method::POST, "/credentials", Some(request)).await
+    }
+
+    /// 删除凭据（不等待进行中的请求）
+    pub async fn delete_credential(&self, id: u64) -> Result<SuccessResponse, ClientError> {
+        self.send(Method::DELETE, &format!("/credentials/{}", id), None::<&()>)
+            .await
+    }
+
+    /// 启用/禁用凭据（不等待进行中的请求）
+    pub async fn set_disabled(
+        &self,
+        id: u64,
+        disabled: bool,
+    ) -> Result<SuccessResponse, ClientError> {
+        let request = SetDisabledRequest {
+            disabled,
+            drain: false,
+            timeout_secs: None,
+        };
+        self.send(
+            Method::POST,
+            &format!("/credentials/{}/disabled", id),
+            Some(&request),
+        )
+        .await
+    }
+
+    /// 设置凭据优先级
+    pub async fn set_priority(
+        &self,
+        id: u64,
+        priority: u32,
+    ) -> Result<SuccessResponse, ClientError> {
+        self.send(
+            Method::POST,
+            &format!("/credentials/{}/priority", id),
+            Some(&SetPriorityRequest { priority }),
+        )
+        .await
+    }
+
+    /// 批量设置凭据的禁用状态与优先级（单个事务）
+    pub async fn bulk_update(
+        &self,
+        updates: &[BulkCredentialUpdate],
+    ) -> Result<SuccessResponse, ClientError> {
+        self.send(Method::POST, "/credentials/bulk", Some(updates))
+            .await
+    }
+
+    /// 重置失败计数并重新启用凭据
+    pub async fn reset_failure_count(&self, id: u64) -> Result<SuccessResponse, ClientError> {
+        self.send(
+            Method::POST,
+            &format!("/credentials/{}/reset", id),
+            None::<&()>,
+        )
+        .await
+    }
+
+    /// 查询凭据余额
+    pub async fn get_balance(&self, id: u64) -> Result<BalanceResponse, ClientError> {
+        self.send(
+            Method::GET,
+            &format!("/credentials/{}/balance", id),
+            None::<&()>,
+        )
+        .await
+    }
+
+    /// 发送请求并解析响应（非 2xx 状态码解析为 [`ClientError::Api`]）
+    async fn send<B, T>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> Result<T, ClientError>
+    where
+        B: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        let mut request = self
+            .http
+            .request(method, format!("{}{}", self.base_url, path))
+            .header("x-api-key", &self.api_key);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response.json().await?);
+        }
+        Err(ClientError::Api {
+            status,
+            response: response.json().await?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::{AdminService, AdminState, create_admin_router};
+    use crate::kiro::db::Database;
+    use crate::kiro::token_manager::MultiTokenManager;
+    use crate::model::config::Config;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_admin_client() {
+        let manager =
+            MultiTokenManager::new(Config::default(), Database::open_in_memory().unwrap(), None)
+                .unwrap();
+        let state = AdminState::new("admin-key", AdminService::new(Arc::new(manager)));
+        let app = axum::Router::new().nest("/api/admin", create_admin_router(state));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/api/admin", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = AdminClient::new(&base_url, "admin-key");
+        let added = client
+            .add_credential(&AddCredentialRequest {
+                refresh_token: "r".repeat(120),
+                auth_method: Some("social".to_string()),
+                priority: Some(3),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        client.set_disabled(added.id, true).await.unwrap();
+
+        let credentials = client.list_credentials().await.unwrap();
+        assert_eq!(credentials.total, 1);
+        let cred = &credentials.credentials[0];
+        assert_eq!((cred.id, cred.priority, cred.disabled), (added.id, 3, true));
+
+        let Err(ClientError::Api { status, response }) = client.delete_credential(999).await else {
+            panic!("不存在的凭据应返回 API 错误");
+        };
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(response.error.message.contains("999"));
+
+        let unauthorized = AdminClient::new(&base_url, "wrong-key");
+        let Err(ClientError::Api { status, .. }) = unauthorized.list_credentials().await else {
+            panic!("错误的 Admin API Key 应返回 API 错误");
+        };
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,81 @@
+//! 凭据连接排空
+//!
+//! 删除或禁用凭据时可以先禁用凭据阻止新请求，等待其进行中的上游连接结束后再执行操作，
+//! 避免直接中断正在输出的流式响应。排空在后台进行，通过任务 ID 轮询进度
+
+use chrono::Utc;
+
+use crate::kiro::connections;
+
+use super::jobs::{Job, JobTable};
+use super::types::{DrainAction, DrainJob, DrainState};
+
+impl Job for DrainJob {
+    fn finished_at(&self) -> Option<&str> {
+        self.finished_at.as_deref()
+    }
+}
+
+/// 排空任务表（仅内存）
+#[derive(Default)]
+pub struct DrainJobs {
+    jobs: JobTable<DrainJob>,
+}
+
+impl DrainJobs {
+    /// 创建排空任务（同时清理过期的已结束任务）
+    pub fn create(&self, credential_id: u64, action: DrainAction) -> DrainJob {
+        self.jobs.create(|id| DrainJob {
+            id,
+            credential_id,
+            action,
+            state: DrainState::Draining,
+            in_flight: connections::in_flight(credential_id),
+            started_at: Utc::now().to_rfc3339(),
+            finished_at: None,
+            error: None,
+        })
+    }
+
+    /// 结束排空任务
+    pub fn finish(&self, id: u64, state: DrainState, error: Option<String>) {
+        self.jobs.update(id, |job| {
+            job.state = state;
+            job.in_flight = connections::in_flight(job.credential_id);
+            job.finished_at = Some(Utc::now().to_rfc3339());
+            job.error = error;
+        });
+    }
+
+    /// 获取排空任务（进行中的任务实时读取连接数）
+    pub fn get(&self, id: u64) -> Option<DrainJob> {
+        let mut job = self.jobs.get(id)?;
+        if job.state == DrainState::Draining {
+            job.in_flight = connections::in_flight(job.credential_id);
+        }
+        Some(job)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_lifecycle() {
+        let jobs = DrainJobs::default();
+        let job = jobs.create(42, DrainAction::Delete);
+        assert_eq!(job.state, DrainState::Draining);
+        assert_eq!(job.in_flight, 0);
+
+        jobs.finish(job.id, DrainState::TimedOut, None);
+        let finished = jobs.get(job.id).unwrap();
+        assert_eq!(finished.state, DrainState::TimedOut);
+        assert!(finished.finished_at.is_some());
+
+        let next = jobs.create(43, DrainAction::Disable);
+        assert_ne!(next.id, job.id);
+        assert!(jobs.get(job.id).is_some());
+        assert!(jobs.get(999).is_none());
+    }
+}
@@ -12,6 +12,27 @@ pub enum AdminServiceError {
     /// 凭据不存在
     NotFound { id: u64 },
 
+    /// 提示词模板不存在
+    PromptTemplateNotFound { name: String },
+
+    /// 客户端 API Key 不存在
+    ApiKeyNotFound { id: u64 },
+
+    /// 受限 Admin Token 不存在
+    AdminTokenNotFound { id: u64 },
+
+    /// 告警通知不存在
+    NotificationNotFound { id: u64 },
+
+    /// 排空任务不存在
+    DrainJobNotFound { id: u64 },
+
+    /// 批量刷新余额任务不存在
+    BalanceRefreshJobNotFound { id: u64 },
+
+    /// 设备授权登录任务不存在
+    LoginJobNotFound { id: u64 },
+
     /// 请求参数无效
     InvalidRequest(String),
 
@@ -28,6 +49,27 @@ impl fmt::Display for AdminServiceError {
             AdminServiceError::NotFound { id } => {
                 write!(f, "凭据不存在: {}", id)
             }
+            AdminServiceError::PromptTemplateNotFound { name } => {
+                write!(f, "提示词模板不存在: {}", name)
+            }
+            AdminServiceError::ApiKeyNotFound { id } => {
+                write!(f, "客户端 API Key 不存在: {}", id)
+            }
+            AdminServiceError::AdminTokenNotFound { id } => {
+                write!(f, "Admin Token 不存在: {}", id)
+            }
+            AdminServiceError::NotificationNotFound { id } => {
+                write!(f, "告警通知不存在: {}", id)
+            }
+            AdminServiceError::DrainJobNotFound { id } => {
+                write!(f, "排空任务不存在: {}", id)
+            }
+            AdminServiceError::BalanceRefreshJobNotFound { id } => {
+                write!(f, "批量刷新余额任务不存在: {}", id)
+            }
+            AdminServiceError::LoginJobNotFound { id } => {
+                write!(f, "登录任务不存在: {}", id)
+            }
             AdminServiceError::InvalidRequest(msg) => write!(f, "请求参数无效: {}", msg),
             AdminServiceError::UpstreamError(msg) => write!(f, "上游服务错误: {}", msg),
             AdminServiceError::InternalError(msg) => write!(f, "内部错误: {}", msg),
@@ -41,7 +83,14 @@ impl AdminServiceError {
     /// 获取对应的 HTTP 状态码
     pub fn status_code(&self) -> StatusCode {
         match self {
-            AdminServiceError::NotFound { .. } => StatusCode::NOT_FOUND,
+            AdminServiceError::NotFound { .. }
+            | AdminServiceError::PromptTemplateNotFound { .. }
+            | AdminServiceError::ApiKeyNotFound { .. }
+            | AdminServiceError::AdminTokenNotFound { .. }
+            | AdminServiceError::NotificationNotFound { .. }
+            | AdminServiceError::DrainJobNotFound { .. }
+            | AdminServiceError::BalanceRefreshJobNotFound { .. }
+            | AdminServiceError::LoginJobNotFound { .. } => StatusCode::NOT_FOUND,
             AdminServiceError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
             AdminServiceError::UpstreamError(_) => StatusCode::BAD_GATEWAY,
             AdminServiceError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
@@ -54,6 +103,27 @@ impl AdminServiceError {
             AdminServiceError::NotFound { id } => {
                 AdminErrorResponse::not_found(format!("凭据不存在: {}", id))
             }
+            AdminServiceError::PromptTemplateNotFound { name } => {
+                AdminErrorResponse::not_found(format!("提示词模板不存在: {}", name))
+            }
+            AdminServiceError::ApiKeyNotFound { id } => {
+                AdminErrorResponse::not_found(format!("客户端 API Key 不存在: {}", id))
+            }
+            AdminServiceError::AdminTokenNotFound { id } => {
+                AdminErrorResponse::not_found(format!("Admin Token 不存在: {}", id))
+            }
+            AdminServiceError::NotificationNotFound { id } => {
+                AdminErrorResponse::not_found(format!("告警通知不存在: {}", id))
+            }
+            AdminServiceError::DrainJobNotFound { id } => {
+                AdminErrorResponse::not_found(format!("排空任务不存在: {}", id))
+            }
+            AdminServiceError::BalanceRefreshJobNotFound { id } => {
+                AdminErrorResponse::not_found(format!("批量刷新余额任务不存在: {}", id))
+            }
+            AdminServiceError::LoginJobNotFound { id } => {
+                AdminErrorResponse::not_found(format!("登录任务不存在: {}", id))
+            }
             AdminServiceError::InvalidRequest(msg) => AdminErrorResponse::invalid_request(msg),
             AdminServiceError::UpstreamError(msg) => AdminErrorResponse::api_error(msg),
             AdminServiceError::InternalError(msg) => AdminErrorResponse::internal_error(msg),
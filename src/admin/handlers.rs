@@ -2,15 +2,22 @@
 
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
     response::IntoResponse,
 };
 
 use super::{
     middleware::AdminState,
+    transfer::{ImportPayload, PASSPHRASE_HEADER},
     types::{
         AddCredentialRequest, AddCredentialResponse, AdminErrorResponse, BalanceResponse,
-        SetDisabledRequest, SetPriorityRequest, SuccessResponse,
+        BulkCredentialUpdate, CreateAdminTokenRequest, CreateApiKeyRequest, DeleteCredentialQuery,
+        DrainAction, HealthChecksQuery, ListTranscriptsQuery, LoginRequest, NotificationsQuery,
+        RecommendationsQuery, RefreshBalancesRequest, SearchRequestLogsQuery,
+        SetAllowedModelsRequest, SetDisabledRequest, SetExtraHeadersRequest, SetMachineIdRequest,
+        SetMachineIdResponse, SetPriorityRequest, SetVersionOverridesRequest, StatsQuery,
+        SuccessResponse, UpdateCircuitBreakerRequest, UpsertPromptTemplateRequest, UsageQuery,
     },
 };
 
@@ -23,12 +30,25 @@ pub async fn get_all_credentials(State(state): State<AdminState>) -> impl IntoRe
 
 /// POST /api/admin/credentials/:id/disabled
 /// 设置凭据禁用状态
+///
+/// 禁用时传入 `drain: true` 会等待进行中的请求完成，返回 202 与排空任务
 pub async fn set_credential_disabled(
     State(state): State<AdminState>,
     Path(id): Path<u64>,
     Json(payload): Json<SetDisabledRequest>,
 ) -> impl IntoResponse {
-    match state.service.set_disabled(id, payload.disabled) {
+    if payload.disabled && payload.drain {
+        return match state
+            .service
+            .start_drain(id, DrainAction::Disable, payload.timeout_secs)
+            .await
+        {
+            Ok(job) => (StatusCode::ACCEPTED, Json(job)).into_response(),
+            Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+        };
+    }
+
+    match state.service.set_disabled(id, payload.disabled).await {
         Ok(_) => {
             let action = if payload.disabled { "禁用" } else { "启用" };
             Json(SuccessResponse::new(format!("凭据 #{} 已{}", id, action))).into_response()
@@ -44,7 +64,7 @@ pub async fn set_credential_priority(
     Path(id): Path<u64>,
     Json(payload): Json<SetPriorityRequest>,
 ) -> impl IntoResponse {
-    match state.service.set_priority(id, payload.priority) {
+    match state.service.set_priority(id, payload.priority).await {
         Ok(_) => Json(SuccessResponse::new(format!(
             "凭据 #{} 优先级已设置为 {}",
             id, payload.priority
@@ -54,13 +74,94 @@ pub async fn set_credential_priority(
     }
 }
 
+/// POST /api/admin/credentials/bulk
+/// 批量设置凭据的禁用状态与优先级（单个事务）
+pub async fn bulk_update_credentials(
+    State(state): State<AdminState>,
+    Json(payload): Json<Vec<BulkCredentialUpdate>>,
+) -> impl IntoResponse {
+    match state.service.bulk_update(payload).await {
+        Ok(count) => Json(SuccessResponse::new(format!("已更新 {} 个凭据", count))).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/credentials/:id/user-agent
+/// 设置凭据的客户端版本覆盖（User-Agent 特征）
+pub async fn set_credential_version_overrides(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+    Json(payload): Json<SetVersionOverridesRequest>,
+) -> impl IntoResponse {
+    match state.service.set_version_overrides(id, payload).await {
+        Ok(_) => Json(SuccessResponse::new(format!(
+            "凭据 #{} 客户端版本覆盖已更新",
+            id
+        )))
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/credentials/:id/models
+/// 设置凭据允许使用的模型
+pub async fn set_credential_allowed_models(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+    Json(payload): Json<SetAllowedModelsRequest>,
+) -> impl IntoResponse {
+    match state.service.set_allowed_models(id, payload).await {
+        Ok(_) => Json(SuccessResponse::new(format!(
+            "凭据 #{} 允许使用的模型已更新",
+            id
+        )))
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/credentials/:id/headers
+/// 设置凭据的自定义上游请求头
+pub async fn set_credential_extra_headers(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+    Json(payload): Json<SetExtraHeadersRequest>,
+) -> impl IntoResponse {
+    match state.service.set_extra_headers(id, payload).await {
+        Ok(_) => Json(SuccessResponse::new(format!(
+            "凭据 #{} 自定义请求头已更新",
+            id
+        )))
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/credentials/:id/machine-id
+/// 设置或重新生成凭据的设备指纹
+pub async fn set_credential_machine_id(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+    Json(payload): Json<SetMachineIdRequest>,
+) -> impl IntoResponse {
+    match state.service.set_machine_id(id, payload).await {
+        Ok(machine_id) => Json(SetMachineIdResponse {
+            success: true,
+            message: format!("凭据 #{} 设备指纹已更新", id),
+            machine_id,
+        })
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
 /// POST /api/admin/credentials/:id/reset
 /// 重置失败计数并重新启用
 pub async fn reset_failure_count(
     State(state): State<AdminState>,
     Path(id): Path<u64>,
 ) -> impl IntoResponse {
-    match state.service.reset_and_enable(id) {
+    match state.service.reset_and_enable(id).await {
         Ok(_) => Json(SuccessResponse::new(format!(
             "凭据 #{} 失败计数已重置并重新启用",
             id
@@ -86,24 +187,38 @@ pub async fn get_credential_balance(
     }
 }
 
+/// POST /api/admin/credentials/:id/check
+/// 检查凭据健康状态（上游调用失败时返回 `healthy: false`）
+pub async fn check_credential(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.service.check_credential(id).await {
+        Ok(check) => Json(check).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/credentials/:id/health-checks
+/// 获取凭据的健康检查历史
+pub async fn list_health_checks(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+    Query(query): Query<HealthChecksQuery>,
+) -> impl IntoResponse {
+    match state.service.list_health_checks(id, query).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
 /// POST /api/admin/credentials
 /// 添加新凭据
 pub async fn add_credential(
     State(state): State<AdminState>,
     Json(payload): Json<AddCredentialRequest>,
 ) -> impl IntoResponse {
-    match state
-        .service
-        .add_credential(
-            payload.refresh_token,
-            payload.auth_method,
-            payload.client_id,
-            payload.client_secret,
-            payload.machine_id,
-            payload.priority,
-        )
-        .await
-    {
+    match state.service.add_credential(payload).await {
         Ok(id) => Json(AddCredentialResponse {
             success: true,
             message: format!("凭据已添加，ID: {}", id),
@@ -114,14 +229,443 @@ pub async fn add_credential(
     }
 }
 
+/// 读取加密口令请求头
+fn passphrase(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(PASSPHRASE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+/// GET /api/admin/credentials/export
+/// 导出所有凭据（携带口令请求头时加密）
+pub async fn export_credentials(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match state.service.export_credentials(passphrase(&headers)).await {
+        Ok(exported) => (
+            [(
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"kiro-credentials.json\"",
+            )],
+            Json(exported),
+        )
+            .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/credentials/import
+/// 批量导入凭据（明文数组或加密信封）
+pub async fn import_credentials(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+    Json(payload): Json<ImportPayload>,
+) -> impl IntoResponse {
+    match state
+        .service
+        .import_credentials(payload, passphrase(&headers))
+        .await
+    {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
 /// DELETE /api/admin/credentials/:id
 /// 删除凭据
+///
+/// 传入 `?drain=true` 会先禁用凭据并等待进行中的请求完成后再删除，返回 202 与排空任务
 pub async fn delete_credential(
     State(state): State<AdminState>,
     Path(id): Path<u64>,
+    Query(query): Query<DeleteCredentialQuery>,
 ) -> impl IntoResponse {
-    match state.service.delete_credential(id) {
+    if query.drain {
+        return match state
+            .service
+            .start_drain(id, DrainAction::Delete, query.timeout_secs)
+            .await
+        {
+            Ok(job) => (StatusCode::ACCEPTED, Json(job)).into_response(),
+            Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+        };
+    }
+
+    match state.service.delete_credential(id).await {
         Ok(_) => Json(SuccessResponse::new(format!("凭据 #{} 已删除", id))).into_response(),
         Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
     }
 }
+
+/// GET /api/admin/drain-jobs/:id
+/// 获取排空任务状态
+pub async fn get_drain_job(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.service.get_drain_job(id) {
+        Ok(job) => Json(job).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/credentials/refresh-balances
+/// 批量刷新余额（后台执行，返回 202 与任务）
+pub async fn refresh_balances(
+    State(state): State<AdminState>,
+    payload: Option<Json<RefreshBalancesRequest>>,
+) -> impl IntoResponse {
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+    match state.service.start_balance_refresh(payload).await {
+        Ok(job) => (StatusCode::ACCEPTED, Json(job)).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/credentials/login
+/// 发起设备授权登录，返回用户码与验证地址（用户授权后自动添加凭据）
+pub async fn start_login(
+    State(state): State<AdminState>,
+    payload: Option<Json<LoginRequest>>,
+) -> impl IntoResponse {
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+    match state.service.start_login(payload).await {
+        Ok(job) => (StatusCode::ACCEPTED, Json(job)).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/login-jobs/:id
+/// 获取设备授权登录任务状态
+pub async fn get_login_job(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.service.get_login_job(id) {
+        Ok(job) => Json(job).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/balance-refresh-jobs/:id
+/// 获取批量刷新余额任务进度
+pub async fn get_balance_refresh_job(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.service.get_balance_refresh_job(id) {
+        Ok(job) => Json(job).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/requests
+/// 浏览对话记录
+pub async fn list_transcripts(
+    State(state): State<AdminState>,
+    Query(query): Query<ListTranscriptsQuery>,
+) -> impl IntoResponse {
+    match state.service.list_transcripts(query).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/requests/search
+/// 按条件搜索请求日志
+pub async fn search_request_logs(
+    State(state): State<AdminState>,
+    Query(query): Query<SearchRequestLogsQuery>,
+) -> impl IntoResponse {
+    match state.service.search_request_logs(query).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/usage
+/// 按时间范围汇总用量（按凭据、按模型）
+pub async fn get_usage(
+    State(state): State<AdminState>,
+    Query(query): Query<UsageQuery>,
+) -> impl IntoResponse {
+    match state.service.get_usage(query).await {
+        Ok(summary) => Json(summary).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/recommendations
+/// 分析凭据池并生成调整建议（附带可一键应用的 API 调用）
+pub async fn get_recommendations(
+    State(state): State<AdminState>,
+    Query(query): Query<RecommendationsQuery>,
+) -> impl IntoResponse {
+    match state.service.get_recommendations(query).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/metrics
+/// 获取运行指标
+pub async fn get_metrics(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_metrics())
+}
+
+/// GET /api/admin/stats
+/// 获取统计摘要（可按 `tag` 只统计携带该请求标签的请求）
+pub async fn get_stats(
+    State(state): State<AdminState>,
+    Query(query): Query<StatsQuery>,
+) -> impl IntoResponse {
+    match state.service.get_stats(query).await {
+        Ok(summary) => Json(summary).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/config
+/// 获取当前生效的运行配置
+pub async fn get_config(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_config())
+}
+
+/// GET /api/admin/leases
+/// 获取多实例租约状态（各后台任务由哪个实例执行）
+pub async fn get_leases(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.get_leases().await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/circuit-breaker
+/// 获取当前熔断参数
+pub async fn get_circuit_breaker(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_circuit_breaker())
+}
+
+/// POST /api/admin/circuit-breaker
+/// 运行时修改熔断参数（仅作用于当前实例，不写回配置文件）
+pub async fn update_circuit_breaker(
+    State(state): State<AdminState>,
+    Json(payload): Json<UpdateCircuitBreakerRequest>,
+) -> impl IntoResponse {
+    match state.service.update_circuit_breaker(payload) {
+        Ok(settings) => Json(settings).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/refresh-lock
+/// 获取 Token 刷新锁状态
+pub async fn get_refresh_lock(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.refresh_lock_status())
+}
+
+/// POST /api/admin/refresh-lock/release
+/// 强制释放卡住的 Token 刷新
+pub async fn release_refresh_lock(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.release_refresh_lock() {
+        Ok(id) => Json(SuccessResponse::new(format!(
+            "已强制释放凭据 #{} 的 Token 刷新",
+            id
+        )))
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/prompt-templates
+/// 获取所有提示词模板
+pub async fn list_prompt_templates(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.list_prompt_templates().await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/prompt-templates
+/// 创建或更新提示词模板
+pub async fn upsert_prompt_template(
+    State(state): State<AdminState>,
+    Json(payload): Json<UpsertPromptTemplateRequest>,
+) -> impl IntoResponse {
+    let name = payload.name.trim().to_string();
+    match state.service.upsert_prompt_template(payload).await {
+        Ok(created) => {
+            let action = if created { "已创建" } else { "已更新" };
+            Json(SuccessResponse::new(format!(
+                "提示词模板 {} {}",
+                name, action
+            )))
+            .into_response()
+        }
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// DELETE /api/admin/prompt-templates/:name
+/// 删除提示词模板
+pub async fn delete_prompt_template(
+    State(state): State<AdminState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    match state.service.delete_prompt_template(&name).await {
+        Ok(_) => Json(SuccessResponse::new(format!("提示词模板 {} 已删除", name))).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/admin-tokens
+/// 获取所有受限 Admin Token（不含明文）
+pub async fn list_admin_tokens(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.list_admin_tokens().await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/admin-tokens
+/// 签发受限 Admin Token（明文仅在响应中返回一次）
+pub async fn create_admin_token(
+    State(state): State<AdminState>,
+    Json(payload): Json<CreateAdminTokenRequest>,
+) -> impl IntoResponse {
+    match state.service.create_admin_token(payload).await {
+        Ok(response) => (StatusCode::CREATED, Json(response)).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/admin-tokens/:id/revoke
+/// 吊销受限 Admin Token
+pub async fn revoke_admin_token(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.service.revoke_admin_token(id).await {
+        Ok(_) => Json(SuccessResponse::new(format!("Admin Token #{} 已吊销", id))).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// DELETE /api/admin/admin-tokens/:id
+/// 删除受限 Admin Token
+pub async fn delete_admin_token(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.service.delete_admin_token(id).await {
+        Ok(_) => Json(SuccessResponse::new(format!("Admin Token #{} 已删除", id))).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/notifications
+/// 获取告警通知投递队列（可按状态过滤）
+pub async fn list_notifications(
+    State(state): State<AdminState>,
+    Query(query): Query<NotificationsQuery>,
+) -> impl IntoResponse {
+    match state.service.list_notifications(query).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/notifications/:id/replay
+/// 重放未投递成功的告警通知
+pub async fn replay_notification(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.service.replay_notification(id).await {
+        Ok(_) => Json(SuccessResponse::new(format!(
+            "告警通知 #{} 已重新加入投递队列",
+            id
+        )))
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/api-keys
+/// 获取所有客户端 API Key（不含明文）
+pub async fn list_api_keys(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.list_api_keys().await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/api-keys
+/// 签发客户端 API Key（明文仅在响应中返回一次）
+pub async fn create_api_key(
+    State(state): State<AdminState>,
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> impl IntoResponse {
+    match state.service.create_api_key(payload).await {
+        Ok(response) => (StatusCode::CREATED, Json(response)).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/api-keys/:id/revoke
+/// 吊销客户端 API Key
+pub async fn revoke_api_key(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.service.revoke_api_key(id).await {
+        Ok(_) => Json(SuccessResponse::new(format!(
+            "客户端 API Key #{} 已吊销",
+            id
+        )))
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// DELETE /api/admin/api-keys/:id
+/// 删除客户端 API Key
+pub async fn delete_api_key(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.service.delete_api_key(id).await {
+        Ok(_) => Json(SuccessResponse::new(format!(
+            "客户端 API Key #{} 已删除",
+            id
+        )))
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/replication/snapshot
+/// 导出凭据快照（供热备实例同步，包含 Token 等敏感信息）
+pub async fn get_replication_snapshot(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.replication_snapshot().await {
+        Ok(snapshot) => Json(snapshot).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/replication/status
+/// 获取热备同步状态
+pub async fn get_replication_status(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.replication_status())
+}
+
+/// POST /api/admin/replication/promote
+/// 将热备实例提升为主实例
+pub async fn promote_replica(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.promote().await {
+        Ok(_) => Json(SuccessResponse::new("实例已提升为主实例")).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
@@ -0,0 +1,110 @@
+//! 后台任务表
+//!
+//! 排空、批量刷新余额与设备授权登录等后台任务共用的内存任务表：
+//! 任务 ID 自增，已结束的任务保留一段时间供轮询，创建新任务时顺带清理
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::Mutex;
+
+/// 已结束任务的保留时长（小时）
+const FINISHED_RETENTION_HOURS: i64 = 1;
+
+/// 可放入 [`JobTable`] 的任务
+pub trait Job: Clone {
+    /// 结束时间（RFC3339，进行中为 None）
+    fn finished_at(&self) -> Option<&str>;
+}
+
+/// 后台任务表（仅内存）
+pub struct JobTable<T> {
+    jobs: Mutex<HashMap<u64, T>>,
+    next_id: AtomicU64,
+}
+
+impl<T> Default for JobTable<T> {
+    fn default() -> Self {
+        Self {
+            jobs: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<T: Job> JobTable<T> {
+    /// 分配任务 ID 并创建任务（同时清理过期的已结束任务）
+    pub fn create(&self, build: impl FnOnce(u64) -> T) -> T {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let job = build(id);
+
+        let cutoff = Utc::now() - Duration::hours(FINISHED_RETENTION_HOURS);
+        let mut jobs = self.jobs.lock();
+        jobs.retain(|_, job| {
+            job.finished_at()
+                .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+                .is_none_or(|t| t > cutoff)
+        });
+        jobs.insert(id, job.clone());
+        job
+    }
+
+    /// 修改任务（任务不存在时忽略）
+    pub fn update(&self, id: u64, f: impl FnOnce(&mut T)) {
+        if let Some(job) = self.jobs.lock().get_mut(&id) {
+            f(job);
+        }
+    }
+
+    /// 获取任务
+    pub fn get(&self, id: u64) -> Option<T> {
+        self.jobs.lock().get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct TestJob {
+        id: u64,
+        finished_at: Option<String>,
+    }
+
+    impl Job for TestJob {
+        fn finished_at(&self) -> Option<&str> {
+            self.finished_at.as_deref()
+        }
+    }
+
+    fn create(jobs: &JobTable<TestJob>) -> u64 {
+        jobs.create(|id| TestJob {
+            id,
+            finished_at: None,
+        })
+        .id
+    }
+
+    #[test]
+    fn test_retains_recent_and_running_jobs() {
+        let jobs = JobTable::default();
+        let running = create(&jobs);
+        let recent = create(&jobs);
+        let expired = create(&jobs);
+        assert_eq!((running, recent, expired), (1, 2, 3));
+
+        jobs.update(recent, |job| {
+            job.finished_at = Some(Utc::now().to_rfc3339());
+        });
+        jobs.update(expired, |job| {
+            job.finished_at = Some((Utc::now() - Duration::hours(2)).to_rfc3339());
+        });
+        create(&jobs);
+
+        assert!(jobs.get(running).is_some());
+        assert!(jobs.get(recent).is_some());
+        assert!(jobs.get(expired).is_none());
+    }
+}
@@ -0,0 +1,104 @@
+//! 设备授权登录任务
+//!
+//! `POST /credentials/login` 发起 AWS SSO OIDC 设备授权后立即返回用户码与验证地址，
+//! 后台按授权服务返回的间隔轮询，用户在浏览器中完成授权后自动添加凭据；通过任务 ID 查询结果
+
+use chrono::{Duration, Utc};
+
+use crate::kiro::device_auth::DeviceAuthorization;
+
+use super::jobs::{Job, JobTable};
+use super::types::{LoginJob, LoginState};
+
+impl Job for LoginJob {
+    fn finished_at(&self) -> Option<&str> {
+        self.finished_at.as_deref()
+    }
+}
+
+/// 设备授权登录任务表（仅内存）
+#[derive(Default)]
+pub struct LoginJobs {
+    jobs: JobTable<LoginJob>,
+}
+
+impl LoginJobs {
+    /// 创建登录任务（同时清理过期的已结束任务）
+    pub fn create(&self, start_url: &str, auth: &DeviceAuthorization) -> LoginJob {
+        let now = Utc::now();
+        self.jobs.create(|id| LoginJob {
+            id,
+            state: LoginState::Pending,
+            start_url: start_url.to_string(),
+            user_code: auth.user_code.clone(),
+            verification_uri: auth.verification_uri.clone(),
+            verification_uri_complete: auth.verification_uri_complete.clone(),
+            expires_at: (now + Duration::seconds(auth.expires_in as i64)).to_rfc3339(),
+            credential_id: None,
+            error: None,
+            started_at: now.to_rfc3339(),
+            finished_at: None,
+        })
+    }
+
+    /// 结束登录任务：成功时记录添加的凭据 ID，失败时记录原因
+    pub fn finish(&self, id: u64, result: Result<u64, String>) {
+        self.jobs.update(id, |job| {
+            match result {
+                Ok(credential_id) => {
+                    job.state = LoginState::Completed;
+                    job.credential_id = Some(credential_id);
+                }
+                Err(error) => {
+                    job.state = LoginState::Failed;
+                    job.error = Some(error);
+                }
+            }
+            job.finished_at = Some(Utc::now().to_rfc3339());
+        });
+    }
+
+    /// 获取登录任务
+    pub fn get(&self, id: u64) -> Option<LoginJob> {
+        self.jobs.get(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authorization() -> DeviceAuthorization {
+        DeviceAuthorization {
+            client_id: "cid".to_string(),
+            client_secret: "secret".to_string(),
+            device_code: "dev".to_string(),
+            user_code: "ABCD-EFGH".to_string(),
+            verification_uri: "https://device.sso.example.com/".to_string(),
+            verification_uri_complete: None,
+            expires_in: 600,
+            interval: 5,
+        }
+    }
+
+    #[test]
+    fn test_login_job_lifecycle() {
+        let jobs = LoginJobs::default();
+        let job = jobs.create("https://view.awsapps.com/start", &authorization());
+        assert_eq!(job.state, LoginState::Pending);
+        assert_eq!(job.user_code, "ABCD-EFGH");
+
+        jobs.finish(job.id, Ok(7));
+        let finished = jobs.get(job.id).unwrap();
+        assert_eq!(finished.state, LoginState::Completed);
+        assert_eq!(finished.credential_id, Some(7));
+        assert!(finished.finished_at.is_some());
+
+        let job = jobs.create("https://view.awsapps.com/start", &authorization());
+        jobs.finish(job.id, Err("用户拒绝了授权".to_string()));
+        let failed = jobs.get(job.id).unwrap();
+        assert_eq!(failed.state, LoginState::Failed);
+        assert_eq!(failed.error.as_deref(), Some("用户拒绝了授权"));
+        assert!(jobs.get(999).is_none());
+    }
+}
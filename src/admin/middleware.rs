@@ -5,14 +5,16 @@ use std::sync::Arc;
 use axum::{
     body::Body,
     extract::State,
-    http::{Request, StatusCode},
+    http::{Method, Request, StatusCode},
     middleware::Next,
     response::{IntoResponse, Json, Response},
 };
 
 use super::service::AdminService;
 use super::types::AdminErrorResponse;
+use super::ws;
 use crate::common::auth;
+use crate::kiro::model::admin_token::AdminScope;
 
 /// Admin API 共享状态
 #[derive(Clone)]
@@ -33,18 +35,169 @@ impl AdminState {
 }
 
 /// Admin API 认证中间件
+///
+/// 仅接受 `x-api-key` / `Authorization: Bearer` 请求头，不使用 Cookie 会话：
+/// 浏览器不会在跨站请求中自动附带这些请求头，因此无需 CSRF Token。
+/// 若将来引入 Cookie 会话认证，需同时为修改类端点签发并校验 CSRF Token。
+/// 浏览器无法为 WebSocket 握手设置请求头，因此也接受通过子协议传递的 Key（见 [`ws`]）
+///
+/// 主 Admin API Key 可以访问所有端点；受限 Admin Token 只能访问权限范围内的端点
 pub async fn admin_auth_middleware(
     State(state): State<AdminState>,
     request: Request<Body>,
     next: Next,
 ) -> Response {
-    let api_key = auth::extract_api_key(&request);
+    let api_key =
+        auth::extract_api_key(&request).or_else(|| ws::protocol_api_key(request.headers()));
+    let Some(api_key) = api_key else {
+        return unauthorized();
+    };
+    if auth::constant_time_eq(&api_key, &state.admin_api_key) {
+        return next.run(request).await;
+    }
+
+    let token = match state.service.find_active_admin_token(&api_key).await {
+        Ok(Some(token)) => token,
+        Ok(None) => return unauthorized(),
+        Err(e) => return (e.status_code(), Json(e.into_response())).into_response(),
+    };
+    match required_scope(request.method(), request.uri().path()) {
+        Some(scope) if token.has_scope(scope) => next.run(request).await,
+        scope => {
+            let message = match scope {
+                Some(scope) => format!("Admin token is missing required scope: {}", scope.as_str()),
+                None => "This endpoint requires the master admin API key".to_string(),
+            };
+            let error = AdminErrorResponse::permission_error(message);
+            (StatusCode::FORBIDDEN, Json(error)).into_response()
+        }
+    }
+}
 
-    match api_key {
-        Some(key) if auth::constant_time_eq(&key, &state.admin_api_key) => next.run(request).await,
-        _ => {
-            let error = AdminErrorResponse::authentication_error();
-            (StatusCode::UNAUTHORIZED, Json(error)).into_response()
+fn unauthorized() -> Response {
+    let error = AdminErrorResponse::authentication_error();
+    (StatusCode::UNAUTHORIZED, Json(error)).into_response()
+}
+
+/// 端点所需的权限范围（路径不含 Admin API 前缀）
+///
+/// 返回 None 表示只允许主 Admin API Key 访问：受限 Token 与客户端 API Key 的管理、
+/// 热备同步（快照包含全部凭据）以及未知端点
+pub fn required_scope(method: &Method, path: &str) -> Option<AdminScope> {
+    let read = method == Method::GET;
+    let mut segments = path.trim_matches('/').split('/');
+    match segments.next()? {
+        // 导出的凭据包含 Refresh Token，与修改凭据同等敏感
+        "credentials" if read && segments.next() != Some("export") => {
+            Some(AdminScope::CredentialsRead)
+        }
+        "credentials" => Some(AdminScope::CredentialsWrite),
+        "drain-jobs" | "balance-refresh-jobs" | "login-jobs" | "recommendations" | "ws" if read => {
+            Some(AdminScope::CredentialsRead)
+        }
+        // 对话记录包含完整的提示词与回复，仅允许主 Admin Key 访问
+        "requests" if segments.next().is_none() => None,
+        "requests" | "usage" | "metrics" | "stats" | "notifications" if read => {
+            Some(AdminScope::StatsRead)
         }
+        "config" | "leases" | "refresh-lock" | "prompt-templates" | "circuit-breaker" if read => {
+            Some(AdminScope::ConfigRead)
+        }
+        "refresh-lock" | "prompt-templates" | "notifications" | "circuit-breaker" => {
+            Some(AdminScope::ConfigWrite)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_scope() {
+        let get = Method::GET;
+        let post = Method::POST;
+        let delete = Method::DELETE;
+
+        assert_eq!(
+            required_scope(&get, "/credentials"),
+            Some(AdminScope::CredentialsRead)
+        );
+        assert_eq!(
+            required_scope(&get, "/credentials/3/balance"),
+            Some(AdminScope::CredentialsRead)
+        );
+        assert_eq!(
+            required_scope(&get, "/credentials/export"),
+            Some(AdminScope::CredentialsWrite)
+        );
+        assert_eq!(
+            required_scope(&post, "/credentials/3/disabled"),
+            Some(AdminScope::CredentialsWrite)
+        );
+        assert_eq!(
+            required_scope(&get, "/credentials/3/health-checks"),
+            Some(AdminScope::CredentialsRead)
+        );
+        assert_eq!(
+            required_scope(&post, "/credentials/3/check"),
+            Some(AdminScope::CredentialsWrite)
+        );
+        assert_eq!(
+            required_scope(&delete, "/credentials/3"),
+            Some(AdminScope::CredentialsWrite)
+        );
+        assert_eq!(
+            required_scope(&get, "/recommendations"),
+            Some(AdminScope::CredentialsRead)
+        );
+        assert_eq!(
+            required_scope(&get, "/ws"),
+            Some(AdminScope::CredentialsRead)
+        );
+        assert_eq!(
+            required_scope(&get, "/requests/search"),
+            Some(AdminScope::StatsRead)
+        );
+        assert_eq!(required_scope(&get, "/requests"), None);
+        assert_eq!(
+            required_scope(&get, "/config"),
+            Some(AdminScope::ConfigRead)
+        );
+        assert_eq!(
+            required_scope(&get, "/leases"),
+            Some(AdminScope::ConfigRead)
+        );
+        assert_eq!(
+            required_scope(&post, "/refresh-lock/release"),
+            Some(AdminScope::ConfigWrite)
+        );
+        assert_eq!(
+            required_scope(&post, "/notifications/1/replay"),
+            Some(AdminScope::ConfigWrite)
+        );
+        assert_eq!(
+            required_scope(&post, "/credentials/login"),
+            Some(AdminScope::CredentialsWrite)
+        );
+        assert_eq!(
+            required_scope(&get, "/login-jobs/1"),
+            Some(AdminScope::CredentialsRead)
+        );
+        assert_eq!(
+            required_scope(&get, "/circuit-breaker"),
+            Some(AdminScope::ConfigRead)
+        );
+        assert_eq!(
+            required_scope(&post, "/circuit-breaker"),
+            Some(AdminScope::ConfigWrite)
+        );
+
+        // 仅主 Key 可访问
+        assert_eq!(required_scope(&get, "/admin-tokens"), None);
+        assert_eq!(required_scope(&post, "/api-keys"), None);
+        assert_eq!(required_scope(&get, "/replication/snapshot"), None);
+        assert_eq!(required_scope(&post, "/config"), None);
     }
 }
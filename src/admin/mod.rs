@@ -8,6 +8,8 @@
 //! - 修改凭据优先级
 //! - 重置失败计数
 //! - 查询凭据余额
+//! - 通过设备授权登录添加 IdC / Builder ID 凭据
+//! - 通过 WebSocket 推送凭据状态变更
 //!
 //! # 使用
 //! ```ignore
@@ -15,13 +17,26 @@
 //! let admin_state = AdminState::new(admin_api_key, admin_service);
 //! let admin_router = create_admin_router(admin_state);
 //! ```
+//!
+//! 启用 `client` feature 后，[`client::AdminClient`] 提供基于 reqwest 的类型化客户端，
+//! 请求/响应类型与服务端共用 [`types`] 中的定义
 
+mod balance_refresh;
+#[cfg(feature = "client")]
+pub mod client;
+mod drain;
+mod effective_config;
 mod error;
 mod handlers;
+mod jobs;
+mod login;
 mod middleware;
+mod recommendations;
 mod router;
 mod service;
+mod transfer;
 pub mod types;
+mod ws;
 
 pub use middleware::AdminState;
 pub use router::create_admin_router;
@@ -0,0 +1,250 @@
+//! 凭据池调整建议
+//!
+//! 根据统计窗口内的用量、错误率、健康检查结果与优先级分析凭据池，
+//! 给出结构化的调整建议（提升闲置健康账号的优先级、禁用持续失败的账号、
+//! 为指纹重复的账号重新生成设备指纹）。每条建议附带可直接执行的 Admin API 调用，
+//! 供 Web UI 一键应用；分析只读，不会修改任何凭据
+
+use std::collections::HashMap;
+
+use serde_json::json;
+
+use super::types::{Recommendation, RecommendationKind, RecommendedAction};
+
+/// 计算错误率所需的最少请求数（样本过少时不据此判断）
+const MIN_REQUESTS: u64 = 20;
+
+/// 错误率达到该值视为持续失败
+const FAILING_ERROR_RATE: f64 = 0.5;
+
+/// 健康检查全部失败且次数达到该值视为持续失败
+const MIN_FAILED_HEALTH_CHECKS: u64 = 3;
+
+/// 错误率低于该值视为健康
+const HEALTHY_ERROR_RATE: f64 = 0.05;
+
+/// 请求数低于启用凭据平均值的该比例视为闲置
+const UNDERUSED_SHARE: f64 = 0.25;
+
+/// 额度使用率低于该值（百分比）才建议提升优先级
+const MAX_USAGE_PERCENTAGE: f64 = 50.0;
+
+/// 单个凭据的分析输入
+#[derive(Debug, Clone, Default)]
+pub struct CredentialStats {
+    pub id: u64,
+    pub priority: u32,
+    pub disabled: bool,
+    /// 额度使用率（百分比，余额未知时为 None）
+    pub usage_percentage: Option<f64>,
+    /// 实际使用的设备指纹（未配置时由凭据信息派生）
+    pub machine_id: Option<String>,
+    /// 统计窗口内的请求数
+    pub requests: u64,
+    /// 统计窗口内的失败请求数
+    pub errors: u64,
+    /// 统计窗口内的健康检查次数
+    pub health_checks: u64,
+    /// 统计窗口内失败的健康检查次数
+    pub health_failures: u64,
+}
+
+impl CredentialStats {
+    /// 错误率（样本不足时为 None）
+    fn error_rate(&self) -> Option<f64> {
+        (self.requests >= MIN_REQUESTS).then(|| self.errors as f64 / self.requests as f64)
+    }
+
+    /// 是否持续失败（错误率过高，或最近的健康检查全部失败）
+    fn failing_reason(&self) -> Option<String> {
+        if let Some(rate) = self.error_rate()
+            && rate >= FAILING_ERROR_RATE
+        {
+            return Some(format!(
+                "最近 {} 次请求中 {} 次失败（错误率 {:.0}%）",
+                self.requests,
+                self.errors,
+                rate * 100.0
+            ));
+        }
+        if self.health_checks >= MIN_FAILED_HEALTH_CHECKS
+            && self.health_failures == self.health_checks
+        {
+            return Some(format!("最近 {} 次健康检查全部失败", self.health_checks));
+        }
+        None
+    }
+
+    /// 是否健康（没有失败的健康检查，错误率低或样本不足）
+    fn is_healthy(&self) -> bool {
+        self.health_failures == 0 && self.error_rate().is_none_or(|r| r < HEALTHY_ERROR_RATE)
+    }
+}
+
+/// 分析凭据池并生成调整建议
+///
+/// 同一凭据被建议禁用时不再给出其他建议
+pub fn analyze(credentials: &[CredentialStats]) -> Vec<Recommendation> {
+    let mut recommendations = Vec::new();
+
+    for c in credentials.iter().filter(|c| !c.disabled) {
+        if let Some(reason) = c.failing_reason() {
+            recommendations.push(Recommendation {
+                kind: RecommendationKind::Disable,
+                credential_id: c.id,
+                reason,
+                action: RecommendedAction::post(
+                    format!("/credentials/{}/disabled", c.id),
+                    json!({ "disabled": true }),
+                ),
+            });
+        }
+    }
+    let flagged: Vec<u64> = recommendations.iter().map(|r| r.credential_id).collect();
+    let active: Vec<&CredentialStats> = credentials
+        .iter()
+        .filter(|c| !c.disabled && !flagged.contains(&c.id))
+        .collect();
+
+    // 提升闲置健康账号的优先级
+    if let Some(top_priority) = active.iter().map(|c| c.priority).min() {
+        let average = active.iter().map(|c| c.requests).sum::<u64>() as f64 / active.len() as f64;
+        for c in &active {
+            let underused = (c.requests as f64) < average * UNDERUSED_SHARE;
+            let has_quota = c.usage_percentage.is_some_and(|p| p < MAX_USAGE_PERCENTAGE);
+            if c.priority > top_priority && underused && has_quota && c.is_healthy() {
+                recommendations.push(Recommendation {
+                    kind: RecommendationKind::RaisePriority,
+                    credential_id: c.id,
+                    reason: format!(
+                        "账号健康且额度已用 {:.0}%，但仅承担 {} 次请求（平均 {:.0} 次），优先级 {} 可提升至 {}",
+                        c.usage_percentage.unwrap_or_default(),
+                        c.requests,
+                        average,
+                        c.priority,
+                        top_priority
+                    ),
+                    action: RecommendedAction::post(
+                        format!("/credentials/{}/priority", c.id),
+                        json!({ "priority": top_priority }),
+                    ),
+                });
+            }
+        }
+    }
+
+    // 设备指纹重复的账号（保留 ID 最小的一个）
+    let mut by_machine_id: HashMap<String, Vec<u64>> = HashMap::new();
+    for c in &active {
+        if let Some(machine_id) = &c.machine_id {
+            by_machine_id
+                .entry(machine_id.to_ascii_lowercase())
+                .or_default()
+                .push(c.id);
+        }
+    }
+    let mut duplicates: Vec<(u64, u64)> = by_machine_id
+        .into_values()
+        .filter(|ids| ids.len() > 1)
+        .flat_map(|mut ids| {
+            ids.sort_unstable();
+            let keep = ids[0];
+            ids.into_iter().skip(1).map(move |id| (id, keep))
+        })
+        .collect();
+    duplicates.sort_unstable();
+    for (id, shared_with) in duplicates {
+        recommendations.push(Recommendation {
+            kind: RecommendationKind::RotateMachineId,
+            credential_id: id,
+            reason: format!("与凭据 #{} 使用相同的设备指纹", shared_with),
+            action: RecommendedAction::post(format!("/credentials/{}/machine-id", id), json!({})),
+        });
+    }
+
+    recommendations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(id: u64, priority: u32, requests: u64, errors: u64) -> CredentialStats {
+        CredentialStats {
+            id,
+            priority,
+            usage_percentage: Some(10.0),
+            machine_id: Some(format!("00000000-0000-4000-8000-00000000000{}", id)),
+            requests,
+            errors,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_disable_failing_credentials() {
+        let mut unhealthy = stats(3, 0, 0, 0);
+        unhealthy.health_checks = 3;
+        unhealthy.health_failures = 3;
+        let mut disabled = stats(4, 0, 100, 100);
+        disabled.disabled = true;
+
+        let recommendations = analyze(&[
+            stats(1, 0, 100, 60),
+            stats(2, 0, 10, 10),
+            unhealthy,
+            disabled,
+        ]);
+        let disable: Vec<u64> = recommendations
+            .iter()
+            .filter(|r| r.kind == RecommendationKind::Disable)
+            .map(|r| r.credential_id)
+            .collect();
+        // 样本不足的 #2 与已禁用的 #4 不建议禁用
+        assert_eq!(disable, vec![1, 3]);
+        assert_eq!(recommendations[0].action.path, "/credentials/1/disabled");
+        assert_eq!(recommendations[0].action.body, json!({ "disabled": true }));
+    }
+
+    #[test]
+    fn test_raise_priority_of_underused_credentials() {
+        let mut unknown_balance = stats(4, 2, 0, 0);
+        unknown_balance.usage_percentage = None;
+        let mut unhealthy = stats(5, 2, 0, 0);
+        unhealthy.health_checks = 2;
+        unhealthy.health_failures = 1;
+
+        let recommendations = analyze(&[
+            stats(1, 0, 300, 0),
+            stats(2, 1, 200, 0),
+            stats(3, 2, 5, 0),
+            unknown_balance,
+            unhealthy,
+        ]);
+        assert_eq!(recommendations.len(), 1);
+        let recommendation = &recommendations[0];
+        assert_eq!(recommendation.kind, RecommendationKind::RaisePriority);
+        assert_eq!(recommendation.credential_id, 3);
+        assert_eq!(recommendation.action.method, "POST");
+        assert_eq!(recommendation.action.body, json!({ "priority": 0 }));
+    }
+
+    #[test]
+    fn test_rotate_duplicate_machine_ids() {
+        let mut a = stats(1, 0, 0, 0);
+        let mut b = stats(2, 0, 0, 0);
+        let mut c = stats(3, 0, 0, 0);
+        a.machine_id = Some("b3981d12-4d61-418c-9b77-461db82a7cc4".to_string());
+        b.machine_id = Some("B3981D12-4D61-418C-9B77-461DB82A7CC4".to_string());
+        c.machine_id = a.machine_id.clone();
+
+        let recommendations = analyze(&[c, b, a, stats(4, 0, 0, 0)]);
+        let rotate: Vec<u64> = recommendations
+            .iter()
+            .filter(|r| r.kind == RecommendationKind::RotateMachineId)
+            .map(|r| r.credential_id)
+            .collect();
+        assert_eq!(rotate, vec![2, 3]);
+        assert_eq!(recommendations[0].action.path, "/credentials/2/machine-id");
+    }
+}
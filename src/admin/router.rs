@@ -7,10 +7,22 @@ use axum::{
 
 use super::{
     handlers::{
-        add_credential, delete_credential, get_all_credentials, get_credential_balance,
-        reset_failure_count, set_credential_disabled, set_credential_priority,
+        add_credential, bulk_update_credentials, check_credential, create_admin_token,
+        create_api_key, delete_admin_token, delete_api_key, delete_credential,
+        delete_prompt_template, export_credentials, get_all_credentials, get_balance_refresh_job,
+        get_circuit_breaker, get_config, get_credential_balance, get_drain_job, get_leases,
+        get_login_job, get_metrics, get_recommendations, get_refresh_lock,
+        get_replication_snapshot, get_replication_status, get_stats, get_usage, import_credentials,
+        list_admin_tokens, list_api_keys, list_health_checks, list_notifications,
+        list_prompt_templates, list_transcripts, promote_replica, refresh_balances,
+        release_refresh_lock, replay_notification, reset_failure_count, revoke_admin_token,
+        revoke_api_key, search_request_logs, set_credential_allowed_models,
+        set_credential_disabled, set_credential_extra_headers, set_credential_machine_id,
+        set_credential_priority, set_credential_version_overrides, start_login,
+        update_circuit_breaker, upsert_prompt_template,
     },
     middleware::{AdminState, admin_auth_middleware},
+    ws::credential_events_ws,
 };
 
 /// 创建 Admin API 路由
@@ -18,27 +30,128 @@ use super::{
 /// # 端点
 /// - `GET /credentials` - 获取所有凭据状态
 /// - `POST /credentials` - 添加新凭据
-/// - `DELETE /credentials/:id` - 删除凭据
+/// - `POST /credentials/refresh-balances` - 批量刷新余额（返回任务）
+/// - `GET /credentials/export` - 导出所有凭据（可加密）
+/// - `POST /credentials/import` - 批量导入凭据
+/// - `POST /credentials/bulk` - 批量设置禁用状态与优先级（单个事务）
+/// - `DELETE /credentials/:id` - 删除凭据（`?drain=true` 时等待进行中的请求完成）
 /// - `POST /credentials/:id/disabled` - 设置凭据禁用状态
 /// - `POST /credentials/:id/priority` - 设置凭据优先级
+/// - `POST /credentials/:id/user-agent` - 设置客户端版本覆盖
+/// - `POST /credentials/:id/models` - 设置允许使用的模型
+/// - `POST /credentials/:id/headers` - 设置自定义上游请求头
+/// - `POST /credentials/:id/machine-id` - 设置或重新生成设备指纹
 /// - `POST /credentials/:id/reset` - 重置失败计数
 /// - `GET /credentials/:id/balance` - 获取凭据余额
+/// - `POST /credentials/:id/check` - 检查凭据健康状态（调用上游并记录结果）
+/// - `GET /credentials/:id/health-checks` - 获取凭据健康检查历史
+/// - `GET /drain-jobs/:id` - 获取排空任务状态
+/// - `GET /balance-refresh-jobs/:id` - 获取批量刷新余额任务进度
+/// - `GET /requests` - 浏览对话记录（需启用 transcriptStore）
+/// - `GET /requests/search` - 搜索请求日志
+/// - `GET /usage` - 按时间范围汇总用量（按凭据、按模型）
+/// - `GET /recommendations` - 分析凭据池并生成调整建议
+/// - `GET /metrics` - 获取运行指标
+/// - `GET /stats` - 获取统计摘要
+/// - `GET /config` - 获取当前生效的运行配置（敏感字段已脱敏）
+/// - `GET /leases` - 获取多实例租约状态
+/// - `GET /refresh-lock` - 获取 Token 刷新锁状态
+/// - `POST /refresh-lock/release` - 强制释放 Token 刷新锁
+/// - `GET /prompt-templates` - 获取所有提示词模板
+/// - `POST /prompt-templates` - 创建或更新提示词模板
+/// - `DELETE /prompt-templates/:name` - 删除提示词模板
+/// - `GET /admin-tokens` - 获取所有受限 Admin Token
+/// - `POST /admin-tokens` - 签发受限 Admin Token
+/// - `POST /admin-tokens/:id/revoke` - 吊销受限 Admin Token
+/// - `DELETE /admin-tokens/:id` - 删除受限 Admin Token
+/// - `GET /notifications` - 获取告警通知投递队列
+/// - `POST /notifications/:id/replay` - 重放未投递成功的告警通知
+/// - `GET /api-keys` - 获取所有客户端 API Key
+/// - `POST /api-keys` - 签发客户端 API Key
+/// - `POST /api-keys/:id/revoke` - 吊销客户端 API Key
+/// - `DELETE /api-keys/:id` - 删除客户端 API Key
+/// - `GET /replication/snapshot` - 导出凭据快照（热备同步）
+/// - `GET /replication/status` - 获取热备同步状态
+/// - `POST /replication/promote` - 将热备实例提升为主实例
+/// - `GET /ws` - WebSocket 推送凭据状态变更
 ///
 /// # 认证
-/// 需要 Admin API Key 认证，支持：
+/// 需要 Admin API Key 或受限 Admin Token 认证（受限 Token 只能访问权限范围内的端点，
+/// 见 [`required_scope`](super::middleware::required_scope)），支持：
 /// - `x-api-key` header
 /// - `Authorization: Bearer <token>` header
+/// - `Sec-WebSocket-Protocol: kiro-admin, kiro-admin-key.<base64url>`（仅用于 WebSocket）
 pub fn create_admin_router(state: AdminState) -> Router {
     Router::new()
         .route(
             "/credentials",
             get(get_all_credentials).post(add_credential),
         )
+        .route("/credentials/refresh-balances", post(refresh_balances))
+        .route("/credentials/export", get(export_credentials))
+        .route("/credentials/import", post(import_credentials))
+        .route("/credentials/login", post(start_login))
+        .route("/credentials/bulk", post(bulk_update_credentials))
         .route("/credentials/{id}", delete(delete_credential))
         .route("/credentials/{id}/disabled", post(set_credential_disabled))
         .route("/credentials/{id}/priority", post(set_credential_priority))
+        .route(
+            "/credentials/{id}/user-agent",
+            post(set_credential_version_overrides),
+        )
+        .route(
+            "/credentials/{id}/models",
+            post(set_credential_allowed_models),
+        )
+        .route(
+            "/credentials/{id}/headers",
+            post(set_credential_extra_headers),
+        )
+        .route(
+            "/credentials/{id}/machine-id",
+            post(set_credential_machine_id),
+        )
         .route("/credentials/{id}/reset", post(reset_failure_count))
         .route("/credentials/{id}/balance", get(get_credential_balance))
+        .route("/credentials/{id}/check", post(check_credential))
+        .route("/credentials/{id}/health-checks", get(list_health_checks))
+        .route("/drain-jobs/{id}", get(get_drain_job))
+        .route("/balance-refresh-jobs/{id}", get(get_balance_refresh_job))
+        .route("/login-jobs/{id}", get(get_login_job))
+        .route("/requests", get(list_transcripts))
+        .route("/requests/search", get(search_request_logs))
+        .route("/usage", get(get_usage))
+        .route("/recommendations", get(get_recommendations))
+        .route("/metrics", get(get_metrics))
+        .route("/stats", get(get_stats))
+        .route("/config", get(get_config))
+        .route(
+            "/circuit-breaker",
+            get(get_circuit_breaker).post(update_circuit_breaker),
+        )
+        .route("/leases", get(get_leases))
+        .route("/refresh-lock", get(get_refresh_lock))
+        .route("/refresh-lock/release", post(release_refresh_lock))
+        .route(
+            "/prompt-templates",
+            get(list_prompt_templates).post(upsert_prompt_template),
+        )
+        .route("/prompt-templates/{name}", delete(delete_prompt_template))
+        .route(
+            "/admin-tokens",
+            get(list_admin_tokens).post(create_admin_token),
+        )
+        .route("/admin-tokens/{id}", delete(delete_admin_token))
+        .route("/admin-tokens/{id}/revoke", post(revoke_admin_token))
+        .route("/notifications", get(list_notifications))
+        .route("/notifications/{id}/replay", post(replay_notification))
+        .route("/api-keys", get(list_api_keys).post(create_api_key))
+        .route("/api-keys/{id}", delete(delete_api_key))
+        .route("/api-keys/{id}/revoke", post(revoke_api_key))
+        .route("/replication/snapshot", get(get_replication_snapshot))
+        .route("/replication/status", get(get_replication_status))
+        .route("/replication/promote", post(promote_replica))
+        .route("/ws", get(credential_events_ws))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             admin_auth_middleware,
@@ -1,17 +1,83 @@
 //! Admin API 业务逻辑服务
 
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use futures::StreamExt;
-use futures::stream::FuturesUnordered;
+use tokio::sync::broadcast;
 use tokio::task;
 use tracing::warn;
 
-use crate::kiro::model::credentials::KiroCredentials;
+use crate::anthropic::deprecation;
+use crate::common::{auth, panic};
+use crate::kiro::circuit_breaker::CircuitBreakerSettings;
+use crate::kiro::credential_events::CredentialEvent;
+use crate::kiro::device_auth::{self, DeviceAuthClient, DeviceAuthorization, PollOutcome};
+use crate::kiro::model::admin_token::{AdminScope, AdminToken};
+use crate::kiro::model::api_key::ApiKey;
+use crate::kiro::model::credentials::{KiroCredentials, normalize_extra_headers};
+use crate::kiro::model::health_check::{HealthCheck, HealthCheckTrigger};
+use crate::kiro::model::prompt_template::PromptTemplate;
+use crate::kiro::model::request_log::RequestLogFilter;
+use crate::kiro::model::stats::StatsSummary;
+use crate::kiro::model::transcript::TranscriptFilter;
+use crate::kiro::model::usage_limits::UsageLimitsResponse;
+use crate::kiro::model::usage_log::{UsageFilter, UsageSummary};
+use crate::kiro::refresh_lock::RefreshLockStatus;
+use crate::kiro::replication::{self, ReplicationSnapshot};
 use crate::kiro::token_manager::MultiTokenManager;
+use crate::kiro::{connections, db, health_check, lease, stats, transcript, version};
 
+use super::balance_refresh::BalanceRefreshJobs;
+use super::drain::DrainJobs;
+use super::effective_config::{self, RuntimeOverrides};
 use super::error::AdminServiceError;
-use super::types::{BalanceResponse, CredentialStatusItem, CredentialsStatusResponse};
+use super::login::LoginJobs;
+use super::recommendations::{self, CredentialStats};
+use super::transfer::{self, ImportPayload};
+use super::types::{
+    AddCredentialRequest, AdminTokenListResponse, ApiKeyListResponse, BalanceRefreshJob,
+    BalanceResponse, BulkCredentialUpdate, ConfigResponse, CreateAdminTokenRequest,
+    CreateAdminTokenResponse, CreateApiKeyRequest, CreateApiKeyResponse, CredentialStatusItem,
+    CredentialsStatusResponse, DrainAction, DrainJob, DrainState, HealthCheckListResponse,
+    HealthChecksQuery, ImportCredentialsResponse, LeasesResponse, ListTranscriptsQuery, LoginJob,
+    LoginRequest, MetricsResponse, NotificationListResponse, NotificationsQuery,
+    PromptTemplateListResponse, RecommendationsQuery, RecommendationsResponse,
+    RefreshBalancesRequest, ReplicationStatusResponse, RequestLogSearchResponse,
+    SearchRequestLogsQuery, SetAllowedModelsRequest, SetExtraHeadersRequest, SetMachineIdRequest,
+    SetVersionOverridesRequest, StatsQuery, TranscriptListResponse, UpdateCircuitBreakerRequest,
+    UpsertPromptTemplateRequest, UsageQuery,
+};
+
+/// 单次批量更新的最大凭据数
+const MAX_BULK_UPDATES: usize = 1000;
+
+/// 请求日志搜索默认返回条数
+const DEFAULT_REQUEST_LOG_LIMIT: usize = 100;
+
+/// 请求日志搜索最大返回条数
+const MAX_REQUEST_LOG_LIMIT: usize = 1000;
+
+/// 批量刷新余额默认并发数
+const DEFAULT_BALANCE_REFRESH_CONCURRENCY: usize = 4;
+
+/// 批量刷新余额最大并发数
+const MAX_BALANCE_REFRESH_CONCURRENCY: usize = 16;
+
+/// 调整建议默认统计窗口（天）
+const DEFAULT_RECOMMENDATION_DAYS: u32 = 7;
+
+/// 调整建议最大统计窗口（天）
+const MAX_RECOMMENDATION_DAYS: u32 = 90;
+
+/// 连接排空默认超时（秒）
+const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 300;
+
+/// 连接排空最大超时（秒）
+const MAX_DRAIN_TIMEOUT_SECS: u64 = 3600;
+
+/// 连接排空轮询间隔
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 /// Admin 服务
 ///
@@ -19,169 +85,1051 @@ use super::types::{BalanceResponse, CredentialStatusItem, CredentialsStatusRespo
 #[derive(Clone)]
 pub struct AdminService {
     token_manager: Arc<MultiTokenManager>,
+    drain_jobs: Arc<DrainJobs>,
+    balance_refresh_jobs: Arc<BalanceRefreshJobs>,
+    login_jobs: Arc<LoginJobs>,
 }
 
 impl AdminService {
     pub fn new(token_manager: Arc<MultiTokenManager>) -> Self {
-        Self { token_manager }
+        Self {
+            token_manager,
+            drain_jobs: Arc::new(DrainJobs::default()),
+            balance_refresh_jobs: Arc::new(BalanceRefreshJobs::default()),
+            login_jobs: Arc::new(LoginJobs::default()),
+        }
     }
 
-    /// 获取所有凭据状态（异步获取余额）
+    /// 获取所有凭据状态（余额为数据库缓存，通过批量刷新任务或单个余额查询更新）
     pub async fn get_all_credentials(&self) -> CredentialsStatusResponse {
-        let snapshot = self.token_manager.snapshot();
+        let snapshot = self.token_manager.blocking(|tm| tm.snapshot()).await;
 
-        // 并行获取所有账号的余额
-        let balances: Vec<_> = snapshot
+        let credentials: Vec<CredentialStatusItem> = snapshot
             .entries
-            .iter()
+            .into_iter()
             .map(|entry| {
-                let token_manager = self.token_manager.clone();
-                async move {
-                    match token_manager.get_usage_limits_for(entry.id).await {
-                        Ok(usage) => {
-                            let current_usage = usage.current_usage();
-                            let usage_limit = usage.usage_limit();
-                            let remaining = (usage_limit - current_usage).max(0.0);
-                            let usage_percentage = if usage_limit > 0.0 {
-                                (current_usage / usage_limit * 100.0).min(100.0)
-                            } else {
-                                0.0
-                            };
-                            (
-                                entry.id,
-                                Some(usage),
-                                current_usage,
-                                usage_limit,
-                                remaining,
-                                usage_percentage,
-                            )
-                        }
-                        Err(e) => {
-                            warn!("获取凭据 #{} 余额失败: {}", entry.id, e);
-                            (entry.id, None, 0.0, 0.0, 0.0, 0.0)
-                        }
+                let remaining = (entry.usage_limit - entry.current_usage).max(0.0);
+                let usage_percentage = if entry.usage_limit > 0.0 {
+                    (entry.current_usage / entry.usage_limit * 100.0).min(100.0)
+                } else {
+                    0.0
+                };
+
+                let latency = self.token_manager.latency_status(entry.id);
+
+                CredentialStatusItem {
+                    id: entry.id,
+                    priority: entry.priority,
+                    disabled: entry.disabled,
+                    failure_count: entry.failure_count,
+                    circuit_state: entry.circuit_state,
+                    circuit_open_until: entry.circuit_open_until.clone(),
+                    is_current: entry.id == snapshot.current_id,
+                    in_flight: connections::in_flight(entry.id),
+                    latency_p95_ms: latency.p95_ms,
+                    latency_demoted_until: latency.demoted_until.map(|t| t.to_rfc3339()),
+                    expires_at: entry.expires_at,
+                    auth_method: entry.auth_method,
+                    has_profile_arn: entry.has_profile_arn,
+                    machine_id: entry.machine_id,
+                    subscription_title: entry.subscription_title,
+                    current_usage: entry.current_usage,
+                    usage_limit: entry.usage_limit,
+                    remaining,
+                    usage_percentage,
+                    next_reset_at: entry.next_reset_at,
+                    balance_updated_at: entry.balance_updated_at,
+                    email: entry.email,
+                    kiro_version: entry.kiro_version,
+                    system_version: entry.system_version,
+                    node_version: entry.node_version,
+                    allowed_models: entry.allowed_models,
+                    extra_header_names: entry.extra_header_names,
+                }
+            })
+            .collect();
+
+        CredentialsStatusResponse {
+            total: snapshot.total,
+            available: snapshot.available,
+            current_id: snapshot.current_id,
+            credentials,
+        }
+    }
+
+    /// 开始批量刷新余额
+    ///
+    /// 按条件筛选凭据后在后台以有限并发查询上游并写入数据库，立即返回任务
+    pub async fn start_balance_refresh(
+        &self,
+        req: RefreshBalancesRequest,
+    ) -> Result<BalanceRefreshJob, AdminServiceError> {
+        let concurrency = req
+            .concurrency
+            .unwrap_or(DEFAULT_BALANCE_REFRESH_CONCURRENCY)
+            .clamp(1, MAX_BALANCE_REFRESH_CONCURRENCY);
+
+        let snapshot = self.token_manager.blocking(|tm| tm.snapshot()).await;
+        if let Some(ids) = &req.ids
+            && let Some(missing) = ids
+                .iter()
+                .find(|id| !snapshot.entries.iter().any(|e| e.id == **id))
+        {
+            return Err(AdminServiceError::NotFound { id: *missing });
+        }
+        let ids: Vec<u64> = snapshot
+            .entries
+            .iter()
+            .filter(|e| req.ids.as_ref().is_none_or(|ids| ids.contains(&e.id)))
+            .filter(|e| req.disabled.is_none_or(|disabled| e.disabled == disabled))
+            .map(|e| e.id)
+            .collect();
+
+        let job = self.balance_refresh_jobs.create(ids.len());
+        tracing::info!(
+            "开始批量刷新余额（任务 #{}，凭据 {} 个，并发 {}）",
+            job.id,
+            ids.len(),
+            concurrency
+        );
+
+        let service = self.clone();
+        let job_id = job.id;
+        tokio::spawn(async move {
+            futures::stream::iter(ids)
+                .for_each_concurrent(concurrency, |id| {
+                    let service = service.clone();
+                    async move {
+                        let result = service.refresh_balance(id).await.map(|_| ()).map_err(|e| {
+                            warn!("批量刷新凭据 #{} 余额失败: {}", id, e);
+                            e.to_string()
+                        });
+                        service.balance_refresh_jobs.record(job_id, id, result);
                     }
+                })
+                .await;
+            service.balance_refresh_jobs.finish(job_id);
+            tracing::info!("批量刷新余额完成（任务 #{}）", job_id);
+        });
+        Ok(job)
+    }
+
+    /// 获取批量刷新余额任务
+    pub fn get_balance_refresh_job(
+        &self,
+        job_id: u64,
+    ) -> Result<BalanceRefreshJob, AdminServiceError> {
+        self.balance_refresh_jobs
+            .get(job_id)
+            .ok_or(AdminServiceError::BalanceRefreshJobNotFound { id: job_id })
+    }
+
+    /// 发起设备授权登录，返回用户码与验证地址；用户授权后在后台自动添加凭据
+    pub async fn start_login(&self, req: LoginRequest) -> Result<LoginJob, AdminServiceError> {
+        let start_url = normalize_optional(req.start_url)
+            .unwrap_or_else(|| device_auth::BUILDER_ID_START_URL.to_string());
+        if !start_url.starts_with("https://") {
+            return Err(AdminServiceError::InvalidRequest(
+                "startUrl 必须是 https 地址".to_string(),
+            ));
+        }
+        let region = normalize_region(req.region)?;
+
+        let client = DeviceAuthClient::new(
+            self.token_manager.config(),
+            region.as_deref(),
+            self.token_manager.proxy().as_ref(),
+        )
+        .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        let auth = client
+            .start(&start_url)
+            .await
+            .map_err(|e| AdminServiceError::UpstreamError(e.to_string()))?;
+        let job = self.login_jobs.create(&start_url, &auth);
+        tracing::info!(
+            "已发起设备授权登录（任务 #{}，用户码 {}，{} 秒内有效）",
+            job.id,
+            auth.user_code,
+            auth.expires_in
+        );
+
+        let service = self.clone();
+        let job_id = job.id;
+        let (priority, allowed_models) = (req.priority, req.allowed_models);
+        tokio::spawn(async move {
+            let result = service
+                .complete_login(&client, &auth, region, priority, allowed_models)
+                .await;
+            match &result {
+                Ok(id) => {
+                    tracing::info!("设备授权登录完成（任务 #{}），已添加凭据 #{}", job_id, id)
+                }
+                Err(e) => warn!("设备授权登录失败（任务 #{}）: {}", job_id, e),
+            }
+            service.login_jobs.finish(job_id, result);
+        });
+        Ok(job)
+    }
+
+    /// 轮询直到用户完成授权，然后添加凭据（网络错误按尚未授权处理，直到设备码过期）
+    async fn complete_login(
+        &self,
+        client: &DeviceAuthClient,
+        auth: &DeviceAuthorization,
+        region: Option<String>,
+        priority: Option<u32>,
+        allowed_models: Option<Vec<String>>,
+    ) -> Result<u64, String> {
+        let deadline = Instant::now() + Duration::from_secs(auth.expires_in);
+        let mut interval = Duration::from_secs(auth.interval);
+        let token = loop {
+            tokio::time::sleep(interval).await;
+            if Instant::now() >= deadline {
+                return Err("设备码已过期，请重新发起登录".to_string());
+            }
+            match client.poll(auth).await.map_err(|e| e.to_string())? {
+                PollOutcome::Pending => {}
+                PollOutcome::SlowDown => {
+                    interval += Duration::from_secs(device_auth::SLOW_DOWN_SECS)
                 }
+                PollOutcome::Authorized(token) => break token,
+            }
+        };
+
+        self.add_credential(AddCredentialRequest {
+            refresh_token: token.refresh_token,
+            auth_method: Some("idc".to_string()),
+            client_id: Some(auth.client_id.clone()),
+            client_secret: Some(auth.client_secret.clone()),
+            access_key_id: None,
+            secret_access_key: None,
+            session_token: None,
+            region,
+            machine_id: None,
+            priority,
+            kiro_version: None,
+            system_version: None,
+            node_version: None,
+            allowed_models,
+            extra_headers: None,
+        })
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    /// 获取设备授权登录任务
+    pub fn get_login_job(&self, job_id: u64) -> Result<LoginJob, AdminServiceError> {
+        self.login_jobs
+            .get(job_id)
+            .ok_or(AdminServiceError::LoginJobNotFound { id: job_id })
+    }
+
+    /// 查询上游余额并更新数据库缓存（写入失败不影响返回结果）
+    async fn refresh_balance(&self, id: u64) -> anyhow::Result<UsageLimitsResponse> {
+        self.token_manager.refresh_balance(id).await
+    }
+
+    /// 设置凭据禁用状态
+    pub async fn set_disabled(&self, id: u64, disabled: bool) -> Result<(), AdminServiceError> {
+        self.token_manager
+            .blocking(move |tm| {
+                // 先获取当前凭据 ID，用于判断是否需要切换
+                let current_id = tm.snapshot().current_id;
+
+                tm.set_disabled(id, disabled)?;
+
+                // 只有禁用的是当前凭据时才尝试切换到下一个
+                if disabled && id == current_id {
+                    let _ = tm.switch_to_next();
+                }
+                Ok(())
             })
-            .collect::<Vec<_>>()
-            .into_iter()
-            .collect::<FuturesUnordered<_>>()
-            .collect()
-            .await;
+            .await
+            .map_err(|e| self.classify_error(e, id))
+    }
 
-        // 构建余额查找表
-        let balance_map: std::collections::HashMap<u64, _> = balances
+    /// 设置凭据优先级
+    pub async fn set_priority(&self, id: u64, priority: u32) -> Result<(), AdminServiceError> {
+        self.token_manager
+            .blocking(move |tm| tm.set_priority(id, priority))
+            .await
+            .map_err(|e| self.classify_error(e, id))
+    }
+
+    /// 批量设置凭据的禁用状态与优先级（单个事务，任一凭据不存在时整体不生效），返回更新的凭据数
+    pub async fn bulk_update(
+        &self,
+        updates: Vec<BulkCredentialUpdate>,
+    ) -> Result<usize, AdminServiceError> {
+        if updates.is_empty() {
+            return Err(AdminServiceError::InvalidRequest(
+                "更新列表不能为空".to_string(),
+            ));
+        }
+        if updates.len() > MAX_BULK_UPDATES {
+            return Err(AdminServiceError::InvalidRequest(format!(
+                "单次最多更新 {} 个凭据",
+                MAX_BULK_UPDATES
+            )));
+        }
+        let mut seen = std::collections::HashSet::new();
+        for update in &updates {
+            if !seen.insert(update.id) {
+                return Err(AdminServiceError::InvalidRequest(format!(
+                    "凭据 #{} 重复出现",
+                    update.id
+                )));
+            }
+            if update.disabled.is_none() && update.priority.is_none() {
+                return Err(AdminServiceError::InvalidRequest(format!(
+                    "凭据 #{} 未指定 disabled 或 priority",
+                    update.id
+                )));
+            }
+        }
+
+        let updates: Vec<db::CredentialUpdate> = updates
             .into_iter()
-            .map(
-                |(id, usage, current_usage, usage_limit, remaining, usage_percentage)| {
-                    (
-                        id,
-                        (
-                            usage,
-                            current_usage,
-                            usage_limit,
-                            remaining,
-                            usage_percentage,
-                        ),
-                    )
-                },
-            )
+            .map(|u| db::CredentialUpdate {
+                id: u.id,
+                disabled: u.disabled,
+                priority: u.priority,
+            })
             .collect();
+        let count = updates.len();
+        match self
+            .token_manager
+            .blocking(move |tm| tm.bulk_update(&updates))
+            .await
+        {
+            Ok(None) => Ok(count),
+            Ok(Some(id)) => Err(AdminServiceError::NotFound { id }),
+            Err(e) => Err(AdminServiceError::InternalError(e.to_string())),
+        }
+    }
+
+    /// 重置失败计数并重新启用
+    pub async fn reset_and_enable(&self, id: u64) -> Result<(), AdminServiceError> {
+        self.token_manager
+            .blocking(move |tm| tm.reset_and_enable(id))
+            .await
+            .map_err(|e| self.classify_error(e, id))
+    }
+
+    /// 设置凭据的客户端版本覆盖
+    pub async fn set_version_overrides(
+        &self,
+        id: u64,
+        req: SetVersionOverridesRequest,
+    ) -> Result<(), AdminServiceError> {
+        let kiro_version = normalize_optional(req.kiro_version);
+        let system_version = normalize_optional(req.system_version);
+        let node_version = normalize_optional(req.node_version);
+
+        self.token_manager
+            .blocking(move |tm| {
+                tm.set_version_overrides(
+                    id,
+                    kiro_version.as_deref(),
+                    system_version.as_deref(),
+                    node_version.as_deref(),
+                )
+            })
+            .await
+            .map_err(|e| self.classify_error(e, id))
+    }
+
+    /// 设置凭据的设备指纹，返回新的指纹
+    pub async fn set_machine_id(
+        &self,
+        id: u64,
+        req: SetMachineIdRequest,
+    ) -> Result<String, AdminServiceError> {
+        let machine_id = match (
+            normalize_optional(req.machine_id),
+            normalize_optional(req.seed),
+        ) {
+            (Some(_), Some(_)) => {
+                return Err(AdminServiceError::InvalidRequest(
+                    "machineId 与 seed 不能同时指定".to_string(),
+                ));
+            }
+            (Some(mid), None) => {
+                if !crate::kiro::machine_id::is_valid_machine_id(&mid) {
+                    return Err(AdminServiceError::InvalidRequest(
+                        "machineId 必须是有效的 UUID v4 格式".to_string(),
+                    ));
+                }
+                mid.to_lowercase()
+            }
+            (None, Some(seed)) => crate::kiro::machine_id::generate_uuid_from_seed(&seed),
+            (None, None) => uuid::Uuid::new_v4().to_string(),
+        };
+
+        let saved = machine_id.clone();
+        self.token_manager
+            .blocking(move |tm| tm.set_machine_id(id, &saved))
+            .await
+            .map_err(|e| self.classify_error(e, id))?;
+        Ok(machine_id)
+    }
+
+    /// 设置凭据允许使用的模型
+    pub async fn set_allowed_models(
+        &self,
+        id: u64,
+        req: SetAllowedModelsRequest,
+    ) -> Result<(), AdminServiceError> {
+        let allowed_models = normalize_models(req.allowed_models);
+        self.token_manager
+            .blocking(move |tm| tm.set_allowed_models(id, allowed_models))
+            .await
+            .map_err(|e| self.classify_error(e, id))
+    }
+
+    /// 设置凭据的自定义上游请求头
+    pub async fn set_extra_headers(
+        &self,
+        id: u64,
+        req: SetExtraHeadersRequest,
+    ) -> Result<(), AdminServiceError> {
+        let extra_headers = normalize_extra_headers(req.extra_headers)
+            .map_err(AdminServiceError::InvalidRequest)?;
+        self.token_manager
+            .blocking(move |tm| tm.set_extra_headers(id, extra_headers))
+            .await
+            .map_err(|e| self.classify_error(e, id))
+    }
+
+    /// 浏览对话记录（未启用 transcriptStore 时返回错误）
+    pub async fn list_transcripts(
+        &self,
+        query: ListTranscriptsQuery,
+    ) -> Result<TranscriptListResponse, AdminServiceError> {
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_REQUEST_LOG_LIMIT)
+            .clamp(1, MAX_REQUEST_LOG_LIMIT);
+        let offset = query.offset.unwrap_or(0);
+        let filter = TranscriptFilter {
+            model: normalize_optional(query.model),
+            request_id: normalize_optional(query.request_id),
+            limit,
+            offset,
+        };
+
+        let config = self.token_manager.config().clone();
+        let (total, transcripts) = self
+            .token_manager
+            .database()
+            .call(move |db| transcript::list(&config, db, &filter))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?
+            .ok_or_else(|| {
+                AdminServiceError::InvalidRequest("未启用对话记录（transcriptStore）".to_string())
+            })?;
+
+        Ok(TranscriptListResponse {
+            total,
+            limit,
+            offset,
+            transcripts,
+        })
+    }
+
+    /// 搜索请求日志
+    pub async fn search_request_logs(
+        &self,
+        query: SearchRequestLogsQuery,
+    ) -> Result<RequestLogSearchResponse, AdminServiceError> {
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_REQUEST_LOG_LIMIT)
+            .clamp(1, MAX_REQUEST_LOG_LIMIT);
+        let offset = query.offset.unwrap_or(0);
+
+        let filter = RequestLogFilter {
+            from: parse_time_param("from", query.from)?,
+            to: parse_time_param("to", query.to)?,
+            model: normalize_optional(query.model),
+            credential_id: query.credential_id,
+            status: query.status,
+            client_key: normalize_optional(query.client_key).map(|key| {
+                // 已经是指纹则直接使用，否则视为明文 Key 计算指纹
+                if key.len() == 16 && key.chars().all(|c| c.is_ascii_hexdigit()) {
+                    key.to_ascii_lowercase()
+                } else {
+                    auth::key_fingerprint(&key)
+                }
+            }),
+            min_latency_ms: query.min_latency_ms,
+            query: normalize_optional(query.q),
+            tag: normalize_optional(query.tag),
+            seed: query.seed,
+            request_id: normalize_optional(query.request_id),
+            limit,
+            offset,
+        };
+
+        let (total, logs) = self
+            .token_manager
+            .database()
+            .call(move |db| db.search_request_logs(&filter))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+
+        Ok(RequestLogSearchResponse {
+            total,
+            limit,
+            offset,
+            logs,
+        })
+    }
+
+    /// 按时间范围汇总用量（按凭据、按模型）
+    pub async fn get_usage(&self, query: UsageQuery) -> Result<UsageSummary, AdminServiceError> {
+        let filter = UsageFilter {
+            from: parse_time_param("from", query.from)?,
+            to: parse_time_param("to", query.to)?,
+            credential_id: query.credential_id,
+            model: normalize_optional(query.model),
+            tag: normalize_optional(query.tag),
+        };
+
+        self.token_manager
+            .database()
+            .call(move |db| db.summarize_usage(&filter))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))
+    }
+
+    /// 分析凭据池并生成调整建议
+    pub async fn get_recommendations(
+        &self,
+        query: RecommendationsQuery,
+    ) -> Result<RecommendationsResponse, AdminServiceError> {
+        let window_days = query
+            .days
+            .unwrap_or(DEFAULT_RECOMMENDATION_DAYS)
+            .clamp(1, MAX_RECOMMENDATION_DAYS);
+        let since = chrono::Utc::now() - chrono::Duration::days(window_days as i64);
+
+        let snapshot = self.token_manager.blocking(|tm| tm.snapshot()).await;
+        let (usage, health, machine_ids) = self
+            .token_manager
+            .database()
+            .call(move |db| {
+                let usage = db.summarize_usage(&UsageFilter {
+                    from: Some(since),
+                    ..Default::default()
+                })?;
+                let health = db.summarize_health_checks(since)?;
+                let machine_ids: std::collections::HashMap<u64, String> = db
+                    .load_credentials()?
+                    .iter()
+                    .filter_map(|c| {
+                        Some((
+                            c.id?,
+                            crate::kiro::machine_id::generate_from_credentials(c)?,
+                        ))
+                    })
+                    .collect();
+                anyhow::Ok((usage, health, machine_ids))
+            })
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+
+        let stats: Vec<CredentialStats> = snapshot
+            .entries
+            .iter()
+            .map(|entry| {
+                let totals = usage
+                    .credentials
+                    .iter()
+                    .find(|u| u.credential_id == Some(entry.id))
+                    .map(|u| &u.totals);
+                let (health_checks, health_failures) =
+                    health.get(&entry.id).copied().unwrap_or_default();
+                CredentialStats {
+                    id: entry.id,
+                    priority: entry.priority,
+                    disabled: entry.disabled,
+                    usage_percentage: (entry.usage_limit > 0.0)
+                        .then(|| entry.current_usage / entry.usage_limit * 100.0),
+                    machine_id: machine_ids.get(&entry.id).cloned(),
+                    requests: totals.map_or(0, |t| t.requests),
+                    errors: totals.map_or(0, |t| t.errors),
+                    health_checks,
+                    health_failures,
+                }
+            })
+            .collect();
+
+        Ok(RecommendationsResponse {
+            generated_at: chrono::Utc::now(),
+            window_days,
+            recommendations: recommendations::analyze(&stats),
+        })
+    }
+
+    /// 列出所有提示词模板
+    pub async fn list_prompt_templates(
+        &self,
+    ) -> Result<PromptTemplateListResponse, AdminServiceError> {
+        let templates = self
+            .token_manager
+            .database()
+            .call(|db| db.list_prompt_templates())
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        Ok(PromptTemplateListResponse { templates })
+    }
+
+    /// 创建或更新提示词模板，返回是否为新建
+    pub async fn upsert_prompt_template(
+        &self,
+        req: UpsertPromptTemplateRequest,
+    ) -> Result<bool, AdminServiceError> {
+        let name = req.name.trim().to_string();
+        if !PromptTemplate::is_valid_name(&name) {
+            return Err(AdminServiceError::InvalidRequest(
+                "模板名称只能包含 1-64 个字母、数字、-、_ 或 .".to_string(),
+            ));
+        }
+        if req.content.trim().is_empty() {
+            return Err(AdminServiceError::InvalidRequest(
+                "模板内容不能为空".to_string(),
+            ));
+        }
+
+        let template = PromptTemplate {
+            name,
+            content: req.content,
+            description: normalize_optional(req.description),
+            updated_at: chrono::Utc::now(),
+        };
+        self.token_manager
+            .database()
+            .call(move |db| db.upsert_prompt_template(&template))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))
+    }
+
+    /// 删除提示词模板
+    pub async fn delete_prompt_template(&self, name: &str) -> Result<(), AdminServiceError> {
+        let owned = name.to_string();
+        let deleted = self
+            .token_manager
+            .database()
+            .call(move |db| db.delete_prompt_template(&owned))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        if !deleted {
+            return Err(AdminServiceError::PromptTemplateNotFound {
+                name: name.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// 列出告警通知（按 ID 倒序）
+    pub async fn list_notifications(
+        &self,
+        query: NotificationsQuery,
+    ) -> Result<NotificationListResponse, AdminServiceError> {
+        let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+        let notifications = self
+            .token_manager
+            .database()
+            .call(move |db| db.list_notifications(query.status, limit))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        Ok(NotificationListResponse { notifications })
+    }
+
+    /// 重放未投递成功的告警通知（重置尝试次数，由发送任务重新投递）
+    pub async fn replay_notification(&self, id: u64) -> Result<(), AdminServiceError> {
+        let replayed = self
+            .token_manager
+            .database()
+            .call(move |db| db.replay_notification(id))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        match replayed {
+            None => Err(AdminServiceError::NotificationNotFound { id }),
+            Some(false) => Err(AdminServiceError::InvalidRequest(format!(
+                "告警通知 #{} 已投递成功，无需重放",
+                id
+            ))),
+            Some(true) => {
+                tracing::info!("已重放告警通知 #{}", id);
+                Ok(())
+            }
+        }
+    }
+
+    /// 列出所有客户端 API Key
+    pub async fn list_api_keys(&self) -> Result<ApiKeyListResponse, AdminServiceError> {
+        let keys = self
+            .token_manager
+            .database()
+            .call(|db| db.list_api_keys())
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        Ok(ApiKeyListResponse { keys })
+    }
+
+    /// 签发客户端 API Key（明文仅在此返回一次）
+    pub async fn create_api_key(
+        &self,
+        req: CreateApiKeyRequest,
+    ) -> Result<CreateApiKeyResponse, AdminServiceError> {
+        let name = req.name.trim().to_string();
+        if name.is_empty() || name.chars().count() > 64 {
+            return Err(AdminServiceError::InvalidRequest(
+                "名称不能为空且不能超过 64 个字符".to_string(),
+            ));
+        }
+        if req.rate_limit_per_minute == Some(0) {
+            return Err(AdminServiceError::InvalidRequest(
+                "rateLimitPerMinute 必须大于 0".to_string(),
+            ));
+        }
+        let now = chrono::Utc::now();
+        if req.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err(AdminServiceError::InvalidRequest(
+                "expiresAt 必须晚于当前时间".to_string(),
+            ));
+        }
+
+        let key = ApiKey::generate();
+        let key_hash = ApiKey::hash(&key);
+        let mut api_key = ApiKey {
+            id: 0,
+            name,
+            fingerprint: ApiKey::fingerprint_of(&key_hash),
+            key_hash,
+            allowed_models: normalize_models(req.allowed_models),
+            rate_limit_per_minute: req.rate_limit_per_minute,
+            expires_at: req.expires_at,
+            revoked: false,
+            created_at: now,
+        };
+        let record = api_key.clone();
+        api_key.id = self
+            .token_manager
+            .database()
+            .call(move |db| db.insert_api_key(&record))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        tracing::info!("已签发客户端 API Key #{} ({})", api_key.id, api_key.name);
+
+        Ok(CreateApiKeyResponse { key, api_key })
+    }
+
+    /// 吊销客户端 API Key（保留记录，立即失效）
+    pub async fn revoke_api_key(&self, id: u64) -> Result<(), AdminServiceError> {
+        let revoked = self
+            .token_manager
+            .database()
+            .call(move |db| db.revoke_api_key(id))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        if !revoked {
+            return Err(AdminServiceError::ApiKeyNotFound { id });
+        }
+        tracing::info!("已吊销客户端 API Key #{}", id);
+        Ok(())
+    }
+
+    /// 删除客户端 API Key
+    pub async fn delete_api_key(&self, id: u64) -> Result<(), AdminServiceError> {
+        let deleted = self
+            .token_manager
+            .database()
+            .call(move |db| db.delete_api_key(id))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        if !deleted {
+            return Err(AdminServiceError::ApiKeyNotFound { id });
+        }
+        Ok(())
+    }
+
+    /// 列出所有受限 Admin Token
+    pub async fn list_admin_tokens(&self) -> Result<AdminTokenListResponse, AdminServiceError> {
+        let tokens = self
+            .token_manager
+            .database()
+            .call(|db| db.list_admin_tokens())
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        Ok(AdminTokenListResponse { tokens })
+    }
+
+    /// 签发受限 Admin Token（明文仅在此返回一次）
+    pub async fn create_admin_token(
+        &self,
+        req: CreateAdminTokenRequest,
+    ) -> Result<CreateAdminTokenResponse, AdminServiceError> {
+        let name = req.name.trim().to_string();
+        if name.is_empty() || name.chars().count() > 64 {
+            return Err(AdminServiceError::InvalidRequest(
+                "名称不能为空且不能超过 64 个字符".to_string(),
+            ));
+        }
+        let mut scopes = Vec::new();
+        for value in &req.scopes {
+            let scope = AdminScope::parse(value.trim()).ok_or_else(|| {
+                AdminServiceError::InvalidRequest(format!(
+                    "未知的权限范围: {}（可选 {}）",
+                    value,
+                    AdminScope::ALL
+                        .iter()
+                        .map(|scope| scope.as_str())
+                        .collect::<Vec<_>>()
+                        .join("、")
+                ))
+            })?;
+            if !scopes.contains(&scope) {
+                scopes.push(scope);
+            }
+        }
+        if scopes.is_empty() {
+            return Err(AdminServiceError::InvalidRequest(
+                "scopes 至少需要一个权限范围".to_string(),
+            ));
+        }
+        let now = chrono::Utc::now();
+        if req.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err(AdminServiceError::InvalidRequest(
+                "expiresAt 必须晚于当前时间".to_string(),
+            ));
+        }
+
+        let token = AdminToken::generate();
+        let mut admin_token = AdminToken {
+            id: 0,
+            name,
+            token_hash: AdminToken::hash(&token),
+            scopes,
+            expires_at: req.expires_at,
+            revoked: false,
+            created_at: now,
+        };
+        let record = admin_token.clone();
+        admin_token.id = self
+            .token_manager
+            .database()
+            .call(move |db| db.insert_admin_token(&record))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        tracing::info!(
+            "已签发 Admin Token #{} ({})，权限范围: {}",
+            admin_token.id,
+            admin_token.name,
+            admin_token
+                .scopes
+                .iter()
+                .map(|scope| scope.as_str())
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+
+        Ok(CreateAdminTokenResponse { token, admin_token })
+    }
+
+    /// 吊销受限 Admin Token（保留记录，立即失效）
+    pub async fn revoke_admin_token(&self, id: u64) -> Result<(), AdminServiceError> {
+        let revoked = self
+            .token_manager
+            .database()
+            .call(move |db| db.revoke_admin_token(id))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        if !revoked {
+            return Err(AdminServiceError::AdminTokenNotFound { id });
+        }
+        tracing::info!("已吊销 Admin Token #{}", id);
+        Ok(())
+    }
+
+    /// 删除受限 Admin Token
+    pub async fn delete_admin_token(&self, id: u64) -> Result<(), AdminServiceError> {
+        let deleted = self
+            .token_manager
+            .database()
+            .call(move |db| db.delete_admin_token(id))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        if !deleted {
+            return Err(AdminServiceError::AdminTokenNotFound { id });
+        }
+        Ok(())
+    }
+
+    /// 按明文查找可用（未吊销、未过期）的受限 Admin Token
+    pub async fn find_active_admin_token(
+        &self,
+        token: &str,
+    ) -> Result<Option<AdminToken>, AdminServiceError> {
+        let token_hash = AdminToken::hash(token);
+        let found = self
+            .token_manager
+            .database()
+            .call(move |db| db.find_admin_token(&token_hash))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        Ok(found.filter(|token| token.is_active(chrono::Utc::now())))
+    }
+
+    /// 导出凭据快照（供热备实例同步）
+    pub async fn replication_snapshot(&self) -> Result<ReplicationSnapshot, AdminServiceError> {
+        self.token_manager
+            .database()
+            .call(replication::build_snapshot)
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))
+    }
+
+    /// 获取热备同步状态
+    pub fn replication_status(&self) -> ReplicationStatusResponse {
+        let standby = replication::is_standby();
+        ReplicationStatusResponse {
+            role: if standby { "standby" } else { "leader" }.to_string(),
+            leader_url: standby
+                .then(|| self.token_manager.config().replication_leader_url.clone())
+                .flatten(),
+            sync: replication::sync_status(),
+        }
+    }
+
+    /// 将热备实例提升为主实例
+    pub async fn promote(&self) -> Result<(), AdminServiceError> {
+        if !replication::promote() {
+            return Err(AdminServiceError::InvalidRequest(
+                "当前实例不是热备实例".to_string(),
+            ));
+        }
+        self.token_manager
+            .blocking(|tm| tm.select_highest_priority())
+            .await;
+        tracing::info!("热备实例已提升为主实例");
+        Ok(())
+    }
 
-        let credentials: Vec<CredentialStatusItem> = snapshot
-            .entries
-            .into_iter()
-            .map(|entry| {
-                let (usage, current_usage, usage_limit, remaining, usage_percentage) = balance_map
-                    .get(&entry.id)
-                    .cloned()
-                    .unwrap_or((None, 0.0, 0.0, 0.0, 0.0));
+    /// 订阅凭据状态变更事件
+    pub fn subscribe_credential_events(&self) -> broadcast::Receiver<CredentialEvent> {
+        self.token_manager.events().subscribe()
+    }
 
-                CredentialStatusItem {
-                    id: entry.id,
-                    priority: entry.priority,
-                    disabled: entry.disabled,
-                    failure_count: entry.failure_count,
-                    is_current: entry.id == snapshot.current_id,
-                    expires_at: entry.expires_at,
-                    auth_method: entry.auth_method,
-                    has_profile_arn: entry.has_profile_arn,
-                    machine_id: entry.machine_id,
-                    subscription_title: usage
-                        .as_ref()
-                        .and_then(|u| u.subscription_title().map(|s| s.to_string())),
-                    current_usage,
-                    usage_limit,
-                    remaining,
-                    usage_percentage,
-                    next_reset_at: usage.as_ref().and_then(|u| u.next_date_reset),
-                    email: entry.email,
-                }
-            })
-            .collect();
+    /// 获取运行指标
+    pub fn get_metrics(&self) -> MetricsResponse {
+        MetricsResponse {
+            panics_total: panic::panic_count(),
+            upstream: connections::stats(),
+            database: db::stats(),
+            deprecations: deprecation::stats(),
+            stats: stats::current(),
+        }
+    }
 
-        // 异步更新数据库中的余额（不阻塞响应）
-        let service = self.clone();
-        task::spawn(async move {
-            for (id, (usage, _, _, _, _)) in balance_map {
-                if let Some(usage) = usage
-                    && let Err(e) = service.token_manager.database().update_balance(
-                        id,
-                        usage.subscription_title(),
-                        usage.current_usage(),
-                        usage.usage_limit(),
-                        usage.next_date_reset,
-                    )
-                {
-                    warn!("异步更新余额到数据库失败 #{}: {}", id, e);
+    /// 获取统计摘要（读取内存快照，尚未生成时立即聚合一次）
+    ///
+    /// 指定请求标签时快照不适用，按该标签即时聚合
+    pub async fn get_stats(&self, query: StatsQuery) -> Result<StatsSummary, AdminServiceError> {
+        let result = match normalize_optional(query.tag) {
+            Some(tag) => {
+                self.token_manager
+                    .database()
+                    .call(move |db| stats::compute(db, Some(&tag)))
+                    .await
+            }
+            None => {
+                if let Some(summary) = stats::current() {
+                    return Ok(summary);
                 }
+                self.token_manager.database().call(stats::refresh).await
             }
-        });
+        };
+        result.map_err(|e| AdminServiceError::InternalError(e.to_string()))
+    }
 
-        CredentialsStatusResponse {
-            total: snapshot.total,
-            available: snapshot.available,
-            current_id: snapshot.current_id,
-            credentials,
-        }
+    /// 获取多实例租约状态
+    pub async fn get_leases(&self) -> Result<LeasesResponse, AdminServiceError> {
+        let leases = self
+            .token_manager
+            .database()
+            .call(|db| db.list_leases())
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        Ok(LeasesResponse {
+            instance_id: lease::instance_id().to_string(),
+            leases,
+        })
     }
 
-    /// 设置凭据禁用状态
-    pub fn set_disabled(&self, id: u64, disabled: bool) -> Result<(), AdminServiceError> {
-        // 先获取当前凭据 ID，用于判断是否需要切换
-        let snapshot = self.token_manager.snapshot();
-        let current_id = snapshot.current_id;
+    /// 获取当前生效的运行配置（敏感字段已脱敏）
+    pub fn get_config(&self) -> ConfigResponse {
+        let config = self.token_manager.config();
+        let overrides = RuntimeOverrides {
+            kiro_version: version::detected_version(),
+            promoted: config.replication_leader_url.is_some() && !replication::is_standby(),
+            circuit_breaker: Some(self.token_manager.circuit_breaker_settings()),
+        };
+        effective_config::build(config, &overrides)
+    }
 
-        self.token_manager
-            .set_disabled(id, disabled)
-            .map_err(|e| self.classify_error(e, id))?;
+    /// 获取当前熔断参数
+    pub fn get_circuit_breaker(&self) -> CircuitBreakerSettings {
+        self.token_manager.circuit_breaker_settings()
+    }
 
-        // 只有禁用的是当前凭据时才尝试切换到下一个
-        if disabled && id == current_id {
-            let _ = self.token_manager.switch_to_next();
+    /// 运行时修改熔断参数（未提供的字段保持不变）
+    ///
+    /// 修改只保存在当前实例的内存中，不写回配置文件，也不同步到其他实例
+    pub fn update_circuit_breaker(
+        &self,
+        req: UpdateCircuitBreakerRequest,
+    ) -> Result<CircuitBreakerSettings, AdminServiceError> {
+        if req.failure_threshold == Some(0) {
+            return Err(AdminServiceError::InvalidRequest(
+                "failureThreshold 必须大于 0".to_string(),
+            ));
         }
-        Ok(())
+        let current = self.token_manager.circuit_breaker_settings();
+        let settings = CircuitBreakerSettings {
+            failure_threshold: req.failure_threshold.unwrap_or(current.failure_threshold),
+            window_secs: req.window_secs.unwrap_or(current.window_secs),
+            open_secs: req.open_secs.unwrap_or(current.open_secs),
+            open_secs_by_auth_method: req
+                .open_secs_by_auth_method
+                .unwrap_or(current.open_secs_by_auth_method),
+        };
+        settings.validate().map_err(|e| {
+            AdminServiceError::InvalidRequest(format!("openSecsByAuthMethod {}", e))
+        })?;
+        Ok(self.token_manager.update_circuit_breaker(settings))
     }
 
-    /// 设置凭据优先级
-    pub fn set_priority(&self, id: u64, priority: u32) -> Result<(), AdminServiceError> {
-        self.token_manager
-            .set_priority(id, priority)
-            .map_err(|e| self.classify_error(e, id))
+    /// 获取 Token 刷新锁状态
+    pub fn refresh_lock_status(&self) -> RefreshLockStatus {
+        self.token_manager.refresh_lock_status()
     }
 
-    /// 重置失败计数并重新启用
-    pub fn reset_and_enable(&self, id: u64) -> Result<(), AdminServiceError> {
+    /// 强制释放 Token 刷新锁，返回被取消刷新的凭据 ID
+    pub fn release_refresh_lock(&self) -> Result<u64, AdminServiceError> {
         self.token_manager
-            .reset_and_enable(id)
-            .map_err(|e| self.classify_error(e, id))
+            .force_release_refresh_lock()
+            .ok_or_else(|| {
+                AdminServiceError::InvalidRequest("当前没有进行中的 Token 刷新".to_string())
+            })
     }
 
     /// 获取凭据余额
     pub async fn get_balance(&self, id: u64) -> Result<BalanceResponse, AdminServiceError> {
         let usage = self
-            .token_manager
-            .get_usage_limits_for(id)
+            .refresh_balance(id)
             .await
             .map_err(|e| self.classify_balance_error(e, id))?;
 
@@ -194,17 +1142,6 @@ impl AdminService {
             0.0
         };
 
-        // 更新余额到数据库
-        if let Err(e) = self.token_manager.database().update_balance(
-            id,
-            usage.subscription_title(),
-            current_usage,
-            usage_limit,
-            usage.next_date_reset,
-        ) {
-            tracing::warn!("更新余额到数据库失败（不影响本次请求）: {}", e);
-        }
-
         Ok(BalanceResponse {
             id,
             subscription_title: usage.subscription_title().map(|s| s.to_string()),
@@ -216,18 +1153,80 @@ impl AdminService {
         })
     }
 
+    /// 检查凭据健康状态（调用上游 getUsageLimits 并记录结果）
+    ///
+    /// 上游调用失败时返回不健康的检查结果而非错误
+    pub async fn check_credential(&self, id: u64) -> Result<HealthCheck, AdminServiceError> {
+        self.ensure_credential_exists(id).await?;
+        Ok(health_check::probe(&self.token_manager, id, HealthCheckTrigger::Manual).await)
+    }
+
+    /// 获取凭据的健康检查历史（按检查时间倒序）
+    pub async fn list_health_checks(
+        &self,
+        id: u64,
+        query: HealthChecksQuery,
+    ) -> Result<HealthCheckListResponse, AdminServiceError> {
+        self.ensure_credential_exists(id).await?;
+        let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+        let checks = self
+            .token_manager
+            .database()
+            .call(move |db| db.list_health_checks(id, limit))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        Ok(HealthCheckListResponse { checks })
+    }
+
+    /// 确认凭据存在
+    async fn ensure_credential_exists(&self, id: u64) -> Result<(), AdminServiceError> {
+        let credential = self
+            .token_manager
+            .database()
+            .call(move |db| db.get_credential(id))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        match credential {
+            Some(_) => Ok(()),
+            None => Err(AdminServiceError::NotFound { id }),
+        }
+    }
+
     /// 添加新凭据
     ///
     /// 先获取 token 和余额，然后一次性写入数据库
     pub async fn add_credential(
         &self,
-        refresh_token: String,
-        auth_method: Option<String>,
-        client_id: Option<String>,
-        client_secret: Option<String>,
-        machine_id: Option<String>,
-        priority: Option<u32>,
+        req: AddCredentialRequest,
     ) -> Result<u64, AdminServiceError> {
+        let AddCredentialRequest {
+            refresh_token,
+            auth_method,
+            client_id,
+            client_secret,
+            access_key_id,
+            secret_access_key,
+            session_token,
+            region,
+            machine_id,
+            priority,
+            kiro_version,
+            system_version,
+            node_version,
+            allowed_models,
+            extra_headers,
+        } = req;
+        let region = normalize_region(region)?;
+        let access_key_id = normalize_optional(access_key_id);
+        let secret_access_key = normalize_optional(secret_access_key);
+        let session_token = normalize_optional(session_token);
+        let allowed_models = normalize_models(allowed_models);
+        let extra_headers =
+            normalize_extra_headers(extra_headers).map_err(AdminServiceError::InvalidRequest)?;
+        let kiro_version = normalize_optional(kiro_version);
+        let system_version = normalize_optional(system_version);
+        let node_version = normalize_optional(node_version);
+
         // 验证 machine_id 格式（如果提供）
         if let Some(ref mid) = machine_id
             && !crate::kiro::machine_id::is_valid_machine_id(mid)
@@ -238,11 +1237,24 @@ impl AdminService {
         }
 
         // 检查 client_id 是否已存在（去重）
-        if let Some(ref cid) = client_id
+        if let Some(cid) = client_id.clone()
+            && self
+                .token_manager
+                .database()
+                .call(move |db| db.client_id_exists(&cid))
+                .await
+                .map_err(|e| AdminServiceError::InternalError(e.to_string()))?
+        {
+            return Err(AdminServiceError::InvalidRequest("账号已存在".to_string()));
+        }
+
+        // 检查 accessKeyId 是否已存在（去重）
+        if let Some(key) = access_key_id.clone()
             && self
                 .token_manager
                 .database()
-                .client_id_exists(cid)
+                .call(move |db| db.access_key_id_exists(&key))
+                .await
                 .map_err(|e| AdminServiceError::InternalError(e.to_string()))?
         {
             return Err(AdminServiceError::InvalidRequest("账号已存在".to_string()));
@@ -250,12 +1262,31 @@ impl AdminService {
 
         // auth_method 默认为 "idc"
         let auth_method = auth_method.unwrap_or_else(|| "idc".to_string());
+        let is_iam = auth_method.eq_ignore_ascii_case("iam");
+
+        // IdC 刷新需要 clientId/clientSecret，缺失时凭据无法使用
+        let is_idc = matches!(auth_method.to_lowercase().as_str(), "idc" | "builder-id");
+        if is_idc {
+            for (name, value) in [("clientId", &client_id), ("clientSecret", &client_secret)] {
+                if value.as_deref().is_none_or(|v| v.trim().is_empty()) {
+                    return Err(AdminServiceError::InvalidRequest(format!(
+                        "IdC 凭据需要提供 {}",
+                        name
+                    )));
+                }
+            }
+        }
 
-        // machine_id 默认为从 refreshToken 生成的 UUID
+        // machine_id 默认为从 refreshToken（IAM 凭据为 accessKeyId）生成的 UUID
         let machine_id = machine_id.or_else(|| {
+            let seed = if is_iam {
+                access_key_id.as_deref().unwrap_or_default()
+            } else {
+                &refresh_token
+            };
             Some(crate::kiro::machine_id::generate_uuid_from_seed(&format!(
                 "KotlinNativeAPI/{}",
-                refresh_token
+                seed
             )))
         });
 
@@ -263,13 +1294,23 @@ impl AdminService {
         let temp_cred = KiroCredentials {
             id: None,
             access_token: None,
-            refresh_token: Some(refresh_token.clone()),
+            refresh_token: (!is_iam).then(|| refresh_token.clone()),
             profile_arn: None,
             expires_at: None,
             auth_method: Some(auth_method.clone()),
             client_id: client_id.clone(),
             client_secret: client_secret.clone(),
+            access_key_id: access_key_id.clone(),
+            secret_access_key: secret_access_key.clone(),
+            session_token: session_token.clone(),
+            region: region.clone(),
             machine_id: machine_id.clone(),
+            kiro_version: kiro_version.clone(),
+            system_version: system_version.clone(),
+            node_version: node_version.clone(),
+            allowed_models: None,
+            // Token 刷新同样需要携带网关请求头
+            extra_headers: extra_headers.clone(),
             priority: 0,
             disabled: false,
             failure_count: 0,
@@ -281,25 +1322,53 @@ impl AdminService {
             email: None,
         };
 
-        // 刷新 token
-        let refreshed = crate::kiro::token_manager::refresh_token(
-            &temp_cred,
-            self.token_manager.config(),
-            self.token_manager.proxy().as_ref(),
-        )
-        .await
-        .map_err(|e| AdminServiceError::UpstreamError(format!("刷新 Token 失败: {}", e)))?;
+        // 未启用添加时校验：检查 refreshToken 格式后直接写入，首次使用时再刷新 Token
+        // IAM 凭据需要访问密钥，其他凭据需要格式正确的 refreshToken
+        if is_iam {
+            crate::kiro::token_manager::validate_iam_keys(&temp_cred)
+                .map_err(|e| AdminServiceError::InvalidRequest(e.to_string()))?;
+        }
+        if !self.token_manager.config().validate_credential_on_add {
+            if !is_iam {
+                crate::kiro::token_manager::validate_refresh_token(&temp_cred)
+                    .map_err(|e| AdminServiceError::InvalidRequest(e.to_string()))?;
+            }
+            let cred = KiroCredentials {
+                allowed_models,
+                priority: priority.unwrap_or(0),
+                ..temp_cred
+            };
+            let id = self
+                .token_manager
+                .blocking(move |tm| tm.add_credential(cred))
+                .await
+                .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+            tracing::info!("凭据 #{} 已添加（未校验，首次使用时刷新 Token）", id);
+            return Ok(id);
+        }
 
-        let token = refreshed
-            .access_token
-            .as_ref()
-            .ok_or_else(|| AdminServiceError::InternalError("刷新后无 access_token".to_string()))?;
+        // 通过一次真实刷新校验凭据（IdC 凭据同时校验 clientId/clientSecret），失败时不写入；
+        // IAM 凭据无需刷新，由下面的签名请求校验访问密钥
+        let refreshed = if is_iam {
+            temp_cred.clone()
+        } else {
+            crate::kiro::token_manager::refresh_token(
+                &temp_cred,
+                self.token_manager.config(),
+                self.token_manager.proxy().as_ref(),
+            )
+            .await
+            .map_err(|e| AdminServiceError::UpstreamError(format!("刷新 Token 失败: {}", e)))?
+        };
+
+        let token = crate::kiro::token_manager::access_token_of(&refreshed)
+            .map_err(|_| AdminServiceError::InternalError("刷新后无 access_token".to_string()))?;
 
         // 获取余额和邮箱
         let usage = crate::kiro::token_manager::get_usage_limits(
             &refreshed,
             self.token_manager.config(),
-            token,
+            &token,
             self.token_manager.proxy().as_ref(),
         )
         .await
@@ -311,13 +1380,24 @@ impl AdminService {
         let cred = KiroCredentials {
             id: None,
             access_token: refreshed.access_token,
-            refresh_token: Some(refreshed.refresh_token.unwrap_or(refresh_token)),
+            refresh_token: refreshed
+                .refresh_token
+                .or_else(|| (!is_iam).then_some(refresh_token)),
             profile_arn: refreshed.profile_arn,
             expires_at: refreshed.expires_at,
             auth_method: Some(auth_method),
             client_id,
             client_secret,
+            access_key_id,
+            secret_access_key,
+            session_token,
+            region,
             machine_id,
+            kiro_version,
+            system_version,
+            node_version,
+            allowed_models,
+            extra_headers,
             priority: priority.unwrap_or(0),
             disabled: false,
             failure_count: 0,
@@ -331,22 +1411,204 @@ impl AdminService {
 
         let id = self
             .token_manager
-            .add_credential(cred)
+            .blocking(move |tm| tm.add_credential(cred))
+            .await
             .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
 
         tracing::info!("凭据 #{} 已添加并获取余额", id);
         Ok(id)
     }
 
+    /// 导出所有凭据（提供口令时加密）
+    pub async fn export_credentials(
+        &self,
+        passphrase: Option<String>,
+    ) -> Result<serde_json::Value, AdminServiceError> {
+        let credentials = self
+            .token_manager
+            .database()
+            .call(|db| db.load_credentials())
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+        let count = credentials.len();
+        let credentials = transfer::to_portable(credentials);
+
+        let exported = match normalize_optional(passphrase) {
+            Some(passphrase) => {
+                let plaintext = serde_json::to_vec(&credentials)
+                    .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+                let envelope =
+                    task::spawn_blocking(move || transfer::encrypt(&plaintext, &passphrase))
+                        .await
+                        .map_err(|e| AdminServiceError::InternalError(e.to_string()))?
+                        .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+                serde_json::to_value(envelope)
+            }
+            None => serde_json::to_value(credentials),
+        }
+        .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+
+        tracing::info!("已导出 {} 个凭据", count);
+        Ok(exported)
+    }
+
+    /// 批量导入凭据（跳过 refreshToken 已存在的凭据，不查询上游）
+    pub async fn import_credentials(
+        &self,
+        payload: ImportPayload,
+        passphrase: Option<String>,
+    ) -> Result<ImportCredentialsResponse, AdminServiceError> {
+        let credentials = match payload {
+            ImportPayload::Plain(credentials) => credentials,
+            ImportPayload::Encrypted(envelope) => {
+                let passphrase = normalize_optional(passphrase).ok_or_else(|| {
+                    AdminServiceError::InvalidRequest(format!(
+                        "导入加密数据需要通过 {} 请求头提供口令",
+                        transfer::PASSPHRASE_HEADER
+                    ))
+                })?;
+                let plaintext =
+                    task::spawn_blocking(move || transfer::decrypt(&envelope, &passphrase))
+                        .await
+                        .map_err(|e| AdminServiceError::InternalError(e.to_string()))?
+                        .map_err(|e| AdminServiceError::InvalidRequest(e.to_string()))?;
+                serde_json::from_slice(&plaintext).map_err(|e| {
+                    AdminServiceError::InvalidRequest(format!("解密后的数据不是凭据数组: {}", e))
+                })?
+            }
+        };
+
+        for (index, credentials) in credentials.iter().enumerate() {
+            if let Some(mid) = &credentials.machine_id
+                && !crate::kiro::machine_id::is_valid_machine_id(mid)
+            {
+                return Err(AdminServiceError::InvalidRequest(format!(
+                    "第 {} 个凭据的 machineId 必须是有效的 UUID v4 格式",
+                    index + 1
+                )));
+            }
+        }
+        // 只导入可移植字段，运行时状态重新开始计算
+        let credentials = transfer::to_portable(credentials);
+
+        let (imported, skipped) = self
+            .token_manager
+            .blocking(move |tm| {
+                let counts = tm.database().import_credentials(&credentials)?;
+                if counts.0 > 0 {
+                    tm.select_highest_priority();
+                }
+                Ok::<_, anyhow::Error>(counts)
+            })
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+
+        tracing::info!("已导入 {} 个凭据，跳过 {} 个", imported, skipped);
+        Ok(ImportCredentialsResponse {
+            success: true,
+            message: format!("已导入 {} 个凭据，跳过 {} 个", imported, skipped),
+            imported,
+            skipped,
+        })
+    }
+
     /// 删除凭据
-    pub fn delete_credential(&self, id: u64) -> Result<(), AdminServiceError> {
-        match self.token_manager.delete_credential(id) {
+    pub async fn delete_credential(&self, id: u64) -> Result<(), AdminServiceError> {
+        match self
+            .token_manager
+            .blocking(move |tm| tm.delete_credential(id))
+            .await
+        {
             Ok(true) => Ok(()),
             Ok(false) => Err(AdminServiceError::NotFound { id }),
             Err(e) => Err(AdminServiceError::InternalError(e.to_string())),
         }
     }
 
+    /// 开始排空凭据
+    ///
+    /// 立即禁用凭据阻止新请求（标记为排空中，不会被熔断半开探测恢复），
+    /// 在后台等待其上游连接结束后执行 `action`；超时后凭据保持禁用，不执行删除
+    pub async fn start_drain(
+        &self,
+        id: u64,
+        action: DrainAction,
+        timeout_secs: Option<u64>,
+    ) -> Result<DrainJob, AdminServiceError> {
+        let timeout_secs = timeout_secs
+            .unwrap_or(DEFAULT_DRAIN_TIMEOUT_SECS)
+            .min(MAX_DRAIN_TIMEOUT_SECS);
+        self.token_manager
+            .blocking(move |tm| {
+                let current_id = tm.snapshot().current_id;
+                tm.set_draining(id)?;
+                if id == current_id {
+                    let _ = tm.switch_to_next();
+                }
+                Ok(())
+            })
+            .await
+            .map_err(|e| self.classify_error(e, id))?;
+
+        let job = self.drain_jobs.create(id, action);
+        tracing::info!(
+            "开始排空凭据 #{}（任务 #{}，进行中的连接 {}，超时 {} 秒）",
+            id,
+            job.id,
+            job.in_flight,
+            timeout_secs
+        );
+
+        let service = self.clone();
+        let job_id = job.id;
+        tokio::spawn(async move {
+            service
+                .run_drain(job_id, id, action, Duration::from_secs(timeout_secs))
+                .await
+        });
+        Ok(job)
+    }
+
+    /// 等待凭据的上游连接结束并执行操作
+    async fn run_drain(&self, job_id: u64, id: u64, action: DrainAction, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        while connections::in_flight(id) > 0 {
+            if Instant::now() >= deadline {
+                tracing::warn!(
+                    "排空凭据 #{} 超时，仍有 {} 个连接进行中，凭据保持禁用",
+                    id,
+                    connections::in_flight(id)
+                );
+                self.drain_jobs.finish(job_id, DrainState::TimedOut, None);
+                return;
+            }
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+
+        let result = match action {
+            DrainAction::Disable => Ok(()),
+            DrainAction::Delete => self.delete_credential(id).await,
+        };
+        match result {
+            Ok(()) => {
+                tracing::info!("凭据 #{} 排空完成", id);
+                self.drain_jobs.finish(job_id, DrainState::Completed, None);
+            }
+            Err(e) => {
+                tracing::warn!("凭据 #{} 排空后执行操作失败: {}", id, e);
+                self.drain_jobs
+                    .finish(job_id, DrainState::Failed, Some(e.to_string()));
+            }
+        }
+    }
+
+    /// 获取排空任务
+    pub fn get_drain_job(&self, job_id: u64) -> Result<DrainJob, AdminServiceError> {
+        self.drain_jobs
+            .get(job_id)
+            .ok_or(AdminServiceError::DrainJobNotFound { id: job_id })
+    }
+
     /// 分类简单操作错误（set_disabled, set_priority, reset_and_enable）
     fn classify_error(&self, e: anyhow::Error, id: u64) -> AdminServiceError {
         let msg = e.to_string();
@@ -390,3 +1652,473 @@ impl AdminService {
         }
     }
 }
+
+/// 解析 RFC3339 时间参数，空字符串视为未设置
+fn parse_time_param(
+    name: &str,
+    value: Option<String>,
+) -> Result<Option<chrono::DateTime<chrono::Utc>>, AdminServiceError> {
+    normalize_optional(value)
+        .map(|v| {
+            chrono::DateTime::parse_from_rfc3339(&v)
+                .map(|t| t.with_timezone(&chrono::Utc))
+                .map_err(|e| {
+                    AdminServiceError::InvalidRequest(format!(
+                        "{} 不是有效的 RFC3339 时间: {}",
+                        name, e
+                    ))
+                })
+        })
+        .transpose()
+}
+
+/// 规范化可选字符串参数：去除首尾空白，空字符串视为未设置
+fn normalize_optional(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// 规范化 OIDC 区域（用于拼接 OIDC 服务地址，只允许小写字母、数字与连字符）
+fn normalize_region(region: Option<String>) -> Result<Option<String>, AdminServiceError> {
+    let region = normalize_optional(region);
+    if let Some(region) = &region
+        && !region
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Err(AdminServiceError::InvalidRequest(format!(
+            "region 格式无效: {}",
+            region
+        )));
+    }
+    Ok(region)
+}
+
+/// 规范化模型列表（去除空白与空项，空列表视为不限制）
+fn normalize_models(models: Option<Vec<String>>) -> Option<Vec<String>> {
+    models
+        .map(|list| {
+            list.into_iter()
+                .map(|m| m.trim().to_string())
+                .filter(|m| !m.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .filter(|list| !list.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::db::Database;
+    use crate::model::config::Config;
+
+    fn service(config: Config) -> AdminService {
+        let db = Database::open_in_memory().unwrap();
+        let manager = MultiTokenManager::new(config, db, None).unwrap();
+        AdminService::new(Arc::new(manager))
+    }
+
+    fn request(value: serde_json::Value) -> AddCredentialRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_update_circuit_breaker_rejects_unknown_auth_method() {
+        let service = service(Config::default());
+        let update = |methods: serde_json::Value| {
+            service.update_circuit_breaker(
+                serde_json::from_value(serde_json::json!({ "openSecsByAuthMethod": methods }))
+                    .unwrap(),
+            )
+        };
+
+        let err = update(serde_json::json!({"oauth": 60})).unwrap_err();
+        assert_eq!(err.status_code(), axum::http::StatusCode::BAD_REQUEST);
+        assert!(
+            service
+                .get_circuit_breaker()
+                .open_secs_by_auth_method
+                .is_empty()
+        );
+
+        let settings = update(serde_json::json!({"builder-id": 3600})).unwrap();
+        assert_eq!(
+            settings.open_secs_by_auth_method,
+            std::collections::HashMap::from([("idc".to_string(), 3600)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_credential_without_validation() {
+        let service = service(Config::default());
+        let refresh_token = "r".repeat(120);
+
+        let err = service
+            .add_credential(request(serde_json::json!({
+                "refreshToken": refresh_token,
+                "clientId": "client",
+            })))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, AdminServiceError::InvalidRequest(ref msg) if msg.contains("clientSecret"))
+        );
+
+        let err = service
+            .add_credential(request(serde_json::json!({
+                "refreshToken": "short",
+                "authMethod": "social",
+            })))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AdminServiceError::InvalidRequest(_)));
+
+        let id = service
+            .add_credential(request(serde_json::json!({
+                "refreshToken": refresh_token,
+                "clientId": "client",
+                "clientSecret": "secret",
+                "priority": 3,
+            })))
+            .await
+            .unwrap();
+        let cred = service
+            .token_manager
+            .database()
+            .get_credential(id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(cred.priority, 3);
+        assert!(cred.access_token.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_add_iam_credential_without_validation() {
+        let service = service(Config::default());
+
+        let err = service
+            .add_credential(request(serde_json::json!({
+                "authMethod": "iam",
+                "accessKeyId": "AKIDEXAMPLE",
+            })))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, AdminServiceError::InvalidRequest(ref msg) if msg.contains("secretAccessKey"))
+        );
+
+        let id = service
+            .add_credential(request(serde_json::json!({
+                "authMethod": "iam",
+                "accessKeyId": "AKIDEXAMPLE",
+                "secretAccessKey": "secret",
+            })))
+            .await
+            .unwrap();
+        let cred = service
+            .token_manager
+            .database()
+            .get_credential(id)
+            .unwrap()
+            .unwrap();
+        assert!(cred.is_iam());
+        assert!(cred.refresh_token.is_none());
+        assert_eq!(cred.access_key_id.as_deref(), Some("AKIDEXAMPLE"));
+        assert!(cred.machine_id.is_some());
+
+        let err = service
+            .add_credential(request(serde_json::json!({
+                "authMethod": "iam",
+                "accessKeyId": "AKIDEXAMPLE",
+                "secretAccessKey": "secret",
+            })))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AdminServiceError::InvalidRequest(ref msg) if msg == "账号已存在"));
+    }
+
+    #[tokio::test]
+    async fn test_admin_token_lifecycle() {
+        let service = service(Config::default());
+        let create = |value: serde_json::Value| {
+            service.create_admin_token(serde_json::from_value(value).unwrap())
+        };
+
+        assert!(matches!(
+            create(serde_json::json!({"name": "backup", "scopes": []})).await,
+            Err(AdminServiceError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            create(serde_json::json!({"name": "backup", "scopes": ["credentials:delete"]})).await,
+            Err(AdminServiceError::InvalidRequest(_))
+        ));
+
+        let created = create(serde_json::json!({
+            "name": "backup",
+            "scopes": ["credentials:read", " stats:read", "credentials:read"],
+        }))
+        .await
+        .unwrap();
+        assert_eq!(
+            created.admin_token.scopes,
+            vec![AdminScope::CredentialsRead, AdminScope::StatsRead]
+        );
+        let body = serde_json::to_value(&created).unwrap();
+        assert!(body.get("tokenHash").is_none());
+        assert_eq!(
+            body["scopes"],
+            serde_json::json!(["credentials:read", "stats:read"])
+        );
+
+        let found = service
+            .find_active_admin_token(&created.token)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.id, created.admin_token.id);
+        assert!(
+            service
+                .find_active_admin_token("kiro-admin-unknown")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let id = created.admin_token.id;
+        service.revoke_admin_token(id).await.unwrap();
+        assert!(
+            service
+                .find_active_admin_token(&created.token)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        service.delete_admin_token(id).await.unwrap();
+        assert!(matches!(
+            service.delete_admin_token(id).await,
+            Err(AdminServiceError::AdminTokenNotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_api_key_lifecycle() {
+        let service = service(Config::default());
+        let create = |value: serde_json::Value| {
+            service.create_api_key(serde_json::from_value(value).unwrap())
+        };
+
+        assert!(matches!(
+            create(serde_json::json!({"name": "  "})).await,
+            Err(AdminServiceError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            create(serde_json::json!({"name": "bob", "expiresAt": "2020-01-01T00:00:00Z"})).await,
+            Err(AdminServiceError::InvalidRequest(_))
+        ));
+
+        let created = create(serde_json::json!({
+            "name": "alice",
+            "allowedModels": [" claude-sonnet-* ", ""],
+            "rateLimitPerMinute": 30,
+        }))
+        .await
+        .unwrap();
+        assert_eq!(
+            created.api_key.fingerprint,
+            auth::key_fingerprint(&created.key)
+        );
+        assert_eq!(
+            created.api_key.allowed_models,
+            Some(vec!["claude-sonnet-*".to_string()])
+        );
+        let body = serde_json::to_value(&created).unwrap();
+        assert!(body.get("keyHash").is_none());
+        assert_eq!(body["name"], "alice");
+
+        let id = created.api_key.id;
+        service.revoke_api_key(id).await.unwrap();
+        assert!(service.list_api_keys().await.unwrap().keys[0].revoked);
+        service.delete_api_key(id).await.unwrap();
+        assert!(matches!(
+            service.delete_api_key(id).await,
+            Err(AdminServiceError::ApiKeyNotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_bulk_update() {
+        let service = service(Config::default());
+        let mut ids = Vec::new();
+        for i in 0..3 {
+            let id = service
+                .add_credential(request(serde_json::json!({
+                    "refreshToken": format!("{}{}", i, "r".repeat(120)),
+                    "authMethod": "social",
+                })))
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+        let updates = |value: serde_json::Value| serde_json::from_value(value).unwrap();
+        let db = service.token_manager.database();
+
+        // 任一凭据不存在时整体不生效
+        assert!(matches!(
+            service
+                .bulk_update(updates(serde_json::json!([
+                    { "id": ids[0], "priority": 9 },
+                    { "id": 999, "disabled": true },
+                ])))
+                .await,
+            Err(AdminServiceError::NotFound { id: 999 })
+        ));
+        assert_eq!(db.get_credential(ids[0]).unwrap().unwrap().priority, 0);
+
+        for invalid in [
+            serde_json::json!([]),
+            serde_json::json!([{ "id": ids[0] }]),
+            serde_json::json!([{ "id": ids[0], "priority": 1 }, { "id": ids[0], "priority": 2 }]),
+        ] {
+            assert!(matches!(
+                service.bulk_update(updates(invalid)).await,
+                Err(AdminServiceError::InvalidRequest(_))
+            ));
+        }
+
+        let count = service
+            .bulk_update(updates(serde_json::json!([
+                { "id": ids[0], "priority": 2 },
+                { "id": ids[1], "priority": 0, "disabled": true },
+                { "id": ids[2], "priority": 1 },
+            ])))
+            .await
+            .unwrap();
+        assert_eq!(count, 3);
+        let stored: Vec<(u32, bool)> = ids
+            .iter()
+            .map(|id| {
+                let cred = db.get_credential(*id).unwrap().unwrap();
+                (cred.priority, cred.disabled)
+            })
+            .collect();
+        assert_eq!(stored, vec![(2, false), (0, true), (1, false)]);
+        assert_eq!(service.token_manager.snapshot().current_id, ids[2]);
+    }
+
+    #[tokio::test]
+    async fn test_drain_is_not_recovered_by_circuit_breaker() {
+        use crate::kiro::token_manager::RequestPriority;
+
+        let service = service(Config {
+            circuit_breaker_open_secs: 0,
+            ..Config::default()
+        });
+        let db = service.token_manager.database();
+        let ids: Vec<u64> = (0..2)
+            .map(|priority| {
+                db.insert_credential(&KiroCredentials {
+                    refresh_token: Some(format!("token{}", priority)),
+                    access_token: Some(format!("access{}", priority)),
+                    expires_at: Some(
+                        (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339(),
+                    ),
+                    priority,
+                    ..Default::default()
+                })
+                .unwrap()
+            })
+            .collect();
+        let next = || async {
+            let lease = service
+                .token_manager
+                .lease(None, RequestPriority::Standard, None)
+                .await
+                .unwrap();
+            let id = lease.id();
+            lease.release();
+            id
+        };
+
+        // 凭据仍有进行中的连接，排空持续进行
+        let connection = connections::track(
+            ids[0],
+            futures::stream::pending::<reqwest::Result<bytes::Bytes>>(),
+            None,
+            None,
+        );
+        let job = service
+            .start_drain(ids[0], DrainAction::Delete, Some(60))
+            .await
+            .unwrap();
+        assert_eq!(job.state, DrainState::Draining);
+
+        // 熔断期已过，排空中的凭据也不作为半开探测候选
+        for _ in 0..3 {
+            assert_eq!(next().await, ids[1]);
+        }
+        assert!(db.get_credential(ids[0]).unwrap().unwrap().disabled);
+
+        // 对照：普通禁用的凭据在熔断期后被探测恢复
+        service.set_disabled(ids[1], true).await.unwrap();
+        assert_eq!(next().await, ids[1]);
+
+        // 连接结束后按排空操作删除凭据
+        drop(connection);
+        for _ in 0..100 {
+            if db.get_credential(ids[0]).unwrap().is_none() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("排空完成后凭据未被删除");
+    }
+
+    #[tokio::test]
+    async fn test_set_machine_id() {
+        let service = service(Config::default());
+        let id = service
+            .add_credential(request(serde_json::json!({
+                "refreshToken": "r".repeat(120),
+                "authMethod": "social",
+            })))
+            .await
+            .unwrap();
+        let set = |value: serde_json::Value| {
+            service.set_machine_id(id, serde_json::from_value(value).unwrap())
+        };
+
+        let fixed = "B3981D12-4D61-418C-9B77-461DB82A7CC4";
+        assert_eq!(
+            set(serde_json::json!({ "machineId": fixed }))
+                .await
+                .unwrap(),
+            fixed.to_lowercase()
+        );
+        let seeded = set(serde_json::json!({ "seed": "ops" })).await.unwrap();
+        assert_eq!(
+            seeded,
+            crate::kiro::machine_id::generate_uuid_from_seed("ops")
+        );
+        let random = set(serde_json::json!({})).await.unwrap();
+        assert_ne!(random, seeded);
+        let stored = service
+            .token_manager
+            .database()
+            .get_credential(id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.machine_id, Some(random));
+
+        assert!(matches!(
+            set(serde_json::json!({ "machineId": "bad" })).await,
+            Err(AdminServiceError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            service
+                .set_machine_id(999, serde_json::from_value(serde_json::json!({})).unwrap())
+                .await,
+            Err(AdminServiceError::NotFound { id: 999 })
+        ));
+    }
+}
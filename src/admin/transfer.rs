@@ -0,0 +1,210 @@
+//! 凭据导入/导出格式
+//!
+//! 导出为凭据 JSON 数组（与旧版 credentials.json 格式相同，不含 ID 和运行时状态）。
+//! 提供口令时整体加密为信封：PBKDF2-HMAC-SHA256 派生密钥，AES-256-GCM 加密；
+//! 导出结果可以原样提交给导入接口
+
+use std::num::NonZeroU32;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::kiro::model::credentials::KiroCredentials;
+
+/// 携带加密口令的请求头（避免口令出现在 URL 和访问日志中）
+pub const PASSPHRASE_HEADER: &str = "x-kiro-passphrase";
+
+/// 信封格式版本
+const ENVELOPE_VERSION: u32 = 1;
+
+/// 密钥派生算法标识
+const KDF_NAME: &str = "pbkdf2-sha256";
+
+/// 加密算法标识
+const CIPHER_NAME: &str = "aes-256-gcm";
+
+/// 导出时的 PBKDF2 迭代次数
+const PBKDF2_ITERATIONS: u32 = 600_000;
+
+/// 导入时允许的最大迭代次数（防止恶意信封消耗 CPU）
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+
+/// 盐长度（字节）
+const SALT_LEN: usize = 16;
+
+/// 加密后的导出信封
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptedExport {
+    /// 信封格式版本
+    pub version: u32,
+    /// 密钥派生算法
+    pub kdf: String,
+    /// PBKDF2 迭代次数
+    pub iterations: u32,
+    /// 加密算法
+    pub cipher: String,
+    /// 盐（Base64）
+    pub salt: String,
+    /// Nonce（Base64）
+    pub nonce: String,
+    /// 密文（Base64，含认证标签）
+    pub ciphertext: String,
+}
+
+/// 导入请求体：明文凭据数组或加密信封
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ImportPayload {
+    Plain(Vec<KiroCredentials>),
+    Encrypted(EncryptedExport),
+}
+
+/// 转换为可移植的导出格式：去除 ID，运行时状态与余额缓存不会被序列化
+pub fn to_portable(credentials: Vec<KiroCredentials>) -> Vec<KiroCredentials> {
+    credentials
+        .into_iter()
+        .map(|c| KiroCredentials { id: None, ..c })
+        .collect()
+}
+
+/// 使用口令加密
+pub fn encrypt(plaintext: &[u8], passphrase: &str) -> anyhow::Result<EncryptedExport> {
+    encrypt_with_iterations(plaintext, passphrase, PBKDF2_ITERATIONS)
+}
+
+fn encrypt_with_iterations(
+    plaintext: &[u8],
+    passphrase: &str,
+    iterations: u32,
+) -> anyhow::Result<EncryptedExport> {
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|_| rng.fill(&mut nonce))
+        .map_err(|_| anyhow::anyhow!("生成随机数失败"))?;
+
+    let key = derive_key(passphrase, &salt, iterations)?;
+    let mut in_out = plaintext.to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::empty(),
+        &mut in_out,
+    )
+    .map_err(|_| anyhow::anyhow!("加密失败"))?;
+
+    Ok(EncryptedExport {
+        version: ENVELOPE_VERSION,
+        kdf: KDF_NAME.to_string(),
+        iterations,
+        cipher: CIPHER_NAME.to_string(),
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(in_out),
+    })
+}
+
+/// 使用口令解密
+pub fn decrypt(envelope: &EncryptedExport, passphrase: &str) -> anyhow::Result<Vec<u8>> {
+    if envelope.version != ENVELOPE_VERSION
+        || envelope.kdf != KDF_NAME
+        || envelope.cipher != CIPHER_NAME
+    {
+        anyhow::bail!(
+            "不支持的加密格式: version={}, kdf={}, cipher={}",
+            envelope.version,
+            envelope.kdf,
+            envelope.cipher
+        );
+    }
+    if envelope.iterations > MAX_PBKDF2_ITERATIONS {
+        anyhow::bail!("迭代次数过大: {}", envelope.iterations);
+    }
+
+    let decode = |name: &str, value: &str| {
+        STANDARD
+            .decode(value)
+            .map_err(|e| anyhow::anyhow!("{} 不是有效的 Base64: {}", name, e))
+    };
+    let salt = decode("salt", &envelope.salt)?;
+    let nonce: [u8; NONCE_LEN] = decode("nonce", &envelope.nonce)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("nonce 长度无效"))?;
+    let mut in_out = decode("ciphertext", &envelope.ciphertext)?;
+
+    let key = derive_key(passphrase, &salt, envelope.iterations)?;
+    let plaintext = key
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut in_out,
+        )
+        .map_err(|_| anyhow::anyhow!("解密失败：口令错误或数据已损坏"))?;
+    Ok(plaintext.to_vec())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> anyhow::Result<LessSafeKey> {
+    let iterations =
+        NonZeroU32::new(iterations).ok_or_else(|| anyhow::anyhow!("迭代次数不能为 0"))?;
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    let key = UnboundKey::new(&AES_256_GCM, &key).map_err(|_| anyhow::anyhow!("无效的密钥"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_roundtrip() {
+        let envelope =
+            encrypt_with_iterations(b"[{\"refreshToken\":\"x\"}]", "secret", 1000).unwrap();
+        assert_eq!(envelope.iterations, 1000);
+        assert_eq!(
+            decrypt(&envelope, "secret").unwrap(),
+            b"[{\"refreshToken\":\"x\"}]"
+        );
+        assert!(decrypt(&envelope, "wrong").is_err());
+
+        let mut tampered = envelope.clone();
+        tampered.iterations = 999;
+        assert!(decrypt(&tampered, "secret").is_err());
+    }
+
+    #[test]
+    fn test_import_payload_accepts_both_formats() {
+        let plain: ImportPayload =
+            serde_json::from_str(r#"[{"refreshToken": "a", "priority": 2}]"#).unwrap();
+        assert!(matches!(plain, ImportPayload::Plain(ref c) if c[0].priority == 2));
+
+        let envelope = encrypt_with_iterations(b"[]", "secret", 1000).unwrap();
+        let encrypted: ImportPayload =
+            serde_json::from_value(serde_json::to_value(&envelope).unwrap()).unwrap();
+        assert!(matches!(encrypted, ImportPayload::Encrypted(_)));
+    }
+
+    #[test]
+    fn test_to_portable_strips_id_and_runtime_state() {
+        let credentials = to_portable(vec![KiroCredentials {
+            id: Some(7),
+            refresh_token: Some("token".to_string()),
+            disabled: true,
+            failure_count: 2,
+            ..Default::default()
+        }]);
+        let json = serde_json::to_value(&credentials).unwrap();
+        assert_eq!(json, serde_json::json!([{"refreshToken": "token"}]));
+    }
+}
@@ -1,11 +1,30 @@
 //! Admin API 类型定义
 
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::anthropic::deprecation::DeprecationStats;
+use crate::common::request_id;
+use crate::kiro::circuit_breaker::CircuitState;
+use crate::kiro::connections::UpstreamStats;
+use crate::kiro::db::{DatabaseStats, Lease};
+use crate::kiro::model::admin_token::AdminToken;
+use crate::kiro::model::api_key::ApiKey;
+use crate::kiro::model::health_check::HealthCheck;
+use crate::kiro::model::notification::{Notification, NotificationStatus};
+use crate::kiro::model::prompt_template::PromptTemplate;
+use crate::kiro::model::request_log::RequestLog;
+use crate::kiro::model::stats::StatsSummary;
+use crate::kiro::model::transcript::Transcript;
+use crate::kiro::replication::SyncStatus;
+
 // ============ 凭据状态 ============
 
 /// 所有凭据状态响应
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "client", derive(Deserialize))]
 #[serde(rename_all = "camelCase")]
 pub struct CredentialsStatusResponse {
     /// 凭据总数
@@ -20,6 +39,7 @@ pub struct CredentialsStatusResponse {
 
 /// 单个凭据的状态信息
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "client", derive(Deserialize))]
 #[serde(rename_all = "camelCase")]
 pub struct CredentialStatusItem {
     /// 凭据唯一 ID
@@ -30,8 +50,18 @@ pub struct CredentialStatusItem {
     pub disabled: bool,
     /// 连续失败次数
     pub failure_count: u32,
+    /// 熔断器状态（closed / open / half_open）
+    pub circuit_state: CircuitState,
+    /// 熔断结束时间（RFC3339，未熔断时为 null）
+    pub circuit_open_until: Option<String>,
     /// 是否为当前活跃凭据
     pub is_current: bool,
+    /// 当前活跃的上游连接数
+    pub in_flight: u64,
+    /// 最近的 p95 上游延迟（毫秒，样本不足时为 null）
+    pub latency_p95_ms: Option<u64>,
+    /// 延迟降级到期时间（RFC3339，未降级时为 null）
+    pub latency_demoted_until: Option<String>,
     /// Token 过期时间（RFC3339 格式）
     pub expires_at: Option<String>,
     /// 认证方式
@@ -52,33 +82,80 @@ pub struct CredentialStatusItem {
     pub usage_percentage: f64,
     /// 下次重置时间（Unix 时间戳）
     pub next_reset_at: Option<f64>,
+    /// 余额更新时间（RFC3339，尚未查询时为 null）
+    pub balance_updated_at: Option<String>,
     /// 账号邮箱
     pub email: Option<String>,
+    /// Kiro IDE 版本覆盖（为空表示使用全局配置）
+    pub kiro_version: Option<String>,
+    /// 系统版本覆盖（为空表示使用全局配置）
+    pub system_version: Option<String>,
+    /// Node.js 版本覆盖（为空表示使用全局配置）
+    pub node_version: Option<String>,
+    /// 允许使用的模型（为空表示不限制）
+    pub allowed_models: Option<Vec<String>>,
+    /// 自定义上游请求头名称（值可能包含令牌，不返回）
+    pub extra_header_names: Vec<String>,
 }
 
 // ============ 操作请求 ============
 
 /// 启用/禁用凭据请求
 #[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "client", derive(Serialize))]
 #[serde(rename_all = "camelCase")]
 pub struct SetDisabledRequest {
     /// 是否禁用
     pub disabled: bool,
+    /// 禁用时是否等待进行中的请求完成（返回排空任务）
+    #[serde(default)]
+    pub drain: bool,
+    /// 排空超时（秒，可选）
+    pub timeout_secs: Option<u64>,
+}
+
+/// 删除凭据参数（Query String）
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteCredentialQuery {
+    /// 是否等待进行中的请求完成后再删除（返回排空任务）
+    #[serde(default)]
+    pub drain: bool,
+    /// 排空超时（秒，可选）
+    pub timeout_secs: Option<u64>,
 }
 
 /// 修改优先级请求
 #[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "client", derive(Serialize))]
 #[serde(rename_all = "camelCase")]
 pub struct SetPriorityRequest {
     /// 新优先级值
     pub priority: u32,
 }
 
-/// 添加凭据请求
+/// 批量更新凭据请求项（`POST /credentials/bulk` 的请求体为该结构的数组）
 #[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "client", derive(Serialize))]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BulkCredentialUpdate {
+    /// 凭据 ID
+    pub id: u64,
+    /// 是否禁用（省略时不变）
+    #[serde(default)]
+    pub disabled: Option<bool>,
+    /// 新优先级（省略时不变）
+    #[serde(default)]
+    pub priority: Option<u32>,
+}
+
+/// 添加凭据请求
+#[derive(Debug, Default, Deserialize)]
+#[cfg_attr(feature = "client", derive(Serialize))]
 #[serde(rename_all = "camelCase")]
 pub struct AddCredentialRequest {
-    /// 刷新令牌（必填）
+    /// 刷新令牌（iam 以外的认证方式必填）
+    #[serde(default)]
     pub refresh_token: String,
     /// 认证方式（可选，默认 "social"）
     pub auth_method: Option<String>,
@@ -86,14 +163,87 @@ pub struct AddCredentialRequest {
     pub client_id: Option<String>,
     /// OIDC Client Secret（IdC 认证需要）
     pub client_secret: Option<String>,
+    /// IAM Access Key ID（iam 认证需要）
+    pub access_key_id: Option<String>,
+    /// IAM Secret Access Key（iam 认证需要）
+    pub secret_access_key: Option<String>,
+    /// IAM 临时凭据的会话令牌（可选）
+    pub session_token: Option<String>,
+    /// OIDC 区域（可选，IdC 凭据刷新 Token 使用；默认使用配置中的 region）
+    pub region: Option<String>,
     /// 设备指纹（可选，UUID v4 格式）
     pub machine_id: Option<String>,
     /// 优先级（可选，默认 0）
     pub priority: Option<u32>,
+    /// Kiro IDE 版本覆盖（可选）
+    pub kiro_version: Option<String>,
+    /// 系统版本覆盖（可选，如 "darwin#24.6.0"）
+    pub system_version: Option<String>,
+    /// Node.js 版本覆盖（可选）
+    pub node_version: Option<String>,
+    /// 允许使用的模型（可选，Kiro 模型 ID，支持 `*` 后缀通配）
+    pub allowed_models: Option<Vec<String>>,
+    /// 自定义上游请求头（可选，请求头名称 -> 值）
+    pub extra_headers: Option<BTreeMap<String, String>>,
+}
+
+/// 设置客户端版本覆盖请求
+///
+/// 字段为空表示清除覆盖，回退到全局配置
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetVersionOverridesRequest {
+    /// Kiro IDE 版本
+    pub kiro_version: Option<String>,
+    /// 系统版本
+    pub system_version: Option<String>,
+    /// Node.js 版本
+    pub node_version: Option<String>,
+}
+
+/// 设置允许使用的模型请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetAllowedModelsRequest {
+    /// Kiro 模型 ID 列表（支持 `*` 后缀通配，null 或空数组表示不限制）
+    pub allowed_models: Option<Vec<String>>,
+}
+
+/// 设置自定义上游请求头请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetExtraHeadersRequest {
+    /// 请求头名称 -> 值（整体替换，null 或空对象表示清除）
+    pub extra_headers: Option<BTreeMap<String, String>>,
+}
+
+/// 设置设备指纹请求
+///
+/// `machineId` 与 `seed` 均未提供时随机生成新的指纹
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetMachineIdRequest {
+    /// 指定的设备指纹（UUID 格式）
+    #[serde(default)]
+    pub machine_id: Option<String>,
+    /// 从种子确定性生成设备指纹（与 machineId 互斥）
+    #[serde(default)]
+    pub seed: Option<String>,
+}
+
+/// 设置设备指纹响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetMachineIdResponse {
+    pub success: bool,
+    pub message: String,
+    /// 新的设备指纹
+    pub machine_id: String,
 }
 
 /// 添加凭据响应
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "client", derive(Deserialize))]
 #[serde(rename_all = "camelCase")]
 pub struct AddCredentialResponse {
     pub success: bool,
@@ -102,10 +252,23 @@ pub struct AddCredentialResponse {
     pub id: u64,
 }
 
+/// 批量导入凭据响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportCredentialsResponse {
+    pub success: bool,
+    pub message: String,
+    /// 导入的凭据数
+    pub imported: usize,
+    /// 跳过的凭据数（refreshToken 已存在或为空）
+    pub skipped: usize,
+}
+
 // ============ 余额查询 ============
 
 /// 余额查询响应
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "client", derive(Deserialize))]
 #[serde(rename_all = "camelCase")]
 pub struct BalanceResponse {
     /// 凭据 ID
@@ -124,10 +287,562 @@ pub struct BalanceResponse {
     pub next_reset_at: Option<f64>,
 }
 
+// ============ 请求日志 ============
+
+/// 请求日志搜索参数（Query String）
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchRequestLogsQuery {
+    /// 起始时间（RFC3339，包含）
+    pub from: Option<String>,
+    /// 结束时间（RFC3339，不包含）
+    pub to: Option<String>,
+    /// 模型（精确匹配）
+    pub model: Option<String>,
+    /// 凭据 ID
+    pub credential_id: Option<u64>,
+    /// HTTP 状态码
+    pub status: Option<u16>,
+    /// 客户端 API Key（明文或指纹）
+    pub client_key: Option<String>,
+    /// 最小延迟（毫秒）
+    pub min_latency_ms: Option<u64>,
+    /// 错误信息关键字
+    pub q: Option<String>,
+    /// 请求标签（精确匹配）
+    pub tag: Option<String>,
+    /// 采样种子
+    pub seed: Option<i64>,
+    /// 请求 ID（`x-request-id`，精确匹配）
+    pub request_id: Option<String>,
+    /// 返回条数（默认 100，最大 1000）
+    pub limit: Option<usize>,
+    /// 偏移量
+    pub offset: Option<usize>,
+}
+
+/// 对话记录查询参数（Query String）
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListTranscriptsQuery {
+    /// 模型（精确匹配）
+    pub model: Option<String>,
+    /// 请求 ID（`x-request-id`，精确匹配）
+    pub request_id: Option<String>,
+    /// 返回条数（默认 100，最大 1000）
+    pub limit: Option<usize>,
+    /// 偏移量
+    pub offset: Option<usize>,
+}
+
+/// 对话记录列表响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscriptListResponse {
+    /// 匹配的记录总数
+    pub total: usize,
+    /// 本页返回条数上限
+    pub limit: usize,
+    /// 偏移量
+    pub offset: usize,
+    /// 对话记录（按时间倒序）
+    pub transcripts: Vec<Transcript>,
+}
+
+/// 请求日志搜索响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestLogSearchResponse {
+    /// 匹配的日志总数
+    pub total: usize,
+    /// 本页返回条数上限
+    pub limit: usize,
+    /// 偏移量
+    pub offset: usize,
+    /// 日志列表（按时间倒序）
+    pub logs: Vec<RequestLog>,
+}
+
+// ============ 用量统计 ============
+
+/// 用量统计参数（Query String）
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageQuery {
+    /// 起始时间（RFC3339，包含）
+    pub from: Option<String>,
+    /// 结束时间（RFC3339，不包含）
+    pub to: Option<String>,
+    /// 凭据 ID
+    pub credential_id: Option<u64>,
+    /// 模型（精确匹配）
+    pub model: Option<String>,
+    /// 请求标签（精确匹配）
+    pub tag: Option<String>,
+}
+
+/// 统计摘要查询参数
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsQuery {
+    /// 请求标签（只统计携带该标签的请求）
+    pub tag: Option<String>,
+}
+
+// ============ 连接排空 ============
+
+/// 排空完成后执行的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DrainAction {
+    /// 保持禁用
+    Disable,
+    /// 删除凭据
+    Delete,
+}
+
+/// 排空任务状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DrainState {
+    /// 等待进行中的请求完成
+    Draining,
+    /// 已完成
+    Completed,
+    /// 超时（凭据保持禁用，未执行删除）
+    TimedOut,
+    /// 执行操作失败
+    Failed,
+}
+
+/// 排空任务
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DrainJob {
+    /// 任务 ID
+    pub id: u64,
+    /// 凭据 ID
+    pub credential_id: u64,
+    /// 排空完成后执行的操作
+    pub action: DrainAction,
+    /// 任务状态
+    pub state: DrainState,
+    /// 当前活跃的上游连接数
+    pub in_flight: u64,
+    /// 开始时间（RFC3339）
+    pub started_at: String,
+    /// 结束时间（RFC3339）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+    /// 错误信息
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// ============ 批量刷新余额 ============
+
+/// 批量刷新余额请求（请求体可省略，默认刷新所有凭据）
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshBalancesRequest {
+    /// 只刷新指定的凭据 ID
+    pub ids: Option<Vec<u64>>,
+    /// 只刷新指定禁用状态的凭据
+    pub disabled: Option<bool>,
+    /// 并发数（默认 4，最大 16）
+    pub concurrency: Option<usize>,
+}
+
+/// 批量刷新余额任务状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BalanceRefreshState {
+    /// 刷新中
+    Running,
+    /// 已完成（部分凭据可能失败，见 `failures`）
+    Completed,
+}
+
+/// 单个凭据的刷新失败信息
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceRefreshFailure {
+    /// 凭据 ID
+    pub credential_id: u64,
+    /// 错误信息
+    pub error: String,
+}
+
+/// 批量刷新余额任务
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceRefreshJob {
+    /// 任务 ID
+    pub id: u64,
+    /// 任务状态
+    pub state: BalanceRefreshState,
+    /// 待刷新的凭据数
+    pub total: usize,
+    /// 刷新成功数
+    pub succeeded: usize,
+    /// 刷新失败数
+    pub failed: usize,
+    /// 失败明细
+    pub failures: Vec<BalanceRefreshFailure>,
+    /// 开始时间（RFC3339）
+    pub started_at: String,
+    /// 结束时间（RFC3339）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+}
+
+// ============ 设备授权登录 ============
+
+/// 设备授权登录请求
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginRequest {
+    /// IAM Identity Center 起始地址（可选，默认使用 AWS Builder ID）
+    pub start_url: Option<String>,
+    /// IAM Identity Center 所在的 OIDC 区域（可选，默认使用配置中的 region）
+    pub region: Option<String>,
+    /// 添加后凭据的优先级（可选，默认 0）
+    pub priority: Option<u32>,
+    /// 允许使用的模型（可选，Kiro 模型 ID，支持 `*` 后缀通配）
+    pub allowed_models: Option<Vec<String>>,
+}
+
+/// 设备授权登录任务状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LoginState {
+    /// 等待用户在浏览器中完成授权
+    Pending,
+    /// 已授权并添加凭据
+    Completed,
+    /// 授权或添加凭据失败（包括用户拒绝、设备码过期）
+    Failed,
+}
+
+/// 设备授权登录任务
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginJob {
+    /// 任务 ID
+    pub id: u64,
+    /// 任务状态
+    pub state: LoginState,
+    /// 起始地址
+    pub start_url: String,
+    /// 用户码（在验证页面输入）
+    pub user_code: String,
+    /// 验证页面地址
+    pub verification_uri: String,
+    /// 已包含用户码的验证页面地址
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification_uri_complete: Option<String>,
+    /// 设备码过期时间（RFC3339）
+    pub expires_at: String,
+    /// 添加的凭据 ID（完成后）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_id: Option<u64>,
+    /// 失败原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 开始时间（RFC3339）
+    pub started_at: String,
+    /// 结束时间（RFC3339）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+}
+
+// ============ 运行指标 ============
+
+/// 运行指标响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsResponse {
+    /// 进程启动以来的 panic 总数
+    pub panics_total: u64,
+    /// 上游连接统计
+    #[serde(flatten)]
+    pub upstream: UpstreamStats,
+    /// 数据库锁竞争统计
+    #[serde(flatten)]
+    pub database: DatabaseStats,
+    /// 模型弃用统计
+    #[serde(flatten)]
+    pub deprecations: DeprecationStats,
+    /// 统计摘要（内存快照，首次刷新完成前为 null）
+    pub stats: Option<StatsSummary>,
+}
+
+// ============ 多实例租约 ============
+
+/// 多实例租约响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeasesResponse {
+    /// 本实例 ID
+    pub instance_id: String,
+    pub leases: Vec<Lease>,
+}
+
+// ============ 熔断参数 ============
+
+/// 修改熔断参数请求（未提供的字段保持不变）
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateCircuitBreakerRequest {
+    /// 熔断阈值（失败次数）
+    pub failure_threshold: Option<u32>,
+    /// 失败计数窗口（秒），`0` 表示统计连续失败
+    pub window_secs: Option<u64>,
+    /// 熔断持续时间（秒）
+    pub open_secs: Option<u64>,
+    /// 按认证方式覆盖的熔断持续时间（秒），整体替换
+    pub open_secs_by_auth_method: Option<HashMap<String, u64>>,
+}
+
+// ============ 运行配置 ============
+
+/// 配置项来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConfigSource {
+    /// 配置文件中显式设置
+    File,
+    /// 未设置，使用默认值
+    Default,
+    /// 运行期间被覆盖（如自动检测到新的 Kiro 版本、热备实例已提升）
+    Runtime,
+}
+
+/// 单个配置项
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigEntry {
+    /// 当前生效的值（敏感字段已脱敏）
+    pub value: serde_json::Value,
+    /// 来源
+    pub source: ConfigSource,
+    /// 被运行期覆盖前的配置值（仅 `source` 为 `runtime` 时）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub configured: Option<serde_json::Value>,
+}
+
+/// 运行配置响应（按字段名排序）
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigResponse {
+    pub config: std::collections::BTreeMap<String, ConfigEntry>,
+}
+
+// ============ 提示词模板 ============
+
+/// 创建或更新提示词模板请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpsertPromptTemplateRequest {
+    /// 模板名称（1-64 个字母、数字、`-`、`_` 或 `.`）
+    pub name: String,
+    /// 模板内容（支持 `{{变量名}}` 占位符）
+    pub content: String,
+    /// 描述（可选）
+    pub description: Option<String>,
+}
+
+/// 提示词模板列表响应
+#[derive(Debug, Serialize)]
+pub struct PromptTemplateListResponse {
+    pub templates: Vec<PromptTemplate>,
+}
+
+// ============ 客户端 API Key ============
+
+/// 创建客户端 API Key 请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateApiKeyRequest {
+    /// 名称（如使用者）
+    pub name: String,
+    /// 允许使用的模型（支持 `*` 结尾的前缀匹配，省略或为空表示不限制）
+    #[serde(default)]
+    pub allowed_models: Option<Vec<String>>,
+    /// 每分钟最大请求数（省略表示不限制）
+    #[serde(default)]
+    pub rate_limit_per_minute: Option<u32>,
+    /// 过期时间（RFC3339，省略表示永不过期）
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// 创建客户端 API Key 响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateApiKeyResponse {
+    /// 明文 Key（仅在创建时返回一次）
+    pub key: String,
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+}
+
+/// 客户端 API Key 列表响应
+#[derive(Debug, Serialize)]
+pub struct ApiKeyListResponse {
+    pub keys: Vec<ApiKey>,
+}
+
+// ============ 受限 Admin Token ============
+
+/// 签发受限 Admin Token 请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateAdminTokenRequest {
+    /// 名称（如使用的脚本）
+    pub name: String,
+    /// 权限范围（如 `credentials:read`，至少一个）
+    pub scopes: Vec<String>,
+    /// 过期时间（RFC3339，省略表示永不过期）
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// 签发受限 Admin Token 响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateAdminTokenResponse {
+    /// 明文 Token（仅在创建时返回一次）
+    pub token: String,
+    #[serde(flatten)]
+    pub admin_token: AdminToken,
+}
+
+/// 受限 Admin Token 列表响应
+#[derive(Debug, Serialize)]
+pub struct AdminTokenListResponse {
+    pub tokens: Vec<AdminToken>,
+}
+
+// ============ 告警通知 ============
+
+/// 告警通知查询参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationsQuery {
+    /// 投递状态（pending / delivered / dead）
+    pub status: Option<NotificationStatus>,
+    /// 最多返回条数（默认 100，最大 1000）
+    pub limit: Option<usize>,
+}
+
+/// 告警通知列表响应
+#[derive(Debug, Serialize)]
+pub struct NotificationListResponse {
+    pub notifications: Vec<Notification>,
+}
+
+// ============ 健康检查 ============
+
+/// 健康检查历史查询参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthChecksQuery {
+    /// 最多返回条数（默认 100，最大 1000）
+    pub limit: Option<usize>,
+}
+
+/// 健康检查历史响应
+#[derive(Debug, Serialize)]
+pub struct HealthCheckListResponse {
+    pub checks: Vec<HealthCheck>,
+}
+
+// ============ 调整建议 ============
+
+/// 调整建议查询参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecommendationsQuery {
+    /// 统计窗口（天，默认 7，最大 90）
+    pub days: Option<u32>,
+}
+
+/// 调整建议类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecommendationKind {
+    /// 提升闲置健康账号的优先级
+    RaisePriority,
+    /// 禁用持续失败的账号
+    Disable,
+    /// 重新生成重复的设备指纹
+    RotateMachineId,
+}
+
+/// 应用建议需要执行的 Admin API 调用
+#[derive(Debug, Clone, Serialize)]
+pub struct RecommendedAction {
+    /// HTTP 方法
+    pub method: &'static str,
+    /// 相对 Admin API 路径的端点
+    pub path: String,
+    /// 请求体
+    pub body: serde_json::Value,
+}
+
+impl RecommendedAction {
+    pub fn post(path: String, body: serde_json::Value) -> Self {
+        Self {
+            method: "POST",
+            path,
+            body,
+        }
+    }
+}
+
+/// 单条调整建议
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recommendation {
+    pub kind: RecommendationKind,
+    pub credential_id: u64,
+    /// 建议原因
+    pub reason: String,
+    /// 一键应用的 API 调用
+    pub action: RecommendedAction,
+}
+
+/// 调整建议响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecommendationsResponse {
+    pub generated_at: DateTime<Utc>,
+    /// 统计窗口（天）
+    pub window_days: u32,
+    pub recommendations: Vec<Recommendation>,
+}
+
+// ============ 热备同步 ============
+
+/// 热备同步状态响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplicationStatusResponse {
+    /// 实例角色（leader / standby）
+    pub role: String,
+    /// 主实例地址（仅热备实例配置）
+    pub leader_url: Option<String>,
+    /// 最近一次同步状态
+    #[serde(flatten)]
+    pub sync: SyncStatus,
+}
+
 // ============ 通用响应 ============
 
 /// 操作成功响应
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "client", derive(Deserialize))]
 pub struct SuccessResponse {
     pub success: bool,
     pub message: String,
@@ -144,11 +859,16 @@ impl SuccessResponse {
 
 /// 错误响应
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "client", derive(Deserialize))]
 pub struct AdminErrorResponse {
     pub error: AdminError,
+    /// 请求 ID（与 `x-request-id` 响应头一致）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "client", derive(Deserialize))]
 pub struct AdminError {
     #[serde(rename = "type")]
     pub error_type: String,
@@ -162,6 +882,7 @@ impl AdminErrorResponse {
                 error_type: error_type.into(),
                 message: message.into(),
             },
+            request_id: request_id::current(),
         }
     }
 
@@ -173,6 +894,10 @@ impl AdminErrorResponse {
         Self::new("authentication_error", "Invalid or missing admin API key")
     }
 
+    pub fn permission_error(message: impl Into<String>) -> Self {
+        Self::new("permission_error", message)
+    }
+
     pub fn not_found(message: impl Into<String>) -> Self {
         Self::new("not_found", message)
     }
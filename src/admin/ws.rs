@@ -0,0 +1,196 @@
+//! Admin WebSocket 推送
+//!
+//! `GET /ws` 升级为 WebSocket 后，以 JSON 文本帧推送凭据状态变更事件
+//! （见 [`CredentialEvent`]），Web UI 据此实时更新而无需轮询凭据列表。
+//! 握手与帧编解码由 axum 的 [`WebSocketUpgrade`] 处理，客户端 Ping 自动回应 Pong。
+//!
+//! 浏览器无法为 WebSocket 握手设置自定义请求头，因此除 `x-api-key` / `Authorization`
+//! 外，还接受通过子协议传递的 Admin API Key：客户端同时提供 `kiro-admin` 与
+//! `kiro-admin-key.<base64url(key)>` 两个子协议，服务端回应 `kiro-admin`
+
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        State,
+        ws::{Message, WebSocket, WebSocketUpgrade, rejection::WebSocketUpgradeRejection},
+    },
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use futures::SinkExt;
+use tokio::sync::broadcast;
+
+use crate::kiro::credential_events::CredentialEvent;
+
+use super::middleware::AdminState;
+use super::types::AdminErrorResponse;
+
+/// 服务端回应的子协议
+const PROTOCOL: &str = "kiro-admin";
+
+/// 携带 Admin API Key 的子协议前缀
+const KEY_PROTOCOL_PREFIX: &str = "kiro-admin-key.";
+
+/// 心跳间隔（保持经过反向代理的空闲连接）
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 从 `Sec-WebSocket-Protocol` 中提取 Admin API Key
+pub fn protocol_api_key(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .find_map(|protocol| {
+            protocol
+                .strip_prefix(KEY_PROTOCOL_PREFIX)
+                .map(str::to_string)
+        })
+        .and_then(|encoded| BASE64_URL.decode(encoded).ok())
+        .and_then(|key| String::from_utf8(key).ok())
+}
+
+/// GET /api/admin/ws
+/// 凭据状态变更推送
+pub async fn credential_events_ws(
+    State(state): State<AdminState>,
+    ws: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Response {
+    let ws = match ws {
+        Ok(ws) => ws,
+        Err(_) => {
+            let error = AdminErrorResponse::invalid_request("Expected a WebSocket upgrade request");
+            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+        }
+    };
+
+    // 升级前订阅，避免握手期间的事件丢失
+    let events = state.service.subscribe_credential_events();
+    ws.protocols([PROTOCOL])
+        .on_failed_upgrade(|e| tracing::warn!("Admin WebSocket 升级失败: {}", e))
+        .on_upgrade(move |socket| serve(socket, events))
+}
+
+/// 推送循环：转发事件、定时发送心跳，直到任一方关闭连接
+async fn serve(mut socket: WebSocket, mut events: broadcast::Receiver<CredentialEvent>) {
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    ping.tick().await;
+    loop {
+        let result = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => send_event(&mut socket, &event).await,
+                // 落后时通知客户端重新获取完整列表
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    send_event(&mut socket, &CredentialEvent::CredentialsChanged).await
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                // 发出排队中的 Close 应答，完成关闭握手
+                Some(Ok(Message::Close(_))) => {
+                    let _ = socket.flush().await;
+                    break;
+                }
+                Some(Err(_)) | None => break,
+                // Ping 已由底层自动回应，其余客户端帧忽略
+                Some(Ok(_)) => Ok(()),
+            },
+            _ = ping.tick() => socket.send(Message::Ping(Default::default())).await,
+        };
+        if result.is_err() {
+            break;
+        }
+    }
+}
+
+async fn send_event(socket: &mut WebSocket, event: &CredentialEvent) -> Result<(), axum::Error> {
+    match serde_json::to_string(event) {
+        Ok(data) => socket.send(Message::Text(data.into())).await,
+        Err(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use axum::{Router, routing::get};
+    use futures::StreamExt;
+    use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest};
+
+    #[test]
+    fn test_protocol_api_key() {
+        let mut headers = HeaderMap::new();
+        let protocols = format!(
+            "kiro-admin, kiro-admin-key.{}",
+            BASE64_URL.encode("sk-admin")
+        );
+        headers.insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_str(&protocols).unwrap(),
+        );
+        assert_eq!(protocol_api_key(&headers).as_deref(), Some("sk-admin"));
+        assert!(protocol_api_key(&HeaderMap::new()).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_serve_pushes_events_and_answers_control_frames() {
+        let (tx, _) = broadcast::channel(16);
+        let sender = tx.clone();
+        let app = Router::new().route(
+            "/ws",
+            get(move |ws: WebSocketUpgrade| {
+                let events = sender.subscribe();
+                async move {
+                    ws.protocols([PROTOCOL])
+                        .on_upgrade(move |socket| serve(socket, events))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut request = format!("ws://{}/ws", addr).into_client_request().unwrap();
+        request.headers_mut().insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static("kiro-admin, kiro-admin-key.c2stYWRtaW4"),
+        );
+        let (mut client, response) = tokio_tungstenite::connect_async(request).await.unwrap();
+        assert_eq!(
+            response
+                .headers()
+                .get(header::SEC_WEBSOCKET_PROTOCOL)
+                .unwrap(),
+            PROTOCOL
+        );
+
+        tx.send(CredentialEvent::CurrentChanged { id: 2 }).unwrap();
+        let tungstenite::Message::Text(text) = client.next().await.unwrap().unwrap() else {
+            panic!("应收到文本帧");
+        };
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&text).unwrap(),
+            serde_json::json!({"type": "current_changed", "id": 2})
+        );
+
+        client
+            .send(tungstenite::Message::Ping(b"hi".to_vec().into()))
+            .await
+            .unwrap();
+        assert_eq!(
+            client.next().await.unwrap().unwrap(),
+            tungstenite::Message::Pong(b"hi".to_vec().into())
+        );
+
+        client.close(None).await.unwrap();
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(tungstenite::Message::Close(_))) | None
+        ));
+    }
+}
@@ -0,0 +1,165 @@
+//! 响应标注
+//!
+//! 多环境部署时，配置 `responseAnnotation` 后在响应中标注处理请求的代理实例与凭据池，
+//! 用于追溯某个输出来自哪个部署。标注只包含配置的名称，不包含任何凭据信息：
+//!
+//! - `metadata`：在响应消息（流式为 `message_start` 的 message）中添加 `kiro_annotation` 字段
+//! - `suffix`：在响应文本末尾追加 `annotationSuffix`；要求 JSON 输出的请求改为添加 `kiro_annotation` 字段
+
+use serde_json::{Value, json};
+
+use crate::kiro::lease;
+use crate::model::config::Config;
+
+/// 标注方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnnotationMode {
+    Metadata,
+    Suffix,
+}
+
+/// 响应标注
+#[derive(Debug, Clone)]
+pub struct Annotation {
+    mode: AnnotationMode,
+    /// 代理实例名称（未配置时为本实例 ID）
+    instance: String,
+    /// 凭据池名称
+    pool: String,
+    /// 后缀模板
+    suffix: String,
+}
+
+impl Annotation {
+    /// 根据配置创建；未配置或配置无效时返回 None
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let mode = match config.response_annotation.as_deref()? {
+            "metadata" => AnnotationMode::Metadata,
+            "suffix" => AnnotationMode::Suffix,
+            other => {
+                tracing::warn!(
+                    "忽略无效的 responseAnnotation: {}（可选值: metadata, suffix）",
+                    other
+                );
+                return None;
+            }
+        };
+        Some(Self {
+            mode,
+            instance: config
+                .annotation_instance
+                .clone()
+                .unwrap_or_else(|| lease::instance_id().to_string()),
+            pool: config.annotation_pool.clone(),
+            suffix: config.annotation_suffix.clone(),
+        })
+    }
+
+    /// `kiro_annotation` 字段的内容
+    fn metadata(&self) -> Value {
+        json!({"instance": self.instance, "pool": self.pool})
+    }
+
+    /// 追加到响应文本末尾的后缀（`json_output` 为 true 时不追加，避免破坏 JSON 输出）
+    pub fn suffix(&self, json_output: bool) -> Option<String> {
+        (self.mode == AnnotationMode::Suffix && !json_output).then(|| {
+            self.suffix
+                .replace("{instance}", &self.instance)
+                .replace("{pool}", &self.pool)
+        })
+    }
+
+    /// 在响应消息中添加 `kiro_annotation` 字段（追加后缀时不添加）
+    pub fn add_metadata(&self, message: &mut Value, json_output: bool) {
+        if self.suffix(json_output).is_none() {
+            message["kiro_annotation"] = self.metadata();
+        }
+    }
+
+    /// 标注非流式响应：追加后缀到最后一个文本块（没有文本块时新增），或添加 `kiro_annotation` 字段
+    pub fn apply(&self, message: &mut Value, json_output: bool) {
+        let Some(suffix) = self.suffix(json_output) else {
+            self.add_metadata(message, json_output);
+            return;
+        };
+        let Some(content) = message["content"].as_array_mut() else {
+            return;
+        };
+        match content
+            .iter_mut()
+            .rev()
+            .find(|block| block["type"] == "text")
+        {
+            Some(block) => {
+                let text = format!("{}{}", block["text"].as_str().unwrap_or(""), suffix);
+                block["text"] = Value::String(text);
+            }
+            None => content.push(json!({"type": "text", "text": suffix.trim_start()})),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annotation(mode: &str) -> Annotation {
+        Annotation::from_config(&Config {
+            response_annotation: Some(mode.to_string()),
+            annotation_instance: Some("prod-eu".to_string()),
+            annotation_pool: "team-a".to_string(),
+            ..Config::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_metadata_annotation() {
+        let mut message = json!({"content": [{"type": "text", "text": "hi"}]});
+        annotation("metadata").apply(&mut message, false);
+        assert_eq!(message["content"][0]["text"], "hi");
+        assert_eq!(
+            message["kiro_annotation"],
+            json!({"instance": "prod-eu", "pool": "team-a"})
+        );
+    }
+
+    #[test]
+    fn test_suffix_annotation() {
+        let suffix = annotation("suffix");
+        let mut message = json!({"content": [
+            {"type": "text", "text": "hi"},
+            {"type": "tool_use", "id": "t1", "name": "read", "input": {}}
+        ]});
+        suffix.apply(&mut message, false);
+        assert_eq!(
+            message["content"][0]["text"],
+            "hi\n\n[served by prod-eu / team-a]"
+        );
+        assert!(message.get("kiro_annotation").is_none());
+
+        // 没有文本块时新增
+        let mut message = json!({"content": []});
+        suffix.apply(&mut message, false);
+        assert_eq!(
+            message["content"][0]["text"],
+            "[served by prod-eu / team-a]"
+        );
+
+        // JSON 输出改为添加字段
+        let mut message = json!({"content": [{"type": "text", "text": "{}"}]});
+        suffix.apply(&mut message, true);
+        assert_eq!(message["content"][0]["text"], "{}");
+        assert_eq!(message["kiro_annotation"]["pool"], "team-a");
+    }
+
+    #[test]
+    fn test_invalid_mode_is_ignored() {
+        let config = Config {
+            response_annotation: Some("header".to_string()),
+            ..Config::default()
+        };
+        assert!(Annotation::from_config(&config).is_none());
+        assert!(Annotation::from_config(&Config::default()).is_none());
+    }
+}
@@ -0,0 +1,104 @@
+//! 客户端 API Key 校验
+//!
+//! 除配置文件中的 `apiKey` 外，客户端也可以使用通过 Admin API 签发的独立 Key，
+//! 每个 Key 可单独限制可用模型、每分钟请求数与有效期，吊销后立即失效（多实例共用数据库时最迟 30 秒后失效）；
+//! 每分钟请求数由按 Key 的令牌桶限制（见 `token_bucket`）
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::kiro::db::Database;
+use crate::kiro::model::api_key::ApiKey;
+
+/// 缓存条目的最长有效期（兜底其他进程对同一数据库的修改）
+const CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// 缓存的最大条目数（超出时整体清空）
+const CACHE_MAX_ENTRIES: usize = 1024;
+
+/// 已签发客户端 API Key 的查询缓存
+///
+/// 仅缓存查到的 Key，未知 Key 每次都查询数据库；
+/// 本进程签发、吊销或删除 Key 后缓存立即失效，其他进程的修改最迟 [`CACHE_TTL`] 后生效
+#[derive(Default)]
+pub struct KeyCache {
+    /// Key 哈希 -> (缓存时间, 缓存时的 Key 变更计数, Key)
+    entries: Mutex<HashMap<String, (Instant, u64, ApiKey)>>,
+}
+
+impl KeyCache {
+    /// 查找客户端 API Key（查询失败时按不存在处理）
+    pub async fn lookup(&self, database: &Arc<Database>, key: &str) -> Option<ApiKey> {
+        let key_hash = ApiKey::hash(key);
+        let generation = database.api_keys_generation();
+        if let Some((cached_at, cached_generation, key)) = self.entries.lock().get(&key_hash)
+            && *cached_generation == generation
+            && cached_at.elapsed() < CACHE_TTL
+        {
+            return Some(key.clone());
+        }
+
+        let hash = key_hash.clone();
+        let key = match database.call(move |db| db.find_api_key(&hash)).await {
+            Ok(key) => key?,
+            Err(e) => {
+                tracing::error!("查询客户端 API Key 失败: {}", e);
+                return None;
+            }
+        };
+
+        let mut entries = self.entries.lock();
+        if entries.len() >= CACHE_MAX_ENTRIES {
+            entries.clear();
+        }
+        entries.insert(key_hash, (Instant::now(), generation, key.clone()));
+        Some(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn api_key(id: u64) -> ApiKey {
+        ApiKey {
+            id,
+            name: format!("key-{}", id),
+            key_hash: String::new(),
+            fingerprint: String::new(),
+            allowed_models: None,
+            rate_limit_per_minute: None,
+            expires_at: None,
+            revoked: false,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lookup() {
+        let database = Database::open_in_memory().unwrap();
+        let key = ApiKey::generate();
+        let key_hash = ApiKey::hash(&key);
+        let mut record = api_key(0);
+        record.fingerprint = ApiKey::fingerprint_of(&key_hash);
+        record.key_hash = key_hash;
+        let id = database.insert_api_key(&record).unwrap();
+
+        let cache = KeyCache::default();
+        assert_eq!(cache.lookup(&database, &key).await.unwrap().name, "key-0");
+        assert!(cache.lookup(&database, "sk-kiro-unknown").await.is_none());
+
+        // 吊销后缓存立即失效
+        assert!(!cache.lookup(&database, &key).await.unwrap().revoked);
+        database.revoke_api_key(id).unwrap();
+        assert!(cache.lookup(&database, &key).await.unwrap().revoked);
+
+        database.delete_api_key(id).unwrap();
+        assert!(cache.lookup(&database, &key).await.is_none());
+    }
+}
@@ -0,0 +1,115 @@
+//! `anthropic-beta` 请求头处理
+//!
+//! 识别客户端声明的 beta 特性，用于开启代理侧的实验性行为，
+//! 并将已支持的特性通过响应头回显，便于客户端做特性检测
+
+use axum::http::HeaderMap;
+
+/// beta 请求/响应头名称
+pub const ANTHROPIC_BETA_HEADER: &str = "anthropic-beta";
+
+/// Prompt Caching：仅为兼容在 usage 中返回缓存字段（代理与上游均不做缓存，固定为 0）
+pub const PROMPT_CACHING: &str = "prompt-caching-2024-07-31";
+
+/// 扩展输出：放宽 thinking 预算上限
+pub const OUTPUT_128K: &str = "output-128k-2025-02-19";
+
+/// 代理支持的 beta 特性列表
+const SUPPORTED_BETAS: &[&str] = &[PROMPT_CACHING, OUTPUT_128K];
+
+/// 本次请求启用的 beta 特性
+#[derive(Debug, Clone, Default)]
+pub struct BetaFeatures {
+    /// 已识别并启用的特性（按请求中出现的顺序，去重）
+    enabled: Vec<&'static str>,
+}
+
+impl BetaFeatures {
+    /// 从请求头解析 beta 特性
+    ///
+    /// 支持多个 `anthropic-beta` 头以及逗号分隔的多个值，未知特性会被忽略
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut enabled = Vec::new();
+        for value in headers.get_all(ANTHROPIC_BETA_HEADER) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            for name in value.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                match SUPPORTED_BETAS.iter().find(|b| **b == name) {
+                    Some(beta) if !enabled.contains(beta) => enabled.push(*beta),
+                    Some(_) => {}
+                    None => tracing::debug!("忽略不支持的 anthropic-beta: {}", name),
+                }
+            }
+        }
+        Self { enabled }
+    }
+
+    /// 是否启用了指定特性
+    pub fn is_enabled(&self, beta: &str) -> bool {
+        self.enabled.contains(&beta)
+    }
+
+    /// 是否在 usage 中返回缓存兼容字段
+    pub fn prompt_caching(&self) -> bool {
+        self.is_enabled(PROMPT_CACHING)
+    }
+
+    /// 是否启用扩展输出
+    pub fn extended_output(&self) -> bool {
+        self.is_enabled(OUTPUT_128K)
+    }
+
+    /// 用于回显的响应头值（没有启用任何特性时返回 None）
+    pub fn header_value(&self) -> Option<String> {
+        (!self.enabled.is_empty()).then(|| self.enabled.join(","))
+    }
+}
+
+/// 为 usage 对象补充 Prompt Caching 兼容字段
+///
+/// 仅用于兼容要求这两个字段存在的客户端：代理不做任何缓存计费，字段固定为 0，
+/// 不代表缓存未命中，也不应据此统计缓存命中率
+pub fn add_cache_compat_fields(usage: &mut serde_json::Value) {
+    if let Some(obj) = usage.as_object_mut() {
+        obj.insert("cache_creation_input_tokens".to_string(), 0.into());
+        obj.insert("cache_read_input_tokens".to_string(), 0.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            ANTHROPIC_BETA_HEADER,
+            HeaderValue::from_static("prompt-caching-2024-07-31, unknown-beta"),
+        );
+        headers.append(
+            ANTHROPIC_BETA_HEADER,
+            HeaderValue::from_static("output-128k-2025-02-19,prompt-caching-2024-07-31"),
+        );
+
+        let betas = BetaFeatures::from_headers(&headers);
+        assert!(betas.prompt_caching());
+        assert!(betas.extended_output());
+        assert_eq!(
+            betas.header_value().as_deref(),
+            Some("prompt-caching-2024-07-31,output-128k-2025-02-19")
+        );
+    }
+
+    #[test]
+    fn test_no_supported_betas() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ANTHROPIC_BETA_HEADER, HeaderValue::from_static("foo-bar"));
+
+        let betas = BetaFeatures::from_headers(&headers);
+        assert!(!betas.prompt_caching());
+        assert!(betas.header_value().is_none());
+    }
+}
@@ -2,6 +2,7 @@
 //!
 //! 负责将 Anthropic API 请求格式转换为 Kiro API 请求格式
 
+use base64::Engine;
 use uuid::Uuid;
 
 use crate::kiro::model::requests::conversation::{
@@ -39,6 +40,8 @@ pub fn map_model(model: &str) -> Option<String> {
 pub struct ConversionResult {
     /// 转换后的 Kiro 请求
     pub conversation_state: ConversationState,
+    /// 映射后的 Kiro 模型 ID
+    pub model_id: String,
 }
 
 /// 转换错误
@@ -59,6 +62,38 @@ impl std::fmt::Display for ConversionError {
 
 impl std::error::Error for ConversionError {}
 
+/// 合并连续的同角色消息
+///
+/// 上游要求 user/assistant 严格交替，部分客户端会连续发送多条 user（或 assistant）消息，
+/// 这里将其内容块按顺序拼接为一条消息；结尾连续的 user 消息会一起成为当前消息，
+/// 避免前面的消息被拆成带占位回复的历史
+pub fn normalize_messages(messages: Vec<super::types::Message>) -> Vec<super::types::Message> {
+    let mut normalized: Vec<super::types::Message> = Vec::with_capacity(messages.len());
+    for msg in messages {
+        match normalized.last_mut() {
+            Some(prev) if prev.role == msg.role => {
+                let mut blocks = content_blocks(std::mem::take(&mut prev.content));
+                blocks.extend(content_blocks(msg.content));
+                prev.content = serde_json::Value::Array(blocks);
+            }
+            _ => normalized.push(msg),
+        }
+    }
+    normalized
+}
+
+/// 将消息内容统一为内容块数组（空字符串不产生内容块）
+fn content_blocks(content: serde_json::Value) -> Vec<serde_json::Value> {
+    match content {
+        serde_json::Value::Array(blocks) => blocks,
+        serde_json::Value::String(text) if text.is_empty() => Vec::new(),
+        serde_json::Value::String(text) => {
+            vec![serde_json::json!({"type": "text", "text": text})]
+        }
+        _ => Vec::new(),
+    }
+}
+
 /// 将 Anthropic 请求转换为 Kiro 请求
 pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, ConversionError> {
     // 1. 映射模型
@@ -70,7 +105,7 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
         return Err(ConversionError::EmptyMessages);
     }
 
-    // 3. 生成会话 ID 和代理 ID
+    // 3. 生成会话 ID 和代理 ID（上游不支持 seed，指定 seed 时同样随机生成，避免相同请求共用上游会话）
     let conversation_id = Uuid::new_v4().to_string();
     let agent_continuation_id = Uuid::new_v4().to_string();
 
@@ -79,10 +114,17 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
 
     // 5. 处理最后一条消息作为 current_message
     let last_message = req.messages.last().unwrap();
-    let (text_content, images, tool_results) = process_message_content(&last_message.content)?;
+    let (mut text_content, images, tool_results) = process_message_content(&last_message.content)?;
+    if let Some(instruction) = tool_choice_instruction(req) {
+        if !text_content.is_empty() {
+            text_content.push_str("\n\n");
+        }
+        text_content.push_str(&instruction);
+    }
 
-    // 6. 转换工具定义
-    let tools = convert_tools(&req.tools);
+    // 6. 转换工具定义（补充历史中调用过但未声明的工具）
+    let mut tools = convert_tools(&req.tools);
+    add_history_tool_placeholders(&mut tools, &req.messages);
 
     // 7. 构建 UserInputMessageContext
     let mut context = UserInputMessageContext::new();
@@ -105,10 +147,11 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
         user_input = user_input.with_images(images);
     }
 
-    let current_message = CurrentMessage::new(user_input);
+    let mut current_message = CurrentMessage::new(user_input);
 
-    // 9. 构建历史消息
-    let history = build_history(req, &model_id)?;
+    // 9. 构建历史消息，并将工具结果与上一条 assistant 的工具调用对齐
+    let mut history = build_history(req, &model_id)?;
+    pair_tool_results(&mut history, &mut current_message);
 
     // 10. 构建 ConversationState
     let conversation_state = ConversationState::new(conversation_id)
@@ -118,21 +161,159 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
         .with_current_message(current_message)
         .with_history(history);
 
-    Ok(ConversionResult { conversation_state })
+    Ok(ConversionResult {
+        conversation_state,
+        model_id,
+    })
 }
 
 /// 确定聊天触发类型
 fn determine_chat_trigger_type(req: &MessagesRequest) -> String {
-    if req.tools.is_some()
-        && let Some(ref tool_choice) = req.tool_choice
-        && let Some(tc_type) = tool_choice.get("type").and_then(|v| v.as_str())
-        && (tc_type == "any" || tc_type == "tool")
-    {
+    if req.tools.is_some() && matches!(req.tool_choice_type(), Some("any" | "tool")) {
         return "AUTO".to_string();
     }
     "MANUAL".to_string()
 }
 
+/// 将 tool_choice 转换为附加在当前消息末尾的指令
+///
+/// 上游没有 tool_choice 参数：指定工具、强制调用、禁止调用与禁止并行调用均以指令表达，
+/// 未声明工具时忽略
+fn tool_choice_instruction(req: &MessagesRequest) -> Option<String> {
+    if req.tools.as_ref().is_none_or(|tools| tools.is_empty()) {
+        return None;
+    }
+
+    let mut parts = Vec::new();
+    match req.tool_choice_type() {
+        Some("tool") => {
+            if let Some(name) = req
+                .tool_choice
+                .as_ref()
+                .and_then(|c| c.get("name"))
+                .and_then(|v| v.as_str())
+            {
+                parts.push(format!("You must respond by calling the `{}` tool.", name));
+            }
+        }
+        Some("any") => {
+            parts.push("You must respond by calling at least one of the available tools.".into())
+        }
+        Some("none") => {
+            parts.push("Do not call any tools in this response; reply with text only.".into())
+        }
+        _ => {}
+    }
+    if req.disable_parallel_tool_use() && req.tool_choice_type() != Some("none") {
+        parts.push("Call at most one tool in this response.".to_string());
+    }
+    (!parts.is_empty()).then(|| parts.join(" "))
+}
+
+/// 为历史中调用过但未在 tools 中声明的工具补充占位定义
+///
+/// 上游要求历史中的每个工具调用都有对应的工具定义；客户端在后续轮次中移除工具时
+/// （如 tool_choice 为 none 时不再发送 tools）请求会被拒绝
+fn add_history_tool_placeholders(tools: &mut Vec<Tool>, messages: &[super::types::Message]) {
+    let used = messages
+        .iter()
+        .filter(|m| m.role == "assistant")
+        .filter_map(|m| m.content.as_array())
+        .flatten()
+        .filter(|block| block.get("type").and_then(|v| v.as_str()) == Some("tool_use"))
+        .filter_map(|block| block.get("name").and_then(|v| v.as_str()));
+
+    for name in used {
+        if is_unsupported_tool(name) || tools.iter().any(|t| t.tool_specification.name == name) {
+            continue;
+        }
+        tools.push(Tool {
+            tool_specification: ToolSpecification {
+                name: name.to_string(),
+                description: "Tool used earlier in this conversation.".to_string(),
+                input_schema: InputSchema::from_json(
+                    serde_json::json!({"type": "object", "properties": {}}),
+                ),
+            },
+        });
+    }
+}
+
+/// 将每条 user 消息的工具结果与上一条 assistant 消息的工具调用对齐
+///
+/// 并行工具调用的结果按调用顺序排列；找不到对应调用的结果（如客户端压缩上下文后残留）
+/// 上游会拒绝，改为以文本形式附加到消息内容
+fn pair_tool_results(history: &mut [Message], current: &mut CurrentMessage) {
+    let mut expected: Vec<String> = Vec::new();
+    for message in history.iter_mut() {
+        match message {
+            Message::Assistant(assistant) => {
+                expected = assistant
+                    .assistant_response_message
+                    .tool_uses
+                    .iter()
+                    .flatten()
+                    .map(|t| t.tool_use_id.clone())
+                    .collect();
+            }
+            Message::User(user) => {
+                let user = &mut user.user_input_message;
+                align_tool_results(
+                    &mut user.content,
+                    &mut user.user_input_message_context.tool_results,
+                    &expected,
+                );
+                expected.clear();
+            }
+        }
+    }
+
+    let user = &mut current.user_input_message;
+    align_tool_results(
+        &mut user.content,
+        &mut user.user_input_message_context.tool_results,
+        &expected,
+    );
+}
+
+/// 按工具调用顺序排列工具结果，无对应调用的结果转为文本
+fn align_tool_results(content: &mut String, results: &mut Vec<ToolResult>, expected: &[String]) {
+    if results.is_empty() {
+        return;
+    }
+    let (mut paired, orphaned): (Vec<_>, Vec<_>) = std::mem::take(results)
+        .into_iter()
+        .partition(|r| expected.contains(&r.tool_use_id));
+    paired.sort_by_key(|r| expected.iter().position(|id| *id == r.tool_use_id));
+    *results = paired;
+
+    for result in orphaned {
+        tracing::debug!(
+            "工具结果 {} 没有对应的工具调用，转为文本",
+            result.tool_use_id
+        );
+        let text = result
+            .content
+            .iter()
+            .filter_map(|item| item.get("text").and_then(|v| v.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if !content.is_empty() {
+            content.push_str("\n\n");
+        }
+        content.push_str(&format!(
+            "<tool_result id=\"{}\"{}>\n{}\n</tool_result>",
+            result.tool_use_id,
+            if result.is_error {
+                " is_error=\"true\""
+            } else {
+                ""
+            },
+            text
+        ));
+    }
+}
+
 /// 处理消息内容，提取文本、图片和工具结果
 fn process_message_content(
     content: &serde_json::Value,
@@ -147,6 +328,13 @@ fn process_message_content(
         }
         serde_json::Value::Array(arr) => {
             for item in arr {
+                // 文档块的 source 形式多样，不经过 ContentBlock 反序列化
+                if is_document_block(item) {
+                    if let Some(text) = document_to_text(item) {
+                        text_parts.push(text);
+                    }
+                    continue;
+                }
                 if let Ok(block) = serde_json::from_value::<ContentBlock>(item.clone()) {
                     match block.block_type.as_str() {
                         "text" => {
@@ -164,6 +352,8 @@ fn process_message_content(
                         "tool_result" => {
                             if let Some(tool_use_id) = block.tool_use_id {
                                 let result_content = extract_tool_result_content(&block.content);
+                                // 上游的工具结果只支持文本，结果中的图片随消息一起发送
+                                images.extend(extract_tool_result_images(&block.content));
                                 let is_error = block.is_error.unwrap_or(false);
 
                                 let mut result = if is_error {
@@ -209,7 +399,9 @@ fn extract_tool_result_content(content: &Option<serde_json::Value>) -> String {
         Some(serde_json::Value::Array(arr)) => {
             let mut parts = Vec::new();
             for item in arr {
-                if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
+                if is_document_block(item) {
+                    parts.extend(document_to_text(item));
+                } else if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
                     parts.push(text.to_string());
                 }
             }
@@ -220,6 +412,102 @@ fn extract_tool_result_content(content: &Option<serde_json::Value>) -> String {
     }
 }
 
+/// 提取 tool_result 内容中的图片
+fn extract_tool_result_images(content: &Option<serde_json::Value>) -> Vec<KiroImage> {
+    let Some(serde_json::Value::Array(arr)) = content else {
+        return Vec::new();
+    };
+    arr.iter()
+        .filter_map(|item| serde_json::from_value::<ContentBlock>(item.clone()).ok())
+        .filter(|block| block.block_type == "image")
+        .filter_map(|block| {
+            let source = block.source?;
+            let format = get_image_format(&source.media_type)?;
+            Some(KiroImage::from_base64(format, source.data))
+        })
+        .collect()
+}
+
+/// 是否为 document / search_result 块
+fn is_document_block(item: &serde_json::Value) -> bool {
+    matches!(
+        item.get("type").and_then(|v| v.as_str()),
+        Some("document" | "search_result")
+    )
+}
+
+/// 将 document / search_result 块展开为文本
+///
+/// 上游不支持文档块与引用（citations），这里把纯文本、base64 纯文本和自定义内容文档
+/// 以及搜索结果展开为带标题的文本；`citations` 配置被忽略，响应中不会包含引用。
+/// PDF、URL、文件 ID 等无法在本地展开的来源会被丢弃并记录警告
+fn document_to_text(block: &serde_json::Value) -> Option<String> {
+    let str_field = |key: &str| block.get(key).and_then(|v| v.as_str());
+    let texts_of = |content: Option<&serde_json::Value>| -> String {
+        content
+            .and_then(|c| c.as_array())
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item.get("text").and_then(|v| v.as_str()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default()
+    };
+
+    let (tag, body) = if str_field("type") == Some("search_result") {
+        ("search_result", texts_of(block.get("content")))
+    } else {
+        let source = block.get("source");
+        let source_type = source.and_then(|s| s.get("type")).and_then(|v| v.as_str());
+        let media_type = source
+            .and_then(|s| s.get("media_type"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+        let data = source.and_then(|s| s.get("data")).and_then(|v| v.as_str());
+
+        let body = match (source_type, data) {
+            (Some("text"), Some(data)) => Some(data.to_string()),
+            (Some("content"), _) => match source.and_then(|s| s.get("content")) {
+                Some(serde_json::Value::String(text)) => Some(text.clone()),
+                content => Some(texts_of(content)),
+            },
+            (Some("base64"), Some(data)) if media_type.starts_with("text/") => {
+                base64::engine::general_purpose::STANDARD
+                    .decode(data)
+                    .ok()
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+            }
+            _ => None,
+        };
+        let Some(body) = body else {
+            tracing::warn!(
+                "忽略无法转换的 document 块（source.type: {}, media_type: {}）：上游仅支持纯文本文档",
+                source_type.unwrap_or("unknown"),
+                media_type
+            );
+            return None;
+        };
+        ("document", body)
+    };
+
+    let mut attributes = String::new();
+    for key in ["title", "source"] {
+        if let Some(value) = str_field(key) {
+            attributes.push_str(&format!(" {}=\"{}\"", key, value.replace('"', "'")));
+        }
+    }
+    let mut text = format!("<{}{}>\n", tag, attributes);
+    if let Some(context) = str_field("context") {
+        text.push_str(context);
+        text.push('\n');
+    }
+    text.push_str(&body);
+    text.push_str(&format!("\n</{}>", tag));
+    Some(text)
+}
+
 /// 转换工具定义
 fn convert_tools(tools: &Option<Vec<super::types::Tool>>) -> Vec<Tool> {
     let Some(tools) = tools else {
@@ -230,11 +518,7 @@ fn convert_tools(tools: &Option<Vec<super::types::Tool>>) -> Vec<Tool> {
         .iter()
         .filter(|t| !is_unsupported_tool(&t.name))
         .map(|t| {
-            let mut description = t.description.clone();
-            // 限制描述长度为 10000 字符
-            if description.len() > 10000 {
-                description = description[..10000].to_string();
-            }
+            let description = truncate_tool_description(&t.description).to_string();
 
             Tool {
                 tool_specification: ToolSpecification {
@@ -247,8 +531,31 @@ fn convert_tools(tools: &Option<Vec<super::types::Tool>>) -> Vec<Tool> {
         .collect()
 }
 
+/// 工具描述最大长度（字节）
+const MAX_TOOL_DESCRIPTION_BYTES: usize = 10000;
+
+/// 截断过长的工具描述（不在字符中间切分）
+fn truncate_tool_description(description: &str) -> &str {
+    let mut end = description.len().min(MAX_TOOL_DESCRIPTION_BYTES);
+    while !description.is_char_boundary(end) {
+        end -= 1;
+    }
+    &description[..end]
+}
+
+/// 将工具列表调整为实际发送给上游的内容：移除不支持的工具，截断过长的描述
+///
+/// 输入 tokens 按调整后的工具计数，与 `convert_request` 发送的内容一致
+pub(super) fn retain_sent_tools(tools: &mut Vec<super::types::Tool>) {
+    tools.retain(|t| !is_unsupported_tool(&t.name));
+    for tool in tools {
+        let len = truncate_tool_description(&tool.description).len();
+        tool.description.truncate(len);
+    }
+}
+
 /// 检查是否为不支持的工具
-fn is_unsupported_tool(name: &str) -> bool {
+pub(super) fn is_unsupported_tool(name: &str) -> bool {
     matches!(name.to_lowercase().as_str(), "web_search" | "websearch")
 }
 
@@ -527,10 +834,192 @@ mod tests {
             tools: None,
             tool_choice: None,
             thinking: None,
+            ..Default::default()
         };
         assert_eq!(determine_chat_trigger_type(&req), "MANUAL");
     }
 
+    #[test]
+    fn test_seed_keeps_conversation_ids_random() {
+        let request = |seed: Option<i64>, text: &str| MessagesRequest {
+            model: "claude-sonnet-4".to_string(),
+            max_tokens: 1024,
+            messages: vec![super::super::types::Message {
+                role: "user".to_string(),
+                content: serde_json::json!(text),
+            }],
+            seed,
+            ..Default::default()
+        };
+        let ids = |req: &MessagesRequest| {
+            let state = convert_request(req).unwrap().conversation_state;
+            (state.conversation_id, state.agent_continuation_id)
+        };
+
+        // 相同 seed 的相同请求（如并发的评测请求）不共用上游会话
+        let seeded = ids(&request(Some(42), "hi"));
+        assert_ne!(seeded.0, ids(&request(Some(42), "hi")).0);
+        assert_ne!(seeded.1, ids(&request(Some(42), "hi")).1);
+        assert_ne!(ids(&request(None, "hi")), ids(&request(None, "hi")));
+    }
+
+    #[test]
+    fn test_normalize_messages_merges_same_role() {
+        let message = |role: &str, content: serde_json::Value| super::super::types::Message {
+            role: role.to_string(),
+            content,
+        };
+        let normalized = normalize_messages(vec![
+            message("user", serde_json::json!("hi")),
+            message(
+                "assistant",
+                serde_json::json!([{"type": "tool_use", "id": "t1", "name": "read", "input": {}}]),
+            ),
+            message(
+                "user",
+                serde_json::json!([{"type": "tool_result", "tool_use_id": "t1", "content": "ok"}]),
+            ),
+            message("user", serde_json::json!("")),
+            message("user", serde_json::json!("continue")),
+        ]);
+
+        assert_eq!(normalized.len(), 3);
+        assert_eq!(normalized[0].content, serde_json::json!("hi"));
+        assert_eq!(
+            normalized[2].content,
+            serde_json::json!([
+                {"type": "tool_result", "tool_use_id": "t1", "content": "ok"},
+                {"type": "text", "text": "continue"}
+            ])
+        );
+
+        // 合并后的当前消息同时包含工具结果和文本，且不再生成占位历史
+        let req = MessagesRequest {
+            model: "claude-sonnet-4".to_string(),
+            max_tokens: 1024,
+            messages: normalized,
+            ..Default::default()
+        };
+        let state = convert_request(&req).unwrap().conversation_state;
+        assert_eq!(state.history.len(), 2);
+        let current = &state.current_message.user_input_message;
+        assert_eq!(current.content, "continue");
+        assert_eq!(current.user_input_message_context.tool_results.len(), 1);
+    }
+
+    #[test]
+    fn test_parallel_tool_results_and_tool_choice() {
+        let message = |role: &str, content: serde_json::Value| super::super::types::Message {
+            role: role.to_string(),
+            content,
+        };
+        let req = MessagesRequest {
+            model: "claude-sonnet-4".to_string(),
+            max_tokens: 1024,
+            messages: vec![
+                message("user", serde_json::json!("find it")),
+                message(
+                    "assistant",
+                    serde_json::json!([
+                        {"type": "text", "text": "Searching."},
+                        {"type": "tool_use", "id": "t1", "name": "read", "input": {"path": "a"}},
+                        {"type": "tool_use", "id": "t2", "name": "grep", "input": {"q": "b"}}
+                    ]),
+                ),
+                message(
+                    "user",
+                    serde_json::json!([
+                        {"type": "tool_result", "tool_use_id": "t9", "content": "stale"},
+                        {"type": "tool_result", "tool_use_id": "t2", "content": "two"},
+                        {"type": "tool_result", "tool_use_id": "t1", "content": "one", "is_error": true}
+                    ]),
+                ),
+            ],
+            tools: Some(vec![super::super::types::Tool {
+                name: "read".to_string(),
+                description: "Read a file".to_string(),
+                input_schema: Default::default(),
+            }]),
+            tool_choice: Some(serde_json::json!({
+                "type": "tool", "name": "read", "disable_parallel_tool_use": true
+            })),
+            ..Default::default()
+        };
+        let state = convert_request(&req).unwrap().conversation_state;
+        assert_eq!(state.chat_trigger_type.as_deref(), Some("AUTO"));
+
+        let Message::Assistant(assistant) = &state.history[1] else {
+            panic!("第二条历史应为 assistant");
+        };
+        let tool_uses = assistant
+            .assistant_response_message
+            .tool_uses
+            .as_ref()
+            .unwrap();
+        assert_eq!(tool_uses.len(), 2);
+
+        // 并行工具结果按调用顺序排列，无对应调用的结果转为文本
+        let current = &state.current_message.user_input_message;
+        let context = &current.user_input_message_context;
+        let ids: Vec<_> = context
+            .tool_results
+            .iter()
+            .map(|r| r.tool_use_id.as_str())
+            .collect();
+        assert_eq!(ids, ["t1", "t2"]);
+        assert!(context.tool_results[0].is_error);
+        assert!(
+            current
+                .content
+                .contains("<tool_result id=\"t9\">\nstale\n</tool_result>")
+        );
+        assert!(current.content.contains("calling the `read` tool"));
+        assert!(current.content.contains("at most one tool"));
+
+        // 历史中调用过但未声明的工具补充占位定义
+        let names: Vec<_> = context
+            .tools
+            .iter()
+            .map(|t| t.tool_specification.name.as_str())
+            .collect();
+        assert_eq!(names, ["read", "grep"]);
+    }
+
+    #[test]
+    fn test_tool_result_images_are_lifted() {
+        let content = serde_json::json!([{
+            "type": "tool_result",
+            "tool_use_id": "t1",
+            "content": [
+                {"type": "text", "text": "screenshot"},
+                {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}}
+            ]
+        }]);
+        let (_, images, tool_results) = process_message_content(&content).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].format, "png");
+        assert_eq!(images[0].source.bytes, "iVBORw0KGgo=");
+        assert_eq!(tool_results.len(), 1);
+    }
+
+    #[test]
+    fn test_retain_sent_tools() {
+        let tool = |name: &str, description: String| super::super::types::Tool {
+            name: name.to_string(),
+            description,
+            input_schema: Default::default(),
+        };
+        let mut tools = vec![
+            tool("web_search", String::new()),
+            tool("read", "读".repeat(4000)),
+        ];
+        retain_sent_tools(&mut tools);
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "read");
+        // 截断到不超过上限的字符边界
+        assert_eq!(tools[0].description.len(), 9999);
+    }
+
     #[test]
     fn test_is_unsupported_tool() {
         assert!(is_unsupported_tool("web_search"));
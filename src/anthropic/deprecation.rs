@@ -0,0 +1,225 @@
+//! 模型弃用提示
+//!
+//! 按配置 `modelDeprecations` 处理请求已弃用模型的请求：请求照常处理，响应附带
+//! `Deprecation` / `Sunset` / `Warning` 响应头并记录日志与指标；配置了后继模型且已过下线日期时，
+//! 请求会被改写为后继模型，便于在整个集群内平滑迁移模型
+
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use axum::http::{HeaderMap, HeaderValue};
+use chrono::{DateTime, SecondsFormat, Utc};
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::model::config::Config;
+
+/// 改写前的模型响应头
+const REWRITTEN_FROM_HEADER: &str = "x-kiro-model-rewritten-from";
+
+/// 各弃用模型的请求次数
+static DEPRECATED_REQUESTS: LazyLock<Mutex<BTreeMap<String, u64>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+/// 下线后被改写为后继模型的请求总数
+static REWRITTEN_TOTAL: AtomicU64 = AtomicU64::new(0);
+
+/// 模型弃用统计
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeprecationStats {
+    /// 各弃用模型的请求次数（按客户端请求的模型名）
+    pub deprecated_model_requests: BTreeMap<String, u64>,
+    /// 下线后被改写为后继模型的请求总数
+    pub model_rewrites_total: u64,
+}
+
+/// 获取模型弃用统计
+pub fn stats() -> DeprecationStats {
+    DeprecationStats {
+        deprecated_model_requests: DEPRECATED_REQUESTS.lock().clone(),
+        model_rewrites_total: REWRITTEN_TOTAL.load(Ordering::Relaxed),
+    }
+}
+
+/// 单次请求的弃用提示
+#[derive(Debug, Clone)]
+pub struct DeprecationNotice {
+    /// 客户端请求的模型
+    pub model: String,
+    /// 后继模型
+    pub successor: Option<String>,
+    /// 下线日期
+    pub sunset_at: Option<DateTime<Utc>>,
+    /// 附加说明
+    pub message: Option<String>,
+    /// 是否已改写为后继模型
+    pub rewritten: bool,
+}
+
+impl DeprecationNotice {
+    /// Warning 响应头内容
+    fn warning(&self) -> String {
+        let mut text = format!("Model '{}' is deprecated", self.model);
+        match (&self.successor, self.sunset_at, self.rewritten) {
+            (Some(successor), _, true) => {
+                text.push_str(&format!(" and was replaced by '{}'", successor))
+            }
+            (Some(successor), Some(sunset_at), false) => text.push_str(&format!(
+                " and will be replaced by '{}' after {}",
+                successor,
+                sunset_at.to_rfc3339_opts(SecondsFormat::Secs, true)
+            )),
+            (Some(successor), None, false) => {
+                text.push_str(&format!(", use '{}' instead", successor))
+            }
+            (None, Some(sunset_at), _) => text.push_str(&format!(
+                " (sunset {})",
+                sunset_at.to_rfc3339_opts(SecondsFormat::Secs, true)
+            )),
+            (None, None, _) => {}
+        }
+        if let Some(message) = &self.message {
+            text.push_str(": ");
+            text.push_str(message);
+        }
+        // Warning 头的引号内不能出现未转义的引号
+        format!("299 kiro-rs \"{}\"", text.replace('"', "'"))
+    }
+
+    /// 为响应添加弃用相关响应头
+    pub fn apply_headers(&self, headers: &mut HeaderMap) {
+        headers.insert("deprecation", HeaderValue::from_static("true"));
+        if let Some(sunset_at) = self.sunset_at
+            && let Ok(value) =
+                HeaderValue::from_str(&sunset_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        {
+            headers.insert("sunset", value);
+        }
+        if let Ok(value) = HeaderValue::from_bytes(self.warning().as_bytes()) {
+            headers.insert("warning", value);
+        }
+        if self.rewritten
+            && let Ok(value) = HeaderValue::from_str(&self.model)
+        {
+            headers.insert(REWRITTEN_FROM_HEADER, value);
+        }
+    }
+}
+
+/// 已下线模型改写后的后继模型（未弃用、未到下线时间或没有后继模型时为 None）
+///
+/// 不记录日志与指标，供 count_tokens 按实际发送的模型计数
+pub fn rewritten_model(config: &Config, model: &str, now: DateTime<Utc>) -> Option<String> {
+    let deprecation = config.model_deprecations.get(model)?;
+    let sunset_passed = deprecation
+        .sunset_at
+        .is_some_and(|sunset_at| now >= sunset_at);
+    deprecation
+        .successor
+        .clone()
+        .filter(|successor| sunset_passed && successor != model)
+}
+
+/// 检查请求的模型是否已弃用，下线后改写为后继模型
+///
+/// 返回弃用提示（未弃用时为 None），并更新日志与指标
+pub fn check(config: &Config, model: &mut String, now: DateTime<Utc>) -> Option<DeprecationNotice> {
+    let deprecation = config.model_deprecations.get(model.as_str())?;
+    let rewrite_to = rewritten_model(config, model, now);
+
+    let notice = DeprecationNotice {
+        model: model.clone(),
+        successor: deprecation.successor.clone(),
+        sunset_at: deprecation.sunset_at,
+        message: deprecation.message.clone(),
+        rewritten: rewrite_to.is_some(),
+    };
+
+    *DEPRECATED_REQUESTS.lock().entry(model.clone()).or_insert(0) += 1;
+    match rewrite_to {
+        Some(successor) => {
+            REWRITTEN_TOTAL.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!("模型 {} 已下线，请求改写为 {}", model, successor);
+            *model = successor;
+        }
+        None => tracing::warn!("请求了已弃用的模型: {}", model),
+    }
+
+    Some(notice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::config::ModelDeprecation;
+
+    fn config(successor: Option<&str>, sunset_at: Option<DateTime<Utc>>) -> Config {
+        let mut config = Config::default();
+        config.model_deprecations.insert(
+            "claude-3-5-sonnet-20241022".to_string(),
+            ModelDeprecation {
+                successor: successor.map(|s| s.to_string()),
+                sunset_at,
+                message: Some("migrate before Q3".to_string()),
+            },
+        );
+        config
+    }
+
+    #[test]
+    fn test_check_deprecated_model() {
+        let now = Utc::now();
+        let before = stats();
+
+        let mut model = "claude-sonnet-4-20250514".to_string();
+        assert!(check(&config(None, None), &mut model, now).is_none());
+
+        // 未到下线日期：仅提示，不改写
+        let sunset_at = now + chrono::Duration::days(30);
+        let mut model = "claude-3-5-sonnet-20241022".to_string();
+        let notice = check(
+            &config(Some("claude-sonnet-4-20250514"), Some(sunset_at)),
+            &mut model,
+            now,
+        )
+        .unwrap();
+        assert_eq!(model, "claude-3-5-sonnet-20241022");
+        assert!(!notice.rewritten);
+
+        let mut headers = HeaderMap::new();
+        notice.apply_headers(&mut headers);
+        assert_eq!(headers["deprecation"], "true");
+        assert!(headers.contains_key("sunset"));
+        let warning = headers["warning"].to_str().unwrap();
+        assert!(warning.starts_with("299 kiro-rs \"Model 'claude-3-5-sonnet-20241022'"));
+        assert!(warning.contains("migrate before Q3"));
+        assert!(!headers.contains_key(REWRITTEN_FROM_HEADER));
+
+        // 已过下线日期：改写为后继模型
+        let notice = check(
+            &config(Some("claude-sonnet-4-20250514"), Some(now)),
+            &mut model,
+            now,
+        )
+        .unwrap();
+        assert_eq!(model, "claude-sonnet-4-20250514");
+        assert!(notice.rewritten);
+        let mut headers = HeaderMap::new();
+        notice.apply_headers(&mut headers);
+        assert_eq!(headers[REWRITTEN_FROM_HEADER], "claude-3-5-sonnet-20241022");
+
+        let after = stats();
+        assert!(after.model_rewrites_total > before.model_rewrites_total);
+        assert!(
+            after.deprecated_model_requests["claude-3-5-sonnet-20241022"]
+                >= before
+                    .deprecated_model_requests
+                    .get("claude-3-5-sonnet-20241022")
+                    .copied()
+                    .unwrap_or(0)
+                    + 2
+        );
+    }
+}
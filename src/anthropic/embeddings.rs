@@ -0,0 +1,217 @@
+//! OpenAI 兼容的 `/v1/embeddings` 透传
+//!
+//! Kiro 上游不提供 embeddings，请求体原样转发到配置的 `embeddingsApiUrl`，
+//! 客户端仍使用代理的 API Key 认证，上游密钥仅保存在服务端
+
+use axum::{
+    Extension,
+    body::Bytes,
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::{Value, json};
+
+use crate::http_client::{ProxyConfig, build_client};
+use crate::kiro::model::api_key::ApiKey;
+use crate::model::config::Config;
+
+use super::middleware::AppState;
+
+/// embeddings 上游
+pub struct EmbeddingsBackend {
+    /// 上游完整地址（如 `https://api.openai.com/v1/embeddings`）
+    url: String,
+    /// 上游 API Key（以 `Authorization: Bearer` 发送）
+    api_key: Option<String>,
+    client: reqwest::Client,
+}
+
+impl EmbeddingsBackend {
+    /// 根据配置创建；未配置 `embeddingsApiUrl` 时返回 None
+    pub fn from_config(
+        config: &Config,
+        proxy: Option<&ProxyConfig>,
+    ) -> anyhow::Result<Option<Self>> {
+        let Some(url) = config.embeddings_api_url.as_ref().filter(|u| !u.is_empty()) else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            url: url.clone(),
+            api_key: config.embeddings_api_key.clone(),
+            client: build_client(proxy, config.embeddings_timeout_secs)?,
+        }))
+    }
+}
+
+/// OpenAI 格式的错误响应
+fn error_response(status: StatusCode, error_type: &str, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(json!({
+            "error": {"message": message.into(), "type": error_type, "code": Value::Null}
+        })),
+    )
+        .into_response()
+}
+
+/// POST /v1/embeddings
+///
+/// 校验客户端 Key 的模型白名单后将请求体转发到 embeddings 上游，
+/// 原样返回上游的状态码与响应体
+pub async fn post_embeddings(
+    State(state): State<AppState>,
+    client_key: Option<Extension<ApiKey>>,
+    body: Bytes,
+) -> Response {
+    let Some(backend) = &state.embeddings else {
+        return error_response(
+            StatusCode::NOT_FOUND,
+            "invalid_request_error",
+            "Embeddings are not enabled on this server",
+        );
+    };
+
+    let model = match serde_json::from_slice::<Value>(&body) {
+        Ok(payload) => payload["model"].as_str().unwrap_or_default().to_string(),
+        Err(e) => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                format!("Invalid JSON body: {}", e),
+            );
+        }
+    };
+    if let Some(key) = client_key.filter(|k| !k.allows_model(&model)) {
+        tracing::warn!(
+            "客户端 API Key {} 不允许使用模型 {}，拒绝 embeddings 请求",
+            key.name,
+            model
+        );
+        return error_response(
+            StatusCode::FORBIDDEN,
+            "permission_error",
+            format!("This API key is not allowed to use model '{}'", model),
+        );
+    }
+
+    let mut request = backend
+        .client
+        .post(&backend.url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body);
+    if let Some(api_key) = &backend.api_key {
+        request = request.bearer_auth(api_key);
+    }
+
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!("embeddings 上游请求失败: {}", e);
+            return error_response(
+                StatusCode::BAD_GATEWAY,
+                "api_error",
+                format!("Embeddings upstream request failed: {}", e),
+            );
+        }
+    };
+    let status = response.status();
+    let content_type = response.headers().get(header::CONTENT_TYPE).cloned();
+    let bytes = match response.bytes().await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("读取 embeddings 上游响应失败: {}", e);
+            return error_response(
+                StatusCode::BAD_GATEWAY,
+                "api_error",
+                format!("Failed to read embeddings upstream response: {}", e),
+            );
+        }
+    };
+    if !status.is_success() {
+        tracing::warn!("embeddings 上游返回 {}", status);
+    }
+
+    let mut response = (status, bytes).into_response();
+    if let Some(content_type) = content_type {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, content_type);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anthropic::create_router_with_provider;
+    use crate::kiro::db::Database;
+    use crate::kiro::provider::KiroProvider;
+    use crate::kiro::token_manager::MultiTokenManager;
+    use axum::{Router, http::HeaderMap, routing::post};
+    use std::sync::Arc;
+
+    async fn spawn(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_passthrough() {
+        // 假上游：回显收到的 Authorization 与模型
+        let upstream = spawn(Router::new().route(
+            "/v1/embeddings",
+            post(|headers: HeaderMap, Json(body): Json<Value>| async move {
+                Json(json!({
+                    "object": "list",
+                    "model": body["model"],
+                    "auth": headers.get("authorization").and_then(|v| v.to_str().ok()),
+                    "data": [{"object": "embedding", "index": 0, "embedding": [0.1, 0.2]}]
+                }))
+            }),
+        ))
+        .await;
+
+        let config = Config {
+            embeddings_api_url: Some(format!("{}/v1/embeddings", upstream)),
+            embeddings_api_key: Some("sk-upstream".to_string()),
+            ..Config::default()
+        };
+        let manager =
+            MultiTokenManager::new(config, Database::open_in_memory().unwrap(), None).unwrap();
+        let provider = KiroProvider::new(Arc::new(manager));
+        let base = spawn(create_router_with_provider(
+            "proxy-key",
+            Some(provider),
+            None,
+            0,
+        ))
+        .await;
+
+        let client = reqwest::Client::new();
+        let request = json!({"model": "text-embedding-3-small", "input": "hello"});
+        let response = client
+            .post(format!("{}/v1/embeddings", base))
+            .bearer_auth("proxy-key")
+            .json(&request)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["model"], "text-embedding-3-small");
+        assert_eq!(body["auth"], "Bearer sk-upstream");
+        assert_eq!(body["data"][0]["embedding"], json!([0.1, 0.2]));
+
+        let response = client
+            .post(format!("{}/v1/embeddings", base))
+            .bearer_auth("wrong-key")
+            .json(&request)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+    }
+}
@@ -0,0 +1,158 @@
+//! Anthropic ↔ Kiro 转换的 golden 测试
+//!
+//! `tests/fixtures/conversion/*.json` 为 Anthropic 请求，与 `*.golden.json` 中的 Kiro 请求比对；
+//! `tests/fixtures/stream/*.json` 为 Kiro 事件序列，与 `*.golden.json` 中的 Anthropic SSE 事件比对。
+//! 新增 fixture 或有意修改转换逻辑后，运行 `cargo test --features golden-update` 重新生成 golden 文件，
+//! 并在提交前检查其 diff
+
+use std::path::{Path, PathBuf};
+
+use serde_json::{Value, json};
+
+use crate::kiro::model::events::Event;
+
+use super::converter::convert_request;
+use super::stream::StreamContext;
+use super::types::MessagesRequest;
+
+/// 随机 ID 在 golden 文件中的占位符
+const ID_PLACEHOLDER: &str = "<id>";
+
+fn fixture_dir(kind: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(kind)
+}
+
+/// 按文件名顺序列出 fixture（不含 golden 文件）
+fn fixtures(kind: &str) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(fixture_dir(kind))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == "json")
+                && !path.to_string_lossy().ends_with(".golden.json")
+        })
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "{} 下没有 fixture", kind);
+    paths
+}
+
+/// 对每个 fixture 计算输出并与 golden 文件比对（golden-update 模式下改为写入）
+fn check_golden(kind: &str, render: impl Fn(Value) -> Value) {
+    let mut failures = Vec::new();
+    for path in fixtures(kind) {
+        let input: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|e| panic!("{} 不是合法 JSON: {}", path.display(), e));
+        let actual = render(input);
+        let golden_path = path.with_extension("golden.json");
+
+        if cfg!(feature = "golden-update") {
+            let content = serde_json::to_string_pretty(&actual).unwrap() + "\n";
+            std::fs::write(&golden_path, content).unwrap();
+            continue;
+        }
+
+        match std::fs::read_to_string(&golden_path) {
+            Ok(content) => {
+                let expected: Value = serde_json::from_str(&content).unwrap();
+                if expected != actual {
+                    failures.push(format!(
+                        "{}:\n--- 期望\n{}\n+++ 实际\n{}",
+                        golden_path.display(),
+                        serde_json::to_string_pretty(&expected).unwrap(),
+                        serde_json::to_string_pretty(&actual).unwrap()
+                    ));
+                }
+            }
+            Err(_) => failures.push(format!("{} 不存在", golden_path.display())),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "golden 比对失败（确认改动符合预期后运行 `cargo test --features golden-update` 更新）:\n\n{}",
+        failures.join("\n\n")
+    );
+}
+
+/// 将请求转换结果渲染为 golden JSON（随机 ID 替换为占位符）
+fn render_conversion(input: Value) -> Value {
+    let request: MessagesRequest = serde_json::from_value(input).unwrap();
+    match convert_request(&request) {
+        Ok(result) => {
+            let mut state = serde_json::to_value(&result.conversation_state).unwrap();
+            for key in ["conversationId", "agentContinuationId"] {
+                if state.get(key).is_some() {
+                    state[key] = json!(ID_PLACEHOLDER);
+                }
+            }
+            json!({
+                "modelId": result.model_id,
+                "conversationState": state
+            })
+        }
+        Err(e) => json!({ "error": e.to_string() }),
+    }
+}
+
+/// 将 fixture 中的 Kiro 事件（`{"<eventType>": payload}`）转换为 Event
+fn parse_event(value: &Value) -> Event {
+    let (kind, payload) = value
+        .as_object()
+        .and_then(|obj| obj.iter().next())
+        .expect("事件格式应为 {\"<eventType>\": payload}");
+    let payload = payload.clone();
+    match kind.as_str() {
+        "assistantResponseEvent" => {
+            Event::AssistantResponse(serde_json::from_value(payload).unwrap())
+        }
+        "toolUseEvent" => Event::ToolUse(serde_json::from_value(payload).unwrap()),
+        "contextUsageEvent" => Event::ContextUsage(serde_json::from_value(payload).unwrap()),
+        "exception" => Event::Exception {
+            exception_type: payload["exceptionType"].as_str().unwrap().to_string(),
+            message: payload["message"].as_str().unwrap_or("").to_string(),
+        },
+        other => panic!("未知事件类型: {}", other),
+    }
+}
+
+/// 将 Kiro 事件序列渲染为 Anthropic SSE 事件序列
+fn render_stream(input: Value) -> Value {
+    let mut ctx = StreamContext::new_with_thinking(
+        input["model"].as_str().unwrap(),
+        input["inputTokens"].as_i64().unwrap_or(1) as i32,
+        input["thinking"].as_bool().unwrap_or(false),
+    );
+    ctx.json_deltas = input["jsonDeltas"].as_bool().unwrap_or(false);
+
+    let mut events = ctx.generate_initial_events();
+    for event in input["events"].as_array().unwrap() {
+        events.extend(ctx.process_kiro_event(&parse_event(event)));
+    }
+    events.extend(ctx.generate_final_events());
+
+    Value::Array(
+        events
+            .into_iter()
+            .map(|event| {
+                let mut data = event.data().into_owned();
+                if data.pointer("/message/id").is_some() {
+                    data["message"]["id"] = json!(ID_PLACEHOLDER);
+                }
+                json!({ "event": event.event, "data": data })
+            })
+            .collect(),
+    )
+}
+
+#[test]
+fn golden_request_conversion() {
+    check_golden("conversion", render_conversion);
+}
+
+#[test]
+fn golden_stream_events() {
+    check_golden("stream", render_stream);
+}
@@ -1,36 +1,64 @@
 //! Anthropic API Handler 函数
 
 use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Instant;
 
+use crate::common::{auth, request_id};
+use crate::kiro::connections;
+use crate::kiro::failure_injection::InjectedFailure;
+use crate::kiro::model::api_key::ApiKey;
 use crate::kiro::model::events::Event;
+use crate::kiro::model::request_log::RequestLog;
 use crate::kiro::model::requests::kiro::KiroRequest;
+use crate::kiro::model::transcript::Transcript;
 use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::replication;
+use crate::kiro::token_manager::{AcquireError, RequestPriority};
+use crate::model::config::Config;
 use crate::token;
 use axum::{
-    Json as JsonExtractor,
+    Extension, Json as JsonExtractor,
     body::Body,
     extract::State,
-    http::{StatusCode, header},
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Json, Response},
 };
 use bytes::Bytes;
 use futures::{Stream, StreamExt, stream};
 use serde_json::json;
 use std::time::Duration;
-use tokio::time::interval;
+use tokio::time::{self, interval};
 use uuid::Uuid;
 
-use super::converter::{ConversionError, convert_request};
+use super::annotation::Annotation;
+use super::beta::{ANTHROPIC_BETA_HEADER, BetaFeatures, add_cache_compat_fields};
+use super::converter::{
+    ConversionError, convert_request, map_model, normalize_messages, retain_sent_tools,
+};
+use super::deprecation;
+use super::images;
 use super::middleware::AppState;
-use super::stream::{SseEvent, StreamContext};
+use super::pacing::pace_sse_stream;
+use super::service_tier::{self, add_service_tier};
+use super::sse::{SseEncoder, SseEvent, SseSequence};
+use super::stream::{
+    CompletionCheck, OUTPUT_LIMIT_STOP_REASON, OutputBudget, StreamContext, TRUNCATED_ERROR_MESSAGE,
+};
+use super::templates::apply_prompt_template;
+use super::tool_schema;
+use super::transcript;
+use super::transforms::TransformLog;
 use super::types::{
     CountTokensRequest, CountTokensResponse, ErrorResponse, MessagesRequest, Model, ModelsResponse,
+    PoolStatus, ReadyResponse,
 };
+use super::usage::UsageTracker;
 
 /// GET /v1/models
 ///
-/// 返回可用的模型列表
-pub async fn get_models() -> impl IntoResponse {
+/// 返回可用的模型列表（使用客户端 API Key 时仅返回该 Key 允许的模型）
+pub async fn get_models(client_key: Option<Extension<ApiKey>>) -> impl IntoResponse {
     tracing::info!("Received GET /v1/models request");
 
     let models = vec![
@@ -62,6 +90,14 @@ pub async fn get_models() -> impl IntoResponse {
             max_tokens: 32000,
         },
     ];
+    let models = models
+        .into_iter()
+        .filter(|m| {
+            client_key
+                .as_ref()
+                .is_none_or(|k| key_allows_model(k, &m.id))
+        })
+        .collect();
 
     Json(ModelsResponse {
         object: "list".to_string(),
@@ -69,12 +105,483 @@ pub async fn get_models() -> impl IntoResponse {
     })
 }
 
+/// GET /ready
+///
+/// 就绪检查：至少有一个可用凭据时返回 200，否则返回 503（热备实例始终返回 503）
+pub async fn ready(State(state): State<AppState>) -> Response {
+    let pool = match &state.kiro_provider {
+        Some(provider) => pool_status(provider).await,
+        None => PoolStatus {
+            total: 0,
+            available: 0,
+        },
+    };
+
+    let (status_code, status) = if replication::is_standby() {
+        (StatusCode::SERVICE_UNAVAILABLE, "standby")
+    } else if pool.available > 0 {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not_ready")
+    };
+
+    (
+        status_code,
+        Json(ReadyResponse {
+            status: status.to_string(),
+            pool,
+        }),
+    )
+        .into_response()
+}
+
+/// 获取凭据池状态
+async fn pool_status(provider: &crate::kiro::provider::KiroProvider) -> PoolStatus {
+    provider
+        .token_manager()
+        .blocking(|token_manager| PoolStatus {
+            total: token_manager.total_count(),
+            available: token_manager.available_count(),
+        })
+        .await
+}
+
+/// 上游实际使用的凭据 ID（通过响应扩展传递给请求日志）
+#[derive(Clone, Copy)]
+struct UpstreamCredential(u64);
+
+/// 实际处理非流式请求的上游凭据
+#[derive(Clone, Copy)]
+struct ServedBy {
+    /// 凭据 ID
+    credential_id: u64,
+    /// 回显的服务等级（客户端未指定 `service_tier` 时为 None）
+    service_tier: Option<RequestPriority>,
+}
+
+/// 客户端指定了 `service_tier` 时，按实际使用的凭据确定回显的服务等级
+async fn served_tier(
+    provider: &crate::kiro::provider::KiroProvider,
+    credential_id: u64,
+    options: &MessagesOptions,
+) -> Option<RequestPriority> {
+    options.service_tier?;
+    Some(
+        provider
+            .token_manager()
+            .served_priority(credential_id)
+            .await,
+    )
+}
+
+/// 单次 /v1/messages 请求的附加选项（由请求头、请求体扩展字段和客户端 Key 决定）
+struct MessagesOptions {
+    /// 启用的 anthropic-beta 特性
+    betas: BetaFeatures,
+    /// 流式输出节流速率（每秒 tokens，None 表示不节流）
+    output_tokens_per_second: Option<u32>,
+    /// 是否保证流式增量的累积内容为可补全的部分 JSON
+    json_deltas: bool,
+    /// 是否最多返回一个工具调用（`tool_choice.disable_parallel_tool_use`）
+    single_tool_use: bool,
+    /// 用量记录（未配置数据库时为 None）
+    usage: Option<UsageTracker>,
+    /// 请求转换记录（客户端通过 `x-kiro-debug: transforms` 要求回显时为 Some）
+    transforms: Option<TransformLog>,
+    /// 客户端指定的服务等级对应的请求优先级（未指定时为 None，按普通优先级处理，响应不回显服务等级）
+    service_tier: Option<RequestPriority>,
+    /// 粘性会话键（未启用 `stickySessions` 或请求未携带会话键时为 None）
+    session: Option<String>,
+    /// 响应标注（未配置 `responseAnnotation` 时为 None）
+    annotation: Option<Arc<Annotation>>,
+}
+
+impl MessagesOptions {
+    /// 选择凭据时使用的请求优先级
+    fn priority(&self) -> RequestPriority {
+        self.service_tier.unwrap_or_default()
+    }
+}
+
+/// 错误响应体读取上限（用于提取错误信息写入请求日志）
+const ERROR_BODY_LIMIT: usize = 64 * 1024;
+
+/// 请求标签请求头（自由文本，记录到请求日志用于按任务/流水线统计用量）
+const REQUEST_TAG_HEADER: &str = "x-kiro-tag";
+
+/// 请求标签最大长度（字符）
+const MAX_REQUEST_TAG_CHARS: usize = 128;
+
+/// 从请求头提取请求标签（去除首尾空白，超长截断，空值忽略）
+pub(super) fn extract_request_tag(headers: &HeaderMap) -> Option<String> {
+    let tag = headers.get(REQUEST_TAG_HEADER)?.to_str().ok()?.trim();
+    (!tag.is_empty()).then(|| tag.chars().take(MAX_REQUEST_TAG_CHARS).collect())
+}
+
+/// 提取粘性会话键：优先使用 `stickySessionHeader` 请求头，其次为 `metadata.user_id`（空值忽略）
+fn sticky_session_key(
+    config: &Config,
+    headers: &HeaderMap,
+    payload: &MessagesRequest,
+) -> Option<String> {
+    if !config.sticky_sessions {
+        return None;
+    }
+    let from_header = config
+        .sticky_session_header
+        .as_deref()
+        .and_then(|name| headers.get(name))
+        .and_then(|v| v.to_str().ok());
+    let from_metadata = payload.metadata.as_ref().and_then(|m| m.user_id.as_deref());
+    from_header
+        .into_iter()
+        .chain(from_metadata)
+        .map(str::trim)
+        .find(|key| !key.is_empty())
+        .map(str::to_string)
+}
+
 /// POST /v1/messages
 ///
-/// 创建消息（对话）
+/// 创建消息（对话），并记录请求日志
 pub async fn post_messages(
     State(state): State<AppState>,
+    client_key: Option<Extension<ApiKey>>,
+    headers: HeaderMap,
     JsonExtractor(payload): JsonExtractor<MessagesRequest>,
+) -> Response {
+    process_messages(state, client_key.as_deref(), &headers, payload).await
+}
+
+/// 客户端 Key 的模型白名单是否允许该模型（匹配请求模型或其映射后的 Kiro 模型 ID 均视为允许）
+fn key_allows_model(key: &ApiKey, model: &str) -> bool {
+    key.allows_model(model) || map_model(model).is_some_and(|id| key.allows_model(&id))
+}
+
+/// 校验客户端 Key 的模型白名单，不允许时返回 403 响应（`model` 应为弃用改写后实际发送的模型）
+fn reject_disallowed_model(client_key: Option<&ApiKey>, model: &str) -> Option<Response> {
+    let key = client_key.filter(|k| !key_allows_model(k, model))?;
+    tracing::warn!(
+        "客户端 API Key {} 不允许使用模型 {}，拒绝请求",
+        key.name,
+        model
+    );
+    Some(
+        (
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse::new(
+                "permission_error",
+                format!("This API key is not allowed to use model '{}'", model),
+            )),
+        )
+            .into_response(),
+    )
+}
+
+/// 处理 Anthropic 格式的消息请求并记录请求日志（/v1/messages 与 OpenAI 兼容端点共用）
+///
+/// `client_key` 为通过 Admin API 签发的客户端 Key（使用配置文件中的 `apiKey` 时为 None）
+pub(super) async fn process_messages(
+    state: AppState,
+    client_key: Option<&ApiKey>,
+    headers: &HeaderMap,
+    mut payload: MessagesRequest,
+) -> Response {
+    let service_tier = match service_tier::parse(payload.service_tier.as_deref()) {
+        Ok(tier) => tier,
+        Err(message) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new("invalid_request_error", message)),
+            )
+                .into_response();
+        }
+    };
+
+    if let Err(response) = validate_request(&state, &mut payload).await {
+        return response;
+    }
+
+    let created_at = chrono::Utc::now();
+    let started = Instant::now();
+    let transforms = TransformLog::from_headers(headers);
+    // 已弃用的模型照常处理，下线后改写为后继模型（日志与用量记录实际使用的模型）
+    let deprecation = state.kiro_provider.as_ref().and_then(|p| {
+        deprecation::check(p.token_manager().config(), &mut payload.model, created_at)
+    });
+    if let (Some(transforms), Some(notice)) = (&transforms, &deprecation)
+        && notice.rewritten
+    {
+        transforms.record(format!("model_rewrite={}->{}", notice.model, payload.model));
+    }
+    if let Some(response) = reject_disallowed_model(client_key, &payload.model) {
+        return response;
+    }
+    let model = payload.model.clone();
+    let stream = payload.stream;
+    let seed = payload.seed;
+    let database = state
+        .kiro_provider
+        .as_ref()
+        .map(|p| p.token_manager().database().clone());
+    let request_logs = state.request_logs.clone();
+    let transcript = state
+        .transcripts
+        .clone()
+        .map(|store| (store, transcript::capture_request(&payload)));
+    let raw_key = auth::extract_api_key_from_headers(headers);
+    let tag = extract_request_tag(headers);
+    let options = MessagesOptions {
+        betas: BetaFeatures::from_headers(headers),
+        output_tokens_per_second: state.kiro_provider.as_ref().and_then(|p| {
+            p.token_manager()
+                .config()
+                .output_tokens_per_second_for(raw_key.as_deref())
+        }),
+        json_deltas: payload.wants_json(),
+        single_tool_use: payload.disable_parallel_tool_use(),
+        usage: database.clone().map(|database| {
+            let tracker = UsageTracker::new(database, model.clone(), tag.clone(), stream);
+            match (&state.token_buckets, &raw_key) {
+                (Some(buckets), Some(key)) => {
+                    tracker.with_token_charge(buckets.clone(), key.clone())
+                }
+                _ => tracker,
+            }
+        }),
+        transforms,
+        service_tier,
+        session: state
+            .kiro_provider
+            .as_ref()
+            .and_then(|p| sticky_session_key(p.token_manager().config(), headers, &payload)),
+        annotation: state.annotation.clone(),
+    };
+
+    let mut response = handle_messages(state, payload, &options).await;
+
+    // 回显已支持的 beta 特性，便于客户端做特性检测
+    if let Some(value) = options.betas.header_value()
+        && let Ok(value) = header::HeaderValue::from_str(&value)
+    {
+        response.headers_mut().insert(ANTHROPIC_BETA_HEADER, value);
+    }
+    if let Some(notice) = &deprecation {
+        notice.apply_headers(response.headers_mut());
+    }
+    if let Some(transforms) = &options.transforms {
+        transforms.apply_header(response.headers_mut());
+    }
+
+    let Some(request_logs) = request_logs else {
+        return response;
+    };
+
+    let credential_id = response
+        .extensions()
+        .get::<UpstreamCredential>()
+        .map(|c| c.0);
+    let (mut response, error) = extract_error_message(response).await;
+    if let Some(usage) = &options.usage {
+        usage.set_response(credential_id, response.status().as_u16());
+    }
+
+    let key_fingerprint = raw_key.map(|key| auth::key_fingerprint(&key));
+    let log = RequestLog {
+        id: None,
+        created_at,
+        model: model.clone(),
+        credential_id,
+        status: response.status().as_u16(),
+        client_key: key_fingerprint.clone(),
+        latency_ms: started.elapsed().as_millis() as u64,
+        stream,
+        error,
+        tag,
+        seed,
+        request_id: request_id::current(),
+        sse_events: None,
+    };
+    // 流式响应在流结束（或客户端断开）时记录，附带已发送的事件数
+    match response.extensions().get::<SseSequence>().cloned() {
+        Some(sequence) => {
+            response = on_body_drop(response, move || {
+                request_logs.record(RequestLog {
+                    sse_events: Some(sequence.last_id()),
+                    ..log
+                })
+            });
+        }
+        None => request_logs.record(log),
+    }
+
+    match transcript {
+        Some((store, request)) => transcript::tee(
+            store,
+            Transcript {
+                id: None,
+                created_at,
+                request_id: request_id::current(),
+                model,
+                credential_id,
+                client_key: key_fingerprint,
+                stream,
+                status: 0,
+                latency_ms: 0,
+                request,
+                response: serde_json::Value::Null,
+                stop_reason: None,
+                input_tokens: None,
+                output_tokens: None,
+            },
+            started,
+            response,
+        ),
+        None => response,
+    }
+}
+
+/// 释放时执行回调
+struct OnDrop<F: FnOnce()>(Option<F>);
+
+impl<F: FnOnce()> Drop for OnDrop<F> {
+    fn drop(&mut self) {
+        if let Some(f) = self.0.take() {
+            f();
+        }
+    }
+}
+
+/// 响应体发送完毕或被丢弃（客户端断开）时执行回调
+fn on_body_drop(response: Response, f: impl FnOnce() + Send + 'static) -> Response {
+    let (parts, body) = response.into_parts();
+    let guard = OnDrop(Some(f));
+    let stream = body.into_data_stream().map(move |chunk| {
+        // 捕获 guard，使其随响应体一起释放
+        let _ = &guard;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+/// 校验工具定义与图片内容（/v1/messages 与 count_tokens 共用）
+///
+/// 工具定义无效时直接拒绝，避免上游返回难以定位的错误；url 来源的图片下载为 base64，
+/// 上游不支持的图片直接拒绝
+async fn validate_request(state: &AppState, payload: &mut MessagesRequest) -> Result<(), Response> {
+    let invalid = |message: String| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new("invalid_request_error", message)),
+        )
+            .into_response()
+    };
+    if let Some(tools) = &payload.tools
+        && let Err(message) = tool_schema::validate_tools(tools)
+    {
+        tracing::warn!("工具定义无效: {}", message);
+        return Err(invalid(message));
+    }
+    if let Some(fetcher) = &state.image_fetcher {
+        fetcher
+            .resolve(&mut payload.messages)
+            .await
+            .map_err(invalid)?;
+    }
+    images::validate_images(&mut payload.messages).map_err(|message| {
+        tracing::warn!("图片内容无效: {}", message);
+        invalid(message)
+    })
+}
+
+/// 展开提示词模板并合并连续的同角色消息（/v1/messages 与 count_tokens 共用）
+async fn normalize_request(
+    provider: &crate::kiro::provider::KiroProvider,
+    payload: &mut MessagesRequest,
+    transforms: Option<&TransformLog>,
+) -> Result<(), Response> {
+    // 展开提示词模板
+    if let Some(reference) = payload.prompt_template.take() {
+        if let Err(message) = apply_prompt_template(
+            provider.token_manager().database(),
+            &reference,
+            &mut payload.system,
+        )
+        .await
+        {
+            tracing::warn!("展开提示词模板失败: {}", message);
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new("invalid_request_error", message)),
+            )
+                .into_response());
+        }
+        if let Some(transforms) = transforms {
+            transforms.record(format!("system_injection=template:{}", reference.name));
+        }
+    }
+
+    // 合并连续的同角色消息
+    if provider.token_manager().config().normalize_messages {
+        let before = payload.messages.len();
+        payload.messages = normalize_messages(std::mem::take(&mut payload.messages));
+        if let Some(transforms) = transforms
+            && payload.messages.len() != before
+        {
+            transforms.record(format!(
+                "messages_merged={}->{}",
+                before,
+                payload.messages.len()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 请求转换失败响应
+fn conversion_error_response(e: &ConversionError) -> Response {
+    let message = match e {
+        ConversionError::UnsupportedModel(model) => format!("模型不支持: {}", model),
+        ConversionError::EmptyMessages => "消息列表为空".to_string(),
+    };
+    tracing::warn!("请求转换失败: {}", e);
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse::new("invalid_request_error", message)),
+    )
+        .into_response()
+}
+
+/// 对失败响应读取错误信息，并重新构建响应体
+async fn extract_error_message(response: Response) -> (Response, Option<String>) {
+    if response.status().is_success() {
+        return (response, None);
+    }
+
+    let (parts, body) = response.into_parts();
+    match axum::body::to_bytes(body, ERROR_BODY_LIMIT).await {
+        Ok(bytes) => {
+            let message = serde_json::from_slice::<serde_json::Value>(&bytes)
+                .ok()
+                .and_then(|v| {
+                    v.pointer("/error/message")
+                        .and_then(|m| m.as_str())
+                        .map(|m| m.to_string())
+                });
+            (Response::from_parts(parts, Body::from(bytes)), message)
+        }
+        Err(e) => {
+            tracing::warn!("读取错误响应体失败: {}", e);
+            (Response::from_parts(parts, Body::empty()), None)
+        }
+    }
+}
+
+/// 处理 /v1/messages 请求
+async fn handle_messages(
+    state: AppState,
+    mut payload: MessagesRequest,
+    options: &MessagesOptions,
 ) -> Response {
     tracing::info!(
         model = %payload.model,
@@ -99,28 +606,64 @@ pub async fn post_messages(
         }
     };
 
+    // 热备实例不对外提供服务
+    if replication::is_standby() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ErrorResponse::new(
+                "service_unavailable",
+                "This instance is a warm standby. Promote it via the Admin API: POST /api/admin/replication/promote",
+            )),
+        )
+            .into_response();
+    }
+
+    // 检查凭据池是否可用（没有凭据或全部禁用时直接返回 503）
+    let pool = pool_status(&provider).await;
+    if pool.available == 0 {
+        tracing::warn!(
+            "凭据池不可用（可用: {}/{}），拒绝请求",
+            pool.available,
+            pool.total
+        );
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ErrorResponse::pool_unavailable(pool)),
+        )
+            .into_response();
+    }
+
+    let transforms = options.transforms.as_ref();
+
+    // 按 beta 特性限制思考预算
+    if let Some(thinking) = payload.thinking.as_mut() {
+        let requested = thinking.budget_tokens;
+        thinking.clamp_budget(options.betas.extended_output());
+        if let Some(transforms) = transforms
+            && thinking.budget_tokens != requested
+        {
+            transforms.record(format!(
+                "thinking_budget_clamp={}->{}",
+                requested, thinking.budget_tokens
+            ));
+        }
+    }
+
+    if let Err(response) = normalize_request(&provider, &mut payload, transforms).await {
+        return response;
+    }
+
     // 转换请求
     let conversion_result = match convert_request(&payload) {
         Ok(result) => result,
-        Err(e) => {
-            let (error_type, message) = match &e {
-                ConversionError::UnsupportedModel(model) => {
-                    ("invalid_request_error", format!("模型不支持: {}", model))
-                }
-                ConversionError::EmptyMessages => {
-                    ("invalid_request_error", "消息列表为空".to_string())
-                }
-            };
-            tracing::warn!("请求转换失败: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(error_type, message)),
-            )
-                .into_response();
-        }
+        Err(e) => return conversion_error_response(&e),
     };
 
     // 构建 Kiro 请求
+    let kiro_model = conversion_result.model_id;
+    if let Some(transforms) = transforms {
+        transforms.record(format!("model_map={}->{}", payload.model, kiro_model));
+    }
     let kiro_request = KiroRequest {
         conversation_state: conversion_result.conversation_state,
         profile_arn: state.profile_arn.clone(),
@@ -143,13 +686,18 @@ pub async fn post_messages(
 
     tracing::debug!("Kiro request body: {}", request_body);
 
-    // 估算输入 tokens
-    let input_tokens = token::count_all_tokens(
-        payload.model.clone(),
-        payload.system,
-        payload.messages,
-        payload.tools,
-    ) as i32;
+    // 估算输入 tokens（按实际发送的工具计数）
+    if let Some(tools) = payload.tools.as_mut() {
+        retain_sent_tools(tools);
+    }
+    let input_tokens = token::count_all_tokens(CountTokensRequest {
+        model: payload.model.clone(),
+        messages: payload.messages,
+        system: payload.system,
+        tools: payload.tools,
+        tool_choice: payload.tool_choice,
+        prompt_template: None,
+    }) as i32;
 
     // 检查是否启用了thinking
     let thinking_enabled = payload
@@ -164,14 +712,63 @@ pub async fn post_messages(
             provider,
             &request_body,
             &payload.model,
+            &kiro_model,
             input_tokens,
             thinking_enabled,
+            options,
         )
         .await
     } else {
         // 非流式响应
-        handle_non_stream_request(provider, &request_body, &payload.model, input_tokens).await
+        handle_non_stream_request(
+            provider,
+            &request_body,
+            &payload.model,
+            &kiro_model,
+            input_tokens,
+            options,
+        )
+        .await
+    }
+}
+
+/// 上游调用失败响应
+///
+/// 无法获取可用凭据（超时或全部失败）时返回 503 并列出各凭据的失败原因，
+/// staging 故障注入按注入的状态码返回，其余返回 502
+fn upstream_error_response(e: &anyhow::Error) -> Response {
+    if let Some(failure) = e.downcast_ref::<InjectedFailure>() {
+        let error_type = match failure.status.as_u16() {
+            401 => "authentication_error",
+            403 => "permission_error",
+            404 => "not_found_error",
+            413 => "request_too_large",
+            429 => "rate_limit_error",
+            529 => "overloaded_error",
+            400..=499 => "invalid_request_error",
+            _ => "api_error",
+        };
+        return (
+            failure.status,
+            Json(ErrorResponse::new(error_type, failure.to_string())),
+        )
+            .into_response();
+    }
+    if let Some(err) = e.downcast_ref::<AcquireError>() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ErrorResponse::new("service_unavailable", err.to_string())),
+        )
+            .into_response();
     }
+    (
+        StatusCode::BAD_GATEWAY,
+        Json(ErrorResponse::new(
+            "api_error",
+            format!("上游 API 调用失败: {}", e),
+        )),
+    )
+        .into_response()
 }
 
 /// 处理流式请求
@@ -179,33 +776,67 @@ async fn handle_stream_request(
     provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
     request_body: &str,
     model: &str,
+    kiro_model: &str,
     input_tokens: i32,
     thinking_enabled: bool,
+    options: &MessagesOptions,
 ) -> Response {
     // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api_stream(request_body).await {
+    let response = match provider
+        .call_api_stream(
+            request_body,
+            kiro_model,
+            options.priority(),
+            options.session.as_deref(),
+        )
+        .await
+    {
         Ok(resp) => resp,
         Err(e) => {
             tracing::error!("Kiro API 调用失败: {}", e);
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse::new(
-                    "api_error",
-                    format!("上游 API 调用失败: {}", e),
-                )),
-            )
-                .into_response();
+            return upstream_error_response(&e);
         }
     };
 
+    let served_tier = served_tier(&provider, response.credential_id, options).await;
+
     // 创建流处理上下文
     let mut ctx = StreamContext::new_with_thinking(model, input_tokens, thinking_enabled);
+    ctx.cache_compat_fields = options.betas.prompt_caching();
+    ctx.json_deltas = options.json_deltas;
+    ctx.single_tool_use = options.single_tool_use;
+    ctx.output_budget = output_budget(&provider);
+    ctx.usage = options.usage.clone();
+    ctx.transforms = options.transforms.clone();
+    ctx.service_tier = served_tier;
+    ctx.annotation = options.annotation.clone();
+    ctx.completion = completion_check(&provider);
 
     // 生成初始事件
     let initial_events = ctx.generate_initial_events();
 
-    // 创建 SSE 流
-    let stream = create_sse_stream(response, ctx, initial_events);
+    // 创建 SSE 流（按需添加输出节流），上游响应体由连接跟踪任务读取
+    let credential_id = response.credential_id;
+    let body = response.into_body(provider.token_manager().config().upstream_max_lifetime());
+    let stream = create_sse_stream(
+        body,
+        ctx,
+        initial_events,
+        credential_id,
+        stream_idle_timeout(&provider),
+    );
+    // 事件 ID 在节流拆分之后分配，与客户端实际收到的事件一一对应
+    let sequence = SseSequence::default();
+    let numbered = {
+        let sequence = sequence.clone();
+        move |chunk: Result<Bytes, Infallible>| chunk.map(|bytes| sequence.number(bytes))
+    };
+    let body = match options.output_tokens_per_second {
+        Some(rate) => {
+            Body::from_stream(pace_sse_stream(stream, rate, !options.json_deltas).map(numbered))
+        }
+        None => Body::from_stream(stream.map(numbered)),
+    };
 
     // 返回 SSE 响应
     Response::builder()
@@ -213,7 +844,9 @@ async fn handle_stream_request(
         .header(header::CONTENT_TYPE, "text/event-stream")
         .header(header::CACHE_CONTROL, "no-cache")
         .header(header::CONNECTION, "keep-alive")
-        .body(Body::from_stream(stream))
+        .extension(UpstreamCredential(credential_id))
+        .extension(sequence)
+        .body(body)
         .unwrap()
 }
 
@@ -222,28 +855,79 @@ const PING_INTERVAL_SECS: u64 = 25;
 
 /// 创建 ping 事件的 SSE 字符串
 fn create_ping_sse() -> Bytes {
-    Bytes::from("event: ping\ndata: {\"type\": \"ping\"}\n\n")
+    Bytes::from_static(b"event: ping\ndata: {\"type\": \"ping\"}\n\n")
+}
+
+/// 按配置创建上游响应完整性检查
+fn completion_check(provider: &crate::kiro::provider::KiroProvider) -> CompletionCheck {
+    CompletionCheck::new(
+        provider
+            .token_manager()
+            .config()
+            .truncation_require_terminal_event,
+    )
+}
+
+/// 按配置创建单次请求的输出预算
+fn output_budget(provider: &crate::kiro::provider::KiroProvider) -> OutputBudget {
+    let config = provider.token_manager().config();
+    OutputBudget::new(config.max_output_bytes, config.max_output_tokens)
+}
+
+/// 流式响应空闲超时配置
+#[derive(Debug, Clone)]
+struct StreamIdleTimeout {
+    timeout: Duration,
+    stop_reason: String,
+}
+
+/// 按配置创建流式响应空闲超时（未启用时返回 None）
+fn stream_idle_timeout(
+    provider: &crate::kiro::provider::KiroProvider,
+) -> Option<StreamIdleTimeout> {
+    let config = provider.token_manager().config();
+    config
+        .stream_idle_timeout()
+        .map(|timeout| StreamIdleTimeout {
+            timeout,
+            stop_reason: config.stream_idle_stop_reason.clone(),
+        })
+}
+
+/// 等待直到距上次输出超过空闲超时（未启用时永不返回）
+async fn wait_idle(last_output: time::Instant, timeout: Option<Duration>) {
+    match timeout {
+        Some(timeout) => time::sleep_until(last_output + timeout).await,
+        None => std::future::pending().await,
+    }
 }
 
 /// 创建 SSE 事件流
 fn create_sse_stream(
-    response: reqwest::Response,
+    body_stream: impl Stream<Item = anyhow::Result<Bytes>> + Send + 'static,
     ctx: StreamContext,
     initial_events: Vec<SseEvent>,
+    credential_id: u64,
+    idle: Option<StreamIdleTimeout>,
 ) -> impl Stream<Item = Result<Bytes, Infallible>> {
-    // 先发送初始事件
-    let initial_stream = stream::iter(
-        initial_events
-            .into_iter()
-            .map(|e| Ok(Bytes::from(e.to_sse_string()))),
-    );
+    // 结束时追加转换摘要注释（需在流结束时生成，以包含输出截断等转换）
+    let trailer = stream::iter(ctx.transforms.clone())
+        .then(|transforms| async move { Ok(transforms.sse_comment()) });
 
-    // 然后处理 Kiro 响应流，同时每25秒发送 ping 保活
-    let body_stream = response.bytes_stream();
+    // 先发送初始事件（整个流复用同一个编码缓冲区）
+    let mut encoder = SseEncoder::default();
+    let initial_bytes: Vec<Result<Bytes, Infallible>> = initial_events
+        .iter()
+        .map(|e| Ok(encoder.encode(e)))
+        .collect();
+    let initial_stream = stream::iter(initial_bytes);
 
+    // 然后处理 Kiro 响应流，同时每25秒发送 ping 保活
     let processing_stream = stream::unfold(
-        (body_stream, ctx, EventStreamDecoder::new(), false, interval(Duration::from_secs(PING_INTERVAL_SECS))),
-        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval)| async move {
+        (Box::pin(body_stream), ctx, EventStreamDecoder::new(), false, interval(Duration::from_secs(PING_INTERVAL_SECS)), time::Instant::now(), encoder),
+        move |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval, mut last_output, mut encoder)| {
+            let idle = idle.clone();
+            async move {
             if finished {
                 return None;
             }
@@ -274,47 +958,107 @@ fn create_sse_stream(
                                 }
                             }
 
+                            if !events.is_empty() {
+                                last_output = time::Instant::now();
+                            }
+                            ctx.report_usage();
+
+                            // 超出输出上限：发送最终事件并结束，丢弃上游流以断开连接
+                            let mut finished = false;
+                            if let Some(reason) = ctx.check_output_budget() {
+                                tracing::warn!(
+                                    "输出超出上限（{}），终止流式响应: 凭据 #{}, 模型 {}",
+                                    reason,
+                                    credential_id,
+                                    ctx.model
+                                );
+                                if let Some(transforms) = &ctx.transforms {
+                                    transforms.record(format!("output_truncated={}", reason));
+                                }
+                                events.extend(ctx.generate_final_events());
+                                finished = true;
+                            }
+
                             // 转换为 SSE 字节流
                             let bytes: Vec<Result<Bytes, Infallible>> = events
                                 .into_iter()
-                                .map(|e| Ok(Bytes::from(e.to_sse_string())))
+                                .map(|e| Ok(encoder.encode(&e)))
                                 .collect();
 
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)))
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, finished, ping_interval, last_output, encoder)))
                         }
                         Some(Err(e)) => {
-                            tracing::error!("读取响应流失败: {}", e);
-                            // 发送最终事件并结束
-                            let final_events = ctx.generate_final_events();
+                            tracing::error!(
+                                "读取响应流失败，以错误结束流式响应: 凭据 #{}, 模型 {}: {}",
+                                credential_id,
+                                ctx.model,
+                                e
+                            );
+                            connections::record_read_error();
+                            // 响应不完整：以 error 事件结束，不发送 message_stop
+                            let final_events = ctx.generate_truncated_events();
                             let bytes: Vec<Result<Bytes, Infallible>> = final_events
                                 .into_iter()
-                                .map(|e| Ok(Bytes::from(e.to_sse_string())))
+                                .map(|e| Ok(encoder.encode(&e)))
                                 .collect();
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)))
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, last_output, encoder)))
                         }
                         None => {
-                            // 流结束，发送最终事件
-                            let final_events = ctx.generate_final_events();
+                            // 流结束：未正常结束时以 error 事件告知客户端响应不完整，否则发送最终事件
+                            let final_events = match ctx.truncation() {
+                                Some(truncation) => {
+                                    tracing::warn!(
+                                        "上游响应被截断（{}），以错误结束流式响应: 凭据 #{}, 模型 {}",
+                                        truncation,
+                                        credential_id,
+                                        ctx.model
+                                    );
+                                    connections::record_truncated();
+                                    ctx.generate_truncated_events()
+                                }
+                                None => ctx.generate_final_events(),
+                            };
                             let bytes: Vec<Result<Bytes, Infallible>> = final_events
                                 .into_iter()
-                                .map(|e| Ok(Bytes::from(e.to_sse_string())))
+                                .map(|e| Ok(encoder.encode(&e)))
                                 .collect();
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)))
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, last_output, encoder)))
                         }
                     }
                 }
+                // 上游长时间无输出：以已生成的内容结束响应，丢弃上游流以断开连接
+                _ = wait_idle(last_output, idle.as_ref().map(|idle| idle.timeout)) => {
+                    let idle = idle.expect("未配置空闲超时时不会触发");
+                    tracing::warn!(
+                        "上游 {} 秒未产生输出，结束流式响应: 凭据 #{}, 模型 {}",
+                        idle.timeout.as_secs(),
+                        credential_id,
+                        ctx.model
+                    );
+                    if let Some(transforms) = &ctx.transforms {
+                        transforms.record(format!("idle_timeout_stop={}", idle.stop_reason));
+                    }
+                    ctx.state_manager.set_stop_reason(idle.stop_reason);
+                    let bytes: Vec<Result<Bytes, Infallible>> = ctx
+                        .generate_final_events()
+                        .into_iter()
+                        .map(|e| Ok(encoder.encode(&e)))
+                        .collect();
+                    Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, last_output, encoder)))
+                }
                 // 发送 ping 保活
                 _ = ping_interval.tick() => {
                     tracing::trace!("发送 ping 保活事件");
                     let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(create_ping_sse())];
-                    Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)))
+                    Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval, last_output, encoder)))
                 }
             }
+            }
         },
     )
     .flatten();
 
-    initial_stream.chain(processing_stream)
+    initial_stream.chain(processing_stream).chain(trailer)
 }
 
 /// 上下文窗口大小（200k tokens）
@@ -325,45 +1069,64 @@ async fn handle_non_stream_request(
     provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
     request_body: &str,
     model: &str,
+    kiro_model: &str,
     input_tokens: i32,
+    options: &MessagesOptions,
 ) -> Response {
     // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api(request_body).await {
+    let response = match provider
+        .call_api(
+            request_body,
+            kiro_model,
+            options.priority(),
+            options.session.as_deref(),
+        )
+        .await
+    {
         Ok(resp) => resp,
         Err(e) => {
             tracing::error!("Kiro API 调用失败: {}", e);
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse::new(
-                    "api_error",
-                    format!("上游 API 调用失败: {}", e),
-                )),
-            )
-                .into_response();
+            return upstream_error_response(&e);
         }
     };
 
-    // 读取响应体
-    let body_bytes = match response.bytes().await {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            tracing::error!("读取响应体失败: {}", e);
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse::new(
-                    "api_error",
-                    format!("读取响应失败: {}", e),
-                )),
-            )
-                .into_response();
-        }
+    let credential_id = response.credential_id;
+    let served = ServedBy {
+        credential_id,
+        service_tier: served_tier(&provider, credential_id, options).await,
     };
+    let body = response.into_body(provider.token_manager().config().upstream_max_lifetime());
+    let mut response = build_non_stream_response(
+        body,
+        model,
+        input_tokens,
+        output_budget(&provider),
+        completion_check(&provider),
+        served,
+        options,
+    )
+    .await;
+    response
+        .extensions_mut()
+        .insert(UpstreamCredential(credential_id));
+    response
+}
 
-    // 解析事件流
+/// 读取上游非流式响应并转换为 Anthropic 响应
+///
+/// 边读取边解码；输出超出预算时停止读取上游并以 output_limit_exceeded 返回已生成的内容
+async fn build_non_stream_response(
+    body: impl Stream<Item = anyhow::Result<Bytes>>,
+    model: &str,
+    input_tokens: i32,
+    mut budget: OutputBudget,
+    mut completion: CompletionCheck,
+    served: ServedBy,
+    options: &MessagesOptions,
+) -> Response {
+    let credential_id = served.credential_id;
     let mut decoder = EventStreamDecoder::new();
-    if let Err(e) = decoder.feed(&body_bytes) {
-        tracing::warn!("缓冲区溢出: {}", e);
-    }
+    let mut body = std::pin::pin!(body);
 
     let mut text_content = String::new();
     let mut tool_uses: Vec<serde_json::Value> = Vec::new();
@@ -375,72 +1138,138 @@ async fn handle_non_stream_request(
     // 收集工具调用的增量 JSON
     let mut tool_json_buffers: std::collections::HashMap<String, String> =
         std::collections::HashMap::new();
+    // 上游响应完整性检查见 `completion`（超出输出上限主动停止读取时不检查）
+    let mut stopped_early = false;
 
-    for result in decoder.decode_iter() {
-        match result {
-            Ok(frame) => {
-                if let Ok(event) = Event::from_frame(frame) {
-                    match event {
-                        Event::AssistantResponse(resp) => {
-                            text_content.push_str(&resp.content);
-                        }
-                        Event::ToolUse(tool_use) => {
-                            has_tool_use = true;
-
-                            // 累积工具的 JSON 输入
-                            let buffer = tool_json_buffers
-                                .entry(tool_use.tool_use_id.clone())
-                                .or_default();
-                            buffer.push_str(&tool_use.input);
-
-                            // 如果是完整的工具调用，添加到列表
-                            if tool_use.stop {
-                                let input: serde_json::Value = serde_json::from_str(buffer)
-                                    .unwrap_or_else(|e| {
-                                        tracing::warn!(
-                                            "工具输入 JSON 解析失败: {}, tool_use_id: {}, 原始内容: {}",
-                                            e, tool_use.tool_use_id, buffer
-                                        );
-                                        serde_json::json!({})
-                                    });
-
-                                tool_uses.push(json!({
-                                    "type": "tool_use",
-                                    "id": tool_use.tool_use_id,
-                                    "name": tool_use.name,
-                                    "input": input
-                                }));
+    while let Some(chunk) = body.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                tracing::error!("读取响应体失败: {}", e);
+                connections::record_read_error();
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    Json(ErrorResponse::new(
+                        "api_error",
+                        format!("读取响应失败: {}", e),
+                    )),
+                )
+                    .into_response();
+            }
+        };
+
+        // 解析事件流
+        if let Err(e) = decoder.feed(&chunk) {
+            tracing::warn!("缓冲区溢出: {}", e);
+        }
+
+        for result in decoder.decode_iter() {
+            match result {
+                Ok(frame) => {
+                    if let Ok(event) = Event::from_frame(frame) {
+                        completion.observe(&event);
+                        match event {
+                            Event::AssistantResponse(resp) => {
+                                budget.record(&resp.content);
+                                text_content.push_str(&resp.content);
                             }
-                        }
-                        Event::ContextUsage(context_usage) => {
-                            // 从上下文使用百分比计算实际的 input_tokens
-                            // 公式: percentage * 200000 / 100 = percentage * 2000
-                            let actual_input_tokens = (context_usage.context_usage_percentage
-                                * (CONTEXT_WINDOW_SIZE as f64)
-                                / 100.0)
-                                as i32;
-                            context_input_tokens = Some(actual_input_tokens);
-                            tracing::debug!(
-                                "收到 contextUsageEvent: {}%, 计算 input_tokens: {}",
-                                context_usage.context_usage_percentage,
-                                actual_input_tokens
-                            );
-                        }
-                        Event::Exception { exception_type, .. } => {
-                            if exception_type == "ContentLengthExceededException" {
+                            Event::ToolUse(tool_use) => {
+                                has_tool_use = true;
+                                budget.record(&tool_use.input);
+
+                                // 累积工具的 JSON 输入
+                                let buffer = tool_json_buffers
+                                    .entry(tool_use.tool_use_id.clone())
+                                    .or_default();
+                                buffer.push_str(&tool_use.input);
+
+                                // 如果是完整的工具调用，添加到列表（禁止并行调用时只保留第一个）
+                                if tool_use.stop
+                                    && (tool_uses.is_empty() || !options.single_tool_use)
+                                {
+                                    // 无参数的工具调用没有输入增量
+                                    let input: serde_json::Value = if buffer.trim().is_empty() {
+                                        json!({})
+                                    } else {
+                                        serde_json::from_str(buffer).unwrap_or_else(|e| {
+                                            tracing::warn!(
+                                                "工具输入 JSON 解析失败: {}, tool_use_id: {}, 原始内容: {}",
+                                                e, tool_use.tool_use_id, buffer
+                                            );
+                                            serde_json::json!({})
+                                        })
+                                    };
+
+                                    tool_uses.push(json!({
+                                        "type": "tool_use",
+                                        "id": tool_use.tool_use_id,
+                                        "name": tool_use.name,
+                                        "input": input
+                                    }));
+                                }
+                            }
+                            Event::ContextUsage(context_usage) => {
+                                // 从上下文使用百分比计算实际的 input_tokens
+                                // 公式: percentage * 200000 / 100 = percentage * 2000
+                                let actual_input_tokens = (context_usage.context_usage_percentage
+                                    * (CONTEXT_WINDOW_SIZE as f64)
+                                    / 100.0)
+                                    as i32;
+                                context_input_tokens = Some(actual_input_tokens);
+                                tracing::debug!(
+                                    "收到 contextUsageEvent: {}%, 计算 input_tokens: {}",
+                                    context_usage.context_usage_percentage,
+                                    actual_input_tokens
+                                );
+                            }
+                            Event::Exception { exception_type, .. }
+                                if exception_type == "ContentLengthExceededException" =>
+                            {
                                 stop_reason = "max_tokens".to_string();
                             }
+                            _ => {}
                         }
-                        _ => {}
                     }
                 }
+                Err(e) => {
+                    tracing::warn!("解码事件失败: {}", e);
+                }
             }
-            Err(e) => {
-                tracing::warn!("解码事件失败: {}", e);
+        }
+
+        // 超出输出上限：停止读取，丢弃上游流以断开连接
+        if let Some(reason) = budget.exceeded() {
+            tracing::warn!(
+                "输出超出上限（{}），终止响应: 凭据 #{}, 模型 {}",
+                reason,
+                credential_id,
+                model
+            );
+            stop_reason = OUTPUT_LIMIT_STOP_REASON.to_string();
+            if let Some(transforms) = &options.transforms {
+                transforms.record(format!("output_truncated={}", reason));
             }
+            stopped_early = true;
+            break;
         }
     }
 
+    // 上游未正常结束：内容不完整，返回错误以便客户端重试
+    if !stopped_early && let Some(truncation) = completion.truncation() {
+        tracing::warn!(
+            "上游响应被截断（{}），返回错误: 凭据 #{}, 模型 {}",
+            truncation,
+            credential_id,
+            model
+        );
+        connections::record_truncated();
+        return (
+            StatusCode::BAD_GATEWAY,
+            Json(ErrorResponse::new("api_error", TRUNCATED_ERROR_MESSAGE)),
+        )
+            .into_response();
+    }
+
     // 确定 stop_reason
     if has_tool_use && stop_reason == "end_turn" {
         stop_reason = "tool_use".to_string();
@@ -463,9 +1292,12 @@ async fn handle_non_stream_request(
 
     // 使用从 contextUsageEvent 计算的 input_tokens，如果没有则使用估算值
     let final_input_tokens = context_input_tokens.unwrap_or(input_tokens);
+    if let Some(usage) = &options.usage {
+        usage.set_tokens(final_input_tokens, output_tokens);
+    }
 
     // 构建 Anthropic 响应
-    let response_body = json!({
+    let mut response_body = json!({
         "id": format!("msg_{}", Uuid::new_v4().to_string().replace('-', "")),
         "type": "message",
         "role": "assistant",
@@ -478,6 +1310,15 @@ async fn handle_non_stream_request(
             "output_tokens": output_tokens
         }
     });
+    if options.betas.prompt_caching() {
+        add_cache_compat_fields(&mut response_body["usage"]);
+    }
+    if let Some(priority) = served.service_tier {
+        add_service_tier(&mut response_body["usage"], priority);
+    }
+    if let Some(annotation) = &options.annotation {
+        annotation.apply(&mut response_body, options.json_deltas);
+    }
 
     (StatusCode::OK, Json(response_body)).into_response()
 }
@@ -486,22 +1327,363 @@ async fn handle_non_stream_request(
 ///
 /// 计算消息的 token 数量
 pub async fn count_tokens(
+    State(state): State<AppState>,
+    client_key: Option<Extension<ApiKey>>,
     JsonExtractor(payload): JsonExtractor<CountTokensRequest>,
-) -> impl IntoResponse {
+) -> Response {
     tracing::info!(
         model = %payload.model,
         message_count = %payload.messages.len(),
         "Received POST /v1/messages/count_tokens request"
     );
 
-    let total_tokens = token::count_all_tokens(
-        payload.model,
-        payload.system,
-        payload.messages,
-        payload.tools,
-    ) as i32;
+    // 与 /v1/messages 执行相同的校验与规范化，使计数与实际发送的内容一致
+    let mut request = MessagesRequest::from(payload);
+    if let Err(response) = validate_request(&state, &mut request).await {
+        return response;
+    }
+    // 已下线的模型按后继模型计数
+    if let Some(provider) = &state.kiro_provider
+        && let Some(successor) = deprecation::rewritten_model(
+            provider.token_manager().config(),
+            &request.model,
+            chrono::Utc::now(),
+        )
+    {
+        request.model = successor;
+    }
+    if let Some(response) = reject_disallowed_model(client_key.as_deref(), &request.model) {
+        return response;
+    }
+    match &state.kiro_provider {
+        Some(provider) => {
+            if let Err(response) = normalize_request(provider, &mut request, None).await {
+                return response;
+            }
+        }
+        None if request.prompt_template.is_some() => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new(
+                    "invalid_request_error",
+                    "Kiro API provider not configured",
+                )),
+            )
+                .into_response();
+        }
+        None => {}
+    }
+    if let Err(e) = convert_request(&request) {
+        return conversion_error_response(&e);
+    }
+    if let Some(tools) = request.tools.as_mut() {
+        retain_sent_tools(tools);
+    }
+
+    let count = token::count_input_tokens(CountTokensRequest {
+        model: request.model,
+        messages: request.messages,
+        system: request.system,
+        tools: request.tools,
+        tool_choice: request.tool_choice,
+        prompt_template: None,
+    });
 
     Json(CountTokensResponse {
-        input_tokens: total_tokens.max(1) as i32,
+        input_tokens: (count.tokens as i32).max(1),
+        estimated: count.estimated,
     })
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_count_tokens_validates_like_messages() {
+        let count = |body: serde_json::Value| async move {
+            count_tokens(
+                State(AppState::new("test-key")),
+                None,
+                JsonExtractor(serde_json::from_value(body).unwrap()),
+            )
+            .await
+        };
+
+        let response = count(json!({
+            "model": "claude-sonnet-4",
+            "messages": [{"role": "user", "content": [
+                {"type": "image", "source": {"type": "base64", "media_type": "image/bmp", "data": "Qk0="}}
+            ]}]
+        }))
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = count(json!({"model": "claude-sonnet-4", "messages": []})).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = count(json!({
+            "model": "claude-sonnet-4",
+            "messages": [{"role": "user", "content": "hello"}]
+        }))
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_count_tokens_checks_key_model_allowlist() {
+        let key = ApiKey {
+            id: 1,
+            name: "alice".to_string(),
+            key_hash: String::new(),
+            fingerprint: String::new(),
+            allowed_models: Some(vec!["claude-sonnet-4.5".to_string()]),
+            rate_limit_per_minute: None,
+            expires_at: None,
+            revoked: false,
+            created_at: chrono::Utc::now(),
+        };
+        let count = |model: &str| {
+            let body = json!({"model": model, "messages": [{"role": "user", "content": "hi"}]});
+            count_tokens(
+                State(AppState::new("test-key")),
+                Some(Extension(key.clone())),
+                JsonExtractor(serde_json::from_value(body).unwrap()),
+            )
+        };
+
+        // 白名单按映射后的 Kiro 模型 ID 匹配
+        assert_eq!(
+            count("claude-sonnet-4-5-20250929").await.status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            count("claude-opus-4-5-20251101").await.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[test]
+    fn test_sticky_session_key() {
+        let payload: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 16,
+            "messages": [],
+            "metadata": {"user_id": "user-1"}
+        }))
+        .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-session-id", "session-1".parse().unwrap());
+
+        let mut config = Config::default();
+        assert_eq!(sticky_session_key(&config, &headers, &payload), None);
+
+        config.sticky_sessions = true;
+        assert_eq!(
+            sticky_session_key(&config, &headers, &payload).as_deref(),
+            Some("user-1")
+        );
+
+        config.sticky_session_header = Some("x-session-id".to_string());
+        assert_eq!(
+            sticky_session_key(&config, &headers, &payload).as_deref(),
+            Some("session-1")
+        );
+
+        // 请求头为空时回退到 metadata.user_id
+        headers.insert("x-session-id", " ".parse().unwrap());
+        assert_eq!(
+            sticky_session_key(&config, &headers, &payload).as_deref(),
+            Some("user-1")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sse_stream_finishes_on_idle_timeout() {
+        let mut ctx = StreamContext::new_with_thinking("claude-sonnet-4", 10, false);
+        let initial_events = ctx.generate_initial_events();
+        // 上游连接保持但不再产生任何数据
+        let body = stream::pending::<anyhow::Result<Bytes>>();
+        let idle = StreamIdleTimeout {
+            timeout: Duration::from_millis(50),
+            stop_reason: "max_tokens".to_string(),
+        };
+
+        let output: Vec<Bytes> = create_sse_stream(body, ctx, initial_events, 1, Some(idle))
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        let output = String::from_utf8(output.concat()).unwrap();
+
+        assert!(output.contains("\"stop_reason\":\"max_tokens\""));
+        assert!(
+            output
+                .trim_end()
+                .ends_with("data: {\"type\":\"message_stop\"}")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sse_stream_appends_transforms_comment() {
+        let mut ctx = StreamContext::new_with_thinking("claude-sonnet-4", 10, false);
+        let transforms = TransformLog::default();
+        transforms.record("model_map=claude-sonnet-4->claude-sonnet-4.5");
+        ctx.transforms = Some(transforms);
+        let initial_events = ctx.generate_initial_events();
+        let body = stream::pending::<anyhow::Result<Bytes>>();
+        let idle = StreamIdleTimeout {
+            timeout: Duration::from_millis(50),
+            stop_reason: "end_turn".to_string(),
+        };
+
+        let output: Vec<Bytes> = create_sse_stream(body, ctx, initial_events, 1, Some(idle))
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        let output = String::from_utf8(output.concat()).unwrap();
+
+        // 响应开始后发生的转换同样包含在结尾注释中
+        assert!(output.ends_with(
+            "data: {\"type\":\"message_stop\"}\n\n: x-kiro-transforms: model_map=claude-sonnet-4->claude-sonnet-4.5; idle_timeout_stop=end_turn\n\n"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sse_stream_reports_truncation() {
+        let before = connections::stats().upstream_truncated_total;
+        let mut ctx = StreamContext::new_with_thinking("claude-sonnet-4", 10, false);
+        ctx.completion = CompletionCheck::new(true);
+        let initial_events = ctx.generate_initial_events();
+        // 上游未发送任何结束事件即关闭连接
+        let body = stream::empty::<anyhow::Result<Bytes>>();
+
+        let output: Vec<Bytes> = create_sse_stream(body, ctx, initial_events, 1, None)
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        let output = String::from_utf8(output.concat()).unwrap();
+
+        assert!(output.contains("event: error"));
+        assert!(output.contains(TRUNCATED_ERROR_MESSAGE));
+        assert!(!output.contains("message_stop"));
+        assert!(connections::stats().upstream_truncated_total > before);
+    }
+
+    #[tokio::test]
+    async fn test_sse_stream_read_error_ends_with_error() {
+        let mut ctx = StreamContext::new_with_thinking("claude-sonnet-4", 10, false);
+        let initial_events = ctx.generate_initial_events();
+        // 读取上游响应中途出错
+        let body = stream::iter(vec![Err(anyhow::anyhow!("connection reset"))]);
+
+        let output: Vec<Bytes> = create_sse_stream(body, ctx, initial_events, 1, None)
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        let output = String::from_utf8(output.concat()).unwrap();
+
+        assert!(output.contains("event: error"));
+        assert!(output.contains(TRUNCATED_ERROR_MESSAGE));
+        assert!(!output.contains("message_stop"));
+    }
+
+    /// 超过最大存活时间后挂起的上游响应
+    fn forced_body() -> impl Stream<Item = anyhow::Result<Bytes>> {
+        let upstream = stream::pending::<reqwest::Result<Bytes>>();
+        connections::track(
+            1,
+            upstream,
+            Some(std::time::Duration::from_millis(20)),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_sse_stream_forced_close_ends_with_error() {
+        let mut ctx = StreamContext::new_with_thinking("claude-sonnet-4", 10, false);
+        let initial_events = ctx.generate_initial_events();
+
+        let output: Vec<Bytes> = create_sse_stream(forced_body(), ctx, initial_events, 1, None)
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        let output = String::from_utf8(output.concat()).unwrap();
+
+        assert!(output.contains("message_start"));
+        assert!(output.contains("event: error"));
+        assert!(!output.contains("message_stop"));
+    }
+
+    #[tokio::test]
+    async fn test_non_stream_response_forced_close() {
+        let options = MessagesOptions {
+            betas: BetaFeatures::default(),
+            output_tokens_per_second: None,
+            json_deltas: false,
+            single_tool_use: false,
+            usage: None,
+            transforms: None,
+            service_tier: None,
+            session: None,
+            annotation: None,
+        };
+        let response = build_non_stream_response(
+            forced_body(),
+            "claude-sonnet-4",
+            10,
+            OutputBudget::default(),
+            CompletionCheck::default(),
+            ServedBy {
+                credential_id: 1,
+                service_tier: None,
+            },
+            &options,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("最大存活时间"));
+    }
+
+    #[tokio::test]
+    async fn test_non_stream_response_reports_truncation() {
+        let options = MessagesOptions {
+            betas: BetaFeatures::default(),
+            output_tokens_per_second: None,
+            json_deltas: false,
+            single_tool_use: false,
+            usage: None,
+            transforms: None,
+            service_tier: None,
+            session: None,
+            annotation: None,
+        };
+        let response = |completion| {
+            build_non_stream_response(
+                stream::empty::<anyhow::Result<Bytes>>(),
+                "claude-sonnet-4",
+                10,
+                OutputBudget::default(),
+                completion,
+                ServedBy {
+                    credential_id: 1,
+                    service_tier: None,
+                },
+                &options,
+            )
+        };
+
+        // 默认不要求结束事件
+        assert_eq!(
+            response(CompletionCheck::default()).await.status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            response(CompletionCheck::new(true)).await.status(),
+            StatusCode::BAD_GATEWAY
+        );
+    }
 }
@@ -0,0 +1,293 @@
+//! 图片内容块（vision）
+//!
+//! 上游只接受 base64 编码的 JPEG / PNG / GIF / WebP 图片。`url` 来源的图片在转换前下载并
+//! 替换为 base64 来源（需配置 `imageUrlFetch`）；格式、大小或来源不受支持的图片在转发前
+//! 以 400 拒绝并指出字段路径，而不是由上游返回难以理解的错误
+
+use base64::Engine;
+use serde_json::{Value, json};
+
+use crate::http_client::{ProxyConfig, build_client};
+use crate::model::config::Config;
+
+use super::types::Message;
+
+/// 上游支持的图片类型
+const SUPPORTED_MEDIA_TYPES: &[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp"];
+
+/// 单张图片大小上限（解码后，与 Anthropic API 一致）
+const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// 下载图片的超时时间（秒）
+const FETCH_TIMEOUT_SECS: u64 = 30;
+
+/// 根据文件头识别图片类型
+fn sniff_media_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+/// 消息中的图片块（含 tool_result 内容中的图片）及其字段路径
+fn image_blocks(messages: &mut [Message]) -> Vec<(String, &mut Value)> {
+    let mut blocks = Vec::new();
+    for (i, message) in messages.iter_mut().enumerate() {
+        let Some(content) = message.content.as_array_mut() else {
+            continue;
+        };
+        for (j, block) in content.iter_mut().enumerate() {
+            let path = format!("messages.{}.content.{}", i, j);
+            match block.get("type").and_then(|v| v.as_str()) {
+                Some("image") => blocks.push((path, block)),
+                Some("tool_result") => {
+                    if let Some(items) = block.get_mut("content").and_then(|c| c.as_array_mut()) {
+                        for (k, item) in items.iter_mut().enumerate() {
+                            if item.get("type").and_then(|v| v.as_str()) == Some("image") {
+                                blocks.push((format!("{}.content.{}", path, k), item));
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    blocks
+}
+
+/// 校验图片块：仅接受上游支持的 base64 图片，错误信息以字段路径开头
+pub(super) fn validate_images(messages: &mut [Message]) -> Result<(), String> {
+    for (path, block) in image_blocks(messages) {
+        let source = &block["source"];
+        match source["type"].as_str() {
+            Some("base64") => {}
+            Some("url") => {
+                return Err(format!(
+                    "{}.source: URL image sources are not enabled on this server; send the image as base64",
+                    path
+                ));
+            }
+            other => {
+                return Err(format!(
+                    "{}.source.type: unsupported image source type '{}'; send the image as base64",
+                    path,
+                    other.unwrap_or("")
+                ));
+            }
+        }
+
+        let media_type = source["media_type"].as_str().unwrap_or("");
+        if !SUPPORTED_MEDIA_TYPES.contains(&media_type) {
+            return Err(format!(
+                "{}.source.media_type: unsupported image type '{}' (supported: {})",
+                path,
+                media_type,
+                SUPPORTED_MEDIA_TYPES.join(", ")
+            ));
+        }
+        let Some(bytes) = source["data"]
+            .as_str()
+            .and_then(|data| base64::engine::general_purpose::STANDARD.decode(data).ok())
+        else {
+            return Err(format!("{}.source.data: invalid base64 image data", path));
+        };
+        if bytes.len() > MAX_IMAGE_BYTES {
+            return Err(format!(
+                "{}.source.data: image exceeds {} MB maximum ({} bytes)",
+                path,
+                MAX_IMAGE_BYTES / 1024 / 1024,
+                bytes.len()
+            ));
+        }
+        if let Some(actual) = sniff_media_type(&bytes)
+            && actual != media_type
+        {
+            return Err(format!(
+                "{}.source.media_type: image data is '{}' but media_type is '{}'",
+                path, actual, media_type
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 图片下载器（配置 `imageUrlFetch` 时启用）
+pub struct ImageFetcher {
+    client: reqwest::Client,
+}
+
+impl ImageFetcher {
+    /// 根据配置创建；未开启 `imageUrlFetch` 时返回 None
+    pub fn from_config(
+        config: &Config,
+        proxy: Option<&ProxyConfig>,
+    ) -> anyhow::Result<Option<Self>> {
+        if !config.image_url_fetch {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            client: build_client(proxy, FETCH_TIMEOUT_SECS)?,
+        }))
+    }
+
+    /// 下载图片，返回 (media_type, base64 数据)
+    async fn fetch(&self, url: &str) -> Result<(String, String), String> {
+        if !(url.starts_with("https://") || url.starts_with("http://")) {
+            return Err("only http(s) image URLs are supported".to_string());
+        }
+        let mut response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| format!("failed to download image: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "failed to download image: HTTP {}",
+                response.status().as_u16()
+            ));
+        }
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| format!("failed to download image: {}", e))?
+        {
+            bytes.extend_from_slice(&chunk);
+            if bytes.len() > MAX_IMAGE_BYTES {
+                return Err(format!(
+                    "image exceeds {} MB maximum",
+                    MAX_IMAGE_BYTES / 1024 / 1024
+                ));
+            }
+        }
+
+        let media_type = sniff_media_type(&bytes).ok_or_else(|| {
+            format!(
+                "unsupported image type (supported: {})",
+                SUPPORTED_MEDIA_TYPES.join(", ")
+            )
+        })?;
+        Ok((
+            media_type.to_string(),
+            base64::engine::general_purpose::STANDARD.encode(&bytes),
+        ))
+    }
+
+    /// 下载消息中 `url` 来源的图片并替换为 base64 来源，返回下载的图片数
+    pub(super) async fn resolve(&self, messages: &mut [Message]) -> Result<usize, String> {
+        let mut resolved = 0;
+        for (path, block) in image_blocks(messages) {
+            if block["source"]["type"] != "url" {
+                continue;
+            }
+            let url = block["source"]["url"].as_str().unwrap_or("").to_string();
+            let (media_type, data) = self.fetch(&url).await.map_err(|e| {
+                tracing::warn!("下载图片失败: {}: {}", url, e);
+                format!("{}.source.url: {}", path, e)
+            })?;
+            block["source"] = json!({"type": "base64", "media_type": media_type, "data": data});
+            resolved += 1;
+        }
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    fn message(content: Value) -> Message {
+        Message {
+            role: "user".to_string(),
+            content,
+        }
+    }
+
+    fn base64_image(media_type: &str, bytes: &[u8]) -> Value {
+        json!({"type": "image", "source": {
+            "type": "base64",
+            "media_type": media_type,
+            "data": base64::engine::general_purpose::STANDARD.encode(bytes)
+        }})
+    }
+
+    #[test]
+    fn test_validate_images() {
+        let mut ok = vec![message(json!([
+            {"type": "text", "text": "what is this?"},
+            base64_image("image/png", PNG)
+        ]))];
+        assert!(validate_images(&mut ok).is_ok());
+
+        let cases = [
+            (
+                base64_image("image/bmp", b"BM"),
+                "messages.0.content.0.source.media_type: unsupported image type 'image/bmp'",
+            ),
+            (
+                base64_image("image/jpeg", PNG),
+                "messages.0.content.0.source.media_type: image data is 'image/png'",
+            ),
+            (
+                json!({"type": "image", "source": {"type": "url", "url": "https://example.com/a.png"}}),
+                "messages.0.content.0.source: URL image sources are not enabled",
+            ),
+        ];
+        for (block, expected) in cases {
+            let err = validate_images(&mut [message(json!([block]))]).unwrap_err();
+            assert!(err.starts_with(expected), "{}", err);
+        }
+
+        // tool_result 中的图片同样校验
+        let err = validate_images(&mut [message(json!([{
+            "type": "tool_result",
+            "tool_use_id": "t1",
+            "content": [{"type": "image", "source": {"type": "file", "file_id": "f"}}]
+        }]))])
+        .unwrap_err();
+        assert!(err.starts_with("messages.0.content.0.content.0.source.type"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_image_urls() {
+        let app = axum::Router::new()
+            .route("/a.png", axum::routing::get(|| async { PNG }))
+            .route("/a.txt", axum::routing::get(|| async { "not an image" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config = Config {
+            image_url_fetch: true,
+            ..Config::default()
+        };
+        let fetcher = ImageFetcher::from_config(&config, None).unwrap().unwrap();
+        let url_image = |path: &str| json!({"type": "image", "source": {"type": "url", "url": format!("{}{}", base, path)}});
+
+        let mut messages = vec![message(json!([url_image("/a.png")]))];
+        assert_eq!(fetcher.resolve(&mut messages).await.unwrap(), 1);
+        assert_eq!(
+            messages[0].content[0]["source"],
+            base64_image("image/png", PNG)["source"]
+        );
+        assert!(validate_images(&mut messages).is_ok());
+
+        let err = fetcher
+            .resolve(&mut [message(json!([url_image("/a.txt")]))])
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("messages.0.content.0.source.url: unsupported image type"));
+    }
+}
@@ -0,0 +1,91 @@
+//! 客户端并发限制
+//!
+//! 按客户端 API Key 限制同时进行中的请求数量（包括流式响应的整个生命周期），
+//! 避免单个客户端占满整个凭据池的并发能力
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::common::auth::key_fingerprint;
+
+/// 按 API Key 的并发限制器
+pub struct KeyConcurrencyLimiter {
+    /// 每个 Key 允许的最大并发数
+    max_per_key: usize,
+    /// Key 指纹 -> 信号量
+    semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl KeyConcurrencyLimiter {
+    /// 创建新的并发限制器
+    pub fn new(max_per_key: usize) -> Self {
+        Self {
+            max_per_key,
+            semaphores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 每个 Key 允许的最大并发数
+    pub fn max_per_key(&self) -> usize {
+        self.max_per_key
+    }
+
+    /// 尝试为指定 Key 获取一个并发名额
+    ///
+    /// 返回的 permit 被 drop 时自动释放名额；已达上限时返回 None
+    pub fn try_acquire(&self, api_key: &str) -> Option<OwnedSemaphorePermit> {
+        let semaphore = {
+            let mut semaphores = self.semaphores.lock();
+            semaphores
+                .entry(key_fingerprint(api_key))
+                .or_insert_with(|| Arc::new(Semaphore::new(self.max_per_key)))
+                .clone()
+        };
+        semaphore.try_acquire_owned().ok()
+    }
+
+    /// 指定 Key 当前剩余的并发名额
+    pub fn available(&self, api_key: &str) -> usize {
+        self.semaphores
+            .lock()
+            .get(&key_fingerprint(api_key))
+            .map(|s| s.available_permits())
+            .unwrap_or(self.max_per_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_acquire_respects_limit() {
+        let limiter = KeyConcurrencyLimiter::new(2);
+
+        let p1 = limiter.try_acquire("key-a");
+        let p2 = limiter.try_acquire("key-a");
+        assert!(p1.is_some());
+        assert!(p2.is_some());
+        assert!(limiter.try_acquire("key-a").is_none());
+
+        assert_eq!(limiter.available("key-a"), 0);
+        assert_eq!(limiter.available("key-b"), 2);
+
+        // 释放后可以再次获取
+        drop(p1);
+        assert_eq!(limiter.available("key-a"), 1);
+        assert!(limiter.try_acquire("key-a").is_some());
+    }
+
+    #[test]
+    fn test_keys_are_isolated() {
+        let limiter = KeyConcurrencyLimiter::new(1);
+
+        let _a = limiter.try_acquire("key-a").unwrap();
+        assert!(limiter.try_acquire("key-a").is_none());
+        assert!(limiter.try_acquire("key-b").is_some());
+    }
+}
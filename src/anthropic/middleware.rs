@@ -1,18 +1,35 @@
 //! Anthropic API 中间件
 
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use axum::{
     body::Body,
     extract::State,
-    http::{Request, StatusCode},
+    http::{Request, StatusCode, header},
     middleware::Next,
     response::{IntoResponse, Json, Response},
 };
 
-use crate::common::auth;
+use futures::{FutureExt, StreamExt};
+
+use crate::common::panic::{PanicReport, take_last_panic};
+use crate::common::{auth, request_id};
+use crate::kiro::model::api_key::ApiKey;
+use crate::kiro::model::request_log::RequestLog;
 use crate::kiro::provider::KiroProvider;
+use crate::kiro::transcript::TranscriptStore;
 
+use super::annotation::Annotation;
+use super::api_keys::KeyCache;
+use super::embeddings::EmbeddingsBackend;
+use super::handlers::extract_request_tag;
+use super::images::ImageFetcher;
+use super::limiter::KeyConcurrencyLimiter;
+use super::ratelimit::{QuotaCache, RateLimitStatus};
+use super::request_log::RequestLogWriter;
+use super::token_bucket::{KeyTokenBuckets, LimitKind};
 use super::types::ErrorResponse;
 
 /// 应用共享状态
@@ -25,6 +42,24 @@ pub struct AppState {
     pub kiro_provider: Option<Arc<KiroProvider>>,
     /// Profile ARN（可选，用于请求）
     pub profile_arn: Option<String>,
+    /// 按 API Key 的并发限制器（可选，未配置时不限制）
+    pub concurrency_limiter: Option<Arc<KeyConcurrencyLimiter>>,
+    /// 凭据池额度缓存（用于限流响应头）
+    pub quota_cache: Arc<QuotaCache>,
+    /// 客户端 API Key 查询缓存
+    pub key_cache: Arc<KeyCache>,
+    /// 按 API Key 的令牌桶限流（随 KiroProvider 启用，同时负责客户端 Key 的 `rateLimitPerMinute`）
+    pub token_buckets: Option<Arc<KeyTokenBuckets>>,
+    /// 请求日志批量写入（随 KiroProvider 启用）
+    pub request_logs: Option<RequestLogWriter>,
+    /// 对话记录存储（配置 `transcriptStore` 时启用）
+    pub transcripts: Option<Arc<TranscriptStore>>,
+    /// embeddings 上游（配置 `embeddingsApiUrl` 时启用）
+    pub embeddings: Option<Arc<EmbeddingsBackend>>,
+    /// 图片下载器（配置 `imageUrlFetch` 时启用）
+    pub image_fetcher: Option<Arc<ImageFetcher>>,
+    /// 响应标注（配置 `responseAnnotation` 时启用）
+    pub annotation: Option<Arc<Annotation>>,
 }
 
 impl AppState {
@@ -34,11 +69,53 @@ impl AppState {
             api_key: api_key.into(),
             kiro_provider: None,
             profile_arn: None,
+            concurrency_limiter: None,
+            quota_cache: Arc::new(QuotaCache::default()),
+            key_cache: Arc::new(KeyCache::default()),
+            token_buckets: None,
+            request_logs: None,
+            transcripts: None,
+            embeddings: None,
+            image_fetcher: None,
+            annotation: None,
         }
     }
 
-    /// 设置 KiroProvider
+    /// 设置 KiroProvider，并启动请求日志写入任务（需在 tokio 运行时中调用）
     pub fn with_kiro_provider(mut self, provider: KiroProvider) -> Self {
+        let token_manager = provider.token_manager();
+        let config = token_manager.config();
+        self.request_logs = Some(RequestLogWriter::spawn(
+            token_manager.database().clone(),
+            config.request_log_batch_size,
+            Duration::from_millis(config.request_log_flush_interval_ms),
+        ));
+        self.token_buckets = Some(Arc::new(KeyTokenBuckets::from_config(config)));
+        self.transcripts =
+            match TranscriptStore::from_config(config, token_manager.database().clone()) {
+                Ok(store) => store.map(Arc::new),
+                Err(e) => {
+                    tracing::error!("对话记录配置无效，未启用: {}", e);
+                    None
+                }
+            };
+        self.embeddings =
+            match EmbeddingsBackend::from_config(config, token_manager.proxy().as_ref()) {
+                Ok(backend) => backend.map(Arc::new),
+                Err(e) => {
+                    tracing::error!("embeddings 上游配置无效，未启用: {}", e);
+                    None
+                }
+            };
+        self.image_fetcher = match ImageFetcher::from_config(config, token_manager.proxy().as_ref())
+        {
+            Ok(fetcher) => fetcher.map(Arc::new),
+            Err(e) => {
+                tracing::error!("图片下载器创建失败，未启用: {}", e);
+                None
+            }
+        };
+        self.annotation = Annotation::from_config(config).map(Arc::new);
         self.kiro_provider = Some(Arc::new(provider));
         self
     }
@@ -48,21 +125,249 @@ impl AppState {
         self.profile_arn = Some(arn.into());
         self
     }
+
+    /// 设置每个 API Key 的最大并发请求数（0 表示不限制）
+    pub fn with_max_concurrent_per_key(mut self, max: usize) -> Self {
+        self.concurrency_limiter = (max > 0).then(|| Arc::new(KeyConcurrencyLimiter::new(max)));
+        self
+    }
 }
 
 /// API Key 认证中间件
+///
+/// 接受配置文件中的 `apiKey` 或通过 Admin API 签发的客户端 Key；
+/// 客户端 Key 需未吊销且未过期，校验通过后写入请求扩展（每分钟请求数限制见 [`token_bucket_middleware`]）
 pub async fn auth_middleware(
+    State(state): State<AppState>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(key) = auth::extract_api_key(&request) else {
+        return unauthorized();
+    };
+    if auth::constant_time_eq(&key, &state.api_key) {
+        return next.run(request).await;
+    }
+
+    let now = chrono::Utc::now();
+    let client_key = match &state.kiro_provider {
+        Some(provider) => {
+            let database = provider.token_manager().database();
+            state.key_cache.lookup(database, &key).await
+        }
+        None => None,
+    };
+    let Some(client_key) = client_key.filter(|k| k.is_active(now)) else {
+        return unauthorized();
+    };
+
+    request.extensions_mut().insert(client_key);
+    next.run(request).await
+}
+
+/// 认证失败响应
+fn unauthorized() -> Response {
+    let error = ErrorResponse::authentication_error();
+    (StatusCode::UNAUTHORIZED, Json(error)).into_response()
+}
+
+/// 按 API Key 的并发限制中间件
+///
+/// 名额在整个响应体（包括流式响应）发送完毕或客户端断开后才释放；
+/// 超出限制时返回 429
+pub async fn concurrency_middleware(
     State(state): State<AppState>,
     request: Request<Body>,
     next: Next,
 ) -> Response {
-    match auth::extract_api_key(&request) {
-        Some(key) if auth::constant_time_eq(&key, &state.api_key) => next.run(request).await,
-        _ => {
-            let error = ErrorResponse::authentication_error();
-            (StatusCode::UNAUTHORIZED, Json(error)).into_response()
+    let Some(limiter) = state.concurrency_limiter.as_ref() else {
+        return next.run(request).await;
+    };
+
+    // 认证中间件已校验过 Key，这里只用于区分客户端
+    let api_key = auth::extract_api_key(&request).unwrap_or_default();
+    let Some(permit) = limiter.try_acquire(&api_key) else {
+        tracing::warn!(
+            "客户端并发请求数已达上限 ({})，拒绝请求",
+            limiter.max_per_key()
+        );
+        let error = ErrorResponse::new(
+            "rate_limit_error",
+            format!(
+                "Too many concurrent requests for this API key (limit: {}). Retry after an in-flight request completes.",
+                limiter.max_per_key()
+            ),
+        );
+        let mut response = (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, "1")],
+            Json(error),
+        )
+            .into_response();
+        RateLimitStatus {
+            requests: Some((limiter.max_per_key(), 0)),
+            ..Default::default()
         }
+        .apply(response.headers_mut(), chrono::Utc::now());
+        return response;
+    };
+
+    let response = next.run(request).await;
+
+    // 将 permit 绑定到响应体上，确保流式响应结束后才释放
+    let (parts, body) = response.into_parts();
+    let body_stream = body.into_data_stream().map(move |chunk| {
+        let _ = &permit;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(body_stream))
+}
+
+/// 按 API Key 的令牌桶限流中间件（每分钟请求数与每分钟 tokens）
+///
+/// 需位于并发限制中间件外侧，被拒绝的请求不占用并发名额；
+/// 客户端 Key 自身的 `rateLimitPerMinute` 与配置的每分钟请求数取较小值；
+/// tokens 在请求结束后按实际用量扣除（见 `UsageTracker`）
+pub async fn token_bucket_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(buckets) = state.token_buckets.as_ref() else {
+        return next.run(request).await;
+    };
+
+    // 认证中间件已校验过 Key，这里只用于区分客户端
+    let api_key = auth::extract_api_key(&request).unwrap_or_default();
+    let key_limit = request
+        .extensions()
+        .get::<ApiKey>()
+        .and_then(|key| key.rate_limit_per_minute);
+    if let Err(limited) = buckets.check(&api_key, key_limit, Instant::now()) {
+        let (what, header_prefix) = match limited.kind {
+            LimitKind::Requests => ("requests", "anthropic-ratelimit-requests"),
+            LimitKind::Tokens => ("tokens", "anthropic-ratelimit-tokens"),
+        };
+        tracing::warn!(
+            "客户端 API Key 超出每分钟 {} 限制 ({})，拒绝请求",
+            what,
+            limited.limit
+        );
+        let error = ErrorResponse::new(
+            "rate_limit_error",
+            format!(
+                "Rate limit exceeded for this API key ({} {} per minute). Retry after {} seconds.",
+                limited.limit, what, limited.retry_after
+            ),
+        );
+        let reset = (chrono::Utc::now() + chrono::Duration::seconds(limited.retry_after as i64))
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [
+                (
+                    header::RETRY_AFTER.to_string(),
+                    limited.retry_after.to_string(),
+                ),
+                (
+                    format!("{}-limit", header_prefix),
+                    limited.limit.to_string(),
+                ),
+                (format!("{}-remaining", header_prefix), "0".to_string()),
+                (format!("{}-reset", header_prefix), reset),
+            ],
+            Json(error),
+        )
+            .into_response();
     }
+
+    next.run(request).await
+}
+
+/// 限流响应头中间件
+///
+/// 在响应中附加 `anthropic-ratelimit-*` 等限流状态头，
+/// 需位于并发限制中间件内侧，使剩余名额包含本次请求占用的名额
+pub async fn rate_limit_headers_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let api_key = auth::extract_api_key(&request).unwrap_or_default();
+    let mut response = next.run(request).await;
+
+    let mut status = RateLimitStatus {
+        requests: state
+            .concurrency_limiter
+            .as_ref()
+            .map(|limiter| (limiter.max_per_key(), limiter.available(&api_key))),
+        ..Default::default()
+    };
+    if let Some(provider) = &state.kiro_provider {
+        let token_manager = provider.token_manager();
+        status.output_tokens_per_second = token_manager
+            .config()
+            .output_tokens_per_second_for(Some(&api_key));
+        status.quota = state.quota_cache.get(token_manager).await;
+    }
+    status.apply(response.headers_mut(), chrono::Utc::now());
+    response
+}
+
+/// Panic 捕获中间件
+///
+/// 将请求处理中的 panic 转换为 Anthropic 格式的 500 错误（附带 panic ID），
+/// 并写入请求日志，避免连接被直接断开。
+/// 注意：响应体（如 SSE 流）已开始发送后的 panic 无法在此捕获
+pub async fn catch_panic_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let created_at = chrono::Utc::now();
+    let started = Instant::now();
+    let path = request.uri().path().to_string();
+    let client_key = auth::extract_api_key(&request);
+    let tag = extract_request_tag(request.headers());
+
+    let response = AssertUnwindSafe(next.run(request)).catch_unwind().await;
+    if let Ok(response) = response {
+        return response;
+    }
+
+    // hook 未安装时（如测试环境）没有报告，补充生成 ID
+    let report = take_last_panic().unwrap_or_else(|| PanicReport {
+        id: uuid::Uuid::new_v4().simple().to_string(),
+        message: "unknown panic".to_string(),
+    });
+    tracing::error!(panic_id = %report.id, "请求处理发生 panic: {}", path);
+
+    if let Some(request_logs) = &state.request_logs {
+        request_logs.record(RequestLog {
+            id: None,
+            created_at,
+            model: "unknown".to_string(),
+            credential_id: None,
+            status: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            client_key: client_key.map(|key| auth::key_fingerprint(&key)),
+            latency_ms: started.elapsed().as_millis() as u64,
+            stream: false,
+            error: Some(format!(
+                "panic {} at {}: {}",
+                report.id, path, report.message
+            )),
+            tag,
+            seed: None,
+            request_id: request_id::current(),
+            sse_events: None,
+        });
+    }
+
+    let error = ErrorResponse::new(
+        "api_error",
+        format!("Internal server error (panic id: {})", report.id),
+    );
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
 }
 
 /// CORS 中间件层
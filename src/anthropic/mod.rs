@@ -6,6 +6,9 @@
 //! - `GET /v1/models` - 获取可用模型列表
 //! - `POST /v1/messages` - 创建消息（对话）
 //! - `POST /v1/messages/count_tokens` - 计算 token 数量
+//! - `POST /v1/chat/completions` - OpenAI 兼容的对话接口
+//! - `POST /v1/embeddings` - 透传到配置的 embeddings 上游
+//! - `GET /ready` - 就绪检查
 //!
 //! # 使用示例
 //! ```rust,ignore
@@ -16,11 +19,33 @@
 //! axum::serve(listener, app).await?;
 //! ```
 
+mod annotation;
+mod api_keys;
+mod beta;
 mod converter;
+pub mod deprecation;
+mod embeddings;
+#[cfg(test)]
+mod golden;
 mod handlers;
+mod images;
+mod limiter;
 mod middleware;
+mod openai;
+mod pacing;
+mod partial_json;
+mod ratelimit;
+mod request_log;
 mod router;
+mod service_tier;
+pub mod sse;
 mod stream;
+mod templates;
+mod token_bucket;
+mod tool_schema;
+mod transcript;
+mod transforms;
 pub mod types;
+mod usage;
 
 pub use router::create_router_with_provider;
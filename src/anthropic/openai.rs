@@ -0,0 +1,909 @@
+//! OpenAI Chat Completions 兼容端点
+//!
+//! 将 `POST /v1/chat/completions` 请求转换为 Anthropic Messages 请求，复用 /v1/messages 的完整处理流程
+//! （凭据选择、节流、输出上限、请求日志等），再把响应（含流式增量与 tool_calls）转换回 OpenAI 格式，
+//! 便于 LobeChat、continue.dev 等只支持 OpenAI 协议的客户端直接接入
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::{
+    Extension, Json as JsonExtractor,
+    body::Body,
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use bytes::Bytes;
+use futures::{StreamExt, stream};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::kiro::model::api_key::ApiKey;
+
+use super::handlers::process_messages;
+use super::middleware::AppState;
+use super::stream::OUTPUT_LIMIT_STOP_REASON;
+use super::types::{Message, MessagesRequest, Metadata, ResponseFormat, SystemMessage, Tool};
+
+/// 未指定 max_tokens 时使用的默认值
+const DEFAULT_MAX_TOKENS: i32 = 8192;
+
+/// 非流式响应体读取上限
+const RESPONSE_BODY_LIMIT: usize = 64 * 1024 * 1024;
+
+// === 请求类型 ===
+
+/// Chat Completions 请求体
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(default)]
+    pub max_tokens: Option<i32>,
+    #[serde(default)]
+    pub max_completion_tokens: Option<i32>,
+    #[serde(default)]
+    pub tools: Option<Vec<ChatTool>>,
+    #[serde(default)]
+    pub tool_choice: Option<Value>,
+    /// 为 false 时映射为 `tool_choice.disable_parallel_tool_use`
+    #[serde(default)]
+    pub parallel_tool_calls: Option<bool>,
+    #[serde(default)]
+    pub response_format: Option<ResponseFormat>,
+    #[serde(default)]
+    pub seed: Option<i64>,
+    /// 终端用户标识（映射为 `metadata.user_id`）
+    #[serde(default)]
+    pub user: Option<String>,
+}
+
+/// 流式选项
+#[derive(Debug, Deserialize)]
+pub struct StreamOptions {
+    /// 是否在流结束前发送一个包含 usage 的 chunk
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+/// Chat 消息
+#[derive(Debug, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    /// 字符串或内容片段数组（assistant 仅包含 tool_calls 时为 null）
+    #[serde(default)]
+    pub content: Option<Value>,
+    #[serde(default)]
+    pub tool_calls: Option<Vec<ChatToolCall>>,
+    #[serde(default)]
+    pub tool_call_id: Option<String>,
+}
+
+/// assistant 消息中的工具调用
+#[derive(Debug, Deserialize)]
+pub struct ChatToolCall {
+    pub id: String,
+    pub function: ChatFunctionCall,
+}
+
+/// 工具调用的函数名与参数（JSON 字符串）
+#[derive(Debug, Deserialize)]
+pub struct ChatFunctionCall {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: String,
+}
+
+/// 工具定义
+#[derive(Debug, Deserialize)]
+pub struct ChatTool {
+    pub function: ChatFunction,
+}
+
+/// 函数定义
+#[derive(Debug, Deserialize)]
+pub struct ChatFunction {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub parameters: Option<HashMap<String, Value>>,
+}
+
+// === 请求转换 ===
+
+/// 将 Chat Completions 请求转换为 Anthropic Messages 请求
+pub fn to_messages_request(req: ChatCompletionRequest) -> Result<MessagesRequest, String> {
+    let mut system = Vec::new();
+    let mut messages: Vec<Message> = Vec::new();
+
+    for msg in req.messages {
+        match msg.role.as_str() {
+            "system" | "developer" => {
+                let text = content_text(msg.content.as_ref());
+                if !text.is_empty() {
+                    system.push(SystemMessage { text });
+                }
+            }
+            "user" => {
+                let content = user_content(msg.content)?;
+                messages.push(Message {
+                    role: "user".to_string(),
+                    content,
+                });
+            }
+            "assistant" => {
+                let mut blocks = Vec::new();
+                let text = content_text(msg.content.as_ref());
+                if !text.is_empty() {
+                    blocks.push(json!({"type": "text", "text": text}));
+                }
+                for call in msg.tool_calls.unwrap_or_default() {
+                    let input = match call.function.arguments.trim() {
+                        "" => json!({}),
+                        arguments => serde_json::from_str(arguments).map_err(|e| {
+                            format!("工具调用 {} 的 arguments 不是合法 JSON: {}", call.id, e)
+                        })?,
+                    };
+                    blocks.push(json!({
+                        "type": "tool_use",
+                        "id": call.id,
+                        "name": call.function.name,
+                        "input": input
+                    }));
+                }
+                messages.push(Message {
+                    role: "assistant".to_string(),
+                    content: Value::Array(blocks),
+                });
+            }
+            "tool" => {
+                let tool_call_id = msg
+                    .tool_call_id
+                    .ok_or_else(|| "tool 消息缺少 tool_call_id".to_string())?;
+                let block = json!({
+                    "type": "tool_result",
+                    "tool_use_id": tool_call_id,
+                    "content": tool_result_content(msg.content)?
+                });
+                // 连续的工具结果合并到同一条 user 消息中
+                match messages.last_mut() {
+                    Some(last) if last.role == "user" && is_tool_results(&last.content) => {
+                        if let Value::Array(blocks) = &mut last.content {
+                            blocks.push(block);
+                        }
+                    }
+                    _ => messages.push(Message {
+                        role: "user".to_string(),
+                        content: Value::Array(vec![block]),
+                    }),
+                }
+            }
+            role => return Err(format!("不支持的消息角色: {}", role)),
+        }
+    }
+
+    let tools = req.tools.map(|tools| {
+        tools
+            .into_iter()
+            .map(|tool| Tool {
+                name: tool.function.name,
+                description: tool.function.description.unwrap_or_default(),
+                input_schema: tool.function.parameters.unwrap_or_else(|| {
+                    HashMap::from([
+                        ("type".to_string(), json!("object")),
+                        ("properties".to_string(), json!({})),
+                    ])
+                }),
+            })
+            .collect()
+    });
+
+    let response_format = req.response_format.map(|format| ResponseFormat {
+        format_type: match format.format_type.as_str() {
+            "json_schema" => "json_object".to_string(),
+            _ => format.format_type,
+        },
+    });
+
+    Ok(MessagesRequest {
+        model: req.model,
+        max_tokens: req
+            .max_completion_tokens
+            .or(req.max_tokens)
+            .unwrap_or(DEFAULT_MAX_TOKENS),
+        messages,
+        stream: req.stream,
+        system: (!system.is_empty()).then_some(system),
+        tools,
+        tool_choice: disable_parallel_tool_use(
+            req.tool_choice.and_then(convert_tool_choice),
+            req.parallel_tool_calls == Some(false),
+        ),
+        thinking: None,
+        prompt_template: None,
+        response_format,
+        seed: req.seed,
+        service_tier: None,
+        metadata: req.user.map(|user_id| Metadata {
+            user_id: Some(user_id),
+        }),
+    })
+}
+
+/// 提取消息中的纯文本（字符串或 text 片段拼接）
+fn content_text(content: Option<&Value>) -> String {
+    match content {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| part.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// 转换 user 消息内容（文本片段、base64 data URL 图片与远程图片地址）
+fn user_content(content: Option<Value>) -> Result<Value, String> {
+    let parts = match content {
+        Some(Value::Array(parts)) => parts,
+        Some(Value::String(text)) => return Ok(Value::String(text)),
+        _ => return Ok(Value::String(String::new())),
+    };
+
+    let mut blocks = Vec::new();
+    for part in &parts {
+        match content_part(part)? {
+            Some(block) => blocks.push(block),
+            None => tracing::debug!("忽略不支持的内容片段类型: {:?}", part.get("type")),
+        }
+    }
+    Ok(Value::Array(blocks))
+}
+
+/// 转换 tool 消息内容：文本与图片片段转为对应的内容块，
+/// 其他片段（如音频、文件）无法作为工具结果转发，以 JSON 文本保留而不丢弃
+fn tool_result_content(content: Option<Value>) -> Result<Value, String> {
+    let Some(Value::Array(parts)) = content else {
+        return Ok(Value::String(content_text(content.as_ref())));
+    };
+
+    let mut blocks = Vec::new();
+    for part in &parts {
+        match content_part(part)? {
+            Some(block) => blocks.push(block),
+            None => blocks.push(json!({"type": "text", "text": part.to_string()})),
+        }
+    }
+    Ok(Value::Array(blocks))
+}
+
+/// 转换单个内容片段（文本、base64 data URL 图片与远程图片地址），不支持的类型返回 None
+fn content_part(part: &Value) -> Result<Option<Value>, String> {
+    match part.get("type").and_then(|t| t.as_str()) {
+        Some("text") => {
+            let text = part.get("text").and_then(|t| t.as_str()).unwrap_or("");
+            Ok(Some(json!({"type": "text", "text": text})))
+        }
+        Some("image_url") => {
+            let url = part
+                .pointer("/image_url/url")
+                .and_then(|u| u.as_str())
+                .unwrap_or("");
+            let source = if let Some((media_type, data)) = parse_data_url(url) {
+                json!({"type": "base64", "media_type": media_type, "data": data})
+            } else if url.starts_with("https://") || url.starts_with("http://") {
+                // 远程图片由消息处理流程按 `imageUrlFetch` 配置下载或拒绝
+                json!({"type": "url", "url": url})
+            } else {
+                return Err("仅支持 base64 data URL 或 http(s) 地址形式的图片".to_string());
+            };
+            Ok(Some(json!({"type": "image", "source": source})))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// 解析 `data:image/png;base64,...` 形式的图片地址
+fn parse_data_url(url: &str) -> Option<(&str, &str)> {
+    let rest = url.strip_prefix("data:")?;
+    let (meta, data) = rest.split_once(',')?;
+    let media_type = meta.strip_suffix(";base64")?;
+    Some((media_type, data))
+}
+
+/// 内容是否全部为工具结果块
+fn is_tool_results(content: &Value) -> bool {
+    content.as_array().is_some_and(|blocks| {
+        !blocks.is_empty()
+            && blocks
+                .iter()
+                .all(|b| b.get("type").and_then(|t| t.as_str()) == Some("tool_result"))
+    })
+}
+
+/// 转换 tool_choice：auto / none / required / 指定函数
+fn convert_tool_choice(choice: Value) -> Option<Value> {
+    match &choice {
+        Value::String(mode) => match mode.as_str() {
+            "auto" => Some(json!({"type": "auto"})),
+            "none" => Some(json!({"type": "none"})),
+            "required" => Some(json!({"type": "any"})),
+            _ => None,
+        },
+        Value::Object(_) => choice
+            .pointer("/function/name")
+            .and_then(|n| n.as_str())
+            .map(|name| json!({"type": "tool", "name": name})),
+        _ => None,
+    }
+}
+
+/// `parallel_tool_calls: false` 时在 tool_choice 上设置 `disable_parallel_tool_use`（未指定时按 auto）
+fn disable_parallel_tool_use(choice: Option<Value>, disable: bool) -> Option<Value> {
+    if !disable {
+        return choice;
+    }
+    let mut choice = choice.unwrap_or_else(|| json!({"type": "auto"}));
+    if choice["type"] != "none" {
+        choice["disable_parallel_tool_use"] = json!(true);
+    }
+    Some(choice)
+}
+
+// === 响应转换 ===
+
+/// 将 Anthropic stop_reason 转换为 OpenAI finish_reason
+fn finish_reason(stop_reason: Option<&str>) -> Option<&'static str> {
+    Some(match stop_reason? {
+        "tool_use" => "tool_calls",
+        "max_tokens" | OUTPUT_LIMIT_STOP_REASON => "length",
+        _ => "stop",
+    })
+}
+
+/// 将 Anthropic usage 转换为 OpenAI usage
+fn convert_usage(usage: &Value) -> Value {
+    let prompt = usage["input_tokens"].as_i64().unwrap_or(0);
+    let completion = usage["output_tokens"].as_i64().unwrap_or(0);
+    json!({
+        "prompt_tokens": prompt,
+        "completion_tokens": completion,
+        "total_tokens": prompt + completion
+    })
+}
+
+/// 生成 chat completion ID（沿用 Anthropic 消息 ID 的随机部分）
+fn completion_id(message_id: Option<&str>) -> String {
+    let suffix = message_id
+        .map(|id| id.trim_start_matches("msg_").to_string())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string());
+    format!("chatcmpl-{}", suffix)
+}
+
+/// 将 Anthropic 非流式响应转换为 chat.completion
+pub fn to_chat_completion(message: &Value) -> Value {
+    let mut text = String::new();
+    let mut tool_calls = Vec::new();
+    for block in message["content"].as_array().into_iter().flatten() {
+        match block["type"].as_str() {
+            Some("text") => text.push_str(block["text"].as_str().unwrap_or("")),
+            Some("tool_use") => tool_calls.push(json!({
+                "id": block["id"],
+                "type": "function",
+                "function": {
+                    "name": block["name"],
+                    "arguments": block["input"].to_string()
+                }
+            })),
+            _ => {}
+        }
+    }
+
+    let mut reply = json!({
+        "role": "assistant",
+        "content": if text.is_empty() && !tool_calls.is_empty() { Value::Null } else { json!(text) },
+    });
+    if !tool_calls.is_empty() {
+        reply["tool_calls"] = Value::Array(tool_calls);
+    }
+
+    json!({
+        "id": completion_id(message["id"].as_str()),
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": message["model"],
+        "choices": [{
+            "index": 0,
+            "message": reply,
+            "finish_reason": finish_reason(message["stop_reason"].as_str())
+        }],
+        "usage": convert_usage(&message["usage"])
+    })
+}
+
+/// 将 Anthropic 错误响应转换为 OpenAI 错误格式
+fn to_openai_error(body: &[u8]) -> Value {
+    openai_error(&serde_json::from_slice::<Value>(body).unwrap_or_default())
+}
+
+/// 将 Anthropic 错误对象（`{"error": {"type", "message"}}`）转换为 OpenAI 错误格式
+fn openai_error(error: &Value) -> Value {
+    let mut converted = json!({
+        "error": {
+            "message": error.pointer("/error/message").and_then(|m| m.as_str()).unwrap_or("Unknown error"),
+            "type": error.pointer("/error/type").and_then(|t| t.as_str()).unwrap_or("api_error"),
+            "code": Value::Null
+        }
+    });
+    if let Some(request_id) = error.get("request_id") {
+        converted["request_id"] = request_id.clone();
+    }
+    converted
+}
+
+/// 流式转换器：把 Anthropic SSE 事件转换为 chat.completion.chunk
+pub struct ChunkTranslator {
+    /// 未处理完的 SSE 字节
+    buffer: Vec<u8>,
+    id: String,
+    model: String,
+    created: i64,
+    include_usage: bool,
+    /// Anthropic 内容块索引 -> OpenAI tool_calls 索引
+    tool_indices: HashMap<i64, usize>,
+    /// message_start 中的 usage（input_tokens）
+    usage: Value,
+    /// 是否已发送 `[DONE]`
+    done: bool,
+}
+
+impl ChunkTranslator {
+    pub fn new(model: impl Into<String>, include_usage: bool) -> Self {
+        Self {
+            buffer: Vec::new(),
+            id: completion_id(None),
+            model: model.into(),
+            created: chrono::Utc::now().timestamp(),
+            include_usage,
+            tool_indices: HashMap::new(),
+            usage: json!({}),
+            done: false,
+        }
+    }
+
+    /// 上游流结束时调用：未收到 message_stop（或 error）时补发 `[DONE]`，
+    /// 避免客户端一直等待结束标记
+    pub fn finish(&mut self) -> Vec<Bytes> {
+        if self.done {
+            return Vec::new();
+        }
+        self.done = true;
+        vec![Bytes::from_static(b"data: [DONE]\n\n")]
+    }
+
+    /// 输入一段 SSE 字节，返回转换后的 SSE 字节（可能为空）
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<Bytes> {
+        if self.done {
+            return Vec::new();
+        }
+        self.buffer.extend_from_slice(chunk);
+        let mut output = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let raw: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let text = String::from_utf8_lossy(&raw);
+            let Some(data) = text.lines().find_map(|line| line.strip_prefix("data:")) else {
+                continue;
+            };
+            if let Ok(event) = serde_json::from_str::<Value>(data.trim()) {
+                output.extend(self.translate(&event));
+            }
+        }
+        output
+    }
+
+    fn chunk(&self, delta: Value, finish_reason: Option<&str>) -> Bytes {
+        sse_data(&json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}]
+        }))
+    }
+
+    fn translate(&mut self, event: &Value) -> Vec<Bytes> {
+        match event["type"].as_str().unwrap_or("") {
+            "message_start" => {
+                self.id = completion_id(event.pointer("/message/id").and_then(|v| v.as_str()));
+                self.usage = event["message"]["usage"].clone();
+                vec![self.chunk(json!({"role": "assistant", "content": ""}), None)]
+            }
+            "content_block_start" => {
+                let block = &event["content_block"];
+                if block["type"] != "tool_use" {
+                    return Vec::new();
+                }
+                let index = self.tool_indices.len();
+                self.tool_indices
+                    .insert(event["index"].as_i64().unwrap_or(-1), index);
+                vec![self.chunk(
+                    json!({"tool_calls": [{
+                        "index": index,
+                        "id": block["id"],
+                        "type": "function",
+                        "function": {"name": block["name"], "arguments": ""}
+                    }]}),
+                    None,
+                )]
+            }
+            "content_block_delta" => {
+                let delta = &event["delta"];
+                let converted = match delta["type"].as_str() {
+                    Some("text_delta") => json!({"content": delta["text"]}),
+                    Some("thinking_delta") => json!({"reasoning_content": delta["thinking"]}),
+                    Some("input_json_delta") => {
+                        let Some(&index) = event["index"]
+                            .as_i64()
+                            .and_then(|i| self.tool_indices.get(&i))
+                        else {
+                            return Vec::new();
+                        };
+                        json!({"tool_calls": [{
+                            "index": index,
+                            "function": {"arguments": delta["partial_json"]}
+                        }]})
+                    }
+                    _ => return Vec::new(),
+                };
+                vec![self.chunk(converted, None)]
+            }
+            "message_delta" => {
+                let usage = &event["usage"];
+                if usage.get("input_tokens").is_some() {
+                    self.usage["input_tokens"] = usage["input_tokens"].clone();
+                }
+                self.usage["output_tokens"] = usage["output_tokens"].clone();
+                let reason =
+                    finish_reason(event.pointer("/delta/stop_reason").and_then(|v| v.as_str()));
+                vec![self.chunk(json!({}), reason.or(Some("stop")))]
+            }
+            "message_stop" => {
+                let mut output = Vec::new();
+                if self.include_usage {
+                    output.push(sse_data(&json!({
+                        "id": self.id,
+                        "object": "chat.completion.chunk",
+                        "created": self.created,
+                        "model": self.model,
+                        "choices": [],
+                        "usage": convert_usage(&self.usage)
+                    })));
+                }
+                output.extend(self.finish());
+                output
+            }
+            // 上游响应被截断等流内错误：转发错误后结束
+            "error" => {
+                let mut output = vec![sse_data(&openai_error(event))];
+                output.extend(self.finish());
+                output
+            }
+            // 保持连接活跃（SSE 注释行，客户端会忽略）
+            "ping" => vec![Bytes::from_static(b": ping\n\n")],
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn sse_data(value: &Value) -> Bytes {
+    Bytes::from(format!("data: {}\n\n", value))
+}
+
+// === Handler ===
+
+/// POST /v1/chat/completions
+///
+/// OpenAI 兼容的对话接口
+pub async fn post_chat_completions(
+    State(state): State<AppState>,
+    client_key: Option<Extension<ApiKey>>,
+    headers: HeaderMap,
+    JsonExtractor(payload): JsonExtractor<ChatCompletionRequest>,
+) -> Response {
+    let stream = payload.stream;
+    let model = payload.model.clone();
+    let include_usage = payload
+        .stream_options
+        .as_ref()
+        .is_some_and(|options| options.include_usage);
+
+    let request = match to_messages_request(payload) {
+        Ok(request) => request,
+        Err(message) => {
+            tracing::warn!("OpenAI 请求转换失败: {}", message);
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": {"message": message, "type": "invalid_request_error", "code": Value::Null}
+                })),
+            )
+                .into_response();
+        }
+    };
+
+    let response = process_messages(state, client_key.as_deref(), &headers, request).await;
+    let (mut parts, body) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+
+    // 错误响应与非流式响应：读取完整响应体后转换
+    if !parts.status.is_success() || !stream {
+        let bytes = match axum::body::to_bytes(body, RESPONSE_BODY_LIMIT).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                tracing::error!("读取响应体失败: {}", e);
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    Json(json!({
+                        "error": {"message": format!("读取响应失败: {}", e), "type": "api_error", "code": Value::Null}
+                    })),
+                )
+                    .into_response();
+            }
+        };
+        let converted = if parts.status.is_success() {
+            let message = serde_json::from_slice::<Value>(&bytes).unwrap_or_default();
+            to_chat_completion(&message)
+        } else {
+            to_openai_error(&bytes)
+        };
+        return Response::from_parts(parts, Body::from(converted.to_string()));
+    }
+
+    // 流式响应：逐事件转换，流结束后补发缺失的 `[DONE]`
+    let translator = Arc::new(Mutex::new(ChunkTranslator::new(model, include_usage)));
+    let tail = translator.clone();
+    let body = body
+        .into_data_stream()
+        .flat_map(move |chunk| {
+            let output: Vec<Result<Bytes, Infallible>> = match chunk {
+                Ok(chunk) => translator.lock().feed(&chunk).into_iter().map(Ok).collect(),
+                Err(e) => {
+                    tracing::warn!("读取流式响应失败: {}", e);
+                    Vec::new()
+                }
+            };
+            stream::iter(output)
+        })
+        .chain(
+            stream::once(async move { tail.lock().finish() })
+                .flat_map(|output| stream::iter(output.into_iter().map(Ok::<_, Infallible>))),
+        );
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(value: Value) -> ChatCompletionRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_to_messages_request() {
+        let req = to_messages_request(request(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [
+                {"role": "system", "content": "be brief"},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "what is this?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+                ]},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function", "function": {"name": "lookup", "arguments": "{\"q\":\"x\"}"}},
+                    {"id": "call_2", "type": "function", "function": {"name": "lookup", "arguments": "{}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "one"},
+                {"role": "tool", "tool_call_id": "call_2", "content": "two"}
+            ],
+            "tools": [{"type": "function", "function": {"name": "lookup", "parameters": {"type": "object"}}}],
+            "tool_choice": "required",
+            "parallel_tool_calls": false,
+            "max_completion_tokens": 100
+        })))
+        .unwrap();
+
+        assert_eq!(req.max_tokens, 100);
+        assert_eq!(req.system.unwrap()[0].text, "be brief");
+        assert_eq!(req.messages.len(), 3);
+        assert_eq!(
+            req.messages[0].content[1]["source"]["media_type"],
+            "image/png"
+        );
+        assert_eq!(req.messages[1].content[0]["input"], json!({"q": "x"}));
+        // 连续的工具结果合并为一条 user 消息
+        let results = req.messages[2].content.as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1]["tool_use_id"], "call_2");
+        assert_eq!(req.tools.unwrap()[0].name, "lookup");
+        assert_eq!(
+            req.tool_choice,
+            Some(json!({"type": "any", "disable_parallel_tool_use": true}))
+        );
+
+        // 远程图片地址转为 url 来源，其他形式不受支持
+        let req = to_messages_request(request(json!({
+            "model": "m",
+            "messages": [{"role": "user", "content": [
+                {"type": "image_url", "image_url": {"url": "https://example.com/a.png"}}
+            ]}]
+        })))
+        .unwrap();
+        assert_eq!(
+            req.messages[0].content[0]["source"],
+            json!({"type": "url", "url": "https://example.com/a.png"})
+        );
+        let err = to_messages_request(request(json!({
+            "model": "m",
+            "messages": [{"role": "user", "content": [
+                {"type": "image_url", "image_url": {"url": "file:///etc/passwd"}}
+            ]}]
+        })));
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_to_chat_completion() {
+        let completion = to_chat_completion(&json!({
+            "id": "msg_abc",
+            "model": "claude-sonnet-4-5",
+            "content": [
+                {"type": "text", "text": "calling"},
+                {"type": "tool_use", "id": "toolu_1", "name": "lookup", "input": {"q": "x"}}
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 10, "output_tokens": 5}
+        }));
+        assert_eq!(completion["id"], "chatcmpl-abc");
+        let choice = &completion["choices"][0];
+        assert_eq!(choice["finish_reason"], "tool_calls");
+        assert_eq!(choice["message"]["content"], "calling");
+        assert_eq!(
+            choice["message"]["tool_calls"][0]["function"]["arguments"],
+            "{\"q\":\"x\"}"
+        );
+        assert_eq!(completion["usage"]["total_tokens"], 15);
+    }
+
+    #[test]
+    fn test_chunk_translator() {
+        let events = [
+            json!({"type": "message_start", "message": {"id": "msg_abc", "usage": {"input_tokens": 7, "output_tokens": 1}}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "hi"}}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "lookup"}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"q\""}}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 3}}),
+            json!({"type": "message_stop"}),
+        ];
+        let input: String = events
+            .iter()
+            .map(|e| format!("event: {}\ndata: {}\n\n", e["type"].as_str().unwrap(), e))
+            .collect();
+
+        let mut translator = ChunkTranslator::new("claude-sonnet-4-5", true);
+        // 按任意边界拆分输入
+        let (head, tail) = input.as_bytes().split_at(50);
+        let mut output = translator.feed(head);
+        output.extend(translator.feed(tail));
+
+        let chunks: Vec<Value> = output
+            .iter()
+            .filter_map(|b| std::str::from_utf8(b).unwrap().strip_prefix("data: "))
+            .filter_map(|data| serde_json::from_str(data.trim()).ok())
+            .collect();
+        assert_eq!(chunks[0]["id"], "chatcmpl-abc");
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(chunks[1]["choices"][0]["delta"]["content"], "hi");
+        assert_eq!(
+            chunks[2]["choices"][0]["delta"]["tool_calls"][0]["id"],
+            "toolu_1"
+        );
+        assert_eq!(
+            chunks[3]["choices"][0]["delta"]["tool_calls"][0]["function"]["arguments"],
+            "{\"q\""
+        );
+        assert_eq!(chunks[4]["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(chunks[5]["usage"]["total_tokens"], 10);
+        assert_eq!(
+            output.last().unwrap(),
+            &Bytes::from_static(b"data: [DONE]\n\n")
+        );
+    }
+
+    #[test]
+    fn test_chunk_translator_forwards_error() {
+        let event =
+            json!({"type": "error", "error": {"type": "api_error", "message": "truncated"}});
+        let mut translator = ChunkTranslator::new("claude-sonnet-4-5", false);
+        let output = translator.feed(format!("event: error\ndata: {}\n\n", event).as_bytes());
+
+        assert_eq!(output.len(), 2);
+        let data: Value = serde_json::from_str(
+            std::str::from_utf8(&output[0])
+                .unwrap()
+                .trim()
+                .strip_prefix("data: ")
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(data["error"]["message"], "truncated");
+        assert_eq!(data["error"]["type"], "api_error");
+        assert_eq!(output[1], Bytes::from_static(b"data: [DONE]\n\n"));
+    }
+
+    #[test]
+    fn test_chunk_translator_finishes_without_message_stop() {
+        let event = json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "hi"}});
+        let mut translator = ChunkTranslator::new("claude-sonnet-4-5", false);
+        let output =
+            translator.feed(format!("event: content_block_delta\ndata: {}\n\n", event).as_bytes());
+        assert_eq!(output.len(), 1);
+
+        assert_eq!(
+            translator.finish(),
+            vec![Bytes::from_static(b"data: [DONE]\n\n")]
+        );
+        // 已结束后不再重复发送
+        assert!(translator.finish().is_empty());
+
+        let mut translator = ChunkTranslator::new("claude-sonnet-4-5", false);
+        let stop = json!({"type": "message_stop"});
+        let output = translator.feed(format!("event: message_stop\ndata: {}\n\n", stop).as_bytes());
+        assert_eq!(output, vec![Bytes::from_static(b"data: [DONE]\n\n")]);
+        assert!(translator.finish().is_empty());
+    }
+
+    #[test]
+    fn test_tool_message_keeps_non_text_parts() {
+        let req = to_messages_request(request(json!({
+            "model": "m",
+            "messages": [
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function", "function": {"name": "screenshot", "arguments": "{}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": [
+                    {"type": "text", "text": "captured"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}},
+                    {"type": "input_audio", "input_audio": {"data": "UklG", "format": "wav"}}
+                ]}
+            ]
+        })))
+        .unwrap();
+
+        let content = req.messages[1].content[0]["content"].as_array().unwrap();
+        assert_eq!(content.len(), 3);
+        assert_eq!(content[0], json!({"type": "text", "text": "captured"}));
+        assert_eq!(content[1]["type"], "image");
+        assert_eq!(content[1]["source"]["media_type"], "image/png");
+        assert_eq!(content[2]["type"], "text");
+        assert!(content[2]["text"].as_str().unwrap().contains("input_audio"));
+
+        // 字符串内容保持不变
+        let req = to_messages_request(request(json!({
+            "model": "m",
+            "messages": [{"role": "tool", "tool_call_id": "call_1", "content": "plain"}]
+        })))
+        .unwrap();
+        assert_eq!(req.messages[0].content[0]["content"], "plain");
+    }
+}
@@ -0,0 +1,204 @@
+//! 流式输出节流
+//!
+//! 将上游一次性返回的大段文本拆分为多个小的 delta 事件，并按每秒最大 token 数匀速发送，
+//! 避免大块突发刷新压垮较慢的终端客户端
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt, stream};
+use tokio::time::Instant;
+
+/// 每秒拆分出的片段数（决定单个片段的大小）
+const CHUNKS_PER_SECOND: u32 = 10;
+
+/// 估算文本 token 数（西文约 4 字符/token，非 ASCII 字符约 1 字符/token）
+fn estimate_tokens(text: &str) -> u64 {
+    let units: u64 = text.chars().map(|c| if c.is_ascii() { 1 } else { 4 }).sum();
+    units.div_ceil(4)
+}
+
+/// 按 token 预算拆分文本，保证不在字符中间切分
+fn split_text(text: &str, max_tokens: u64) -> Vec<String> {
+    let max_units = max_tokens.max(1) * 4;
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    let mut units = 0;
+
+    for c in text.chars() {
+        let cost = if c.is_ascii() { 1 } else { 4 };
+        if units + cost > max_units && !current.is_empty() {
+            pieces.push(std::mem::take(&mut current));
+            units = 0;
+        }
+        current.push(c);
+        units += cost;
+    }
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+    pieces
+}
+
+/// 从 SSE 字符串中解析出事件名和数据
+fn parse_sse(raw: &str) -> Option<(&str, serde_json::Value)> {
+    let mut lines = raw.lines();
+    let event = lines.next()?.strip_prefix("event: ")?;
+    let data = lines.next()?.strip_prefix("data: ")?;
+    Some((event, serde_json::from_str(data).ok()?))
+}
+
+/// 拆分单个 SSE 事件，返回 (片段, 片段 token 数) 列表
+///
+/// 只有文本/思考增量会被拆分和计入节流预算，其他事件原样透传；
+/// `chunk_tokens` 为 None 时只计入预算不拆分
+fn split_event(raw: Bytes, chunk_tokens: Option<u64>) -> Vec<(Bytes, u64)> {
+    let Some((event, data)) = std::str::from_utf8(&raw).ok().and_then(parse_sse) else {
+        return vec![(raw, 0)];
+    };
+    if event != "content_block_delta" {
+        return vec![(raw, 0)];
+    }
+
+    let field = match data["delta"]["type"].as_str() {
+        Some("text_delta") => "text",
+        Some("thinking_delta") => "thinking",
+        _ => return vec![(raw, 0)],
+    };
+    let Some(text) = data["delta"][field].as_str() else {
+        return vec![(raw, 0)];
+    };
+
+    let pieces = chunk_tokens
+        .map(|max_tokens| split_text(text, max_tokens))
+        .unwrap_or_default();
+    if pieces.len() <= 1 {
+        let tokens = estimate_tokens(text);
+        return vec![(raw, tokens)];
+    }
+
+    pieces
+        .into_iter()
+        .map(|piece| {
+            let tokens = estimate_tokens(&piece);
+            let mut chunk = data.clone();
+            chunk["delta"][field] = serde_json::Value::String(piece);
+            let sse = format!(
+                "event: {}\ndata: {}\n\n",
+                event,
+                serde_json::to_string(&chunk).unwrap_or_default()
+            );
+            (Bytes::from(sse), tokens)
+        })
+        .collect()
+}
+
+/// 节流状态
+struct PacingState<S> {
+    inner: Pin<Box<S>>,
+    pending: VecDeque<(Bytes, u64)>,
+    next_allowed: Instant,
+    tokens_per_second: u32,
+    /// 单个片段的 token 预算（None 表示不拆分）
+    chunk_tokens: Option<u64>,
+}
+
+/// 为 SSE 字节流添加输出节流
+///
+/// 每个输入项应为一个完整的 SSE 事件（与 `SseEncoder::encode` 的输出一致）。
+/// `split_deltas` 为 false 时只节流不拆分（JSON 模式下拆分会破坏增量的安全边界）
+pub fn pace_sse_stream<S>(
+    inner: S,
+    tokens_per_second: u32,
+    split_deltas: bool,
+) -> impl Stream<Item = Result<Bytes, Infallible>>
+where
+    S: Stream<Item = Result<Bytes, Infallible>> + Send + 'static,
+{
+    let tokens_per_second = tokens_per_second.max(1);
+    let state = PacingState {
+        inner: Box::pin(inner),
+        pending: VecDeque::new(),
+        next_allowed: Instant::now(),
+        tokens_per_second,
+        chunk_tokens: split_deltas.then(|| (tokens_per_second / CHUNKS_PER_SECOND).max(1) as u64),
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some((bytes, tokens)) = state.pending.pop_front() {
+                if tokens > 0 {
+                    tokio::time::sleep_until(state.next_allowed).await;
+                    let cost =
+                        Duration::from_secs_f64(tokens as f64 / state.tokens_per_second as f64);
+                    state.next_allowed = state.next_allowed.max(Instant::now()) + cost;
+                }
+                return Some((Ok(bytes), state));
+            }
+
+            let Ok(raw) = state.inner.next().await?;
+            state.pending.extend(split_event(raw, state.chunk_tokens));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn text_delta(text: &str) -> Bytes {
+        let data = json!({
+            "type": "content_block_delta",
+            "index": 0,
+            "delta": {"type": "text_delta", "text": text}
+        });
+        Bytes::from(format!("event: content_block_delta\ndata: {}\n\n", data))
+    }
+
+    #[test]
+    fn test_split_text_respects_char_boundaries() {
+        let pieces = split_text("你好世界abcd", 1);
+        assert_eq!(pieces, vec!["你", "好", "世", "界", "abcd"]);
+        assert_eq!(split_text("abcdefgh", 1), vec!["abcd", "efgh"]);
+    }
+
+    #[test]
+    fn test_split_event() {
+        let chunks = split_event(text_delta(&"a".repeat(40)), Some(5));
+        assert_eq!(chunks.len(), 2);
+        for (bytes, tokens) in &chunks {
+            let (event, data) = parse_sse(std::str::from_utf8(bytes).unwrap()).unwrap();
+            assert_eq!(event, "content_block_delta");
+            assert_eq!(data["delta"]["text"].as_str().unwrap().len(), 20);
+            assert_eq!(*tokens, 5);
+        }
+
+        // 非增量事件原样透传，不计入预算
+        let ping = Bytes::from("event: ping\ndata: {\"type\": \"ping\"}\n\n");
+        let chunks = split_event(ping.clone(), Some(5));
+        assert_eq!(chunks, vec![(ping, 0)]);
+
+        // 不拆分时整段透传，但仍计入预算
+        let delta = text_delta(&"a".repeat(40));
+        assert_eq!(split_event(delta.clone(), None), vec![(delta, 10)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pace_sse_stream_throttles_output() {
+        // 2000 tokens，每秒 2000 tokens，拆分为 10 片，首片立即发送，其余每片间隔 100ms
+        // （时钟暂停，等待时自动推进，不依赖真实耗时）
+        let input = stream::iter(vec![Ok(text_delta(&"a".repeat(8000)))]);
+        let started = Instant::now();
+
+        let output: Vec<_> = pace_sse_stream(input, 2000, true).collect().await;
+
+        assert_eq!(output.len(), 10);
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(900), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(1000), "{:?}", elapsed);
+    }
+}
@@ -0,0 +1,231 @@
+//! 流式 JSON 部分有效性保证
+//!
+//! 上游的增量可能在数字、`true`/`false`/`null` 字面量或转义序列中间截断，
+//! 此时客户端累积的 JSON 前缀无法通过简单补全（闭合字符串和括号）解析。
+//! 这里只缓冲到下一个安全边界，保证每次输出后累积内容都可以被补全解析
+
+/// 字符串内的转义状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    None,
+    /// 刚读到 `\`
+    Backslash,
+    /// `\u` 之后还需读取的十六进制位数及已读取的值
+    Unicode {
+        remaining: u8,
+        value: u32,
+    },
+}
+
+/// 部分 JSON 增量缓冲区
+#[derive(Debug)]
+pub struct PartialJsonBuffer {
+    /// 尚未输出的内容
+    pending: String,
+    in_string: bool,
+    escape: Escape,
+    /// 是否位于数字或字面量中间
+    in_token: bool,
+    /// 上一个 `\uXXXX` 是高位代理，需要等待低位代理
+    awaiting_low_surrogate: bool,
+}
+
+impl Default for PartialJsonBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialJsonBuffer {
+    pub fn new() -> Self {
+        Self {
+            pending: String::new(),
+            in_string: false,
+            escape: Escape::None,
+            in_token: false,
+            awaiting_low_surrogate: false,
+        }
+    }
+
+    /// 追加增量，返回截至最后一个安全边界可以输出的内容（可能为空）
+    pub fn push(&mut self, chunk: &str) -> String {
+        let start = self.pending.len();
+        self.pending.push_str(chunk);
+
+        let mut safe_len = if self.is_safe() { start } else { 0 };
+        for (offset, c) in chunk.char_indices() {
+            self.advance(c);
+            if self.is_safe() {
+                safe_len = start + offset + c.len_utf8();
+            }
+        }
+
+        let rest = self.pending.split_off(safe_len);
+        std::mem::replace(&mut self.pending, rest)
+    }
+
+    /// 取出剩余全部内容（流结束或内容块结束时调用）
+    pub fn finish(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// 当前位置是否为安全边界
+    fn is_safe(&self) -> bool {
+        !self.in_token && self.escape == Escape::None && !self.awaiting_low_surrogate
+    }
+
+    /// 按单个字符推进扫描状态
+    fn advance(&mut self, c: char) {
+        if !self.in_string {
+            match c {
+                '"' => {
+                    self.in_token = false;
+                    self.in_string = true;
+                }
+                c if c.is_whitespace() || matches!(c, ',' | ':' | '[' | ']' | '{' | '}') => {
+                    self.in_token = false;
+                }
+                _ => self.in_token = true,
+            }
+            return;
+        }
+
+        match self.escape {
+            Escape::Backslash => {
+                self.escape = if c == 'u' {
+                    Escape::Unicode {
+                        remaining: 4,
+                        value: 0,
+                    }
+                } else {
+                    self.awaiting_low_surrogate = false;
+                    Escape::None
+                };
+            }
+            Escape::Unicode { remaining, value } => {
+                let value = value * 16 + c.to_digit(16).unwrap_or(0);
+                if remaining > 1 {
+                    self.escape = Escape::Unicode {
+                        remaining: remaining - 1,
+                        value,
+                    };
+                } else {
+                    self.escape = Escape::None;
+                    self.awaiting_low_surrogate = (0xD800..=0xDBFF).contains(&value);
+                }
+            }
+            Escape::None => {
+                if c == '\\' {
+                    self.escape = Escape::Backslash;
+                } else {
+                    self.awaiting_low_surrogate = false;
+                    if c == '"' {
+                        self.in_string = false;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 补全 JSON 前缀：闭合字符串、补全缺失的值、闭合括号
+    fn repair(prefix: &str) -> String {
+        let mut stack = Vec::new();
+        let mut in_string = false;
+        let mut escaped = false;
+        for c in prefix.chars() {
+            if in_string {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == '"' {
+                    in_string = false;
+                }
+                continue;
+            }
+            match c {
+                '"' => in_string = true,
+                '{' => stack.push('}'),
+                '[' => stack.push(']'),
+                '}' | ']' => {
+                    stack.pop();
+                }
+                _ => {}
+            }
+        }
+
+        let mut out = prefix.trim_end().to_string();
+        if in_string {
+            out.push('"');
+        }
+        if let Some(stripped) = out.trim_end().strip_suffix(',') {
+            out = stripped.to_string();
+        }
+        if out.trim_end().ends_with(':') {
+            out.push_str("null");
+        } else if stack.last() == Some(&'}') && out.ends_with('"') {
+            // 对象中刚结束的字符串可能是缺少值的键
+            let candidate = format!("{}:null", out);
+            let closing: String = stack.iter().rev().collect();
+            if serde_json::from_str::<serde_json::Value>(&format!("{}{}", candidate, closing))
+                .is_ok()
+            {
+                out = candidate;
+            }
+        }
+        out.extend(stack.iter().rev());
+        out
+    }
+
+    #[test]
+    fn test_holds_back_partial_tokens() {
+        let mut buffer = PartialJsonBuffer::new();
+        assert_eq!(buffer.push(r#"{"count": 12"#), r#"{"count": "#);
+        assert_eq!(buffer.push("3, \"ok\": tr"), "123, \"ok\": ");
+        assert_eq!(buffer.push("ue}"), "true}");
+        assert_eq!(buffer.finish(), "");
+    }
+
+    #[test]
+    fn test_holds_back_escape_sequences() {
+        let mut buffer = PartialJsonBuffer::new();
+        assert_eq!(buffer.push(r#"{"a": "x\"#), r#"{"a": "x"#);
+        assert_eq!(buffer.push(r#"u00"#), "");
+        assert_eq!(buffer.push(r#"e9\ud83d"#), r#"\u00e9"#);
+        // 高位代理需要等待低位代理
+        assert_eq!(buffer.push(r#"\ude00"}"#), r#"\ud83d\ude00"}"#);
+    }
+
+    #[test]
+    fn test_every_split_is_repairable() {
+        let document = r#"{"path": "src/main.rs", "lines": [1, -2.5e3, 30], "flags": {"dry_run": false, "note": null}, "text": "tab\tquote\" é 😀 end"}"#;
+        assert!(serde_json::from_str::<serde_json::Value>(document).is_ok());
+
+        // 以所有可能的位置切成两段，每次输出后的累积内容都必须能被补全解析
+        let boundaries: Vec<usize> = document.char_indices().map(|(i, _)| i).collect();
+        for &split in &boundaries {
+            let mut buffer = PartialJsonBuffer::new();
+            let mut emitted = String::new();
+            for chunk in [&document[..split], &document[split..]] {
+                emitted.push_str(&buffer.push(chunk));
+                if !emitted.is_empty() {
+                    let repaired = repair(&emitted);
+                    assert!(
+                        serde_json::from_str::<serde_json::Value>(&repaired).is_ok(),
+                        "split at {}: {:?} -> {:?}",
+                        split,
+                        emitted,
+                        repaired
+                    );
+                }
+            }
+            emitted.push_str(&buffer.finish());
+            assert_eq!(emitted, document);
+        }
+    }
+}
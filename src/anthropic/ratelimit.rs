@@ -0,0 +1,211 @@
+//! 限流响应头
+//!
+//! 按 Anthropic 的 `anthropic-ratelimit-*` 约定在 `/v1/messages` 响应中返回本代理的限流状态，
+//! 内置节流逻辑的客户端 SDK 可据此自行降速：
+//! - `requests-*`: 按 API Key 的并发限制（仅配置 `maxConcurrentRequestsPerKey` 时）
+//! - `output-tokens-*`: 输出 token 速率限制，按每分钟换算（仅配置输出速率时）
+//! - `x-kiro-quota-*`: 凭据池剩余额度（Kiro 额度单位，来自最近一次余额查询）
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use chrono::{DateTime, SecondsFormat, Utc};
+use parking_lot::Mutex;
+
+use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::token_manager::MultiTokenManager;
+
+/// 凭据池额度缓存时长
+const QUOTA_CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// 凭据池额度
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoolQuota {
+    /// 可用凭据的额度总和
+    pub limit: f64,
+    /// 可用凭据的剩余额度总和
+    pub remaining: f64,
+    /// 最早的额度重置时间（Unix 时间戳）
+    pub reset_at: Option<f64>,
+}
+
+/// 汇总凭据池额度（忽略已禁用和尚未查询过余额的凭据）
+pub fn pool_quota(credentials: &[KiroCredentials]) -> Option<PoolQuota> {
+    let known: Vec<_> = credentials
+        .iter()
+        .filter(|c| !c.disabled && c.usage_limit > 0.0)
+        .collect();
+    if known.is_empty() {
+        return None;
+    }
+    Some(PoolQuota {
+        limit: known.iter().map(|c| c.usage_limit).sum(),
+        remaining: known
+            .iter()
+            .map(|c| (c.usage_limit - c.current_usage).max(0.0))
+            .sum(),
+        reset_at: known
+            .iter()
+            .filter_map(|c| c.next_reset_at)
+            .min_by(|a, b| a.total_cmp(b)),
+    })
+}
+
+/// 凭据池额度缓存（避免每个请求都读取全部凭据）
+#[derive(Default)]
+pub struct QuotaCache {
+    inner: Mutex<Option<(Instant, Option<PoolQuota>)>>,
+}
+
+impl QuotaCache {
+    /// 获取凭据池额度（缓存过期时重新计算）
+    pub async fn get(&self, token_manager: &Arc<MultiTokenManager>) -> Option<PoolQuota> {
+        if let Some((at, quota)) = *self.inner.lock()
+            && at.elapsed() < QUOTA_CACHE_TTL
+        {
+            return quota;
+        }
+
+        let credentials = token_manager
+            .database()
+            .call(|db| db.load_credentials())
+            .await
+            .unwrap_or_default();
+        let quota = pool_quota(&credentials);
+        *self.inner.lock() = Some((Instant::now(), quota));
+        quota
+    }
+}
+
+/// 限流状态
+#[derive(Debug, Default)]
+pub struct RateLimitStatus {
+    /// 并发限制与剩余名额
+    pub requests: Option<(usize, usize)>,
+    /// 每秒输出 token 速率
+    pub output_tokens_per_second: Option<u32>,
+    /// 凭据池额度
+    pub quota: Option<PoolQuota>,
+}
+
+impl RateLimitStatus {
+    /// 写入响应头
+    pub fn apply(&self, headers: &mut HeaderMap, now: DateTime<Utc>) {
+        let now_rfc3339 = rfc3339(now);
+
+        if let Some((limit, remaining)) = self.requests {
+            insert(headers, "anthropic-ratelimit-requests-limit", limit);
+            insert(headers, "anthropic-ratelimit-requests-remaining", remaining);
+            // 并发名额在进行中的请求结束后即归还
+            insert(headers, "anthropic-ratelimit-requests-reset", &now_rfc3339);
+        }
+
+        if let Some(rate) = self.output_tokens_per_second {
+            let per_minute = u64::from(rate) * 60;
+            insert(
+                headers,
+                "anthropic-ratelimit-output-tokens-limit",
+                per_minute,
+            );
+            // 速率限制通过节流实现，不会拒绝请求
+            insert(
+                headers,
+                "anthropic-ratelimit-output-tokens-remaining",
+                per_minute,
+            );
+            insert(
+                headers,
+                "anthropic-ratelimit-output-tokens-reset",
+                &now_rfc3339,
+            );
+        }
+
+        if let Some(quota) = self.quota {
+            insert(headers, "x-kiro-quota-limit", format!("{:.2}", quota.limit));
+            insert(
+                headers,
+                "x-kiro-quota-remaining",
+                format!("{:.2}", quota.remaining),
+            );
+            if let Some(reset) = quota
+                .reset_at
+                .and_then(|ts| DateTime::from_timestamp(ts as i64, 0))
+            {
+                insert(headers, "x-kiro-quota-reset", rfc3339(reset));
+            }
+        }
+    }
+}
+
+/// 格式化为秒级 RFC3339 时间
+fn rfc3339(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// 写入单个响应头
+fn insert(headers: &mut HeaderMap, name: &'static str, value: impl ToString) {
+    if let Ok(value) = HeaderValue::from_str(&value.to_string()) {
+        headers.insert(HeaderName::from_static(name), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credential(limit: f64, usage: f64, reset: Option<f64>, disabled: bool) -> KiroCredentials {
+        KiroCredentials {
+            usage_limit: limit,
+            current_usage: usage,
+            next_reset_at: reset,
+            disabled,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pool_quota() {
+        assert_eq!(pool_quota(&[credential(0.0, 0.0, None, false)]), None);
+
+        let quota = pool_quota(&[
+            credential(100.0, 30.0, Some(2_000_000_000.0), false),
+            credential(50.0, 80.0, Some(1_900_000_000.0), false),
+            credential(500.0, 0.0, Some(1_000_000_000.0), true),
+        ])
+        .unwrap();
+        assert_eq!(quota.limit, 150.0);
+        assert_eq!(quota.remaining, 70.0);
+        assert_eq!(quota.reset_at, Some(1_900_000_000.0));
+    }
+
+    #[test]
+    fn test_apply_headers() {
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let status = RateLimitStatus {
+            requests: Some((4, 3)),
+            output_tokens_per_second: Some(50),
+            quota: Some(PoolQuota {
+                limit: 150.0,
+                remaining: 70.5,
+                reset_at: Some(1_700_003_600.0),
+            }),
+        };
+        let mut headers = HeaderMap::new();
+        status.apply(&mut headers, now);
+
+        assert_eq!(headers["anthropic-ratelimit-requests-limit"], "4");
+        assert_eq!(headers["anthropic-ratelimit-requests-remaining"], "3");
+        assert_eq!(
+            headers["anthropic-ratelimit-requests-reset"],
+            "2023-11-14T22:13:20Z"
+        );
+        assert_eq!(headers["anthropic-ratelimit-output-tokens-limit"], "3000");
+        assert_eq!(headers["x-kiro-quota-remaining"], "70.50");
+        assert_eq!(headers["x-kiro-quota-reset"], "2023-11-14T23:13:20Z");
+
+        let mut empty = HeaderMap::new();
+        RateLimitStatus::default().apply(&mut empty, now);
+        assert!(empty.is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+//! 请求日志批量写入
+//!
+//! 请求日志经有界通道交给后台写入任务，缓冲达到批量条数或等待超过刷新间隔时在单个事务中写入，
+//! 请求处理路径上不产生数据库写入；通道已满（数据库长时间不可写）时丢弃日志而不阻塞请求
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+
+use crate::kiro::db::Database;
+use crate::kiro::model::request_log::RequestLog;
+
+/// 写入任务落后时最多缓冲的日志条数
+const CHANNEL_CAPACITY: usize = 10_000;
+
+/// 请求日志写入句柄（可克隆，共享同一个写入任务）
+#[derive(Clone)]
+pub struct RequestLogWriter {
+    tx: mpsc::Sender<RequestLog>,
+}
+
+impl RequestLogWriter {
+    /// 启动后台写入任务（需在 tokio 运行时中调用）
+    ///
+    /// 所有句柄释放后写入剩余的缓冲日志并退出
+    pub fn spawn(database: Arc<Database>, batch_size: usize, flush_interval: Duration) -> Self {
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        tokio::spawn(run(database, rx, batch_size.max(1), flush_interval));
+        Self { tx }
+    }
+
+    /// 提交一条请求日志（不等待写入）
+    pub fn record(&self, log: RequestLog) {
+        if let Err(e) = self.tx.try_send(log) {
+            tracing::warn!("请求日志缓冲已满或写入任务已退出，丢弃日志: {}", e);
+        }
+    }
+}
+
+/// 写入任务主循环：收到第一条日志后开始计时，达到批量条数或刷新间隔到期时写入
+async fn run(
+    database: Arc<Database>,
+    mut rx: mpsc::Receiver<RequestLog>,
+    batch_size: usize,
+    flush_interval: Duration,
+) {
+    let mut batch = Vec::with_capacity(batch_size);
+    while let Some(log) = rx.recv().await {
+        batch.push(log);
+        let deadline = Instant::now() + flush_interval;
+        while batch.len() < batch_size {
+            match time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(log)) => batch.push(log),
+                Ok(None) | Err(_) => break,
+            }
+        }
+        flush(&database, &mut batch).await;
+    }
+}
+
+/// 写入当前批次并清空缓冲（写入失败时丢弃该批次）
+async fn flush(database: &Arc<Database>, batch: &mut Vec<RequestLog>) {
+    let logs = std::mem::take(batch);
+    let count = logs.len();
+    if let Err(e) = database.call(move |db| db.insert_request_logs(&logs)).await {
+        tracing::warn!("批量写入 {} 条请求日志失败: {}", count, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::request_log::RequestLogFilter;
+
+    fn request_log(model: &str) -> RequestLog {
+        RequestLog {
+            id: None,
+            created_at: chrono::Utc::now(),
+            model: model.to_string(),
+            credential_id: Some(1),
+            status: 200,
+            client_key: None,
+            latency_ms: 10,
+            stream: false,
+            error: None,
+            tag: None,
+            seed: None,
+            request_id: None,
+            sse_events: None,
+        }
+    }
+
+    fn count(database: &Database) -> usize {
+        let filter = RequestLogFilter {
+            limit: 100,
+            ..Default::default()
+        };
+        database.search_request_logs(&filter).unwrap().0
+    }
+
+    #[tokio::test]
+    async fn test_flushes_by_size_and_interval() {
+        let database = Database::open_in_memory().unwrap();
+        let writer = RequestLogWriter::spawn(database.clone(), 3, Duration::from_millis(300));
+
+        // 达到批量条数立即写入
+        for _ in 0..3 {
+            writer.record(request_log("claude-sonnet-4"));
+        }
+        wait_for(&database, 3).await;
+
+        // 未达到批量条数时，刷新间隔到期前不写入
+        writer.record(request_log("claude-opus-4"));
+        tokio::task::yield_now().await;
+        assert_eq!(count(&database), 3);
+        wait_for(&database, 4).await;
+    }
+
+    #[tokio::test]
+    async fn test_flushes_remaining_on_shutdown() {
+        let database = Database::open_in_memory().unwrap();
+        let writer = RequestLogWriter::spawn(database.clone(), 100, Duration::from_secs(3600));
+        writer.record(request_log("claude-sonnet-4"));
+        writer.record(request_log("claude-sonnet-4"));
+        drop(writer);
+
+        wait_for(&database, 2).await;
+    }
+
+    async fn wait_for(database: &Database, expected: usize) {
+        for _ in 0..100 {
+            if count(database) == expected {
+                return;
+            }
+            time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!(
+            "请求日志未写入: 期望 {} 条，实际 {} 条",
+            expected,
+            count(database)
+        );
+    }
+}
@@ -8,8 +8,13 @@ use axum::{
 use crate::kiro::provider::KiroProvider;
 
 use super::{
-    handlers::{count_tokens, get_models, post_messages},
-    middleware::{AppState, auth_middleware, cors_layer},
+    embeddings::post_embeddings,
+    handlers::{count_tokens, get_models, post_messages, ready},
+    middleware::{
+        AppState, auth_middleware, catch_panic_middleware, concurrency_middleware, cors_layer,
+        rate_limit_headers_middleware, token_bucket_middleware,
+    },
+    openai::post_chat_completions,
 };
 
 /// 创建 Anthropic API 路由
@@ -18,6 +23,9 @@ use super::{
 /// - `GET /v1/models` - 获取可用模型列表
 /// - `POST /v1/messages` - 创建消息（对话）
 /// - `POST /v1/messages/count_tokens` - 计算 token 数量
+/// - `POST /v1/chat/completions` - OpenAI 兼容的对话接口
+/// - `POST /v1/embeddings` - 透传到配置的 embeddings 上游
+/// - `GET /ready` - 就绪检查（无需认证）
 ///
 /// # 认证
 /// 所有 `/v1` 路径需要 API Key 认证，支持：
@@ -27,14 +35,16 @@ use super::{
 /// # 参数
 /// - `api_key`: API 密钥，用于验证客户端请求
 /// - `kiro_provider`: 可选的 KiroProvider，用于调用上游 API
+/// - `max_concurrent_per_key`: 每个 API Key 的最大并发请求数（0 表示不限制）
 ///
 /// 创建带有 KiroProvider 的 Anthropic API 路由
 pub fn create_router_with_provider(
     api_key: impl Into<String>,
     kiro_provider: Option<KiroProvider>,
     profile_arn: Option<String>,
+    max_concurrent_per_key: usize,
 ) -> Router {
-    let mut state = AppState::new(api_key);
+    let mut state = AppState::new(api_key).with_max_concurrent_per_key(max_concurrent_per_key);
     if let Some(provider) = kiro_provider {
         state = state.with_kiro_provider(provider);
     }
@@ -45,15 +55,52 @@ pub fn create_router_with_provider(
     // 需要认证的 /v1 路由
     let v1_routes = Router::new()
         .route("/models", get(get_models))
-        .route("/messages", post(post_messages))
+        .route(
+            "/messages",
+            post(post_messages)
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    rate_limit_headers_middleware,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    concurrency_middleware,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    token_bucket_middleware,
+                )),
+        )
         .route("/messages/count_tokens", post(count_tokens))
+        .route(
+            "/chat/completions",
+            post(post_chat_completions)
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    rate_limit_headers_middleware,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    concurrency_middleware,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    token_bucket_middleware,
+                )),
+        )
+        .route("/embeddings", post(post_embeddings))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
         ));
 
     Router::new()
+        .route("/ready", get(ready))
         .nest("/v1", v1_routes)
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            catch_panic_middleware,
+        ))
         .layer(cors_layer())
         .with_state(state)
 }
@@ -0,0 +1,61 @@
+//! Anthropic `service_tier` 支持
+//!
+//! 请求的 `service_tier` 映射为内部的请求优先级类别：`auto` 与 `standard_only` 均为普通优先级
+//! （`auto` 是客户端的默认值，不代表延迟敏感，不能使用尚未启用的优先级分段中的凭据）。
+//! 客户端指定了 `service_tier` 时，响应的 `usage.service_tier` 回显实际提供服务的等级：
+//! 使用尚未启用的优先级分段中的凭据时为 `priority`，否则为 `standard`
+
+use serde_json::Value;
+
+use crate::kiro::token_manager::RequestPriority;
+
+/// 解析请求的 `service_tier`（未指定时为 None）
+pub fn parse(value: Option<&str>) -> Result<Option<RequestPriority>, String> {
+    match value {
+        None => Ok(None),
+        Some("auto" | "standard_only") => Ok(Some(RequestPriority::Standard)),
+        Some(other) => Err(format!(
+            "service_tier: Input should be 'auto' or 'standard_only', got '{}'",
+            other
+        )),
+    }
+}
+
+/// 优先级类别对应的响应 `usage.service_tier`
+pub fn effective_tier(priority: RequestPriority) -> &'static str {
+    match priority {
+        RequestPriority::Standard => "standard",
+        RequestPriority::Priority => "priority",
+    }
+}
+
+/// 在 usage 对象中写入实际提供服务的等级
+pub fn add_service_tier(usage: &mut Value, priority: RequestPriority) {
+    usage["service_tier"] = Value::from(effective_tier(priority));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(None), Ok(None));
+        assert_eq!(parse(Some("auto")), Ok(Some(RequestPriority::Standard)));
+        assert_eq!(
+            parse(Some("standard_only")),
+            Ok(Some(RequestPriority::Standard))
+        );
+        assert!(parse(Some("priority")).is_err());
+    }
+
+    #[test]
+    fn test_add_service_tier() {
+        let mut usage = json!({"input_tokens": 10, "output_tokens": 1});
+        add_service_tier(&mut usage, RequestPriority::Priority);
+        assert_eq!(usage["service_tier"], "priority");
+        add_service_tier(&mut usage, RequestPriority::Standard);
+        assert_eq!(usage["service_tier"], "standard");
+    }
+}
@@ -0,0 +1,244 @@
+//! SSE 事件与编码
+//!
+//! 流式响应中每个上游增量都会产生一个 `content_block_delta` 事件，是流式转换的热点路径：
+//! - 增量事件以类型化字段保存，编码时直接写出 JSON，不构建 `serde_json::Value`
+//! - 编码器将事件写入复用的缓冲区，输出的 `Bytes` 与缓冲区共享底层内存，不分配中间 `String`
+//!
+//! 本模块不依赖 crate 内其他模块，以便基准测试（`benches/streaming.rs`）直接引用
+
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// 编码缓冲区每次分配的容量
+const BUFFER_CAPACITY: usize = 8 * 1024;
+
+/// 增量类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaKind {
+    /// 文本增量（`text_delta`）
+    Text,
+    /// 思考增量（`thinking_delta`）
+    Thinking,
+    /// 工具参数增量（`input_json_delta`）
+    InputJson,
+}
+
+impl DeltaKind {
+    /// 增量的 `type` 字段与内容字段名
+    fn names(self) -> (&'static str, &'static str) {
+        match self {
+            DeltaKind::Text => ("text_delta", "text"),
+            DeltaKind::Thinking => ("thinking_delta", "thinking"),
+            DeltaKind::InputJson => ("input_json_delta", "partial_json"),
+        }
+    }
+}
+
+/// 事件数据
+#[derive(Debug, Clone)]
+enum Payload {
+    /// 任意 JSON 数据
+    Value(serde_json::Value),
+    /// `content_block_delta` 事件的增量内容
+    Delta {
+        index: i32,
+        kind: DeltaKind,
+        content: String,
+    },
+}
+
+/// SSE 事件
+#[derive(Debug, Clone)]
+pub struct SseEvent {
+    pub event: &'static str,
+    payload: Payload,
+}
+
+impl SseEvent {
+    pub fn new(event: &'static str, data: serde_json::Value) -> Self {
+        Self {
+            event,
+            payload: Payload::Value(data),
+        }
+    }
+
+    /// 创建 `content_block_delta` 事件
+    pub fn delta(index: i32, kind: DeltaKind, content: impl Into<String>) -> Self {
+        Self {
+            event: "content_block_delta",
+            payload: Payload::Delta {
+                index,
+                kind,
+                content: content.into(),
+            },
+        }
+    }
+
+    /// 事件数据（增量事件按需构建 JSON，仅供测试检查，编码时不经过此路径）
+    #[cfg(test)]
+    pub fn data(&self) -> std::borrow::Cow<'_, serde_json::Value> {
+        use std::borrow::Cow;
+
+        match &self.payload {
+            Payload::Value(value) => Cow::Borrowed(value),
+            Payload::Delta {
+                index,
+                kind,
+                content,
+            } => {
+                let (delta_type, field) = kind.names();
+                Cow::Owned(serde_json::json!({
+                    "type": "content_block_delta",
+                    "index": index,
+                    "delta": {"type": delta_type, field: content}
+                }))
+            }
+        }
+    }
+
+    /// 按 SSE 格式（`event: ...\ndata: ...\n\n`）写入缓冲区
+    pub fn write_to(&self, buf: &mut BytesMut) {
+        buf.extend_from_slice(b"event: ");
+        buf.extend_from_slice(self.event.as_bytes());
+        buf.extend_from_slice(b"\ndata: ");
+        let mut writer = (&mut *buf).writer();
+        // 写入内存缓冲区不会失败
+        match &self.payload {
+            Payload::Value(value) => {
+                let _ = serde_json::to_writer(&mut writer, value);
+            }
+            Payload::Delta {
+                index,
+                kind,
+                content,
+            } => {
+                let (delta_type, field) = kind.names();
+                let _ = write!(
+                    writer,
+                    "{{\"type\":\"content_block_delta\",\"index\":{},\"delta\":{{\"type\":\"{}\",\"{}\":",
+                    index, delta_type, field
+                );
+                let _ = serde_json::to_writer(&mut writer, content);
+                let _ = writer.write_all(b"}}");
+            }
+        }
+        buf.extend_from_slice(b"\n\n");
+    }
+}
+
+/// 复用缓冲区的 SSE 编码器
+///
+/// 每个事件编码后从缓冲区切出（`split().freeze()`），剩余容量留给后续事件，
+/// 只有容量耗尽时才重新分配
+#[derive(Debug, Default)]
+pub struct SseEncoder {
+    buf: BytesMut,
+}
+
+impl SseEncoder {
+    /// 编码单个事件
+    pub fn encode(&mut self, event: &SseEvent) -> Bytes {
+        if self.buf.capacity() - self.buf.len() < BUFFER_CAPACITY / 8 {
+            self.buf.reserve(BUFFER_CAPACITY);
+        }
+        event.write_to(&mut self.buf);
+        self.buf.split().freeze()
+    }
+}
+
+/// 单个流式响应的 SSE 事件序号
+///
+/// 每个事件发送前附加单调递增的 `id` 行（从 1 开始），最后一个 ID 记录到请求日志，
+/// 便于将客户端报告的截断位置与服务端实际发送的事件数对照
+#[derive(Debug, Clone, Default)]
+pub struct SseSequence(Arc<AtomicU64>);
+
+impl SseSequence {
+    /// 已发送的事件数（即最后一个事件的 ID）
+    pub fn last_id(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// 为 SSE 事件添加 `id` 行（注释等非事件内容原样返回）
+    pub fn number(&self, chunk: Bytes) -> Bytes {
+        if !chunk.starts_with(b"event:") {
+            return chunk;
+        }
+        let id = self.0.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut buf = BytesMut::with_capacity(chunk.len() + 24);
+        let _ = writeln!((&mut buf).writer(), "id: {}", id);
+        buf.extend_from_slice(&chunk);
+        buf.freeze()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sse_event_format() {
+        let event = SseEvent::new("message_start", json!({"type": "message_start"}));
+        let bytes = SseEncoder::default().encode(&event);
+
+        assert_eq!(
+            bytes,
+            Bytes::from_static(b"event: message_start\ndata: {\"type\":\"message_start\"}\n\n")
+        );
+    }
+
+    #[test]
+    fn test_sse_sequence() {
+        let sequence = SseSequence::default();
+        let mut encoder = SseEncoder::default();
+        let event = SseEvent::new("ping", json!({"type": "ping"}));
+
+        let first = sequence.number(encoder.encode(&event));
+        assert!(first.starts_with(b"id: 1\nevent: ping\ndata: "));
+        let second = sequence.clone().number(encoder.encode(&event));
+        assert!(second.starts_with(b"id: 2\nevent: ping\n"));
+        // 注释不计入事件数
+        let comment = Bytes::from_static(b": transforms\n\n");
+        assert_eq!(sequence.number(comment.clone()), comment);
+        assert_eq!(sequence.last_id(), 2);
+    }
+
+    #[test]
+    fn test_delta_encoding_matches_data() {
+        let mut encoder = SseEncoder::default();
+        for kind in [DeltaKind::Text, DeltaKind::Thinking, DeltaKind::InputJson] {
+            let event = SseEvent::delta(3, kind, "引号\"与换行\n{\"a\":1}");
+            let bytes = encoder.encode(&event);
+            let text = std::str::from_utf8(&bytes).unwrap();
+
+            let data = text
+                .strip_prefix("event: content_block_delta\ndata: ")
+                .and_then(|rest| rest.strip_suffix("\n\n"))
+                .unwrap();
+            let parsed: serde_json::Value = serde_json::from_str(data).unwrap();
+            assert_eq!(parsed, *event.data());
+        }
+    }
+
+    #[test]
+    fn test_encoder_reuses_buffer() {
+        let mut encoder = SseEncoder::default();
+        let first = encoder.encode(&SseEvent::new("ping", json!({"type": "ping"})));
+        let second = encoder.encode(&SseEvent::delta(0, DeltaKind::Text, "你好\n"));
+
+        // 之前输出的 Bytes 不受后续编码影响
+        assert_eq!(
+            first,
+            Bytes::from_static(b"event: ping\ndata: {\"type\":\"ping\"}\n\n")
+        );
+        assert_eq!(
+            std::str::from_utf8(&second).unwrap(),
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"你好\\n\"}}\n\n"
+        );
+        assert!(encoder.buf.capacity() > 0);
+    }
+}
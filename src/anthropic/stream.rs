@@ -2,12 +2,23 @@
 //!
 //! 实现 Kiro → Anthropic 流式响应转换和 SSE 状态管理
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
 
 use serde_json::json;
 use uuid::Uuid;
 
-use crate::kiro::model::events::Event;
+use crate::kiro::model::events::{Event, ToolUseEvent};
+
+use super::annotation::Annotation;
+use super::beta::add_cache_compat_fields;
+use super::partial_json::PartialJsonBuffer;
+use super::service_tier::add_service_tier;
+use super::sse::{DeltaKind, SseEvent};
+use super::transforms::TransformLog;
+use super::usage::UsageTracker;
+use crate::kiro::token_manager::RequestPriority;
 
 /// 找到小于等于目标位置的最近有效UTF-8字符边界
 ///
@@ -131,31 +142,6 @@ fn find_real_thinking_start_tag(buffer: &str) -> Option<usize> {
     None
 }
 
-/// SSE 事件
-#[derive(Debug, Clone)]
-pub struct SseEvent {
-    pub event: String,
-    pub data: serde_json::Value,
-}
-
-impl SseEvent {
-    pub fn new(event: impl Into<String>, data: serde_json::Value) -> Self {
-        Self {
-            event: event.into(),
-            data,
-        }
-    }
-
-    /// 格式化为 SSE 字符串
-    pub fn to_sse_string(&self) -> String {
-        format!(
-            "event: {}\ndata: {}\n\n",
-            self.event,
-            serde_json::to_string(&self.data).unwrap_or_default()
-        )
-    }
-}
-
 /// 内容块状态
 #[derive(Debug, Clone)]
 struct BlockState {
@@ -311,7 +297,8 @@ impl SseStateManager {
     pub fn handle_content_block_delta(
         &mut self,
         index: i32,
-        data: serde_json::Value,
+        kind: DeltaKind,
+        content: &str,
     ) -> Option<SseEvent> {
         // 确保块已启动
         if let Some(block) = self.active_blocks.get(&index) {
@@ -330,7 +317,7 @@ impl SseStateManager {
             return None;
         }
 
-        Some(SseEvent::new("content_block_delta", data))
+        Some(SseEvent::delta(index, kind, content))
     }
 
     /// 处理 content_block_stop 事件
@@ -353,22 +340,14 @@ impl SseStateManager {
     }
 
     /// 生成最终事件序列
-    pub fn generate_final_events(&mut self, input_tokens: i32) -> Vec<SseEvent> {
-        let mut events = Vec::new();
-
-        // 关闭所有未关闭的块
-        for (index, block) in self.active_blocks.iter_mut() {
-            if block.started && !block.stopped {
-                events.push(SseEvent::new(
-                    "content_block_stop",
-                    json!({
-                        "type": "content_block_stop",
-                        "index": index
-                    }),
-                ));
-                block.stopped = true;
-            }
-        }
+    ///
+    /// `message_delta` 中的 usage 为整个响应的最终用量（客户端据此计费）
+    pub fn generate_final_events(
+        &mut self,
+        input_tokens: i32,
+        output_tokens: i32,
+    ) -> Vec<SseEvent> {
+        let mut events = self.close_open_blocks();
 
         // 发送 message_delta
         if !self.message_delta_sent {
@@ -383,7 +362,7 @@ impl SseStateManager {
                     },
                     "usage": {
                         "input_tokens": input_tokens,
-                        "output_tokens": 1
+                        "output_tokens": output_tokens
                     }
                 }),
             ));
@@ -400,11 +379,174 @@ impl SseStateManager {
 
         events
     }
+
+    /// 生成错误结束事件序列
+    ///
+    /// 关闭所有未关闭的块后发送 `error` 事件，不再发送 message_delta / message_stop
+    pub fn generate_error_events(&mut self, error_type: &str, message: &str) -> Vec<SseEvent> {
+        let mut events = self.close_open_blocks();
+        if !self.message_ended {
+            self.message_delta_sent = true;
+            self.message_ended = true;
+            events.push(SseEvent::new(
+                "error",
+                json!({
+                    "type": "error",
+                    "error": {
+                        "type": error_type,
+                        "message": message
+                    }
+                }),
+            ));
+        }
+        events
+    }
+
+    /// 关闭所有未关闭的块
+    fn close_open_blocks(&mut self) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        for (index, block) in self.active_blocks.iter_mut() {
+            if block.started && !block.stopped {
+                events.push(SseEvent::new(
+                    "content_block_stop",
+                    json!({
+                        "type": "content_block_stop",
+                        "index": index
+                    }),
+                ));
+                block.stopped = true;
+            }
+        }
+        events
+    }
 }
 
 /// 上下文窗口大小（200k tokens）
 const CONTEXT_WINDOW_SIZE: i32 = 200_000;
 
+/// 超出输出上限时使用的 stop_reason
+pub const OUTPUT_LIMIT_STOP_REASON: &str = "output_limit_exceeded";
+
+/// 上游响应被截断时返回给客户端的错误消息
+pub const TRUNCATED_ERROR_MESSAGE: &str = "Upstream response ended unexpectedly before completion";
+
+/// 上游响应被截断的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Truncation {
+    /// 上游流结束时未收到结束事件（meteringEvent / contextUsageEvent / 异常事件）
+    MissingTerminalEvent,
+    /// 工具调用未收到结束标记（参数 JSON 可能不完整）
+    IncompleteToolUse(String),
+}
+
+impl fmt::Display for Truncation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Truncation::MissingTerminalEvent => write!(f, "未收到结束事件"),
+            Truncation::IncompleteToolUse(id) => write!(f, "工具调用 {} 未结束", id),
+        }
+    }
+}
+
+/// 上游响应完整性检查
+///
+/// 每个工具调用都以带 `stop` 标记的事件结束，上游连接中途断开时会缺失；
+/// 正常结束的响应通常还会在最后收到 meteringEvent / contextUsageEvent（或异常事件），
+/// 但上游不保证发送，仅在 `require_terminal_event` 时检查
+#[derive(Debug, Clone, Default)]
+pub struct CompletionCheck {
+    /// 未收到结束事件时是否视为截断
+    require_terminal_event: bool,
+    /// 是否已收到结束事件
+    terminal_received: bool,
+    /// 已开始但未结束的工具调用
+    open_tool_uses: BTreeSet<String>,
+}
+
+impl CompletionCheck {
+    /// 创建完整性检查（`require_terminal_event` 见 `truncationRequireTerminalEvent` 配置）
+    pub fn new(require_terminal_event: bool) -> Self {
+        Self {
+            require_terminal_event,
+            ..Self::default()
+        }
+    }
+
+    /// 记录上游事件
+    pub fn observe(&mut self, event: &Event) {
+        match event {
+            Event::ToolUse(tool_use) => {
+                if tool_use.stop {
+                    self.open_tool_uses.remove(&tool_use.tool_use_id);
+                } else {
+                    self.open_tool_uses.insert(tool_use.tool_use_id.clone());
+                }
+            }
+            Event::Metering(_) | Event::ContextUsage(_) => self.terminal_received = true,
+            // 异常（如 ContentLengthExceededException）表示上游主动结束，未完成的工具调用不视为截断
+            Event::Error { .. } | Event::Exception { .. } => {
+                self.terminal_received = true;
+                self.open_tool_uses.clear();
+            }
+            _ => {}
+        }
+    }
+
+    /// 上游流结束时检查响应是否被截断
+    pub fn truncation(&self) -> Option<Truncation> {
+        if let Some(id) = self.open_tool_uses.iter().next() {
+            return Some(Truncation::IncompleteToolUse(id.clone()));
+        }
+        (self.require_terminal_event && !self.terminal_received)
+            .then_some(Truncation::MissingTerminalEvent)
+    }
+}
+
+/// 单次请求的输出预算（文本与工具参数的字节数及估算 tokens）
+///
+/// 用于在上游失控生成时提前终止响应，避免单个请求耗尽账号额度
+#[derive(Debug, Clone, Default)]
+pub struct OutputBudget {
+    /// 最大输出字节数（0 表示不限制）
+    max_bytes: usize,
+    /// 最大输出 tokens（0 表示不限制）
+    max_tokens: i32,
+    /// 已输出字节数
+    bytes: usize,
+    /// 已输出估算 tokens
+    tokens: i32,
+}
+
+impl OutputBudget {
+    pub fn new(max_bytes: usize, max_tokens: u32) -> Self {
+        Self {
+            max_bytes,
+            max_tokens: i32::try_from(max_tokens).unwrap_or(i32::MAX),
+            ..Self::default()
+        }
+    }
+
+    /// 记录一段输出内容
+    pub fn record(&mut self, content: &str) {
+        if content.is_empty() {
+            return;
+        }
+        self.bytes += content.len();
+        self.tokens += estimate_tokens(content);
+    }
+
+    /// 超出上限时返回描述（如 "字节数 1048577 > 1048576"）
+    pub fn exceeded(&self) -> Option<String> {
+        if self.max_bytes > 0 && self.bytes > self.max_bytes {
+            return Some(format!("字节数 {} > {}", self.bytes, self.max_bytes));
+        }
+        if self.max_tokens > 0 && self.tokens > self.max_tokens {
+            return Some(format!("tokens {} > {}", self.tokens, self.max_tokens));
+        }
+        None
+    }
+}
+
 /// 流处理上下文
 pub struct StreamContext {
     /// SSE 状态管理器
@@ -421,6 +563,12 @@ pub struct StreamContext {
     pub output_tokens: i32,
     /// 工具块索引映射 (tool_id -> block_index)
     pub tool_block_indices: HashMap<String, i32>,
+    /// 是否最多输出一个工具调用（客户端设置了 `disable_parallel_tool_use`）
+    pub single_tool_use: bool,
+    /// 当前未结束的工具块（tool_id）
+    open_tool_use: Option<String>,
+    /// 并行工具调用交错到达时，等待当前工具块结束后再输出的事件
+    pending_tool_events: VecDeque<ToolUseEvent>,
     /// thinking 是否启用
     pub thinking_enabled: bool,
     /// thinking 内容缓冲区
@@ -433,6 +581,26 @@ pub struct StreamContext {
     pub thinking_block_index: Option<i32>,
     /// 文本块索引（thinking 启用时动态分配）
     pub text_block_index: Option<i32>,
+    /// 是否在 usage 中返回 Prompt Caching 兼容字段（anthropic-beta，固定为 0）
+    pub cache_compat_fields: bool,
+    /// 是否保证文本/工具参数增量的累积内容为可补全的部分 JSON（response_format）
+    pub json_deltas: bool,
+    /// JSON 模式下文本增量的缓冲区
+    text_json_buffer: PartialJsonBuffer,
+    /// JSON 模式下工具参数增量的缓冲区 (block_index -> buffer)
+    tool_json_buffers: HashMap<i32, PartialJsonBuffer>,
+    /// 输出预算（超出后以 output_limit_exceeded 结束响应）
+    pub output_budget: OutputBudget,
+    /// 用量记录（流结束或断开时写入 usage_log）
+    pub(super) usage: Option<UsageTracker>,
+    /// 请求转换记录（客户端要求回显时为 Some）
+    pub(super) transforms: Option<TransformLog>,
+    /// 客户端指定服务等级时，在 message_start 的 usage 中回显生效的等级
+    pub(super) service_tier: Option<RequestPriority>,
+    /// 响应标注（在 message_start 中添加字段或在文本末尾追加后缀）
+    pub(super) annotation: Option<Arc<Annotation>>,
+    /// 上游响应完整性检查
+    pub(super) completion: CompletionCheck,
 }
 
 impl StreamContext {
@@ -450,18 +618,31 @@ impl StreamContext {
             context_input_tokens: None,
             output_tokens: 0,
             tool_block_indices: HashMap::new(),
+            single_tool_use: false,
+            open_tool_use: None,
+            pending_tool_events: VecDeque::new(),
             thinking_enabled,
             thinking_buffer: String::new(),
             in_thinking_block: false,
             thinking_extracted: false,
             thinking_block_index: None,
             text_block_index: None,
+            cache_compat_fields: false,
+            json_deltas: false,
+            text_json_buffer: PartialJsonBuffer::new(),
+            tool_json_buffers: HashMap::new(),
+            output_budget: OutputBudget::default(),
+            usage: None,
+            transforms: None,
+            service_tier: None,
+            annotation: None,
+            completion: CompletionCheck::default(),
         }
     }
 
     /// 生成 message_start 事件
     pub fn create_message_start_event(&self) -> serde_json::Value {
-        json!({
+        let mut event = json!({
             "type": "message_start",
             "message": {
                 "id": self.message_id,
@@ -476,7 +657,17 @@ impl StreamContext {
                     "output_tokens": 1
                 }
             }
-        })
+        });
+        if self.cache_compat_fields {
+            add_cache_compat_fields(&mut event["message"]["usage"]);
+        }
+        if let Some(priority) = self.service_tier {
+            add_service_tier(&mut event["message"]["usage"], priority);
+        }
+        if let Some(annotation) = &self.annotation {
+            annotation.add_metadata(&mut event["message"], self.json_deltas);
+        }
+        event
     }
 
     /// 生成初始事件序列 (message_start + 文本块 start)
@@ -518,11 +709,37 @@ impl StreamContext {
         events
     }
 
+    /// 将当前的 token 用量同步到用量记录
+    pub fn report_usage(&self) {
+        if let Some(usage) = &self.usage {
+            usage.set_tokens(
+                self.context_input_tokens.unwrap_or(self.input_tokens),
+                self.output_tokens,
+            );
+        }
+    }
+
+    /// 检查输出是否超出预算，超出时设置 stop_reason 并返回描述
+    ///
+    /// 调用方应随后发送最终事件并停止读取上游
+    pub fn check_output_budget(&mut self) -> Option<String> {
+        let reason = self.output_budget.exceeded()?;
+        self.state_manager.set_stop_reason(OUTPUT_LIMIT_STOP_REASON);
+        Some(reason)
+    }
+
     /// 处理 Kiro 事件并转换为 Anthropic SSE 事件
     pub fn process_kiro_event(&mut self, event: &Event) -> Vec<SseEvent> {
+        self.completion.observe(event);
         match event {
-            Event::AssistantResponse(resp) => self.process_assistant_response(&resp.content),
-            Event::ToolUse(tool_use) => self.process_tool_use(tool_use),
+            Event::AssistantResponse(resp) => {
+                self.output_budget.record(&resp.content);
+                self.process_assistant_response(&resp.content)
+            }
+            Event::ToolUse(tool_use) => {
+                self.output_budget.record(&tool_use.input);
+                self.schedule_tool_use(tool_use)
+            }
             Event::ContextUsage(context_usage) => {
                 // 从上下文使用百分比计算实际的 input_tokens
                 // 公式: percentage * 200000 / 100 = percentage * 2000
@@ -590,15 +807,14 @@ impl StreamContext {
                 // 查找 <thinking> 开始标签（跳过被反引号包裹的）
                 if let Some(start_pos) = find_real_thinking_start_tag(&self.thinking_buffer) {
                     // 发送 <thinking> 之前的内容作为 text_delta
-                    let before_thinking = self.thinking_buffer[..start_pos].to_string();
+                    let before_thinking: String = self.thinking_buffer.drain(..start_pos).collect();
                     if !before_thinking.is_empty() {
                         events.extend(self.create_text_delta_events(&before_thinking));
                     }
 
-                    // 进入 thinking 块
+                    // 进入 thinking 块（原地移除标签，不重新分配缓冲区）
                     self.in_thinking_block = true;
-                    self.thinking_buffer =
-                        self.thinking_buffer[start_pos + "<thinking>".len()..].to_string();
+                    self.thinking_buffer.drain(.."<thinking>".len());
 
                     // 创建 thinking 块的 content_block_start 事件
                     let thinking_index = self.state_manager.next_block_index();
@@ -625,11 +841,8 @@ impl StreamContext {
                         .saturating_sub("<thinking>".len());
                     let safe_len = find_char_boundary(&self.thinking_buffer, target_len);
                     if safe_len > 0 {
-                        let safe_content = self.thinking_buffer[..safe_len].to_string();
-                        if !safe_content.is_empty() {
-                            events.extend(self.create_text_delta_events(&safe_content));
-                        }
-                        self.thinking_buffer = self.thinking_buffer[safe_len..].to_string();
+                        let safe_content: String = self.thinking_buffer.drain(..safe_len).collect();
+                        events.extend(self.create_text_delta_events(&safe_content));
                     }
                     break;
                 }
@@ -637,13 +850,13 @@ impl StreamContext {
                 // 在 thinking 块内，查找 </thinking> 结束标签（跳过被反引号包裹的）
                 if let Some(end_pos) = find_real_thinking_end_tag(&self.thinking_buffer) {
                     // 提取 thinking 内容
-                    let thinking_content = self.thinking_buffer[..end_pos].to_string();
-                    if !thinking_content.is_empty()
+                    if end_pos > 0
                         && let Some(thinking_index) = self.thinking_block_index
                     {
-                        events.push(
-                            self.create_thinking_delta_event(thinking_index, &thinking_content),
-                        );
+                        events.push(self.create_thinking_delta_event(
+                            thinking_index,
+                            &self.thinking_buffer[..end_pos],
+                        ));
                     }
 
                     // 结束 thinking 块
@@ -662,8 +875,7 @@ impl StreamContext {
                         }
                     }
 
-                    self.thinking_buffer =
-                        self.thinking_buffer[end_pos + "</thinking>".len()..].to_string();
+                    self.thinking_buffer.drain(..end_pos + "</thinking>".len());
                 } else {
                     // 没有找到结束标签，发送当前缓冲区内容作为 thinking_delta
                     // 保留可能是部分标签的内容
@@ -673,23 +885,20 @@ impl StreamContext {
                         .saturating_sub("</thinking>".len());
                     let safe_len = find_char_boundary(&self.thinking_buffer, target_len);
                     if safe_len > 0 {
-                        let safe_content = self.thinking_buffer[..safe_len].to_string();
-                        if !safe_content.is_empty()
-                            && let Some(thinking_index) = self.thinking_block_index
-                        {
-                            events.push(
-                                self.create_thinking_delta_event(thinking_index, &safe_content),
-                            );
+                        if let Some(thinking_index) = self.thinking_block_index {
+                            events.push(self.create_thinking_delta_event(
+                                thinking_index,
+                                &self.thinking_buffer[..safe_len],
+                            ));
                         }
-                        self.thinking_buffer = self.thinking_buffer[safe_len..].to_string();
+                        self.thinking_buffer.drain(..safe_len);
                     }
                     break;
                 }
             } else {
                 // thinking 已提取完成，剩余内容作为 text_delta
                 if !self.thinking_buffer.is_empty() {
-                    let remaining = self.thinking_buffer.clone();
-                    self.thinking_buffer.clear();
+                    let remaining = std::mem::take(&mut self.thinking_buffer);
                     events.extend(self.create_text_delta_events(&remaining));
                 }
                 break;
@@ -701,11 +910,34 @@ impl StreamContext {
 
     /// 创建 text_delta 事件
     ///
+    /// JSON 模式下只输出到最后一个安全边界，其余内容留在缓冲区等待后续增量
+    fn create_text_delta_events(&mut self, text: &str) -> Vec<SseEvent> {
+        if !self.json_deltas {
+            return self.emit_text_delta_events(text);
+        }
+        let safe = self.text_json_buffer.push(text);
+        if safe.is_empty() {
+            return Vec::new();
+        }
+        self.emit_text_delta_events(&safe)
+    }
+
+    /// 输出 JSON 模式下缓冲的剩余文本
+    fn flush_text_json_buffer(&mut self) -> Vec<SseEvent> {
+        let remaining = self.text_json_buffer.finish();
+        if remaining.is_empty() {
+            return Vec::new();
+        }
+        self.emit_text_delta_events(&remaining)
+    }
+
+    /// 发送 text_delta 事件
+    ///
     /// 如果文本块尚未创建，会先创建文本块。
     /// 当发生 tool_use 时，状态机会自动关闭当前文本块；后续文本会自动创建新的文本块继续输出。
     ///
     /// 返回值包含可能的 content_block_start 事件和 content_block_delta 事件。
-    fn create_text_delta_events(&mut self, text: &str) -> Vec<SseEvent> {
+    fn emit_text_delta_events(&mut self, text: &str) -> Vec<SseEvent> {
         let mut events = Vec::new();
 
         // 如果当前 text_block_index 指向的块已经被关闭（例如 tool_use 开始时自动 stop），
@@ -742,17 +974,10 @@ impl StreamContext {
         };
 
         // 发送 content_block_delta 事件
-        if let Some(delta_event) = self.state_manager.handle_content_block_delta(
-            text_index,
-            json!({
-                "type": "content_block_delta",
-                "index": text_index,
-                "delta": {
-                    "type": "text_delta",
-                    "text": text
-                }
-            }),
-        ) {
+        if let Some(delta_event) =
+            self.state_manager
+                .handle_content_block_delta(text_index, DeltaKind::Text, text)
+        {
             events.push(delta_event);
         }
 
@@ -761,24 +986,50 @@ impl StreamContext {
 
     /// 创建 thinking_delta 事件
     fn create_thinking_delta_event(&self, index: i32, thinking: &str) -> SseEvent {
-        SseEvent::new(
-            "content_block_delta",
-            json!({
-                "type": "content_block_delta",
-                "index": index,
-                "delta": {
-                    "type": "thinking_delta",
-                    "thinking": thinking
-                }
-            }),
-        )
+        SseEvent::delta(index, DeltaKind::Thinking, thinking)
+    }
+
+    /// 按工具块顺序输出工具使用事件
+    ///
+    /// Anthropic 客户端要求内容块依次开始和结束：另一个工具块未结束时到达的并行工具调用
+    /// 先缓存，待当前工具块结束后按到达顺序输出。`single_tool_use` 时丢弃第一个之外的工具调用
+    fn schedule_tool_use(&mut self, tool_use: &ToolUseEvent) -> Vec<SseEvent> {
+        let id = &tool_use.tool_use_id;
+        if self.single_tool_use
+            && !self.tool_block_indices.is_empty()
+            && !self.tool_block_indices.contains_key(id)
+        {
+            tracing::debug!("客户端禁止并行工具调用，丢弃工具调用 {}", id);
+            return Vec::new();
+        }
+        if self.open_tool_use.as_ref().is_some_and(|open| open != id) {
+            self.pending_tool_events.push_back(tool_use.clone());
+            return Vec::new();
+        }
+
+        let mut events = self.process_tool_use(tool_use);
+        // 当前工具块结束后，依次输出缓存的工具调用（同一工具的后续事件优先）
+        loop {
+            let next = match &self.open_tool_use {
+                Some(open) => self
+                    .pending_tool_events
+                    .iter()
+                    .position(|e| &e.tool_use_id == open),
+                None => (!self.pending_tool_events.is_empty()).then_some(0),
+            };
+            let Some(next) = next.and_then(|i| self.pending_tool_events.remove(i)) else {
+                break;
+            };
+            if self.single_tool_use && !self.tool_block_indices.contains_key(&next.tool_use_id) {
+                continue;
+            }
+            events.extend(self.process_tool_use(&next));
+        }
+        events
     }
 
     /// 处理工具使用事件
-    fn process_tool_use(
-        &mut self,
-        tool_use: &crate::kiro::model::events::ToolUseEvent,
-    ) -> Vec<SseEvent> {
+    fn process_tool_use(&mut self, tool_use: &ToolUseEvent) -> Vec<SseEvent> {
         let mut events = Vec::new();
 
         self.state_manager.set_has_tool_use(true);
@@ -794,6 +1045,8 @@ impl StreamContext {
             let buffered = std::mem::take(&mut self.thinking_buffer);
             events.extend(self.create_text_delta_events(&buffered));
         }
+        // 同理，JSON 模式下暂存的文本也需要在文本块关闭前输出
+        events.extend(self.flush_text_json_buffer());
 
         // 获取或分配块索引
         let block_index = if let Some(&idx) = self.tool_block_indices.get(&tool_use.tool_use_id) {
@@ -826,33 +1079,90 @@ impl StreamContext {
         if !tool_use.input.is_empty() {
             self.output_tokens += (tool_use.input.len() as i32 + 3) / 4; // 估算 token
 
-            if let Some(delta_event) = self.state_manager.handle_content_block_delta(
-                block_index,
-                json!({
-                    "type": "content_block_delta",
-                    "index": block_index,
-                    "delta": {
-                        "type": "input_json_delta",
-                        "partial_json": tool_use.input
-                    }
-                }),
-            ) {
-                events.push(delta_event);
-            }
+            let partial_json = if self.json_deltas {
+                self.tool_json_buffers
+                    .entry(block_index)
+                    .or_default()
+                    .push(&tool_use.input)
+            } else {
+                tool_use.input.clone()
+            };
+            events.extend(self.create_input_json_delta_event(block_index, &partial_json));
         }
 
         // 如果是完整的工具调用（stop=true），发送 content_block_stop
-        if tool_use.stop
-            && let Some(stop_event) = self.state_manager.handle_content_block_stop(block_index)
-        {
-            events.push(stop_event);
+        self.open_tool_use = (!tool_use.stop).then(|| tool_use.tool_use_id.clone());
+        if tool_use.stop {
+            if let Some(mut buffer) = self.tool_json_buffers.remove(&block_index) {
+                let remaining = buffer.finish();
+                events.extend(self.create_input_json_delta_event(block_index, &remaining));
+            }
+            if let Some(stop_event) = self.state_manager.handle_content_block_stop(block_index) {
+                events.push(stop_event);
+            }
+        }
+
+        events
+    }
+
+    /// 创建 input_json_delta 事件（空内容不发送）
+    fn create_input_json_delta_event(
+        &mut self,
+        block_index: i32,
+        partial_json: &str,
+    ) -> Option<SseEvent> {
+        if partial_json.is_empty() {
+            return None;
         }
+        self.state_manager.handle_content_block_delta(
+            block_index,
+            DeltaKind::InputJson,
+            partial_json,
+        )
+    }
 
+    /// 上游流结束时检查响应是否被截断
+    pub fn truncation(&self) -> Option<Truncation> {
+        self.completion.truncation()
+    }
+
+    /// 生成截断结束事件序列（输出已缓冲的内容后以 `error` 事件结束）
+    pub fn generate_truncated_events(&mut self) -> Vec<SseEvent> {
+        let mut events = self.flush_pending();
+        self.report_usage();
+        events.extend(
+            self.state_manager
+                .generate_error_events("api_error", TRUNCATED_ERROR_MESSAGE),
+        );
         events
     }
 
     /// 生成最终事件序列
     pub fn generate_final_events(&mut self) -> Vec<SseEvent> {
+        let mut events = self.flush_pending();
+        if let Some(suffix) = self
+            .annotation
+            .as_ref()
+            .and_then(|annotation| annotation.suffix(self.json_deltas))
+        {
+            events.extend(self.emit_text_delta_events(&suffix));
+        }
+
+        self.report_usage();
+
+        // 使用从 contextUsageEvent 计算的 input_tokens，如果没有则使用估算值
+        let final_input_tokens = self.context_input_tokens.unwrap_or(self.input_tokens);
+
+        // 生成最终事件
+        events.extend(
+            self.state_manager
+                .generate_final_events(final_input_tokens, self.output_tokens),
+        );
+        events
+    }
+
+    /// 输出 thinking 探测与 JSON 模式下暂存的内容
+    fn flush_pending(&mut self) -> Vec<SseEvent> {
         let mut events = Vec::new();
 
         // Flush thinking_buffer 中的剩余内容
@@ -883,11 +1193,14 @@ impl StreamContext {
             self.thinking_buffer.clear();
         }
 
-        // 使用从 contextUsageEvent 计算的 input_tokens，如果没有则使用估算值
-        let final_input_tokens = self.context_input_tokens.unwrap_or(self.input_tokens);
-
-        // 生成最终事件
-        events.extend(self.state_manager.generate_final_events(final_input_tokens));
+        // Flush JSON 模式下暂存的文本和工具参数
+        events.extend(self.flush_text_json_buffer());
+        let mut tool_buffers: Vec<_> = self.tool_json_buffers.drain().collect();
+        tool_buffers.sort_by_key(|(index, _)| *index);
+        for (block_index, mut buffer) in tool_buffers {
+            let remaining = buffer.finish();
+            events.extend(self.create_input_json_delta_event(block_index, &remaining));
+        }
         events
     }
 }
@@ -917,16 +1230,6 @@ fn estimate_tokens(text: &str) -> i32 {
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_sse_event_format() {
-        let event = SseEvent::new("message_start", json!({"type": "message_start"}));
-        let sse_str = event.to_sse_string();
-
-        assert!(sse_str.starts_with("event: message_start\n"));
-        assert!(sse_str.contains("data: "));
-        assert!(sse_str.ends_with("\n\n"));
-    }
-
     #[test]
     fn test_sse_state_manager_message_start() {
         let mut manager = SseStateManager::new();
@@ -949,7 +1252,7 @@ mod tests {
         assert_eq!(events.len(), 1);
 
         // delta
-        let event = manager.handle_content_block_delta(0, json!({}));
+        let event = manager.handle_content_block_delta(0, DeltaKind::Text, "");
         assert!(event.is_some());
 
         // stop
@@ -961,24 +1264,201 @@ mod tests {
         assert!(event.is_none());
     }
 
+    fn tool_use_event(id: &str, stop: bool) -> Event {
+        Event::ToolUse(ToolUseEvent {
+            name: "lookup".to_string(),
+            tool_use_id: id.to_string(),
+            input: if stop {
+                String::new()
+            } else {
+                "{\"q\"".to_string()
+            },
+            stop,
+        })
+    }
+
     #[test]
-    fn test_text_delta_after_tool_use_restarts_text_block() {
+    fn test_completion_check() {
+        // 默认不要求结束事件
+        let mut check = CompletionCheck::default();
+        assert_eq!(check.truncation(), None);
+        check.observe(&tool_use_event("tool_0", false));
+        assert_eq!(
+            check.truncation(),
+            Some(Truncation::IncompleteToolUse("tool_0".to_string()))
+        );
+
+        let mut check = CompletionCheck::new(true);
+        assert_eq!(check.truncation(), Some(Truncation::MissingTerminalEvent));
+
+        check.observe(&tool_use_event("tool_1", false));
+        check.observe(&Event::Metering(()));
+        assert_eq!(
+            check.truncation(),
+            Some(Truncation::IncompleteToolUse("tool_1".to_string()))
+        );
+
+        check.observe(&tool_use_event("tool_1", true));
+        assert_eq!(check.truncation(), None);
+
+        // 异常事件表示上游主动结束
+        let mut check = CompletionCheck::default();
+        check.observe(&tool_use_event("tool_2", false));
+        check.observe(&Event::Exception {
+            exception_type: "ContentLengthExceededException".to_string(),
+            message: String::new(),
+        });
+        assert_eq!(check.truncation(), None);
+    }
+
+    /// 将事件简化为 (事件名, 块索引, 工具 ID)
+    fn block_events(events: &[SseEvent]) -> Vec<(&'static str, i64, String)> {
+        events
+            .iter()
+            .map(|e| {
+                let data = e.data();
+                (
+                    e.event,
+                    data["index"].as_i64().unwrap_or(-1),
+                    data["content_block"]["id"]
+                        .as_str()
+                        .unwrap_or("")
+                        .to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_interleaved_parallel_tool_uses_are_serialized() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        let _ = ctx.generate_initial_events();
+        let mut events = Vec::new();
+        for (id, stop) in [("t1", false), ("t2", false), ("t2", true), ("t1", true)] {
+            events.extend(ctx.process_kiro_event(&tool_use_event(id, stop)));
+        }
+        // 先关闭初始文本块；t2 在 t1 结束后才开始，块依次开始和结束
+        let blocks: Vec<_> = block_events(&events)
+            .into_iter()
+            .filter(|(name, _, _)| *name != "content_block_delta")
+            .collect();
+        assert_eq!(
+            blocks,
+            [
+                ("content_block_stop", 0, String::new()),
+                ("content_block_start", 1, "t1".to_string()),
+                ("content_block_stop", 1, String::new()),
+                ("content_block_start", 2, "t2".to_string()),
+                ("content_block_stop", 2, String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_single_tool_use_drops_parallel_calls() {
         let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        ctx.single_tool_use = true;
+        let _ = ctx.generate_initial_events();
+        let mut events = Vec::new();
+        for (id, stop) in [("t1", false), ("t2", false), ("t1", true), ("t2", true)] {
+            events.extend(ctx.process_kiro_event(&tool_use_event(id, stop)));
+        }
+        let starts: Vec<_> = block_events(&events)
+            .into_iter()
+            .filter(|(name, _, _)| *name == "content_block_start")
+            .map(|(_, _, id)| id)
+            .collect();
+        assert_eq!(starts, ["t1"]);
+    }
 
-        let initial_events = ctx.generate_initial_events();
+    #[test]
+    fn test_truncated_events_end_with_error() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        let _ = ctx.generate_initial_events();
+        ctx.process_kiro_event(&tool_use_event("tool_1", false));
+        assert!(ctx.truncation().is_some());
+
+        let events = ctx.generate_truncated_events();
+        let names: Vec<_> = events.iter().map(|e| e.event).collect();
+        assert_eq!(names, ["content_block_stop", "error"]);
+        assert_eq!(events[1].data()["error"]["type"], "api_error");
+        // 错误结束后不再生成 message_delta / message_stop
+        assert!(ctx.generate_final_events().is_empty());
+    }
+
+    #[test]
+    fn test_message_start_cache_compat_fields() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 10, false);
+        let usage = &ctx.create_message_start_event()["message"]["usage"];
+        assert!(usage.get("cache_read_input_tokens").is_none());
+
+        ctx.cache_compat_fields = true;
+        let usage = &ctx.create_message_start_event()["message"]["usage"];
+        assert_eq!(usage["input_tokens"], 10);
+        assert_eq!(usage["cache_creation_input_tokens"], 0);
+        assert_eq!(usage["cache_read_input_tokens"], 0);
+    }
+
+    #[test]
+    fn test_message_start_service_tier() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 10, false);
+        let usage = &ctx.create_message_start_event()["message"]["usage"];
+        assert!(usage.get("service_tier").is_none());
+
+        ctx.service_tier = Some(RequestPriority::Priority);
+        let usage = &ctx.create_message_start_event()["message"]["usage"];
+        assert_eq!(usage["service_tier"], "priority");
+    }
+
+    #[test]
+    fn test_annotation() {
+        let annotation = |mode: &str| {
+            crate::anthropic::annotation::Annotation::from_config(&crate::model::config::Config {
+                response_annotation: Some(mode.to_string()),
+                annotation_instance: Some("prod-eu".to_string()),
+                ..Default::default()
+            })
+            .map(Arc::new)
+        };
+
+        let mut ctx = StreamContext::new_with_thinking("test-model", 10, false);
+        ctx.annotation = annotation("metadata");
+        let message = &ctx.create_message_start_event()["message"];
+        assert_eq!(message["kiro_annotation"]["instance"], "prod-eu");
+        assert_eq!(message["kiro_annotation"]["pool"], "default");
+
+        // suffix：结束前在文本块末尾追加后缀
+        let mut ctx = StreamContext::new_with_thinking("test-model", 10, false);
+        ctx.annotation = annotation("suffix");
         assert!(
-            initial_events
-                .iter()
-                .any(|e| e.event == "content_block_start"
-                    && e.data["content_block"]["type"] == "text")
+            ctx.create_message_start_event()["message"]
+                .get("kiro_annotation")
+                .is_none()
+        );
+        let _ = ctx.generate_initial_events();
+        let events = ctx.generate_final_events();
+        assert_eq!(events[0].event, "content_block_delta");
+        assert_eq!(
+            events[0].data()["delta"]["text"],
+            "\n\n[served by prod-eu / default]"
         );
+    }
+
+    #[test]
+    fn test_text_delta_after_tool_use_restarts_text_block() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+
+        let initial_events = ctx.generate_initial_events();
+        assert!(initial_events.iter().any(
+            |e| e.event == "content_block_start" && e.data()["content_block"]["type"] == "text"
+        ));
 
         let initial_text_index = ctx
             .text_block_index
             .expect("initial text block index should exist");
 
         // tool_use 开始会自动关闭现有 text block
-        let tool_events = ctx.process_tool_use(&crate::kiro::model::events::ToolUseEvent {
+        let tool_events = ctx.process_tool_use(&ToolUseEvent {
             name: "test_tool".to_string(),
             tool_use_id: "tool_1".to_string(),
             input: "{}".to_string(),
@@ -987,7 +1467,7 @@ mod tests {
         assert!(
             tool_events.iter().any(|e| {
                 e.event == "content_block_stop"
-                    && e.data["index"].as_i64() == Some(initial_text_index as i64)
+                    && e.data()["index"].as_i64() == Some(initial_text_index as i64)
             }),
             "tool_use should stop the previous text block"
         );
@@ -995,8 +1475,8 @@ mod tests {
         // 之后再来文本增量，应自动创建新的 text block 而不是往已 stop 的块里写 delta
         let text_events = ctx.process_assistant_response("hello");
         let new_text_start_index = text_events.iter().find_map(|e| {
-            if e.event == "content_block_start" && e.data["content_block"]["type"] == "text" {
-                e.data["index"].as_i64()
+            if e.event == "content_block_start" && e.data()["content_block"]["type"] == "text" {
+                e.data()["index"].as_i64()
             } else {
                 None
             }
@@ -1013,8 +1493,8 @@ mod tests {
         assert!(
             text_events.iter().any(|e| {
                 e.event == "content_block_delta"
-                    && e.data["delta"]["type"] == "text_delta"
-                    && e.data["delta"]["text"] == "hello"
+                    && e.data()["delta"]["type"] == "text_delta"
+                    && e.data()["delta"]["text"] == "hello"
             }),
             "should emit text_delta after restarting text block"
         );
@@ -1040,7 +1520,7 @@ mod tests {
             "short prefix should still be buffered under thinking mode"
         );
 
-        let events = ctx.process_tool_use(&crate::kiro::model::events::ToolUseEvent {
+        let events = ctx.process_tool_use(&ToolUseEvent {
             name: "Write".to_string(),
             tool_use_id: "tool_1".to_string(),
             input: "{}".to_string(),
@@ -1048,22 +1528,22 @@ mod tests {
         });
 
         let text_start_index = events.iter().find_map(|e| {
-            if e.event == "content_block_start" && e.data["content_block"]["type"] == "text" {
-                e.data["index"].as_i64()
+            if e.event == "content_block_start" && e.data()["content_block"]["type"] == "text" {
+                e.data()["index"].as_i64()
             } else {
                 None
             }
         });
         let pos_text_delta = events.iter().position(|e| {
-            e.event == "content_block_delta" && e.data["delta"]["type"] == "text_delta"
+            e.event == "content_block_delta" && e.data()["delta"]["type"] == "text_delta"
         });
         let pos_text_stop = text_start_index.and_then(|idx| {
             events.iter().position(|e| {
-                e.event == "content_block_stop" && e.data["index"].as_i64() == Some(idx)
+                e.event == "content_block_stop" && e.data()["index"].as_i64() == Some(idx)
             })
         });
         let pos_tool_start = events.iter().position(|e| {
-            e.event == "content_block_start" && e.data["content_block"]["type"] == "tool_use"
+            e.event == "content_block_start" && e.data()["content_block"]["type"] == "tool_use"
         });
 
         assert!(
@@ -1092,13 +1572,114 @@ mod tests {
         assert!(
             events.iter().any(|e| {
                 e.event == "content_block_delta"
-                    && e.data["delta"]["type"] == "text_delta"
-                    && e.data["delta"]["text"] == "有修改："
+                    && e.data()["delta"]["type"] == "text_delta"
+                    && e.data()["delta"]["text"] == "有修改："
             }),
             "flushed text should equal the buffered prefix"
         );
     }
 
+    #[test]
+    fn test_json_deltas_hold_back_partial_tokens() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        ctx.json_deltas = true;
+        let _ = ctx.generate_initial_events();
+
+        let deltas = |events: &[SseEvent], field: &str| -> Vec<String> {
+            events
+                .iter()
+                .filter(|e| e.event == "content_block_delta")
+                .map(|e| e.data()["delta"][field].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let events = ctx.process_assistant_response(r#"{"n": 4"#);
+        assert_eq!(deltas(&events, "text"), vec![r#"{"n": "#]);
+        let events = ctx.process_assistant_response("2}");
+        assert_eq!(deltas(&events, "text"), vec!["42}"]);
+
+        let tool = |input: &str, stop: bool| ToolUseEvent {
+            name: "test_tool".to_string(),
+            tool_use_id: "tool_1".to_string(),
+            input: input.to_string(),
+            stop,
+        };
+        let events = ctx.process_tool_use(&tool(r#"{"ok": fa"#, false));
+        assert_eq!(deltas(&events, "partial_json"), vec![r#"{"ok": "#]);
+
+        // 工具块结束前输出剩余内容
+        let events = ctx.process_tool_use(&tool("lse}", true));
+        assert_eq!(deltas(&events, "partial_json"), vec!["false}"]);
+        assert_eq!(events.last().unwrap().event, "content_block_stop");
+
+        // 流结束时输出剩余文本
+        let _ = ctx.process_assistant_response("1");
+        let events = ctx.generate_final_events();
+        assert_eq!(deltas(&events, "text"), vec!["1"]);
+    }
+
+    #[test]
+    fn test_usage_reported_in_message_start_and_delta() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 42, false);
+        let initial = ctx.generate_initial_events();
+        assert_eq!(initial[0].data()["message"]["usage"]["input_tokens"], 42);
+
+        let _ = ctx.process_assistant_response(&"hello world ".repeat(20));
+        let _ = ctx.process_tool_use(&ToolUseEvent {
+            name: "test_tool".to_string(),
+            tool_use_id: "tool_1".to_string(),
+            input: r#"{"path": "src/main.rs"}"#.to_string(),
+            stop: true,
+        });
+        let expected_output = ctx.output_tokens;
+        assert!(expected_output > 1);
+
+        let events = ctx.generate_final_events();
+        let delta = events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .expect("message_delta should be emitted");
+        assert_eq!(delta.data()["usage"]["output_tokens"], expected_output);
+        assert_eq!(delta.data()["usage"]["input_tokens"], 42);
+    }
+
+    #[test]
+    fn test_output_budget_stops_with_distinct_reason() {
+        let text = |content: &str| {
+            Event::AssistantResponse(serde_json::from_value(json!({ "content": content })).unwrap())
+        };
+
+        // 未配置上限时不会终止
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        let _ = ctx.process_kiro_event(&text(&"a".repeat(10_000)));
+        assert!(ctx.check_output_budget().is_none());
+
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        ctx.output_budget = OutputBudget::new(100, 0);
+        let _ = ctx.generate_initial_events();
+        let _ = ctx.process_kiro_event(&text(&"a".repeat(60)));
+        assert!(ctx.check_output_budget().is_none());
+        let _ = ctx.process_kiro_event(&text(&"a".repeat(60)));
+        assert!(ctx.check_output_budget().is_some());
+
+        let events = ctx.generate_final_events();
+        let delta = events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .expect("message_delta should be emitted");
+        assert_eq!(
+            delta.data()["delta"]["stop_reason"],
+            OUTPUT_LIMIT_STOP_REASON
+        );
+
+        // tokens 上限同样生效（工具参数也计入）
+        let mut budget = OutputBudget::new(0, 10);
+        budget.record(&"word ".repeat(4));
+        assert!(budget.exceeded().is_none());
+        budget.record(&"word ".repeat(20));
+        assert!(budget.exceeded().unwrap().starts_with("tokens"));
+    }
+
     #[test]
     fn test_estimate_tokens() {
         assert!(estimate_tokens("Hello") > 0);
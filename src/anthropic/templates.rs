@@ -0,0 +1,80 @@
+//! 提示词模板展开
+//!
+//! 客户端通过扩展字段 `prompt_template` 引用服务端保存的模板，
+//! 避免每次请求重复发送相同的大段 system 提示词
+
+use std::sync::Arc;
+
+use crate::kiro::db::Database;
+
+use super::types::{PromptTemplateRef, SystemMessage};
+
+/// 展开模板引用，并将结果插入到 system 开头
+///
+/// 返回面向客户端的错误信息（模板不存在或缺少变量）
+pub async fn apply_prompt_template(
+    database: &Arc<Database>,
+    reference: &PromptTemplateRef,
+    system: &mut Option<Vec<SystemMessage>>,
+) -> Result<(), String> {
+    let name = reference.name.clone();
+    let template = database
+        .call(move |db| db.get_prompt_template(&name))
+        .await
+        .map_err(|e| {
+            tracing::error!("读取提示词模板失败: {}", e);
+            format!("读取提示词模板失败: {}", reference.name)
+        })?
+        .ok_or_else(|| format!("提示词模板不存在: {}", reference.name))?;
+
+    let text = template.render(&reference.variables)?;
+    system
+        .get_or_insert_with(Vec::new)
+        .insert(0, SystemMessage { text });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::prompt_template::PromptTemplate;
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_apply_prompt_template() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path().join("test.db")).unwrap();
+        db.upsert_prompt_template(&PromptTemplate {
+            name: "agent".to_string(),
+            content: "You are {{role}}.".to_string(),
+            description: None,
+            updated_at: chrono::Utc::now(),
+        })
+        .unwrap();
+
+        let reference = PromptTemplateRef {
+            name: "agent".to_string(),
+            variables: HashMap::from([("role".to_string(), "a reviewer".to_string())]),
+        };
+        let mut system = Some(vec![SystemMessage {
+            text: "Be brief.".to_string(),
+        }]);
+        apply_prompt_template(&db, &reference, &mut system)
+            .await
+            .unwrap();
+
+        let texts: Vec<_> = system.unwrap().into_iter().map(|s| s.text).collect();
+        assert_eq!(texts, vec!["You are a reviewer.", "Be brief."]);
+
+        let missing = PromptTemplateRef {
+            name: "nope".to_string(),
+            variables: HashMap::new(),
+        };
+        assert!(
+            apply_prompt_template(&db, &missing, &mut None)
+                .await
+                .is_err()
+        );
+    }
+}
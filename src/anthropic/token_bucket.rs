@@ -0,0 +1,356 @@
+//! 按 API Key 的令牌桶限流
+//!
+//! 每个客户端 Key 两个令牌桶：
+//! - 请求桶：容量为每分钟请求数，按速率持续补充，每个请求消耗 1 个
+//! - token 桶：容量为每分钟 tokens，请求结束后按实际用量（输入 + 输出）扣除，
+//!   允许扣成负数（单个大请求不会被拒绝），余额不为正时拒绝新请求直到补充回来
+//!
+//! 令牌桶允许短时突发（最多一分钟的额度），长期速率不超过配置值。
+//! 通过 Admin API 签发的客户端 Key 自身的 `rateLimitPerMinute` 同样由请求桶限制（与配置取较小值）
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use parking_lot::Mutex;
+
+use crate::common::auth::key_fingerprint;
+use crate::model::config::Config;
+
+/// 被拒绝的限流维度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    /// 每分钟请求数
+    Requests,
+    /// 每分钟 tokens
+    Tokens,
+}
+
+/// 超出限流时的信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimited {
+    pub kind: LimitKind,
+    /// 每分钟限额
+    pub limit: u64,
+    /// 建议的重试等待时间（秒，至少 1）
+    pub retry_after: u64,
+}
+
+/// 令牌桶
+#[derive(Debug)]
+struct Bucket {
+    /// 容量（每分钟限额）
+    capacity: f64,
+    /// 当前余额（token 桶可为负）
+    balance: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(capacity: u64, now: Instant) -> Self {
+        Self {
+            capacity: capacity as f64,
+            balance: capacity as f64,
+            updated: now,
+        }
+    }
+
+    /// 每秒补充量
+    fn rate(&self) -> f64 {
+        self.capacity / 60.0
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.balance = (self.balance + elapsed * self.rate()).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// 余额补充到 `target` 所需的秒数（向上取整，至少 1）
+    fn secs_until(&self, target: f64) -> u64 {
+        (((target - self.balance) / self.rate()).ceil() as u64).max(1)
+    }
+}
+
+/// 单个 Key 的限额（0 表示不限制）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Limits {
+    requests_per_minute: u32,
+    tokens_per_minute: u64,
+}
+
+impl Limits {
+    /// 叠加客户端 Key 自身的每分钟请求数限制（与配置同时存在时取较小值）
+    fn with_key_limit(mut self, key_limit: Option<u32>) -> Self {
+        if let Some(limit) = key_limit.filter(|limit| *limit > 0) {
+            self.requests_per_minute = match self.requests_per_minute {
+                0 => limit,
+                configured => configured.min(limit),
+            };
+        }
+        self
+    }
+
+    fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// 单个 Key 的令牌桶
+struct KeyBuckets {
+    /// 创建令牌桶时的限额（限额变化后重建）
+    limits: Limits,
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+}
+
+impl KeyBuckets {
+    fn new(limits: Limits, now: Instant) -> Self {
+        Self {
+            limits,
+            requests: (limits.requests_per_minute > 0)
+                .then(|| Bucket::new(limits.requests_per_minute as u64, now)),
+            tokens: (limits.tokens_per_minute > 0)
+                .then(|| Bucket::new(limits.tokens_per_minute, now)),
+        }
+    }
+}
+
+/// 按 API Key 的令牌桶限流器
+pub struct KeyTokenBuckets {
+    /// 默认限额
+    defaults: Limits,
+    /// 按 Key 指纹覆盖的限额
+    overrides: HashMap<String, Limits>,
+    /// Key 指纹 -> 令牌桶
+    buckets: Mutex<HashMap<String, KeyBuckets>>,
+}
+
+impl KeyTokenBuckets {
+    /// 从配置创建（未配置任何限额时只限制设置了 `rateLimitPerMinute` 的客户端 Key）
+    pub fn from_config(config: &Config) -> Self {
+        let defaults = Limits {
+            requests_per_minute: config.requests_per_minute_per_key,
+            tokens_per_minute: config.tokens_per_minute_per_key,
+        };
+        let overrides: HashMap<_, _> = config
+            .rate_limits_by_key
+            .iter()
+            .map(|(key, limit)| {
+                let limits = Limits {
+                    requests_per_minute: limit
+                        .requests_per_minute
+                        .unwrap_or(defaults.requests_per_minute),
+                    tokens_per_minute: limit
+                        .tokens_per_minute
+                        .unwrap_or(defaults.tokens_per_minute),
+                };
+                (key_fingerprint(key), limits)
+            })
+            .collect();
+
+        Self {
+            defaults,
+            overrides,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn limits(&self, fingerprint: &str) -> Limits {
+        self.overrides
+            .get(fingerprint)
+            .copied()
+            .unwrap_or(self.defaults)
+    }
+
+    /// 在请求开始时检查限额并消耗一个请求令牌
+    ///
+    /// `key_limit` 为客户端 Key 自身的每分钟请求数限制（使用配置文件中的 `apiKey` 时为 None）。
+    /// token 桶余额不为正或请求桶不足 1 个令牌时拒绝（被拒绝的请求不消耗令牌）
+    pub fn check(
+        &self,
+        api_key: &str,
+        key_limit: Option<u32>,
+        now: Instant,
+    ) -> Result<(), RateLimited> {
+        let fingerprint = key_fingerprint(api_key);
+        let limits = self.limits(&fingerprint).with_key_limit(key_limit);
+        let mut buckets = self.buckets.lock();
+        if limits.is_unlimited() {
+            buckets.remove(&fingerprint);
+            return Ok(());
+        }
+        let entry = buckets
+            .entry(fingerprint)
+            .or_insert_with(|| KeyBuckets::new(limits, now));
+        // 限额修改后（如通过 Admin API 修改客户端 Key）按新限额重新计算
+        if entry.limits != limits {
+            *entry = KeyBuckets::new(limits, now);
+        }
+
+        if let Some(tokens) = entry.tokens.as_mut() {
+            tokens.refill(now);
+            if tokens.balance <= 0.0 {
+                return Err(RateLimited {
+                    kind: LimitKind::Tokens,
+                    limit: limits.tokens_per_minute,
+                    // 余额恢复到至少 1 个 token
+                    retry_after: tokens.secs_until(1.0),
+                });
+            }
+        }
+        if let Some(requests) = entry.requests.as_mut() {
+            requests.refill(now);
+            if requests.balance < 1.0 {
+                return Err(RateLimited {
+                    kind: LimitKind::Requests,
+                    limit: limits.requests_per_minute as u64,
+                    retry_after: requests.secs_until(1.0),
+                });
+            }
+            requests.balance -= 1.0;
+        }
+        Ok(())
+    }
+
+    /// 请求结束后按实际用量扣除 tokens
+    pub fn charge_tokens(&self, api_key: &str, tokens: u64, now: Instant) {
+        let mut buckets = self.buckets.lock();
+        if let Some(bucket) = buckets
+            .get_mut(&key_fingerprint(api_key))
+            .and_then(|entry| entry.tokens.as_mut())
+        {
+            bucket.refill(now);
+            bucket.balance -= tokens as f64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::model::config::KeyRateLimit;
+
+    fn limiter(requests_per_minute: u32, tokens_per_minute: u64) -> KeyTokenBuckets {
+        KeyTokenBuckets::from_config(&Config {
+            requests_per_minute_per_key: requests_per_minute,
+            tokens_per_minute_per_key: tokens_per_minute,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_unlimited_without_limits() {
+        let limiter = KeyTokenBuckets::from_config(&Config::default());
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert!(limiter.check("key-a", None, now).is_ok());
+        }
+        assert!(limiter.buckets.lock().is_empty());
+    }
+
+    #[test]
+    fn test_client_key_rate_limit() {
+        let now = Instant::now();
+
+        // 未配置 requestsPerMinutePerKey 时按客户端 Key 自身的限制
+        let key_only = limiter(0, 0);
+        assert!(key_only.check("sk-kiro-a", Some(2), now).is_ok());
+        assert!(key_only.check("sk-kiro-a", Some(2), now).is_ok());
+        let limited = key_only.check("sk-kiro-a", Some(2), now).unwrap_err();
+        assert_eq!(limited.kind, LimitKind::Requests);
+        assert_eq!(limited.limit, 2);
+        assert_eq!(limited.retry_after, 30);
+
+        // 与配置同时存在时取较小值
+        let combined = limiter(60, 0);
+        assert!(combined.check("sk-kiro-b", Some(1), now).is_ok());
+        assert_eq!(
+            combined.check("sk-kiro-b", Some(1), now).unwrap_err().limit,
+            1
+        );
+        // 限制修改后按新限额重新计算
+        assert!(combined.check("sk-kiro-b", Some(10), now).is_ok());
+        assert!(combined.check("sk-kiro-b", Some(100), now).is_ok());
+    }
+
+    #[test]
+    fn test_requests_bucket_refills() {
+        let limiter = limiter(60, 0);
+        let now = Instant::now();
+        for _ in 0..60 {
+            assert!(limiter.check("key-a", None, now).is_ok());
+        }
+        assert_eq!(
+            limiter.check("key-a", None, now),
+            Err(RateLimited {
+                kind: LimitKind::Requests,
+                limit: 60,
+                retry_after: 1,
+            })
+        );
+        // 其他 Key 不受影响
+        assert!(limiter.check("key-b", None, now).is_ok());
+        // 每秒补充 1 个
+        assert!(
+            limiter
+                .check("key-a", None, now + Duration::from_secs(1))
+                .is_ok()
+        );
+        assert!(
+            limiter
+                .check("key-a", None, now + Duration::from_secs(1))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_tokens_charged_after_request() {
+        let limiter = limiter(0, 6000);
+        let now = Instant::now();
+        assert!(limiter.check("key-a", None, now).is_ok());
+        // 单个大请求允许扣成负数
+        limiter.charge_tokens("key-a", 7000, now);
+        let limited = limiter.check("key-a", None, now).unwrap_err();
+        assert_eq!(limited.kind, LimitKind::Tokens);
+        assert_eq!(limited.limit, 6000);
+        // 欠 1000 个，每秒补充 100 个，恢复到 1 个需要 11 秒
+        assert_eq!(limited.retry_after, 11);
+        assert!(
+            limiter
+                .check("key-a", None, now + Duration::from_secs(11))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_per_key_overrides() {
+        let mut config = Config {
+            requests_per_minute_per_key: 1,
+            ..Default::default()
+        };
+        config.rate_limits_by_key.insert(
+            "sk-batch".to_string(),
+            KeyRateLimit {
+                requests_per_minute: Some(0),
+                tokens_per_minute: Some(100),
+            },
+        );
+        let limiter = KeyTokenBuckets::from_config(&config);
+        let now = Instant::now();
+
+        assert!(limiter.check("sk-other", None, now).is_ok());
+        assert!(limiter.check("sk-other", None, now).is_err());
+
+        // 覆盖后不限请求数，只限 tokens
+        for _ in 0..5 {
+            assert!(limiter.check("sk-batch", None, now).is_ok());
+        }
+        limiter.charge_tokens("sk-batch", 100, now);
+        assert_eq!(
+            limiter.check("sk-batch", None, now).unwrap_err().kind,
+            LimitKind::Tokens
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! 工具定义校验
+//!
+//! 在转发上游之前校验客户端提供的工具名称与 `input_schema`（JSON Schema 结构、
+//! 嵌套深度与大小），无效时立即返回指明字段路径的 400 错误，
+//! 而不是等待上游数秒后返回难以定位的错误
+
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+use super::converter::is_unsupported_tool;
+use super::types::Tool;
+
+/// 工具名称最大长度
+const MAX_NAME_LEN: usize = 64;
+
+/// 单个工具 input_schema 序列化后的最大字节数
+const MAX_SCHEMA_BYTES: usize = 64 * 1024;
+
+/// 全部工具 input_schema 序列化后的最大字节数
+const MAX_TOTAL_SCHEMA_BYTES: usize = 512 * 1024;
+
+/// input_schema 的最大嵌套深度（按子 schema 计）
+const MAX_SCHEMA_DEPTH: usize = 32;
+
+/// JSON Schema 允许的类型
+const SCHEMA_TYPES: &[&str] = &[
+    "object", "array", "string", "number", "integer", "boolean", "null",
+];
+
+/// 值为单个子 schema 的关键字
+const SCHEMA_KEYWORDS: &[&str] = &[
+    "additionalProperties",
+    "additionalItems",
+    "unevaluatedProperties",
+    "unevaluatedItems",
+    "propertyNames",
+    "contains",
+    "not",
+    "if",
+    "then",
+    "else",
+];
+
+/// 值为子 schema 数组的关键字
+const SCHEMA_ARRAY_KEYWORDS: &[&str] = &["anyOf", "oneOf", "allOf", "prefixItems"];
+
+/// 值为 名称 -> 子 schema 映射的关键字
+const SCHEMA_MAP_KEYWORDS: &[&str] = &["properties", "patternProperties", "$defs", "definitions"];
+
+/// 校验请求中的工具定义，返回第一个错误（错误信息以字段路径开头）
+pub fn validate_tools(tools: &[Tool]) -> Result<(), String> {
+    let mut names = HashSet::new();
+    let mut total_bytes = 0;
+
+    for (i, tool) in tools.iter().enumerate() {
+        let path = format!("tools.{}", i);
+        validate_name(&tool.name).map_err(|e| format!("{}.name: {}", path, e))?;
+        if !names.insert(tool.name.as_str()) {
+            return Err(format!(
+                "{}.name: Tool names must be unique, '{}' is duplicated",
+                path, tool.name
+            ));
+        }
+        // 不支持的工具（如 web_search）转换时会被丢弃，无需校验
+        if is_unsupported_tool(&tool.name) {
+            continue;
+        }
+
+        let path = format!("{}.input_schema", path);
+        let bytes = serde_json::to_vec(&tool.input_schema)
+            .map(|b| b.len())
+            .unwrap_or_default();
+        if bytes > MAX_SCHEMA_BYTES {
+            return Err(format!(
+                "{}: Schema is too large ({} bytes, limit {} bytes)",
+                path, bytes, MAX_SCHEMA_BYTES
+            ));
+        }
+        total_bytes += bytes;
+        if total_bytes > MAX_TOTAL_SCHEMA_BYTES {
+            return Err(format!(
+                "tools: Tool schemas are too large in total (limit {} bytes)",
+                MAX_TOTAL_SCHEMA_BYTES
+            ));
+        }
+
+        match tool.input_schema.get("type") {
+            Some(Value::String(t)) if t == "object" => {}
+            Some(other) => {
+                return Err(format!(
+                    "{}.type: Input schema type must be 'object', got {}",
+                    path, other
+                ));
+            }
+            None => return Err(format!("{}.type: Field required", path)),
+        }
+        for (key, value) in &tool.input_schema {
+            validate_keyword(key, value, &format!("{}.{}", path, key), 1)?;
+        }
+    }
+    Ok(())
+}
+
+/// 工具名称需匹配 `^[a-zA-Z0-9_-]{1,64}$`
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(format!(
+            "Tool name must be 1-{} characters, got {}",
+            MAX_NAME_LEN,
+            name.len()
+        ));
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '_' || *c == '-'))
+    {
+        return Err(format!(
+            "String should match pattern '^[a-zA-Z0-9_-]{{1,{}}}$', found invalid character '{}'",
+            MAX_NAME_LEN, c
+        ));
+    }
+    Ok(())
+}
+
+/// 校验子 schema（对象或布尔值）
+fn validate_schema(schema: &Value, path: &str, depth: usize) -> Result<(), String> {
+    if depth > MAX_SCHEMA_DEPTH {
+        return Err(format!(
+            "{}: Schema is nested too deeply (limit {} levels)",
+            path, MAX_SCHEMA_DEPTH
+        ));
+    }
+    match schema {
+        Value::Bool(_) => Ok(()),
+        Value::Object(map) => {
+            for (key, value) in map {
+                validate_keyword(key, value, &format!("{}.{}", path, key), depth)?;
+            }
+            Ok(())
+        }
+        other => Err(format!(
+            "{}: Schema must be an object or boolean, got {}",
+            path,
+            type_name(other)
+        )),
+    }
+}
+
+/// 校验单个关键字的值；未知关键字不做限制
+fn validate_keyword(key: &str, value: &Value, path: &str, depth: usize) -> Result<(), String> {
+    match key {
+        "type" => validate_type(value, path),
+        "required" => match value {
+            Value::Array(items) if items.iter().all(Value::is_string) => Ok(()),
+            _ => Err(format!("{}: Must be an array of strings", path)),
+        },
+        "enum" => match value {
+            Value::Array(items) if !items.is_empty() => Ok(()),
+            _ => Err(format!("{}: Must be a non-empty array", path)),
+        },
+        "$ref" => match value {
+            Value::String(_) => Ok(()),
+            _ => Err(format!("{}: Must be a string", path)),
+        },
+        "items" => match value {
+            // 旧版 JSON Schema 允许 items 为 schema 数组
+            Value::Array(items) => validate_schema_array(items, path, depth),
+            _ => validate_schema(value, path, depth + 1),
+        },
+        _ if SCHEMA_KEYWORDS.contains(&key) => validate_schema(value, path, depth + 1),
+        _ if SCHEMA_ARRAY_KEYWORDS.contains(&key) => match value {
+            Value::Array(items) if !items.is_empty() => validate_schema_array(items, path, depth),
+            _ => Err(format!("{}: Must be a non-empty array of schemas", path)),
+        },
+        _ if SCHEMA_MAP_KEYWORDS.contains(&key) => match value {
+            Value::Object(map) => {
+                for (name, schema) in map {
+                    validate_schema(schema, &format!("{}.{}", path, name), depth + 1)?;
+                }
+                Ok(())
+            }
+            other => Err(format!(
+                "{}: Must be an object, got {}",
+                path,
+                type_name(other)
+            )),
+        },
+        _ => Ok(()),
+    }
+}
+
+fn validate_schema_array(items: &[Value], path: &str, depth: usize) -> Result<(), String> {
+    for (i, schema) in items.iter().enumerate() {
+        validate_schema(schema, &format!("{}.{}", path, i), depth + 1)?;
+    }
+    Ok(())
+}
+
+/// `type` 为类型名或非空的类型名数组
+fn validate_type(value: &Value, path: &str) -> Result<(), String> {
+    let valid = |t: &Value| t.as_str().is_some_and(|t| SCHEMA_TYPES.contains(&t));
+    match value {
+        Value::String(_) if valid(value) => Ok(()),
+        Value::Array(types) if !types.is_empty() && types.iter().all(valid) => Ok(()),
+        _ => Err(format!(
+            "{}: Must be one of {} (or an array of them), got {}",
+            path,
+            SCHEMA_TYPES.join(", "),
+            value
+        )),
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tool(name: &str, schema: Value) -> Tool {
+        Tool {
+            name: name.to_string(),
+            description: String::new(),
+            input_schema: serde_json::from_value(schema).unwrap(),
+        }
+    }
+
+    fn validate(schema: Value) -> Result<(), String> {
+        validate_tools(&[tool("search", schema)])
+    }
+
+    #[test]
+    fn test_valid_schemas() {
+        assert!(validate(json!({"type": "object"})).is_ok());
+        assert!(
+            validate(json!({
+                "type": "object",
+                "properties": {
+                    "query": {"type": "string", "description": "x"},
+                    "limit": {"type": ["integer", "null"]},
+                    "tags": {"type": "array", "items": {"type": "string", "enum": ["a"]}},
+                    "filter": {"anyOf": [{"$ref": "#/$defs/f"}, true]}
+                },
+                "required": ["query"],
+                "additionalProperties": false,
+                "$defs": {"f": {"type": "object"}},
+                "x-custom": 1
+            }))
+            .is_ok()
+        );
+        // 不支持的工具会被丢弃，不校验 schema
+        assert!(validate_tools(&[tool("web_search", json!({}))]).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_schemas_report_path() {
+        let err = |schema| validate(schema).unwrap_err();
+
+        assert_eq!(
+            err(json!({"properties": {}})),
+            "tools.0.input_schema.type: Field required"
+        );
+        assert!(err(json!({"type": "array"})).starts_with("tools.0.input_schema.type: "));
+        assert!(
+            err(json!({"type": "object", "properties": {"q": {"type": "strng"}}}))
+                .starts_with("tools.0.input_schema.properties.q.type: ")
+        );
+        assert_eq!(
+            err(json!({"type": "object", "properties": {"q": "string"}})),
+            "tools.0.input_schema.properties.q: Schema must be an object or boolean, got string"
+        );
+        assert_eq!(
+            err(json!({"type": "object", "required": "q"})),
+            "tools.0.input_schema.required: Must be an array of strings"
+        );
+        assert_eq!(
+            err(json!({"type": "object", "properties": {"a": {"anyOf": [{}, 1]}}})),
+            "tools.0.input_schema.properties.a.anyOf.1: Schema must be an object or boolean, got number"
+        );
+    }
+
+    #[test]
+    fn test_names() {
+        let schema = json!({"type": "object"});
+        assert!(validate_tools(&[tool("get-weather_2", schema.clone())]).is_ok());
+        assert!(
+            validate_tools(&[tool("get weather", schema.clone())])
+                .unwrap_err()
+                .starts_with("tools.0.name: ")
+        );
+        assert!(validate_tools(&[tool(&"a".repeat(65), schema.clone())]).is_err());
+        assert!(
+            validate_tools(&[tool("a", schema.clone()), tool("a", schema)])
+                .unwrap_err()
+                .starts_with("tools.1.name: Tool names must be unique")
+        );
+    }
+
+    #[test]
+    fn test_size_limits() {
+        let mut nested = json!({"type": "string"});
+        for _ in 0..MAX_SCHEMA_DEPTH {
+            nested = json!({"type": "array", "items": nested});
+        }
+        let err = validate(json!({"type": "object", "properties": {"a": nested}})).unwrap_err();
+        assert!(err.contains("nested too deeply"), "{}", err);
+
+        let large = json!({
+            "type": "object",
+            "description": "x".repeat(MAX_SCHEMA_BYTES),
+        });
+        let err = validate(large).unwrap_err();
+        assert!(
+            err.starts_with("tools.0.input_schema: Schema is too large"),
+            "{}",
+            err
+        );
+    }
+}
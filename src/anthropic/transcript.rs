@@ -0,0 +1,333 @@
+//! 对话记录：请求内容与响应体的旁路复制
+//!
+//! 响应体按原样发送给客户端，同时逐块解析：流式响应按 SSE 事件重建内容块，
+//! 非流式响应读取完整 JSON。响应体发送完毕或客户端断开时写入对话记录（断开时为已发送的部分）
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::body::Body;
+use axum::http::header;
+use axum::response::Response;
+use futures::StreamExt;
+use serde_json::{Value, json};
+
+use crate::kiro::model::transcript::Transcript;
+use crate::kiro::transcript::TranscriptStore;
+
+use super::types::MessagesRequest;
+
+/// 非流式响应体的最大缓冲字节数（超出后不再记录响应内容）
+const MAX_BODY_BYTES: usize = 8 * 1024 * 1024;
+
+/// 提取请求中需要记录的内容（system、messages 与工具名称）
+pub(super) fn capture_request(payload: &MessagesRequest) -> Value {
+    let mut request = json!({
+        "maxTokens": payload.max_tokens,
+        "messages": payload.messages,
+    });
+    if let Some(system) = &payload.system {
+        request["system"] = json!(system);
+    }
+    if let Some(tools) = &payload.tools {
+        request["tools"] = json!(tools.iter().map(|t| &t.name).collect::<Vec<_>>());
+    }
+    if let Some(template) = &payload.prompt_template {
+        request["promptTemplate"] = json!(template.name);
+    }
+    request
+}
+
+/// 由流式 SSE 事件重建的响应内容
+#[derive(Default)]
+struct StreamAssembler {
+    /// 未处理完的行
+    line: Vec<u8>,
+    blocks: Vec<Value>,
+    /// tool_use 块的参数 JSON 片段（与 blocks 下标对应）
+    tool_inputs: Vec<String>,
+    stop_reason: Option<String>,
+    input_tokens: Option<u64>,
+    output_tokens: Option<u64>,
+    error: Option<Value>,
+}
+
+impl StreamAssembler {
+    fn feed(&mut self, chunk: &[u8]) {
+        for &byte in chunk {
+            if byte == b'\n' {
+                let line = std::mem::take(&mut self.line);
+                if let Some(data) = line.strip_prefix(b"data:")
+                    && let Ok(event) = serde_json::from_slice::<Value>(data.trim_ascii())
+                {
+                    self.handle(&event);
+                }
+            } else {
+                self.line.push(byte);
+            }
+        }
+    }
+
+    fn handle(&mut self, event: &Value) {
+        let index = event["index"].as_u64().map(|i| i as usize);
+        match event["type"].as_str() {
+            Some("message_start") => {
+                self.input_tokens = event["message"]["usage"]["input_tokens"].as_u64();
+            }
+            Some("content_block_start") => {
+                let Some(index) = index else { return };
+                if self.blocks.len() <= index {
+                    self.blocks.resize(index + 1, Value::Null);
+                    self.tool_inputs.resize(index + 1, String::new());
+                }
+                self.blocks[index] = event["content_block"].clone();
+            }
+            Some("content_block_delta") => {
+                let Some(block) = index.and_then(|i| self.blocks.get_mut(i)) else {
+                    return;
+                };
+                let delta = &event["delta"];
+                let append = |block: &mut Value, field: &str, text: &str| {
+                    let existing = block[field].as_str().unwrap_or_default();
+                    block[field] = Value::String(format!("{}{}", existing, text));
+                };
+                match delta["type"].as_str() {
+                    Some("text_delta") => {
+                        append(block, "text", delta["text"].as_str().unwrap_or_default())
+                    }
+                    Some("thinking_delta") => append(
+                        block,
+                        "thinking",
+                        delta["thinking"].as_str().unwrap_or_default(),
+                    ),
+                    Some("input_json_delta") => self.tool_inputs[index.unwrap()]
+                        .push_str(delta["partial_json"].as_str().unwrap_or_default()),
+                    _ => {}
+                }
+            }
+            Some("message_delta") => {
+                if let Some(reason) = event["delta"]["stop_reason"].as_str() {
+                    self.stop_reason = Some(reason.to_string());
+                }
+                if let Some(tokens) = event["usage"]["output_tokens"].as_u64() {
+                    self.output_tokens = Some(tokens);
+                }
+            }
+            Some("error") => self.error = Some(event.clone()),
+            _ => {}
+        }
+    }
+
+    /// 输出内容块数组（工具参数解析为 JSON，无法解析时保留原文）
+    fn finish(mut self, transcript: &mut Transcript) {
+        for (block, input) in self.blocks.iter_mut().zip(&self.tool_inputs) {
+            if block["type"] == "tool_use" && !input.is_empty() {
+                block["input"] = serde_json::from_str(input).unwrap_or(json!(input));
+            }
+        }
+        self.blocks.retain(|b| !b.is_null());
+        transcript.response = match self.error {
+            Some(error) => json!({ "content": self.blocks, "error": error["error"] }),
+            None => Value::Array(self.blocks),
+        };
+        transcript.stop_reason = self.stop_reason;
+        transcript.input_tokens = self.input_tokens;
+        transcript.output_tokens = self.output_tokens;
+    }
+}
+
+/// 响应内容的解析方式
+enum Capture {
+    Stream(StreamAssembler),
+    /// 非流式响应或错误响应：缓冲完整响应体（超出上限时为 None）
+    Body(Option<Vec<u8>>),
+}
+
+/// 响应体发送完毕或被丢弃时写入对话记录
+struct Pending {
+    store: Arc<TranscriptStore>,
+    transcript: Option<Transcript>,
+    capture: Capture,
+    started: Instant,
+}
+
+impl Pending {
+    fn feed(&mut self, chunk: &[u8]) {
+        match &mut self.capture {
+            Capture::Stream(assembler) => assembler.feed(chunk),
+            Capture::Body(buffer) => {
+                if let Some(bytes) = buffer {
+                    if bytes.len() + chunk.len() > MAX_BODY_BYTES {
+                        *buffer = None;
+                    } else {
+                        bytes.extend_from_slice(chunk);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        let Some(mut transcript) = self.transcript.take() else {
+            return;
+        };
+        transcript.latency_ms = self.started.elapsed().as_millis() as u64;
+        match std::mem::replace(&mut self.capture, Capture::Body(None)) {
+            Capture::Stream(assembler) => assembler.finish(&mut transcript),
+            Capture::Body(Some(bytes)) => {
+                let body: Value = serde_json::from_slice(&bytes)
+                    .unwrap_or_else(|_| json!(String::from_utf8_lossy(&bytes)));
+                transcript.stop_reason = body["stop_reason"].as_str().map(str::to_string);
+                transcript.input_tokens = body["usage"]["input_tokens"].as_u64();
+                transcript.output_tokens = body["usage"]["output_tokens"].as_u64();
+                transcript.response = match body.get("content") {
+                    Some(content) if transcript.status < 400 => content.clone(),
+                    _ => body,
+                };
+            }
+            Capture::Body(None) => {}
+        }
+        self.store.sanitize(&mut transcript.request);
+        self.store.sanitize(&mut transcript.response);
+
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let store = self.store.clone();
+        runtime.spawn(async move { store.write(transcript).await });
+    }
+}
+
+/// 旁路复制响应体，发送完毕后写入对话记录
+///
+/// `transcript` 需已填写请求信息；状态码与响应内容由本函数填写
+pub(super) fn tee(
+    store: Arc<TranscriptStore>,
+    mut transcript: Transcript,
+    started: Instant,
+    response: Response,
+) -> Response {
+    let (parts, body) = response.into_parts();
+    transcript.status = parts.status.as_u16();
+    let is_sse = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    let mut pending = Pending {
+        store,
+        transcript: Some(transcript),
+        capture: if is_sse {
+            Capture::Stream(StreamAssembler::default())
+        } else {
+            Capture::Body(Some(Vec::new()))
+        },
+        started,
+    };
+
+    let stream = body.into_data_stream().map(move |chunk| {
+        // 捕获整个 pending，使其随响应体一起释放
+        let pending = &mut pending;
+        if let Ok(data) = &chunk {
+            pending.feed(data);
+        }
+        chunk
+    });
+    axum::response::Response::from_parts(parts, Body::from_stream(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::db::Database;
+    use crate::kiro::model::transcript::TranscriptFilter;
+    use crate::model::config::Config;
+
+    fn transcript() -> Transcript {
+        Transcript {
+            id: None,
+            created_at: chrono::Utc::now(),
+            request_id: None,
+            model: "claude-sonnet-4".to_string(),
+            credential_id: Some(1),
+            client_key: None,
+            stream: true,
+            status: 0,
+            latency_ms: 0,
+            request: json!({"messages": [{"role": "user", "content": "token sk-abcdefghijklmnopqrstuvwxyz"}]}),
+            response: Value::Null,
+            stop_reason: None,
+            input_tokens: None,
+            output_tokens: None,
+        }
+    }
+
+    async fn recorded(database: &Arc<Database>) -> Transcript {
+        let filter = TranscriptFilter {
+            limit: 1,
+            ..Default::default()
+        };
+        for _ in 0..100 {
+            if let Some(t) = database.list_transcripts(&filter).unwrap().1.pop() {
+                return t;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("未写入对话记录");
+    }
+
+    #[tokio::test]
+    async fn test_tee_stream_response() {
+        let database = Database::open_in_memory().unwrap();
+        let config = Config {
+            transcript_store: Some("sqlite".to_string()),
+            ..Config::default()
+        };
+        let store = Arc::new(
+            TranscriptStore::from_config(&config, database.clone())
+                .unwrap()
+                .unwrap(),
+        );
+
+        let events = [
+            r#"{"type":"message_start","message":{"usage":{"input_tokens":12}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hel"}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"lo"}}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"t1","name":"search","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"q\":"}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"\"rust\"}"}}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":7}}"#,
+        ];
+        let sse: String = events
+            .iter()
+            .map(|e| format!("event: x\ndata: {}\n\n", e))
+            .collect();
+        // 按任意位置切分，验证跨块的行拼接
+        let (a, b) = sse.split_at(sse.len() / 3);
+        let body = Body::from_stream(futures::stream::iter([
+            Ok::<_, std::convert::Infallible>(a.to_string()),
+            Ok(b.to_string()),
+        ]));
+        let response = Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .body(body)
+            .unwrap();
+
+        let response = tee(store, transcript(), Instant::now(), response);
+        let sent = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(sent, sse.as_bytes());
+
+        let t = recorded(&database).await;
+        assert_eq!(t.status, 200);
+        assert_eq!(t.stop_reason.as_deref(), Some("tool_use"));
+        assert_eq!((t.input_tokens, t.output_tokens), (Some(12), Some(7)));
+        assert_eq!(t.response[0]["text"], "Hello");
+        assert_eq!(t.response[1]["input"], json!({"q": "rust"}));
+        assert_eq!(t.request["messages"][0]["content"], "token sk-[REDACTED]");
+    }
+}
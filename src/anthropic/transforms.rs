@@ -0,0 +1,113 @@
+//! 请求转换调试回显
+//!
+//! 客户端携带 `x-kiro-debug: transforms` 时，响应头 `x-kiro-transforms` 列出本次请求实际应用的转换
+//! （模型改写与映射、思考预算限制、system 注入、消息合并等）；流式响应在结束时额外追加一条
+//! SSE 注释，包含响应开始后才发生的转换（输出截断、空闲超时结束）。
+//! 便于客户端在无法查看服务端日志时排查异常行为；仅 `/v1` 下通过认证的请求可用
+
+use std::sync::Arc;
+
+use axum::http::{HeaderMap, HeaderValue};
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+/// 调试请求头
+pub const DEBUG_HEADER: &str = "x-kiro-debug";
+
+/// 转换摘要响应头
+pub const TRANSFORMS_HEADER: &str = "x-kiro-transforms";
+
+/// 单次请求应用的转换记录（克隆后共享同一份记录）
+#[derive(Debug, Clone, Default)]
+pub struct TransformLog {
+    entries: Arc<Mutex<Vec<String>>>,
+}
+
+impl TransformLog {
+    /// 请求头要求回显转换时创建记录
+    ///
+    /// `x-kiro-debug` 支持逗号分隔的多个值，忽略大小写
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        headers
+            .get_all(DEBUG_HEADER)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case("transforms"))
+            .then(Self::default)
+    }
+
+    /// 记录一项转换（如 `model_map=claude-sonnet-4-20250514->claude-sonnet-4.5`）
+    pub fn record(&self, entry: impl Into<String>) {
+        self.entries.lock().push(entry.into());
+    }
+
+    /// 转换摘要（`; ` 分隔，未应用任何转换时为 `none`）
+    pub fn summary(&self) -> String {
+        let entries = self.entries.lock();
+        if entries.is_empty() {
+            "none".to_string()
+        } else {
+            entries.join("; ")
+        }
+    }
+
+    /// 写入转换摘要响应头
+    pub fn apply_header(&self, headers: &mut HeaderMap) {
+        // 模型名等来自客户端输入，去除无法放入响应头的字符
+        let summary: String = self
+            .summary()
+            .chars()
+            .filter(|c| c.is_ascii() && !c.is_ascii_control())
+            .collect();
+        if let Ok(value) = HeaderValue::from_str(&summary) {
+            headers.insert(TRANSFORMS_HEADER, value);
+        }
+    }
+
+    /// 流式响应结尾的 SSE 注释（客户端按 SSE 规范会忽略注释行）
+    pub fn sse_comment(&self) -> Bytes {
+        let summary = self.summary().replace(['\r', '\n'], " ");
+        Bytes::from(format!(": {}: {}\n\n", TRANSFORMS_HEADER, summary))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert!(TransformLog::from_headers(&headers).is_none());
+
+        headers.insert(DEBUG_HEADER, HeaderValue::from_static("timing"));
+        assert!(TransformLog::from_headers(&headers).is_none());
+
+        headers.insert(DEBUG_HEADER, HeaderValue::from_static("timing, Transforms"));
+        assert!(TransformLog::from_headers(&headers).is_some());
+    }
+
+    #[test]
+    fn test_summary() {
+        let log = TransformLog::default();
+        assert_eq!(log.summary(), "none");
+
+        let shared = log.clone();
+        shared.record("model_map=claude-sonnet-4-20250514->claude-sonnet-4.5");
+        log.record("system_injection=template:code-review");
+
+        let mut headers = HeaderMap::new();
+        log.apply_header(&mut headers);
+        assert_eq!(
+            headers[TRANSFORMS_HEADER],
+            "model_map=claude-sonnet-4-20250514->claude-sonnet-4.5; system_injection=template:code-review"
+        );
+        assert_eq!(
+            log.sse_comment(),
+            Bytes::from(
+                ": x-kiro-transforms: model_map=claude-sonnet-4-20250514->claude-sonnet-4.5; system_injection=template:code-review\n\n"
+            )
+        );
+    }
+}
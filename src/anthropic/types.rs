@@ -3,12 +3,20 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::common::request_id;
+
 // === 错误响应 ===
 
 /// API 错误响应
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
     pub error: ErrorDetail,
+    /// 凭据池状态（仅凭据不可用时返回，便于诊断）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<PoolStatus>,
+    /// 请求 ID（与 `x-request-id` 响应头一致，便于对照服务端日志）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 /// 错误详情
@@ -19,6 +27,15 @@ pub struct ErrorDetail {
     pub message: String,
 }
 
+/// 凭据池状态
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStatus {
+    /// 凭据总数
+    pub total: usize,
+    /// 可用凭据数量（未禁用）
+    pub available: usize,
+}
+
 impl ErrorResponse {
     /// 创建新的错误响应
     pub fn new(error_type: impl Into<String>, message: impl Into<String>) -> Self {
@@ -27,6 +44,8 @@ impl ErrorResponse {
                 error_type: error_type.into(),
                 message: message.into(),
             },
+            pool: None,
+            request_id: request_id::current(),
         }
     }
 
@@ -34,6 +53,22 @@ impl ErrorResponse {
     pub fn authentication_error() -> Self {
         Self::new("authentication_error", "Invalid API key")
     }
+
+    /// 创建凭据池不可用错误响应
+    ///
+    /// - 没有任何凭据：提示通过 Admin API 添加
+    /// - 所有凭据均被禁用：提示等待冷却或通过 Admin API 重新启用
+    pub fn pool_unavailable(pool: PoolStatus) -> Self {
+        let message = if pool.total == 0 {
+            "No credentials configured. Add one via the Admin API: POST /api/admin/credentials"
+        } else {
+            "All credentials are disabled. Wait for the cooldown to expire or re-enable them via the Admin API: POST /api/admin/credentials/:id/reset"
+        };
+        Self {
+            pool: Some(pool),
+            ..Self::new("service_unavailable", message)
+        }
+    }
 }
 
 // === Models 端点类型 ===
@@ -63,6 +98,9 @@ pub struct ModelsResponse {
 /// 最大思考预算 tokens
 const MAX_BUDGET_TOKENS: i32 = 24576;
 
+/// 启用扩展输出 beta 时的最大思考预算 tokens
+const EXTENDED_MAX_BUDGET_TOKENS: i32 = 32768;
+
 /// Thinking 配置
 #[derive(Debug, Deserialize, Clone)]
 pub struct Thinking {
@@ -83,21 +121,99 @@ where
     D: serde::Deserializer<'de>,
 {
     let value = i32::deserialize(deserializer)?;
-    Ok(value.min(MAX_BUDGET_TOKENS))
+    Ok(value.min(EXTENDED_MAX_BUDGET_TOKENS))
+}
+
+impl Thinking {
+    /// 按是否启用扩展输出限制思考预算
+    pub fn clamp_budget(&mut self, extended_output: bool) {
+        let max = if extended_output {
+            EXTENDED_MAX_BUDGET_TOKENS
+        } else {
+            MAX_BUDGET_TOKENS
+        };
+        self.budget_tokens = self.budget_tokens.min(max);
+    }
 }
 
 /// Messages 请求体
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct MessagesRequest {
     pub model: String,
     pub max_tokens: i32,
     pub messages: Vec<Message>,
     #[serde(default)]
     pub stream: bool,
+    #[serde(default, deserialize_with = "deserialize_system")]
     pub system: Option<Vec<SystemMessage>>,
     pub tools: Option<Vec<Tool>>,
     pub tool_choice: Option<serde_json::Value>,
     pub thinking: Option<Thinking>,
+    /// 扩展字段：引用服务端提示词模板（展开后插入到 system 开头）
+    #[serde(default)]
+    pub prompt_template: Option<PromptTemplateRef>,
+    /// 扩展字段：期望的输出格式（`json_object` 时流式增量保证 JSON 部分有效）
+    #[serde(default)]
+    pub response_format: Option<ResponseFormat>,
+    /// 扩展字段：采样种子（OpenAI 风格）
+    ///
+    /// 上游不支持采样参数，无法透传也无法模拟；仅记录到请求日志，便于检索同一评测批次的请求
+    #[serde(default)]
+    pub seed: Option<i64>,
+    /// 服务等级（`auto` / `standard_only`），映射为内部请求优先级
+    #[serde(default)]
+    pub service_tier: Option<String>,
+    /// 请求元数据（`user_id` 可作为粘性会话键）
+    #[serde(default)]
+    pub metadata: Option<Metadata>,
+}
+
+impl MessagesRequest {
+    /// 客户端是否要求 JSON 输出
+    pub fn wants_json(&self) -> bool {
+        self.response_format
+            .as_ref()
+            .is_some_and(|f| matches!(f.format_type.as_str(), "json" | "json_object"))
+    }
+
+    /// tool_choice 的类型（auto / any / tool / none）
+    pub fn tool_choice_type(&self) -> Option<&str> {
+        self.tool_choice.as_ref()?.get("type")?.as_str()
+    }
+
+    /// 客户端是否要求单次响应最多调用一个工具（`tool_choice.disable_parallel_tool_use`）
+    pub fn disable_parallel_tool_use(&self) -> bool {
+        self.tool_choice
+            .as_ref()
+            .and_then(|c| c.get("disable_parallel_tool_use"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    }
+}
+
+/// 请求元数据
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Metadata {
+    /// 终端用户标识
+    #[serde(default)]
+    pub user_id: Option<String>,
+}
+
+/// 输出格式（扩展字段）
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResponseFormat {
+    #[serde(rename = "type")]
+    pub format_type: String,
+}
+
+/// 提示词模板引用（扩展字段）
+#[derive(Debug, Clone, Deserialize)]
+pub struct PromptTemplateRef {
+    /// 模板名称
+    pub name: String,
+    /// 模板变量
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
 }
 
 /// 消息
@@ -114,6 +230,26 @@ pub struct SystemMessage {
     pub text: String,
 }
 
+/// 反序列化 system 字段，同时支持字符串和数组两种形式
+fn deserialize_system<'de, D>(deserializer: D) -> Result<Option<Vec<SystemMessage>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum SystemField {
+        Text(String),
+        Blocks(Vec<SystemMessage>),
+    }
+
+    Ok(
+        Option::<SystemField>::deserialize(deserializer)?.map(|field| match field {
+            SystemField::Text(text) => vec![SystemMessage { text }],
+            SystemField::Blocks(blocks) => blocks,
+        }),
+    )
+}
+
 /// 工具定义
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Tool {
@@ -163,14 +299,58 @@ pub struct ImageSource {
 pub struct CountTokensRequest {
     pub model: String,
     pub messages: Vec<Message>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_system",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub system: Option<Vec<SystemMessage>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<Tool>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<serde_json::Value>,
+    /// 扩展字段：引用服务端提示词模板（计数前展开，不转发给外部 API）
+    #[serde(default, skip_serializing)]
+    pub prompt_template: Option<PromptTemplateRef>,
+}
+
+impl From<CountTokensRequest> for MessagesRequest {
+    /// 转换为消息请求，以便 count_tokens 复用 /v1/messages 的校验与规范化流程
+    fn from(req: CountTokensRequest) -> Self {
+        Self {
+            model: req.model,
+            max_tokens: 1,
+            messages: req.messages,
+            stream: false,
+            system: req.system,
+            tools: req.tools,
+            tool_choice: req.tool_choice,
+            thinking: None,
+            prompt_template: req.prompt_template,
+            response_format: None,
+            seed: None,
+            service_tier: None,
+            metadata: None,
+        }
+    }
 }
 
 /// Token 计数响应
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CountTokensResponse {
     pub input_tokens: i32,
+    /// 外部 count_tokens API 不可用，返回的是本地估算值
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub estimated: bool,
+}
+
+// === Ready 端点类型 ===
+
+/// 就绪检查响应
+#[derive(Debug, Serialize)]
+pub struct ReadyResponse {
+    /// "ready" 或 "not_ready"
+    pub status: String,
+    /// 凭据池状态
+    pub pool: PoolStatus,
 }
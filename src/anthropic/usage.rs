@@ -0,0 +1,137 @@
+//! 消息请求用量记录
+//!
+//! 流式响应的输出 tokens 要到流结束才能确定，因此用量记录由请求处理与响应流共同持有，
+//! 最后一个持有者释放时写入 `usage_log` 表（客户端中途断开时记录已生成的部分），
+//! 并按实际用量扣除客户端 Key 的 token 令牌桶
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use parking_lot::Mutex;
+
+use crate::kiro::db::Database;
+use crate::kiro::model::usage_log::UsageLog;
+
+use super::token_bucket::KeyTokenBuckets;
+
+/// 单次请求的用量记录句柄（可克隆，共享同一条记录）
+#[derive(Clone)]
+pub(super) struct UsageTracker(Arc<Mutex<PendingUsage>>);
+
+struct PendingUsage {
+    database: Arc<Database>,
+    started: Instant,
+    log: UsageLog,
+    /// 请求结束时扣除 tokens 的令牌桶与客户端 Key
+    token_charge: Option<(Arc<KeyTokenBuckets>, String)>,
+}
+
+impl UsageTracker {
+    pub(super) fn new(
+        database: Arc<Database>,
+        model: String,
+        tag: Option<String>,
+        stream: bool,
+    ) -> Self {
+        Self(Arc::new(Mutex::new(PendingUsage {
+            database,
+            started: Instant::now(),
+            log: UsageLog {
+                created_at: chrono::Utc::now(),
+                credential_id: None,
+                model,
+                tag,
+                input_tokens: 0,
+                output_tokens: 0,
+                latency_ms: 0,
+                status: 0,
+                stream,
+            },
+            token_charge: None,
+        })))
+    }
+
+    /// 请求结束时按实际用量扣除客户端 Key 的 token 令牌桶
+    pub(super) fn with_token_charge(self, buckets: Arc<KeyTokenBuckets>, api_key: String) -> Self {
+        self.0.lock().token_charge = Some((buckets, api_key));
+        self
+    }
+
+    /// 更新 token 用量（可多次调用，以最后一次为准）
+    pub(super) fn set_tokens(&self, input_tokens: i32, output_tokens: i32) {
+        let mut pending = self.0.lock();
+        pending.log.input_tokens = input_tokens.max(0) as u64;
+        pending.log.output_tokens = output_tokens.max(0) as u64;
+    }
+
+    /// 记录响应状态与实际使用的凭据
+    pub(super) fn set_response(&self, credential_id: Option<u64>, status: u16) {
+        let mut pending = self.0.lock();
+        pending.log.credential_id = credential_id;
+        pending.log.status = status;
+    }
+}
+
+impl Drop for PendingUsage {
+    fn drop(&mut self) {
+        self.log.latency_ms = self.started.elapsed().as_millis() as u64;
+        if let Some((buckets, api_key)) = &self.token_charge {
+            buckets.charge_tokens(
+                api_key,
+                self.log.input_tokens + self.log.output_tokens,
+                Instant::now(),
+            );
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let database = self.database.clone();
+        let log = self.log.clone();
+        runtime.spawn(async move {
+            if let Err(e) = database.call(move |db| db.insert_usage_log(&log)).await {
+                tracing::warn!("写入用量记录失败: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::usage_log::UsageFilter;
+
+    #[tokio::test]
+    async fn test_records_when_last_handle_dropped() {
+        let database = Database::open_in_memory().unwrap();
+        let tracker =
+            UsageTracker::new(database.clone(), "claude-sonnet-4".to_string(), None, true);
+        tracker.set_response(Some(3), 200);
+
+        // 响应流仍持有句柄时不写入
+        let stream_handle = tracker.clone();
+        drop(tracker);
+        stream_handle.set_tokens(120, 45);
+        tokio::task::yield_now().await;
+        let summary = database.summarize_usage(&UsageFilter::default()).unwrap();
+        assert_eq!(summary.total.requests, 0);
+
+        drop(stream_handle);
+        for _ in 0..100 {
+            if database
+                .summarize_usage(&UsageFilter::default())
+                .unwrap()
+                .total
+                .requests
+                > 0
+            {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let summary = database.summarize_usage(&UsageFilter::default()).unwrap();
+        assert_eq!(summary.total.requests, 1);
+        assert_eq!(summary.total.input_tokens, 120);
+        assert_eq!(summary.total.output_tokens, 45);
+        assert_eq!(summary.credentials[0].credential_id, Some(3));
+    }
+}
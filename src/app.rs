@@ -0,0 +1,634 @@
+//! 应用组装
+//!
+//! 按配置打开数据库、创建凭据管理器、启动后台任务并构建路由；`main.rs` 与嵌入使用共用这里的逻辑。
+//! 嵌入到自有 axum 服务时使用 [`build_app`]，需要凭据管理器等组件时使用 [`App`]
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use axum::Router;
+
+use crate::common::access_log::{self, AccessLog};
+use crate::common::{request_id, tls};
+use crate::kiro::db::{CredentialStore, Database, SharedCredentials};
+use crate::kiro::provider::KiroProvider;
+use crate::kiro::token_manager::MultiTokenManager;
+use crate::model::config::Config;
+use crate::{admin, anthropic, http_client, kiro, status, token, web};
+
+/// 按配置构建完整的路由（Anthropic API、Admin API、状态页与 Web UI）
+///
+/// 用于嵌入到自有 axum 服务或添加自定义路由。`adminPort` 不生效，Admin API 与主路由合并；
+/// TLS 与访问日志由调用方负责。需在 Tokio 运行时中调用（会启动后台任务）
+///
+/// ```ignore
+/// let config = kiro_rs::Config::load("config.json")?;
+/// let app = kiro_rs::build_app(config)?.route("/healthz", axum::routing::get(|| async { "ok" }));
+/// let listener = tokio::net::TcpListener::bind("0.0.0.0:8990").await?;
+/// axum::serve(listener, app).await?;
+/// ```
+pub fn build_app(mut config: Config) -> anyhow::Result<Router> {
+    config.admin_port = None;
+    let app = App::build(config)?;
+    Ok(app
+        .router
+        .layer(axum::middleware::from_fn(request_id::request_id_middleware)))
+}
+
+/// 按配置打开数据库（`databaseInMemory` 时为内存数据库）
+pub fn open_database(config: &Config) -> anyhow::Result<Arc<Database>> {
+    let store = match config.database_url.as_deref().filter(|u| !u.is_empty()) {
+        Some(url) => Some(open_postgres(url)?),
+        None => None,
+    };
+    let shared = config
+        .shared_database_paths
+        .iter()
+        .enumerate()
+        .map(|(index, path)| SharedCredentials::open(path, index))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    for path in &config.shared_database_paths {
+        tracing::info!("已挂载只读共享凭据库: {}", path);
+    }
+    let path = (!config.database_in_memory).then(|| std::path::Path::new(&config.database_path));
+    let db =
+        Database::open_with_shared_credentials(path, store, shared).context("打开数据库失败")?;
+    if config.database_in_memory {
+        tracing::warn!("使用内存数据库，凭据与请求日志不会持久化，重启后丢失");
+    } else {
+        tracing::info!("数据库已打开: {}", config.database_path);
+    }
+    Ok(db)
+}
+
+/// 连接 PostgreSQL 凭据存储（其余数据仍使用本地 SQLite）
+#[cfg(feature = "postgres")]
+fn open_postgres(url: &str) -> anyhow::Result<Box<dyn CredentialStore>> {
+    let store = kiro::db::PostgresCredentialStore::connect(url)?;
+    tracing::info!("凭据存储: PostgreSQL（多个实例可共享凭据池）");
+    Ok(Box::new(store))
+}
+
+/// 未启用 `postgres` feature 时拒绝 `databaseUrl`，避免静默退回本地凭据池
+#[cfg(not(feature = "postgres"))]
+fn open_postgres(_url: &str) -> anyhow::Result<Box<dyn CredentialStore>> {
+    anyhow::bail!(
+        "配置了 databaseUrl，但当前程序未启用 PostgreSQL 支持，请使用 --features postgres 编译"
+    )
+}
+
+/// 组装完成的应用
+pub struct App {
+    /// 应用配置
+    pub config: Config,
+    /// 多凭据 Token 管理器
+    pub token_manager: Arc<MultiTokenManager>,
+    /// 主端口路由（已挂载到 `basePath`）
+    pub router: Router,
+    /// 独立 Admin 端口的监听地址与路由（配置 `adminPort` 且启用 Admin API 时）
+    pub admin_server: Option<(String, Router)>,
+    /// 是否启用 Admin API
+    pub admin_enabled: bool,
+    /// 是否启用状态页
+    pub status_enabled: bool,
+}
+
+impl App {
+    /// 按配置打开数据库并组装应用
+    pub fn build(config: Config) -> anyhow::Result<Self> {
+        let db = open_database(&config)?;
+        Self::with_database(config, db)
+    }
+
+    /// 使用已打开的数据库组装应用
+    ///
+    /// 迁移配置文件内联的旧版凭据，启动告警、统计刷新、健康检查、版本检测与热备同步等后台任务
+    pub fn with_database(config: Config, db: Arc<Database>) -> anyhow::Result<Self> {
+        kiro::legacy::import(&db, &config.legacy_credentials, "配置文件")
+            .context("迁移旧版凭据失败")?;
+
+        // 获取 API Key
+        let Some(api_key) = config.api_key.clone() else {
+            anyhow::bail!("配置文件中未设置 apiKey");
+        };
+
+        // 初始化上游 DNS 静态解析覆盖（需在创建任何 HTTP Client 之前）
+        http_client::init_dns_overrides(&config.dns_overrides).context("DNS 覆盖配置无效")?;
+        http_client::init_doh(
+            config.doh_url.as_deref(),
+            Duration::from_millis(config.doh_timeout_ms),
+        )
+        .context("DoH 配置无效")?;
+
+        // 构建代理配置
+        let proxy_config = config.proxy_url.as_ref().map(|url| {
+            let mut proxy = http_client::ProxyConfig::new(url);
+            if let (Some(username), Some(password)) =
+                (&config.proxy_username, &config.proxy_password)
+            {
+                proxy = proxy.with_auth(username, password);
+            }
+            proxy
+        });
+
+        if let Some(url) = &config.proxy_url {
+            tracing::info!("已配置 HTTP 代理: {}", url);
+        }
+
+        // 启动邮件告警
+        let alerts = match kiro::alert::EmailConfig::from_config(&config)
+            .context("邮件告警配置无效")?
+        {
+            Some(email) => {
+                tracing::info!(
+                    "已启用邮件告警: {}:{} -> {}",
+                    email.host,
+                    email.port,
+                    email.to.join(", ")
+                );
+                Some(kiro::alert::spawn(email, db.clone()))
+            }
+            None => None,
+        };
+
+        // 创建 MultiTokenManager 和 KiroProvider
+        let token_manager =
+            MultiTokenManager::new(config.clone(), db.clone(), proxy_config.clone())
+                .context("创建 Token 管理器失败")?
+                .with_alerts(alerts);
+
+        let credentials_count = token_manager.total_count();
+        if credentials_count == 0 {
+            tracing::warn!("数据库中没有凭据，请通过 Admin API 添加凭据后使用");
+        } else {
+            tracing::info!("已加载 {} 个凭据", credentials_count);
+        }
+
+        // 获取第一个凭据用于日志显示
+        let first_credentials = token_manager.credentials();
+
+        let token_manager = Arc::new(token_manager);
+        let kiro_provider = KiroProvider::with_proxy(token_manager.clone(), proxy_config.clone());
+
+        // 启动统计快照刷新
+        kiro::stats::spawn_refresher(
+            db.clone(),
+            Duration::from_secs(config.stats_refresh_interval_secs),
+        );
+
+        // 启动过期记录清理
+        let retention = kiro::retention::RetentionSettings {
+            request_log_days: config.request_log_retention_days,
+            request_log_max_rows: config.request_log_max_rows,
+            usage_log_days: config.usage_log_retention_days,
+        };
+        if retention.is_enabled() {
+            kiro::retention::spawn_pruner(db.clone(), retention);
+        }
+
+        // 启动凭据定时健康检查
+        if config.health_check_interval_mins > 0 {
+            kiro::health_check::spawn_scheduler(
+                token_manager.clone(),
+                Duration::from_secs(config.health_check_interval_mins * 60),
+            );
+            tracing::info!(
+                "已启用凭据健康检查（间隔 {} 分钟）",
+                config.health_check_interval_mins
+            );
+        }
+
+        // 启动凭据余额定时刷新
+        if config.balance_refresh_interval_mins > 0 {
+            kiro::balance::spawn_refresher(
+                token_manager.clone(),
+                Duration::from_secs(config.balance_refresh_interval_mins * 60),
+            );
+            tracing::info!(
+                "已启用凭据余额定时刷新（间隔 {} 分钟）",
+                config.balance_refresh_interval_mins
+            );
+        }
+
+        // 启动额度跳过凭据的余额重新查询
+        if config.quota_skip_threshold().is_some() {
+            kiro::balance::spawn_quota_recheck(token_manager.clone());
+        }
+
+        // 启动 Kiro 版本自动检测
+        if config.kiro_version_auto_update {
+            kiro::version::spawn_auto_update(&config, proxy_config.clone());
+            tracing::info!(
+                "已启用 Kiro 版本自动检测（间隔 {} 秒）",
+                config.kiro_version_check_interval_secs
+            );
+        }
+
+        // 启动热备同步（配置了主实例地址时本实例作为热备）
+        if let Some(leader_url) = &config.replication_leader_url {
+            kiro::replication::spawn_follower(&config, token_manager.clone());
+            tracing::info!(
+                "热备模式已启用，从主实例同步凭据: {}（间隔 {} 秒）",
+                leader_url,
+                config.replication_interval_secs
+            );
+        }
+
+        // 初始化 count_tokens 配置
+        token::init_config(token::CountTokensConfig {
+            api_url: config.count_tokens_api_url.clone(),
+            api_key: config.count_tokens_api_key.clone(),
+            auth_type: config.count_tokens_auth_type.clone(),
+            timeout: Duration::from_millis(config.count_tokens_timeout_ms),
+            budget_per_minute: config.count_tokens_budget_per_minute,
+            proxy: proxy_config,
+        });
+
+        // 构建 Anthropic API 路由（从第一个凭据获取 profile_arn）
+        let anthropic_app = anthropic::create_router_with_provider(
+            &api_key,
+            Some(kiro_provider),
+            first_credentials.profile_arn.clone(),
+            config.max_concurrent_requests_per_key,
+        );
+
+        // 构建 Admin API 路由（如果配置了非空的 admin_api_key）
+        // 安全检查：空字符串被视为未配置，防止空 key 绕过认证
+        let admin_app = match &config.admin_api_key {
+            Some(admin_key) if admin_key.trim().is_empty() => {
+                tracing::warn!("admin_api_key 配置为空，Admin API 未启用");
+                None
+            }
+            Some(admin_key) => {
+                let admin_service = admin::AdminService::new(token_manager.clone());
+                let admin_state = admin::AdminState::new(admin_key, admin_service);
+                tracing::info!("Admin API 已启用");
+                Some(admin::create_admin_router(admin_state))
+            }
+            None => None,
+        };
+        let admin_enabled = admin_app.is_some();
+        let admin_path = config.admin_path();
+
+        // 配置 adminPort 后 Admin API 与 Web UI 仅在独立端口提供
+        let admin_addr = config.admin_addr();
+        if admin_addr.is_some() && !admin_enabled {
+            tracing::warn!("已配置 adminPort 但 Admin API 未启用，忽略独立端口");
+        }
+        let (app, admin_server) = match (admin_app, admin_addr) {
+            (Some(admin_app), Some(addr)) => (
+                anthropic_app,
+                Some((addr, Router::new().nest(&admin_path, admin_app))),
+            ),
+            (Some(admin_app), None) => (anthropic_app.nest(&admin_path, admin_app), None),
+            (None, _) => (anthropic_app, None),
+        };
+
+        // 添加无 JS 状态页
+        // 配置独立 Admin 端口时公共端口的状态页不接受 Admin API Key，需认证的状态页随 Admin API 提供
+        let status_router =
+            status::create_status_router(token_manager.clone(), admin_server.is_none());
+        let status_enabled = status_router.is_some();
+        let app = match status_router {
+            Some(router) => app.merge(router),
+            None => app,
+        };
+        let admin_server = admin_server.map(|(addr, admin)| {
+            match status::create_status_router(token_manager.clone(), true) {
+                Some(router) => (addr, admin.merge(router)),
+                None => (addr, admin),
+            }
+        });
+
+        // 添加前端静态文件服务（作为 fallback，避免覆盖 API 路由）
+        // 禁用时不注册 fallback，非 API 路径直接返回 404；配置独立 Admin 端口时随 Admin API 提供
+        // 公共端口上的 Web UI 不注入 Admin API 路径，避免泄露自定义的 adminPath
+        let (app, admin_server) = if config.web_ui_enabled {
+            if let Some(dir) = &config.web_ui_dir {
+                tracing::info!("Web UI 使用外部目录: {}", dir);
+            }
+            match admin_server {
+                Some((addr, admin)) => {
+                    let web = web::create_web_router(&config, true);
+                    (app, Some((addr, admin.fallback_service(web))))
+                }
+                None => (
+                    app.fallback_service(web::create_web_router(&config, false)),
+                    None,
+                ),
+            }
+        } else {
+            tracing::info!("Web UI 已禁用");
+            (app, admin_server)
+        };
+
+        // 挂载到路径前缀（所有路由与 Web UI 共用）
+        let base_path = config.base_path();
+        if !base_path.is_empty() {
+            tracing::info!("服务挂载于路径前缀: {}", base_path);
+        }
+        let router = mount_base_path(app, &base_path);
+        let admin_server =
+            admin_server.map(|(addr, admin)| (addr, mount_base_path(admin, &base_path)));
+
+        Ok(Self {
+            config,
+            token_manager,
+            router,
+            admin_server,
+            admin_enabled,
+            status_enabled,
+        })
+    }
+
+    /// 按配置监听端口并提供服务（含独立 Admin 端口、HTTPS、HTTP 重定向与访问日志）
+    pub async fn serve(self) -> anyhow::Result<()> {
+        let Self {
+            config,
+            router,
+            admin_server,
+            admin_enabled,
+            status_enabled,
+            ..
+        } = self;
+
+        // 加载 HTTPS 证书（未配置时以 HTTP 提供服务）
+        let tls = config
+            .tls_paths()?
+            .map(|(cert_path, key_path)| tls::load_acceptor(cert_path, key_path))
+            .transpose()
+            .context("加载 HTTPS 证书失败")?;
+        let scheme = if tls.is_some() { "https" } else { "http" };
+
+        // 打开 HTTP 访问日志
+        let access_log = AccessLog::from_config(&config).context("访问日志配置无效")?;
+        if let Some(format) = &config.access_log_format {
+            tracing::info!(
+                "已启用访问日志（{}）: {}",
+                format,
+                config.access_log_path.as_deref().unwrap_or("stdout")
+            );
+        }
+
+        // 启动服务器
+        let addr = format!("{}:{}", config.host, config.port);
+        tracing::info!("启动 Anthropic API 端点: {}://{}", scheme, addr);
+        if let Some(api_key) = &config.api_key {
+            tracing::info!("API Key: {}***", &api_key[..(api_key.len() / 2)]);
+        }
+        tracing::info!("可用 API:");
+        tracing::info!("  GET  /v1/models");
+        tracing::info!("  POST /v1/messages");
+        tracing::info!("  POST /v1/messages/count_tokens");
+        tracing::info!("  POST /v1/chat/completions");
+        if config.embeddings_api_url.is_some() {
+            tracing::info!("  POST /v1/embeddings");
+        }
+        tracing::info!("  GET  /ready");
+        if status_enabled {
+            tracing::info!("  GET  /status（状态页: {}）", config.status_page);
+        }
+        if admin_enabled {
+            match &admin_server {
+                Some((admin_addr, _)) => tracing::info!("Admin API（独立端口 {}）:", admin_addr),
+                None => tracing::info!("Admin API:"),
+            }
+            log_admin_endpoints(&config.admin_path());
+        }
+        let web_addr = admin_server
+            .as_ref()
+            .map_or(&addr, |(admin_addr, _)| admin_addr);
+        tracing::info!("Web UI: {}://{}{}", scheme, web_addr, config.base_path());
+
+        // HTTP → HTTPS 重定向
+        match (&tls, config.tls_redirect_port) {
+            (Some(_), Some(port)) => {
+                let redirect_addr = format!("{}:{}", config.host, port);
+                tracing::info!("HTTP 重定向端点: http://{} -> https", redirect_addr);
+                let redirect = tls::redirect_router(config.port);
+                let access_log = access_log.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve_router(&redirect_addr, redirect, None, access_log).await {
+                        tracing::error!("HTTP 重定向端点启动失败: {:#}", e);
+                    }
+                });
+            }
+            (None, Some(_)) => tracing::warn!("未启用 HTTPS，忽略 tlsRedirectPort"),
+            _ => {}
+        }
+
+        // 独立 Admin 端口与公共端口任一失败（如端口被占用）时整体退出
+        let public = serve_router(&addr, router, tls.clone(), access_log.clone());
+        match admin_server {
+            Some((admin_addr, admin_app)) => {
+                let admin = async {
+                    serve_router(&admin_addr, admin_app, tls, access_log)
+                        .await
+                        .context("Admin 端口启动失败")
+                };
+                tokio::try_join!(public, admin).map(|_| ())
+            }
+            None => public.await,
+        }
+    }
+}
+
+/// 在指定地址启动服务（提供证书时为 HTTPS）
+async fn serve_router(
+    addr: &str,
+    app: Router,
+    tls: Option<tokio_rustls::TlsAcceptor>,
+    access_log: Option<Arc<AccessLog>>,
+) -> anyhow::Result<()> {
+    let app = match access_log {
+        Some(log) => app.layer(axum::middleware::from_fn_with_state(
+            log,
+            access_log::access_log_middleware,
+        )),
+        None => app,
+    };
+    let app = app
+        .layer(axum::middleware::from_fn(request_id::request_id_middleware))
+        .into_make_service_with_connect_info::<access_log::RemoteAddr>();
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("监听 {} 失败", addr))?;
+    match tls {
+        Some(acceptor) => {
+            let listener = tls::TlsListener::new(listener, acceptor)?;
+            axum::serve(listener, app).await?
+        }
+        None => axum::serve(listener, app).await?,
+    }
+    Ok(())
+}
+
+/// 将路由挂载到路径前缀下（前缀为空时原样返回）
+fn mount_base_path(app: Router, base_path: &str) -> Router {
+    if base_path.is_empty() {
+        return app;
+    }
+    // 带尾部斜杠的前缀不会匹配嵌套路由，重定向到不带斜杠的地址
+    let target = base_path.to_string();
+    Router::new().nest(base_path, app).route(
+        &format!("{}/", base_path),
+        axum::routing::get(move || async move { axum::response::Redirect::permanent(&target) }),
+    )
+}
+
+/// 输出 Admin API 端点列表
+fn log_admin_endpoints(admin_path: &str) {
+    tracing::info!("  GET  {}/credentials", admin_path);
+    tracing::info!("  POST {}/credentials/:id/disabled", admin_path);
+    tracing::info!("  POST {}/credentials/:id/priority", admin_path);
+    tracing::info!("  POST {}/credentials/:id/user-agent", admin_path);
+    tracing::info!("  POST {}/credentials/:id/models", admin_path);
+    tracing::info!("  POST {}/credentials/:id/headers", admin_path);
+    tracing::info!("  POST {}/credentials/:id/machine-id", admin_path);
+    tracing::info!("  POST {}/credentials/:id/reset", admin_path);
+    tracing::info!("  GET  {}/credentials/:id/balance", admin_path);
+    tracing::info!("  POST {}/credentials", admin_path);
+    tracing::info!("  DELETE {}/credentials/:id", admin_path);
+    tracing::info!("  GET  {}/drain-jobs/:id", admin_path);
+    tracing::info!("  POST {}/credentials/refresh-balances", admin_path);
+    tracing::info!("  GET  {}/credentials/export", admin_path);
+    tracing::info!("  POST {}/credentials/import", admin_path);
+    tracing::info!("  POST {}/credentials/bulk", admin_path);
+    tracing::info!("  GET  {}/balance-refresh-jobs/:id", admin_path);
+    tracing::info!("  GET  {}/requests", admin_path);
+    tracing::info!("  GET  {}/requests/search", admin_path);
+    tracing::info!("  GET  {}/usage", admin_path);
+    tracing::info!("  GET  {}/metrics", admin_path);
+    tracing::info!("  GET  {}/stats", admin_path);
+    tracing::info!("  GET  {}/config", admin_path);
+    tracing::info!("  GET  {}/refresh-lock", admin_path);
+    tracing::info!("  POST {}/refresh-lock/release", admin_path);
+    tracing::info!("  GET  {}/prompt-templates", admin_path);
+    tracing::info!("  POST {}/prompt-templates", admin_path);
+    tracing::info!("  DELETE {}/prompt-templates/:name", admin_path);
+    tracing::info!("  GET  {}/admin-tokens", admin_path);
+    tracing::info!("  POST {}/admin-tokens", admin_path);
+    tracing::info!("  POST {}/admin-tokens/:id/revoke", admin_path);
+    tracing::info!("  DELETE {}/admin-tokens/:id", admin_path);
+    tracing::info!("  GET  {}/notifications", admin_path);
+    tracing::info!("  POST {}/notifications/:id/replay", admin_path);
+    tracing::info!("  GET  {}/api-keys", admin_path);
+    tracing::info!("  POST {}/api-keys", admin_path);
+    tracing::info!("  POST {}/api-keys/:id/revoke", admin_path);
+    tracing::info!("  DELETE {}/api-keys/:id", admin_path);
+    tracing::info!("  GET  {}/replication/snapshot", admin_path);
+    tracing::info!("  GET  {}/replication/status", admin_path);
+    tracing::info!("  POST {}/replication/promote", admin_path);
+    tracing::info!("  GET  {}/ws", admin_path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_build_app_with_custom_route() {
+        let config = Config {
+            api_key: Some("sk-test".to_string()),
+            admin_api_key: Some("admin-key".to_string()),
+            admin_port: Some(0),
+            database_in_memory: true,
+            web_ui_enabled: false,
+            ..Config::default()
+        };
+        let app = build_app(config)
+            .unwrap()
+            .route("/custom", axum::routing::get(|| async { "custom" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!("{}/custom", base_url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "custom");
+
+        // 嵌入时忽略 adminPort，Admin API 挂载在同一路由
+        let response = client
+            .get(format!("{}/api/admin/credentials", base_url))
+            .header("x-api-key", "admin-key")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+        let response = client
+            .get(format!("{}/v1/models", base_url))
+            .header("x-api-key", "sk-test")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert!(response.headers().contains_key("x-request-id"));
+    }
+
+    #[tokio::test]
+    async fn test_admin_port_status_page_requires_admin_listener() {
+        let config = Config {
+            api_key: Some("sk-test".to_string()),
+            admin_api_key: Some("admin-key".to_string()),
+            admin_port: Some(0),
+            status_page: "auth".to_string(),
+            web_ui_enabled: false,
+            ..Config::default()
+        };
+        let app = App::with_database(config, Database::open_in_memory().unwrap()).unwrap();
+        let serve = |router: Router| async move {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let base_url = format!("http://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move { axum::serve(listener, router).await });
+            base_url
+        };
+        let public_url = serve(app.router).await;
+        let admin_url = serve(app.admin_server.unwrap().1).await;
+        let client = reqwest::Client::new();
+
+        // 公共端口不校验 Admin API Key，需认证的状态页只在独立 Admin 端口提供
+        let response = client
+            .get(format!("{}/status", public_url))
+            .header("x-api-key", "admin-key")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let response = client
+            .get(format!("{}/status", admin_url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let response = client
+            .get(format!("{}/status", admin_url))
+            .header("x-api-key", "admin-key")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_serve_rejects_partial_tls_config() {
+        let config = Config {
+            api_key: Some("sk-test".to_string()),
+            port: 0,
+            tls_cert_path: Some("/etc/kiro/fullchain.pem".to_string()),
+            web_ui_enabled: false,
+            ..Config::default()
+        };
+        let app = App::with_database(config, Database::open_in_memory().unwrap()).unwrap();
+
+        // 只配置证书时启动失败，不退回 HTTP
+        let error = app.serve().await.unwrap_err();
+        assert!(error.to_string().contains("tlsKeyPath"));
+    }
+}
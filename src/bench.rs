@@ -0,0 +1,376 @@
+//! 内置压测命令（`kiro-rs bench`）
+//!
+//! 向运行中的实例（或进程内的 mock 上游，见 [`spawn_mock`]）并发发送合成的 /v1/messages 请求，
+//! 统计延迟、首字延迟（TTFT）与吞吐，便于用一致的方式评估基础设施改动
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::{Router, body::Body, extract::State, response::Response, routing::post};
+use bytes::Bytes;
+use chrono::Utc;
+use futures::{StreamExt, stream};
+use serde_json::json;
+
+use crate::anthropic::create_router_with_provider;
+use crate::http_client::build_client;
+use crate::kiro::db::Database;
+use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::parser::crc::crc32;
+use crate::kiro::provider::KiroProvider;
+use crate::kiro::token_manager::MultiTokenManager;
+use crate::model::arg::BenchArgs;
+use crate::model::config::Config;
+
+/// mock 模式下进程内代理的 API Key
+const MOCK_API_KEY: &str = "bench-mock-key";
+
+/// mock 上游逐个返回的文本增量（含多字节字符）
+const MOCK_CHUNKS: &[&str] = &["Hello", "! ", "这是", "压测 ", "mock ", "上游的", "响应。"];
+
+/// 单个成功请求的测量结果
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    /// 总延迟
+    latency: Duration,
+    /// 首个内容增量的延迟（非流式请求等于总延迟）
+    ttft: Duration,
+    /// 上游报告的输出 tokens
+    output_tokens: u64,
+}
+
+/// 压测目标
+pub struct BenchTarget {
+    /// 服务地址（如 `http://127.0.0.1:8990`）
+    pub base_url: String,
+    /// 客户端 API Key
+    pub api_key: String,
+}
+
+/// 执行压测并打印报告
+pub async fn run(target: BenchTarget, args: BenchArgs) -> anyhow::Result<()> {
+    let client = build_client(None, args.timeout_secs)?;
+    let url = format!("{}/v1/messages", target.base_url.trim_end_matches('/'));
+    let body = json!({
+        "model": args.model,
+        "max_tokens": args.max_tokens,
+        "stream": !args.no_stream,
+        "messages": [{"role": "user", "content": args.prompt}]
+    });
+
+    println!(
+        "压测 {}：{} 个请求，并发 {}，模型 {}，{}",
+        url,
+        args.requests,
+        args.concurrency,
+        args.model,
+        if args.no_stream {
+            "非流式"
+        } else {
+            "流式"
+        }
+    );
+
+    let started = Instant::now();
+    let results: Vec<Result<Sample, String>> = stream::iter(0..args.requests)
+        .map(|_| send_one(&client, &url, &target.api_key, &body, !args.no_stream))
+        .buffer_unordered(args.concurrency.max(1))
+        .collect()
+        .await;
+    let elapsed = started.elapsed();
+
+    print_report(&results, elapsed);
+    Ok(())
+}
+
+/// 发送单个请求并测量
+async fn send_one(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: &str,
+    body: &serde_json::Value,
+    stream: bool,
+) -> Result<Sample, String> {
+    let started = Instant::now();
+    let response = client
+        .post(url)
+        .header("x-api-key", api_key)
+        .header("anthropic-version", "2023-06-01")
+        .header("x-kiro-tag", "bench")
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| format!("请求失败: {}", e))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("HTTP {}", status.as_u16()));
+    }
+
+    if !stream {
+        let json: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| format!("读取响应失败: {}", e))?;
+        let latency = started.elapsed();
+        return Ok(Sample {
+            latency,
+            ttft: latency,
+            output_tokens: json["usage"]["output_tokens"].as_u64().unwrap_or(0),
+        });
+    }
+
+    let mut body = response.bytes_stream();
+    // 按字节缓冲，完整事件到齐后再解码，避免多字节字符被拆到两个分块中
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut ttft = None;
+    let mut output_tokens = 0;
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| format!("读取响应流失败: {}", e))?;
+        buffer.extend_from_slice(&chunk);
+
+        while let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+            let raw: Vec<u8> = buffer.drain(..end + 2).collect();
+            let event = String::from_utf8_lossy(&raw);
+            let Some(data) = event.lines().find_map(|line| line.strip_prefix("data: ")) else {
+                continue;
+            };
+            let Ok(data) = serde_json::from_str::<serde_json::Value>(data) else {
+                continue;
+            };
+            match data["type"].as_str() {
+                Some("content_block_delta") if ttft.is_none() => {
+                    ttft = Some(started.elapsed());
+                }
+                Some("message_delta") => {
+                    output_tokens = data["usage"]["output_tokens"].as_u64().unwrap_or(0);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let latency = started.elapsed();
+    Ok(Sample {
+        latency,
+        ttft: ttft.unwrap_or(latency),
+        output_tokens,
+    })
+}
+
+/// 启动进程内的 mock 上游与代理，返回指向该代理的压测目标
+///
+/// mock 上游按 Kiro 事件流格式逐个返回固定的文本增量（相邻事件间隔 `delay`），
+/// 代理使用真实的请求转换、凭据选择与流式编码，可在不消耗上游额度的情况下评估代理自身的开销
+pub async fn spawn_mock(delay: Duration) -> anyhow::Result<BenchTarget> {
+    let upstream = Router::new()
+        .route("/generateAssistantResponse", post(mock_upstream))
+        .with_state(delay);
+    let upstream_url = serve_local(upstream).await?;
+
+    let db = Database::open_in_memory()?;
+    db.insert_credential(&KiroCredentials {
+        refresh_token: Some("mock".repeat(32)),
+        access_token: Some("mock-access-token".to_string()),
+        expires_at: Some((Utc::now() + chrono::Duration::days(1)).to_rfc3339()),
+        ..Default::default()
+    })?;
+    let config = Config {
+        api_key: Some(MOCK_API_KEY.to_string()),
+        ..Config::default()
+    };
+    let token_manager = Arc::new(MultiTokenManager::new(config, db, None)?);
+    let provider = KiroProvider::new(token_manager)
+        .with_upstream_url(format!("{}/generateAssistantResponse", upstream_url));
+    let base_url = serve_local(create_router_with_provider(
+        MOCK_API_KEY,
+        Some(provider),
+        None,
+        0,
+    ))
+    .await?;
+
+    Ok(BenchTarget {
+        base_url,
+        api_key: MOCK_API_KEY.to_string(),
+    })
+}
+
+/// 在本机随机端口上启动服务，返回服务地址
+async fn serve_local(router: Router) -> anyhow::Result<String> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, router).await {
+            tracing::error!("压测 mock 服务异常退出: {}", e);
+        }
+    });
+    Ok(format!("http://{}", addr))
+}
+
+/// mock 上游：以事件流返回 [`MOCK_CHUNKS`]
+async fn mock_upstream(State(delay): State<Duration>) -> Response {
+    let frames = stream::iter(MOCK_CHUNKS).then(move |text| async move {
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        let frame = encode_event("assistantResponseEvent", &json!({ "content": text }));
+        Ok::<_, Infallible>(Bytes::from(frame))
+    });
+    Response::new(Body::from_stream(frames))
+}
+
+/// 编码 AWS Event Stream 事件帧（格式见 `kiro::parser::frame`）
+fn encode_event(event_type: &str, payload: &serde_json::Value) -> Vec<u8> {
+    let mut headers = Vec::new();
+    for (name, value) in [
+        (":message-type", "event"),
+        (":event-type", event_type),
+        (":content-type", "application/json"),
+    ] {
+        headers.push(name.len() as u8);
+        headers.extend_from_slice(name.as_bytes());
+        // 值类型 7：字符串
+        headers.push(7);
+        headers.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        headers.extend_from_slice(value.as_bytes());
+    }
+    let payload = serde_json::to_vec(payload).unwrap_or_default();
+
+    let total_length = 12 + headers.len() + payload.len() + 4;
+    let mut frame = Vec::with_capacity(total_length);
+    frame.extend_from_slice(&(total_length as u32).to_be_bytes());
+    frame.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+    let prelude_crc = crc32(&frame);
+    frame.extend_from_slice(&prelude_crc.to_be_bytes());
+    frame.extend_from_slice(&headers);
+    frame.extend_from_slice(&payload);
+    let message_crc = crc32(&frame);
+    frame.extend_from_slice(&message_crc.to_be_bytes());
+    frame
+}
+
+/// 计算百分位数（最近秩法，输入需已排序）
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// 格式化耗时（毫秒）
+fn ms(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// 打印压测报告
+fn print_report(results: &[Result<Sample, String>], elapsed: Duration) {
+    let samples: Vec<Sample> = results
+        .iter()
+        .filter_map(|r| r.as_ref().ok())
+        .copied()
+        .collect();
+    let mut errors: HashMap<&str, usize> = HashMap::new();
+    for error in results.iter().filter_map(|r| r.as_ref().err()) {
+        *errors.entry(error.as_str()).or_default() += 1;
+    }
+
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    let output_tokens: u64 = samples.iter().map(|s| s.output_tokens).sum();
+
+    println!();
+    println!("总耗时:       {:.2}s", secs);
+    println!(
+        "成功 / 失败:  {} / {}",
+        samples.len(),
+        results.len() - samples.len()
+    );
+    println!("吞吐:         {:.2} req/s", samples.len() as f64 / secs);
+    println!("输出吞吐:     {:.1} tokens/s", output_tokens as f64 / secs);
+
+    if !samples.is_empty() {
+        let mut latencies: Vec<Duration> = samples.iter().map(|s| s.latency).collect();
+        let mut ttfts: Vec<Duration> = samples.iter().map(|s| s.ttft).collect();
+        latencies.sort();
+        ttfts.sort();
+        println!(
+            "延迟:         p50 {}  p95 {}  max {}",
+            ms(percentile(&latencies, 50.0)),
+            ms(percentile(&latencies, 95.0)),
+            ms(percentile(&latencies, 100.0))
+        );
+        println!(
+            "首字延迟:     p50 {}  p95 {}  max {}",
+            ms(percentile(&ttfts, 50.0)),
+            ms(percentile(&ttfts, 95.0)),
+            ms(percentile(&ttfts, 100.0))
+        );
+    }
+
+    if !errors.is_empty() {
+        let mut errors: Vec<_> = errors.into_iter().collect();
+        errors.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        println!("错误:");
+        for (error, count) in errors {
+            println!("  {:>5} × {}", count, error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let values: Vec<Duration> = (1..=20).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&values, 50.0), Duration::from_millis(10));
+        assert_eq!(percentile(&values, 95.0), Duration::from_millis(19));
+        assert_eq!(percentile(&values, 100.0), Duration::from_millis(20));
+        assert_eq!(percentile(&values, 0.0), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_encode_event_round_trips() {
+        let frame = encode_event("assistantResponseEvent", &json!({ "content": "你好" }));
+        let (parsed, consumed) = crate::kiro::parser::frame::parse_frame(&frame)
+            .unwrap()
+            .unwrap();
+        assert_eq!(consumed, frame.len());
+        assert_eq!(parsed.message_type(), Some("event"));
+        assert_eq!(parsed.event_type(), Some("assistantResponseEvent"));
+        assert_eq!(parsed.payload_as_str(), r#"{"content":"你好"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_send_one_against_mock() {
+        let target = spawn_mock(Duration::ZERO).await.unwrap();
+        let client = build_client(None, 30).unwrap();
+        let url = format!("{}/v1/messages", target.base_url);
+
+        for stream in [true, false] {
+            let body = json!({
+                "model": "claude-sonnet-4-5-20250929",
+                "max_tokens": 64,
+                "stream": stream,
+                "messages": [{"role": "user", "content": "hi"}]
+            });
+            let sample = send_one(&client, &url, &target.api_key, &body, stream)
+                .await
+                .unwrap();
+            assert!(sample.output_tokens > 0, "stream={}", stream);
+            assert!(sample.ttft <= sample.latency);
+        }
+
+        // Key 错误时返回 HTTP 状态
+        let body = json!({"model": "claude-sonnet-4-5-20250929", "max_tokens": 8, "messages": []});
+        let error = send_one(&client, &url, "wrong-key", &body, true)
+            .await
+            .unwrap_err();
+        assert_eq!(error, "HTTP 401");
+    }
+}
@@ -0,0 +1,387 @@
+//! HTTP 访问日志
+//!
+//! 独立于 tracing 日志，每个请求输出一行访问记录，格式可选 Common / Combined Log Format
+//! （与 nginx、Apache 一致）或 JSON，便于直接复用为 nginx 日志构建的分析流水线。
+//! 记录在响应体发送完毕（或客户端断开）时写出，流式响应的字节数与耗时为实际发送的部分。
+//! 写入在独立线程中进行，不阻塞请求处理
+
+use std::fs::OpenOptions;
+use std::io::{self, LineWriter, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant};
+
+use axum::body::{Body, HttpBody};
+use axum::extract::connect_info::Connected;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{HeaderValue, Version, header};
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::serve::IncomingStream;
+use chrono::{DateTime, Local};
+use futures::StreamExt;
+use tokio::net::TcpListener;
+
+use crate::common::request_id;
+use crate::common::tls::TlsListener;
+use crate::model::config::Config;
+
+/// 访问日志格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    /// Common Log Format：`%h %l %u %t "%r" %>s %b`
+    Common,
+    /// Combined Log Format：Common 格式加 `"%{Referer}i" "%{User-agent}i"`
+    Combined,
+    /// 每行一个 JSON 对象（额外包含耗时与请求 ID）
+    Json,
+}
+
+impl AccessLogFormat {
+    fn parse(value: &str) -> anyhow::Result<Self> {
+        match value {
+            "common" => Ok(Self::Common),
+            "combined" => Ok(Self::Combined),
+            "json" => Ok(Self::Json),
+            other => anyhow::bail!(
+                "accessLogFormat 无效: {}（可选 common、combined、json）",
+                other
+            ),
+        }
+    }
+}
+
+/// 单个请求的访问记录
+#[derive(Debug, Clone)]
+struct Entry {
+    time: DateTime<Local>,
+    remote_addr: Option<SocketAddr>,
+    method: String,
+    uri: String,
+    version: &'static str,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    request_id: Option<String>,
+    status: u16,
+    bytes: u64,
+    duration: Duration,
+}
+
+impl Entry {
+    fn format(&self, format: AccessLogFormat) -> String {
+        let host = self
+            .remote_addr
+            .map_or_else(|| "-".to_string(), |addr| addr.ip().to_string());
+        let bytes = match self.bytes {
+            0 => "-".to_string(),
+            n => n.to_string(),
+        };
+        let common = format!(
+            "{} - - [{}] \"{} {} {}\" {} {}",
+            host,
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            escape(&self.method),
+            escape(&self.uri),
+            self.version,
+            self.status,
+            bytes
+        );
+
+        match format {
+            AccessLogFormat::Common => common,
+            AccessLogFormat::Combined => format!(
+                "{} \"{}\" \"{}\"",
+                common,
+                escape(self.referer.as_deref().unwrap_or("-")),
+                escape(self.user_agent.as_deref().unwrap_or("-"))
+            ),
+            AccessLogFormat::Json => serde_json::json!({
+                "time": self.time.to_rfc3339(),
+                "remoteAddr": self.remote_addr.map(|addr| addr.ip().to_string()),
+                "method": self.method,
+                "uri": self.uri,
+                "protocol": self.version,
+                "status": self.status,
+                "bytes": self.bytes,
+                "referer": self.referer,
+                "userAgent": self.user_agent,
+                "durationMs": self.duration.as_millis() as u64,
+                "requestId": self.request_id,
+            })
+            .to_string(),
+        }
+    }
+}
+
+/// 按 nginx 的方式转义引号字段中的 `"`、`\` 与不可见字符（`\xHH`）
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'"' | b'\\' => escaped.push_str(&format!("\\x{:02X}", byte)),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\x{:02X}", byte)),
+        }
+    }
+    escaped
+}
+
+/// 访问日志
+pub struct AccessLog {
+    format: AccessLogFormat,
+    tx: mpsc::Sender<String>,
+}
+
+impl AccessLog {
+    /// 按配置创建访问日志（未配置 `accessLogFormat` 时返回 None）
+    pub fn from_config(config: &Config) -> anyhow::Result<Option<Arc<Self>>> {
+        let Some(format) = &config.access_log_format else {
+            return Ok(None);
+        };
+        let format = AccessLogFormat::parse(format)?;
+        let path = config.access_log_path.as_deref().unwrap_or("stdout");
+        Self::open(format, path).map(Some)
+    }
+
+    /// 打开写入目标：`stdout`、`stderr` 或文件路径（追加写入）
+    fn open(format: AccessLogFormat, path: &str) -> anyhow::Result<Arc<Self>> {
+        let sink: Box<dyn Write + Send> = match path {
+            "stdout" | "-" => Box::new(io::stdout()),
+            "stderr" => Box::new(io::stderr()),
+            path => Box::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| anyhow::anyhow!("打开访问日志文件 {} 失败: {}", path, e))?,
+            ),
+        };
+
+        let (tx, rx) = mpsc::channel::<String>();
+        std::thread::Builder::new()
+            .name("access-log".to_string())
+            .spawn(move || {
+                let mut sink = LineWriter::new(sink);
+                for line in rx {
+                    if let Err(e) = writeln!(sink, "{}", line) {
+                        tracing::warn!("写入访问日志失败: {}", e);
+                    }
+                }
+            })?;
+        Ok(Arc::new(Self { format, tx }))
+    }
+
+    fn write(&self, entry: &Entry) {
+        // 写入线程仅在进程退出时结束
+        let _ = self.tx.send(entry.format(self.format));
+    }
+}
+
+/// 响应体发送完毕或被丢弃时写出访问记录
+struct Pending {
+    log: Arc<AccessLog>,
+    entry: Entry,
+    start: Instant,
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.entry.duration = self.start.elapsed();
+        self.log.write(&self.entry);
+    }
+}
+
+impl Entry {
+    /// 从请求中提取访问记录（状态码、字节数与耗时在响应后填写）
+    fn from_request(request: &Request) -> Self {
+        let header = |name| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|v: &HeaderValue| v.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            time: Local::now(),
+            remote_addr: request
+                .extensions()
+                .get::<ConnectInfo<RemoteAddr>>()
+                .map(|info| info.0.0),
+            method: request.method().to_string(),
+            uri: request
+                .uri()
+                .path_and_query()
+                .map_or_else(|| request.uri().path().to_string(), |p| p.to_string()),
+            version: match request.version() {
+                Version::HTTP_09 => "HTTP/0.9",
+                Version::HTTP_10 => "HTTP/1.0",
+                Version::HTTP_2 => "HTTP/2.0",
+                Version::HTTP_3 => "HTTP/3.0",
+                _ => "HTTP/1.1",
+            },
+            referer: header(header::REFERER),
+            user_agent: header(header::USER_AGENT),
+            request_id: request_id::current(),
+            status: 0,
+            bytes: 0,
+            duration: Duration::ZERO,
+        }
+    }
+}
+
+/// 客户端地址（`ConnectInfo`，HTTP 与 HTTPS 监听器通用）
+#[derive(Debug, Clone, Copy)]
+pub struct RemoteAddr(pub SocketAddr);
+
+impl Connected<IncomingStream<'_, TcpListener>> for RemoteAddr {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        Self(*stream.remote_addr())
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for RemoteAddr {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        Self(*stream.remote_addr())
+    }
+}
+
+/// 访问日志中间件（需位于请求 ID 中间件之内）
+pub async fn access_log_middleware(
+    State(log): State<Arc<AccessLog>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let entry = Entry::from_request(&request);
+
+    let response = next.run(request).await;
+    let (parts, body) = response.into_parts();
+    let mut pending = Pending { log, entry, start };
+    pending.entry.status = parts.status.as_u16();
+
+    // 长度已知的响应体直接记录，流式响应体在发送完毕时记录
+    if let Some(len) = body.size_hint().exact() {
+        pending.entry.bytes = len;
+        drop(pending);
+        return Response::from_parts(parts, body);
+    }
+    let stream = body.into_data_stream().map(move |chunk| {
+        // 捕获整个 pending，使其随响应体一起释放
+        let pending = &mut pending;
+        if let Ok(data) = &chunk {
+            pending.entry.bytes += data.len() as u64;
+        }
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, middleware, routing::get};
+    use chrono::TimeZone;
+
+    fn entry() -> Entry {
+        Entry {
+            time: Local.with_ymd_and_hms(2025, 10, 10, 13, 55, 36).unwrap(),
+            remote_addr: Some("127.0.0.1:4321".parse().unwrap()),
+            method: "POST".to_string(),
+            uri: "/v1/messages?beta=true".to_string(),
+            version: "HTTP/1.1",
+            referer: None,
+            user_agent: Some("claude-cli/1.0 \"test\"".to_string()),
+            request_id: Some("req-1".to_string()),
+            status: 200,
+            bytes: 2326,
+            duration: Duration::from_millis(1500),
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let entry = entry();
+        let time = entry.time.format("%d/%b/%Y:%H:%M:%S %z").to_string();
+        assert_eq!(
+            entry.format(AccessLogFormat::Common),
+            format!(
+                "127.0.0.1 - - [{}] \"POST /v1/messages?beta=true HTTP/1.1\" 200 2326",
+                time
+            )
+        );
+        assert_eq!(
+            entry.format(AccessLogFormat::Combined),
+            format!(
+                "127.0.0.1 - - [{}] \"POST /v1/messages?beta=true HTTP/1.1\" 200 2326 \
+                 \"-\" \"claude-cli/1.0 \\x22test\\x22\"",
+                time
+            )
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&entry.format(AccessLogFormat::Json)).unwrap();
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["durationMs"], 1500);
+        assert_eq!(json["requestId"], "req-1");
+        assert_eq!(json["referer"], serde_json::Value::Null);
+
+        let empty = Entry {
+            bytes: 0,
+            remote_addr: None,
+            ..entry
+        };
+        assert!(empty.format(AccessLogFormat::Common).starts_with("- - - ["));
+        assert!(empty.format(AccessLogFormat::Common).ends_with(" 200 -"));
+        assert!(AccessLogFormat::parse("nginx").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_middleware_logs_streamed_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let log = AccessLog::open(AccessLogFormat::Combined, path.to_str().unwrap()).unwrap();
+
+        let app = Router::new()
+            .route("/fixed", get(|| async { "hello" }))
+            .route(
+                "/stream",
+                get(|| async {
+                    let chunks = futures::stream::iter(["ab", "cde"])
+                        .map(|s| Ok::<_, std::convert::Infallible>(bytes::Bytes::from(s)));
+                    Body::from_stream(chunks)
+                }),
+            )
+            .layer(middleware::from_fn_with_state(log, access_log_middleware));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<RemoteAddr>(),
+            )
+            .await
+        });
+
+        let client = reqwest::Client::new();
+        for path in ["/fixed", "/stream", "/missing?x=1"] {
+            client
+                .get(format!("{}{}", url, path))
+                .header("user-agent", "test-agent")
+                .send()
+                .await
+                .unwrap()
+                .bytes()
+                .await
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", content);
+        assert!(lines[0].starts_with("127.0.0.1 - - ["));
+        assert!(lines[0].ends_with("\"GET /fixed HTTP/1.1\" 200 5 \"-\" \"test-agent\""));
+        assert!(lines[1].ends_with("\"GET /stream HTTP/1.1\" 200 5 \"-\" \"test-agent\""));
+        assert!(lines[2].ends_with("\"GET /missing?x=1 HTTP/1.1\" 404 - \"-\" \"test-agent\""));
+    }
+}
@@ -2,8 +2,9 @@
 
 use axum::{
     body::Body,
-    http::{Request, header},
+    http::{HeaderMap, Request, header},
 };
+use sha2::{Digest, Sha256};
 use subtle::ConstantTimeEq;
 
 /// 从请求中提取 API Key
@@ -12,24 +13,33 @@ use subtle::ConstantTimeEq;
 /// - `x-api-key` header
 /// - `Authorization: Bearer <token>` header
 pub fn extract_api_key(request: &Request<Body>) -> Option<String> {
+    extract_api_key_from_headers(request.headers())
+}
+
+/// 从请求头中提取 API Key（规则同 [`extract_api_key`]）
+pub fn extract_api_key_from_headers(headers: &HeaderMap) -> Option<String> {
     // 优先检查 x-api-key
-    if let Some(key) = request
-        .headers()
-        .get("x-api-key")
-        .and_then(|v| v.to_str().ok())
-    {
+    if let Some(key) = headers.get("x-api-key").and_then(|v| v.to_str().ok()) {
         return Some(key.to_string());
     }
 
     // 其次检查 Authorization: Bearer
-    request
-        .headers()
+    headers
         .get(header::AUTHORIZATION)
         .and_then(|v| v.to_str().ok())
         .and_then(|v| v.strip_prefix("Bearer "))
         .map(|s| s.to_string())
 }
 
+/// 计算 API Key 指纹（SHA-256 前 16 位十六进制）
+///
+/// 用于在日志、统计等场景中区分客户端，避免保存明文 Key
+pub fn key_fingerprint(api_key: &str) -> String {
+    let mut fingerprint = hex::encode(Sha256::digest(api_key.as_bytes()));
+    fingerprint.truncate(16);
+    fingerprint
+}
+
 /// 常量时间字符串比较，防止时序攻击
 ///
 /// 无论字符串内容如何，比较所需的时间都是恒定的，
@@ -1,3 +1,7 @@
 //! 公共工具模块
 
+pub mod access_log;
 pub mod auth;
+pub mod panic;
+pub mod request_id;
+pub mod tls;
@@ -0,0 +1,66 @@
+//! Panic 捕获与统计
+//!
+//! 全局 panic hook 会为每次 panic 分配唯一 ID，并连同调用栈写入日志；
+//! 请求处理中间件通过 [`take_last_panic`] 获取同一 ID 返回给客户端，便于按 ID 定位日志
+
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use uuid::Uuid;
+
+/// 进程启动以来的 panic 总数
+static PANIC_COUNT: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// 当前线程最近一次 panic 的报告（由 hook 写入，由捕获方取走）
+    static LAST_PANIC: RefCell<Option<PanicReport>> = const { RefCell::new(None) };
+}
+
+/// 单次 panic 的报告
+#[derive(Debug, Clone)]
+pub struct PanicReport {
+    /// panic ID（与日志中的调用栈对应）
+    pub id: String,
+    /// panic 信息
+    pub message: String,
+}
+
+/// 安装全局 panic hook
+///
+/// 应在应用启动时调用一次
+pub fn install_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        let id = Uuid::new_v4().simple().to_string();
+        let payload = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        let message = match info.location() {
+            Some(location) => format!("{} ({})", payload, location),
+            None => payload,
+        };
+
+        PANIC_COUNT.fetch_add(1, Ordering::Relaxed);
+        tracing::error!(
+            panic_id = %id,
+            "发生 panic: {}\n{}",
+            message,
+            Backtrace::force_capture()
+        );
+
+        LAST_PANIC.with(|last| *last.borrow_mut() = Some(PanicReport { id, message }));
+    }));
+}
+
+/// 取走当前线程最近一次 panic 的报告
+pub fn take_last_panic() -> Option<PanicReport> {
+    LAST_PANIC.with(|last| last.borrow_mut().take())
+}
+
+/// 进程启动以来的 panic 总数
+pub fn panic_count() -> u64 {
+    PANIC_COUNT.load(Ordering::Relaxed)
+}
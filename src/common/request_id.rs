@@ -0,0 +1,122 @@
+//! 请求 ID
+//!
+//! 每个请求分配一个请求 ID（客户端携带合法的 `x-request-id` 时沿用），
+//! 在请求处理期间通过任务局部变量传递：日志写入包含请求 ID 的 tracing span，
+//! 上游 Kiro 请求、响应头、错误响应体与请求日志都携带同一个 ID，
+//! 便于将客户端看到的错误与服务端日志及实际使用的凭据关联起来
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+/// 请求 ID 请求头/响应头
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// 客户端请求 ID 的最大长度
+const MAX_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// 当前请求的 ID（不在请求处理期间时为 None）
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// 在当前请求的 tracing span 中记录实际使用的凭据（后续日志均带有该字段）
+pub fn record_credential(id: u64) {
+    tracing::Span::current().record("credential_id", id);
+}
+
+/// 上游调用 ID（`amz-sdk-invocation-id`）：请求 ID 为 UUID 时直接使用，便于对照上游日志，否则生成新 ID
+pub fn invocation_id() -> String {
+    current()
+        .filter(|id| uuid::Uuid::parse_str(id).is_ok())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// 沿用客户端携带的请求 ID（仅接受长度不超过 128 的可见 ASCII 字符），否则生成新 ID
+fn resolve(value: Option<&HeaderValue>) -> String {
+    value
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty() && v.len() <= MAX_LEN && v.bytes().all(|b| b.is_ascii_graphic()))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// 请求 ID 中间件：分配请求 ID，在其作用域与 tracing span 内处理请求，并写入响应头
+pub async fn request_id_middleware(request: Request, next: Next) -> Response {
+    let id = resolve(request.headers().get(&REQUEST_ID_HEADER));
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        credential_id = tracing::field::Empty
+    );
+
+    let mut response = REQUEST_ID
+        .scope(id.clone(), next.run(request).instrument(span))
+        .await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, middleware, routing::get};
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            resolve(Some(&HeaderValue::from_static(" req_123 "))),
+            "req_123"
+        );
+        for invalid in [
+            None,
+            Some("".to_string()),
+            Some("a b".to_string()),
+            Some("x".repeat(129)),
+        ] {
+            let value = invalid.map(|v| HeaderValue::from_str(&v).unwrap());
+            let id = resolve(value.as_ref());
+            assert!(uuid::Uuid::parse_str(&id).is_ok(), "{}", id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware_propagates_id() {
+        let app = Router::new()
+            .route("/", get(|| async { current().unwrap_or_default() }))
+            .layer(middleware::from_fn(request_id_middleware));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(&url)
+            .header("x-request-id", "client-id")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "client-id");
+        assert_eq!(response.text().await.unwrap(), "client-id");
+
+        let response = client.get(&url).send().await.unwrap();
+        let id = response.headers()[REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(uuid::Uuid::parse_str(&id).is_ok());
+        assert_eq!(response.text().await.unwrap(), id);
+        assert!(current().is_none());
+    }
+}
@@ -0,0 +1,204 @@
+//! 内置 HTTPS
+//!
+//! 配置 `tlsCertPath` / `tlsKeyPath` 后直接以 HTTPS 提供服务，小规模部署无需额外的反向代理。
+//! TLS 握手在独立任务中进行（带超时），慢速或异常客户端不会阻塞其他连接的接入；
+//! 可选在 `tlsRedirectPort` 上监听明文 HTTP 并重定向到 HTTPS
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use axum::Router;
+use axum::http::{HeaderMap, StatusCode, Uri, header};
+use axum::response::{IntoResponse, Redirect};
+use axum::serve::Listener;
+use rustls::ServerConfig;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+
+/// TLS 握手超时
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 已完成握手、等待交给 HTTP 服务的连接数
+const ACCEPT_BACKLOG: usize = 64;
+
+/// 从 PEM 文件加载证书链与私钥
+pub fn load_acceptor(cert_path: &str, key_path: &str) -> anyhow::Result<TlsAcceptor> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("读取证书文件 {} 失败", cert_path))?;
+    if certs.is_empty() {
+        anyhow::bail!("证书文件 {} 中没有证书", cert_path);
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .with_context(|| format!("读取私钥文件 {} 失败", key_path))?;
+
+    let mut config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .context("初始化 TLS 失败")?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .context("证书与私钥不匹配或格式不受支持")?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// HTTPS 监听器
+///
+/// 在后台任务中接受 TCP 连接并完成 TLS 握手，握手成功的连接交给 `axum::serve`
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    rx: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    pub fn new(mut listener: TcpListener, acceptor: TlsAcceptor) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (tx, rx) = mpsc::channel(ACCEPT_BACKLOG);
+
+        tokio::spawn(async move {
+            while !tx.is_closed() {
+                let (stream, addr) = Listener::accept(&mut listener).await;
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = tx.send((stream, addr)).await;
+                        }
+                        Ok(Err(e)) => tracing::debug!("TLS 握手失败（{}）: {}", addr, e),
+                        Err(_) => tracing::debug!("TLS 握手超时（{}）", addr),
+                    }
+                });
+            }
+        });
+
+        Ok(Self { local_addr, rx })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.rx.recv().await {
+            Some(connection) => connection,
+            // 接入任务仅在监听器被丢弃后退出
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// 创建 HTTP → HTTPS 重定向服务（所有请求以 308 重定向到同一主机的 `https_port`）
+pub fn redirect_router(https_port: u16) -> Router {
+    Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move {
+        let Some(host) = headers.get(header::HOST).and_then(|h| h.to_str().ok()) else {
+            return (StatusCode::BAD_REQUEST, "Missing Host header").into_response();
+        };
+        let path = uri.path_and_query().map_or("/", |p| p.as_str());
+        Redirect::permanent(&https_url(host, https_port, path)).into_response()
+    })
+}
+
+/// 按请求的 Host 构建 HTTPS 地址（替换端口，443 时省略）
+fn https_url(host: &str, https_port: u16, path_and_query: &str) -> String {
+    let hostname = if host.starts_with('[') {
+        // IPv6 字面量：[::1]:8080
+        host.find(']').map_or(host, |end| &host[..=end])
+    } else {
+        host.split(':').next().unwrap_or(host)
+    };
+    match https_port {
+        443 => format!("https://{}{}", hostname, path_and_query),
+        port => format!("https://{}:{}{}", hostname, port, path_and_query),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_https_url() {
+        assert_eq!(
+            https_url("example.com", 443, "/v1/messages?beta=true"),
+            "https://example.com/v1/messages?beta=true"
+        );
+        assert_eq!(
+            https_url("example.com:8080", 8443, "/"),
+            "https://example.com:8443/"
+        );
+        assert_eq!(https_url("[::1]:8080", 8443, "/a"), "https://[::1]:8443/a");
+        assert_eq!(https_url("[::1]", 443, "/"), "https://[::1]/");
+    }
+
+    /// 测试用自签名证书（CN=localhost）
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[test]
+    fn test_load_acceptor_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert = dir.path().join("cert.pem");
+        let key = fixture("key.pem");
+
+        let err = load_acceptor(cert.to_str().unwrap(), &key).err().unwrap();
+        assert!(err.to_string().contains("读取证书文件"));
+
+        std::fs::write(&cert, "not a certificate").unwrap();
+        let err = load_acceptor(cert.to_str().unwrap(), &key).err().unwrap();
+        assert!(err.to_string().contains("没有证书"));
+    }
+
+    #[tokio::test]
+    async fn test_tls_listener_serves_https() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let acceptor = load_acceptor(&fixture("cert.pem"), &fixture("key.pem")).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listener = TlsListener::new(listener, acceptor).unwrap();
+        let addr = Listener::local_addr(&listener).unwrap();
+        let app = Router::new().route("/ping", axum::routing::get(|| async { "pong" }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in CertificateDer::pem_file_iter(fixture("cert.pem")).unwrap() {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let config = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut stream = connector
+            .connect("localhost".try_into().unwrap(), stream)
+            .await
+            .unwrap();
+
+        stream
+            .write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("pong"));
+    }
+}
@@ -1,9 +1,200 @@
 //! HTTP Client 构建模块
 //!
-//! 提供统一的 HTTP Client 构建功能，支持代理配置
+//! 提供统一的 HTTP Client 构建功能，支持代理配置、DNS 静态解析覆盖与 DNS-over-HTTPS
 
+use parking_lot::Mutex;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
 use reqwest::{Client, Proxy};
-use std::time::Duration;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// 全局 DNS 静态解析覆盖（域名 -> 地址）
+static DNS_OVERRIDES: OnceLock<Vec<(String, SocketAddr)>> = OnceLock::new();
+
+/// 解析 DNS 覆盖配置（域名 -> IP）
+///
+/// reqwest 会忽略覆盖地址中的端口，实际使用 URL 中的端口
+fn parse_dns_overrides(
+    overrides: &HashMap<String, String>,
+) -> anyhow::Result<Vec<(String, SocketAddr)>> {
+    let mut parsed: Vec<(String, SocketAddr)> = overrides
+        .iter()
+        .map(|(host, ip)| {
+            let ip: IpAddr = ip.trim().parse().map_err(|e| {
+                anyhow::anyhow!("dnsOverrides 中 {} 的 IP 无效 ({}): {}", host, ip, e)
+            })?;
+            Ok((host.trim().to_ascii_lowercase(), SocketAddr::new(ip, 0)))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    parsed.sort();
+    Ok(parsed)
+}
+
+/// 初始化 DNS 静态解析覆盖
+///
+/// 应在应用启动时、创建任何 HTTP Client 之前调用一次
+pub fn init_dns_overrides(overrides: &HashMap<String, String>) -> anyhow::Result<()> {
+    let parsed = parse_dns_overrides(overrides)?;
+    for (host, addr) in &parsed {
+        tracing::info!("DNS 覆盖: {} -> {}", host, addr.ip());
+    }
+    let _ = DNS_OVERRIDES.set(parsed);
+    Ok(())
+}
+
+/// 全局 DNS-over-HTTPS 解析器（配置 `dohUrl` 时启用）
+static DOH_RESOLVER: OnceLock<DohResolver> = OnceLock::new();
+
+/// DoH 解析结果的最短/最长缓存时间（秒）
+const DOH_MIN_TTL_SECS: u64 = 30;
+const DOH_MAX_TTL_SECS: u64 = 3600;
+
+/// DoH JSON 响应（`application/dns-json`）
+#[derive(Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Status")]
+    status: u32,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    #[serde(rename = "TTL", default)]
+    ttl: u64,
+    data: String,
+}
+
+/// 缓存的解析结果
+struct CachedAddrs {
+    addrs: Vec<IpAddr>,
+    expires_at: Instant,
+}
+
+/// DNS-over-HTTPS 解析器
+///
+/// 通过 DoH JSON 接口（如 `https://1.1.1.1/dns-query`）解析 A/AAAA 记录，按 TTL 缓存；
+/// DoH 查询失败或无结果时回退到系统 DNS。DoH 服务器自身使用系统 DNS 解析，
+/// 建议配置为 IP 地址以避免引导问题
+#[derive(Clone)]
+pub struct DohResolver {
+    url: Arc<str>,
+    /// 查询 DoH 服务器使用的 Client（系统 DNS，不经过本解析器）
+    client: Client,
+    cache: Arc<Mutex<HashMap<String, CachedAddrs>>>,
+}
+
+impl DohResolver {
+    /// 创建解析器
+    pub fn new(url: impl Into<String>, timeout: Duration) -> anyhow::Result<Self> {
+        let url = url.into();
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            anyhow::bail!("dohUrl 必须是 http(s) 地址: {}", url);
+        }
+        Ok(Self {
+            url: url.into(),
+            client: Client::builder().timeout(timeout).build()?,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// 查询单类记录，返回地址与最小 TTL
+    async fn query(&self, host: &str, record_type: u16) -> anyhow::Result<(Vec<IpAddr>, u64)> {
+        let response: DohResponse = self
+            .client
+            .get(&*self.url)
+            .query(&[("name", host), ("type", &record_type.to_string())])
+            .header("accept", "application/dns-json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if response.status != 0 {
+            // NXDOMAIN 等：视为无记录
+            return Ok((Vec::new(), DOH_MIN_TTL_SECS));
+        }
+        let mut ttl = DOH_MAX_TTL_SECS;
+        let addrs = response
+            .answer
+            .iter()
+            .filter(|a| a.record_type == record_type)
+            .filter_map(|a| {
+                ttl = ttl.min(a.ttl);
+                a.data.parse().ok()
+            })
+            .collect();
+        Ok((addrs, ttl))
+    }
+
+    /// 通过 DoH 解析域名（A 与 AAAA 并发查询，IPv4 优先）
+    async fn lookup(&self, host: &str) -> anyhow::Result<Vec<IpAddr>> {
+        let host = host.to_ascii_lowercase();
+        if let Some(cached) = self.cache.lock().get(&host)
+            && cached.expires_at > Instant::now()
+        {
+            return Ok(cached.addrs.clone());
+        }
+
+        let (v4, v6) = futures::join!(self.query(&host, 1), self.query(&host, 28));
+        let (mut addrs, mut ttl) = v4?;
+        // AAAA 查询失败不影响 A 记录结果
+        if let Ok((v6, v6_ttl)) = v6 {
+            addrs.extend(v6);
+            ttl = ttl.min(v6_ttl);
+        }
+        if addrs.is_empty() {
+            anyhow::bail!("DoH 未返回 {} 的地址", host);
+        }
+
+        let ttl = ttl.clamp(DOH_MIN_TTL_SECS, DOH_MAX_TTL_SECS);
+        self.cache.lock().insert(
+            host,
+            CachedAddrs {
+                addrs: addrs.clone(),
+                expires_at: Instant::now() + Duration::from_secs(ttl),
+            },
+        );
+        Ok(addrs)
+    }
+}
+
+impl Resolve for DohResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Addrs = match resolver.lookup(host).await {
+                Ok(ips) => Box::new(ips.into_iter().map(|ip| SocketAddr::new(ip, 0))),
+                Err(e) => {
+                    tracing::warn!("DoH 解析 {} 失败，回退到系统 DNS: {}", host, e);
+                    let addrs: Vec<SocketAddr> =
+                        tokio::net::lookup_host((host, 0)).await?.collect();
+                    Box::new(addrs.into_iter())
+                }
+            };
+            Ok(addrs)
+        })
+    }
+}
+
+/// 初始化 DNS-over-HTTPS 解析
+///
+/// 应在应用启动时、创建任何 HTTP Client 之前调用一次；`url` 为 None 时使用系统 DNS
+pub fn init_doh(url: Option<&str>, timeout: Duration) -> anyhow::Result<()> {
+    let Some(url) = url.filter(|u| !u.trim().is_empty()) else {
+        return Ok(());
+    };
+    let resolver = DohResolver::new(url.trim(), timeout)?;
+    tracing::info!("已启用 DNS-over-HTTPS: {}", url.trim());
+    let _ = DOH_RESOLVER.set(resolver);
+    Ok(())
+}
 
 /// 代理配置
 #[derive(Debug, Clone, Default)]
@@ -45,6 +236,17 @@ impl ProxyConfig {
 pub fn build_client(proxy: Option<&ProxyConfig>, timeout_secs: u64) -> anyhow::Result<Client> {
     let mut builder = Client::builder().timeout(Duration::from_secs(timeout_secs));
 
+    // 静态覆盖优先于 DoH
+    if let Some(resolver) = DOH_RESOLVER.get() {
+        builder = builder.dns_resolver(Arc::new(resolver.clone()));
+    }
+
+    if let Some(overrides) = DNS_OVERRIDES.get() {
+        for (host, addr) in overrides {
+            builder = builder.resolve(host, *addr);
+        }
+    }
+
     if let Some(proxy_config) = proxy {
         let mut proxy = Proxy::all(&proxy_config.url)?;
 
@@ -86,6 +288,77 @@ mod tests {
         assert!(client.is_ok());
     }
 
+    #[test]
+    fn test_parse_dns_overrides() {
+        let overrides = HashMap::from([
+            (
+                "Q.us-east-1.amazonaws.com".to_string(),
+                "10.0.0.5".to_string(),
+            ),
+            (
+                "oidc.us-east-1.amazonaws.com".to_string(),
+                " ::1 ".to_string(),
+            ),
+        ]);
+        let parsed = parse_dns_overrides(&overrides).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].0, "oidc.us-east-1.amazonaws.com");
+        assert_eq!(parsed[0].1.ip(), "::1".parse::<IpAddr>().unwrap());
+        assert_eq!(parsed[1].0, "q.us-east-1.amazonaws.com");
+
+        let invalid = HashMap::from([("example.com".to_string(), "not-an-ip".to_string())]);
+        assert!(parse_dns_overrides(&invalid).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_doh_resolver() {
+        use axum::{Json, Router, extract::Query, routing::get};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let queries = Arc::new(AtomicUsize::new(0));
+        let counter = queries.clone();
+        let app = Router::new().route(
+            "/dns-query",
+            get(move |Query(params): Query<HashMap<String, String>>| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    let answer = match (params["name"].as_str(), params["type"].as_str()) {
+                        ("q.us-east-1.amazonaws.com", "1") => serde_json::json!([
+                            {"name": "q.us-east-1.amazonaws.com", "type": 5, "TTL": 60, "data": "alias.example."},
+                            {"name": "alias.example", "type": 1, "TTL": 120, "data": "10.0.0.7"}
+                        ]),
+                        _ => serde_json::json!([]),
+                    };
+                    Json(serde_json::json!({"Status": 0, "Answer": answer}))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let resolver =
+            DohResolver::new(format!("http://{}/dns-query", addr), Duration::from_secs(2)).unwrap();
+        let ips = resolver.lookup("Q.us-east-1.amazonaws.com").await.unwrap();
+        assert_eq!(ips, vec!["10.0.0.7".parse::<IpAddr>().unwrap()]);
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        // 命中缓存，不再查询
+        resolver.lookup("q.us-east-1.amazonaws.com").await.unwrap();
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        // 无记录时回退到系统 DNS
+        assert!(resolver.lookup("localhost").await.is_err());
+        let addrs: Vec<SocketAddr> = resolver
+            .resolve("localhost".parse().unwrap())
+            .await
+            .unwrap()
+            .collect();
+        assert!(!addrs.is_empty());
+
+        assert!(DohResolver::new("1.1.1.1", Duration::from_secs(2)).is_err());
+    }
+
     #[test]
     fn test_build_client_with_proxy() {
         let config = ProxyConfig::new("http://127.0.0.1:7890");
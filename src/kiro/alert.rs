@@ -0,0 +1,573 @@
+//! 邮件告警
+//!
+//! 凭据因失败被熔断（自动禁用）、所有凭据均已禁用等关键事件通过 SMTP 邮件通知运维人员。
+//! 事件经有界通道交给后台发送任务，请求处理路径上不进行网络 I/O；
+//! 同一事件（同类型、同凭据）在冷却时间内只发送一次，避免凭据反复恢复、禁用时刷屏。
+//!
+//! 待发送的邮件先写入数据库的 `notifications` 表再投递：SMTP 服务器暂时不可用时按指数退避重试，
+//! 重试次数耗尽后进入死信状态，可通过 Admin API 查看并重放；进程重启后继续投递未完成的通知
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, bail};
+use lettre::message::{Mailbox, header::ContentType};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use tokio::sync::mpsc;
+
+use crate::kiro::db::Database;
+use crate::kiro::lease::Lease;
+use crate::kiro::model::notification::Notification;
+use crate::model::config::Config;
+
+/// 待发送事件最多缓冲的条数
+const CHANNEL_CAPACITY: usize = 256;
+
+/// 单封邮件的发送超时
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 检查待投递通知的最长间隔（通过 Admin API 重放的通知最迟在此间隔后投递）
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// 告警通知投递租约名称（多实例共享数据库时只由持有者投递）
+const NOTIFICATION_LEASE: &str = "notifications";
+
+/// 每轮最多投递的通知数
+const BATCH_SIZE: usize = 20;
+
+/// 重试退避基数，第 n 次失败后等待 `基数 × 2^(n-1)`
+const RETRY_BACKOFF_BASE: Duration = Duration::from_secs(30);
+
+/// 重试退避上限
+const RETRY_BACKOFF_MAX: Duration = Duration::from_secs(3600);
+
+/// 支持的事件类型（`alertEmailEvents` 的可选值）
+pub const EVENT_KINDS: &[&str] = &["credential_disabled", "all_credentials_disabled"];
+
+/// 告警事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlertEvent {
+    /// 凭据失败达到熔断阈值，已被自动禁用
+    CredentialDisabled { id: u64, failures: u32 },
+    /// 所有凭据均已禁用，服务无法处理请求
+    AllCredentialsDisabled { total: usize },
+}
+
+impl AlertEvent {
+    /// 事件类型
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AlertEvent::CredentialDisabled { .. } => "credential_disabled",
+            AlertEvent::AllCredentialsDisabled { .. } => "all_credentials_disabled",
+        }
+    }
+
+    /// 冷却去重使用的键
+    fn dedup_key(&self) -> String {
+        match self {
+            AlertEvent::CredentialDisabled { id, .. } => format!("{}:{}", self.kind(), id),
+            AlertEvent::AllCredentialsDisabled { .. } => self.kind().to_string(),
+        }
+    }
+
+    fn subject(&self) -> String {
+        match self {
+            AlertEvent::CredentialDisabled { id, .. } => {
+                format!("[kiro-rs] 凭据 #{} 已被禁用", id)
+            }
+            AlertEvent::AllCredentialsDisabled { .. } => "[kiro-rs] 所有凭据均已禁用".to_string(),
+        }
+    }
+
+    fn body(&self) -> String {
+        match self {
+            AlertEvent::CredentialDisabled { id, failures } => format!(
+                "凭据 #{} 已失败 {} 次，已被熔断（自动禁用）。\n\
+                 熔断期过后会发送一次探测请求，成功则自动恢复；也可以通过 Admin API 手动启用。",
+                id, failures
+            ),
+            AlertEvent::AllCredentialsDisabled { total } => format!(
+                "所有凭据（共 {} 个）均已禁用，服务当前无法处理请求。\n\
+                 请检查凭据状态，并通过 Admin API 启用或添加凭据。",
+                total
+            ),
+        }
+    }
+}
+
+/// 告警事件发送句柄（可克隆，共享同一个发送任务）
+#[derive(Clone)]
+pub struct AlertSender {
+    tx: mpsc::Sender<AlertEvent>,
+}
+
+impl AlertSender {
+    /// 提交告警事件（不等待发送）
+    pub fn notify(&self, event: AlertEvent) {
+        if let Err(e) = self.tx.try_send(event) {
+            tracing::warn!("告警缓冲已满或发送任务已退出，丢弃告警: {}", e);
+        }
+    }
+}
+
+/// SMTP 连接加密方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpSecurity {
+    /// 隐式 TLS（通常为 465 端口）
+    Tls,
+    /// 明文连接后通过 STARTTLS 升级（通常为 587 端口）
+    StartTls,
+    /// 不加密（仅用于内网中继）
+    None,
+}
+
+impl SmtpSecurity {
+    fn parse(value: &str) -> anyhow::Result<Self> {
+        match value {
+            "tls" => Ok(SmtpSecurity::Tls),
+            "starttls" => Ok(SmtpSecurity::StartTls),
+            "none" => Ok(SmtpSecurity::None),
+            other => bail!(
+                "alertSmtpSecurity 无效: {}（可选 tls、starttls、none）",
+                other
+            ),
+        }
+    }
+}
+
+/// 邮件告警配置
+#[derive(Debug, Clone)]
+pub struct EmailConfig {
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// 需要发送邮件的事件类型
+    pub events: Vec<String>,
+    /// 同一事件的最小发送间隔
+    pub cooldown: Duration,
+    /// 单个通知的最大投递次数，耗尽后进入死信状态
+    pub max_attempts: u32,
+}
+
+impl EmailConfig {
+    /// 从应用配置读取邮件告警配置（未配置 `alertSmtpHost` 时为 None）
+    pub fn from_config(config: &Config) -> anyhow::Result<Option<Self>> {
+        let Some(host) = config.alert_smtp_host.clone() else {
+            return Ok(None);
+        };
+        let Some(from) = config.alert_email_from.clone() else {
+            bail!("已配置 alertSmtpHost，但未设置 alertEmailFrom");
+        };
+        if config.alert_email_to.is_empty() {
+            bail!("已配置 alertSmtpHost，但 alertEmailTo 为空");
+        }
+        for address in std::iter::once(&from).chain(&config.alert_email_to) {
+            validate_address(address)?;
+        }
+        for event in &config.alert_email_events {
+            if !EVENT_KINDS.contains(&event.as_str()) {
+                bail!(
+                    "alertEmailEvents 包含未知事件: {}（可选 {}）",
+                    event,
+                    EVENT_KINDS.join("、")
+                );
+            }
+        }
+        if config.alert_smtp_username.is_some() != config.alert_smtp_password.is_some() {
+            bail!("alertSmtpUsername 与 alertSmtpPassword 需要同时设置");
+        }
+
+        Ok(Some(Self {
+            host,
+            port: config.alert_smtp_port,
+            security: SmtpSecurity::parse(&config.alert_smtp_security)?,
+            username: config.alert_smtp_username.clone(),
+            password: config.alert_smtp_password.clone(),
+            from,
+            to: config.alert_email_to.clone(),
+            events: config.alert_email_events.clone(),
+            cooldown: Duration::from_secs(config.alert_email_cooldown_secs),
+            max_attempts: config.alert_max_attempts.max(1),
+        }))
+    }
+}
+
+/// 校验邮件地址（支持 `Name <user@example.com>` 形式）
+fn validate_address(address: &str) -> anyhow::Result<()> {
+    address
+        .parse::<Mailbox>()
+        .with_context(|| format!("邮件地址无效: {:?}", address))?;
+    Ok(())
+}
+
+/// 启动邮件发送任务（需在 tokio 运行时中调用）
+pub fn spawn(config: EmailConfig, db: Arc<Database>) -> AlertSender {
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+    tokio::spawn(run(config, db, rx));
+    AlertSender { tx }
+}
+
+/// 发送任务主循环：事件按类型过滤与冷却时间决定是否入队，然后投递到期的通知
+async fn run(config: EmailConfig, db: Arc<Database>, mut rx: mpsc::Receiver<AlertEvent>) {
+    let mut throttle = Throttle::new(config.cooldown);
+    // 多实例共享数据库时只由持有租约的实例投递，其他实例只负责入队
+    let lease = Lease::spawn(db.clone(), NOTIFICATION_LEASE);
+    loop {
+        let wait = if lease.is_held() {
+            deliver_due(&config, &db).await;
+            next_delivery_wait(&db).await
+        } else {
+            POLL_INTERVAL
+        };
+
+        tokio::select! {
+            event = rx.recv() => {
+                let Some(event) = event else { break };
+                enqueue(&config, &db, &mut throttle, event).await;
+            }
+            _ = tokio::time::sleep(wait) => {}
+        }
+    }
+}
+
+/// 距下一个待投递通知的等待时间（最长 `POLL_INTERVAL`）
+async fn next_delivery_wait(db: &Arc<Database>) -> Duration {
+    match db.call(|db| db.next_notification_at()).await {
+        Ok(Some(next)) => (next - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default()
+            .min(POLL_INTERVAL),
+        Ok(None) => POLL_INTERVAL,
+        Err(e) => {
+            tracing::warn!("读取待投递通知失败: {}", e);
+            POLL_INTERVAL
+        }
+    }
+}
+
+/// 过滤事件并写入投递队列
+async fn enqueue(
+    config: &EmailConfig,
+    db: &Arc<Database>,
+    throttle: &mut Throttle,
+    event: AlertEvent,
+) {
+    if !config.events.iter().any(|kind| kind == event.kind()) {
+        return;
+    }
+    if !throttle.allow(&event.dedup_key(), Instant::now()) {
+        tracing::debug!("告警处于冷却期，跳过邮件: {}", event.dedup_key());
+        return;
+    }
+
+    let (kind, subject, body) = (event.kind(), event.subject(), event.body());
+    if let Err(e) = db
+        .call(move |db| db.enqueue_notification(kind, &subject, &body))
+        .await
+    {
+        tracing::warn!("告警写入投递队列失败: {}", e);
+    }
+}
+
+/// 投递所有到期的通知
+async fn deliver_due(config: &EmailConfig, db: &Arc<Database>) {
+    let due = match db
+        .call(|db| db.due_notifications(chrono::Utc::now(), BATCH_SIZE))
+        .await
+    {
+        Ok(due) => due,
+        Err(e) => {
+            tracing::warn!("读取待投递通知失败: {}", e);
+            return;
+        }
+    };
+
+    for notification in due {
+        let result = deliver(config, &notification).await;
+        let id = notification.id;
+        let update = match result {
+            Ok(()) => {
+                tracing::info!("已发送告警邮件: {}", notification.subject);
+                db.call(move |db| db.mark_notification_delivered(id)).await
+            }
+            Err(e) => {
+                let error = format!("{:#}", e);
+                let attempts = notification.attempts + 1;
+                let next_attempt_at = (attempts < config.max_attempts).then(|| {
+                    chrono::Utc::now()
+                        + chrono::Duration::from_std(retry_backoff(attempts)).unwrap_or_default()
+                });
+                match next_attempt_at {
+                    Some(at) => tracing::warn!(
+                        "发送告警邮件失败（第 {} 次），将于 {} 重试: {}",
+                        attempts,
+                        at.to_rfc3339(),
+                        error
+                    ),
+                    None => tracing::error!(
+                        "发送告警邮件失败（第 {} 次），已放弃，可通过 Admin API 重放（通知 #{}）: {}",
+                        attempts,
+                        id,
+                        error
+                    ),
+                }
+                db.call(move |db| db.mark_notification_failed(id, &error, next_attempt_at))
+                    .await
+            }
+        };
+        if let Err(e) = update {
+            tracing::warn!("更新通知 #{} 投递状态失败: {}", id, e);
+        }
+    }
+}
+
+/// 投递单个通知
+async fn deliver(config: &EmailConfig, notification: &Notification) -> anyhow::Result<()> {
+    let message = build_message(config, &notification.subject, &notification.body)?;
+    match tokio::time::timeout(SEND_TIMEOUT, send_mail(config, message)).await {
+        Ok(result) => result,
+        Err(_) => bail!("发送超时"),
+    }
+}
+
+/// 第 `attempts` 次失败后的重试等待时间
+fn retry_backoff(attempts: u32) -> Duration {
+    RETRY_BACKOFF_BASE
+        .saturating_mul(1u32 << attempts.saturating_sub(1).min(16))
+        .min(RETRY_BACKOFF_MAX)
+}
+
+/// 按事件键记录最近一次发送时间
+struct Throttle {
+    cooldown: Duration,
+    last_sent: HashMap<String, Instant>,
+}
+
+impl Throttle {
+    fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            last_sent: HashMap::new(),
+        }
+    }
+
+    /// 冷却期外返回 true 并记录本次发送
+    fn allow(&mut self, key: &str, now: Instant) -> bool {
+        if let Some(last) = self.last_sent.get(key)
+            && now.duration_since(*last) < self.cooldown
+        {
+            return false;
+        }
+        self.last_sent.insert(key.to_string(), now);
+        true
+    }
+}
+
+/// 构建邮件（标题与正文的编码由 lettre 处理）
+fn build_message(config: &EmailConfig, subject: &str, body: &str) -> anyhow::Result<Message> {
+    let mut builder = Message::builder()
+        .from(config.from.parse().context("alertEmailFrom 无效")?)
+        .subject(subject)
+        .message_id(Some(format!("<{}@kiro-rs>", uuid::Uuid::new_v4())))
+        .header(ContentType::TEXT_PLAIN);
+    for to in &config.to {
+        builder = builder.to(to
+            .parse()
+            .with_context(|| format!("alertEmailTo 无效: {}", to))?);
+    }
+    builder.body(body.to_string()).context("构建告警邮件失败")
+}
+
+/// 按配置创建 SMTP 传输（TLS 使用 rustls）
+fn transport(config: &EmailConfig) -> anyhow::Result<AsyncSmtpTransport<Tokio1Executor>> {
+    let builder = match config.security {
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+        SmtpSecurity::StartTls => {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?
+        }
+        SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+    };
+    let mut builder = builder.port(config.port).timeout(Some(SEND_TIMEOUT));
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    Ok(builder.build())
+}
+
+/// 通过 SMTP 发送一封邮件
+async fn send_mail(config: &EmailConfig, message: Message) -> anyhow::Result<()> {
+    transport(config)?
+        .send(message)
+        .await
+        .with_context(|| format!("通过 SMTP 服务器 {}:{} 发送失败", config.host, config.port))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn email_config(port: u16) -> EmailConfig {
+        EmailConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            security: SmtpSecurity::None,
+            username: Some("ops".to_string()),
+            password: Some("secret".to_string()),
+            from: "kiro@example.com".to_string(),
+            to: vec!["a@example.com".to_string(), "b@example.com".to_string()],
+            events: EVENT_KINDS.iter().map(|s| s.to_string()).collect(),
+            cooldown: Duration::from_secs(600),
+            max_attempts: 3,
+        }
+    }
+
+    /// 极简 SMTP 服务器：按顺序应答并记录收到的全部内容
+    async fn fake_smtp_server(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut transcript = String::new();
+        reader
+            .get_mut()
+            .write_all(b"220 localhost ESMTP\r\n")
+            .await
+            .unwrap();
+
+        let mut in_data = false;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+            transcript.push_str(&line);
+            let reply: &[u8] = if in_data {
+                if line != ".\r\n" {
+                    continue;
+                }
+                in_data = false;
+                b"250 queued\r\n"
+            } else if line.starts_with("EHLO") {
+                b"250-localhost\r\n250 AUTH PLAIN\r\n"
+            } else if line.starts_with("AUTH") {
+                b"235 ok\r\n"
+            } else if line.starts_with("DATA") {
+                in_data = true;
+                b"354 go ahead\r\n"
+            } else if line.starts_with("QUIT") {
+                reader.get_mut().write_all(b"221 bye\r\n").await.unwrap();
+                break;
+            } else {
+                b"250 ok\r\n"
+            };
+            reader.get_mut().write_all(reply).await.unwrap();
+        }
+        transcript
+    }
+
+    #[tokio::test]
+    async fn test_send_mail() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = email_config(listener.local_addr().unwrap().port());
+        let server = tokio::spawn(fake_smtp_server(listener));
+
+        let event = AlertEvent::AllCredentialsDisabled { total: 3 };
+        let message = build_message(&config, &event.subject(), &event.body()).unwrap();
+        send_mail(&config, message).await.unwrap();
+
+        let transcript = server.await.unwrap();
+        let auth = BASE64.encode("\0ops\0secret");
+        assert!(transcript.contains(&format!("AUTH PLAIN {}\r\n", auth)));
+        assert!(transcript.contains("MAIL FROM:<kiro@example.com>"));
+        assert!(transcript.contains("RCPT TO:<a@example.com>"));
+        assert!(transcript.contains("RCPT TO:<b@example.com>"));
+        assert!(transcript.contains(&format!(
+            "Subject: [kiro-rs] =?utf-8?b?{}?=\r\n",
+            BASE64.encode("所有凭据均已禁用")
+        )));
+        assert!(transcript.contains("Message-ID: <"));
+        assert!(transcript.ends_with(".\r\nQUIT\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_retries_then_dead_letters() {
+        use crate::kiro::model::notification::NotificationStatus;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::open(dir.path().join("test.db")).unwrap());
+        // 绑定后立即释放端口，连接会被拒绝
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let config = email_config(port);
+        db.enqueue_notification("all_credentials_disabled", "subject", "body")
+            .unwrap();
+
+        deliver_due(&config, &db).await;
+        let queued = db.list_notifications(None, 10).unwrap();
+        assert_eq!(queued[0].status, NotificationStatus::Pending);
+        assert_eq!(queued[0].attempts, 1);
+        assert!(queued[0].last_error.is_some());
+        // 未到重试时间
+        deliver_due(&config, &db).await;
+        assert_eq!(db.list_notifications(None, 10).unwrap()[0].attempts, 1);
+
+        // 模拟又一次失败且已到重试时间，第 3 次失败后重试次数耗尽，进入死信
+        db.mark_notification_failed(queued[0].id, "x", Some(chrono::Utc::now()))
+            .unwrap();
+        deliver_due(&config, &db).await;
+        let dead = db.list_notifications(None, 10).unwrap();
+        assert_eq!(dead[0].status, NotificationStatus::Dead);
+        assert_eq!(dead[0].attempts, 3);
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        assert_eq!(retry_backoff(1), Duration::from_secs(30));
+        assert_eq!(retry_backoff(2), Duration::from_secs(60));
+        assert_eq!(retry_backoff(4), Duration::from_secs(240));
+        assert_eq!(retry_backoff(10), RETRY_BACKOFF_MAX);
+        assert_eq!(retry_backoff(u32::MAX), RETRY_BACKOFF_MAX);
+    }
+
+    #[test]
+    fn test_throttle() {
+        let mut throttle = Throttle::new(Duration::from_secs(60));
+        let now = Instant::now();
+        assert!(throttle.allow("credential_disabled:1", now));
+        assert!(!throttle.allow("credential_disabled:1", now + Duration::from_secs(30)));
+        // 不同凭据互不影响
+        assert!(throttle.allow("credential_disabled:2", now + Duration::from_secs(30)));
+        assert!(throttle.allow("credential_disabled:1", now + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn test_from_config() {
+        let mut config = Config::default();
+        assert!(EmailConfig::from_config(&config).unwrap().is_none());
+
+        config.alert_smtp_host = Some("smtp.example.com".to_string());
+        config.alert_email_from = Some("kiro@example.com".to_string());
+        config.alert_email_to = vec!["ops@example.com".to_string()];
+        let email = EmailConfig::from_config(&config).unwrap().unwrap();
+        assert_eq!(email.security, SmtpSecurity::Tls);
+        assert_eq!(email.events, EVENT_KINDS);
+
+        config.alert_email_events = vec!["credential_refreshed".to_string()];
+        assert!(EmailConfig::from_config(&config).is_err());
+
+        config.alert_email_events = vec!["all_credentials_disabled".to_string()];
+        config.alert_email_to = vec!["ops@example.com\r\nRCPT TO:<x@y>".to_string()];
+        assert!(EmailConfig::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+//! 凭据余额后台刷新
+//!
+//! 额度跳过（`quotaSkipThreshold`）与利用率分段依据数据库中缓存的余额选择凭据。配置
+//! `balanceRefreshIntervalMins` 后后台定期查询全部启用凭据的余额；上游未返回额度重置时间的凭据
+//! 被跳过后无法按重置时间恢复，另由重新查询任务定期刷新其余额
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+
+use crate::kiro::lease::Lease;
+use crate::kiro::replication;
+use crate::kiro::token_manager::{MultiTokenManager, QUOTA_RECHECK_INTERVAL_SECS};
+
+/// 定时刷新租约名称（多实例共享数据库时只由持有者刷新）
+const REFRESHER_LEASE: &str = "balance_refresh";
+
+/// 定时刷新的并发数
+const REFRESH_CONCURRENCY: usize = 4;
+
+/// 启动余额定时刷新任务
+///
+/// 每 `interval` 刷新一次全部启用凭据的余额（热备期间跳过，避免刷新主实例正在使用的 Token；
+/// 多实例共享数据库时只由持有租约的实例执行）
+pub fn spawn_refresher(token_manager: Arc<MultiTokenManager>, interval: Duration) {
+    let lease = Lease::spawn(token_manager.database().clone(), REFRESHER_LEASE);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // 首个 tick 立即完成，跳过以免启动时与其他初始化请求争用
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if replication::is_standby() || !lease.is_held() {
+                continue;
+            }
+
+            let ids: Vec<u64> = token_manager
+                .blocking(|tm| tm.snapshot())
+                .await
+                .entries
+                .into_iter()
+                .filter(|entry| !entry.disabled)
+                .map(|entry| entry.id)
+                .collect();
+            let total = ids.len();
+            let failed = futures::stream::iter(ids)
+                .map(|id| {
+                    let token_manager = token_manager.clone();
+                    async move {
+                        let result = token_manager.refresh_balance(id).await;
+                        if let Err(e) = &result {
+                            tracing::warn!("定时刷新凭据 #{} 余额失败: {}", id, e);
+                        }
+                        result.is_err()
+                    }
+                })
+                .buffer_unordered(REFRESH_CONCURRENCY)
+                .filter(|failed| std::future::ready(*failed))
+                .count()
+                .await;
+            tracing::info!("凭据余额刷新完成: {} 个凭据，{} 个失败", total, failed);
+        }
+    });
+}
+
+/// 启动被跳过凭据的余额重新查询任务
+///
+/// 每 [`QUOTA_RECHECK_INTERVAL_SECS`] 秒执行一次（热备期间跳过，避免刷新主实例正在使用的 Token）
+pub fn spawn_quota_recheck(token_manager: Arc<MultiTokenManager>) {
+    tokio::spawn(async move {
+        let mut ticker =
+            tokio::time::interval(Duration::from_secs(QUOTA_RECHECK_INTERVAL_SECS as u64));
+        // 首个 tick 立即完成，此时尚无被跳过的凭据
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if replication::is_standby() {
+                continue;
+            }
+            token_manager.recheck_quota_skipped().await;
+        }
+    });
+}
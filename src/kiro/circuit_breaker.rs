@@ -0,0 +1,422 @@
+//! 凭据熔断器
+//!
+//! 每个凭据一个熔断器，三种状态：
+//! - 关闭（closed）：正常参与选择，失败计数达到阈值时打开
+//! - 打开（open）：凭据被禁用，熔断期内不参与选择
+//! - 半开（half_open）：熔断期已过，下一个请求作为探测请求使用该凭据，
+//!   成功则关闭熔断器并重新启用，失败则重新打开
+//!
+//! 打开状态通过数据库的 `disabled` / `disabled_at` 持久化（重启后熔断期继续计算）；
+//! 失败时间窗口与进行中的探测请求仅保存在内存中。
+//!
+//! 熔断参数（[`CircuitBreakerSettings`]）可通过 Admin API 运行时修改，修改只作用于当前实例的内存，
+//! 不写回配置文件，也不会同步到共享数据库的其他实例，重启后恢复为配置文件中的值
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+
+use crate::model::config::Config;
+
+/// 可单独配置熔断持续时间的认证方式
+pub const AUTH_METHODS: &[&str] = &["social", "idc", "iam"];
+
+/// 规范化认证方式：统一为小写，builder-id 与 Token 刷新一致按 idc 处理
+fn normalize_auth_method(method: &str) -> String {
+    match method.to_ascii_lowercase().as_str() {
+        "builder-id" => "idc".to_string(),
+        method => method.to_string(),
+    }
+}
+
+/// 探测请求的最长等待时间，超时未结算（如客户端断开且未计为失败）时允许发起新的探测
+const PROBE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// 熔断器状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "client", derive(serde::Deserialize))]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// 正常
+    Closed,
+    /// 熔断中
+    Open,
+    /// 熔断期已过，等待或正在进行探测
+    HalfOpen,
+}
+
+/// 熔断参数
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitBreakerSettings {
+    /// 打开熔断器的失败次数阈值
+    pub failure_threshold: u32,
+    /// 失败计数窗口（秒），`0` 表示只统计连续失败，不按时间过期
+    pub window_secs: u64,
+    /// 熔断持续时间（秒）
+    pub open_secs: u64,
+    /// 按认证方式（`social` / `idc` / `iam`，builder-id 按 idc 处理）覆盖的熔断持续时间（秒）
+    #[serde(default)]
+    pub open_secs_by_auth_method: HashMap<String, u64>,
+}
+
+impl CircuitBreakerSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            failure_threshold: config.circuit_breaker_failure_threshold,
+            window_secs: config.circuit_breaker_window_secs,
+            open_secs: config.circuit_breaker_open_secs,
+            open_secs_by_auth_method: config.circuit_breaker_open_secs_by_auth_method.clone(),
+        }
+    }
+
+    /// 规范化：阈值至少为 1，认证方式统一为小写（builder-id 按 idc 处理）
+    fn normalized(self) -> Self {
+        Self {
+            failure_threshold: self.failure_threshold.max(1),
+            open_secs_by_auth_method: self
+                .open_secs_by_auth_method
+                .into_iter()
+                .map(|(method, secs)| (normalize_auth_method(&method), secs))
+                .collect(),
+            ..self
+        }
+    }
+
+    /// 检查按认证方式覆盖的熔断持续时间只包含支持的认证方式
+    pub fn validate(&self) -> Result<(), String> {
+        let mut unsupported: Vec<&str> = self
+            .open_secs_by_auth_method
+            .keys()
+            .filter(|method| !AUTH_METHODS.contains(&normalize_auth_method(method).as_str()))
+            .map(String::as_str)
+            .collect();
+        if unsupported.is_empty() {
+            return Ok(());
+        }
+        unsupported.sort_unstable();
+        Err(format!(
+            "不支持的认证方式: {}（可选 {}）",
+            unsupported.join(", "),
+            AUTH_METHODS.join(" / ")
+        ))
+    }
+
+    /// 失败计数窗口（None 表示只统计连续失败）
+    fn window(&self) -> Option<Duration> {
+        (self.window_secs > 0).then(|| Duration::from_secs(self.window_secs))
+    }
+
+    /// 指定认证方式的熔断持续时间（未配置认证方式时按 social 处理）
+    fn open_duration(&self, auth_method: Option<&str>) -> Duration {
+        let method = normalize_auth_method(auth_method.unwrap_or("social"));
+        Duration::from_secs(
+            self.open_secs_by_auth_method
+                .get(&method)
+                .copied()
+                .unwrap_or(self.open_secs),
+        )
+    }
+}
+
+/// 凭据熔断器
+pub struct CircuitBreaker {
+    /// 熔断参数
+    settings: RwLock<CircuitBreakerSettings>,
+    /// 窗口内的失败时间（仅配置窗口时使用）
+    failures: Mutex<HashMap<u64, VecDeque<Instant>>>,
+    /// 进行中的探测请求及其开始时间
+    probes: Mutex<HashMap<u64, Instant>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: &Config) -> Self {
+        let settings = CircuitBreakerSettings::from_config(config);
+        if let Err(e) = settings.validate() {
+            tracing::warn!(
+                "circuitBreakerOpenSecsByAuthMethod 中的部分配置不会生效: {}",
+                e
+            );
+        }
+        Self {
+            settings: RwLock::new(settings.normalized()),
+            failures: Mutex::new(HashMap::new()),
+            probes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 当前熔断参数
+    pub fn settings(&self) -> CircuitBreakerSettings {
+        self.settings.read().clone()
+    }
+
+    /// 修改熔断参数，返回规范化后的参数
+    ///
+    /// 已熔断的凭据按新的持续时间重新计算熔断结束时间；修改窗口时清空已记录的失败时间
+    pub fn update(&self, settings: CircuitBreakerSettings) -> CircuitBreakerSettings {
+        let settings = settings.normalized();
+        let mut current = self.settings.write();
+        if current.window_secs != settings.window_secs {
+            self.failures.lock().clear();
+        }
+        *current = settings.clone();
+        settings
+    }
+
+    /// 打开熔断器的失败次数阈值
+    pub fn failure_threshold(&self) -> u32 {
+        self.settings.read().failure_threshold
+    }
+
+    /// 指定认证方式的凭据的熔断持续时间
+    pub fn open_duration(&self, auth_method: Option<&str>) -> Duration {
+        self.settings.read().open_duration(auth_method)
+    }
+
+    /// 所有认证方式中最短的熔断持续时间
+    pub fn min_open_duration(&self) -> Duration {
+        let settings = self.settings.read();
+        let min = settings
+            .open_secs_by_auth_method
+            .values()
+            .copied()
+            .fold(settings.open_secs, u64::min);
+        Duration::from_secs(min)
+    }
+
+    /// 记录一次失败，返回用于判断阈值的失败次数
+    ///
+    /// 未配置窗口时为连续失败次数 `consecutive`（数据库中的失败计数）；
+    /// 配置窗口时为窗口内（且上次成功之后）的失败次数
+    pub fn record_failure(&self, id: u64, consecutive: u32, now: Instant) -> u32 {
+        let Some(window) = self.settings.read().window() else {
+            return consecutive;
+        };
+        let mut failures = self.failures.lock();
+        let times = failures.entry(id).or_default();
+        times.push_back(now);
+        while times
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > window)
+        {
+            times.pop_front();
+        }
+        times.len() as u32
+    }
+
+    /// 记录一次成功（清空失败窗口）
+    pub fn record_success(&self, id: u64) {
+        if self.settings.read().window_secs > 0 {
+            self.failures.lock().remove(&id);
+        }
+    }
+
+    /// 失败次数是否达到阈值
+    pub fn should_trip(&self, failures: u32) -> bool {
+        failures >= self.failure_threshold()
+    }
+
+    /// 熔断器打开时清空失败窗口（重新关闭后从零计数）
+    pub fn on_open(&self, id: u64) {
+        self.failures.lock().remove(&id);
+    }
+
+    /// 尝试为半开的凭据发起探测（已有未超时的探测时返回 false）
+    pub fn try_begin_probe(&self, id: u64, now: Instant) -> bool {
+        let mut probes = self.probes.lock();
+        if probes
+            .get(&id)
+            .is_some_and(|started| now.duration_since(*started) < PROBE_TIMEOUT)
+        {
+            return false;
+        }
+        probes.insert(id, now);
+        true
+    }
+
+    /// 结束探测，返回该凭据是否有进行中的探测
+    pub fn end_probe(&self, id: u64) -> bool {
+        self.probes.lock().remove(&id).is_some()
+    }
+
+    /// 清除凭据的全部状态（凭据删除或手动启用时）
+    pub fn reset(&self, id: u64) {
+        self.failures.lock().remove(&id);
+        self.probes.lock().remove(&id);
+    }
+
+    /// 计算凭据的熔断器状态与熔断结束时间
+    ///
+    /// `auth_method` 为凭据的认证方式，`disabled_at` 为凭据被禁用的时间（未禁用时为 None）
+    pub fn state(
+        &self,
+        id: u64,
+        auth_method: Option<&str>,
+        disabled_at: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> (CircuitState, Option<DateTime<Utc>>) {
+        let Some(disabled_at) = disabled_at else {
+            return (CircuitState::Closed, None);
+        };
+        let open_until = disabled_at
+            + chrono::Duration::from_std(self.open_duration(auth_method))
+                .unwrap_or(chrono::Duration::zero());
+        if now < open_until && !self.probes.lock().contains_key(&id) {
+            (CircuitState::Open, Some(open_until))
+        } else {
+            (CircuitState::HalfOpen, Some(open_until))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(threshold: u32, window_secs: u64) -> CircuitBreaker {
+        CircuitBreaker::new(&Config {
+            circuit_breaker_failure_threshold: threshold,
+            circuit_breaker_window_secs: window_secs,
+            circuit_breaker_open_secs: 60,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_consecutive_failures_without_window() {
+        let breaker = breaker(3, 0);
+        let now = Instant::now();
+        assert_eq!(breaker.record_failure(1, 2, now), 2);
+        assert!(!breaker.should_trip(2));
+        assert!(breaker.should_trip(breaker.record_failure(1, 3, now)));
+    }
+
+    #[test]
+    fn test_failures_expire_outside_window() {
+        let breaker = breaker(3, 10);
+        let start = Instant::now();
+        assert_eq!(breaker.record_failure(1, 1, start), 1);
+        assert_eq!(
+            breaker.record_failure(1, 2, start + Duration::from_secs(5)),
+            2
+        );
+        // 第一次失败已移出窗口
+        assert_eq!(
+            breaker.record_failure(1, 3, start + Duration::from_secs(12)),
+            2
+        );
+        assert_eq!(
+            breaker.record_failure(1, 4, start + Duration::from_secs(13)),
+            3
+        );
+
+        // 成功后重新计数
+        breaker.record_success(1);
+        assert_eq!(
+            breaker.record_failure(1, 1, start + Duration::from_secs(14)),
+            1
+        );
+    }
+
+    #[test]
+    fn test_single_probe_in_half_open() {
+        let breaker = breaker(3, 0);
+        let now = Instant::now();
+        assert!(breaker.try_begin_probe(1, now));
+        assert!(!breaker.try_begin_probe(1, now + Duration::from_secs(1)));
+        // 其他凭据互不影响
+        assert!(breaker.try_begin_probe(2, now));
+        // 探测超时后允许重新探测
+        assert!(breaker.try_begin_probe(1, now + PROBE_TIMEOUT));
+
+        assert!(breaker.end_probe(1));
+        assert!(!breaker.end_probe(1));
+    }
+
+    #[test]
+    fn test_state() {
+        let breaker = breaker(3, 0);
+        let now = Utc::now();
+        assert_eq!(
+            breaker.state(1, None, None, now),
+            (CircuitState::Closed, None)
+        );
+
+        let disabled_at = now - chrono::Duration::seconds(30);
+        let open_until = disabled_at + chrono::Duration::seconds(60);
+        assert_eq!(
+            breaker.state(1, None, Some(disabled_at), now),
+            (CircuitState::Open, Some(open_until))
+        );
+        assert_eq!(
+            breaker.state(
+                1,
+                None,
+                Some(disabled_at),
+                now + chrono::Duration::seconds(31)
+            ),
+            (CircuitState::HalfOpen, Some(open_until))
+        );
+    }
+
+    #[test]
+    fn test_update_settings_by_auth_method() {
+        let breaker = breaker(3, 0);
+        let settings = breaker.update(CircuitBreakerSettings {
+            failure_threshold: 0,
+            window_secs: 0,
+            open_secs: 60,
+            open_secs_by_auth_method: HashMap::from([("IdC".to_string(), 3600)]),
+        });
+        assert_eq!(settings.failure_threshold, 1);
+        assert_eq!(breaker.failure_threshold(), 1);
+        assert_eq!(breaker.open_duration(None), Duration::from_secs(60));
+        assert_eq!(
+            breaker.open_duration(Some("social")),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            breaker.open_duration(Some("idc")),
+            Duration::from_secs(3600)
+        );
+        // builder-id 与 Token 刷新一致按 idc 处理
+        assert_eq!(
+            breaker.open_duration(Some("builder-id")),
+            Duration::from_secs(3600)
+        );
+        assert_eq!(breaker.min_open_duration(), Duration::from_secs(60));
+
+        // IdC 凭据熔断期更长
+        let now = Utc::now();
+        let disabled_at = now - chrono::Duration::seconds(120);
+        assert_eq!(
+            breaker.state(1, Some("social"), Some(disabled_at), now).0,
+            CircuitState::HalfOpen
+        );
+        assert_eq!(
+            breaker.state(1, Some("idc"), Some(disabled_at), now).0,
+            CircuitState::Open
+        );
+    }
+
+    #[test]
+    fn test_validate_auth_methods() {
+        let settings = |methods: &[&str]| CircuitBreakerSettings {
+            failure_threshold: 3,
+            window_secs: 0,
+            open_secs: 60,
+            open_secs_by_auth_method: methods.iter().map(|m| (m.to_string(), 600)).collect(),
+        };
+        assert!(
+            settings(&["social", "IdC", "iam", "builder-id"])
+                .validate()
+                .is_ok()
+        );
+        let err = settings(&["idc", "oauth"]).validate().unwrap_err();
+        assert!(err.contains("oauth"));
+        assert!(!err.contains("idc,"));
+    }
+}
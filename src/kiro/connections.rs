@@ -0,0 +1,379 @@
+//! 上游连接跟踪
+//!
+//! 反向代理断开后客户端连接可能处于半开状态，响应体不再被读取，
+//! 上游流随之挂起并持续消耗额度。这里在独立任务中读取上游响应体：
+//! 接收端被丢弃（客户端已断开）时立即关闭上游连接，超过最大存活时间时强制关闭。
+//! 凭据租约在响应体读取结束后结算：读完报告成功，读取出错报告失败，
+//! 客户端断开或超过最大存活时间只释放租约
+
+use std::collections::HashMap;
+use std::future::pending;
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt, stream};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::kiro::token_manager::CredentialLease;
+
+/// 上游响应体转发缓冲的分片数
+const PUMP_BUFFER: usize = 32;
+
+/// 当前活跃的上游连接数
+static ACTIVE: AtomicU64 = AtomicU64::new(0);
+
+/// 各凭据当前活跃的上游连接数
+static ACTIVE_BY_CREDENTIAL: LazyLock<Mutex<HashMap<u64, u64>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// 客户端断开后被清理的上游连接总数
+static ORPHANED_TOTAL: AtomicU64 = AtomicU64::new(0);
+
+/// 超过最大存活时间被强制关闭的上游连接总数
+static FORCED_CLOSED_TOTAL: AtomicU64 = AtomicU64::new(0);
+
+/// 读取上游响应体失败的总数
+static READ_ERRORS_TOTAL: AtomicU64 = AtomicU64::new(0);
+
+/// 上游响应未正常结束（被截断）的总数
+static TRUNCATED_TOTAL: AtomicU64 = AtomicU64::new(0);
+
+/// 上游连接统计
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpstreamStats {
+    /// 当前活跃的上游连接数
+    pub upstream_active: u64,
+    /// 客户端断开后被清理的上游连接总数
+    pub upstream_orphaned_total: u64,
+    /// 超过最大存活时间被强制关闭的上游连接总数
+    pub upstream_forced_closed_total: u64,
+    /// 读取上游响应体失败的总数
+    pub upstream_read_errors_total: u64,
+    /// 上游响应未正常结束（被截断）的总数
+    pub upstream_truncated_total: u64,
+}
+
+/// 获取上游连接统计
+pub fn stats() -> UpstreamStats {
+    UpstreamStats {
+        upstream_active: ACTIVE.load(Ordering::Relaxed),
+        upstream_orphaned_total: ORPHANED_TOTAL.load(Ordering::Relaxed),
+        upstream_forced_closed_total: FORCED_CLOSED_TOTAL.load(Ordering::Relaxed),
+        upstream_read_errors_total: READ_ERRORS_TOTAL.load(Ordering::Relaxed),
+        upstream_truncated_total: TRUNCATED_TOTAL.load(Ordering::Relaxed),
+    }
+}
+
+/// 记录一次读取上游响应体失败
+pub fn record_read_error() {
+    READ_ERRORS_TOTAL.fetch_add(1, Ordering::Relaxed);
+}
+
+/// 记录一次上游响应被截断
+pub fn record_truncated() {
+    TRUNCATED_TOTAL.fetch_add(1, Ordering::Relaxed);
+}
+
+/// 获取指定凭据当前活跃的上游连接数
+pub fn in_flight(credential_id: u64) -> u64 {
+    ACTIVE_BY_CREDENTIAL
+        .lock()
+        .get(&credential_id)
+        .copied()
+        .unwrap_or(0)
+}
+
+/// 活跃连接计数守卫（任务结束时自动减一）
+struct ActiveGuard {
+    credential_id: u64,
+}
+
+impl ActiveGuard {
+    fn new(credential_id: u64) -> Self {
+        ACTIVE.fetch_add(1, Ordering::Relaxed);
+        *ACTIVE_BY_CREDENTIAL
+            .lock()
+            .entry(credential_id)
+            .or_default() += 1;
+        Self { credential_id }
+    }
+}
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        ACTIVE.fetch_sub(1, Ordering::Relaxed);
+        let mut by_credential = ACTIVE_BY_CREDENTIAL.lock();
+        if let Some(count) = by_credential.get_mut(&self.credential_id) {
+            *count -= 1;
+            if *count == 0 {
+                by_credential.remove(&self.credential_id);
+            }
+        }
+    }
+}
+
+/// 跟踪上游响应体
+///
+/// 在后台任务中读取 `body` 并通过通道转发，返回的流被丢弃后上游连接随即关闭；
+/// `max_lifetime` 到期时无论返回的流是否仍在被读取都会立即关闭上游连接，
+/// 随后在已转发的分片之后向下游发送一个错误。`lease` 在读取结束后结算
+pub fn track<S>(
+    credential_id: u64,
+    body: S,
+    max_lifetime: Option<Duration>,
+    lease: Option<CredentialLease>,
+) -> impl Stream<Item = anyhow::Result<Bytes>> + Send + 'static
+where
+    S: Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(PUMP_BUFFER);
+    let guard = ActiveGuard::new(credential_id);
+
+    tokio::spawn(async move {
+        let end = pump(credential_id, body, max_lifetime, &tx).await;
+        // 上游连接已关闭（body 已随 pump 结束释放）
+        drop(guard);
+        match end {
+            PumpEnd::Completed => {
+                if let Some(lease) = lease {
+                    lease.succeed().await;
+                }
+            }
+            PumpEnd::ReadError => {
+                if let Some(lease) = lease {
+                    lease.fail().await;
+                }
+            }
+            PumpEnd::Orphaned => {
+                if let Some(lease) = lease {
+                    lease.release();
+                }
+            }
+            PumpEnd::Forced => {
+                if let Some(lease) = lease {
+                    lease.release();
+                }
+                // 等待读取方取走已缓冲的分片后送达错误（读取方丢弃时发送失败，忽略即可）
+                let secs = max_lifetime.unwrap_or_default().as_secs();
+                let _ = tx
+                    .send(Err(anyhow::anyhow!(
+                        "上游连接超过最大存活时间 {} 秒，已强制关闭",
+                        secs
+                    )))
+                    .await;
+            }
+        }
+    });
+
+    stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|item| (item, rx))
+    })
+}
+
+/// 上游响应体读取结束的原因
+enum PumpEnd {
+    /// 上游响应体已读完
+    Completed,
+    /// 读取上游响应体出错
+    ReadError,
+    /// 客户端已断开
+    Orphaned,
+    /// 超过最大存活时间被强制关闭
+    Forced,
+}
+
+/// 将上游响应体转发到通道，直到读完、出错、客户端断开或超过最大存活时间
+async fn pump<S>(
+    credential_id: u64,
+    body: S,
+    max_lifetime: Option<Duration>,
+    tx: &mpsc::Sender<anyhow::Result<Bytes>>,
+) -> PumpEnd
+where
+    S: Stream<Item = reqwest::Result<Bytes>>,
+{
+    let deadline = async {
+        match max_lifetime {
+            Some(lifetime) => tokio::time::sleep(lifetime).await,
+            None => pending().await,
+        }
+    };
+    tokio::pin!(body);
+    tokio::pin!(deadline);
+
+    let orphaned = || {
+        ORPHANED_TOTAL.fetch_add(1, Ordering::Relaxed);
+        tracing::info!("客户端已断开，关闭上游连接（凭据 #{}）", credential_id);
+        PumpEnd::Orphaned
+    };
+    let forced = || {
+        FORCED_CLOSED_TOTAL.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(
+            "上游连接超过最大存活时间 {} 秒，强制关闭（凭据 #{}）",
+            max_lifetime.unwrap_or_default().as_secs(),
+            credential_id
+        );
+        PumpEnd::Forced
+    };
+
+    loop {
+        let item = tokio::select! {
+            item = body.next() => item,
+            _ = tx.closed() => return orphaned(),
+            _ = &mut deadline => return forced(),
+        };
+        let Some(item) = item else {
+            return PumpEnd::Completed;
+        };
+
+        let failed = item.is_err();
+        tokio::select! {
+            result = tx.send(item.map_err(anyhow::Error::from)) => {
+                if result.is_err() {
+                    return orphaned();
+                }
+            }
+            _ = &mut deadline => return forced(),
+        }
+        if failed {
+            return PumpEnd::ReadError;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 统计为全局计数，测试只断言增量
+    fn chunks(items: Vec<&'static str>) -> impl Stream<Item = reqwest::Result<Bytes>> {
+        stream::iter(items.into_iter().map(|s| Ok(Bytes::from(s)))).chain(stream::pending())
+    }
+
+    #[tokio::test]
+    async fn test_forwards_body_until_deadline() {
+        let before = stats().upstream_forced_closed_total;
+        let tracked = track(
+            1,
+            chunks(vec!["a", "b"]),
+            Some(Duration::from_millis(100)),
+            None,
+        );
+
+        let items: Vec<_> = tracked.collect().await;
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].as_ref().unwrap(), &Bytes::from("a"));
+        assert!(
+            items[2]
+                .as_ref()
+                .unwrap_err()
+                .to_string()
+                .contains("最大存活时间")
+        );
+        assert!(stats().upstream_forced_closed_total > before);
+    }
+
+    #[tokio::test]
+    async fn test_deadline_applies_without_reader() {
+        let before = stats().upstream_forced_closed_total;
+        // 大量分片填满通道后读取方不再读取，上游仍需按时关闭
+        let body = stream::iter((0..PUMP_BUFFER * 2).map(|_| Ok(Bytes::from("x"))))
+            .chain(stream::pending());
+        let tracked = track(2, body, Some(Duration::from_millis(50)), None);
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(stats().upstream_forced_closed_total > before);
+        assert_eq!(in_flight(2), 0);
+
+        // 通道已满时错误不会丢失，排在已缓冲的分片之后送达
+        let items: Vec<_> = tracked.collect().await;
+        assert_eq!(items.len(), PUMP_BUFFER + 1);
+        assert!(items[..PUMP_BUFFER].iter().all(|item| item.is_ok()));
+        assert!(
+            items[PUMP_BUFFER]
+                .as_ref()
+                .unwrap_err()
+                .to_string()
+                .contains("最大存活时间")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dropping_reader_closes_upstream() {
+        let before = stats().upstream_orphaned_total;
+        let mut tracked = Box::pin(track(3, chunks(vec!["a"]), None, None));
+        assert!(tracked.next().await.unwrap().is_ok());
+        assert_eq!(in_flight(3), 1);
+        drop(tracked);
+
+        for _ in 0..50 {
+            if stats().upstream_orphaned_total > before && in_flight(3) == 0 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("上游连接未在读取方丢弃后关闭");
+    }
+
+    #[tokio::test]
+    async fn test_settles_lease_after_body() {
+        use crate::kiro::db::Database;
+        use crate::kiro::model::credentials::KiroCredentials;
+        use crate::kiro::token_manager::{MultiTokenManager, RequestPriority};
+        use crate::model::config::Config;
+        use std::sync::Arc;
+
+        let db = Database::open_in_memory().unwrap();
+        let id = db
+            .insert_credential(&KiroCredentials {
+                refresh_token: Some("rt".to_string()),
+                access_token: Some("at".to_string()),
+                expires_at: Some((chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339()),
+                ..Default::default()
+            })
+            .unwrap();
+        let manager =
+            Arc::new(MultiTokenManager::new(Config::default(), db.clone(), None).unwrap());
+        let lease = || async {
+            manager
+                .lease(None, RequestPriority::Standard, None)
+                .await
+                .unwrap()
+        };
+        let failures = || db.get_credential(id).unwrap().unwrap().failure_count;
+        let settled = |expected: u32| async move {
+            for _ in 0..50 {
+                if failures() == expected {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            panic!("租约未在响应体读取结束后结算");
+        };
+
+        // 读取出错计为失败
+        let body = stream::iter(vec![Ok(Bytes::from("a"))]).chain(stream::once(async {
+            Err(reqwest::get("http://127.0.0.1:0").await.unwrap_err())
+        }));
+        let items: Vec<_> = track(id, body, None, Some(lease().await)).collect().await;
+        assert!(items[1].is_err());
+        settled(1).await;
+
+        // 客户端断开只释放租约
+        let tracked = track(id, chunks(vec!["a"]), None, Some(lease().await));
+        drop(tracked);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(failures(), 1);
+
+        // 读完后报告成功
+        let body = stream::iter(vec![Ok(Bytes::from("a"))]);
+        let items: Vec<_> = track(id, body, None, Some(lease().await)).collect().await;
+        assert_eq!(items.len(), 1);
+        settled(0).await;
+    }
+}
@@ -0,0 +1,103 @@
+//! 凭据状态变更事件
+//!
+//! Token 管理器与 Admin 服务在凭据状态变化时发布事件，Admin WebSocket（`/ws`）
+//! 将其推送给 Web UI，避免轮询凭据列表。事件只描述变化，不保证送达：
+//! 订阅者落后时会丢失事件，此时应重新获取完整的凭据列表
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// 每个订阅者最多缓冲的事件数
+const CHANNEL_CAPACITY: usize = 256;
+
+/// 凭据状态变更事件
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(
+    tag = "type",
+    rename_all = "snake_case",
+    rename_all_fields = "camelCase"
+)]
+pub enum CredentialEvent {
+    /// 禁用状态或失败计数变化
+    StatusChanged {
+        id: u64,
+        disabled: bool,
+        failure_count: u32,
+    },
+    /// 当前活动凭据切换
+    CurrentChanged { id: u64 },
+    /// 余额刷新完成
+    BalanceUpdated {
+        id: u64,
+        current_usage: f64,
+        usage_limit: f64,
+    },
+    /// 凭据列表变化（添加、删除等），需要重新获取完整列表
+    CredentialsChanged,
+}
+
+/// 凭据事件广播
+pub struct CredentialEvents {
+    tx: broadcast::Sender<CredentialEvent>,
+}
+
+impl Default for CredentialEvents {
+    fn default() -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { tx }
+    }
+}
+
+impl CredentialEvents {
+    /// 发布事件（没有订阅者时直接丢弃）
+    pub fn publish(&self, event: CredentialEvent) {
+        let _ = self.tx.send(event);
+    }
+
+    /// 是否有订阅者（构建事件需要查询数据库时先检查，避免无人订阅时的额外开销）
+    pub fn has_subscribers(&self) -> bool {
+        self.tx.receiver_count() > 0
+    }
+
+    /// 订阅事件
+    pub fn subscribe(&self) -> broadcast::Receiver<CredentialEvent> {
+        self.tx.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_format() {
+        let event = CredentialEvent::StatusChanged {
+            id: 3,
+            disabled: true,
+            failure_count: 3,
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"type": "status_changed", "id": 3, "disabled": true, "failureCount": 3})
+        );
+        assert_eq!(
+            serde_json::to_value(CredentialEvent::CredentialsChanged).unwrap(),
+            serde_json::json!({"type": "credentials_changed"})
+        );
+    }
+
+    #[test]
+    fn test_publish_without_subscribers() {
+        let events = CredentialEvents::default();
+        assert!(!events.has_subscribers());
+        events.publish(CredentialEvent::CurrentChanged { id: 1 });
+
+        let mut rx = events.subscribe();
+        assert!(events.has_subscribers());
+        events.publish(CredentialEvent::CurrentChanged { id: 2 });
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            CredentialEvent::CurrentChanged { id: 2 }
+        );
+    }
+}
@@ -1,41 +1,594 @@
 //! SQLite 数据库模块
 //!
-//! 提供凭据的持久化存储
+//! 提供凭据、请求日志、提示词模板、告警通知队列、凭据健康检查记录与多实例租约的持久化存储
 
 use anyhow::{Context, Result};
 use parking_lot::Mutex;
-use rusqlite::{Connection, params};
+use rusqlite::{Connection, TransactionBehavior, params};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::kiro::model::admin_token::{AdminScope, AdminToken};
+use crate::kiro::model::api_key::ApiKey;
+use crate::kiro::model::credentials::{KiroCredentials, normalize_expires_at};
+use crate::kiro::model::health_check::{HealthCheck, HealthCheckTrigger};
+use crate::kiro::model::notification::{Notification, NotificationStatus};
+use crate::kiro::model::prompt_template::PromptTemplate;
+use crate::kiro::model::request_log::{RequestLog, RequestLogFilter};
+use crate::kiro::model::stats::{
+    CredentialShare, HourlyStats, ModelStats, StatsSummary, TodayStats,
+};
+use crate::kiro::model::transcript::{Transcript, TranscriptFilter};
+use crate::kiro::model::usage_log::{
+    CredentialUsage, ModelUsage, TagUsage, UsageFilter, UsageLog, UsageSummary, UsageTotals,
+};
+
+mod merged;
+mod migrations;
+#[cfg(feature = "postgres")]
+mod postgres;
+mod store;
+
+pub use merged::SharedCredentials;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresCredentialStore;
+pub use store::CredentialStore;
+
+/// 遇到锁竞争（SQLITE_BUSY）时的最大重试次数
+const BUSY_MAX_RETRIES: u32 = 8;
+
+/// 锁竞争重试退避基数（毫秒），第 n 次重试前等待 `基数 × 2^n`
+const BUSY_BACKOFF_BASE_MS: u64 = 5;
+
+/// 锁竞争单次重试退避上限（毫秒）
+const BUSY_BACKOFF_MAX_MS: u64 = 500;
+
+/// 连接上的 busy timeout（毫秒）：持有连接锁时 SQLite 最多等待这么久，更长的等待交给
+/// [`with_conn`] 在释放连接锁后退避
+const BUSY_TIMEOUT_MS: u64 = 20;
+
+/// 遇到锁竞争的操作总数
+static BUSY_TOTAL: AtomicU64 = AtomicU64::new(0);
+
+/// 锁竞争重试总次数
+static BUSY_RETRIES_TOTAL: AtomicU64 = AtomicU64::new(0);
+
+/// 重试耗尽仍返回 SQLITE_BUSY 的操作总数
+static BUSY_EXHAUSTED_TOTAL: AtomicU64 = AtomicU64::new(0);
+
+/// 数据库锁竞争统计
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseStats {
+    /// 遇到锁竞争（SQLITE_BUSY）的操作总数
+    pub sqlite_busy_total: u64,
+    /// 锁竞争重试总次数
+    pub sqlite_busy_retries_total: u64,
+    /// 重试耗尽仍失败的操作总数
+    pub sqlite_busy_exhausted_total: u64,
+}
+
+/// 获取数据库锁竞争统计
+pub fn stats() -> DatabaseStats {
+    DatabaseStats {
+        sqlite_busy_total: BUSY_TOTAL.load(Ordering::Relaxed),
+        sqlite_busy_retries_total: BUSY_RETRIES_TOTAL.load(Ordering::Relaxed),
+        sqlite_busy_exhausted_total: BUSY_EXHAUSTED_TOTAL.load(Ordering::Relaxed),
+    }
+}
+
+/// 判断错误是否由锁竞争（SQLITE_BUSY）导致
+fn is_busy(err: &anyhow::Error) -> bool {
+    err.chain().any(|e| {
+        e.downcast_ref::<rusqlite::Error>()
+            .and_then(rusqlite::Error::sqlite_error_code)
+            == Some(rusqlite::ErrorCode::DatabaseBusy)
+    })
+}
+
+/// 持有连接执行数据库操作，锁被其他连接（如共享数据库的其他实例）占用时退避重试
+///
+/// 连接上的 busy timeout 很短，SQLite 只在持有连接锁时短暂等待；仍返回 SQLITE_BUSY 时
+/// 先释放连接锁再退避，避免阻塞同一进程内的其他数据库调用。`op` 失败时其事务已回滚，
+/// 因此可整体重试；写事务使用 IMMEDIATE 模式在 BEGIN 时加锁，避免事务中途升级写锁失败
+fn with_conn<T>(
+    conn: &Mutex<Connection>,
+    mut op: impl FnMut(&mut Connection) -> Result<T>,
+) -> Result<T> {
+    let mut attempt = 0;
+    loop {
+        let err = match op(&mut conn.lock()) {
+            Err(e) if is_busy(&e) => e,
+            result => return result,
+        };
+        if attempt == 0 {
+            BUSY_TOTAL.fetch_add(1, Ordering::Relaxed);
+        }
+        if attempt >= BUSY_MAX_RETRIES {
+            BUSY_EXHAUSTED_TOTAL.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!("数据库锁竞争重试 {} 次后仍未获得锁", attempt);
+            return Err(err);
+        }
+        BUSY_RETRIES_TOTAL.fetch_add(1, Ordering::Relaxed);
+        let backoff = BUSY_BACKOFF_BASE_MS
+            .saturating_mul(1 << attempt.min(16))
+            .min(BUSY_BACKOFF_MAX_MS);
+        std::thread::sleep(Duration::from_millis(backoff));
+        attempt += 1;
+    }
+}
+
+/// 开启写事务（IMMEDIATE：BEGIN 时即获取写锁，锁竞争由 [`with_conn`] 重试）
+fn write_transaction(conn: &mut Connection) -> rusqlite::Result<rusqlite::Transaction<'_>> {
+    conn.transaction_with_behavior(TransactionBehavior::Immediate)
+}
+
+/// 凭据表查询列（顺序需与 `row_to_credential` 保持一致）
+const CREDENTIAL_COLUMNS: &str = "id, refresh_token, access_token, expires_at, auth_method, \
+     client_id, client_secret, profile_arn, priority, \
+     disabled, failure_count, \
+     subscription_title, current_usage, usage_limit, next_reset_at, balance_updated_at, \
+     machine_id, email, \
+     kiro_version, system_version, node_version, allowed_models, extra_headers, \
+     access_key_id, secret_access_key, session_token, region";
+
+/// 将查询行映射为凭据（列顺序见 `CREDENTIAL_COLUMNS`）
+fn row_to_credential(row: &rusqlite::Row<'_>) -> rusqlite::Result<KiroCredentials> {
+    Ok(KiroCredentials {
+        id: Some(row.get::<_, i64>(0)? as u64),
+        // IAM 凭据没有 refreshToken，入库时以空字符串占位
+        refresh_token: row.get::<_, Option<String>>(1)?.filter(|t| !t.is_empty()),
+        access_token: row.get(2)?,
+        expires_at: row.get(3)?,
+        auth_method: row.get(4)?,
+        client_id: row.get(5)?,
+        client_secret: row.get(6)?,
+        profile_arn: row.get(7)?,
+        priority: row.get::<_, i64>(8)? as u32,
+        disabled: row.get::<_, i64>(9)? != 0,
+        failure_count: row.get::<_, i64>(10)? as u32,
+        subscription_title: row.get(11)?,
+        current_usage: row.get::<_, Option<f64>>(12)?.unwrap_or(0.0),
+        usage_limit: row.get::<_, Option<f64>>(13)?.unwrap_or(0.0),
+        next_reset_at: row.get(14)?,
+        balance_updated_at: row.get(15)?,
+        machine_id: row.get(16)?,
+        email: row.get(17)?,
+        kiro_version: row.get(18)?,
+        system_version: row.get(19)?,
+        node_version: row.get(20)?,
+        allowed_models: split_models(row.get(21)?),
+        extra_headers: decode_headers(row.get(22)?),
+        access_key_id: row.get(23)?,
+        secret_access_key: row.get(24)?,
+        session_token: row.get(25)?,
+        region: row.get(26)?,
+    })
+}
+
+/// 将允许的模型列表编码为逗号分隔的字符串（None 表示不限制）
+fn join_models(models: &Option<Vec<String>>) -> Option<String> {
+    models.as_ref().map(|models| models.join(","))
+}
+
+/// 将自定义请求头编码为 JSON 对象字符串（None 表示未配置）
+fn encode_headers(headers: &Option<BTreeMap<String, String>>) -> Option<String> {
+    headers
+        .as_ref()
+        .and_then(|headers| serde_json::to_string(headers).ok())
+}
+
+/// 解码 JSON 对象形式的自定义请求头（格式无效时忽略）
+fn decode_headers(value: Option<String>) -> Option<BTreeMap<String, String>> {
+    value.and_then(|value| serde_json::from_str(&value).ok())
+}
+
+/// 解码逗号分隔的允许模型列表
+fn split_models(value: Option<String>) -> Option<Vec<String>> {
+    value.map(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|m| !m.is_empty())
+            .map(str::to_string)
+            .collect()
+    })
+}
+
+/// 请求日志查询列（顺序需与 `row_to_request_log` 保持一致）
+const REQUEST_LOG_COLUMNS: &str = "id, created_at, model, credential_id, status, client_key, \
+     latency_ms, stream, error, tag, seed, request_id, sse_events";
+
+/// 将查询行映射为请求日志（列顺序见 `REQUEST_LOG_COLUMNS`）
+fn row_to_request_log(row: &rusqlite::Row<'_>) -> rusqlite::Result<RequestLog> {
+    Ok(RequestLog {
+        id: Some(row.get::<_, i64>(0)? as u64),
+        created_at: chrono::DateTime::from_timestamp_millis(row.get(1)?).unwrap_or_default(),
+        model: row.get(2)?,
+        credential_id: row.get::<_, Option<i64>>(3)?.map(|id| id as u64),
+        status: row.get::<_, i64>(4)? as u16,
+        client_key: row.get(5)?,
+        latency_ms: row.get::<_, i64>(6)? as u64,
+        stream: row.get::<_, i64>(7)? != 0,
+        error: row.get(8)?,
+        tag: row.get(9)?,
+        seed: row.get(10)?,
+        request_id: row.get(11)?,
+        sse_events: row.get::<_, Option<i64>>(12)?.map(|n| n as u64),
+    })
+}
+
+/// 对话记录查询列（顺序需与 `row_to_transcript` 保持一致）
+const TRANSCRIPT_COLUMNS: &str = "id, created_at, request_id, model, credential_id, client_key, \
+     stream, status, latency_ms, request, response, stop_reason, input_tokens, output_tokens";
+
+/// 将查询行映射为对话记录（列顺序见 `TRANSCRIPT_COLUMNS`）
+fn row_to_transcript(row: &rusqlite::Row<'_>) -> rusqlite::Result<Transcript> {
+    let json = |idx: usize| -> rusqlite::Result<serde_json::Value> {
+        let text: String = row.get(idx)?;
+        Ok(serde_json::from_str(&text).unwrap_or(serde_json::Value::Null))
+    };
+    Ok(Transcript {
+        id: Some(row.get::<_, i64>(0)? as u64),
+        created_at: chrono::DateTime::from_timestamp_millis(row.get(1)?).unwrap_or_default(),
+        request_id: row.get(2)?,
+        model: row.get(3)?,
+        credential_id: row.get::<_, Option<i64>>(4)?.map(|id| id as u64),
+        client_key: row.get(5)?,
+        stream: row.get::<_, i64>(6)? != 0,
+        status: row.get::<_, i64>(7)? as u16,
+        latency_ms: row.get::<_, i64>(8)? as u64,
+        request: json(9)?,
+        response: json(10)?,
+        stop_reason: row.get(11)?,
+        input_tokens: row.get::<_, Option<i64>>(12)?.map(|n| n as u64),
+        output_tokens: row.get::<_, Option<i64>>(13)?.map(|n| n as u64),
+    })
+}
+
+/// 用量汇总聚合列（顺序需与 `row_to_usage_totals` 保持一致）
+const USAGE_TOTALS_COLUMNS: &str = "COUNT(*), COALESCE(SUM(status >= 400), 0), \
+    COALESCE(SUM(input_tokens), 0), COALESCE(SUM(output_tokens), 0), AVG(latency_ms)";
+
+/// 将从 `offset` 列开始的聚合结果映射为用量汇总（列顺序见 `USAGE_TOTALS_COLUMNS`）
+fn row_to_usage_totals(row: &rusqlite::Row<'_>, offset: usize) -> rusqlite::Result<UsageTotals> {
+    Ok(UsageTotals {
+        requests: row.get::<_, i64>(offset)? as u64,
+        errors: row.get::<_, i64>(offset + 1)? as u64,
+        input_tokens: row.get::<_, i64>(offset + 2)? as u64,
+        output_tokens: row.get::<_, i64>(offset + 3)? as u64,
+        avg_latency_ms: row.get::<_, Option<f64>>(offset + 4)?.unwrap_or(0.0),
+    })
+}
+
+/// 提示词模板查询列（顺序需与 `row_to_prompt_template` 保持一致）
+const PROMPT_TEMPLATE_COLUMNS: &str = "name, content, description, updated_at";
+
+/// 将查询行映射为提示词模板（列顺序见 `PROMPT_TEMPLATE_COLUMNS`）
+fn row_to_prompt_template(row: &rusqlite::Row<'_>) -> rusqlite::Result<PromptTemplate> {
+    Ok(PromptTemplate {
+        name: row.get(0)?,
+        content: row.get(1)?,
+        description: row.get(2)?,
+        updated_at: chrono::DateTime::from_timestamp_millis(row.get(3)?).unwrap_or_default(),
+    })
+}
+
+/// API Key 表查询列（顺序需与 `row_to_api_key` 保持一致）
+const API_KEY_COLUMNS: &str =
+    "id, name, key_hash, allowed_models, rate_limit_per_minute, expires_at, revoked, created_at";
+
+/// 将查询行映射为 API Key（列顺序见 `API_KEY_COLUMNS`）
+fn row_to_api_key(row: &rusqlite::Row<'_>) -> rusqlite::Result<ApiKey> {
+    let key_hash: String = row.get(2)?;
+    Ok(ApiKey {
+        id: row.get::<_, i64>(0)? as u64,
+        name: row.get(1)?,
+        fingerprint: ApiKey::fingerprint_of(&key_hash),
+        key_hash,
+        allowed_models: split_models(row.get(3)?),
+        rate_limit_per_minute: row.get::<_, Option<i64>>(4)?.map(|v| v as u32),
+        expires_at: row
+            .get::<_, Option<i64>>(5)?
+            .and_then(chrono::DateTime::from_timestamp_millis),
+        revoked: row.get::<_, i64>(6)? != 0,
+        created_at: chrono::DateTime::from_timestamp_millis(row.get(7)?).unwrap_or_default(),
+    })
+}
+
+/// Admin Token 表查询列（顺序需与 `row_to_admin_token` 保持一致）
+const ADMIN_TOKEN_COLUMNS: &str = "id, name, token_hash, scopes, expires_at, revoked, created_at";
+
+/// 将查询行映射为 Admin Token（列顺序见 `ADMIN_TOKEN_COLUMNS`）
+fn row_to_admin_token(row: &rusqlite::Row<'_>) -> rusqlite::Result<AdminToken> {
+    let scopes: String = row.get(3)?;
+    Ok(AdminToken {
+        id: row.get::<_, i64>(0)? as u64,
+        name: row.get(1)?,
+        token_hash: row.get(2)?,
+        // 忽略无法识别的权限范围（如降级后数据库中的新权限）
+        scopes: scopes.split(',').filter_map(AdminScope::parse).collect(),
+        expires_at: row
+            .get::<_, Option<i64>>(4)?
+            .and_then(chrono::DateTime::from_timestamp_millis),
+        revoked: row.get::<_, i64>(5)? != 0,
+        created_at: chrono::DateTime::from_timestamp_millis(row.get(6)?).unwrap_or_default(),
+    })
+}
+
+/// 通知表查询列（顺序需与 `row_to_notification` 保持一致）
+const NOTIFICATION_COLUMNS: &str = "id, kind, subject, body, status, attempts, next_attempt_at, \
+     last_error, created_at, delivered_at";
+
+/// 将查询行映射为通知（列顺序见 `NOTIFICATION_COLUMNS`）
+fn row_to_notification(row: &rusqlite::Row<'_>) -> rusqlite::Result<Notification> {
+    Ok(Notification {
+        id: row.get::<_, i64>(0)? as u64,
+        kind: row.get(1)?,
+        subject: row.get(2)?,
+        body: row.get(3)?,
+        status: NotificationStatus::parse(&row.get::<_, String>(4)?),
+        attempts: row.get::<_, i64>(5)? as u32,
+        next_attempt_at: chrono::DateTime::from_timestamp_millis(row.get(6)?).unwrap_or_default(),
+        last_error: row.get(7)?,
+        created_at: chrono::DateTime::from_timestamp_millis(row.get(8)?).unwrap_or_default(),
+        delivered_at: row
+            .get::<_, Option<i64>>(9)?
+            .and_then(chrono::DateTime::from_timestamp_millis),
+    })
+}
+
+/// 健康检查记录查询列（顺序需与 `row_to_health_check` 保持一致）
+const HEALTH_CHECK_COLUMNS: &str =
+    "id, credential_id, checked_at, healthy, latency_ms, error, trigger";
+
+/// 将查询行映射为健康检查记录（列顺序见 `HEALTH_CHECK_COLUMNS`）
+fn row_to_health_check(row: &rusqlite::Row<'_>) -> rusqlite::Result<HealthCheck> {
+    Ok(HealthCheck {
+        id: row.get::<_, i64>(0)? as u64,
+        credential_id: row.get::<_, i64>(1)? as u64,
+        checked_at: chrono::DateTime::from_timestamp_millis(row.get(2)?).unwrap_or_default(),
+        healthy: row.get::<_, i64>(3)? != 0,
+        latency_ms: row.get::<_, i64>(4)? as u64,
+        error: row.get(5)?,
+        trigger: HealthCheckTrigger::parse(&row.get::<_, String>(6)?),
+    })
+}
+
+/// 转义 LIKE 模式中的通配符（配合 `ESCAPE '\'` 使用）
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// 在阻塞线程池中执行同步操作
+///
+/// rusqlite 为同步 API，在异步任务中直接调用时，磁盘较慢或 VACUUM 等长操作
+/// 会阻塞 tokio 工作线程，拖慢同一线程上无关的流式响应。
+/// 任务内的 panic 会在调用方重新抛出
+pub async fn blocking<T, F>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(e) => panic!("阻塞任务被取消: {}", e),
+    }
+}
+
+/// 计算入库的过期时间（规范化为 UTC RFC3339）
+///
+/// 无法解析的值按缺失处理（视为已过期，下次使用时刷新），避免与其他行比较时静默出错
+fn stored_expires_at(cred: &KiroCredentials) -> Option<String> {
+    let raw = cred.expires_at.as_deref()?;
+    let normalized = normalize_expires_at(raw);
+    if normalized.is_none() {
+        tracing::warn!(
+            "凭据 #{} 的过期时间无法解析，已忽略: {}",
+            cred.id.map_or_else(|| "-".to_string(), |id| id.to_string()),
+            raw
+        );
+    }
+    normalized
+}
+
+/// 入库的 refreshToken（列为 NOT NULL，IAM 凭据没有 refreshToken 时写入空字符串）
+fn stored_refresh_token(cred: &KiroCredentials) -> &str {
+    cred.refresh_token.as_deref().unwrap_or_default()
+}
+
+/// 插入一行凭据，返回分配的 ID
+fn insert_credential_row(conn: &Connection, cred: &KiroCredentials) -> Result<u64> {
+    conn.execute(
+        r#"
+        INSERT INTO credentials (refresh_token, access_token, expires_at, auth_method,
+                                 client_id, client_secret, profile_arn, priority,
+                                 disabled, failure_count,
+                                 subscription_title, current_usage, usage_limit, next_reset_at, balance_updated_at,
+                                 machine_id, email, kiro_version, system_version, node_version,
+                                 allowed_models, extra_headers,
+                                 access_key_id, secret_access_key, session_token, region)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+                ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26)
+        "#,
+        params![
+            stored_refresh_token(cred),
+            cred.access_token,
+            stored_expires_at(cred),
+            cred.auth_method,
+            cred.client_id,
+            cred.client_secret,
+            cred.profile_arn,
+            cred.priority as i64,
+            cred.disabled as i64,
+            cred.failure_count as i64,
+            cred.subscription_title,
+            cred.current_usage,
+            cred.usage_limit,
+            cred.next_reset_at,
+            cred.balance_updated_at,
+            cred.machine_id,
+            cred.email,
+            cred.kiro_version,
+            cred.system_version,
+            cred.node_version,
+            join_models(&cred.allowed_models),
+            encode_headers(&cred.extra_headers),
+            cred.access_key_id,
+            cred.secret_access_key,
+            cred.session_token,
+            cred.region,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid() as u64)
+}
+
+/// 写入 refreshToken（IAM 凭据为 accessKeyId）尚不存在的凭据，返回 (导入数, 跳过数)
+fn insert_new_credentials(conn: &Connection, creds: &[KiroCredentials]) -> Result<(usize, usize)> {
+    let mut imported = 0;
+    let mut skipped = 0;
+    for cred in creds {
+        let (column, key) = if cred.is_iam() {
+            ("access_key_id", cred.access_key_id.as_deref())
+        } else {
+            ("refresh_token", cred.refresh_token.as_deref())
+        };
+        let Some(key) = key.filter(|k| !k.is_empty()) else {
+            skipped += 1;
+            continue;
+        };
+        let exists: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM credentials WHERE {} = ?1", column),
+            params![key],
+            |row| row.get(0),
+        )?;
+        if exists > 0 {
+            skipped += 1;
+            continue;
+        }
+        insert_credential_row(conn, cred)?;
+        imported += 1;
+    }
+    Ok((imported, skipped))
+}
+
+/// 排空中的凭据的禁用原因（见 [`CredentialStore::set_draining`]）
+const DISABLED_REASON_DRAIN: &str = "drain";
+
+/// 单个凭据的批量更新项（省略的字段保持不变）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CredentialUpdate {
+    pub id: u64,
+    /// 是否禁用（启用时同时重置失败计数）
+    pub disabled: Option<bool>,
+    pub priority: Option<u32>,
+}
+
+/// 多实例租约记录
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lease {
+    /// 租约名称（后台任务）
+    pub name: String,
+    /// 持有者实例 ID
+    pub holder: String,
+    /// 当前持有者获取租约的时间
+    pub acquired_at: chrono::DateTime<chrono::Utc>,
+    /// 过期时间（持有者需在此之前续期）
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
 
-use crate::kiro::model::credentials::KiroCredentials;
+/// 打开或创建 SQLite 数据库文件
+fn connect(path: &Path) -> Result<Connection> {
+    // 确保父目录存在
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+        && !parent.exists()
+    {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("创建数据库目录失败: {:?}", parent))?;
+    }
+
+    let conn = Connection::open(path).with_context(|| format!("打开数据库失败: {:?}", path))?;
+
+    // 使用 DELETE 模式，只保留单个 db 文件
+    conn.execute_batch("PRAGMA journal_mode=DELETE;")?;
+    Ok(conn)
+}
 
 /// 数据库连接包装器
+///
+/// 所有方法均为同步调用；在异步上下文中应通过 [`Database::call`] 访问
 pub struct Database {
-    conn: Mutex<Connection>,
+    conn: Arc<Mutex<Connection>>,
+    /// 凭据与租约存储
+    credentials: Box<dyn CredentialStore>,
+    /// 客户端 API Key 变更计数（签发、吊销、删除时递增，用于使 Key 缓存失效）
+    api_keys_generation: AtomicU64,
 }
 
 impl Database {
     /// 打开或创建数据库
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Arc<Self>> {
-        let path = path.as_ref();
+        Self::with_connection(connect(path.as_ref())?, None, Vec::new())
+    }
 
-        // 确保父目录存在
-        if let Some(parent) = path.parent()
-            && !parent.as_os_str().is_empty()
-            && !parent.exists()
-        {
-            std::fs::create_dir_all(parent)
-                .with_context(|| format!("创建数据库目录失败: {:?}", parent))?;
-        }
+    /// 打开内存数据库（不持久化，进程退出后数据丢失）
+    pub fn open_in_memory() -> Result<Arc<Self>> {
+        let conn = Connection::open_in_memory().context("打开内存数据库失败")?;
+        Self::with_connection(conn, None, Vec::new())
+    }
 
-        let conn = Connection::open(path).with_context(|| format!("打开数据库失败: {:?}", path))?;
+    /// 打开数据库，凭据与租约改由 `store` 读写（如 PostgreSQL）
+    ///
+    /// 其余数据仍写入 `path` 处的 SQLite；`path` 为 None 时使用内存数据库
+    pub fn open_with_credential_store(
+        path: Option<&Path>,
+        store: Box<dyn CredentialStore>,
+    ) -> Result<Arc<Self>> {
+        Self::open_with_shared_credentials(path, Some(store), Vec::new())
+    }
 
-        // 使用 DELETE 模式，只保留单个 db 文件
-        conn.execute_batch("PRAGMA journal_mode=DELETE;")?;
+    /// 打开数据库，并在选择凭据时合并只读共享凭据库中的凭据
+    ///
+    /// `store` 为 None 时本地凭据存储在 `path` 处的 SQLite 中；`path` 为 None 时使用内存数据库
+    pub fn open_with_shared_credentials(
+        path: Option<&Path>,
+        store: Option<Box<dyn CredentialStore>>,
+        shared: Vec<SharedCredentials>,
+    ) -> Result<Arc<Self>> {
+        let conn = match path {
+            Some(path) => connect(path)?,
+            None => Connection::open_in_memory().context("打开内存数据库失败")?,
+        };
+        Self::with_connection(conn, store, shared)
+    }
 
+    /// 使用已打开的连接初始化数据库（`store` 为 None 时凭据存储在同一连接中）
+    fn with_connection(
+        conn: Connection,
+        store: Option<Box<dyn CredentialStore>>,
+        shared: Vec<SharedCredentials>,
+    ) -> Result<Arc<Self>> {
+        conn.busy_timeout(Duration::from_millis(BUSY_TIMEOUT_MS))?;
+        let conn = Arc::new(Mutex::new(conn));
+        let local = store.unwrap_or_else(|| Box::new(SqliteCredentialStore { conn: conn.clone() }));
+        let credentials: Box<dyn CredentialStore> = if shared.is_empty() {
+            local
+        } else {
+            Box::new(merged::MergedCredentialStore::new(local, shared))
+        };
         let db = Self {
-            conn: Mutex::new(conn),
+            conn,
+            credentials,
+            api_keys_generation: AtomicU64::new(0),
         };
 
         db.init_schema()?;
@@ -43,245 +596,1439 @@ impl Database {
         Ok(Arc::new(db))
     }
 
-    /// 初始化数据库 schema
+    /// 在阻塞线程池中访问数据库（供异步上下文使用）
+    pub async fn call<T, F>(self: &Arc<Self>, f: F) -> T
+    where
+        F: FnOnce(&Database) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let db = self.clone();
+        blocking(move || f(&db)).await
+    }
+
+    /// 初始化数据库 schema（按版本执行未应用的迁移）
     fn init_schema(&self) -> Result<()> {
-        let conn = self.conn.lock();
-        conn.execute_batch(
-            r#"
-            CREATE TABLE IF NOT EXISTS credentials (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                refresh_token TEXT NOT NULL,
-                access_token TEXT,
-                expires_at TEXT,
-                auth_method TEXT DEFAULT 'social',
-                client_id TEXT,
-                client_secret TEXT,
-                profile_arn TEXT,
-                priority INTEGER DEFAULT 0,
-                disabled INTEGER DEFAULT 0,
-                failure_count INTEGER DEFAULT 0,
-                disabled_at TEXT,
-                subscription_title TEXT,
-                current_usage REAL DEFAULT 0,
-                usage_limit REAL DEFAULT 0,
-                next_reset_at REAL,
-                balance_updated_at TEXT,
-                machine_id TEXT,
-                email TEXT,
-                created_at TEXT DEFAULT CURRENT_TIMESTAMP,
-                updated_at TEXT DEFAULT CURRENT_TIMESTAMP
-            );
+        with_conn(&self.conn, migrations::run)
+    }
 
-            CREATE INDEX IF NOT EXISTS idx_credentials_priority ON credentials(priority);
-            CREATE INDEX IF NOT EXISTS idx_credentials_disabled ON credentials(disabled);
-            "#,
-        )?;
+    /// 当前 schema 版本（已应用的最大迁移版本）
+    pub fn schema_version(&self) -> Result<u32> {
+        with_conn(&self.conn, |conn| migrations::current_version(conn))
+    }
 
-        // 迁移：为已存在的数据库添加 email 列
-        self.migrate_add_email_column(&conn)?;
+    /// 在单个事务中批量写入请求日志
+    pub fn insert_request_logs(&self, logs: &[RequestLog]) -> Result<()> {
+        with_conn(&self.conn, |conn| {
+            let tx = write_transaction(conn)?;
+            {
+                let mut stmt = tx.prepare_cached(
+                    r#"
+                    INSERT INTO request_logs (created_at, model, credential_id, status, client_key,
+                                              latency_ms, stream, error, tag, seed, request_id,
+                                              sse_events)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+                    "#,
+                )?;
+                for log in logs {
+                    stmt.execute(params![
+                        log.created_at.timestamp_millis(),
+                        log.model,
+                        log.credential_id.map(|id| id as i64),
+                        log.status as i64,
+                        log.client_key,
+                        log.latency_ms as i64,
+                        log.stream as i64,
+                        log.error,
+                        log.tag,
+                        log.seed,
+                        log.request_id,
+                        log.sse_events.map(|n| n as i64),
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+    }
 
-        Ok(())
+    /// 删除早于指定时间的请求日志，返回删除条数
+    pub fn prune_request_logs(&self, before: chrono::DateTime<chrono::Utc>) -> Result<usize> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                "DELETE FROM request_logs WHERE created_at < ?1",
+                params![before.timestamp_millis()],
+            )?;
+            Ok(affected)
+        })
     }
 
-    /// 迁移：添加 email 列（如果不存在）
-    fn migrate_add_email_column(&self, conn: &rusqlite::Connection) -> Result<()> {
-        // 检查 email 列是否已存在
-        let has_column = conn.query_row(
-            "SELECT COUNT(*) FROM pragma_table_info('credentials') WHERE name = 'email'",
-            [],
-            |row| row.get::<_, i64>(0),
-        )? > 0;
+    /// 只保留最新的 `max_rows` 条请求日志，返回删除条数
+    pub fn trim_request_logs(&self, max_rows: usize) -> Result<usize> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                r#"
+                DELETE FROM request_logs
+                WHERE id NOT IN (SELECT id FROM request_logs ORDER BY id DESC LIMIT ?1)
+                "#,
+                params![max_rows as i64],
+            )?;
+            Ok(affected)
+        })
+    }
 
-        if !has_column {
-            tracing::info!("正在迁移数据库：添加 email 列");
-            conn.execute("ALTER TABLE credentials ADD COLUMN email TEXT", [])?;
-            tracing::info!("数据库迁移完成：email 列已添加");
+    /// 按条件搜索请求日志（按时间倒序），返回 (匹配总数, 当前页日志)
+    pub fn search_request_logs(
+        &self,
+        filter: &RequestLogFilter,
+    ) -> Result<(usize, Vec<RequestLog>)> {
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+
+        if let Some(from) = filter.from {
+            conditions.push("created_at >= ?");
+            values.push(from.timestamp_millis().into());
+        }
+        if let Some(to) = filter.to {
+            conditions.push("created_at < ?");
+            values.push(to.timestamp_millis().into());
+        }
+        if let Some(model) = &filter.model {
+            conditions.push("model = ?");
+            values.push(model.clone().into());
+        }
+        if let Some(credential_id) = filter.credential_id {
+            conditions.push("credential_id = ?");
+            values.push((credential_id as i64).into());
+        }
+        if let Some(status) = filter.status {
+            conditions.push("status = ?");
+            values.push((status as i64).into());
+        }
+        if let Some(client_key) = &filter.client_key {
+            conditions.push("client_key = ?");
+            values.push(client_key.clone().into());
+        }
+        if let Some(min_latency_ms) = filter.min_latency_ms {
+            conditions.push("latency_ms >= ?");
+            values.push((min_latency_ms as i64).into());
+        }
+        if let Some(query) = &filter.query {
+            conditions.push("error LIKE ? ESCAPE '\\'");
+            values.push(format!("%{}%", escape_like(query)).into());
+        }
+        if let Some(tag) = &filter.tag {
+            conditions.push("tag = ?");
+            values.push(tag.clone().into());
+        }
+        if let Some(seed) = filter.seed {
+            conditions.push("seed = ?");
+            values.push(seed.into());
         }
+        if let Some(request_id) = &filter.request_id {
+            conditions.push("request_id = ?");
+            values.push(request_id.clone().into());
+        }
+
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
 
-        Ok(())
+        with_conn(&self.conn, |conn| {
+            let total: i64 = conn.query_row(
+                &format!("SELECT COUNT(*) FROM request_logs {where_clause}"),
+                rusqlite::params_from_iter(values.iter()),
+                |row| row.get(0),
+            )?;
+
+            values.push((filter.limit as i64).into());
+            values.push((filter.offset as i64).into());
+            let mut stmt = conn.prepare(&format!(
+                r#"
+                SELECT {REQUEST_LOG_COLUMNS}
+                FROM request_logs
+                {where_clause}
+                ORDER BY created_at DESC, id DESC
+                LIMIT ? OFFSET ?
+                "#
+            ))?;
+            let rows = stmt.query_map(
+                rusqlite::params_from_iter(values.iter()),
+                row_to_request_log,
+            )?;
+
+            let mut logs = Vec::new();
+            for row in rows {
+                logs.push(row?);
+            }
+            Ok((total as usize, logs))
+        })
     }
 
-    /// 加载所有凭据（按优先级排序）
-    pub fn load_credentials(&self) -> Result<Vec<KiroCredentials>> {
-        let conn = self.conn.lock();
-        let mut stmt = conn.prepare(
-            r#"
-            SELECT id, refresh_token, access_token, expires_at, auth_method,
-                   client_id, client_secret, profile_arn, priority,
-                   disabled, failure_count,
-                   subscription_title, current_usage, usage_limit, next_reset_at, balance_updated_at,
-                   machine_id, email
-            FROM credentials
-            ORDER BY priority ASC
-            "#,
-        )?;
+    /// 写入一条对话记录，并删除超出 `max_records` 的最早记录（`0` 表示不限制）
+    pub fn insert_transcript(&self, transcript: &Transcript, max_records: usize) -> Result<()> {
+        with_conn(&self.conn, |conn| {
+            let tx = write_transaction(conn)?;
+            tx.execute(
+                r#"
+                INSERT INTO transcripts (created_at, request_id, model, credential_id, client_key,
+                                         stream, status, latency_ms, request, response, stop_reason,
+                                         input_tokens, output_tokens)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+                "#,
+                params![
+                    transcript.created_at.timestamp_millis(),
+                    transcript.request_id,
+                    transcript.model,
+                    transcript.credential_id.map(|id| id as i64),
+                    transcript.client_key,
+                    transcript.stream as i64,
+                    transcript.status as i64,
+                    transcript.latency_ms as i64,
+                    transcript.request.to_string(),
+                    transcript.response.to_string(),
+                    transcript.stop_reason,
+                    transcript.input_tokens.map(|n| n as i64),
+                    transcript.output_tokens.map(|n| n as i64),
+                ],
+            )?;
+            if max_records > 0 {
+                tx.execute(
+                    r#"
+                    DELETE FROM transcripts
+                    WHERE id NOT IN (SELECT id FROM transcripts ORDER BY id DESC LIMIT ?1)
+                    "#,
+                    params![max_records as i64],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    /// 按条件查询对话记录（按时间倒序），返回 (匹配总数, 当前页记录)
+    pub fn list_transcripts(&self, filter: &TranscriptFilter) -> Result<(usize, Vec<Transcript>)> {
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+        if let Some(model) = &filter.model {
+            conditions.push("model = ?");
+            values.push(model.clone().into());
+        }
+        if let Some(request_id) = &filter.request_id {
+            conditions.push("request_id = ?");
+            values.push(request_id.clone().into());
+        }
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        with_conn(&self.conn, |conn| {
+            let total: i64 = conn.query_row(
+                &format!("SELECT COUNT(*) FROM transcripts {where_clause}"),
+                rusqlite::params_from_iter(values.iter()),
+                |row| row.get(0),
+            )?;
+
+            values.push((filter.limit as i64).into());
+            values.push((filter.offset as i64).into());
+            let mut stmt = conn.prepare(&format!(
+                r#"
+                SELECT {TRANSCRIPT_COLUMNS}
+                FROM transcripts
+                {where_clause}
+                ORDER BY created_at DESC, id DESC
+                LIMIT ? OFFSET ?
+                "#
+            ))?;
+            let transcripts = stmt
+                .query_map(rusqlite::params_from_iter(values.iter()), row_to_transcript)?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok((total as usize, transcripts))
+        })
+    }
+
+    /// 写入一条用量记录
+    pub fn insert_usage_log(&self, log: &UsageLog) -> Result<()> {
+        with_conn(&self.conn, |conn| {
+            conn.execute(
+                r#"
+                INSERT INTO usage_log (created_at, credential_id, model, tag, input_tokens,
+                                       output_tokens, latency_ms, status, stream)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                "#,
+                params![
+                    log.created_at.timestamp_millis(),
+                    log.credential_id.map(|id| id as i64),
+                    log.model,
+                    log.tag,
+                    log.input_tokens as i64,
+                    log.output_tokens as i64,
+                    log.latency_ms as i64,
+                    log.status as i64,
+                    log.stream as i64,
+                ],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// 删除早于指定时间的用量记录，返回删除条数
+    pub fn prune_usage_log(&self, before: chrono::DateTime<chrono::Utc>) -> Result<usize> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                "DELETE FROM usage_log WHERE created_at < ?1",
+                params![before.timestamp_millis()],
+            )?;
+            Ok(affected)
+        })
+    }
+
+    /// 按条件汇总用量：总计、按凭据、按模型、按请求标签
+    pub fn summarize_usage(&self, filter: &UsageFilter) -> Result<UsageSummary> {
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+
+        if let Some(from) = filter.from {
+            conditions.push("created_at >= ?");
+            values.push(from.timestamp_millis().into());
+        }
+        if let Some(to) = filter.to {
+            conditions.push("created_at < ?");
+            values.push(to.timestamp_millis().into());
+        }
+        if let Some(credential_id) = filter.credential_id {
+            conditions.push("credential_id = ?");
+            values.push((credential_id as i64).into());
+        }
+        if let Some(model) = &filter.model {
+            conditions.push("model = ?");
+            values.push(model.clone().into());
+        }
+        if let Some(tag) = &filter.tag {
+            conditions.push("tag = ?");
+            values.push(tag.clone().into());
+        }
 
-        let rows = stmt.query_map([], |row| {
-            Ok(KiroCredentials {
-                id: Some(row.get::<_, i64>(0)? as u64),
-                refresh_token: row.get(1)?,
-                access_token: row.get(2)?,
-                expires_at: row.get(3)?,
-                auth_method: row.get(4)?,
-                client_id: row.get(5)?,
-                client_secret: row.get(6)?,
-                profile_arn: row.get(7)?,
-                priority: row.get::<_, i64>(8)? as u32,
-                disabled: row.get::<_, i64>(9)? != 0,
-                failure_count: row.get::<_, i64>(10)? as u32,
-                subscription_title: row.get(11)?,
-                current_usage: row.get::<_, Option<f64>>(12)?.unwrap_or(0.0),
-                usage_limit: row.get::<_, Option<f64>>(13)?.unwrap_or(0.0),
-                next_reset_at: row.get(14)?,
-                balance_updated_at: row.get(15)?,
-                machine_id: row.get(16)?,
-                email: row.get(17)?,
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        with_conn(&self.conn, |conn| {
+            let total = conn.query_row(
+                &format!("SELECT {USAGE_TOTALS_COLUMNS} FROM usage_log {where_clause}"),
+                rusqlite::params_from_iter(values.iter()),
+                |row| row_to_usage_totals(row, 0),
+            )?;
+
+            let mut stmt = conn.prepare(&format!(
+                r#"
+                SELECT credential_id, {USAGE_TOTALS_COLUMNS}
+                FROM usage_log
+                {where_clause}
+                GROUP BY credential_id
+                ORDER BY SUM(output_tokens) DESC, credential_id
+                "#
+            ))?;
+            let credentials = stmt
+                .query_map(rusqlite::params_from_iter(values.iter()), |row| {
+                    Ok(CredentialUsage {
+                        credential_id: row.get::<_, Option<i64>>(0)?.map(|id| id as u64),
+                        totals: row_to_usage_totals(row, 1)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+
+            let mut stmt = conn.prepare(&format!(
+                r#"
+                SELECT model, {USAGE_TOTALS_COLUMNS}
+                FROM usage_log
+                {where_clause}
+                GROUP BY model
+                ORDER BY SUM(output_tokens) DESC, model
+                "#
+            ))?;
+            let models = stmt
+                .query_map(rusqlite::params_from_iter(values.iter()), |row| {
+                    Ok(ModelUsage {
+                        model: row.get(0)?,
+                        totals: row_to_usage_totals(row, 1)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+
+            let mut stmt = conn.prepare(&format!(
+                r#"
+                SELECT tag, {USAGE_TOTALS_COLUMNS}
+                FROM usage_log
+                {where_clause}
+                GROUP BY tag
+                ORDER BY SUM(output_tokens) DESC, tag
+                "#
+            ))?;
+            let tags = stmt
+                .query_map(rusqlite::params_from_iter(values.iter()), |row| {
+                    Ok(TagUsage {
+                        tag: row.get(0)?,
+                        totals: row_to_usage_totals(row, 1)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+
+            Ok(UsageSummary {
+                total,
+                credentials,
+                models,
+                tags,
             })
+        })
+    }
+
+    /// 本连接累计写入的行数（用于检测数据变化）
+    pub fn total_changes(&self) -> u64 {
+        self.conn.lock().total_changes()
+    }
+
+    /// 聚合统计摘要，`since` 之后的请求计入"最近"统计，`today` 之后的用量计入今日统计
+    ///
+    /// 指定 `tag` 时请求与用量统计只计入携带该标签的请求（凭据数量不受影响）
+    pub fn compute_stats(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+        today: chrono::DateTime<chrono::Utc>,
+        tag: Option<&str>,
+    ) -> Result<StatsSummary> {
+        let today = self.compute_today_stats(today, tag)?;
+        with_conn(&self.conn, |conn| {
+            let since = since.timestamp_millis();
+
+            let (credentials_total, credentials_available): (i64, i64) = conn.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(disabled = 0), 0) FROM credentials",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            let requests_total: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM request_logs WHERE ?1 IS NULL OR tag = ?1",
+                params![tag],
+                |row| row.get(0),
+            )?;
+            let (requests_last_hour, errors_last_hour, avg_latency): (i64, i64, Option<f64>) = conn
+                .query_row(
+                    r#"
+                    SELECT COUNT(*), COALESCE(SUM(status >= 400), 0), AVG(latency_ms)
+                    FROM request_logs
+                    WHERE created_at >= ?1 AND (?2 IS NULL OR tag = ?2)
+                    "#,
+                    params![since, tag],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )?;
+
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT model, COUNT(*) AS requests, SUM(status >= 400)
+                FROM request_logs
+                WHERE created_at >= ?1 AND (?2 IS NULL OR tag = ?2)
+                GROUP BY model
+                ORDER BY requests DESC, model
+                "#,
+            )?;
+            let models_last_hour = stmt
+                .query_map(params![since, tag], |row| {
+                    Ok(ModelStats {
+                        model: row.get(0)?,
+                        requests: row.get::<_, i64>(1)? as u64,
+                        errors: row.get::<_, i64>(2)? as u64,
+                    })
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+
+            Ok(StatsSummary {
+                refreshed_at: chrono::Utc::now(),
+                tag: tag.map(str::to_string),
+                credentials_total: credentials_total as u64,
+                credentials_available: credentials_available as u64,
+                requests_total: requests_total as u64,
+                requests_last_hour: requests_last_hour as u64,
+                errors_last_hour: errors_last_hour as u64,
+                avg_latency_ms_last_hour: avg_latency.unwrap_or(0.0),
+                models_last_hour,
+                today: today.clone(),
+            })
+        })
+    }
+
+    /// 聚合 `since` 之后的用量（总计、按模型、按凭据占比与按小时统计）
+    fn compute_today_stats(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+        tag: Option<&str>,
+    ) -> Result<TodayStats> {
+        let usage = self.summarize_usage(&UsageFilter {
+            from: Some(since),
+            tag: tag.map(str::to_string),
+            ..Default::default()
         })?;
+        let requests = usage.total.requests;
+        let ratio = |count: u64| {
+            if requests == 0 {
+                0.0
+            } else {
+                count as f64 / requests as f64
+            }
+        };
 
-        let mut credentials = Vec::new();
-        for row in rows {
-            credentials.push(row?);
-        }
-        Ok(credentials)
+        let mut credentials: Vec<CredentialShare> = usage
+            .credentials
+            .into_iter()
+            .map(|c| CredentialShare {
+                credential_id: c.credential_id,
+                share: ratio(c.totals.requests),
+                totals: c.totals,
+            })
+            .collect();
+        credentials.sort_by_key(|c| std::cmp::Reverse(c.totals.requests));
+
+        let hourly = with_conn(&self.conn, |conn| {
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT created_at / 3600000 AS hour, COUNT(*), COALESCE(SUM(status >= 400), 0),
+                       COALESCE(SUM(output_tokens), 0)
+                FROM usage_log
+                WHERE created_at >= ?1 AND (?2 IS NULL OR tag = ?2)
+                GROUP BY hour
+                ORDER BY hour
+                "#,
+            )?;
+            let hourly = stmt
+                .query_map(params![since.timestamp_millis(), tag], |row| {
+                    let hour = row.get::<_, i64>(0)?;
+                    Ok(HourlyStats {
+                        hour: chrono::DateTime::from_timestamp(hour * 3600, 0).unwrap_or_default(),
+                        requests: row.get::<_, i64>(1)? as u64,
+                        errors: row.get::<_, i64>(2)? as u64,
+                        output_tokens: row.get::<_, i64>(3)? as u64,
+                    })
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(hourly)
+        })?;
+
+        Ok(TodayStats {
+            since,
+            success_rate: if requests == 0 {
+                1.0
+            } else {
+                1.0 - ratio(usage.total.errors)
+            },
+            totals: usage.total,
+            models: usage.models,
+            credentials,
+            hourly,
+        })
+    }
+
+    /// 列出所有提示词模板（按名称排序）
+    pub fn list_prompt_templates(&self) -> Result<Vec<PromptTemplate>> {
+        with_conn(&self.conn, |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {PROMPT_TEMPLATE_COLUMNS} FROM prompt_templates ORDER BY name"
+            ))?;
+            let templates = stmt
+                .query_map([], row_to_prompt_template)?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(templates)
+        })
+    }
+
+    /// 获取单个提示词模板
+    pub fn get_prompt_template(&self, name: &str) -> Result<Option<PromptTemplate>> {
+        with_conn(&self.conn, |conn| {
+            let result = conn.query_row(
+                &format!("SELECT {PROMPT_TEMPLATE_COLUMNS} FROM prompt_templates WHERE name = ?1"),
+                params![name],
+                row_to_prompt_template,
+            );
+
+            match result {
+                Ok(template) => Ok(Some(template)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    /// 创建或更新提示词模板，返回是否为新建
+    pub fn upsert_prompt_template(&self, template: &PromptTemplate) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let exists: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM prompt_templates WHERE name = ?1",
+                params![template.name],
+                |row| row.get(0),
+            )?;
+            conn.execute(
+                r#"
+                INSERT INTO prompt_templates (name, content, description, updated_at)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT(name) DO UPDATE SET
+                    content = excluded.content, description = excluded.description,
+                    updated_at = excluded.updated_at
+                "#,
+                params![
+                    template.name,
+                    template.content,
+                    template.description,
+                    template.updated_at.timestamp_millis(),
+                ],
+            )?;
+            Ok(exists == 0)
+        })
+    }
+
+    /// 删除提示词模板
+    pub fn delete_prompt_template(&self, name: &str) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                "DELETE FROM prompt_templates WHERE name = ?1",
+                params![name],
+            )?;
+            Ok(affected > 0)
+        })
+    }
+
+    /// 列出所有客户端 API Key（按 ID 排序）
+    pub fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        with_conn(&self.conn, |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {API_KEY_COLUMNS} FROM api_keys ORDER BY id"
+            ))?;
+            let keys = stmt
+                .query_map([], row_to_api_key)?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(keys)
+        })
+    }
+
+    /// 客户端 API Key 变更计数（本进程内签发、吊销或删除 Key 后递增）
+    pub fn api_keys_generation(&self) -> u64 {
+        self.api_keys_generation.load(Ordering::Acquire)
+    }
+
+    /// 按哈希查找客户端 API Key
+    pub fn find_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        with_conn(&self.conn, |conn| {
+            let result = conn.query_row(
+                &format!("SELECT {API_KEY_COLUMNS} FROM api_keys WHERE key_hash = ?1"),
+                params![key_hash],
+                row_to_api_key,
+            );
+
+            match result {
+                Ok(key) => Ok(Some(key)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    /// 新增客户端 API Key，返回分配的 ID
+    pub fn insert_api_key(&self, key: &ApiKey) -> Result<u64> {
+        with_conn(&self.conn, |conn| {
+            conn.execute(
+                r#"
+                INSERT INTO api_keys (name, key_hash, allowed_models, rate_limit_per_minute,
+                                      expires_at, revoked, created_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                "#,
+                params![
+                    key.name,
+                    key.key_hash,
+                    join_models(&key.allowed_models),
+                    key.rate_limit_per_minute.map(|v| v as i64),
+                    key.expires_at.map(|t| t.timestamp_millis()),
+                    key.revoked as i64,
+                    key.created_at.timestamp_millis(),
+                ],
+            )?;
+            self.api_keys_generation.fetch_add(1, Ordering::AcqRel);
+            Ok(conn.last_insert_rowid() as u64)
+        })
+    }
+
+    /// 吊销客户端 API Key（保留记录）
+    pub fn revoke_api_key(&self, id: u64) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                "UPDATE api_keys SET revoked = 1 WHERE id = ?1",
+                params![id as i64],
+            )?;
+            self.api_keys_generation.fetch_add(1, Ordering::AcqRel);
+            Ok(affected > 0)
+        })
+    }
+
+    /// 删除客户端 API Key
+    pub fn delete_api_key(&self, id: u64) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected =
+                conn.execute("DELETE FROM api_keys WHERE id = ?1", params![id as i64])?;
+            self.api_keys_generation.fetch_add(1, Ordering::AcqRel);
+            Ok(affected > 0)
+        })
+    }
+
+    /// 列出所有受限 Admin Token（按 ID 排序）
+    pub fn list_admin_tokens(&self) -> Result<Vec<AdminToken>> {
+        with_conn(&self.conn, |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {ADMIN_TOKEN_COLUMNS} FROM admin_tokens ORDER BY id"
+            ))?;
+            let tokens = stmt
+                .query_map([], row_to_admin_token)?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(tokens)
+        })
+    }
+
+    /// 按哈希查找受限 Admin Token
+    pub fn find_admin_token(&self, token_hash: &str) -> Result<Option<AdminToken>> {
+        with_conn(&self.conn, |conn| {
+            let result = conn.query_row(
+                &format!("SELECT {ADMIN_TOKEN_COLUMNS} FROM admin_tokens WHERE token_hash = ?1"),
+                params![token_hash],
+                row_to_admin_token,
+            );
+
+            match result {
+                Ok(token) => Ok(Some(token)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    /// 新增受限 Admin Token，返回分配的 ID
+    pub fn insert_admin_token(&self, token: &AdminToken) -> Result<u64> {
+        with_conn(&self.conn, |conn| {
+            let scopes = token
+                .scopes
+                .iter()
+                .map(|scope| scope.as_str())
+                .collect::<Vec<_>>()
+                .join(",");
+            conn.execute(
+                r#"
+                INSERT INTO admin_tokens (name, token_hash, scopes, expires_at, revoked, created_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                "#,
+                params![
+                    token.name,
+                    token.token_hash,
+                    scopes,
+                    token.expires_at.map(|t| t.timestamp_millis()),
+                    token.revoked as i64,
+                    token.created_at.timestamp_millis(),
+                ],
+            )?;
+            Ok(conn.last_insert_rowid() as u64)
+        })
+    }
+
+    /// 吊销受限 Admin Token（保留记录）
+    pub fn revoke_admin_token(&self, id: u64) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                "UPDATE admin_tokens SET revoked = 1 WHERE id = ?1",
+                params![id as i64],
+            )?;
+            Ok(affected > 0)
+        })
+    }
+
+    /// 删除受限 Admin Token
+    pub fn delete_admin_token(&self, id: u64) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected =
+                conn.execute("DELETE FROM admin_tokens WHERE id = ?1", params![id as i64])?;
+            Ok(affected > 0)
+        })
+    }
+
+    /// 将通知加入投递队列（立即可投递），返回分配的 ID
+    pub fn enqueue_notification(&self, kind: &str, subject: &str, body: &str) -> Result<u64> {
+        with_conn(&self.conn, |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            conn.execute(
+                r#"
+                INSERT INTO notifications (kind, subject, body, status, attempts, next_attempt_at, created_at)
+                VALUES (?1, ?2, ?3, 'pending', 0, ?4, ?4)
+                "#,
+                params![kind, subject, body, now],
+            )?;
+            Ok(conn.last_insert_rowid() as u64)
+        })
+    }
+
+    /// 获取已到投递时间的待投递通知（按下次尝试时间排序）
+    pub fn due_notifications(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+        limit: usize,
+    ) -> Result<Vec<Notification>> {
+        with_conn(&self.conn, |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {NOTIFICATION_COLUMNS} FROM notifications \
+                 WHERE status = 'pending' AND next_attempt_at <= ?1 \
+                 ORDER BY next_attempt_at, id LIMIT ?2"
+            ))?;
+            let notifications = stmt
+                .query_map(
+                    params![now.timestamp_millis(), limit as i64],
+                    row_to_notification,
+                )?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(notifications)
+        })
+    }
+
+    /// 最早的待投递通知的下次尝试时间（没有待投递通知时为 None）
+    pub fn next_notification_at(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+        with_conn(&self.conn, |conn| {
+            let next: Option<i64> = conn.query_row(
+                "SELECT MIN(next_attempt_at) FROM notifications WHERE status = 'pending'",
+                [],
+                |row| row.get(0),
+            )?;
+            Ok(next.and_then(chrono::DateTime::from_timestamp_millis))
+        })
+    }
+
+    /// 标记通知投递成功
+    pub fn mark_notification_delivered(&self, id: u64) -> Result<()> {
+        with_conn(&self.conn, |conn| {
+            conn.execute(
+                r#"
+                UPDATE notifications
+                SET status = 'delivered', attempts = attempts + 1, last_error = NULL, delivered_at = ?2
+                WHERE id = ?1
+                "#,
+                params![id as i64, chrono::Utc::now().timestamp_millis()],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// 记录一次投递失败
+    ///
+    /// `next_attempt_at` 为下次重试时间，None 表示不再重试（进入死信状态）
+    pub fn mark_notification_failed(
+        &self,
+        id: u64,
+        error: &str,
+        next_attempt_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<()> {
+        with_conn(&self.conn, |conn| {
+            let status = if next_attempt_at.is_some() {
+                NotificationStatus::Pending
+            } else {
+                NotificationStatus::Dead
+            };
+            conn.execute(
+                r#"
+                UPDATE notifications
+                SET status = ?2, attempts = attempts + 1, last_error = ?3,
+                    next_attempt_at = COALESCE(?4, next_attempt_at)
+                WHERE id = ?1
+                "#,
+                params![
+                    id as i64,
+                    status.as_str(),
+                    error,
+                    next_attempt_at.map(|t| t.timestamp_millis()),
+                ],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// 列出通知（按 ID 倒序），可按状态过滤
+    pub fn list_notifications(
+        &self,
+        status: Option<NotificationStatus>,
+        limit: usize,
+    ) -> Result<Vec<Notification>> {
+        with_conn(&self.conn, |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {NOTIFICATION_COLUMNS} FROM notifications \
+                 WHERE ?1 IS NULL OR status = ?1 ORDER BY id DESC LIMIT ?2"
+            ))?;
+            let notifications = stmt
+                .query_map(
+                    params![status.map(NotificationStatus::as_str), limit as i64],
+                    row_to_notification,
+                )?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(notifications)
+        })
+    }
+
+    /// 重放未投递成功的通知：重置尝试次数并立即重新投递
+    ///
+    /// 返回 None 表示通知不存在，Some(false) 表示通知已投递成功（不重放）
+    pub fn replay_notification(&self, id: u64) -> Result<Option<bool>> {
+        with_conn(&self.conn, |conn| {
+            let status: Option<String> = match conn.query_row(
+                "SELECT status FROM notifications WHERE id = ?1",
+                params![id as i64],
+                |row| row.get(0),
+            ) {
+                Ok(status) => Some(status),
+                Err(rusqlite::Error::QueryReturnedNoRows) => None,
+                Err(e) => return Err(e.into()),
+            };
+            let Some(status) = status else {
+                return Ok(None);
+            };
+            if NotificationStatus::parse(&status) == NotificationStatus::Delivered {
+                return Ok(Some(false));
+            }
+            conn.execute(
+                "UPDATE notifications SET status = 'pending', attempts = 0, next_attempt_at = ?2 WHERE id = ?1",
+                params![id as i64, chrono::Utc::now().timestamp_millis()],
+            )?;
+            Ok(Some(true))
+        })
+    }
+
+    /// 写入健康检查记录，返回分配的 ID
+    pub fn insert_health_check(&self, check: &HealthCheck) -> Result<u64> {
+        with_conn(&self.conn, |conn| {
+            conn.execute(
+                r#"
+                INSERT INTO credential_health_checks (credential_id, checked_at, healthy, latency_ms, error, trigger)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                "#,
+                params![
+                    check.credential_id as i64,
+                    check.checked_at.timestamp_millis(),
+                    check.healthy as i64,
+                    check.latency_ms as i64,
+                    check.error,
+                    check.trigger.as_str(),
+                ],
+            )?;
+            Ok(conn.last_insert_rowid() as u64)
+        })
+    }
+
+    /// 列出凭据的健康检查记录（按检查时间倒序）
+    pub fn list_health_checks(&self, credential_id: u64, limit: usize) -> Result<Vec<HealthCheck>> {
+        with_conn(&self.conn, |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {HEALTH_CHECK_COLUMNS} FROM credential_health_checks \
+                 WHERE credential_id = ?1 ORDER BY checked_at DESC, id DESC LIMIT ?2"
+            ))?;
+            let checks = stmt
+                .query_map(
+                    params![credential_id as i64, limit as i64],
+                    row_to_health_check,
+                )?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(checks)
+        })
+    }
+
+    /// 按凭据统计指定时间之后的健康检查，返回 凭据 ID -> (检查次数, 失败次数)
+    pub fn summarize_health_checks(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<HashMap<u64, (u64, u64)>> {
+        with_conn(&self.conn, |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT credential_id, COUNT(*), COALESCE(SUM(healthy = 0), 0) \
+                 FROM credential_health_checks WHERE checked_at >= ?1 GROUP BY credential_id",
+            )?;
+            let summary = stmt
+                .query_map(params![since.timestamp_millis()], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)? as u64,
+                        (row.get::<_, i64>(1)? as u64, row.get::<_, i64>(2)? as u64),
+                    ))
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(summary)
+        })
+    }
+
+    /// 删除早于指定时间的健康检查记录，返回删除条数
+    pub fn prune_health_checks(&self, before: chrono::DateTime<chrono::Utc>) -> Result<usize> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                "DELETE FROM credential_health_checks WHERE checked_at < ?1",
+                params![before.timestamp_millis()],
+            )?;
+            Ok(affected)
+        })
+    }
+}
+
+/// 凭据与租约：委托给凭据存储（默认为同一 SQLite 连接，见 [`CredentialStore`]）
+impl Database {
+    /// 加载所有凭据（按优先级排序）
+    pub fn load_credentials(&self) -> Result<Vec<KiroCredentials>> {
+        self.credentials.load_credentials()
     }
 
     /// 插入新凭据，返回分配的 ID
     pub fn insert_credential(&self, cred: &KiroCredentials) -> Result<u64> {
-        let conn = self.conn.lock();
-        conn.execute(
-            r#"
-            INSERT INTO credentials (refresh_token, access_token, expires_at, auth_method,
-                                     client_id, client_secret, profile_arn, priority,
-                                     disabled, failure_count,
-                                     subscription_title, current_usage, usage_limit, next_reset_at, balance_updated_at,
-                                     machine_id, email)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)
-            "#,
-            params![
-                cred.refresh_token,
-                cred.access_token,
-                cred.expires_at,
-                cred.auth_method,
-                cred.client_id,
-                cred.client_secret,
-                cred.profile_arn,
-                cred.priority as i64,
-                cred.disabled as i64,
-                cred.failure_count as i64,
-                cred.subscription_title,
-                cred.current_usage,
-                cred.usage_limit,
-                cred.next_reset_at,
-                cred.balance_updated_at,
-                cred.machine_id,
-                cred.email,
-            ],
-        )?;
-        Ok(conn.last_insert_rowid() as u64)
+        self.credentials.insert_credential(cred)
     }
 
-    /// 更新凭据
-    pub fn update_credential(&self, cred: &KiroCredentials) -> Result<bool> {
-        let id = cred.id.ok_or_else(|| anyhow::anyhow!("凭据缺少 ID"))?;
-        let conn = self.conn.lock();
-        let affected = conn.execute(
-            r#"
-            UPDATE credentials
-            SET refresh_token = ?1, access_token = ?2, expires_at = ?3, auth_method = ?4,
-                client_id = ?5, client_secret = ?6, profile_arn = ?7, priority = ?8,
-                disabled = ?9, failure_count = ?10,
-                subscription_title = ?11, current_usage = ?12, usage_limit = ?13,
-                next_reset_at = ?14, balance_updated_at = ?15, machine_id = ?16, email = ?17,
-                updated_at = CURRENT_TIMESTAMP
-            WHERE id = ?18
-            "#,
-            params![
-                cred.refresh_token,
-                cred.access_token,
-                cred.expires_at,
-                cred.auth_method,
-                cred.client_id,
-                cred.client_secret,
-                cred.profile_arn,
-                cred.priority as i64,
-                cred.disabled as i64,
-                cred.failure_count as i64,
-                cred.subscription_title,
-                cred.current_usage,
-                cred.usage_limit,
-                cred.next_reset_at,
-                cred.balance_updated_at,
-                cred.machine_id,
-                cred.email,
-                id as i64,
-            ],
-        )?;
-        Ok(affected > 0)
+    /// 一次性导入凭据（用于旧版 JSON 凭据迁移）
+    pub fn import_credentials_once(
+        &self,
+        creds: &[KiroCredentials],
+        marker: &str,
+    ) -> Result<Option<(usize, usize)>> {
+        self.credentials.import_credentials_once(creds, marker)
+    }
+
+    /// 批量导入凭据（Admin 导入接口）
+    pub fn import_credentials(&self, creds: &[KiroCredentials]) -> Result<(usize, usize)> {
+        self.credentials.import_credentials(creds)
+    }
+
+    /// 保存刷新后的 Token（以刷新前的 refreshToken 作为乐观锁）
+    pub fn save_refreshed_token(
+        &self,
+        cred: &KiroCredentials,
+        previous_refresh_token: &str,
+    ) -> Result<bool> {
+        self.credentials
+            .save_refreshed_token(cred, previous_refresh_token)
+    }
+
+    /// 使缓存的 accessToken 失效（清空过期时间，下次使用时强制刷新）
+    pub fn invalidate_access_token(&self, id: u64, access_token: &str) -> Result<bool> {
+        self.credentials.invalidate_access_token(id, access_token)
+    }
+
+    /// 用给定的凭据集合整体替换凭据表（热备同步使用）
+    pub fn replace_credentials(&self, creds: &[KiroCredentials]) -> Result<usize> {
+        self.credentials.replace_credentials(creds)
     }
 
     /// 删除凭据
     pub fn delete_credential(&self, id: u64) -> Result<bool> {
-        let conn = self.conn.lock();
-        let affected = conn.execute("DELETE FROM credentials WHERE id = ?1", params![id as i64])?;
-        Ok(affected > 0)
+        self.credentials.delete_credential(id)
+    }
+
+    /// 凭据是否来自只读的共享凭据库
+    pub fn is_read_only(&self, id: u64) -> bool {
+        self.credentials.is_read_only(id)
     }
 
     /// 获取单个凭据
     pub fn get_credential(&self, id: u64) -> Result<Option<KiroCredentials>> {
-        let conn = self.conn.lock();
-        let mut stmt = conn.prepare(
-            r#"
-            SELECT id, refresh_token, access_token, expires_at, auth_method,
-                   client_id, client_secret, profile_arn, priority,
-                   disabled, failure_count,
-                   subscription_title, current_usage, usage_limit, next_reset_at, balance_updated_at,
-                   machine_id, email
-            FROM credentials
-            WHERE id = ?1
-            "#,
-        )?;
+        self.credentials.get_credential(id)
+    }
 
-        let result = stmt.query_row(params![id as i64], |row| {
-            Ok(KiroCredentials {
-                id: Some(row.get::<_, i64>(0)? as u64),
-                refresh_token: row.get(1)?,
-                access_token: row.get(2)?,
-                expires_at: row.get(3)?,
-                auth_method: row.get(4)?,
-                client_id: row.get(5)?,
-                client_secret: row.get(6)?,
-                profile_arn: row.get(7)?,
-                priority: row.get::<_, i64>(8)? as u32,
-                disabled: row.get::<_, i64>(9)? != 0,
-                failure_count: row.get::<_, i64>(10)? as u32,
-                subscription_title: row.get(11)?,
-                current_usage: row.get::<_, Option<f64>>(12)?.unwrap_or(0.0),
-                usage_limit: row.get::<_, Option<f64>>(13)?.unwrap_or(0.0),
-                next_reset_at: row.get(14)?,
-                balance_updated_at: row.get(15)?,
-                machine_id: row.get(16)?,
-                email: row.get(17)?,
-            })
-        });
+    /// 获取凭据数量
+    pub fn count_credentials(&self) -> Result<usize> {
+        self.credentials.count_credentials()
+    }
+
+    /// 更新凭据余额信息
+    pub fn update_balance(
+        &self,
+        id: u64,
+        subscription_title: Option<&str>,
+        current_usage: f64,
+        usage_limit: f64,
+        next_reset_at: Option<f64>,
+    ) -> Result<bool> {
+        self.credentials.update_balance(
+            id,
+            subscription_title,
+            current_usage,
+            usage_limit,
+            next_reset_at,
+        )
+    }
+
+    /// 设置凭据禁用状态
+    pub fn set_disabled(&self, id: u64, disabled: bool) -> Result<bool> {
+        self.credentials.set_disabled(id, disabled)
+    }
+
+    /// 禁用凭据并标记为排空中（不参与熔断恢复）
+    pub fn set_draining(&self, id: u64) -> Result<bool> {
+        self.credentials.set_draining(id)
+    }
+
+    /// 在单个事务中批量更新凭据的禁用状态与优先级
+    pub fn bulk_update_credentials(&self, updates: &[CredentialUpdate]) -> Result<Option<u64>> {
+        self.credentials.bulk_update_credentials(updates)
+    }
+
+    /// 设置凭据优先级
+    pub fn set_priority(&self, id: u64, priority: u32) -> Result<bool> {
+        self.credentials.set_priority(id, priority)
+    }
+
+    /// 增加失败计数
+    pub fn increment_failure_count(&self, id: u64) -> Result<u32> {
+        self.credentials.increment_failure_count(id)
+    }
+
+    /// 重置失败计数
+    pub fn reset_failure_count(&self, id: u64) -> Result<bool> {
+        self.credentials.reset_failure_count(id)
+    }
+
+    /// 重置失败计数并启用凭据
+    pub fn reset_and_enable(&self, id: u64) -> Result<bool> {
+        self.credentials.reset_and_enable(id)
+    }
+
+    /// 获取熔断期已过的禁用凭据（按优先级排序，半开探测候选）
+    pub fn list_cooled_down(&self, open_duration: Duration) -> Result<Vec<KiroCredentials>> {
+        self.credentials.list_cooled_down(open_duration)
+    }
+
+    /// 获取所有禁用凭据的禁用时间
+    pub fn disabled_since(&self) -> Result<HashMap<u64, chrono::DateTime<chrono::Utc>>> {
+        self.credentials.disabled_since()
+    }
+
+    /// 获取优先级最高的可用凭据
+    pub fn get_highest_priority_available(&self) -> Result<Option<KiroCredentials>> {
+        self.credentials.get_highest_priority_available()
+    }
+
+    /// 获取下一个优先级最高的可用凭据（排除指定 ID）
+    pub fn get_next_available(&self, exclude_id: u64) -> Result<Option<KiroCredentials>> {
+        self.credentials.get_next_available(exclude_id)
+    }
+
+    /// 获取可用凭据数量
+    pub fn count_available(&self) -> Result<usize> {
+        self.credentials.count_available()
+    }
+
+    /// 设置凭据的 machine_id
+    pub fn set_machine_id(&self, id: u64, machine_id: Option<&str>) -> Result<bool> {
+        self.credentials.set_machine_id(id, machine_id)
+    }
+
+    /// 更新凭据的邮箱
+    pub fn update_email(&self, id: u64, email: Option<&str>) -> Result<bool> {
+        self.credentials.update_email(id, email)
+    }
+
+    /// 设置凭据的客户端版本覆盖（None 表示使用全局配置）
+    pub fn set_version_overrides(
+        &self,
+        id: u64,
+        kiro_version: Option<&str>,
+        system_version: Option<&str>,
+        node_version: Option<&str>,
+    ) -> Result<bool> {
+        self.credentials
+            .set_version_overrides(id, kiro_version, system_version, node_version)
+    }
+
+    /// 设置凭据允许使用的模型（None 表示不限制）
+    pub fn set_allowed_models(
+        &self,
+        id: u64,
+        allowed_models: &Option<Vec<String>>,
+    ) -> Result<bool> {
+        self.credentials.set_allowed_models(id, allowed_models)
+    }
+
+    /// 设置凭据的自定义上游请求头（None 表示清除）
+    pub fn set_extra_headers(
+        &self,
+        id: u64,
+        extra_headers: &Option<BTreeMap<String, String>>,
+    ) -> Result<bool> {
+        self.credentials.set_extra_headers(id, extra_headers)
+    }
+
+    /// 检查 client_id 是否已存在
+    pub fn client_id_exists(&self, client_id: &str) -> Result<bool> {
+        self.credentials.client_id_exists(client_id)
+    }
+
+    /// 检查 IAM accessKeyId 是否已存在
+    pub fn access_key_id_exists(&self, access_key_id: &str) -> Result<bool> {
+        self.credentials.access_key_id_exists(access_key_id)
+    }
+
+    /// 获取或续期租约
+    pub fn try_acquire_lease(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool> {
+        self.credentials.try_acquire_lease(name, holder, ttl)
+    }
+
+    /// 释放租约（仅当由 `holder` 持有时）
+    pub fn release_lease(&self, name: &str, holder: &str) -> Result<bool> {
+        self.credentials.release_lease(name, holder)
+    }
+
+    /// 列出所有租约（包括已过期但尚未被接管的）
+    pub fn list_leases(&self) -> Result<Vec<Lease>> {
+        self.credentials.list_leases()
+    }
+}
+
+/// SQLite 凭据存储（与 [`Database`] 共享连接）
+struct SqliteCredentialStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl CredentialStore for SqliteCredentialStore {
+    fn load_credentials(&self) -> Result<Vec<KiroCredentials>> {
+        with_conn(&self.conn, |conn| {
+            let mut stmt = conn.prepare(&format!(
+                r#"
+                SELECT {CREDENTIAL_COLUMNS}
+                FROM credentials
+                ORDER BY priority ASC
+                "#
+            ))?;
+
+            let rows = stmt.query_map([], row_to_credential)?;
+
+            let mut credentials = Vec::new();
+            for row in rows {
+                credentials.push(row?);
+            }
+            Ok(credentials)
+        })
+    }
+
+    fn insert_credential(&self, cred: &KiroCredentials) -> Result<u64> {
+        with_conn(&self.conn, |conn| insert_credential_row(conn, cred))
+    }
+
+    fn import_credentials_once(
+        &self,
+        creds: &[KiroCredentials],
+        marker: &str,
+    ) -> Result<Option<(usize, usize)>> {
+        with_conn(&self.conn, |conn| {
+            let tx = write_transaction(conn)?;
+
+            let done: i64 = tx.query_row(
+                "SELECT COUNT(*) FROM meta WHERE key = ?1",
+                params![marker],
+                |row| row.get(0),
+            )?;
+            if done > 0 {
+                return Ok(None);
+            }
+
+            let counts = insert_new_credentials(&tx, creds)?;
+
+            tx.execute(
+                "INSERT INTO meta (key, value) VALUES (?1, ?2)",
+                params![marker, chrono::Utc::now().to_rfc3339()],
+            )?;
+            tx.commit()?;
+            Ok(Some(counts))
+        })
+    }
+
+    fn import_credentials(&self, creds: &[KiroCredentials]) -> Result<(usize, usize)> {
+        with_conn(&self.conn, |conn| {
+            let tx = write_transaction(conn)?;
+            let counts = insert_new_credentials(&tx, creds)?;
+            tx.commit()?;
+            Ok(counts)
+        })
+    }
+
+    fn save_refreshed_token(
+        &self,
+        cred: &KiroCredentials,
+        previous_refresh_token: &str,
+    ) -> Result<bool> {
+        let id = cred.id.ok_or_else(|| anyhow::anyhow!("凭据缺少 ID"))?;
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                r#"
+                UPDATE credentials
+                SET refresh_token = ?1, access_token = ?2, expires_at = ?3,
+                    profile_arn = COALESCE(?4, profile_arn),
+                    updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?5 AND refresh_token = ?6
+                "#,
+                params![
+                    cred.refresh_token,
+                    cred.access_token,
+                    stored_expires_at(cred),
+                    cred.profile_arn,
+                    id as i64,
+                    previous_refresh_token,
+                ],
+            )?;
+            Ok(affected > 0)
+        })
+    }
+
+    fn invalidate_access_token(&self, id: u64, access_token: &str) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                r#"
+                UPDATE credentials
+                SET expires_at = NULL, updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?1 AND access_token = ?2
+                "#,
+                params![id as i64, access_token],
+            )?;
+            Ok(affected > 0)
+        })
+    }
+
+    fn replace_credentials(&self, creds: &[KiroCredentials]) -> Result<usize> {
+        with_conn(&self.conn, |conn| {
+            let tx = write_transaction(conn)?;
+            let now = chrono::Utc::now().to_rfc3339();
+
+            let keep: std::collections::HashSet<i64> = creds
+                .iter()
+                .filter_map(|c| c.id)
+                .map(|id| id as i64)
+                .collect();
+            let existing: Vec<i64> = {
+                let mut stmt = tx.prepare("SELECT id FROM credentials")?;
+                stmt.query_map([], |row| row.get(0))?
+                    .collect::<rusqlite::Result<_>>()?
+            };
+            let mut removed = 0;
+            for id in existing.into_iter().filter(|id| !keep.contains(id)) {
+                removed += tx.execute("DELETE FROM credentials WHERE id = ?1", params![id])?;
+            }
+
+            for cred in creds {
+                let id = cred.id.ok_or_else(|| anyhow::anyhow!("凭据缺少 ID"))?;
+                tx.execute(
+                    r#"
+                    INSERT INTO credentials (id, refresh_token, access_token, expires_at, auth_method,
+                                             client_id, client_secret, profile_arn, priority,
+                                             disabled, failure_count,
+                                             subscription_title, current_usage, usage_limit, next_reset_at, balance_updated_at,
+                                             machine_id, email, kiro_version, system_version, node_version,
+                                             allowed_models, extra_headers, disabled_at,
+                                             access_key_id, secret_access_key, session_token, region)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+                            ?18, ?19, ?20, ?21, ?22, ?23, CASE WHEN ?10 = 1 THEN ?24 ELSE NULL END,
+                            ?25, ?26, ?27, ?28)
+                    ON CONFLICT(id) DO UPDATE SET
+                        refresh_token = excluded.refresh_token, access_token = excluded.access_token,
+                        expires_at = excluded.expires_at, auth_method = excluded.auth_method,
+                        client_id = excluded.client_id, client_secret = excluded.client_secret,
+                        profile_arn = excluded.profile_arn, priority = excluded.priority,
+                        disabled = excluded.disabled, failure_count = excluded.failure_count,
+                        subscription_title = excluded.subscription_title,
+                        current_usage = excluded.current_usage, usage_limit = excluded.usage_limit,
+                        next_reset_at = excluded.next_reset_at,
+                        balance_updated_at = excluded.balance_updated_at,
+                        machine_id = excluded.machine_id, email = excluded.email,
+                        kiro_version = excluded.kiro_version, system_version = excluded.system_version,
+                        node_version = excluded.node_version,
+                        allowed_models = excluded.allowed_models,
+                        extra_headers = excluded.extra_headers,
+                        access_key_id = excluded.access_key_id,
+                        secret_access_key = excluded.secret_access_key,
+                        session_token = excluded.session_token,
+                        region = excluded.region,
+                        disabled_at = CASE WHEN excluded.disabled = 1
+                                           THEN COALESCE(credentials.disabled_at, excluded.disabled_at)
+                                           ELSE NULL END,
+                        disabled_reason = CASE WHEN excluded.disabled = 1
+                                               THEN credentials.disabled_reason
+                                               ELSE NULL END,
+                        updated_at = CURRENT_TIMESTAMP
+                    "#,
+                    params![
+                        id as i64,
+                        stored_refresh_token(cred),
+                        cred.access_token,
+                        stored_expires_at(cred),
+                        cred.auth_method,
+                        cred.client_id,
+                        cred.client_secret,
+                        cred.profile_arn,
+                        cred.priority as i64,
+                        cred.disabled as i64,
+                        cred.failure_count as i64,
+                        cred.subscription_title,
+                        cred.current_usage,
+                        cred.usage_limit,
+                        cred.next_reset_at,
+                        cred.balance_updated_at,
+                        cred.machine_id,
+                        cred.email,
+                        cred.kiro_version,
+                        cred.system_version,
+                        cred.node_version,
+                        join_models(&cred.allowed_models),
+                        encode_headers(&cred.extra_headers),
+                        now,
+                        cred.access_key_id,
+                        cred.secret_access_key,
+                        cred.session_token,
+                        cred.region,
+                    ],
+                )?;
+            }
+
+            tx.commit()?;
+            Ok(removed)
+        })
+    }
+
+    fn delete_credential(&self, id: u64) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected =
+                conn.execute("DELETE FROM credentials WHERE id = ?1", params![id as i64])?;
+            Ok(affected > 0)
+        })
+    }
 
-        match result {
-            Ok(cred) => Ok(Some(cred)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(e.into()),
-        }
+    fn get_credential(&self, id: u64) -> Result<Option<KiroCredentials>> {
+        with_conn(&self.conn, |conn| {
+            let mut stmt = conn.prepare(&format!(
+                r#"
+                SELECT {CREDENTIAL_COLUMNS}
+                FROM credentials
+                WHERE id = ?1
+                "#
+            ))?;
+
+            let result = stmt.query_row(params![id as i64], row_to_credential);
+
+            match result {
+                Ok(cred) => Ok(Some(cred)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
     }
 
-    /// 获取凭据数量
-    pub fn count_credentials(&self) -> Result<usize> {
-        let conn = self.conn.lock();
-        let count: i64 =
-            conn.query_row("SELECT COUNT(*) FROM credentials", [], |row| row.get(0))?;
-        Ok(count as usize)
+    fn count_credentials(&self) -> Result<usize> {
+        with_conn(&self.conn, |conn| {
+            let count: i64 =
+                conn.query_row("SELECT COUNT(*) FROM credentials", [], |row| row.get(0))?;
+            Ok(count as usize)
+        })
     }
 
-    /// 更新凭据余额信息
-    pub fn update_balance(
+    fn update_balance(
         &self,
         id: u64,
         subscription_title: Option<&str>,
@@ -289,278 +2036,430 @@ impl Database {
         usage_limit: f64,
         next_reset_at: Option<f64>,
     ) -> Result<bool> {
-        let conn = self.conn.lock();
-        let now = chrono::Utc::now().to_rfc3339();
-        let affected = conn.execute(
-            r#"
-            UPDATE credentials
-            SET subscription_title = ?1, current_usage = ?2, usage_limit = ?3,
-                next_reset_at = ?4, balance_updated_at = ?5, updated_at = CURRENT_TIMESTAMP
-            WHERE id = ?6
-            "#,
-            params![
-                subscription_title,
-                current_usage,
-                usage_limit,
-                next_reset_at,
-                now,
-                id as i64,
-            ],
-        )?;
-        Ok(affected > 0)
+        with_conn(&self.conn, |conn| {
+            let now = chrono::Utc::now().to_rfc3339();
+            let affected = conn.execute(
+                r#"
+                UPDATE credentials
+                SET subscription_title = ?1, current_usage = ?2, usage_limit = ?3,
+                    next_reset_at = ?4, balance_updated_at = ?5, updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?6
+                "#,
+                params![
+                    subscription_title,
+                    current_usage,
+                    usage_limit,
+                    next_reset_at,
+                    now,
+                    id as i64,
+                ],
+            )?;
+            Ok(affected > 0)
+        })
     }
 
-    /// 设置凭据禁用状态
-    ///
-    /// 禁用时记录 disabled_at 时间戳，启用时清除
-    pub fn set_disabled(&self, id: u64, disabled: bool) -> Result<bool> {
-        let conn = self.conn.lock();
-        let disabled_at = if disabled {
-            Some(chrono::Utc::now().to_rfc3339())
-        } else {
-            None
-        };
-        let affected = conn.execute(
-            r#"
-            UPDATE credentials
-            SET disabled = ?1, disabled_at = ?2, updated_at = CURRENT_TIMESTAMP
-            WHERE id = ?3
-            "#,
-            params![disabled as i64, disabled_at, id as i64],
-        )?;
-        Ok(affected > 0)
+    fn set_disabled(&self, id: u64, disabled: bool) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let disabled_at = if disabled {
+                Some(chrono::Utc::now().to_rfc3339())
+            } else {
+                None
+            };
+            let affected = conn.execute(
+                r#"
+                UPDATE credentials
+                SET disabled = ?1, disabled_at = ?2,
+                    disabled_reason = CASE WHEN ?1 = 1 THEN disabled_reason ELSE NULL END,
+                    updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?3
+                "#,
+                params![disabled as i64, disabled_at, id as i64],
+            )?;
+            Ok(affected > 0)
+        })
     }
 
-    /// 设置凭据优先级
-    pub fn set_priority(&self, id: u64, priority: u32) -> Result<bool> {
-        let conn = self.conn.lock();
-        let affected = conn.execute(
-            r#"
-            UPDATE credentials
-            SET priority = ?1, updated_at = CURRENT_TIMESTAMP
-            WHERE id = ?2
-            "#,
-            params![priority as i64, id as i64],
-        )?;
-        Ok(affected > 0)
+    fn set_draining(&self, id: u64) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                r#"
+                UPDATE credentials
+                SET disabled = 1, disabled_at = ?1, disabled_reason = ?2,
+                    updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?3
+                "#,
+                params![
+                    chrono::Utc::now().to_rfc3339(),
+                    DISABLED_REASON_DRAIN,
+                    id as i64
+                ],
+            )?;
+            Ok(affected > 0)
+        })
     }
 
-    /// 增加失败计数
-    pub fn increment_failure_count(&self, id: u64) -> Result<u32> {
-        let conn = self.conn.lock();
-        conn.execute(
-            r#"
-            UPDATE credentials
-            SET failure_count = failure_count + 1, updated_at = CURRENT_TIMESTAMP
-            WHERE id = ?1
-            "#,
-            params![id as i64],
-        )?;
-        let count: i64 = conn.query_row(
-            "SELECT failure_count FROM credentials WHERE id = ?1",
-            params![id as i64],
-            |row| row.get(0),
-        )?;
-        Ok(count as u32)
+    fn bulk_update_credentials(&self, updates: &[CredentialUpdate]) -> Result<Option<u64>> {
+        with_conn(&self.conn, |conn| {
+            let tx = write_transaction(conn)?;
+            let now = chrono::Utc::now().to_rfc3339();
+
+            for update in updates {
+                let id = update.id as i64;
+                let exists: i64 = tx.query_row(
+                    "SELECT COUNT(*) FROM credentials WHERE id = ?1",
+                    params![id],
+                    |row| row.get(0),
+                )?;
+                if exists == 0 {
+                    return Ok(Some(update.id));
+                }
+                match update.disabled {
+                    Some(true) => {
+                        tx.execute(
+                            r#"
+                            UPDATE credentials
+                            SET disabled = 1, disabled_at = ?1, updated_at = CURRENT_TIMESTAMP
+                            WHERE id = ?2
+                            "#,
+                            params![now, id],
+                        )?;
+                    }
+                    Some(false) => {
+                        tx.execute(
+                            r#"
+                            UPDATE credentials
+                            SET failure_count = 0, disabled = 0, disabled_at = NULL,
+                                disabled_reason = NULL, updated_at = CURRENT_TIMESTAMP
+                            WHERE id = ?1
+                            "#,
+                            params![id],
+                        )?;
+                    }
+                    None => {}
+                }
+                if let Some(priority) = update.priority {
+                    tx.execute(
+                        r#"
+                        UPDATE credentials
+                        SET priority = ?1, updated_at = CURRENT_TIMESTAMP
+                        WHERE id = ?2
+                        "#,
+                        params![priority as i64, id],
+                    )?;
+                }
+            }
+            tx.commit()?;
+            Ok(None)
+        })
     }
 
-    /// 重置失败计数
-    pub fn reset_failure_count(&self, id: u64) -> Result<bool> {
-        let conn = self.conn.lock();
-        let affected = conn.execute(
-            r#"
-            UPDATE credentials
-            SET failure_count = 0, updated_at = CURRENT_TIMESTAMP
-            WHERE id = ?1
-            "#,
-            params![id as i64],
-        )?;
-        Ok(affected > 0)
+    fn set_priority(&self, id: u64, priority: u32) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                r#"
+                UPDATE credentials
+                SET priority = ?1, updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?2
+                "#,
+                params![priority as i64, id as i64],
+            )?;
+            Ok(affected > 0)
+        })
     }
 
-    /// 重置失败计数并启用凭据
-    pub fn reset_and_enable(&self, id: u64) -> Result<bool> {
-        let conn = self.conn.lock();
-        let affected = conn.execute(
-            r#"
-            UPDATE credentials
-            SET failure_count = 0, disabled = 0, disabled_at = NULL, updated_at = CURRENT_TIMESTAMP
-            WHERE id = ?1
-            "#,
-            params![id as i64],
-        )?;
-        Ok(affected > 0)
+    fn increment_failure_count(&self, id: u64) -> Result<u32> {
+        with_conn(&self.conn, |conn| {
+            conn.execute(
+                r#"
+                UPDATE credentials
+                SET failure_count = failure_count + 1, updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?1
+                "#,
+                params![id as i64],
+            )?;
+            let count: i64 = conn.query_row(
+                "SELECT failure_count FROM credentials WHERE id = ?1",
+                params![id as i64],
+                |row| row.get(0),
+            )?;
+            Ok(count as u32)
+        })
     }
 
-    /// 尝试恢复冷却期已过的禁用凭据
-    ///
-    /// 返回恢复的凭据数量
-    pub fn try_recover_disabled(&self, cooldown_seconds: i64) -> Result<usize> {
-        let conn = self.conn.lock();
-        let cutoff = chrono::Utc::now() - chrono::Duration::seconds(cooldown_seconds);
-        let cutoff_str = cutoff.to_rfc3339();
-
-        let affected = conn.execute(
-            r#"
-            UPDATE credentials
-            SET disabled = 0, disabled_at = NULL, failure_count = 0, updated_at = CURRENT_TIMESTAMP
-            WHERE disabled = 1 AND disabled_at IS NOT NULL AND disabled_at < ?1
-            "#,
-            params![cutoff_str],
-        )?;
+    fn reset_failure_count(&self, id: u64) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                r#"
+                UPDATE credentials
+                SET failure_count = 0, updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?1 AND failure_count > 0
+                "#,
+                params![id as i64],
+            )?;
+            Ok(affected > 0)
+        })
+    }
 
-        if affected > 0 {
-            tracing::info!("已自动恢复 {} 个冷却期已过的凭据", affected);
-        }
+    fn reset_and_enable(&self, id: u64) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                r#"
+                UPDATE credentials
+                SET failure_count = 0, disabled = 0, disabled_at = NULL, disabled_reason = NULL,
+                    updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?1
+                "#,
+                params![id as i64],
+            )?;
+            Ok(affected > 0)
+        })
+    }
 
-        Ok(affected)
+    fn list_cooled_down(&self, open_duration: Duration) -> Result<Vec<KiroCredentials>> {
+        with_conn(&self.conn, |conn| {
+            let cutoff = chrono::Utc::now()
+                - chrono::Duration::from_std(open_duration).unwrap_or(chrono::Duration::zero());
+            let mut stmt = conn.prepare(&format!(
+                r#"
+                SELECT {CREDENTIAL_COLUMNS}
+                FROM credentials
+                WHERE disabled = 1 AND disabled_at IS NOT NULL AND disabled_at < ?1
+                  AND disabled_reason IS NULL
+                ORDER BY priority ASC, id ASC
+                "#
+            ))?;
+            let rows = stmt.query_map(params![cutoff.to_rfc3339()], row_to_credential)?;
+            Ok(rows.collect::<rusqlite::Result<_>>()?)
+        })
     }
 
-    /// 获取优先级最高的可用凭据
-    pub fn get_highest_priority_available(&self) -> Result<Option<KiroCredentials>> {
-        let conn = self.conn.lock();
-        let mut stmt = conn.prepare(
-            r#"
-            SELECT id, refresh_token, access_token, expires_at, auth_method,
-                   client_id, client_secret, profile_arn, priority,
-                   disabled, failure_count,
-                   subscription_title, current_usage, usage_limit, next_reset_at, balance_updated_at,
-                   machine_id, email
-            FROM credentials
-            WHERE disabled = 0
-            ORDER BY priority ASC
-            LIMIT 1
-            "#,
-        )?;
+    fn disabled_since(&self) -> Result<HashMap<u64, chrono::DateTime<chrono::Utc>>> {
+        with_conn(&self.conn, |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, disabled_at FROM credentials \
+                 WHERE disabled = 1 AND disabled_at IS NOT NULL AND disabled_reason IS NULL",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get::<_, i64>(0)? as u64, row.get::<_, String>(1)?))
+            })?;
+
+            let mut result = HashMap::new();
+            for row in rows {
+                let (id, disabled_at) = row?;
+                if let Ok(t) = chrono::DateTime::parse_from_rfc3339(&disabled_at) {
+                    result.insert(id, t.with_timezone(&chrono::Utc));
+                }
+            }
+            Ok(result)
+        })
+    }
 
-        let result = stmt.query_row([], |row| {
-            Ok(KiroCredentials {
-                id: Some(row.get::<_, i64>(0)? as u64),
-                refresh_token: row.get(1)?,
-                access_token: row.get(2)?,
-                expires_at: row.get(3)?,
-                auth_method: row.get(4)?,
-                client_id: row.get(5)?,
-                client_secret: row.get(6)?,
-                profile_arn: row.get(7)?,
-                priority: row.get::<_, i64>(8)? as u32,
-                disabled: row.get::<_, i64>(9)? != 0,
-                failure_count: row.get::<_, i64>(10)? as u32,
-                subscription_title: row.get(11)?,
-                current_usage: row.get::<_, Option<f64>>(12)?.unwrap_or(0.0),
-                usage_limit: row.get::<_, Option<f64>>(13)?.unwrap_or(0.0),
-                next_reset_at: row.get(14)?,
-                balance_updated_at: row.get(15)?,
-                machine_id: row.get(16)?,
-                email: row.get(17)?,
-            })
-        });
+    fn get_highest_priority_available(&self) -> Result<Option<KiroCredentials>> {
+        with_conn(&self.conn, |conn| {
+            let mut stmt = conn.prepare(&format!(
+                r#"
+                SELECT {CREDENTIAL_COLUMNS}
+                FROM credentials
+                WHERE disabled = 0
+                ORDER BY priority ASC
+                LIMIT 1
+                "#
+            ))?;
+
+            let result = stmt.query_row([], row_to_credential);
+
+            match result {
+                Ok(cred) => Ok(Some(cred)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
 
-        match result {
-            Ok(cred) => Ok(Some(cred)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(e.into()),
-        }
+    fn get_next_available(&self, exclude_id: u64) -> Result<Option<KiroCredentials>> {
+        with_conn(&self.conn, |conn| {
+            let mut stmt = conn.prepare(&format!(
+                r#"
+                SELECT {CREDENTIAL_COLUMNS}
+                FROM credentials
+                WHERE disabled = 0 AND id != ?1
+                ORDER BY priority ASC
+                LIMIT 1
+                "#
+            ))?;
+
+            let result = stmt.query_row(params![exclude_id as i64], row_to_credential);
+
+            match result {
+                Ok(cred) => Ok(Some(cred)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
     }
 
-    /// 获取下一个优先级最高的可用凭据（排除指定 ID）
-    pub fn get_next_available(&self, exclude_id: u64) -> Result<Option<KiroCredentials>> {
-        let conn = self.conn.lock();
-        let mut stmt = conn.prepare(
-            r#"
-            SELECT id, refresh_token, access_token, expires_at, auth_method,
-                   client_id, client_secret, profile_arn, priority,
-                   disabled, failure_count,
-                   subscription_title, current_usage, usage_limit, next_reset_at, balance_updated_at,
-                   machine_id, email
-            FROM credentials
-            WHERE disabled = 0 AND id != ?1
-            ORDER BY priority ASC
-            LIMIT 1
-            "#,
-        )?;
+    fn count_available(&self) -> Result<usize> {
+        with_conn(&self.conn, |conn| {
+            let count: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM credentials WHERE disabled = 0",
+                [],
+                |row| row.get(0),
+            )?;
+            Ok(count as usize)
+        })
+    }
 
-        let result = stmt.query_row(params![exclude_id as i64], |row| {
-            Ok(KiroCredentials {
-                id: Some(row.get::<_, i64>(0)? as u64),
-                refresh_token: row.get(1)?,
-                access_token: row.get(2)?,
-                expires_at: row.get(3)?,
-                auth_method: row.get(4)?,
-                client_id: row.get(5)?,
-                client_secret: row.get(6)?,
-                profile_arn: row.get(7)?,
-                priority: row.get::<_, i64>(8)? as u32,
-                disabled: row.get::<_, i64>(9)? != 0,
-                failure_count: row.get::<_, i64>(10)? as u32,
-                subscription_title: row.get(11)?,
-                current_usage: row.get::<_, Option<f64>>(12)?.unwrap_or(0.0),
-                usage_limit: row.get::<_, Option<f64>>(13)?.unwrap_or(0.0),
-                next_reset_at: row.get(14)?,
-                balance_updated_at: row.get(15)?,
-                machine_id: row.get(16)?,
-                email: row.get(17)?,
-            })
-        });
+    fn set_machine_id(&self, id: u64, machine_id: Option<&str>) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                r#"
+                UPDATE credentials
+                SET machine_id = ?1, updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?2
+                "#,
+                params![machine_id, id as i64],
+            )?;
+            Ok(affected > 0)
+        })
+    }
 
-        match result {
-            Ok(cred) => Ok(Some(cred)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(e.into()),
-        }
+    fn update_email(&self, id: u64, email: Option<&str>) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                r#"
+                UPDATE credentials
+                SET email = ?1, updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?2
+                "#,
+                params![email, id as i64],
+            )?;
+            Ok(affected > 0)
+        })
     }
 
-    /// 获取可用凭据数量
-    pub fn count_available(&self) -> Result<usize> {
-        let conn = self.conn.lock();
-        let count: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM credentials WHERE disabled = 0",
-            [],
-            |row| row.get(0),
-        )?;
-        Ok(count as usize)
+    fn set_version_overrides(
+        &self,
+        id: u64,
+        kiro_version: Option<&str>,
+        system_version: Option<&str>,
+        node_version: Option<&str>,
+    ) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                r#"
+                UPDATE credentials
+                SET kiro_version = ?1, system_version = ?2, node_version = ?3,
+                    updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?4
+                "#,
+                params![kiro_version, system_version, node_version, id as i64],
+            )?;
+            Ok(affected > 0)
+        })
     }
 
-    /// 设置凭据的 machine_id
-    #[allow(dead_code)]
-    pub fn set_machine_id(&self, id: u64, machine_id: Option<&str>) -> Result<bool> {
-        let conn = self.conn.lock();
-        let affected = conn.execute(
-            r#"
-            UPDATE credentials
-            SET machine_id = ?1, updated_at = CURRENT_TIMESTAMP
-            WHERE id = ?2
-            "#,
-            params![machine_id, id as i64],
-        )?;
-        Ok(affected > 0)
+    fn set_allowed_models(&self, id: u64, allowed_models: &Option<Vec<String>>) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                r#"
+                UPDATE credentials
+                SET allowed_models = ?1, updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?2
+                "#,
+                params![join_models(allowed_models), id as i64],
+            )?;
+            Ok(affected > 0)
+        })
     }
 
-    /// 更新凭据的邮箱
-    pub fn update_email(&self, id: u64, email: Option<&str>) -> Result<bool> {
-        let conn = self.conn.lock();
-        let affected = conn.execute(
-            r#"
-            UPDATE credentials
-            SET email = ?1, updated_at = CURRENT_TIMESTAMP
-            WHERE id = ?2
-            "#,
-            params![email, id as i64],
-        )?;
-        Ok(affected > 0)
+    fn set_extra_headers(
+        &self,
+        id: u64,
+        extra_headers: &Option<BTreeMap<String, String>>,
+    ) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                r#"
+                UPDATE credentials
+                SET extra_headers = ?1, updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?2
+                "#,
+                params![encode_headers(extra_headers), id as i64],
+            )?;
+            Ok(affected > 0)
+        })
     }
 
-    /// 检查 client_id 是否已存在
-    ///
-    /// 用于添加凭据时去重，只检查非空的 client_id
-    pub fn client_id_exists(&self, client_id: &str) -> Result<bool> {
-        let conn = self.conn.lock();
-        let count: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM credentials WHERE client_id = ?1",
-            params![client_id],
-            |row| row.get(0),
-        )?;
-        Ok(count > 0)
+    fn client_id_exists(&self, client_id: &str) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let count: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM credentials WHERE client_id = ?1",
+                params![client_id],
+                |row| row.get(0),
+            )?;
+            Ok(count > 0)
+        })
+    }
+
+    fn access_key_id_exists(&self, access_key_id: &str) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let count: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM credentials WHERE access_key_id = ?1",
+                params![access_key_id],
+                |row| row.get(0),
+            )?;
+            Ok(count > 0)
+        })
+    }
+
+    fn try_acquire_lease(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let affected = conn.execute(
+                r#"
+                INSERT INTO leases (name, holder, acquired_at, expires_at)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT(name) DO UPDATE SET
+                    acquired_at = CASE WHEN holder = excluded.holder THEN acquired_at ELSE excluded.acquired_at END,
+                    holder = excluded.holder,
+                    expires_at = excluded.expires_at
+                WHERE holder = excluded.holder OR expires_at <= excluded.acquired_at
+                "#,
+                params![name, holder, now, now + ttl.as_millis() as i64],
+            )?;
+            Ok(affected > 0)
+        })
+    }
+
+    fn release_lease(&self, name: &str, holder: &str) -> Result<bool> {
+        with_conn(&self.conn, |conn| {
+            let affected = conn.execute(
+                "DELETE FROM leases WHERE name = ?1 AND holder = ?2",
+                params![name, holder],
+            )?;
+            Ok(affected > 0)
+        })
+    }
+
+    fn list_leases(&self) -> Result<Vec<Lease>> {
+        with_conn(&self.conn, |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT name, holder, acquired_at, expires_at FROM leases ORDER BY name",
+            )?;
+            let leases = stmt
+                .query_map([], |row| {
+                    Ok(Lease {
+                        name: row.get(0)?,
+                        holder: row.get(1)?,
+                        acquired_at: chrono::DateTime::from_timestamp_millis(row.get(2)?)
+                            .unwrap_or_default(),
+                        expires_at: chrono::DateTime::from_timestamp_millis(row.get(3)?)
+                            .unwrap_or_default(),
+                    })
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(leases)
+        })
     }
 }
 
@@ -575,6 +2474,55 @@ mod tests {
         let db_path = dir.path().join("test.db");
         let db = Database::open(&db_path).unwrap();
         assert_eq!(db.count_credentials().unwrap(), 0);
+        let version = db.schema_version().unwrap();
+        assert!(version >= 1);
+        drop(db);
+
+        // 重新打开时不重复执行迁移
+        let db = Database::open(&db_path).unwrap();
+        assert_eq!(db.schema_version().unwrap(), version);
+    }
+
+    #[tokio::test]
+    async fn test_call_runs_on_blocking_pool() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path().join("test.db")).unwrap();
+        let count = db.call(|db| db.count_credentials()).await.unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "boom")]
+    async fn test_blocking_propagates_panic() {
+        blocking(|| panic!("boom")).await
+    }
+
+    #[test]
+    fn test_busy_retries_lock_contention() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let db = Database::open(&db_path).unwrap();
+        let before = stats();
+
+        // 另一个连接（如共享数据库的其他实例）短暂持有写锁
+        let other = Connection::open(&db_path).unwrap();
+        other.execute_batch("BEGIN EXCLUSIVE").unwrap();
+        let holder = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            other.execute_batch("COMMIT").unwrap();
+        });
+
+        db.insert_credential(&KiroCredentials {
+            refresh_token: Some("contended".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        holder.join().unwrap();
+
+        let after = stats();
+        assert!(after.sqlite_busy_total > before.sqlite_busy_total);
+        assert!(after.sqlite_busy_retries_total > before.sqlite_busy_retries_total);
+        assert_eq!(db.count_credentials().unwrap(), 1);
     }
 
     #[test]
@@ -591,8 +2539,20 @@ mod tests {
             auth_method: Some("social".to_string()),
             client_id: None,
             client_secret: None,
+            access_key_id: None,
+            secret_access_key: None,
+            session_token: None,
+            region: Some("eu-west-1".to_string()),
             profile_arn: None,
             machine_id: None,
+            allowed_models: Some(vec![
+                "claude-sonnet-*".to_string(),
+                "claude-haiku-4.5".to_string(),
+            ]),
+            extra_headers: Some(BTreeMap::from([(
+                "x-egress-token".to_string(),
+                "corp-token".to_string(),
+            )])),
             priority: 0,
             disabled: false,
             failure_count: 0,
@@ -602,6 +2562,9 @@ mod tests {
             next_reset_at: None,
             balance_updated_at: None,
             email: None,
+            kiro_version: Some("0.9.0".to_string()),
+            system_version: None,
+            node_version: None,
         };
 
         let id = db.insert_credential(&cred).unwrap();
@@ -611,28 +2574,160 @@ mod tests {
         assert_eq!(loaded.len(), 1);
         assert_eq!(loaded[0].id, Some(id));
         assert_eq!(loaded[0].refresh_token, Some("test_refresh".to_string()));
+        assert_eq!(loaded[0].kiro_version, Some("0.9.0".to_string()));
+        assert_eq!(loaded[0].allowed_models, cred.allowed_models);
+        assert_eq!(loaded[0].extra_headers, cred.extra_headers);
+        assert_eq!(loaded[0].region.as_deref(), Some("eu-west-1"));
+
+        assert!(db.set_extra_headers(id, &None).unwrap());
+        assert_eq!(db.load_credentials().unwrap()[0].extra_headers, None);
+        assert!(!db.set_extra_headers(id + 1, &None).unwrap());
+
+        // IAM 凭据没有 refreshToken
+        let iam = db
+            .insert_credential(&KiroCredentials {
+                auth_method: Some("iam".to_string()),
+                access_key_id: Some("AKIDEXAMPLE".to_string()),
+                secret_access_key: Some("secret".to_string()),
+                session_token: Some("session".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        let loaded = db.get_credential(iam).unwrap().unwrap();
+        assert_eq!(loaded.refresh_token, None);
+        assert_eq!(loaded.access_key_id.as_deref(), Some("AKIDEXAMPLE"));
+        assert_eq!(loaded.secret_access_key.as_deref(), Some("secret"));
+        assert_eq!(loaded.session_token.as_deref(), Some("session"));
+        assert!(db.access_key_id_exists("AKIDEXAMPLE").unwrap());
+    }
+
+    #[test]
+    fn test_open_in_memory() {
+        let db = Database::open_in_memory().unwrap();
+        let id = db
+            .insert_credential(&KiroCredentials {
+                refresh_token: Some("memory".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(db.get_credential(id).unwrap().is_some());
+        assert!(
+            Database::open_in_memory()
+                .unwrap()
+                .get_credential(id)
+                .unwrap()
+                .is_none()
+        );
     }
 
     #[test]
-    fn test_update_credential() {
+    fn test_expires_at_normalized_and_validated() {
         let dir = tempdir().unwrap();
         let db_path = dir.path().join("test.db");
         let db = Database::open(&db_path).unwrap();
 
-        let mut cred = KiroCredentials {
-            id: None,
-            refresh_token: Some("original".to_string()),
+        let offset = db
+            .insert_credential(&KiroCredentials {
+                refresh_token: Some("offset".to_string()),
+                expires_at: Some("2025-01-01T16:00:00+08:00".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        let epoch = db
+            .insert_credential(&KiroCredentials {
+                refresh_token: Some("epoch".to_string()),
+                expires_at: Some("1735718400000".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        let invalid = db
+            .insert_credential(&KiroCredentials {
+                refresh_token: Some("invalid".to_string()),
+                expires_at: Some("tomorrow".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        for id in [offset, epoch] {
+            let loaded = db.get_credential(id).unwrap().unwrap();
+            assert_eq!(loaded.expires_at.as_deref(), Some("2025-01-01T08:00:00Z"));
+        }
+        assert!(
+            db.get_credential(invalid)
+                .unwrap()
+                .unwrap()
+                .expires_at
+                .is_none()
+        );
+
+        // 绕过写入规范化的原始 SQL 会被触发器拒绝
+        {
+            let conn = db.conn.lock();
+            let err = conn
+                .execute(
+                    "UPDATE credentials SET expires_at = '2025-01-01T16:00:00+08:00' WHERE id = ?1",
+                    params![offset as i64],
+                )
+                .unwrap_err();
+            assert!(err.to_string().contains("UTC RFC3339"));
+
+            // 模拟迁移框架与触发器出现前写入的历史数据
+            conn.execute_batch(
+                "DROP TABLE schema_version;
+                 DROP TRIGGER trg_credentials_expires_at_update;
+                 UPDATE credentials SET expires_at = '2025-01-01 08:00:00' WHERE refresh_token = 'offset';
+                 UPDATE credentials SET expires_at = 'garbage' WHERE refresh_token = 'epoch';",
+            )
+            .unwrap();
+        }
+        drop(db);
+
+        // 重新打开时迁移历史数据
+        let db = Database::open(&db_path).unwrap();
+        let loaded = db.get_credential(offset).unwrap().unwrap();
+        assert_eq!(loaded.expires_at.as_deref(), Some("2025-01-01T08:00:00Z"));
+        assert!(
+            db.get_credential(epoch)
+                .unwrap()
+                .unwrap()
+                .expires_at
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_save_refreshed_token_detects_rotation() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path().join("test.db")).unwrap();
+        let id = db
+            .insert_credential(&KiroCredentials {
+                refresh_token: Some("r1".to_string()),
+                priority: 4,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let refreshed = |refresh: &str, access: &str| KiroCredentials {
+            id: Some(id),
+            refresh_token: Some(refresh.to_string()),
+            access_token: Some(access.to_string()),
             ..Default::default()
         };
 
-        let id = db.insert_credential(&cred).unwrap();
-        cred.id = Some(id);
-        cred.refresh_token = Some("updated".to_string());
-
-        assert!(db.update_credential(&cred).unwrap());
+        // 实例 A 以 r1 刷新得到 r2
+        assert!(
+            db.save_refreshed_token(&refreshed("r2", "a2"), "r1")
+                .unwrap()
+        );
+        // 实例 B 同样以 r1 刷新，写入时发现已被轮换
+        assert!(
+            !db.save_refreshed_token(&refreshed("r3", "a3"), "r1")
+                .unwrap()
+        );
 
         let loaded = db.get_credential(id).unwrap().unwrap();
-        assert_eq!(loaded.refresh_token, Some("updated".to_string()));
+        assert_eq!(loaded.refresh_token.as_deref(), Some("r2"));
+        assert_eq!(loaded.access_token.as_deref(), Some("a2"));
+        assert_eq!(loaded.priority, 4);
     }
 
     #[test]
@@ -676,4 +2771,737 @@ mod tests {
         assert_eq!(loaded[1].refresh_token, Some("medium".to_string()));
         assert_eq!(loaded[2].refresh_token, Some("low".to_string()));
     }
+
+    #[test]
+    fn test_set_version_overrides() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let db = Database::open(&db_path).unwrap();
+
+        let cred = KiroCredentials {
+            refresh_token: Some("versioned".to_string()),
+            ..Default::default()
+        };
+        let id = db.insert_credential(&cred).unwrap();
+
+        assert!(
+            db.set_version_overrides(id, Some("0.9.1"), None, Some("20.18.0"))
+                .unwrap()
+        );
+        let loaded = db.get_credential(id).unwrap().unwrap();
+        assert_eq!(loaded.kiro_version, Some("0.9.1".to_string()));
+        assert_eq!(loaded.system_version, None);
+        assert_eq!(loaded.node_version, Some("20.18.0".to_string()));
+
+        // 不存在的凭据返回 false
+        assert!(!db.set_version_overrides(999, None, None, None).unwrap());
+    }
+
+    #[test]
+    fn test_replace_credentials() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path().join("test.db")).unwrap();
+
+        for token in ["a", "b"] {
+            db.insert_credential(&KiroCredentials {
+                refresh_token: Some(token.to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+
+        // 保留 #2 并更新，删除 #1，新增 #5
+        let replica = vec![
+            KiroCredentials {
+                id: Some(2),
+                refresh_token: Some("b2".to_string()),
+                disabled: true,
+                failure_count: 3,
+                ..Default::default()
+            },
+            KiroCredentials {
+                id: Some(5),
+                refresh_token: Some("e".to_string()),
+                priority: 1,
+                ..Default::default()
+            },
+        ];
+        assert_eq!(db.replace_credentials(&replica).unwrap(), 1);
+
+        let loaded = db.load_credentials().unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].id, Some(2));
+        assert_eq!(loaded[0].refresh_token, Some("b2".to_string()));
+        assert!(loaded[0].disabled);
+        assert_eq!(loaded[0].failure_count, 3);
+        assert_eq!(loaded[1].id, Some(5));
+
+        // 新插入的凭据不会复用已同步的 ID
+        let id = db
+            .insert_credential(&KiroCredentials {
+                refresh_token: Some("f".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(id, 6);
+    }
+
+    #[test]
+    fn test_prompt_templates() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path().join("test.db")).unwrap();
+
+        let mut template = PromptTemplate {
+            name: "agent".to_string(),
+            content: "v1".to_string(),
+            description: None,
+            updated_at: chrono::Utc::now(),
+        };
+        assert!(db.upsert_prompt_template(&template).unwrap());
+        template.content = "v2".to_string();
+        assert!(!db.upsert_prompt_template(&template).unwrap());
+
+        let loaded = db.get_prompt_template("agent").unwrap().unwrap();
+        assert_eq!(loaded.content, "v2");
+        assert_eq!(db.list_prompt_templates().unwrap().len(), 1);
+
+        assert!(db.delete_prompt_template("agent").unwrap());
+        assert!(!db.delete_prompt_template("agent").unwrap());
+        assert!(db.get_prompt_template("agent").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_api_keys() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path().join("test.db")).unwrap();
+
+        let key_hash = ApiKey::hash("sk-kiro-alice");
+        let id = db
+            .insert_api_key(&ApiKey {
+                id: 0,
+                name: "alice".to_string(),
+                fingerprint: ApiKey::fingerprint_of(&key_hash),
+                key_hash: key_hash.clone(),
+                allowed_models: Some(vec!["claude-sonnet-*".to_string()]),
+                rate_limit_per_minute: Some(60),
+                expires_at: Some(chrono::Utc::now() + chrono::Duration::days(1)),
+                revoked: false,
+                created_at: chrono::Utc::now(),
+            })
+            .unwrap();
+
+        let loaded = db.find_api_key(&key_hash).unwrap().unwrap();
+        assert_eq!(loaded.id, id);
+        assert_eq!(loaded.name, "alice");
+        assert_eq!(
+            loaded.fingerprint,
+            crate::common::auth::key_fingerprint("sk-kiro-alice")
+        );
+        assert_eq!(
+            loaded.allowed_models,
+            Some(vec!["claude-sonnet-*".to_string()])
+        );
+        assert_eq!(loaded.rate_limit_per_minute, Some(60));
+        assert!(loaded.expires_at.is_some());
+        assert!(
+            db.find_api_key(&ApiKey::hash("sk-kiro-bob"))
+                .unwrap()
+                .is_none()
+        );
+
+        assert!(db.revoke_api_key(id).unwrap());
+        assert!(db.find_api_key(&key_hash).unwrap().unwrap().revoked);
+        assert_eq!(db.list_api_keys().unwrap().len(), 1);
+
+        assert!(db.delete_api_key(id).unwrap());
+        assert!(!db.delete_api_key(id).unwrap());
+        assert!(!db.revoke_api_key(id).unwrap());
+    }
+
+    #[test]
+    fn test_admin_tokens() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path().join("test.db")).unwrap();
+
+        let token_hash = AdminToken::hash("kiro-admin-backup");
+        let id = db
+            .insert_admin_token(&AdminToken {
+                id: 0,
+                name: "backup".to_string(),
+                token_hash: token_hash.clone(),
+                scopes: vec![AdminScope::CredentialsRead, AdminScope::StatsRead],
+                expires_at: None,
+                revoked: false,
+                created_at: chrono::Utc::now(),
+            })
+            .unwrap();
+
+        let loaded = db.find_admin_token(&token_hash).unwrap().unwrap();
+        assert_eq!(loaded.id, id);
+        assert_eq!(
+            loaded.scopes,
+            vec![AdminScope::CredentialsRead, AdminScope::StatsRead]
+        );
+        assert!(loaded.expires_at.is_none());
+        assert!(
+            db.find_admin_token(&AdminToken::hash("kiro-admin-other"))
+                .unwrap()
+                .is_none()
+        );
+
+        assert!(db.revoke_admin_token(id).unwrap());
+        assert!(db.find_admin_token(&token_hash).unwrap().unwrap().revoked);
+        assert_eq!(db.list_admin_tokens().unwrap().len(), 1);
+
+        assert!(db.delete_admin_token(id).unwrap());
+        assert!(!db.delete_admin_token(id).unwrap());
+    }
+
+    #[test]
+    fn test_notification_queue() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path().join("test.db")).unwrap();
+        let now = chrono::Utc::now();
+
+        let id = db
+            .enqueue_notification("credential_disabled", "subject", "body")
+            .unwrap();
+        let due = db
+            .due_notifications(now + chrono::Duration::seconds(1), 10)
+            .unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].status, NotificationStatus::Pending);
+
+        // 失败后等待重试，未到时间不会再次取出
+        let retry_at = now + chrono::Duration::seconds(60);
+        db.mark_notification_failed(id, "connection refused", Some(retry_at))
+            .unwrap();
+        assert!(
+            db.due_notifications(now + chrono::Duration::seconds(1), 10)
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            db.next_notification_at()
+                .unwrap()
+                .map(|t| t.timestamp_millis()),
+            Some(retry_at.timestamp_millis())
+        );
+
+        // 重试耗尽进入死信
+        db.mark_notification_failed(id, "timeout", None).unwrap();
+        let dead = db
+            .list_notifications(Some(NotificationStatus::Dead), 10)
+            .unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].attempts, 2);
+        assert_eq!(dead[0].last_error.as_deref(), Some("timeout"));
+        assert!(db.next_notification_at().unwrap().is_none());
+
+        // 重放后重新投递
+        assert_eq!(db.replay_notification(id).unwrap(), Some(true));
+        let due = db.due_notifications(chrono::Utc::now(), 10).unwrap();
+        assert_eq!(due[0].attempts, 0);
+        db.mark_notification_delivered(id).unwrap();
+        let all = db.list_notifications(None, 10).unwrap();
+        assert_eq!(all[0].status, NotificationStatus::Delivered);
+        assert!(all[0].delivered_at.is_some());
+
+        // 已投递的通知不重放
+        assert_eq!(db.replay_notification(id).unwrap(), Some(false));
+        assert_eq!(db.replay_notification(id + 1).unwrap(), None);
+    }
+
+    #[test]
+    fn test_health_checks() {
+        let db = Database::open_in_memory().unwrap();
+        let now = chrono::Utc::now();
+        let check = |credential_id, minutes_ago, error: Option<&str>| HealthCheck {
+            id: 0,
+            credential_id,
+            checked_at: now - chrono::Duration::minutes(minutes_ago),
+            healthy: error.is_none(),
+            latency_ms: 120,
+            error: error.map(str::to_string),
+            trigger: HealthCheckTrigger::Scheduled,
+        };
+
+        db.insert_health_check(&check(1, 120, Some("401 Unauthorized")))
+            .unwrap();
+        let id = db.insert_health_check(&check(1, 5, None)).unwrap();
+        db.insert_health_check(&check(2, 5, None)).unwrap();
+
+        let checks = db.list_health_checks(1, 10).unwrap();
+        assert_eq!(checks.len(), 2);
+        assert_eq!(checks[0].id, id);
+        assert!(checks[0].healthy);
+        assert_eq!(checks[1].error.as_deref(), Some("401 Unauthorized"));
+        assert_eq!(checks[1].trigger, HealthCheckTrigger::Scheduled);
+        assert_eq!(db.list_health_checks(1, 1).unwrap().len(), 1);
+
+        let summary = db
+            .summarize_health_checks(now - chrono::Duration::days(1))
+            .unwrap();
+        assert_eq!(summary[&1], (2, 1));
+        assert_eq!(summary[&2], (1, 0));
+
+        assert_eq!(
+            db.prune_health_checks(now - chrono::Duration::hours(1))
+                .unwrap(),
+            1
+        );
+        assert_eq!(db.list_health_checks(1, 10).unwrap().len(), 1);
+        assert_eq!(db.list_health_checks(2, 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_leases() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        // 两个连接模拟共享数据库的两个实例
+        let a = Database::open(&path).unwrap();
+        let b = Database::open(&path).unwrap();
+        let ttl = Duration::from_secs(30);
+
+        assert!(a.try_acquire_lease("health_check", "a", ttl).unwrap());
+        assert!(!b.try_acquire_lease("health_check", "b", ttl).unwrap());
+        // 不同名称的租约互不影响
+        assert!(b.try_acquire_lease("notifications", "b", ttl).unwrap());
+
+        // 续期保留首次获取时间
+        let acquired_at = a.list_leases().unwrap()[0].acquired_at;
+        assert!(a.try_acquire_lease("health_check", "a", ttl).unwrap());
+        let leases = b.list_leases().unwrap();
+        assert_eq!(leases[0].name, "health_check");
+        assert_eq!(leases[0].holder, "a");
+        assert_eq!(leases[0].acquired_at, acquired_at);
+
+        // 过期后可被其他实例接管
+        assert!(
+            a.try_acquire_lease("health_check", "a", Duration::ZERO)
+                .unwrap()
+        );
+        assert!(b.try_acquire_lease("health_check", "b", ttl).unwrap());
+        assert!(!a.try_acquire_lease("health_check", "a", ttl).unwrap());
+
+        // 只有持有者可以释放
+        assert!(!a.release_lease("health_check", "a").unwrap());
+        assert!(b.release_lease("health_check", "b").unwrap());
+        assert!(a.try_acquire_lease("health_check", "a", ttl).unwrap());
+    }
+
+    fn request_log(model: &str, status: u16, latency_ms: u64, error: Option<&str>) -> RequestLog {
+        RequestLog {
+            id: None,
+            created_at: chrono::Utc::now(),
+            model: model.to_string(),
+            credential_id: Some(1),
+            status,
+            client_key: Some("abcd".to_string()),
+            latency_ms,
+            stream: false,
+            error: error.map(|e| e.to_string()),
+            tag: None,
+            seed: None,
+            request_id: None,
+            sse_events: None,
+        }
+    }
+
+    #[test]
+    fn test_compute_stats() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path().join("test.db")).unwrap();
+        for token in ["a", "b"] {
+            db.insert_credential(&KiroCredentials {
+                refresh_token: Some(token.to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+        db.set_disabled(2, true).unwrap();
+
+        db.insert_request_logs(&[request_log("claude-sonnet-4", 200, 100, None)])
+            .unwrap();
+        db.insert_request_logs(&[request_log("claude-sonnet-4", 502, 300, Some("boom"))])
+            .unwrap();
+        db.insert_request_logs(&[RequestLog {
+            created_at: chrono::Utc::now() - chrono::Duration::hours(2),
+            ..request_log("claude-opus-4", 200, 900, None)
+        }])
+        .unwrap();
+
+        let usage = |credential_id, model: &str, output, status| UsageLog {
+            created_at: chrono::Utc::now(),
+            credential_id,
+            model: model.to_string(),
+            tag: None,
+            input_tokens: 10,
+            output_tokens: output,
+            latency_ms: 100,
+            status,
+            stream: false,
+        };
+        db.insert_usage_log(&usage(Some(1), "claude-sonnet-4", 50, 200))
+            .unwrap();
+        db.insert_usage_log(&usage(Some(1), "claude-opus-4", 30, 200))
+            .unwrap();
+        db.insert_usage_log(&usage(Some(2), "claude-sonnet-4", 20, 200))
+            .unwrap();
+        db.insert_usage_log(&usage(None, "claude-sonnet-4", 0, 503))
+            .unwrap();
+        db.insert_usage_log(&UsageLog {
+            created_at: chrono::Utc::now() - chrono::Duration::days(2),
+            ..usage(Some(2), "claude-sonnet-4", 1000, 200)
+        })
+        .unwrap();
+
+        let changes = db.total_changes();
+        let stats = db
+            .compute_stats(
+                chrono::Utc::now() - chrono::Duration::hours(1),
+                chrono::Utc::now() - chrono::Duration::hours(1),
+                None,
+            )
+            .unwrap();
+        assert_eq!(db.total_changes(), changes);
+        assert_eq!(stats.credentials_total, 2);
+        assert_eq!(stats.credentials_available, 1);
+        assert_eq!(stats.requests_total, 3);
+        assert_eq!(stats.requests_last_hour, 2);
+        assert_eq!(stats.errors_last_hour, 1);
+        assert_eq!(stats.avg_latency_ms_last_hour, 200.0);
+        assert_eq!(stats.models_last_hour.len(), 1);
+        assert_eq!(stats.models_last_hour[0].model, "claude-sonnet-4");
+        assert_eq!(stats.models_last_hour[0].errors, 1);
+
+        // 今日统计不包含两天前的用量
+        let today = &stats.today;
+        assert_eq!(today.totals.requests, 4);
+        assert_eq!(today.totals.errors, 1);
+        assert_eq!(today.totals.output_tokens, 100);
+        assert_eq!(today.success_rate, 0.75);
+        assert_eq!(today.models[0].model, "claude-sonnet-4");
+        assert_eq!(today.models[0].totals.output_tokens, 70);
+        assert_eq!(today.credentials[0].credential_id, Some(1));
+        assert_eq!(today.credentials[0].share, 0.5);
+        let hourly_requests: u64 = today.hourly.iter().map(|h| h.requests).sum();
+        assert_eq!(hourly_requests, 4);
+    }
+
+    #[test]
+    fn test_summarize_usage() {
+        let db = Database::open_in_memory().unwrap();
+        let usage = |credential_id, model: &str, input, output, status| UsageLog {
+            created_at: chrono::Utc::now(),
+            credential_id,
+            model: model.to_string(),
+            tag: None,
+            input_tokens: input,
+            output_tokens: output,
+            latency_ms: 100,
+            status,
+            stream: true,
+        };
+        db.insert_usage_log(&usage(Some(1), "claude-sonnet-4", 100, 50, 200))
+            .unwrap();
+        db.insert_usage_log(&usage(Some(1), "claude-opus-4", 200, 300, 200))
+            .unwrap();
+        db.insert_usage_log(&usage(Some(2), "claude-sonnet-4", 10, 20, 200))
+            .unwrap();
+        db.insert_usage_log(&usage(None, "claude-sonnet-4", 0, 0, 400))
+            .unwrap();
+
+        let summary = db.summarize_usage(&UsageFilter::default()).unwrap();
+        assert_eq!(summary.total.requests, 4);
+        assert_eq!(summary.total.errors, 1);
+        assert_eq!(summary.total.input_tokens, 310);
+        assert_eq!(summary.total.output_tokens, 370);
+        // 按输出 tokens 倒序
+        assert_eq!(summary.credentials[0].credential_id, Some(1));
+        assert_eq!(summary.credentials[0].totals.output_tokens, 350);
+        assert_eq!(summary.credentials[1].credential_id, Some(2));
+        assert_eq!(summary.models[0].model, "claude-opus-4");
+
+        let filtered = db
+            .summarize_usage(&UsageFilter {
+                credential_id: Some(1),
+                model: Some("claude-sonnet-4".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(filtered.total.requests, 1);
+        assert_eq!(filtered.total.input_tokens, 100);
+
+        let future = db
+            .summarize_usage(&UsageFilter {
+                from: Some(chrono::Utc::now() + chrono::Duration::hours(1)),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(future.total, UsageTotals::default());
+        assert!(future.credentials.is_empty());
+    }
+
+    #[test]
+    fn test_prune_usage_log() {
+        let db = Database::open_in_memory().unwrap();
+        let now = chrono::Utc::now();
+        for days in [0, 10, 100] {
+            db.insert_usage_log(&UsageLog {
+                created_at: now - chrono::Duration::days(days),
+                credential_id: Some(1),
+                model: "claude-sonnet-4".to_string(),
+                tag: None,
+                input_tokens: 1,
+                output_tokens: 1,
+                latency_ms: 100,
+                status: 200,
+                stream: false,
+            })
+            .unwrap();
+        }
+
+        let pruned = db
+            .prune_usage_log(now - chrono::Duration::days(90))
+            .unwrap();
+        assert_eq!(pruned, 1);
+        let summary = db.summarize_usage(&UsageFilter::default()).unwrap();
+        assert_eq!(summary.total.requests, 2);
+    }
+
+    #[test]
+    fn test_usage_and_stats_by_tag() {
+        let db = Database::open_in_memory().unwrap();
+        let tagged = |tag: Option<&str>, output, status| {
+            db.insert_request_logs(&[RequestLog {
+                tag: tag.map(str::to_string),
+                ..request_log("claude-sonnet-4", status, 100, None)
+            }])
+            .unwrap();
+            db.insert_usage_log(&UsageLog {
+                created_at: chrono::Utc::now(),
+                credential_id: Some(1),
+                model: "claude-sonnet-4".to_string(),
+                tag: tag.map(str::to_string),
+                input_tokens: 10,
+                output_tokens: output,
+                latency_ms: 100,
+                status,
+                stream: false,
+            })
+            .unwrap();
+        };
+        tagged(Some("nightly"), 100, 200);
+        tagged(Some("nightly"), 0, 500);
+        tagged(Some("ci"), 30, 200);
+        tagged(None, 5, 200);
+
+        let summary = db.summarize_usage(&UsageFilter::default()).unwrap();
+        assert_eq!(summary.tags.len(), 3);
+        assert_eq!(summary.tags[0].tag.as_deref(), Some("nightly"));
+        assert_eq!(summary.tags[0].totals.requests, 2);
+        assert_eq!(summary.tags[0].totals.output_tokens, 100);
+        assert_eq!(summary.tags[2].tag, None);
+
+        let filtered = db
+            .summarize_usage(&UsageFilter {
+                tag: Some("ci".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(filtered.total.requests, 1);
+        assert_eq!(filtered.total.output_tokens, 30);
+
+        let since = chrono::Utc::now() - chrono::Duration::hours(1);
+        let stats = db.compute_stats(since, since, Some("nightly")).unwrap();
+        assert_eq!(stats.tag.as_deref(), Some("nightly"));
+        assert_eq!(stats.requests_total, 2);
+        assert_eq!(stats.requests_last_hour, 2);
+        assert_eq!(stats.errors_last_hour, 1);
+        assert_eq!(stats.today.totals.requests, 2);
+        assert_eq!(stats.today.totals.output_tokens, 100);
+        assert_eq!(
+            stats.today.hourly.iter().map(|h| h.requests).sum::<u64>(),
+            2
+        );
+
+        let all = db.compute_stats(since, since, None).unwrap();
+        assert_eq!(all.tag, None);
+        assert_eq!(all.requests_total, 4);
+        assert_eq!(all.today.totals.output_tokens, 135);
+    }
+
+    #[test]
+    fn test_prune_and_trim_request_logs() {
+        let db = Database::open_in_memory().unwrap();
+        let now = chrono::Utc::now();
+        for days in [40, 2, 1, 0] {
+            let mut log = request_log("claude-sonnet-4", 200, 100, None);
+            log.created_at = now - chrono::Duration::days(days);
+            db.insert_request_logs(&[log]).unwrap();
+        }
+
+        let pruned = db
+            .prune_request_logs(now - chrono::Duration::days(30))
+            .unwrap();
+        assert_eq!(pruned, 1);
+
+        // 超出条数上限时删除最早的日志
+        assert_eq!(db.trim_request_logs(2).unwrap(), 1);
+        let (total, logs) = db
+            .search_request_logs(&RequestLogFilter::default())
+            .unwrap();
+        assert_eq!(total, 2);
+        assert!(
+            logs.iter()
+                .all(|log| log.created_at > now - chrono::Duration::days(2))
+        );
+        assert_eq!(db.trim_request_logs(2).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_search_request_logs() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path().join("test.db")).unwrap();
+
+        db.insert_request_logs(&[request_log("claude-sonnet-4", 200, 120, None)])
+            .unwrap();
+        db.insert_request_logs(&[request_log(
+            "claude-sonnet-4",
+            502,
+            3000,
+            Some("upstream 100% failed"),
+        )])
+        .unwrap();
+        db.insert_request_logs(&[RequestLog {
+            tag: Some("nightly-eval".to_string()),
+            seed: Some(42),
+            request_id: Some("req-1".to_string()),
+            sse_events: Some(412),
+            ..request_log("claude-opus-4", 200, 800, None)
+        }])
+        .unwrap();
+
+        let all = RequestLogFilter {
+            limit: 10,
+            ..Default::default()
+        };
+        let (total, logs) = db.search_request_logs(&all).unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(logs.len(), 3);
+        // 按时间倒序，最新的在前
+        assert_eq!(logs[0].model, "claude-opus-4");
+
+        let by_model = RequestLogFilter {
+            model: Some("claude-sonnet-4".to_string()),
+            min_latency_ms: Some(500),
+            limit: 10,
+            ..Default::default()
+        };
+        let (total, logs) = db.search_request_logs(&by_model).unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(logs[0].status, 502);
+
+        // LIKE 通配符需按字面匹配
+        let by_text = RequestLogFilter {
+            query: Some("100%".to_string()),
+            limit: 10,
+            ..Default::default()
+        };
+        assert_eq!(db.search_request_logs(&by_text).unwrap().0, 1);
+        let wildcard = RequestLogFilter {
+            query: Some("%".to_string()),
+            status: Some(200),
+            limit: 10,
+            ..Default::default()
+        };
+        assert_eq!(db.search_request_logs(&wildcard).unwrap().0, 0);
+
+        let future = RequestLogFilter {
+            from: Some(chrono::Utc::now() + chrono::Duration::hours(1)),
+            limit: 10,
+            ..Default::default()
+        };
+        assert_eq!(db.search_request_logs(&future).unwrap().0, 0);
+
+        let by_tag = RequestLogFilter {
+            tag: Some("nightly-eval".to_string()),
+            limit: 10,
+            ..Default::default()
+        };
+        let (total, logs) = db.search_request_logs(&by_tag).unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(logs[0].tag.as_deref(), Some("nightly-eval"));
+
+        let by_seed = RequestLogFilter {
+            seed: Some(42),
+            limit: 10,
+            ..Default::default()
+        };
+        let (total, logs) = db.search_request_logs(&by_seed).unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(logs[0].seed, Some(42));
+
+        let by_request_id = RequestLogFilter {
+            request_id: Some("req-1".to_string()),
+            limit: 10,
+            ..Default::default()
+        };
+        let (total, logs) = db.search_request_logs(&by_request_id).unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(logs[0].request_id.as_deref(), Some("req-1"));
+        assert_eq!(logs[0].sse_events, Some(412));
+
+        let paged = RequestLogFilter {
+            limit: 1,
+            offset: 1,
+            ..Default::default()
+        };
+        let (total, logs) = db.search_request_logs(&paged).unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].status, 502);
+    }
+
+    #[test]
+    fn test_migrate_request_logs_tag_column() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        // 旧版本的 request_logs 表没有 tag、seed 列
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                r#"
+                CREATE TABLE request_logs (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    created_at INTEGER NOT NULL,
+                    model TEXT NOT NULL,
+                    credential_id INTEGER,
+                    status INTEGER NOT NULL,
+                    client_key TEXT,
+                    latency_ms INTEGER NOT NULL,
+                    stream INTEGER NOT NULL DEFAULT 0,
+                    error TEXT
+                );
+                "#,
+            )
+            .unwrap();
+
+        let db = Database::open(&path).unwrap();
+        db.insert_request_logs(&[RequestLog {
+            tag: Some("ci".to_string()),
+            seed: Some(7),
+            ..request_log("claude-sonnet-4", 200, 10, None)
+        }])
+        .unwrap();
+        let (_, logs) = db
+            .search_request_logs(&RequestLogFilter {
+                limit: 10,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(logs[0].tag.as_deref(), Some("ci"));
+        assert_eq!(logs[0].seed, Some(7));
+    }
 }
@@ -0,0 +1,748 @@
+//! 共享凭据库（只读）合并
+//!
+//! 配置 `sharedDatabasePaths` 后，除本地可写的凭据存储外，还从一个或多个只读的 SQLite 文件
+//! （如团队共享的凭据池）读取凭据，选择凭据时与本地凭据合并，实现共享池与个人池混用而无需部署
+//! PostgreSQL：
+//!
+//! - 第 N 个（从 1 开始）共享库中的凭据 ID 映射为 `N * 1_000_000_000 + 原 ID`，与本地凭据不冲突
+//! - 共享库文件只读打开，从不写入；每隔 [`SYNC_INTERVAL`] 检查 `PRAGMA data_version`，
+//!   其他连接提交修改后重新读取，新增、删除与修改在下次选择凭据时生效
+//! - 共享凭据不刷新 Token（刷新会轮换 refreshToken，使共享库及其他实例中的副本失效），
+//!   只使用共享库中的 accessToken，过期后视为不可用，由共享库的维护方负责刷新
+//! - 共享凭据的运行状态（禁用状态与失败计数、余额）保存在本实例的内存副本中，
+//!   重启后从共享库重新读取；共享库中的 refreshToken 变化时丢弃该凭据的运行状态
+//! - 共享凭据的优先级、模型限制等配置以共享库为准，不能通过 Admin API 修改或删除
+
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use rusqlite::{Connection, OpenFlags};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::store::CredentialStore;
+use super::{
+    CREDENTIAL_COLUMNS, CredentialUpdate, Lease, SqliteCredentialStore, migrations,
+    row_to_credential,
+};
+use crate::kiro::model::credentials::KiroCredentials;
+
+/// 共享凭据 ID 的区间大小（第 N 个共享库的凭据 ID 从 `N * SHARED_ID_BASE` 开始）
+const SHARED_ID_BASE: u64 = 1_000_000_000;
+
+/// 检查共享库是否变化的最小间隔
+const SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 上次同步的状态
+#[derive(Default)]
+struct SyncState {
+    /// 上次检查的时间
+    checked_at: Option<Instant>,
+    /// 上次读取时共享库的 `PRAGMA data_version`
+    data_version: Option<i64>,
+    /// 各凭据在共享库中的 refreshToken
+    refresh_tokens: HashMap<u64, Option<String>>,
+    /// 各凭据在共享库中的 accessToken
+    access_tokens: HashMap<u64, Option<String>>,
+}
+
+/// 只读共享凭据库
+pub struct SharedCredentials {
+    /// 文件路径
+    path: PathBuf,
+    /// 只读连接
+    source: Mutex<Connection>,
+    /// 内存副本（保存运行状态）
+    mirror: SqliteCredentialStore,
+    /// 凭据 ID 偏移
+    offset: u64,
+    /// 检查共享库是否变化的最小间隔
+    sync_interval: Duration,
+    /// 上次同步的状态
+    synced: Mutex<SyncState>,
+}
+
+impl SharedCredentials {
+    /// 只读打开第 `index`（从 0 开始）个共享凭据库
+    pub fn open(path: impl AsRef<Path>, index: usize) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let source = Connection::open_with_flags(
+            &path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .with_context(|| format!("打开共享凭据库失败: {:?}", path))?;
+        source.busy_timeout(Duration::from_secs(5))?;
+
+        let mut mirror = Connection::open_in_memory().context("打开内存数据库失败")?;
+        migrations::run(&mut mirror)?;
+
+        let shared = Self {
+            path,
+            source: Mutex::new(source),
+            mirror: SqliteCredentialStore {
+                conn: Arc::new(Mutex::new(mirror)),
+            },
+            offset: SHARED_ID_BASE * (index as u64 + 1),
+            sync_interval: SYNC_INTERVAL,
+            synced: Mutex::new(SyncState::default()),
+        };
+        shared.sync()?;
+        Ok(shared)
+    }
+
+    /// 共享库有其他连接提交的修改时重新读取，保留未变化凭据的运行状态
+    ///
+    /// 两次检查至少间隔 `sync_interval`，避免每次选择凭据都查询共享库
+    fn sync(&self) -> Result<()> {
+        let mut synced = self.synced.lock();
+        if synced
+            .checked_at
+            .is_some_and(|at| at.elapsed() < self.sync_interval)
+        {
+            return Ok(());
+        }
+        synced.checked_at = Some(Instant::now());
+
+        let source = self.source.lock();
+        let data_version: i64 = source
+            .query_row("PRAGMA data_version", [], |row| row.get(0))
+            .with_context(|| format!("读取共享凭据库失败: {:?}", self.path))?;
+        if synced.data_version == Some(data_version) {
+            return Ok(());
+        }
+
+        let creds = {
+            let mut stmt = source.prepare(&format!(
+                "SELECT {CREDENTIAL_COLUMNS} FROM credentials ORDER BY priority ASC"
+            ))?;
+            stmt.query_map([], row_to_credential)?
+                .collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        drop(source);
+        // 超出 ID 区间的凭据无法映射为全局 ID
+        let creds: Vec<KiroCredentials> = creds
+            .into_iter()
+            .filter(|c| c.id.is_some_and(|id| id < SHARED_ID_BASE))
+            .collect();
+        let mut mirror: HashMap<u64, KiroCredentials> = self
+            .mirror
+            .load_credentials()?
+            .into_iter()
+            .filter_map(|c| Some((c.id?, c)))
+            .collect();
+
+        let mut refresh_tokens = HashMap::new();
+        let mut access_tokens = HashMap::new();
+        let merged: Vec<KiroCredentials> = creds
+            .into_iter()
+            .map(|cred| {
+                let id = cred.id.unwrap_or(0);
+                refresh_tokens.insert(id, cred.refresh_token.clone());
+                access_tokens.insert(id, cred.access_token.clone());
+                match mirror.remove(&id) {
+                    // refreshToken 未变化：保留运行状态，配置以共享库为准
+                    Some(state) if synced.refresh_tokens.get(&id) == Some(&cred.refresh_token) => {
+                        // 共享库中的 accessToken 已更新时采用新 Token，否则保留本地的失效标记
+                        let (access_token, expires_at) =
+                            if synced.access_tokens.get(&id) == Some(&cred.access_token) {
+                                (state.access_token.clone(), state.expires_at.clone())
+                            } else {
+                                (cred.access_token, cred.expires_at)
+                            };
+                        KiroCredentials {
+                            access_token,
+                            expires_at,
+                            auth_method: cred.auth_method,
+                            client_id: cred.client_id,
+                            client_secret: cred.client_secret,
+                            access_key_id: cred.access_key_id,
+                            secret_access_key: cred.secret_access_key,
+                            session_token: cred.session_token,
+                            region: cred.region,
+                            machine_id: cred.machine_id.or(state.machine_id.clone()),
+                            kiro_version: cred.kiro_version,
+                            system_version: cred.system_version,
+                            node_version: cred.node_version,
+                            allowed_models: cred.allowed_models,
+                            extra_headers: cred.extra_headers,
+                            priority: cred.priority,
+                            ..state
+                        }
+                    }
+                    _ => cred,
+                }
+            })
+            .collect();
+        self.mirror.replace_credentials(&merged)?;
+        tracing::info!(
+            "已读取共享凭据库 {:?}（{} 个凭据）",
+            self.path,
+            merged.len()
+        );
+        *synced = SyncState {
+            checked_at: synced.checked_at,
+            data_version: Some(data_version),
+            refresh_tokens,
+            access_tokens,
+        };
+        Ok(())
+    }
+
+    /// 同步后返回内存副本（同步失败时继续使用上次读取的内容）
+    fn store(&self) -> &SqliteCredentialStore {
+        if let Err(e) = self.sync() {
+            tracing::warn!("同步共享凭据库失败，继续使用上次读取的凭据: {:#}", e);
+        }
+        &self.mirror
+    }
+
+    /// 将内存副本中的凭据映射为全局 ID
+    fn global(&self, mut cred: KiroCredentials) -> KiroCredentials {
+        cred.id = cred.id.map(|id| id + self.offset);
+        cred
+    }
+
+    fn read_only(&self, id: u64) -> anyhow::Error {
+        anyhow::anyhow!(
+            "凭据 #{} 来自只读的共享凭据库 {:?}，不能修改",
+            id + self.offset,
+            self.path
+        )
+    }
+}
+
+/// 合并本地凭据存储与只读共享凭据库的存储
+pub struct MergedCredentialStore {
+    /// 本地可写存储（新增、导入的凭据与租约）
+    primary: Box<dyn CredentialStore>,
+    /// 共享凭据库
+    shared: Vec<SharedCredentials>,
+}
+
+impl MergedCredentialStore {
+    pub fn new(primary: Box<dyn CredentialStore>, shared: Vec<SharedCredentials>) -> Self {
+        Self { primary, shared }
+    }
+
+    /// 按 ID 定位共享凭据库，返回共享库与库内 ID（本地凭据返回 None）
+    fn route(&self, id: u64) -> Option<(&SharedCredentials, u64)> {
+        let index = (id / SHARED_ID_BASE).checked_sub(1)? as usize;
+        self.shared.get(index).map(|s| (s, id % SHARED_ID_BASE))
+    }
+
+    /// 合并所有共享库的查询结果（映射为全局 ID）
+    fn collect_shared(
+        &self,
+        f: impl Fn(&SqliteCredentialStore) -> Result<Vec<KiroCredentials>>,
+    ) -> Result<Vec<KiroCredentials>> {
+        let mut result = Vec::new();
+        for shared in &self.shared {
+            result.extend(f(shared.store())?.into_iter().map(|c| shared.global(c)));
+        }
+        Ok(result)
+    }
+
+    /// 本地与共享库中优先级最高的凭据（优先级相同时本地优先）
+    fn first_by_priority(
+        &self,
+        primary: Option<KiroCredentials>,
+        f: impl Fn(&SqliteCredentialStore) -> Result<Option<KiroCredentials>>,
+    ) -> Result<Option<KiroCredentials>> {
+        let mut candidates: Vec<KiroCredentials> = primary.into_iter().collect();
+        for shared in &self.shared {
+            candidates.extend(f(shared.store())?.map(|c| shared.global(c)));
+        }
+        Ok(candidates.into_iter().min_by_key(|c| c.priority))
+    }
+}
+
+impl CredentialStore for MergedCredentialStore {
+    fn load_credentials(&self) -> Result<Vec<KiroCredentials>> {
+        let mut creds = self.primary.load_credentials()?;
+        creds.extend(self.collect_shared(|s| s.load_credentials())?);
+        creds.sort_by_key(|c| c.priority);
+        Ok(creds)
+    }
+
+    fn insert_credential(&self, cred: &KiroCredentials) -> Result<u64> {
+        self.primary.insert_credential(cred)
+    }
+
+    fn import_credentials_once(
+        &self,
+        creds: &[KiroCredentials],
+        marker: &str,
+    ) -> Result<Option<(usize, usize)>> {
+        self.primary.import_credentials_once(creds, marker)
+    }
+
+    fn import_credentials(&self, creds: &[KiroCredentials]) -> Result<(usize, usize)> {
+        self.primary.import_credentials(creds)
+    }
+
+    fn is_read_only(&self, id: u64) -> bool {
+        self.route(id).is_some()
+    }
+
+    fn save_refreshed_token(
+        &self,
+        cred: &KiroCredentials,
+        previous_refresh_token: &str,
+    ) -> Result<bool> {
+        match cred.id.and_then(|id| self.route(id)) {
+            Some((shared, id)) => shared.store().save_refreshed_token(
+                &KiroCredentials {
+                    id: Some(id),
+                    ..cred.clone()
+                },
+                previous_refresh_token,
+            ),
+            None => self
+                .primary
+                .save_refreshed_token(cred, previous_refresh_token),
+        }
+    }
+
+    fn invalidate_access_token(&self, id: u64, access_token: &str) -> Result<bool> {
+        match self.route(id) {
+            Some((shared, id)) => shared.store().invalidate_access_token(id, access_token),
+            None => self.primary.invalidate_access_token(id, access_token),
+        }
+    }
+
+    /// 热备同步：快照中的共享凭据由热备实例从自己的共享库读取，不写入本地
+    fn replace_credentials(&self, creds: &[KiroCredentials]) -> Result<usize> {
+        let local: Vec<KiroCredentials> = creds
+            .iter()
+            .filter(|c| c.id.is_none_or(|id| id < SHARED_ID_BASE))
+            .cloned()
+            .collect();
+        self.primary.replace_credentials(&local)
+    }
+
+    fn delete_credential(&self, id: u64) -> Result<bool> {
+        match self.route(id) {
+            Some((shared, id)) => Err(shared.read_only(id)),
+            None => self.primary.delete_credential(id),
+        }
+    }
+
+    fn get_credential(&self, id: u64) -> Result<Option<KiroCredentials>> {
+        match self.route(id) {
+            Some((shared, id)) => Ok(shared.store().get_credential(id)?.map(|c| shared.global(c))),
+            None => self.primary.get_credential(id),
+        }
+    }
+
+    fn count_credentials(&self) -> Result<usize> {
+        let mut count = self.primary.count_credentials()?;
+        for shared in &self.shared {
+            count += shared.store().count_credentials()?;
+        }
+        Ok(count)
+    }
+
+    fn update_balance(
+        &self,
+        id: u64,
+        subscription_title: Option<&str>,
+        current_usage: f64,
+        usage_limit: f64,
+        next_reset_at: Option<f64>,
+    ) -> Result<bool> {
+        match self.route(id) {
+            Some((shared, id)) => shared.store().update_balance(
+                id,
+                subscription_title,
+                current_usage,
+                usage_limit,
+                next_reset_at,
+            ),
+            None => self.primary.update_balance(
+                id,
+                subscription_title,
+                current_usage,
+                usage_limit,
+                next_reset_at,
+            ),
+        }
+    }
+
+    fn set_disabled(&self, id: u64, disabled: bool) -> Result<bool> {
+        match self.route(id) {
+            Some((shared, id)) => shared.store().set_disabled(id, disabled),
+            None => self.primary.set_disabled(id, disabled),
+        }
+    }
+
+    fn set_draining(&self, id: u64) -> Result<bool> {
+        match self.route(id) {
+            Some((shared, id)) => shared.store().set_draining(id),
+            None => self.primary.set_draining(id),
+        }
+    }
+
+    /// 本地凭据在单个事务中更新；共享凭据只能修改禁用状态，在本地事务成功后写入内存副本
+    fn bulk_update_credentials(&self, updates: &[CredentialUpdate]) -> Result<Option<u64>> {
+        let (shared_updates, local): (Vec<_>, Vec<_>) = updates
+            .iter()
+            .cloned()
+            .partition(|u| self.route(u.id).is_some());
+        for update in &shared_updates {
+            let (shared, id) = self.route(update.id).unwrap();
+            if shared.store().get_credential(id)?.is_none() {
+                return Ok(Some(update.id));
+            }
+            if update.priority.is_some() {
+                return Err(shared.read_only(id));
+            }
+        }
+        if !local.is_empty()
+            && let Some(missing) = self.primary.bulk_update_credentials(&local)?
+        {
+            return Ok(Some(missing));
+        }
+        for update in shared_updates {
+            if let Some(disabled) = update.disabled {
+                self.set_disabled(update.id, disabled)?;
+            }
+        }
+        Ok(None)
+    }
+
+    fn set_priority(&self, id: u64, priority: u32) -> Result<bool> {
+        match self.route(id) {
+            Some((shared, id)) => Err(shared.read_only(id)),
+            None => self.primary.set_priority(id, priority),
+        }
+    }
+
+    fn increment_failure_count(&self, id: u64) -> Result<u32> {
+        match self.route(id) {
+            Some((shared, id)) => shared.store().increment_failure_count(id),
+            None => self.primary.increment_failure_count(id),
+        }
+    }
+
+    fn reset_failure_count(&self, id: u64) -> Result<bool> {
+        match self.route(id) {
+            Some((shared, id)) => shared.store().reset_failure_count(id),
+            None => self.primary.reset_failure_count(id),
+        }
+    }
+
+    fn reset_and_enable(&self, id: u64) -> Result<bool> {
+        match self.route(id) {
+            Some((shared, id)) => shared.store().reset_and_enable(id),
+            None => self.primary.reset_and_enable(id),
+        }
+    }
+
+    fn list_cooled_down(&self, open_duration: Duration) -> Result<Vec<KiroCredentials>> {
+        let mut creds = self.primary.list_cooled_down(open_duration)?;
+        creds.extend(self.collect_shared(|s| s.list_cooled_down(open_duration))?);
+        creds.sort_by_key(|c| c.priority);
+        Ok(creds)
+    }
+
+    fn disabled_since(&self) -> Result<HashMap<u64, chrono::DateTime<chrono::Utc>>> {
+        let mut result = self.primary.disabled_since()?;
+        for shared in &self.shared {
+            result.extend(
+                shared
+                    .store()
+                    .disabled_since()?
+                    .into_iter()
+                    .map(|(id, t)| (id + shared.offset, t)),
+            );
+        }
+        Ok(result)
+    }
+
+    fn get_highest_priority_available(&self) -> Result<Option<KiroCredentials>> {
+        self.first_by_priority(self.primary.get_highest_priority_available()?, |s| {
+            s.get_highest_priority_available()
+        })
+    }
+
+    fn get_next_available(&self, exclude_id: u64) -> Result<Option<KiroCredentials>> {
+        let primary = match self.route(exclude_id) {
+            Some(_) => self.primary.get_highest_priority_available()?,
+            None => self.primary.get_next_available(exclude_id)?,
+        };
+        let mut candidates: Vec<KiroCredentials> = primary.into_iter().collect();
+        for shared in &self.shared {
+            let store = shared.store();
+            let next = match exclude_id.checked_sub(shared.offset) {
+                Some(id) if id < SHARED_ID_BASE => store.get_next_available(id)?,
+                _ => store.get_highest_priority_available()?,
+            };
+            candidates.extend(next.map(|c| shared.global(c)));
+        }
+        Ok(candidates.into_iter().min_by_key(|c| c.priority))
+    }
+
+    fn count_available(&self) -> Result<usize> {
+        let mut count = self.primary.count_available()?;
+        for shared in &self.shared {
+            count += shared.store().count_available()?;
+        }
+        Ok(count)
+    }
+
+    fn set_machine_id(&self, id: u64, machine_id: Option<&str>) -> Result<bool> {
+        match self.route(id) {
+            Some((shared, id)) => shared.store().set_machine_id(id, machine_id),
+            None => self.primary.set_machine_id(id, machine_id),
+        }
+    }
+
+    fn update_email(&self, id: u64, email: Option<&str>) -> Result<bool> {
+        match self.route(id) {
+            Some((shared, id)) => shared.store().update_email(id, email),
+            None => self.primary.update_email(id, email),
+        }
+    }
+
+    fn set_version_overrides(
+        &self,
+        id: u64,
+        kiro_version: Option<&str>,
+        system_version: Option<&str>,
+        node_version: Option<&str>,
+    ) -> Result<bool> {
+        match self.route(id) {
+            Some((shared, id)) => Err(shared.read_only(id)),
+            None => {
+                self.primary
+                    .set_version_overrides(id, kiro_version, system_version, node_version)
+            }
+        }
+    }
+
+    fn set_allowed_models(&self, id: u64, allowed_models: &Option<Vec<String>>) -> Result<bool> {
+        match self.route(id) {
+            Some((shared, id)) => Err(shared.read_only(id)),
+            None => self.primary.set_allowed_models(id, allowed_models),
+        }
+    }
+
+    fn set_extra_headers(
+        &self,
+        id: u64,
+        extra_headers: &Option<BTreeMap<String, String>>,
+    ) -> Result<bool> {
+        match self.route(id) {
+            Some((shared, id)) => Err(shared.read_only(id)),
+            None => self.primary.set_extra_headers(id, extra_headers),
+        }
+    }
+
+    fn client_id_exists(&self, client_id: &str) -> Result<bool> {
+        if self.primary.client_id_exists(client_id)? {
+            return Ok(true);
+        }
+        for shared in &self.shared {
+            if shared.store().client_id_exists(client_id)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn access_key_id_exists(&self, access_key_id: &str) -> Result<bool> {
+        if self.primary.access_key_id_exists(access_key_id)? {
+            return Ok(true);
+        }
+        for shared in &self.shared {
+            if shared.store().access_key_id_exists(access_key_id)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn try_acquire_lease(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool> {
+        self.primary.try_acquire_lease(name, holder, ttl)
+    }
+
+    fn release_lease(&self, name: &str, holder: &str) -> Result<bool> {
+        self.primary.release_lease(name, holder)
+    }
+
+    fn list_leases(&self) -> Result<Vec<Lease>> {
+        self.primary.list_leases()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::db::Database;
+    use tempfile::tempdir;
+
+    fn cred(refresh_token: &str, priority: u32) -> KiroCredentials {
+        KiroCredentials {
+            refresh_token: Some(refresh_token.to_string()),
+            priority,
+            ..Default::default()
+        }
+    }
+
+    /// 打开共享库（每次访问都检查变化）
+    fn open_shared(path: &Path) -> SharedCredentials {
+        let mut shared = SharedCredentials::open(path, 0).unwrap();
+        shared.sync_interval = Duration::ZERO;
+        shared
+    }
+
+    #[test]
+    fn test_merge_shared_credentials() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("shared.db");
+        let source = Database::open(&path).unwrap();
+        let shared_id = source.insert_credential(&cred("shared-rt", 0)).unwrap();
+        source.insert_credential(&cred("shared-low", 5)).unwrap();
+
+        let db = Database::open_with_shared_credentials(
+            None,
+            None,
+            vec![SharedCredentials::open(&path, 0).unwrap()],
+        )
+        .unwrap();
+        let local_id = db.insert_credential(&cred("local-rt", 1)).unwrap();
+        let global_id = SHARED_ID_BASE + shared_id;
+
+        let creds = db.load_credentials().unwrap();
+        assert_eq!(creds.len(), 3);
+        assert_eq!(creds[0].id, Some(global_id));
+        assert_eq!(creds[1].id, Some(local_id));
+        assert_eq!(db.count_available().unwrap(), 3);
+        assert_eq!(
+            db.get_highest_priority_available().unwrap().unwrap().id,
+            Some(global_id)
+        );
+        assert_eq!(
+            db.get_next_available(global_id).unwrap().unwrap().id,
+            Some(local_id)
+        );
+
+        // 运行状态写入内存副本，不写入共享库
+        assert!(db.set_disabled(global_id, true).unwrap());
+        assert!(db.get_credential(global_id).unwrap().unwrap().disabled);
+        assert!(!source.get_credential(shared_id).unwrap().unwrap().disabled);
+        assert_eq!(
+            db.get_highest_priority_available().unwrap().unwrap().id,
+            Some(local_id)
+        );
+
+        // 配置不能修改
+        let err = db.set_priority(global_id, 9).unwrap_err();
+        assert!(err.to_string().contains("只读"));
+        assert!(db.delete_credential(global_id).is_err());
+    }
+
+    #[test]
+    fn test_sync_keeps_runtime_state() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("shared.db");
+        let source = Database::open(&path).unwrap();
+        let a = source.insert_credential(&cred("rt-a", 0)).unwrap();
+        let b = source.insert_credential(&cred("rt-b", 1)).unwrap();
+
+        let shared = open_shared(&path);
+        let store = MergedCredentialStore::new(
+            Box::new(SqliteCredentialStore {
+                conn: Arc::new(Mutex::new({
+                    let mut conn = Connection::open_in_memory().unwrap();
+                    migrations::run(&mut conn).unwrap();
+                    conn
+                })),
+            }),
+            vec![shared],
+        );
+        assert_eq!(
+            store.increment_failure_count(SHARED_ID_BASE + a).unwrap(),
+            1
+        );
+        assert_eq!(
+            store.increment_failure_count(SHARED_ID_BASE + b).unwrap(),
+            1
+        );
+
+        // a 仅修改优先级（保留运行状态），b 更换 refreshToken（丢弃运行状态），新增 c
+        source.set_priority(a, 7).unwrap();
+        source
+            .save_refreshed_token(
+                &KiroCredentials {
+                    id: Some(b),
+                    ..cred("rt-b2", 1)
+                },
+                "rt-b",
+            )
+            .unwrap();
+        let c = source.insert_credential(&cred("rt-c", 2)).unwrap();
+
+        let a = store.get_credential(SHARED_ID_BASE + a).unwrap().unwrap();
+        assert_eq!(a.priority, 7);
+        assert_eq!(a.failure_count, 1);
+        let b = store.get_credential(SHARED_ID_BASE + b).unwrap().unwrap();
+        assert_eq!(b.refresh_token.as_deref(), Some("rt-b2"));
+        assert_eq!(b.failure_count, 0);
+        assert!(store.get_credential(SHARED_ID_BASE + c).unwrap().is_some());
+        assert_eq!(store.count_credentials().unwrap(), 3);
+
+        assert!(store.is_read_only(SHARED_ID_BASE + c));
+        assert!(!store.is_read_only(1));
+
+        // 热备快照中的共享凭据不写入本地
+        assert_eq!(store.replace_credentials(&[a]).unwrap(), 0);
+        assert_eq!(store.primary.count_credentials().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_sync_adopts_updated_access_token() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("shared.db");
+        let source = Database::open(&path).unwrap();
+        let id = source
+            .insert_credential(&KiroCredentials {
+                access_token: Some("at-1".to_string()),
+                ..cred("rt", 0)
+            })
+            .unwrap();
+
+        let mut shared = open_shared(&path);
+        // 本地标记 Token 失效后，共享库未变化时保持失效
+        assert!(shared.store().invalidate_access_token(id, "at-1").unwrap());
+        source.set_priority(id, 1).unwrap();
+        let stale = shared.store().get_credential(id).unwrap().unwrap();
+        assert_eq!(stale.priority, 1);
+        assert!(stale.expires_at.is_none());
+
+        // 维护方在共享库中写入新 Token 后采用新 Token
+        source
+            .save_refreshed_token(
+                &KiroCredentials {
+                    id: Some(id),
+                    access_token: Some("at-2".to_string()),
+                    expires_at: Some("2099-01-01T00:00:00Z".to_string()),
+                    ..cred("rt", 1)
+                },
+                "rt",
+            )
+            .unwrap();
+        let fresh = shared.store().get_credential(id).unwrap().unwrap();
+        assert_eq!(fresh.access_token.as_deref(), Some("at-2"));
+        assert!(fresh.expires_at.is_some());
+
+        // 检查间隔内不重新读取共享库
+        shared.sync_interval = Duration::from_secs(3600);
+        source.set_priority(id, 9).unwrap();
+        assert_eq!(
+            shared.store().get_credential(id).unwrap().unwrap().priority,
+            1
+        );
+    }
+}
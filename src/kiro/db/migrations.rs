@@ -0,0 +1,438 @@
+//! 数据库 schema 迁移
+//!
+//! 迁移按版本号顺序执行，已应用的版本记录在 `schema_version` 表中；
+//! 每个迁移在独立的写事务中执行，失败时回滚并中止打开数据库。
+//! 新的 schema 变更应追加到 [`MIGRATIONS`] 末尾，已发布的迁移不可修改
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, params};
+
+use super::write_transaction;
+use crate::kiro::model::credentials::normalize_expires_at;
+
+/// 单个迁移
+struct Migration {
+    /// 版本号（从 1 开始连续递增）
+    version: u32,
+    /// 说明（记录在 schema_version 表中）
+    description: &'static str,
+    apply: fn(&Connection) -> Result<()>,
+}
+
+/// 全部迁移（按版本号升序）
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "基线 schema",
+        apply: baseline,
+    },
+    Migration {
+        version: 2,
+        description: "请求日志记录 SSE 事件数",
+        apply: request_log_sse_events,
+    },
+    Migration {
+        version: 3,
+        description: "凭据禁用原因",
+        apply: credential_disabled_reason,
+    },
+    Migration {
+        version: 4,
+        description: "用量记录请求标签",
+        apply: usage_log_tag,
+    },
+    Migration {
+        version: 5,
+        description: "凭据 OIDC 区域",
+        apply: credential_region,
+    },
+];
+
+/// 基线 schema
+///
+/// 迁移框架引入前的数据库没有 `schema_version` 表，同样从此版本开始执行，
+/// 因此本迁移需保持幂等：建表使用 IF NOT EXISTS，并为旧表补齐历史新增列
+const BASELINE_SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS credentials (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    refresh_token TEXT NOT NULL,
+    access_token TEXT,
+    expires_at TEXT,
+    auth_method TEXT DEFAULT 'social',
+    client_id TEXT,
+    client_secret TEXT,
+    profile_arn TEXT,
+    priority INTEGER DEFAULT 0,
+    disabled INTEGER DEFAULT 0,
+    failure_count INTEGER DEFAULT 0,
+    disabled_at TEXT,
+    subscription_title TEXT,
+    current_usage REAL DEFAULT 0,
+    usage_limit REAL DEFAULT 0,
+    next_reset_at REAL,
+    balance_updated_at TEXT,
+    machine_id TEXT,
+    email TEXT,
+    kiro_version TEXT,
+    system_version TEXT,
+    node_version TEXT,
+    allowed_models TEXT,
+    extra_headers TEXT,
+    access_key_id TEXT,
+    secret_access_key TEXT,
+    session_token TEXT,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_credentials_priority ON credentials(priority);
+CREATE INDEX IF NOT EXISTS idx_credentials_disabled ON credentials(disabled);
+
+CREATE TABLE IF NOT EXISTS request_logs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at INTEGER NOT NULL,
+    model TEXT NOT NULL,
+    credential_id INTEGER,
+    status INTEGER NOT NULL,
+    client_key TEXT,
+    latency_ms INTEGER NOT NULL,
+    stream INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    tag TEXT,
+    seed INTEGER,
+    request_id TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_request_logs_created_at ON request_logs(created_at);
+CREATE INDEX IF NOT EXISTS idx_request_logs_model ON request_logs(model, created_at);
+CREATE INDEX IF NOT EXISTS idx_request_logs_credential ON request_logs(credential_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_request_logs_status ON request_logs(status, created_at);
+CREATE INDEX IF NOT EXISTS idx_request_logs_client_key ON request_logs(client_key, created_at);
+CREATE INDEX IF NOT EXISTS idx_request_logs_latency ON request_logs(latency_ms);
+
+CREATE TABLE IF NOT EXISTS transcripts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at INTEGER NOT NULL,
+    request_id TEXT,
+    model TEXT NOT NULL,
+    credential_id INTEGER,
+    client_key TEXT,
+    stream INTEGER NOT NULL DEFAULT 0,
+    status INTEGER NOT NULL,
+    latency_ms INTEGER NOT NULL,
+    request TEXT NOT NULL,
+    response TEXT NOT NULL,
+    stop_reason TEXT,
+    input_tokens INTEGER,
+    output_tokens INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_transcripts_created_at ON transcripts(created_at);
+CREATE INDEX IF NOT EXISTS idx_transcripts_request_id ON transcripts(request_id);
+
+CREATE TABLE IF NOT EXISTS usage_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at INTEGER NOT NULL,
+    credential_id INTEGER,
+    model TEXT NOT NULL,
+    input_tokens INTEGER NOT NULL DEFAULT 0,
+    output_tokens INTEGER NOT NULL DEFAULT 0,
+    latency_ms INTEGER NOT NULL,
+    status INTEGER NOT NULL,
+    stream INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS idx_usage_log_created_at ON usage_log(created_at);
+CREATE INDEX IF NOT EXISTS idx_usage_log_credential ON usage_log(credential_id, created_at);
+
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS prompt_templates (
+    name TEXT PRIMARY KEY,
+    content TEXT NOT NULL,
+    description TEXT,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS api_keys (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    allowed_models TEXT,
+    rate_limit_per_minute INTEGER,
+    expires_at INTEGER,
+    revoked INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS admin_tokens (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT NOT NULL,
+    expires_at INTEGER,
+    revoked INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER NOT NULL,
+    last_error TEXT,
+    created_at INTEGER NOT NULL,
+    delivered_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_notifications_status ON notifications(status, next_attempt_at);
+
+CREATE TABLE IF NOT EXISTS credential_health_checks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    credential_id INTEGER NOT NULL,
+    checked_at INTEGER NOT NULL,
+    healthy INTEGER NOT NULL,
+    latency_ms INTEGER NOT NULL,
+    error TEXT,
+    trigger TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_health_checks_credential ON credential_health_checks(credential_id, checked_at);
+CREATE INDEX IF NOT EXISTS idx_health_checks_checked_at ON credential_health_checks(checked_at);
+
+CREATE TABLE IF NOT EXISTS leases (
+    name TEXT PRIMARY KEY,
+    holder TEXT NOT NULL,
+    acquired_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+"#;
+
+/// `credentials.expires_at` 格式校验触发器
+const EXPIRES_AT_TRIGGERS: &str = r#"
+CREATE TRIGGER IF NOT EXISTS trg_credentials_expires_at_insert
+BEFORE INSERT ON credentials
+WHEN NEW.expires_at IS NOT NULL
+     AND (julianday(NEW.expires_at) IS NULL OR substr(NEW.expires_at, -1) != 'Z')
+BEGIN
+    SELECT RAISE(ABORT, 'credentials.expires_at 必须为 UTC RFC3339 格式');
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_credentials_expires_at_update
+BEFORE UPDATE OF expires_at ON credentials
+WHEN NEW.expires_at IS NOT NULL
+     AND (julianday(NEW.expires_at) IS NULL OR substr(NEW.expires_at, -1) != 'Z')
+BEGIN
+    SELECT RAISE(ABORT, 'credentials.expires_at 必须为 UTC RFC3339 格式');
+END;
+"#;
+
+/// 执行所有未应用的迁移
+pub(super) fn run(conn: &mut Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at INTEGER NOT NULL
+        );
+        "#,
+    )?;
+
+    let current = current_version(conn)?;
+    let latest = MIGRATIONS.last().map_or(0, |m| m.version);
+    if current > latest {
+        anyhow::bail!(
+            "数据库 schema 版本 {} 高于当前程序支持的版本 {}，请升级程序",
+            current,
+            latest
+        );
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let tx = write_transaction(conn)?;
+        (migration.apply)(&tx).with_context(|| {
+            format!(
+                "数据库迁移 v{}（{}）失败",
+                migration.version, migration.description
+            )
+        })?;
+        tx.execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, ?3)",
+            params![
+                migration.version,
+                migration.description,
+                chrono::Utc::now().timestamp()
+            ],
+        )?;
+        tx.commit()?;
+        tracing::info!(
+            "数据库迁移完成：v{}（{}）",
+            migration.version,
+            migration.description
+        );
+    }
+    Ok(())
+}
+
+/// 当前 schema 版本（未执行过迁移时为 0）
+pub(super) fn current_version(conn: &Connection) -> Result<u32> {
+    Ok(conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )?)
+}
+
+/// v1：基线 schema
+fn baseline(conn: &Connection) -> Result<()> {
+    conn.execute_batch(BASELINE_SCHEMA)?;
+
+    // 为迁移框架引入前的数据库补充历史新增列
+    add_column(conn, "credentials", "email", "TEXT")?;
+    add_column(conn, "credentials", "kiro_version", "TEXT")?;
+    add_column(conn, "credentials", "system_version", "TEXT")?;
+    add_column(conn, "credentials", "node_version", "TEXT")?;
+    add_column(conn, "credentials", "allowed_models", "TEXT")?;
+    add_column(conn, "credentials", "extra_headers", "TEXT")?;
+    add_column(conn, "credentials", "access_key_id", "TEXT")?;
+    add_column(conn, "credentials", "secret_access_key", "TEXT")?;
+    add_column(conn, "credentials", "session_token", "TEXT")?;
+    add_column(conn, "request_logs", "tag", "TEXT")?;
+    add_column(conn, "request_logs", "seed", "INTEGER")?;
+    add_column(conn, "request_logs", "request_id", "TEXT")?;
+
+    // 依赖新增列的索引
+    conn.execute_batch(
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_request_logs_tag ON request_logs(tag, created_at);
+        CREATE INDEX IF NOT EXISTS idx_request_logs_request_id ON request_logs(request_id);
+        "#,
+    )?;
+
+    // 先规范化历史数据，再创建校验触发器
+    normalize_expires_at_column(conn)?;
+    conn.execute_batch(EXPIRES_AT_TRIGGERS)?;
+    Ok(())
+}
+
+/// v2：请求日志记录流式响应已发送的 SSE 事件数（最后一个事件 ID）
+fn request_log_sse_events(conn: &Connection) -> Result<()> {
+    add_column(conn, "request_logs", "sse_events", "INTEGER")
+}
+
+/// v3：凭据禁用原因（排空中的凭据不参与熔断恢复）
+fn credential_disabled_reason(conn: &Connection) -> Result<()> {
+    add_column(conn, "credentials", "disabled_reason", "TEXT")
+}
+
+/// v4：用量记录请求标签（`x-kiro-tag`），用于按标签归属用量
+fn usage_log_tag(conn: &Connection) -> Result<()> {
+    add_column(conn, "usage_log", "tag", "TEXT")?;
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_usage_log_tag ON usage_log(tag, created_at);",
+    )?;
+    Ok(())
+}
+
+/// v5：凭据的 OIDC 区域（设备授权登录时指定，IdC 凭据按此区域刷新 Token）
+fn credential_region(conn: &Connection) -> Result<()> {
+    add_column(conn, "credentials", "region", "TEXT")
+}
+
+/// 将已存储的过期时间统一规范化为 UTC RFC3339
+///
+/// 历史导入可能混有时区偏移、无时区或时间戳格式；无法解析的值置空（视为已过期）
+fn normalize_expires_at_column(conn: &Connection) -> Result<()> {
+    let rows: Vec<(i64, String)> = {
+        let mut stmt =
+            conn.prepare("SELECT id, expires_at FROM credentials WHERE expires_at IS NOT NULL")?;
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?
+    };
+
+    let mut changed = 0;
+    for (id, raw) in rows {
+        let normalized = normalize_expires_at(&raw);
+        if normalized.as_deref() == Some(raw.as_str()) {
+            continue;
+        }
+        if normalized.is_none() {
+            tracing::warn!("凭据 #{} 的过期时间无法解析，已清空: {}", id, raw);
+        }
+        conn.execute(
+            "UPDATE credentials SET expires_at = ?1 WHERE id = ?2",
+            params![normalized, id],
+        )?;
+        changed += 1;
+    }
+
+    if changed > 0 {
+        tracing::info!("已规范化 {} 条凭据的过期时间", changed);
+    }
+    Ok(())
+}
+
+/// 为指定表添加列（如果不存在）
+fn add_column(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    let has_column = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+        params![table, column],
+        |row| row.get::<_, i64>(0),
+    )? > 0;
+
+    if !has_column {
+        tracing::info!("{} 表添加 {} 列", table, column);
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(
+                migration.version as usize,
+                i + 1,
+                "迁移版本号需从 1 连续递增"
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_is_idempotent_and_rejects_newer_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run(&mut conn).unwrap();
+        let latest = MIGRATIONS.last().unwrap().version;
+        assert_eq!(current_version(&conn).unwrap(), latest);
+
+        // 再次执行不重复应用
+        run(&mut conn).unwrap();
+        let applied: i64 = conn
+            .query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(applied, latest as i64);
+
+        // 更新版本程序写入的数据库
+        conn.execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, 'future', 0)",
+            params![latest + 1],
+        )
+        .unwrap();
+        let err = run(&mut conn).unwrap_err();
+        assert!(err.to_string().contains("请升级程序"));
+    }
+}
@@ -0,0 +1,175 @@
+//! 凭据存储抽象
+//!
+//! 凭据（含禁用状态与失败计数）和多实例租约通过 [`CredentialStore`] 读写。
+//! 默认实现与 [`Database`](super::Database) 共享 SQLite 连接；配置 `databaseUrl` 后
+//! 改用 PostgreSQL（`postgres` feature），多个副本共享同一个凭据池与故障状态，
+//! 请求日志、对话记录等其余数据仍写入各实例本地的 SQLite
+
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use super::{CredentialUpdate, Lease};
+use crate::kiro::model::credentials::KiroCredentials;
+
+/// 凭据存储
+///
+/// 所有方法均为同步调用，由 [`Database`](super::Database) 委托
+pub trait CredentialStore: Send + Sync {
+    /// 加载所有凭据（按优先级排序）
+    fn load_credentials(&self) -> Result<Vec<KiroCredentials>>;
+
+    /// 插入新凭据，返回分配的 ID
+    fn insert_credential(&self, cred: &KiroCredentials) -> Result<u64>;
+
+    /// 一次性导入凭据（用于旧版 JSON 凭据迁移）
+    ///
+    /// 在同一事务中检查并写入幂等标记 `marker`：标记已存在时不做任何修改并返回 None；
+    /// 否则跳过 refreshToken 已存在（或为空）的凭据，返回 (导入数, 跳过数)
+    fn import_credentials_once(
+        &self,
+        creds: &[KiroCredentials],
+        marker: &str,
+    ) -> Result<Option<(usize, usize)>>;
+
+    /// 批量导入凭据（Admin 导入接口）
+    ///
+    /// 在同一事务中写入，跳过 refreshToken 已存在（或为空）的凭据，返回 (导入数, 跳过数)
+    fn import_credentials(&self, creds: &[KiroCredentials]) -> Result<(usize, usize)>;
+
+    /// 凭据是否只读（来自共享凭据库）：只读凭据不刷新 Token，只使用库中的 accessToken
+    fn is_read_only(&self, _id: u64) -> bool {
+        false
+    }
+
+    /// 保存刷新后的 Token（以刷新前的 refreshToken 作为乐观锁）
+    ///
+    /// 仅当数据库中的 refreshToken 仍为 `previous_refresh_token` 时写入 Token 相关字段，
+    /// 返回是否写入成功。多个实例共享凭据时，写入失败说明其他实例已先完成刷新并轮换了
+    /// refreshToken，此时不能覆盖，否则会用已失效的 refreshToken 打断轮换链
+    fn save_refreshed_token(
+        &self,
+        cred: &KiroCredentials,
+        previous_refresh_token: &str,
+    ) -> Result<bool>;
+
+    /// 使缓存的 accessToken 失效（清空过期时间，下次使用时强制刷新）
+    ///
+    /// 仅当数据库中仍是 `access_token` 时生效，避免覆盖其他请求刚刷新的 Token；返回是否生效
+    fn invalidate_access_token(&self, id: u64, access_token: &str) -> Result<bool>;
+
+    /// 用给定的凭据集合整体替换凭据表（热备同步使用）
+    ///
+    /// 保留原有 ID：存在则更新、不存在则插入，集合中没有的凭据会被删除。
+    /// 在单个事务中完成，返回被删除的凭据数量
+    fn replace_credentials(&self, creds: &[KiroCredentials]) -> Result<usize>;
+
+    /// 删除凭据
+    fn delete_credential(&self, id: u64) -> Result<bool>;
+
+    /// 获取单个凭据
+    fn get_credential(&self, id: u64) -> Result<Option<KiroCredentials>>;
+
+    /// 获取凭据数量
+    fn count_credentials(&self) -> Result<usize>;
+
+    /// 更新凭据余额信息
+    fn update_balance(
+        &self,
+        id: u64,
+        subscription_title: Option<&str>,
+        current_usage: f64,
+        usage_limit: f64,
+        next_reset_at: Option<f64>,
+    ) -> Result<bool>;
+
+    /// 设置凭据禁用状态
+    ///
+    /// 禁用时记录 disabled_at 时间戳，启用时清除（同时清除排空标记）
+    fn set_disabled(&self, id: u64, disabled: bool) -> Result<bool>;
+
+    /// 禁用凭据并标记为排空中
+    ///
+    /// 排空中的凭据不参与熔断恢复（不出现在 `list_cooled_down` 与 `disabled_since` 中），
+    /// 直到被启用或删除
+    fn set_draining(&self, id: u64) -> Result<bool>;
+
+    /// 在单个事务中批量更新凭据的禁用状态与优先级
+    ///
+    /// 任一凭据不存在时不做任何修改，返回该凭据 ID
+    fn bulk_update_credentials(&self, updates: &[CredentialUpdate]) -> Result<Option<u64>>;
+
+    /// 设置凭据优先级
+    fn set_priority(&self, id: u64, priority: u32) -> Result<bool>;
+
+    /// 增加失败计数
+    fn increment_failure_count(&self, id: u64) -> Result<u32>;
+
+    /// 重置失败计数
+    ///
+    /// 返回失败计数是否发生变化（凭据不存在或计数已为 0 时返回 false）
+    fn reset_failure_count(&self, id: u64) -> Result<bool>;
+
+    /// 重置失败计数并启用凭据
+    fn reset_and_enable(&self, id: u64) -> Result<bool>;
+
+    /// 获取熔断期已过的禁用凭据（按优先级排序，半开探测候选）
+    fn list_cooled_down(&self, open_duration: Duration) -> Result<Vec<KiroCredentials>>;
+
+    /// 获取所有禁用凭据的禁用时间
+    fn disabled_since(&self) -> Result<HashMap<u64, chrono::DateTime<chrono::Utc>>>;
+
+    /// 获取优先级最高的可用凭据
+    fn get_highest_priority_available(&self) -> Result<Option<KiroCredentials>>;
+
+    /// 获取下一个优先级最高的可用凭据（排除指定 ID）
+    fn get_next_available(&self, exclude_id: u64) -> Result<Option<KiroCredentials>>;
+
+    /// 获取可用凭据数量
+    fn count_available(&self) -> Result<usize>;
+
+    /// 设置凭据的 machine_id
+    fn set_machine_id(&self, id: u64, machine_id: Option<&str>) -> Result<bool>;
+
+    /// 更新凭据的邮箱
+    fn update_email(&self, id: u64, email: Option<&str>) -> Result<bool>;
+
+    /// 设置凭据的客户端版本覆盖（None 表示使用全局配置）
+    fn set_version_overrides(
+        &self,
+        id: u64,
+        kiro_version: Option<&str>,
+        system_version: Option<&str>,
+        node_version: Option<&str>,
+    ) -> Result<bool>;
+
+    /// 设置凭据允许使用的模型（None 表示不限制）
+    fn set_allowed_models(&self, id: u64, allowed_models: &Option<Vec<String>>) -> Result<bool>;
+
+    /// 设置凭据的自定义上游请求头（None 表示清除）
+    fn set_extra_headers(
+        &self,
+        id: u64,
+        extra_headers: &Option<BTreeMap<String, String>>,
+    ) -> Result<bool>;
+
+    /// 检查 client_id 是否已存在
+    ///
+    /// 用于添加凭据时去重，只检查非空的 client_id
+    fn client_id_exists(&self, client_id: &str) -> Result<bool>;
+
+    /// 检查 IAM accessKeyId 是否已存在
+    fn access_key_id_exists(&self, access_key_id: &str) -> Result<bool>;
+
+    /// 获取或续期租约
+    ///
+    /// 租约空闲、已过期或已由 `holder` 持有时写入新的过期时间并返回 true；
+    /// 由其他实例持有且未过期时返回 false
+    fn try_acquire_lease(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool>;
+
+    /// 释放租约（仅当由 `holder` 持有时）
+    fn release_lease(&self, name: &str, holder: &str) -> Result<bool>;
+
+    /// 列出所有租约（包括已过期但尚未被接管的）
+    fn list_leases(&self) -> Result<Vec<Lease>>;
+}
@@ -0,0 +1,345 @@
+//! AWS SSO OIDC 设备授权
+//!
+//! 用于通过 Admin API 登录添加 IdC / Builder ID 凭据，无需从 Kiro IDE 中手动提取 Token：
+//! 1. 注册 OIDC 公共客户端，获得 clientId / clientSecret
+//! 2. 发起设备授权，获得用户码与验证地址，由用户在浏览器中完成登录
+//! 3. 按返回的间隔轮询 Token 端点，直到用户授权、拒绝或设备码过期
+//!
+//! OIDC 区域默认使用配置中的 `region`，登录时可为 IAM Identity Center 所在区域单独指定；
+//! 指定的区域会保存到凭据中，之后按该区域刷新 Token
+
+use anyhow::bail;
+
+use crate::http_client::{ProxyConfig, build_client};
+use crate::kiro::model::token_refresh::{
+    DeviceTokenRequest, IdcRefreshResponse, OidcErrorResponse, RegisterClientRequest,
+    RegisterClientResponse, StartDeviceAuthorizationRequest, StartDeviceAuthorizationResponse,
+};
+use crate::model::config::Config;
+
+/// AWS Builder ID 的起始地址（未指定 IAM Identity Center 起始地址时使用）
+pub const BUILDER_ID_START_URL: &str = "https://view.awsapps.com/start";
+
+/// 注册的客户端名称
+const CLIENT_NAME: &str = "kiro-rs";
+
+/// 申请的权限范围（与 Kiro IDE 一致）
+const SCOPES: &[&str] = &[
+    "codewhisperer:completions",
+    "codewhisperer:analysis",
+    "codewhisperer:conversations",
+    "codewhisperer:transformations",
+    "codewhisperer:taskassist",
+];
+
+/// 设备码授权类型
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// 未返回轮询间隔时的默认值（秒）
+const DEFAULT_INTERVAL_SECS: u64 = 5;
+
+/// 收到 slow_down 时增加的轮询间隔（秒，RFC 8628）
+pub const SLOW_DOWN_SECS: u64 = 5;
+
+/// 进行中的设备授权
+#[derive(Debug, Clone)]
+pub struct DeviceAuthorization {
+    pub client_id: String,
+    pub client_secret: String,
+    pub device_code: String,
+    /// 用户在验证页面输入的用户码
+    pub user_code: String,
+    /// 验证页面地址
+    pub verification_uri: String,
+    /// 已包含用户码的验证页面地址
+    pub verification_uri_complete: Option<String>,
+    /// 设备码有效期（秒）
+    pub expires_in: u64,
+    /// 轮询间隔（秒）
+    pub interval: u64,
+}
+
+/// 授权完成后获得的 Token
+#[derive(Debug, Clone)]
+pub struct DeviceToken {
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+/// 单次轮询结果
+#[derive(Debug)]
+pub enum PollOutcome {
+    /// 用户尚未完成授权
+    Pending,
+    /// 轮询过快，需要增加间隔
+    SlowDown,
+    /// 用户已授权
+    Authorized(DeviceToken),
+}
+
+/// 设备授权客户端
+pub struct DeviceAuthClient {
+    client: reqwest::Client,
+    /// OIDC 服务地址
+    endpoint: String,
+    /// x-amz-user-agent 请求头
+    amz_user_agent: String,
+}
+
+impl DeviceAuthClient {
+    /// 创建客户端（`region` 为 None 时使用配置中的 `region`）
+    pub fn new(
+        config: &Config,
+        region: Option<&str>,
+        proxy: Option<&ProxyConfig>,
+    ) -> anyhow::Result<Self> {
+        let region = region.unwrap_or(&config.region);
+        Ok(Self {
+            client: build_client(proxy, 60)?,
+            endpoint: format!("https://oidc.{}.amazonaws.com", region),
+            amz_user_agent: config.idc_amz_user_agent.clone(),
+        })
+    }
+
+    fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .post(format!("{}{}", self.endpoint, path))
+            .header("Content-Type", "application/json")
+            .header("x-amz-user-agent", &self.amz_user_agent)
+            .header("User-Agent", "node")
+    }
+
+    /// 注册客户端并发起设备授权
+    pub async fn start(&self, start_url: &str) -> anyhow::Result<DeviceAuthorization> {
+        let response = self
+            .post("/client/register")
+            .json(&RegisterClientRequest {
+                client_name: CLIENT_NAME.to_string(),
+                client_type: "public".to_string(),
+                scopes: SCOPES.iter().map(|s| s.to_string()).collect(),
+            })
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            bail!(
+                "注册 OIDC 客户端失败: {} {}",
+                status,
+                response.text().await.unwrap_or_default()
+            );
+        }
+        let client: RegisterClientResponse = response.json().await?;
+
+        let response = self
+            .post("/device_authorization")
+            .json(&StartDeviceAuthorizationRequest {
+                client_id: client.client_id.clone(),
+                client_secret: client.client_secret.clone(),
+                start_url: start_url.to_string(),
+            })
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            bail!(
+                "发起设备授权失败: {} {}",
+                status,
+                response.text().await.unwrap_or_default()
+            );
+        }
+        let device: StartDeviceAuthorizationResponse = response.json().await?;
+
+        Ok(DeviceAuthorization {
+            client_id: client.client_id,
+            client_secret: client.client_secret,
+            device_code: device.device_code,
+            user_code: device.user_code,
+            verification_uri: device.verification_uri,
+            verification_uri_complete: device.verification_uri_complete,
+            expires_in: device.expires_in,
+            interval: device.interval.unwrap_or(DEFAULT_INTERVAL_SECS).max(1),
+        })
+    }
+
+    /// 轮询一次授权结果
+    ///
+    /// 网络错误视为暂时性故障，按用户尚未完成授权处理（由调用方在设备码过期前继续轮询）
+    pub async fn poll(&self, auth: &DeviceAuthorization) -> anyhow::Result<PollOutcome> {
+        let response = match self
+            .post("/token")
+            .json(&DeviceTokenRequest {
+                client_id: auth.client_id.clone(),
+                client_secret: auth.client_secret.clone(),
+                device_code: auth.device_code.clone(),
+                grant_type: DEVICE_CODE_GRANT.to_string(),
+            })
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!("轮询设备授权结果失败，稍后重试: {}", e);
+                return Ok(PollOutcome::Pending);
+            }
+        };
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let error = serde_json::from_str::<OidcErrorResponse>(&body).ok();
+            return match error.as_ref().map(|e| e.error.as_str()) {
+                Some("authorization_pending") => Ok(PollOutcome::Pending),
+                Some("slow_down") => Ok(PollOutcome::SlowDown),
+                Some("access_denied") => bail!("用户拒绝了授权"),
+                Some("expired_token") => bail!("设备码已过期，请重新发起登录"),
+                _ => bail!("获取 Token 失败: {} {}", status, body),
+            };
+        }
+
+        let data: IdcRefreshResponse = response.json().await?;
+        let Some(refresh_token) = data.refresh_token else {
+            bail!("授权响应缺少 refreshToken");
+        };
+        Ok(PollOutcome::Authorized(DeviceToken {
+            access_token: data.access_token,
+            refresh_token,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use axum::{Json, Router, http::StatusCode, routing::post};
+    use serde_json::{Value, json};
+
+    #[tokio::test]
+    async fn test_device_authorization_flow() {
+        let polls = Arc::new(AtomicU32::new(0));
+        let app = Router::new()
+            .route(
+                "/client/register",
+                post(|Json(body): Json<Value>| async move {
+                    assert_eq!(body["clientType"], "public");
+                    Json(json!({"clientId": "cid", "clientSecret": "secret"}))
+                }),
+            )
+            .route(
+                "/device_authorization",
+                post(|Json(body): Json<Value>| async move {
+                    assert_eq!(body["startUrl"], BUILDER_ID_START_URL);
+                    Json(json!({
+                        "deviceCode": "dev",
+                        "userCode": "ABCD-EFGH",
+                        "verificationUri": "https://device.sso.example.com/",
+                        "verificationUriComplete": "https://device.sso.example.com/?user_code=ABCD-EFGH",
+                        "expiresIn": 600,
+                        "interval": 1
+                    }))
+                }),
+            )
+            .route(
+                "/token",
+                post({
+                    let polls = polls.clone();
+                    move |Json(body): Json<Value>| async move {
+                        assert_eq!(body["grantType"], DEVICE_CODE_GRANT);
+                        match polls.fetch_add(1, Ordering::SeqCst) {
+                            0 => (
+                                StatusCode::BAD_REQUEST,
+                                Json(json!({"error": "authorization_pending"})),
+                            ),
+                            1 => (
+                                StatusCode::BAD_REQUEST,
+                                Json(json!({"error": "slow_down"})),
+                            ),
+                            2 => (
+                                StatusCode::OK,
+                                Json(json!({
+                                    "accessToken": "at",
+                                    "refreshToken": "rt",
+                                    "expiresIn": 3600
+                                })),
+                            ),
+                            _ => (
+                                StatusCode::BAD_REQUEST,
+                                Json(json!({"error": "expired_token"})),
+                            ),
+                        }
+                    }
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = DeviceAuthClient {
+            endpoint,
+            ..DeviceAuthClient::new(&Config::default(), None, None).unwrap()
+        };
+        let auth = client.start(BUILDER_ID_START_URL).await.unwrap();
+        assert_eq!(auth.client_id, "cid");
+        assert_eq!(auth.user_code, "ABCD-EFGH");
+        assert_eq!(auth.interval, 1);
+
+        assert!(matches!(
+            client.poll(&auth).await.unwrap(),
+            PollOutcome::Pending
+        ));
+        assert!(matches!(
+            client.poll(&auth).await.unwrap(),
+            PollOutcome::SlowDown
+        ));
+        match client.poll(&auth).await.unwrap() {
+            PollOutcome::Authorized(token) => {
+                assert_eq!(token.refresh_token, "rt");
+                assert_eq!(token.access_token, "at");
+            }
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+        let err = client.poll(&auth).await.unwrap_err();
+        assert!(err.to_string().contains("设备码已过期"));
+    }
+
+    #[tokio::test]
+    async fn test_poll_network_error_is_pending() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let client = DeviceAuthClient {
+            endpoint,
+            ..DeviceAuthClient::new(&Config::default(), Some("eu-west-1"), None).unwrap()
+        };
+        let auth = DeviceAuthorization {
+            client_id: "cid".to_string(),
+            client_secret: "secret".to_string(),
+            device_code: "dev".to_string(),
+            user_code: "ABCD-EFGH".to_string(),
+            verification_uri: "https://device.sso.example.com/".to_string(),
+            verification_uri_complete: None,
+            expires_in: 600,
+            interval: 1,
+        };
+
+        // 连接失败按尚未授权处理，由调用方继续轮询直到设备码过期
+        assert!(matches!(
+            client.poll(&auth).await.unwrap(),
+            PollOutcome::Pending
+        ));
+    }
+
+    #[test]
+    fn test_login_region_overrides_config() {
+        let client = DeviceAuthClient::new(&Config::default(), Some("eu-west-1"), None).unwrap();
+        assert_eq!(client.endpoint, "https://oidc.eu-west-1.amazonaws.com");
+        let client = DeviceAuthClient::new(&Config::default(), None, None).unwrap();
+        assert_eq!(
+            client.endpoint,
+            format!("https://oidc.{}.amazonaws.com", Config::default().region)
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! 上游故障注入（预发布环境）
+//!
+//! 配置 `staging: true` 后，Provider 在调用上游之前按配置确定性地注入延迟与失败，
+//! 用于在没有真实故障的情况下验证客户端对本代理的重试行为。注入的失败直接返回给客户端，
+//! 不经过凭据故障转移，也不计入凭据失败次数
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use crate::model::config::Config;
+
+/// 注入的上游失败
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InjectedFailure {
+    /// 返回给客户端的状态码
+    pub status: StatusCode,
+    /// 触发失败的请求序号（从 1 开始）
+    pub request: u64,
+}
+
+impl fmt::Display for InjectedFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "故障注入：第 {} 个请求返回 {}",
+            self.request,
+            self.status.as_u16()
+        )
+    }
+}
+
+impl std::error::Error for InjectedFailure {}
+
+/// 故障注入器
+#[derive(Debug)]
+pub struct FailureInjector {
+    /// 每 N 个请求失败一次（0 表示不注入失败）
+    every_nth: u64,
+    /// 依次轮换的失败状态码
+    status_codes: Vec<StatusCode>,
+    /// 每个请求附加的延迟
+    latency: Duration,
+    /// 已处理的请求数
+    requests: AtomicU64,
+}
+
+impl FailureInjector {
+    /// 根据配置创建；未开启 `staging` 或未配置任何注入时返回 None
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if !config.staging {
+            return None;
+        }
+        let status_codes: Vec<StatusCode> = config
+            .failure_injection_status_codes
+            .iter()
+            .filter_map(|&code| match StatusCode::from_u16(code) {
+                Ok(status) if status.is_client_error() || status.is_server_error() => Some(status),
+                _ => {
+                    tracing::warn!("忽略无效的故障注入状态码: {}", code);
+                    None
+                }
+            })
+            .collect();
+        let injector = Self {
+            every_nth: config.failure_injection_every_nth,
+            status_codes: if status_codes.is_empty() {
+                vec![StatusCode::SERVICE_UNAVAILABLE]
+            } else {
+                status_codes
+            },
+            latency: Duration::from_millis(config.failure_injection_latency_ms),
+            requests: AtomicU64::new(0),
+        };
+        if injector.every_nth == 0 && injector.latency.is_zero() {
+            tracing::warn!(
+                "已开启 staging，但未配置故障注入（failureInjectionEveryNth / failureInjectionLatencyMs）"
+            );
+            return None;
+        }
+        tracing::warn!(
+            "已开启 staging 故障注入：每 {} 个请求失败一次（状态码 {:?}），附加延迟 {}ms，请勿用于生产环境",
+            injector.every_nth,
+            injector
+                .status_codes
+                .iter()
+                .map(|s| s.as_u16())
+                .collect::<Vec<_>>(),
+            injector.latency.as_millis()
+        );
+        Some(injector)
+    }
+
+    /// 记录一个请求，返回该请求是否应失败
+    ///
+    /// 第 N、2N、3N… 个请求失败，状态码按配置顺序轮换
+    pub fn next(&self) -> Option<InjectedFailure> {
+        let request = self.requests.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.every_nth == 0 || !request.is_multiple_of(self.every_nth) {
+            return None;
+        }
+        let round = (request / self.every_nth - 1) as usize;
+        Some(InjectedFailure {
+            status: self.status_codes[round % self.status_codes.len()],
+            request,
+        })
+    }
+
+    /// 附加延迟后决定本次请求是否失败
+    pub async fn apply(&self) -> Result<(), InjectedFailure> {
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        match self.next() {
+            Some(failure) => {
+                tracing::warn!("{}", failure);
+                Err(failure)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_injection_is_deterministic() {
+        let config = Config {
+            staging: true,
+            failure_injection_every_nth: 3,
+            failure_injection_status_codes: vec![429, 200, 529],
+            ..Config::default()
+        };
+        let injector = FailureInjector::from_config(&config).unwrap();
+        let statuses: Vec<_> = (0..9)
+            .map(|_| injector.next().map(|f| f.status.as_u16()))
+            .collect();
+        // 无效状态码 200 被忽略，429 与 529 轮换
+        assert_eq!(
+            statuses,
+            [
+                None,
+                None,
+                Some(429),
+                None,
+                None,
+                Some(529),
+                None,
+                None,
+                Some(429)
+            ]
+        );
+
+        // 未开启 staging 时不注入
+        let config = Config {
+            staging: false,
+            ..config
+        };
+        assert!(FailureInjector::from_config(&config).is_none());
+    }
+}
@@ -0,0 +1,113 @@
+//! 凭据健康检查
+//!
+//! 通过轻量的上游调用（getUsageLimits）检查凭据是否可用并记录耗时，
+//! 结果写入健康检查历史表。可通过 Admin API 手动检查单个凭据，
+//! 也可配置 `healthCheckIntervalMins` 在后台定期探测全部凭据
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use futures::StreamExt;
+
+use crate::kiro::lease::Lease;
+use crate::kiro::model::health_check::{HealthCheck, HealthCheckTrigger};
+use crate::kiro::replication;
+use crate::kiro::token_manager::MultiTokenManager;
+
+/// 定时探测租约名称（多实例共享数据库时只由持有者探测）
+const SCHEDULER_LEASE: &str = "health_check";
+
+/// 定时探测的并发数
+const PROBE_CONCURRENCY: usize = 4;
+
+/// 健康检查记录保留天数
+const RETENTION_DAYS: i64 = 7;
+
+/// 检查单个凭据并记录结果（写入失败不影响返回结果）
+///
+/// 上游调用失败不视为错误，而是记录为不健康的检查结果
+pub async fn probe(
+    token_manager: &MultiTokenManager,
+    id: u64,
+    trigger: HealthCheckTrigger,
+) -> HealthCheck {
+    let checked_at = Utc::now();
+    let started = Instant::now();
+    let result = token_manager.get_usage_limits_for(id).await;
+
+    let mut check = HealthCheck {
+        id: 0,
+        credential_id: id,
+        checked_at,
+        healthy: result.is_ok(),
+        latency_ms: started.elapsed().as_millis() as u64,
+        error: result.err().map(|e| e.to_string()),
+        trigger,
+    };
+
+    let record = check.clone();
+    match token_manager
+        .database()
+        .call(move |db| db.insert_health_check(&record))
+        .await
+    {
+        Ok(id) => check.id = id,
+        Err(e) => tracing::warn!("写入凭据 #{} 健康检查记录失败: {}", id, e),
+    }
+    check
+}
+
+/// 启动定时探测任务
+///
+/// 每 `interval` 探测一次全部凭据（热备期间跳过，避免刷新主实例正在使用的 Token；
+/// 多实例共享数据库时只由持有租约的实例执行），并清理超过保留期的记录
+pub fn spawn_scheduler(token_manager: Arc<MultiTokenManager>, interval: Duration) {
+    let lease = Lease::spawn(token_manager.database().clone(), SCHEDULER_LEASE);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // 首个 tick 立即完成，跳过以免启动时与其他初始化请求争用
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if replication::is_standby() || !lease.is_held() {
+                continue;
+            }
+
+            let ids: Vec<u64> = token_manager
+                .blocking(|tm| tm.snapshot())
+                .await
+                .entries
+                .into_iter()
+                .map(|entry| entry.id)
+                .collect();
+            let total = ids.len();
+            let unhealthy = futures::stream::iter(ids)
+                .map(|id| {
+                    let token_manager = token_manager.clone();
+                    async move { probe(&token_manager, id, HealthCheckTrigger::Scheduled).await }
+                })
+                .buffer_unordered(PROBE_CONCURRENCY)
+                .filter(|check| std::future::ready(!check.healthy))
+                .inspect(|check| {
+                    tracing::warn!(
+                        "凭据 #{} 健康检查失败: {}",
+                        check.credential_id,
+                        check.error.as_deref().unwrap_or_default()
+                    )
+                })
+                .count()
+                .await;
+            tracing::info!("凭据健康检查完成: {} 个凭据，{} 个异常", total, unhealthy);
+
+            let cutoff = Utc::now() - chrono::Duration::days(RETENTION_DAYS);
+            if let Err(e) = token_manager
+                .database()
+                .call(move |db| db.prune_health_checks(cutoff))
+                .await
+            {
+                tracing::warn!("清理健康检查记录失败: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,243 @@
+//! 凭据延迟跟踪与自动降级
+//!
+//! 记录每个凭据最近的上游首包延迟，滚动 p95 持续超过阈值（如凭据位于其他区域、被上游限速）时
+//! 将其临时降级：选择凭据时优先使用未降级的凭据。降级期间若该凭据仍有请求且延迟恢复则提前恢复，
+//! 否则降级到期后自动恢复并重新采样。流量切走后降级凭据不再产生样本，因此到期恢复不依赖新样本，
+//! 由凭据选择时通过 [`LatencyTracker::take_recovered`] 检查并重新按优先级选择
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+
+/// 每个凭据保留的延迟样本数
+const WINDOW_SIZE: usize = 50;
+
+/// 计算 p95 所需的最少样本数
+const MIN_SAMPLES: usize = 10;
+
+/// 降级持续时间
+const DEMOTION_DURATION: Duration = Duration::from_secs(300);
+
+/// 单个凭据的延迟状态
+#[derive(Default)]
+struct CredentialLatency {
+    /// 最近的延迟样本（毫秒）
+    samples: VecDeque<u64>,
+    /// 降级到期时间
+    demoted_until: Option<(Instant, DateTime<Utc>)>,
+    /// 降级已到期，尚未被 `take_recovered` 取走
+    recovered: bool,
+}
+
+impl CredentialLatency {
+    fn p95(&self) -> Option<u64> {
+        if self.samples.len() < MIN_SAMPLES {
+            return None;
+        }
+        let mut sorted: Vec<u64> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let index = (sorted.len() * 95).div_ceil(100) - 1;
+        Some(sorted[index])
+    }
+
+    /// 降级已到期时清除降级状态，返回是否仍处于降级
+    fn check_demoted(&mut self, id: u64) -> bool {
+        match self.demoted_until {
+            Some((until, _)) if Instant::now() < until => true,
+            Some(_) => {
+                self.demoted_until = None;
+                self.samples.clear();
+                self.recovered = true;
+                tracing::info!("凭据 #{} 延迟降级已到期，恢复参与选择", id);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+/// 凭据延迟状态（Admin API 展示）
+#[derive(Debug, Clone, Default)]
+pub struct LatencyStatus {
+    /// 最近的 p95 延迟（毫秒，样本不足时为 None）
+    pub p95_ms: Option<u64>,
+    /// 降级到期时间（未降级时为 None）
+    pub demoted_until: Option<DateTime<Utc>>,
+}
+
+/// 凭据延迟跟踪器
+pub struct LatencyTracker {
+    /// p95 降级阈值（None 表示不降级，仅统计）
+    threshold: Option<Duration>,
+    credentials: Mutex<HashMap<u64, CredentialLatency>>,
+}
+
+impl LatencyTracker {
+    /// 创建延迟跟踪器，`threshold_ms` 为 0 时不降级
+    pub fn new(threshold_ms: u64) -> Self {
+        Self {
+            threshold: (threshold_ms > 0).then(|| Duration::from_millis(threshold_ms)),
+            credentials: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 记录一次上游延迟
+    pub fn record(&self, id: u64, latency: Duration) {
+        let mut credentials = self.credentials.lock();
+        let entry = credentials.entry(id).or_default();
+        if entry.samples.len() == WINDOW_SIZE {
+            entry.samples.pop_front();
+        }
+        entry.samples.push_back(latency.as_millis() as u64);
+
+        let Some(threshold) = self.threshold else {
+            return;
+        };
+        let Some(p95) = entry.p95() else {
+            return;
+        };
+        let threshold_ms = threshold.as_millis() as u64;
+        let demoted = entry.check_demoted(id);
+
+        if !demoted && p95 > threshold_ms {
+            let until = Instant::now() + DEMOTION_DURATION;
+            let until_utc =
+                Utc::now() + chrono::Duration::from_std(DEMOTION_DURATION).unwrap_or_default();
+            entry.demoted_until = Some((until, until_utc));
+            // 清空样本，恢复判断只依据降级后的新样本
+            entry.samples.clear();
+            tracing::warn!(
+                "凭据 #{} p95 延迟 {}ms 超过阈值 {}ms，临时降级 {} 秒",
+                id,
+                p95,
+                threshold_ms,
+                DEMOTION_DURATION.as_secs()
+            );
+        } else if demoted && p95 <= threshold_ms {
+            entry.demoted_until = None;
+            tracing::info!("凭据 #{} p95 延迟恢复到 {}ms，解除降级", id, p95);
+        }
+    }
+
+    /// 凭据是否处于降级状态
+    pub fn is_demoted(&self, id: u64) -> bool {
+        self.credentials
+            .lock()
+            .get_mut(&id)
+            .is_some_and(|entry| entry.check_demoted(id))
+    }
+
+    /// 所有处于降级状态的凭据 ID
+    pub fn demoted_ids(&self) -> Vec<u64> {
+        self.credentials
+            .lock()
+            .iter_mut()
+            .filter_map(|(&id, entry)| entry.check_demoted(id).then_some(id))
+            .collect()
+    }
+
+    /// 清除已到期的降级，返回自上次调用以来降级到期的凭据 ID
+    pub fn take_recovered(&self) -> Vec<u64> {
+        let mut credentials = self.credentials.lock();
+        let mut recovered: Vec<u64> = credentials
+            .iter_mut()
+            .filter_map(|(&id, entry)| {
+                entry.check_demoted(id);
+                std::mem::take(&mut entry.recovered).then_some(id)
+            })
+            .collect();
+        recovered.sort_unstable();
+        recovered
+    }
+
+    /// 获取凭据延迟状态
+    pub fn status(&self, id: u64) -> LatencyStatus {
+        let mut credentials = self.credentials.lock();
+        let Some(entry) = credentials.get_mut(&id) else {
+            return LatencyStatus::default();
+        };
+        let demoted = entry.check_demoted(id);
+        LatencyStatus {
+            p95_ms: entry.p95(),
+            demoted_until: entry
+                .demoted_until
+                .filter(|_| demoted)
+                .map(|(_, until)| until),
+        }
+    }
+
+    /// 使凭据的降级立即到期（测试用）
+    #[cfg(test)]
+    pub fn expire_demotion(&self, id: u64) {
+        if let Some(entry) = self.credentials.lock().get_mut(&id) {
+            entry.demoted_until = entry.demoted_until.map(|_| (Instant::now(), Utc::now()));
+        }
+    }
+
+    /// 删除凭据的延迟状态
+    pub fn remove(&self, id: u64) {
+        self.credentials.lock().remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_n(tracker: &LatencyTracker, id: u64, ms: u64, n: usize) {
+        for _ in 0..n {
+            tracker.record(id, Duration::from_millis(ms));
+        }
+    }
+
+    #[test]
+    fn test_demotion_and_recovery() {
+        let tracker = LatencyTracker::new(1000);
+        record_n(&tracker, 1, 200, MIN_SAMPLES);
+        assert_eq!(tracker.status(1).p95_ms, Some(200));
+        assert!(!tracker.is_demoted(1));
+
+        // 偶发慢请求不会触发降级
+        record_n(&tracker, 1, 200, 30);
+        record_n(&tracker, 1, 5000, 1);
+        assert!(!tracker.is_demoted(1));
+
+        // 持续变慢触发降级
+        let tracker = LatencyTracker::new(1000);
+        record_n(&tracker, 1, 3000, MIN_SAMPLES);
+        assert!(tracker.is_demoted(1));
+        assert_eq!(tracker.demoted_ids(), vec![1]);
+        let status = tracker.status(1);
+        assert!(status.demoted_until.is_some());
+        assert_eq!(status.p95_ms, None);
+
+        // 降级期间延迟恢复则提前解除
+        record_n(&tracker, 1, 300, MIN_SAMPLES);
+        assert!(!tracker.is_demoted(1));
+        assert!(tracker.status(1).demoted_until.is_none());
+    }
+
+    #[test]
+    fn test_take_recovered_after_expiry() {
+        let tracker = LatencyTracker::new(1000);
+        record_n(&tracker, 1, 3000, MIN_SAMPLES);
+        assert!(tracker.is_demoted(1));
+        assert!(tracker.take_recovered().is_empty());
+
+        // 没有新样本时降级同样按时到期
+        tracker.expire_demotion(1);
+        assert_eq!(tracker.take_recovered(), vec![1]);
+        assert!(!tracker.is_demoted(1));
+        assert!(tracker.take_recovered().is_empty());
+    }
+
+    #[test]
+    fn test_disabled_threshold_only_tracks() {
+        let tracker = LatencyTracker::new(0);
+        record_n(&tracker, 1, 60_000, MIN_SAMPLES * 2);
+        assert!(!tracker.is_demoted(1));
+        assert_eq!(tracker.status(1).p95_ms, Some(60_000));
+    }
+}
@@ -0,0 +1,122 @@
+//! 多实例协调租约
+//!
+//! 多个实例共享同一个数据库时，后台任务（定时健康检查、余额刷新、告警通知投递、记录清理）
+//! 只应由一个实例执行，避免重复轮询上游或重复发送邮件。每个后台任务对应一个命名租约，
+//! 实例在后台定期续期，只有持有租约的实例执行该任务；持有者退出或失联后租约过期，
+//! 由其他实例接管。租约只是协调手段而非互斥锁：接管前后可能短暂重叠一个续期周期
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use crate::kiro::db::Database;
+
+/// 租约有效期
+const LEASE_TTL: Duration = Duration::from_secs(30);
+
+/// 续期间隔（需明显小于有效期，容忍偶发的数据库锁竞争）
+const RENEW_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 本实例 ID（进程启动时随机生成）
+static INSTANCE_ID: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "{}-{}",
+        std::process::id(),
+        &uuid::Uuid::new_v4().simple().to_string()[..8]
+    )
+});
+
+/// 获取本实例 ID
+pub fn instance_id() -> &'static str {
+    &INSTANCE_ID
+}
+
+/// 命名租约（由后台任务自动续期）
+pub struct Lease {
+    name: &'static str,
+    held: AtomicBool,
+}
+
+impl Lease {
+    /// 启动租约续期任务（需在 tokio 运行时中调用）
+    pub fn spawn(db: Arc<Database>, name: &'static str) -> Arc<Self> {
+        let lease = Arc::new(Self {
+            name,
+            held: AtomicBool::new(false),
+        });
+
+        let weak = Arc::downgrade(&lease);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(RENEW_INTERVAL);
+            loop {
+                ticker.tick().await;
+                // 使用方全部退出后释放租约，其他实例无需等待过期即可接管
+                let Some(lease) = weak.upgrade() else {
+                    if let Err(e) = db
+                        .call(move |db| db.release_lease(name, instance_id()))
+                        .await
+                    {
+                        tracing::warn!("释放租约 {} 失败: {}", name, e);
+                    }
+                    break;
+                };
+                let held = db
+                    .call(move |db| db.try_acquire_lease(name, instance_id(), LEASE_TTL))
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::warn!("续期租约 {} 失败: {}", name, e);
+                        false
+                    });
+                lease.set_held(held);
+            }
+        });
+        lease
+    }
+
+    /// 本实例当前是否持有租约
+    pub fn is_held(&self) -> bool {
+        self.held.load(Ordering::Relaxed)
+    }
+
+    fn set_held(&self, held: bool) {
+        if self.held.swap(held, Ordering::Relaxed) != held {
+            if held {
+                tracing::info!("已获取租约 {}，由本实例执行该后台任务", self.name);
+            } else {
+                tracing::info!("租约 {} 由其他实例持有，本实例暂停该后台任务", self.name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lease_acquired_on_spawn() {
+        let db = Database::open_in_memory().unwrap();
+        db.try_acquire_lease("taken", "other-instance", LEASE_TTL)
+            .unwrap();
+
+        let free = Lease::spawn(db.clone(), "free");
+        let taken = Lease::spawn(db.clone(), "taken");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(free.is_held());
+        assert!(!taken.is_held());
+        let holders: Vec<(String, String)> = db
+            .list_leases()
+            .unwrap()
+            .into_iter()
+            .map(|l| (l.name, l.holder))
+            .collect();
+        assert_eq!(
+            holders,
+            vec![
+                ("free".to_string(), instance_id().to_string()),
+                ("taken".to_string(), "other-instance".to_string()),
+            ]
+        );
+    }
+}
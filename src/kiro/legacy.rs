@@ -0,0 +1,133 @@
+//! 旧版 JSON 凭据迁移
+//!
+//! 引入 SQLite 之前，凭据以内联数组形式写在 config.json 的 `credentials` 字段中，
+//! 或保存在单独的凭据文件（`--credentials`，单个对象或数组）中。
+//! 启动时自动将其导入数据库，并以内容摘要作为幂等标记，避免重复导入
+
+use std::path::Path;
+
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+
+use crate::kiro::db::Database;
+use crate::kiro::model::credentials::KiroCredentials;
+
+/// 幂等标记键前缀（完整键为前缀 + 凭据内容摘要）
+const MARKER_PREFIX: &str = "legacy_credentials_import:";
+
+/// 解析旧版凭据文件内容（支持单个对象或数组）
+pub fn parse_credentials(content: &str) -> anyhow::Result<Vec<KiroCredentials>> {
+    let value: serde_json::Value = serde_json::from_str(content)?;
+    if value.is_array() {
+        Ok(serde_json::from_value(value)?)
+    } else {
+        Ok(vec![serde_json::from_value(value)?])
+    }
+}
+
+/// 读取旧版凭据文件
+pub fn load_credentials_file(path: &Path) -> anyhow::Result<Vec<KiroCredentials>> {
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("读取凭据文件失败: {:?}", path))?;
+    parse_credentials(&content).with_context(|| format!("解析凭据文件失败: {:?}", path))
+}
+
+/// 幂等标记键（由凭据内容决定，内容变化后会再次导入，已存在的 refreshToken 仍会被跳过）
+fn marker_for(credentials: &[KiroCredentials]) -> anyhow::Result<String> {
+    let digest = Sha256::digest(serde_json::to_vec(credentials)?);
+    Ok(format!("{}{}", MARKER_PREFIX, &hex::encode(digest)[..16]))
+}
+
+/// 将旧版凭据导入数据库，返回导入的数量
+///
+/// `source` 仅用于日志
+pub fn import(
+    db: &Database,
+    credentials: &[KiroCredentials],
+    source: &str,
+) -> anyhow::Result<usize> {
+    if credentials.is_empty() {
+        return Ok(0);
+    }
+
+    let marker = marker_for(credentials)?;
+    match db.import_credentials_once(credentials, &marker)? {
+        Some((imported, skipped)) => {
+            tracing::info!(
+                "已从{}迁移旧版凭据: 导入 {} 个，跳过 {} 个（已存在或缺少 refreshToken）",
+                source,
+                imported,
+                skipped
+            );
+            if imported > 0 {
+                tracing::info!("迁移完成后可从{}中移除旧版凭据", source);
+            }
+            Ok(imported)
+        }
+        None => {
+            tracing::debug!("{}中的旧版凭据已迁移过，跳过", source);
+            Ok(0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_single_and_array() {
+        let single = parse_credentials(r#"{"refreshToken": "a", "priority": 2}"#).unwrap();
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].priority, 2);
+
+        let array = parse_credentials(
+            r#"[{"refreshToken": "a"}, {"refreshToken": "b", "authMethod": "idc", "clientId": "c"}]"#,
+        )
+        .unwrap();
+        assert_eq!(array.len(), 2);
+        assert_eq!(array[1].auth_method.as_deref(), Some("idc"));
+    }
+
+    #[test]
+    fn test_import_is_idempotent() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path().join("test.db")).unwrap();
+        let credentials = parse_credentials(
+            r#"[
+                {"refreshToken": "a", "priority": 1},
+                {"refreshToken": "b", "authMethod": "idc", "clientId": "cid", "clientSecret": "secret"},
+                {"accessToken": "missing-refresh"}
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(import(&db, &credentials, "配置文件").unwrap(), 2);
+        // 再次启动时不会重复导入
+        assert_eq!(import(&db, &credentials, "配置文件").unwrap(), 0);
+
+        let stored = db.load_credentials().unwrap();
+        assert_eq!(stored.len(), 2);
+        let a = stored
+            .iter()
+            .find(|c| c.refresh_token.as_deref() == Some("a"))
+            .unwrap();
+        assert_eq!(a.priority, 1);
+        let b = stored
+            .iter()
+            .find(|c| c.refresh_token.as_deref() == Some("b"))
+            .unwrap();
+        assert_eq!(b.auth_method.as_deref(), Some("idc"));
+        assert_eq!(b.client_secret.as_deref(), Some("secret"));
+
+        // 内容变化后再次导入，已存在的 refreshToken 被跳过
+        let mut changed = credentials.clone();
+        changed.push(KiroCredentials {
+            refresh_token: Some("c".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(import(&db, &changed, "配置文件").unwrap(), 1);
+        assert_eq!(db.load_credentials().unwrap().len(), 3);
+    }
+}
@@ -44,7 +44,7 @@ pub fn is_valid_machine_id(machine_id: &str) -> bool {
 
 /// 根据凭证信息生成唯一的 Machine ID
 ///
-/// 优先使用凭据的 machine_id，然后使用 profileArn 生成，否则使用 refreshToken 生成
+/// 优先使用凭据的 machine_id，然后使用 profileArn 生成，否则使用 refreshToken（IAM 凭据为 accessKeyId）生成
 pub fn generate_from_credentials(credentials: &KiroCredentials) -> Option<String> {
     // 如果凭据配置了 machineId 且为有效 UUID v4，优先使用
     if let Some(ref machine_id) = credentials.machine_id
@@ -73,6 +73,16 @@ pub fn generate_from_credentials(credentials: &KiroCredentials) -> Option<String
         )));
     }
 
+    // IAM 凭据没有 refreshToken，使用 accessKeyId 生成
+    if let Some(ref access_key_id) = credentials.access_key_id
+        && !access_key_id.is_empty()
+    {
+        return Some(generate_uuid_from_seed(&format!(
+            "KotlinNativeAPI/{}",
+            access_key_id
+        )));
+    }
+
     // 没有有效的凭证
     None
 }
@@ -150,8 +160,10 @@ mod tests {
 
     #[test]
     fn test_generate_with_credential_machine_id() {
-        let mut credentials = KiroCredentials::default();
-        credentials.machine_id = Some("b3981d12-4d61-418c-9b77-461db82a7cc4".to_string());
+        let credentials = KiroCredentials {
+            machine_id: Some("b3981d12-4d61-418c-9b77-461db82a7cc4".to_string()),
+            ..Default::default()
+        };
 
         let result = generate_from_credentials(&credentials);
         assert_eq!(
@@ -162,10 +174,12 @@ mod tests {
 
     #[test]
     fn test_generate_with_invalid_credential_machine_id() {
-        let mut credentials = KiroCredentials::default();
-        // 旧的 64 字符格式现在被视为无效
-        credentials.machine_id = Some("a".repeat(64));
-        credentials.profile_arn = Some("arn:aws:sso::123456789:profile/test".to_string());
+        let credentials = KiroCredentials {
+            // 旧的 64 字符格式现在被视为无效
+            machine_id: Some("a".repeat(64)),
+            profile_arn: Some("arn:aws:sso::123456789:profile/test".to_string()),
+            ..Default::default()
+        };
 
         let result = generate_from_credentials(&credentials);
         // 应该回退到使用 profileArn 生成
@@ -176,8 +190,10 @@ mod tests {
 
     #[test]
     fn test_generate_with_profile_arn() {
-        let mut credentials = KiroCredentials::default();
-        credentials.profile_arn = Some("arn:aws:sso::123456789:profile/test".to_string());
+        let credentials = KiroCredentials {
+            profile_arn: Some("arn:aws:sso::123456789:profile/test".to_string()),
+            ..Default::default()
+        };
 
         let result = generate_from_credentials(&credentials);
         assert!(result.is_some());
@@ -187,8 +203,10 @@ mod tests {
 
     #[test]
     fn test_generate_with_refresh_token() {
-        let mut credentials = KiroCredentials::default();
-        credentials.refresh_token = Some("test_refresh_token".to_string());
+        let credentials = KiroCredentials {
+            refresh_token: Some("test_refresh_token".to_string()),
+            ..Default::default()
+        };
 
         let result = generate_from_credentials(&credentials);
         assert!(result.is_some());
@@ -207,9 +225,11 @@ mod tests {
     #[test]
     fn test_credential_machine_id_priority() {
         // 凭据的 machine_id 应该优先于 profileArn
-        let mut credentials = KiroCredentials::default();
-        credentials.profile_arn = Some("arn:aws:sso::123456789:profile/test".to_string());
-        credentials.machine_id = Some("b3981d12-4d61-418c-9b77-461db82a7cc4".to_string());
+        let credentials = KiroCredentials {
+            profile_arn: Some("arn:aws:sso::123456789:profile/test".to_string()),
+            machine_id: Some("b3981d12-4d61-418c-9b77-461db82a7cc4".to_string()),
+            ..Default::default()
+        };
 
         let result = generate_from_credentials(&credentials);
         assert_eq!(
@@ -1,8 +1,27 @@
 //! Kiro API 客户端模块
 
+pub mod alert;
+pub mod balance;
+pub mod circuit_breaker;
+pub mod connections;
+pub mod credential_events;
 pub mod db;
+pub mod device_auth;
+pub mod failure_injection;
+pub mod health_check;
+pub mod latency;
+pub mod lease;
+pub mod legacy;
 pub mod machine_id;
 pub mod model;
 pub mod parser;
 pub mod provider;
+pub mod refresh_lock;
+pub mod replication;
+pub mod retention;
+pub mod retry;
+pub mod sigv4;
+pub mod stats;
 pub mod token_manager;
+pub mod transcript;
+pub mod version;
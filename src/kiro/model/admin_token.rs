@@ -0,0 +1,147 @@
+//! 受限 Admin Token 类型定义
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// 生成的 Admin Token 前缀
+const TOKEN_PREFIX: &str = "kiro-admin-";
+
+/// Admin 权限范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum AdminScope {
+    /// 查看凭据状态、余额与后台任务进度，订阅凭据状态推送
+    #[serde(rename = "credentials:read")]
+    CredentialsRead,
+    /// 添加、删除、修改、导入导出凭据
+    #[serde(rename = "credentials:write")]
+    CredentialsWrite,
+    /// 查看请求日志、用量、运行指标、统计摘要与告警通知
+    #[serde(rename = "stats:read")]
+    StatsRead,
+    /// 查看运行配置、刷新锁与提示词模板
+    #[serde(rename = "config:read")]
+    ConfigRead,
+    /// 修改提示词模板、释放刷新锁、重放告警通知
+    #[serde(rename = "config:write")]
+    ConfigWrite,
+}
+
+impl AdminScope {
+    /// 全部权限范围
+    pub const ALL: &[AdminScope] = &[
+        AdminScope::CredentialsRead,
+        AdminScope::CredentialsWrite,
+        AdminScope::StatsRead,
+        AdminScope::ConfigRead,
+        AdminScope::ConfigWrite,
+    ];
+
+    /// 权限范围名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AdminScope::CredentialsRead => "credentials:read",
+            AdminScope::CredentialsWrite => "credentials:write",
+            AdminScope::StatsRead => "stats:read",
+            AdminScope::ConfigRead => "config:read",
+            AdminScope::ConfigWrite => "config:write",
+        }
+    }
+
+    /// 解析权限范围名称
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|scope| scope.as_str() == value)
+    }
+}
+
+/// 受限 Admin Token
+///
+/// 由主 Admin API Key 签发，只能访问权限范围内的端点；
+/// 数据库仅保存 Token 的 SHA-256 哈希，明文只在创建时返回一次
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminToken {
+    /// Token ID
+    pub id: u64,
+    /// 名称（如使用的脚本）
+    pub name: String,
+    /// Token 的 SHA-256 哈希（十六进制）
+    #[serde(skip)]
+    pub token_hash: String,
+    /// 权限范围
+    pub scopes: Vec<AdminScope>,
+    /// 过期时间（None 表示永不过期）
+    pub expires_at: Option<DateTime<Utc>>,
+    /// 是否已吊销
+    pub revoked: bool,
+    /// 创建时间
+    pub created_at: DateTime<Utc>,
+}
+
+impl AdminToken {
+    /// 生成新的明文 Token
+    pub fn generate() -> String {
+        format!("{}{}", TOKEN_PREFIX, uuid::Uuid::new_v4().simple())
+    }
+
+    /// 计算 Token 的 SHA-256 哈希（十六进制）
+    pub fn hash(token: &str) -> String {
+        hex::encode(Sha256::digest(token.as_bytes()))
+    }
+
+    /// Token 是否可用（未吊销且未过期）
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        !self.revoked && self.expires_at.is_none_or(|expires_at| now < expires_at)
+    }
+
+    /// Token 是否拥有指定的权限范围
+    pub fn has_scope(&self, scope: AdminScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_names() {
+        for scope in AdminScope::ALL {
+            assert_eq!(AdminScope::parse(scope.as_str()), Some(*scope));
+            assert_eq!(
+                serde_json::to_value(scope).unwrap(),
+                serde_json::json!(scope.as_str())
+            );
+        }
+        assert_eq!(AdminScope::parse("credentials:delete"), None);
+    }
+
+    #[test]
+    fn test_is_active_and_scopes() {
+        let now = Utc::now();
+        let token = AdminToken::generate();
+        assert!(token.starts_with(TOKEN_PREFIX));
+
+        let mut token = AdminToken {
+            id: 1,
+            name: "backup".to_string(),
+            token_hash: AdminToken::hash(&token),
+            scopes: vec![AdminScope::CredentialsRead],
+            expires_at: Some(now + chrono::Duration::hours(1)),
+            revoked: false,
+            created_at: now,
+        };
+        assert!(token.is_active(now));
+        assert!(token.has_scope(AdminScope::CredentialsRead));
+        assert!(!token.has_scope(AdminScope::CredentialsWrite));
+
+        token.expires_at = Some(now);
+        assert!(!token.is_active(now));
+        token.expires_at = None;
+        token.revoked = true;
+        assert!(!token.is_active(now));
+    }
+}
@@ -0,0 +1,109 @@
+//! 客户端 API Key 类型定义
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use super::credentials::model_allowed;
+
+/// 生成的 API Key 前缀
+const KEY_PREFIX: &str = "sk-kiro-";
+
+/// 客户端 API Key
+///
+/// 数据库仅保存 Key 的 SHA-256 哈希，明文只在创建时返回一次
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKey {
+    /// Key ID
+    pub id: u64,
+    /// 名称（如使用者）
+    pub name: String,
+    /// Key 的 SHA-256 哈希（十六进制）
+    #[serde(skip)]
+    pub key_hash: String,
+    /// Key 指纹（与请求日志中的 `clientKey` 一致）
+    pub fingerprint: String,
+    /// 允许使用的模型（支持 `*` 结尾的前缀匹配，None 表示不限制）
+    pub allowed_models: Option<Vec<String>>,
+    /// 每分钟最大请求数（None 表示不限制）
+    pub rate_limit_per_minute: Option<u32>,
+    /// 过期时间（None 表示永不过期）
+    pub expires_at: Option<DateTime<Utc>>,
+    /// 是否已吊销
+    pub revoked: bool,
+    /// 创建时间
+    pub created_at: DateTime<Utc>,
+}
+
+impl ApiKey {
+    /// 生成新的明文 Key
+    pub fn generate() -> String {
+        format!("{}{}", KEY_PREFIX, uuid::Uuid::new_v4().simple())
+    }
+
+    /// 计算 Key 的 SHA-256 哈希（十六进制）
+    pub fn hash(key: &str) -> String {
+        hex::encode(Sha256::digest(key.as_bytes()))
+    }
+
+    /// 由哈希得到 Key 指纹（哈希前 16 位，同 `auth::key_fingerprint`）
+    pub fn fingerprint_of(key_hash: &str) -> String {
+        key_hash.chars().take(16).collect()
+    }
+
+    /// Key 是否可用（未吊销且未过期）
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        !self.revoked && self.expires_at.is_none_or(|expires_at| now < expires_at)
+    }
+
+    /// Key 是否允许使用指定的模型（按客户端请求的模型名匹配）
+    pub fn allows_model(&self, model: &str) -> bool {
+        model_allowed(self.allowed_models.as_deref(), model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::auth::key_fingerprint;
+
+    fn api_key(key: &str) -> ApiKey {
+        let key_hash = ApiKey::hash(key);
+        ApiKey {
+            id: 1,
+            name: "alice".to_string(),
+            fingerprint: ApiKey::fingerprint_of(&key_hash),
+            key_hash,
+            allowed_models: Some(vec!["claude-sonnet-*".to_string()]),
+            rate_limit_per_minute: None,
+            expires_at: None,
+            revoked: false,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_generate_and_fingerprint() {
+        let key = ApiKey::generate();
+        assert!(key.starts_with(KEY_PREFIX));
+        assert_ne!(key, ApiKey::generate());
+        assert_eq!(api_key(&key).fingerprint, key_fingerprint(&key));
+    }
+
+    #[test]
+    fn test_is_active_and_allows_model() {
+        let now = Utc::now();
+        let mut key = api_key("sk-kiro-test");
+        assert!(key.is_active(now));
+        assert!(key.allows_model("claude-sonnet-4-20250514"));
+        assert!(!key.allows_model("claude-opus-4-20250514"));
+
+        key.expires_at = Some(now);
+        assert!(!key.is_active(now));
+        key.expires_at = Some(now + chrono::Duration::hours(1));
+        assert!(key.is_active(now));
+        key.revoked = true;
+        assert!(!key.is_active(now));
+    }
+}
@@ -2,7 +2,14 @@
 //!
 //! 凭证存储在 SQLite 数据库中
 
-use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::kiro::version;
+use crate::model::config::Config;
 
 /// Kiro OAuth 凭证
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -24,11 +31,17 @@ pub struct KiroCredentials {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub profile_arn: Option<String>,
 
-    /// 过期时间 (RFC3339 格式)
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// 过期时间 (RFC3339 格式，入库时统一规范化为 UTC，见 [`normalize_expires_at`])
+    ///
+    /// 反序列化时也接受 Unix 时间戳数字（秒或毫秒）
+    #[serde(
+        default,
+        deserialize_with = "deserialize_expires_at",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub expires_at: Option<String>,
 
-    /// 认证方式 (social / idc / builder-id)
+    /// 认证方式 (social / idc / builder-id / iam)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub auth_method: Option<String>,
 
@@ -40,10 +53,46 @@ pub struct KiroCredentials {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub client_secret: Option<String>,
 
+    /// IAM Access Key ID（iam 认证需要）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_key_id: Option<String>,
+
+    /// IAM Secret Access Key（iam 认证需要）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_access_key: Option<String>,
+
+    /// IAM 临时凭据的会话令牌（可选）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_token: Option<String>,
+
+    /// OIDC 区域（设备授权登录时指定；为空时使用全局配置 region，仅用于 IdC 凭据刷新 Token）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+
     /// 设备指纹（UUID v4 格式）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub machine_id: Option<String>,
 
+    /// Kiro IDE 版本覆盖（为空时使用全局配置 kiroVersion）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kiro_version: Option<String>,
+
+    /// 系统版本覆盖（为空时使用全局配置 systemVersion）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_version: Option<String>,
+
+    /// Node.js 版本覆盖（为空时使用全局配置 nodeVersion）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_version: Option<String>,
+
+    /// 允许使用的模型（Kiro 模型 ID，支持 `*` 后缀通配；为空表示不限制）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_models: Option<Vec<String>>,
+
+    /// 附加的上游请求头（合并到对话、Token 刷新与额度查询请求，同名时覆盖默认值）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extra_headers: Option<BTreeMap<String, String>>,
+
     /// 凭据优先级（数字越小优先级越高，默认为 0）
     #[serde(default)]
     #[serde(skip_serializing_if = "is_zero")]
@@ -84,7 +133,384 @@ pub struct KiroCredentials {
     pub email: Option<String>,
 }
 
+/// 模型是否在允许列表中
+///
+/// 未配置或配置为空列表时不限制；条目以 `*` 结尾时按前缀匹配，忽略大小写
+pub fn model_allowed(allowed: Option<&[String]>, model_id: &str) -> bool {
+    let Some(allowed) = allowed.filter(|m| !m.is_empty()) else {
+        return true;
+    };
+    let model_id = model_id.to_ascii_lowercase();
+    allowed.iter().any(|pattern| {
+        let pattern = pattern.to_ascii_lowercase();
+        match pattern.strip_suffix('*') {
+            Some(prefix) => model_id.starts_with(prefix),
+            None => model_id == pattern,
+        }
+    })
+}
+
+/// 不允许通过自定义请求头覆盖的请求头（由服务自身设置）
+const PROTECTED_HEADERS: &[&str] = &[
+    "authorization",
+    "host",
+    "content-type",
+    "content-length",
+    "transfer-encoding",
+];
+
+/// 校验并规范化自定义请求头（名称转为小写；空表为 None）
+pub fn normalize_extra_headers(
+    headers: Option<BTreeMap<String, String>>,
+) -> Result<Option<BTreeMap<String, String>>, String> {
+    let Some(headers) = headers.filter(|h| !h.is_empty()) else {
+        return Ok(None);
+    };
+    let mut normalized = BTreeMap::new();
+    for (name, value) in headers {
+        let name = name.trim().to_ascii_lowercase();
+        if HeaderName::from_bytes(name.as_bytes()).is_err() {
+            return Err(format!("无效的请求头名称: {:?}", name));
+        }
+        if PROTECTED_HEADERS.contains(&name.as_str()) {
+            return Err(format!("请求头 {} 由服务设置，不能自定义", name));
+        }
+        if HeaderValue::from_str(&value).is_err() {
+            return Err(format!("请求头 {} 的值包含无效字符", name));
+        }
+        normalized.insert(name, value);
+    }
+    Ok(Some(normalized))
+}
+
+impl KiroCredentials {
+    /// 是否为 IAM 访问密钥凭据（请求使用 SigV4 签名而非 Bearer Token，无需刷新）
+    pub fn is_iam(&self) -> bool {
+        self.auth_method
+            .as_deref()
+            .is_some_and(|m| m.eq_ignore_ascii_case("iam"))
+    }
+
+    /// 凭据是否允许使用指定的 Kiro 模型
+    ///
+    /// 未配置或配置为空列表时不限制；条目以 `*` 结尾时按前缀匹配，忽略大小写
+    pub fn allows_model(&self, model_id: &str) -> bool {
+        model_allowed(self.allowed_models.as_deref(), model_id)
+    }
+
+    /// 凭据的自定义上游请求头（跳过无效条目；写入时已校验，此处仅防御旧数据）
+    pub fn extra_header_map(&self) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in self.extra_headers.iter().flatten() {
+            match (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                (Ok(name), Ok(value)) => {
+                    map.insert(name, value);
+                }
+                _ => tracing::warn!("凭据 #{:?} 的自定义请求头 {} 无效，已忽略", self.id, name),
+            }
+        }
+        map
+    }
+
+    /// 缓存的剩余额度是否低于阈值（额度已在 `next_reset_at` 重置时视为充足）
+    ///
+    /// 尚未查询过余额（`usage_limit` 为 0）的凭据不视为耗尽
+    pub fn is_quota_exhausted(&self, threshold: f64, now: DateTime<Utc>) -> bool {
+        if self.usage_limit <= 0.0 || self.usage_limit - self.current_usage >= threshold {
+            return false;
+        }
+        self.next_reset_at
+            .is_none_or(|reset_at| (now.timestamp() as f64) < reset_at)
+    }
+
+    /// 凭据利用率（0~1）
+    ///
+    /// 已禁用视为 1；按缓存的余额计算，尚未查询余额或已过额度重置时间时视为 0
+    pub fn utilization(&self, now: DateTime<Utc>) -> f64 {
+        if self.disabled {
+            return 1.0;
+        }
+        let reset = self
+            .next_reset_at
+            .is_some_and(|reset_at| (now.timestamp() as f64) >= reset_at);
+        if self.usage_limit <= 0.0 || reset {
+            return 0.0;
+        }
+        (self.current_usage / self.usage_limit).clamp(0.0, 1.0)
+    }
+
+    /// 获取生效的 Kiro IDE 版本
+    ///
+    /// 优先级：凭据覆盖 > 自动检测到的最新版本 > 全局配置
+    pub fn effective_kiro_version(&self, config: &Config) -> String {
+        self.kiro_version
+            .clone()
+            .or_else(version::detected_version)
+            .unwrap_or_else(|| config.kiro_version.clone())
+    }
+
+    /// 获取生效的系统版本（凭据覆盖优先于全局配置）
+    pub fn effective_system_version<'a>(&'a self, config: &'a Config) -> &'a str {
+        self.system_version
+            .as_deref()
+            .unwrap_or(&config.system_version)
+    }
+
+    /// 获取生效的 Node.js 版本（凭据覆盖优先于全局配置）
+    pub fn effective_node_version<'a>(&'a self, config: &'a Config) -> &'a str {
+        self.node_version.as_deref().unwrap_or(&config.node_version)
+    }
+}
+
+/// 时间戳数值大于该值时按毫秒解析（对应 5138 年，秒级时间戳不会达到）
+const EPOCH_MILLIS_THRESHOLD: i64 = 100_000_000_000;
+
+/// 将过期时间规范化为 UTC RFC3339（如 `2025-01-01T08:00:00Z`）
+///
+/// 接受：
+/// - 任意时区偏移的 RFC3339（`2025-01-01T16:00:00+08:00`）
+/// - 不带时区的日期时间（`2025-01-01 08:00:00`，按 UTC 处理，与 SQLite `CURRENT_TIMESTAMP` 一致）
+/// - Unix 时间戳（秒或毫秒，允许小数）
+///
+/// 无法解析时返回 None
+pub fn normalize_expires_at(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return None;
+    }
+
+    let parsed = if let Ok(dt) = DateTime::parse_from_rfc3339(raw) {
+        Some(dt.with_timezone(&Utc))
+    } else if let Ok(epoch) = raw.parse::<i64>() {
+        if epoch.abs() >= EPOCH_MILLIS_THRESHOLD {
+            DateTime::from_timestamp_millis(epoch)
+        } else {
+            DateTime::from_timestamp(epoch, 0)
+        }
+    } else if let Ok(epoch) = raw.parse::<f64>() {
+        let millis = if epoch.abs() >= EPOCH_MILLIS_THRESHOLD as f64 {
+            epoch
+        } else {
+            epoch * 1000.0
+        };
+        millis
+            .is_finite()
+            .then(|| DateTime::from_timestamp_millis(millis.round() as i64))
+            .flatten()
+    } else {
+        ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+            .iter()
+            .find_map(|fmt| NaiveDateTime::parse_from_str(raw, fmt).ok())
+            .map(|naive| naive.and_utc())
+    };
+
+    parsed.map(|dt| dt.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+/// 反序列化过期时间：兼容字符串与 Unix 时间戳数字
+fn deserialize_expires_at<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawExpiresAt {
+        Text(String),
+        Integer(i64),
+        Float(f64),
+    }
+
+    Ok(
+        Option::<RawExpiresAt>::deserialize(deserializer)?.map(|raw| match raw {
+            RawExpiresAt::Text(text) => text,
+            RawExpiresAt::Integer(epoch) => epoch.to_string(),
+            RawExpiresAt::Float(epoch) => epoch.to_string(),
+        }),
+    )
+}
+
 /// 判断是否为零（用于跳过序列化）
 fn is_zero(value: &u32) -> bool {
     *value == 0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_versions_fallback_to_config() {
+        let config = Config {
+            kiro_version: "0.8.0".to_string(),
+            system_version: "darwin#24.6.0".to_string(),
+            node_version: "22.21.1".to_string(),
+            ..Default::default()
+        };
+        let credentials = KiroCredentials::default();
+
+        assert_eq!(credentials.effective_kiro_version(&config), "0.8.0");
+        assert_eq!(
+            credentials.effective_system_version(&config),
+            "darwin#24.6.0"
+        );
+        assert_eq!(credentials.effective_node_version(&config), "22.21.1");
+    }
+
+    #[test]
+    fn test_effective_versions_prefer_credential_override() {
+        let config = Config::default();
+        let credentials = KiroCredentials {
+            kiro_version: Some("0.9.2".to_string()),
+            system_version: Some("win32#10.0.22631".to_string()),
+            node_version: Some("20.18.0".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(credentials.effective_kiro_version(&config), "0.9.2");
+        assert_eq!(
+            credentials.effective_system_version(&config),
+            "win32#10.0.22631"
+        );
+        assert_eq!(credentials.effective_node_version(&config), "20.18.0");
+    }
+
+    #[test]
+    fn test_allows_model() {
+        let unrestricted = KiroCredentials::default();
+        assert!(unrestricted.allows_model("claude-opus-4.5"));
+
+        let free_tier = KiroCredentials {
+            allowed_models: Some(vec![
+                "claude-sonnet-*".to_string(),
+                "Claude-Haiku-4.5".to_string(),
+            ]),
+            ..Default::default()
+        };
+        assert!(free_tier.allows_model("claude-sonnet-4.5"));
+        assert!(free_tier.allows_model("claude-haiku-4.5"));
+        assert!(!free_tier.allows_model("claude-opus-4.5"));
+
+        let empty = KiroCredentials {
+            allowed_models: Some(vec![]),
+            ..Default::default()
+        };
+        assert!(empty.allows_model("claude-opus-4.5"));
+    }
+
+    #[test]
+    fn test_extra_headers() {
+        assert_eq!(normalize_extra_headers(None), Ok(None));
+        assert_eq!(normalize_extra_headers(Some(BTreeMap::new())), Ok(None));
+        assert!(
+            normalize_extra_headers(Some(BTreeMap::from([(
+                "Authorization".to_string(),
+                "Bearer x".to_string()
+            )])))
+            .is_err()
+        );
+        assert!(
+            normalize_extra_headers(Some(BTreeMap::from([(
+                "bad header".to_string(),
+                "x".to_string()
+            )])))
+            .is_err()
+        );
+        assert!(
+            normalize_extra_headers(Some(BTreeMap::from([(
+                "x-egress-token".to_string(),
+                "line\nbreak".to_string()
+            )])))
+            .is_err()
+        );
+
+        let headers = normalize_extra_headers(Some(BTreeMap::from([(
+            " X-Egress-Token ".to_string(),
+            "secret".to_string(),
+        )])))
+        .unwrap();
+        let cred = KiroCredentials {
+            extra_headers: headers,
+            ..Default::default()
+        };
+        let map = cred.extra_header_map();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map["x-egress-token"], "secret");
+        assert!(KiroCredentials::default().extra_header_map().is_empty());
+    }
+
+    #[test]
+    fn test_normalize_expires_at() {
+        assert_eq!(
+            normalize_expires_at("2025-01-01T16:00:00+08:00").as_deref(),
+            Some("2025-01-01T08:00:00Z")
+        );
+        assert_eq!(
+            normalize_expires_at("2025-01-01T08:00:00.123456+00:00").as_deref(),
+            Some("2025-01-01T08:00:00.123456Z")
+        );
+        assert_eq!(
+            normalize_expires_at("2025-01-01 08:00:00").as_deref(),
+            Some("2025-01-01T08:00:00Z")
+        );
+        // 秒、毫秒与小数秒时间戳
+        assert_eq!(
+            normalize_expires_at("1735718400").as_deref(),
+            Some("2025-01-01T08:00:00Z")
+        );
+        assert_eq!(
+            normalize_expires_at("1735718400000").as_deref(),
+            Some("2025-01-01T08:00:00Z")
+        );
+        assert_eq!(
+            normalize_expires_at("1735718400.5").as_deref(),
+            Some("2025-01-01T08:00:00.500Z")
+        );
+        assert_eq!(normalize_expires_at(""), None);
+        assert_eq!(normalize_expires_at("tomorrow"), None);
+    }
+
+    #[test]
+    fn test_deserialize_epoch_expires_at() {
+        let cred: KiroCredentials =
+            serde_json::from_str(r#"{"refreshToken":"r","expiresAt":1735718400000}"#).unwrap();
+        assert_eq!(cred.expires_at.as_deref(), Some("1735718400000"));
+
+        let cred: KiroCredentials =
+            serde_json::from_str(r#"{"refreshToken":"r","expiresAt":null}"#).unwrap();
+        assert!(cred.expires_at.is_none());
+
+        let cred: KiroCredentials = serde_json::from_str(r#"{"refreshToken":"r"}"#).unwrap();
+        assert!(cred.expires_at.is_none());
+    }
+
+    #[test]
+    fn test_is_quota_exhausted() {
+        let now = Utc::now();
+        let cred = KiroCredentials {
+            current_usage: 95.0,
+            usage_limit: 100.0,
+            next_reset_at: Some((now.timestamp() + 3600) as f64),
+            ..Default::default()
+        };
+        assert!(cred.is_quota_exhausted(10.0, now));
+        assert!(!cred.is_quota_exhausted(5.0, now));
+
+        // 重置时间已过，额度视为已恢复
+        let reset = KiroCredentials {
+            next_reset_at: Some((now.timestamp() - 1) as f64),
+            ..cred.clone()
+        };
+        assert!(!reset.is_quota_exhausted(10.0, now));
+
+        // 未知重置时间时保持跳过；尚未查询余额时不跳过
+        let unknown = KiroCredentials {
+            next_reset_at: None,
+            ..cred
+        };
+        assert!(unknown.is_quota_exhausted(10.0, now));
+        assert!(!KiroCredentials::default().is_quota_exhausted(10.0, now));
+    }
+}
@@ -0,0 +1,52 @@
+//! 凭据健康检查记录类型定义
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// 健康检查触发方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthCheckTrigger {
+    /// 通过 Admin API 手动触发
+    Manual,
+    /// 后台定时探测
+    Scheduled,
+}
+
+impl HealthCheckTrigger {
+    /// 数据库中保存的值
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HealthCheckTrigger::Manual => "manual",
+            HealthCheckTrigger::Scheduled => "scheduled",
+        }
+    }
+
+    /// 解析数据库中保存的值（未知值视为定时探测）
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "manual" => HealthCheckTrigger::Manual,
+            _ => HealthCheckTrigger::Scheduled,
+        }
+    }
+}
+
+/// 一次健康检查的结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthCheck {
+    /// 记录 ID（尚未写入数据库时为 0）
+    pub id: u64,
+    /// 凭据 ID
+    pub credential_id: u64,
+    /// 检查时间
+    pub checked_at: DateTime<Utc>,
+    /// 上游调用是否成功
+    pub healthy: bool,
+    /// 上游调用耗时（毫秒，包含必要的 Token 刷新）
+    pub latency_ms: u64,
+    /// 失败原因
+    pub error: Option<String>,
+    /// 触发方式
+    pub trigger: HealthCheckTrigger,
+}
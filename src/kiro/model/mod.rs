@@ -1,16 +1,34 @@
 //! Kiro 数据模型
 //!
 //! 包含 Kiro API 的所有数据类型定义：
+//! - `admin_token`: 受限 Admin Token
+//! - `api_key`: 客户端 API Key
 //! - `common`: 共享类型（枚举和辅助结构体）
 //! - `events`: 响应事件类型
 //! - `requests`: 请求类型
 //! - `credentials`: OAuth 凭证
+//! - `health_check`: 凭据健康检查记录
+//! - `notification`: 告警通知投递队列
+//! - `prompt_template`: 提示词模板
+//! - `request_log`: 请求日志
+//! - `stats`: 统计摘要
 //! - `token_refresh`: Token 刷新
+//! - `transcript`: 对话记录
 //! - `usage_limits`: 使用额度查询
+//! - `usage_log`: 用量记录
 
+pub mod admin_token;
+pub mod api_key;
 pub mod common;
 pub mod credentials;
 pub mod events;
+pub mod health_check;
+pub mod notification;
+pub mod prompt_template;
+pub mod request_log;
 pub mod requests;
+pub mod stats;
 pub mod token_refresh;
+pub mod transcript;
 pub mod usage_limits;
+pub mod usage_log;
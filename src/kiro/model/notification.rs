@@ -0,0 +1,62 @@
+//! 告警通知投递队列类型定义
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// 通知投递状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationStatus {
+    /// 等待投递（包括等待重试）
+    Pending,
+    /// 已投递
+    Delivered,
+    /// 重试次数耗尽，等待人工重放
+    Dead,
+}
+
+impl NotificationStatus {
+    /// 数据库中保存的值
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NotificationStatus::Pending => "pending",
+            NotificationStatus::Delivered => "delivered",
+            NotificationStatus::Dead => "dead",
+        }
+    }
+
+    /// 解析数据库中保存的值（未知值视为等待投递）
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "delivered" => NotificationStatus::Delivered,
+            "dead" => NotificationStatus::Dead,
+            _ => NotificationStatus::Pending,
+        }
+    }
+}
+
+/// 待投递（或已投递）的告警通知
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Notification {
+    /// 通知 ID
+    pub id: u64,
+    /// 事件类型（如 `credential_disabled`）
+    pub kind: String,
+    /// 标题
+    pub subject: String,
+    /// 正文
+    pub body: String,
+    /// 投递状态
+    pub status: NotificationStatus,
+    /// 已尝试投递的次数
+    pub attempts: u32,
+    /// 下次尝试投递的时间
+    pub next_attempt_at: DateTime<Utc>,
+    /// 最近一次投递失败的原因
+    pub last_error: Option<String>,
+    /// 创建时间
+    pub created_at: DateTime<Utc>,
+    /// 投递成功时间
+    pub delivered_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,98 @@
+//! 提示词模板类型定义
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// 命名提示词模板
+///
+/// 内容中的 `{{变量名}}` 会在使用时替换为客户端传入的变量值
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptTemplate {
+    /// 模板名称（唯一）
+    pub name: String,
+    /// 模板内容
+    pub content: String,
+    /// 描述
+    pub description: Option<String>,
+    /// 最后更新时间
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PromptTemplate {
+    /// 校验模板名称（1-64 个字母、数字、`-`、`_` 或 `.`）
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && name.len() <= 64
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    }
+
+    /// 替换模板变量
+    ///
+    /// 变量名两侧允许空白（`{{ name }}`）；模板引用了未提供的变量时返回错误
+    pub fn render(&self, variables: &HashMap<String, String>) -> Result<String, String> {
+        let mut output = String::with_capacity(self.content.len());
+        let mut rest = self.content.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start + 2..].find("}}") else {
+                break;
+            };
+            let name = rest[start + 2..start + 2 + len].trim();
+            let value = variables
+                .get(name)
+                .ok_or_else(|| format!("模板 {} 缺少变量: {}", self.name, name))?;
+            output.push_str(&rest[..start]);
+            output.push_str(value);
+            rest = &rest[start + 2 + len + 2..];
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(content: &str) -> PromptTemplate {
+        PromptTemplate {
+            name: "test".to_string(),
+            content: content.to_string(),
+            description: None,
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let variables = HashMap::from([
+            ("project".to_string(), "kiro-rs".to_string()),
+            ("lang".to_string(), "Rust".to_string()),
+        ]);
+        assert_eq!(
+            template("You work on {{project}} in {{ lang }}.")
+                .render(&variables)
+                .unwrap(),
+            "You work on kiro-rs in Rust."
+        );
+        // 未闭合的占位符原样保留
+        assert_eq!(
+            template("{{project}} {{ oops").render(&variables).unwrap(),
+            "kiro-rs {{ oops"
+        );
+        assert!(template("{{missing}}").render(&variables).is_err());
+    }
+
+    #[test]
+    fn test_is_valid_name() {
+        assert!(PromptTemplate::is_valid_name("coding-agent_v1.2"));
+        assert!(!PromptTemplate::is_valid_name(""));
+        assert!(!PromptTemplate::is_valid_name("has space"));
+        assert!(!PromptTemplate::is_valid_name(&"a".repeat(65)));
+    }
+}
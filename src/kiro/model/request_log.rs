@@ -0,0 +1,67 @@
+//! 请求日志类型定义
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// 单条请求日志
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestLog {
+    /// 日志 ID（写入前为 None）
+    pub id: Option<u64>,
+    /// 请求开始时间
+    pub created_at: DateTime<Utc>,
+    /// 请求的模型
+    pub model: String,
+    /// 实际使用的凭据 ID（未到达上游时为 None）
+    pub credential_id: Option<u64>,
+    /// 返回给客户端的 HTTP 状态码
+    pub status: u16,
+    /// 客户端 API Key 指纹（不保存明文）
+    pub client_key: Option<String>,
+    /// 延迟（毫秒，流式请求为首字节延迟）
+    pub latency_ms: u64,
+    /// 是否为流式请求
+    pub stream: bool,
+    /// 错误信息（仅失败请求）
+    pub error: Option<String>,
+    /// 请求标签（`x-kiro-tag` 请求头）
+    pub tag: Option<String>,
+    /// 请求指定的采样种子（`seed` 扩展字段）
+    pub seed: Option<i64>,
+    /// 请求 ID（`x-request-id`）
+    pub request_id: Option<String>,
+    /// 流式响应已发送的 SSE 事件数（即最后一个事件的 `id`，非流式请求为 None）
+    pub sse_events: Option<u64>,
+}
+
+/// 请求日志查询条件
+#[derive(Debug, Clone, Default)]
+pub struct RequestLogFilter {
+    /// 起始时间（包含）
+    pub from: Option<DateTime<Utc>>,
+    /// 结束时间（不包含）
+    pub to: Option<DateTime<Utc>>,
+    /// 模型（精确匹配）
+    pub model: Option<String>,
+    /// 凭据 ID
+    pub credential_id: Option<u64>,
+    /// HTTP 状态码
+    pub status: Option<u16>,
+    /// 客户端 API Key 指纹
+    pub client_key: Option<String>,
+    /// 最小延迟（毫秒）
+    pub min_latency_ms: Option<u64>,
+    /// 错误信息关键字（子串匹配）
+    pub query: Option<String>,
+    /// 请求标签（精确匹配）
+    pub tag: Option<String>,
+    /// 采样种子
+    pub seed: Option<i64>,
+    /// 请求 ID（精确匹配）
+    pub request_id: Option<String>,
+    /// 返回条数
+    pub limit: usize,
+    /// 偏移量
+    pub offset: usize,
+}
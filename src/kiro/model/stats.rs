@@ -0,0 +1,90 @@
+//! 统计摘要类型定义
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::usage_log::{ModelUsage, UsageTotals};
+
+/// 统计摘要（由 SQLite 聚合生成，缓存在内存中供高频查询）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsSummary {
+    /// 生成时间
+    pub refreshed_at: DateTime<Utc>,
+    /// 统计范围限定的请求标签（None 表示全部请求）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// 凭据总数
+    pub credentials_total: u64,
+    /// 可用凭据数量（未禁用）
+    pub credentials_available: u64,
+    /// 请求日志总数
+    pub requests_total: u64,
+    /// 最近一小时的请求数
+    pub requests_last_hour: u64,
+    /// 最近一小时的失败请求数（状态码 >= 400）
+    pub errors_last_hour: u64,
+    /// 最近一小时的平均延迟（毫秒）
+    pub avg_latency_ms_last_hour: f64,
+    /// 最近一小时按模型统计（按请求数倒序）
+    pub models_last_hour: Vec<ModelStats>,
+    /// 今日（UTC 零点起）统计
+    pub today: TodayStats,
+}
+
+/// 今日统计（由 `usage_log` 聚合，重启后不丢失）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TodayStats {
+    /// 统计起点（UTC 零点）
+    pub since: DateTime<Utc>,
+    /// 今日合计（请求数、失败数、tokens、平均延迟）
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+    /// 成功率（0~1，无请求时为 1）
+    pub success_rate: f64,
+    /// 按模型统计（按输出 tokens 倒序）
+    pub models: Vec<ModelUsage>,
+    /// 按凭据统计（按请求数倒序）
+    pub credentials: Vec<CredentialShare>,
+    /// 按小时统计（仅包含有请求的小时，按时间正序）
+    pub hourly: Vec<HourlyStats>,
+}
+
+/// 单个凭据的请求占比
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialShare {
+    /// 凭据 ID（None 表示未到达上游的请求）
+    pub credential_id: Option<u64>,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+    /// 占今日请求数的比例（0~1）
+    pub share: f64,
+}
+
+/// 单个小时的请求统计
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HourlyStats {
+    /// 小时起点
+    pub hour: DateTime<Utc>,
+    /// 请求数
+    pub requests: u64,
+    /// 失败请求数
+    pub errors: u64,
+    /// 输出 tokens 合计
+    pub output_tokens: u64,
+}
+
+/// 单个模型的请求统计
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelStats {
+    /// 模型
+    pub model: String,
+    /// 请求数
+    pub requests: u64,
+    /// 失败请求数
+    pub errors: u64,
+}
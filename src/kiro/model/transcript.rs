@@ -0,0 +1,63 @@
+//! 对话记录模型
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// 单次消息请求的对话记录（提示词与生成内容，已截断与脱敏）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transcript {
+    /// 记录 ID（SQLite 存储写入后分配；JSONL 存储为 None）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    /// 请求开始时间
+    pub created_at: DateTime<Utc>,
+    /// 请求 ID（`x-request-id`）
+    pub request_id: Option<String>,
+    /// 请求的模型
+    pub model: String,
+    /// 实际使用的凭据 ID（未到达上游时为 None）
+    pub credential_id: Option<u64>,
+    /// 客户端 API Key 指纹
+    pub client_key: Option<String>,
+    /// 是否为流式请求
+    pub stream: bool,
+    /// 返回给客户端的 HTTP 状态码
+    pub status: u16,
+    /// 总耗时（毫秒，流式请求为流结束或客户端断开的时间）
+    pub latency_ms: u64,
+    /// 请求内容：`system`、`messages` 与工具名称
+    pub request: serde_json::Value,
+    /// 响应内容：成功时为 `content` 内容块数组，失败时为错误响应体
+    pub response: serde_json::Value,
+    /// 停止原因
+    pub stop_reason: Option<String>,
+    /// 输入 tokens
+    pub input_tokens: Option<u64>,
+    /// 输出 tokens
+    pub output_tokens: Option<u64>,
+}
+
+/// 对话记录查询条件
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptFilter {
+    /// 模型（精确匹配）
+    pub model: Option<String>,
+    /// 请求 ID（精确匹配）
+    pub request_id: Option<String>,
+    /// 返回条数
+    pub limit: usize,
+    /// 偏移量
+    pub offset: usize,
+}
+
+impl TranscriptFilter {
+    /// 记录是否满足查询条件（分页除外）
+    pub fn matches(&self, transcript: &Transcript) -> bool {
+        self.model.as_ref().is_none_or(|m| *m == transcript.model)
+            && self
+                .request_id
+                .as_ref()
+                .is_none_or(|id| transcript.request_id.as_ref() == Some(id))
+    }
+}
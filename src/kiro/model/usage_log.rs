@@ -0,0 +1,103 @@
+//! 用量记录类型定义
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// 单次消息请求的用量记录
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageLog {
+    /// 请求开始时间
+    pub created_at: DateTime<Utc>,
+    /// 实际使用的凭据 ID（未到达上游时为 None）
+    pub credential_id: Option<u64>,
+    /// 请求的模型
+    pub model: String,
+    /// 请求标签（`x-kiro-tag` 请求头）
+    pub tag: Option<String>,
+    /// 输入 tokens
+    pub input_tokens: u64,
+    /// 输出 tokens
+    pub output_tokens: u64,
+    /// 总耗时（毫秒，流式请求为整个流的耗时）
+    pub latency_ms: u64,
+    /// 返回给客户端的 HTTP 状态码
+    pub status: u16,
+    /// 是否为流式请求
+    pub stream: bool,
+}
+
+/// 用量查询条件
+#[derive(Debug, Clone, Default)]
+pub struct UsageFilter {
+    /// 起始时间（包含）
+    pub from: Option<DateTime<Utc>>,
+    /// 结束时间（不包含）
+    pub to: Option<DateTime<Utc>>,
+    /// 凭据 ID
+    pub credential_id: Option<u64>,
+    /// 模型（精确匹配）
+    pub model: Option<String>,
+    /// 请求标签（精确匹配）
+    pub tag: Option<String>,
+}
+
+/// 一组请求的用量汇总
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageTotals {
+    /// 请求数
+    pub requests: u64,
+    /// 失败请求数（状态码 >= 400）
+    pub errors: u64,
+    /// 输入 tokens 合计
+    pub input_tokens: u64,
+    /// 输出 tokens 合计
+    pub output_tokens: u64,
+    /// 平均耗时（毫秒）
+    pub avg_latency_ms: f64,
+}
+
+/// 单个凭据的用量汇总
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialUsage {
+    /// 凭据 ID（None 表示未到达上游的请求）
+    pub credential_id: Option<u64>,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+/// 单个模型的用量汇总
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelUsage {
+    /// 模型
+    pub model: String,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+/// 单个请求标签的用量汇总
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagUsage {
+    /// 请求标签（None 表示未携带标签的请求）
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+/// 用量汇总报告
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageSummary {
+    /// 全部匹配请求的汇总
+    pub total: UsageTotals,
+    /// 按凭据汇总（按输出 tokens 倒序）
+    pub credentials: Vec<CredentialUsage>,
+    /// 按模型汇总（按输出 tokens 倒序）
+    pub models: Vec<ModelUsage>,
+    /// 按请求标签汇总（按输出 tokens 倒序）
+    pub tags: Vec<TagUsage>,
+}
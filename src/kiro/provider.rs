@@ -4,21 +4,52 @@
 //! 支持流式和非流式请求
 //! 支持多凭据故障转移和重试
 
-use reqwest::Client;
-use reqwest::header::{AUTHORIZATION, CONNECTION, CONTENT_TYPE, HOST, HeaderMap, HeaderValue};
+use bytes::Bytes;
+use futures::Stream;
+use reqwest::header::{CONNECTION, CONTENT_TYPE, HOST, HeaderMap, HeaderValue};
+use reqwest::{Client, StatusCode};
 use std::sync::Arc;
-use uuid::Uuid;
+use std::time::Duration;
 
+use crate::common::request_id;
 use crate::http_client::{ProxyConfig, build_client};
+use crate::kiro::connections;
+use crate::kiro::failure_injection::FailureInjector;
 use crate::kiro::machine_id;
-use crate::kiro::token_manager::{CallContext, MultiTokenManager};
+use crate::kiro::retry::RetryPolicy;
+use crate::kiro::sigv4;
+use crate::kiro::token_manager::{
+    AcquireError, CallContext, CredentialLease, MultiTokenManager, RequestPriority,
+};
 
-/// 每个凭据的最大重试次数
-const MAX_RETRIES_PER_CREDENTIAL: usize = 3;
-
-/// 总重试次数硬上限（避免无限重试）
+/// 总尝试次数硬上限（避免无限重试）
 const MAX_TOTAL_RETRIES: usize = 9;
 
+/// 上游 API 调用结果
+pub struct ApiResponse {
+    /// 实际处理请求的凭据 ID
+    pub credential_id: u64,
+    /// 上游原始 HTTP 响应
+    pub response: reqwest::Response,
+    /// 凭据租约（响应体读取结束后结算）
+    lease: CredentialLease,
+}
+
+impl ApiResponse {
+    /// 在连接跟踪任务中读取上游响应体，读取结束后结算凭据租约
+    pub fn into_body(
+        self,
+        max_lifetime: Option<Duration>,
+    ) -> impl Stream<Item = anyhow::Result<Bytes>> + Send + 'static {
+        connections::track(
+            self.credential_id,
+            self.response.bytes_stream(),
+            max_lifetime,
+            Some(self.lease),
+        )
+    }
+}
+
 /// Kiro API Provider
 ///
 /// 核心组件，负责与 Kiro API 通信
@@ -26,6 +57,10 @@ const MAX_TOTAL_RETRIES: usize = 9;
 pub struct KiroProvider {
     token_manager: Arc<MultiTokenManager>,
     client: Client,
+    /// 预发布环境的故障注入（未开启 staging 时为 None）
+    failure_injector: Option<FailureInjector>,
+    /// 替换后的上游地址（压测 mock 模式使用，None 表示按 region 访问 Kiro API）
+    upstream_url: Option<String>,
 }
 
 impl KiroProvider {
@@ -40,20 +75,31 @@ impl KiroProvider {
         let client = build_client(proxy.as_ref(), 720) // 12 分钟超时
             .expect("创建 HTTP 客户端失败");
 
+        let failure_injector = FailureInjector::from_config(token_manager.config());
         Self {
             token_manager,
             client,
+            failure_injector,
+            upstream_url: None,
         }
     }
 
+    /// 替换上游地址（见 `bench::spawn_mock`）
+    pub fn with_upstream_url(mut self, url: impl Into<String>) -> Self {
+        self.upstream_url = Some(url.into());
+        self
+    }
+
     /// 获取 token_manager 的引用
-    #[allow(dead_code)]
-    pub fn token_manager(&self) -> &MultiTokenManager {
+    pub fn token_manager(&self) -> &Arc<MultiTokenManager> {
         &self.token_manager
     }
 
     /// 获取 API 基础 URL
     pub fn base_url(&self) -> String {
+        if let Some(url) = &self.upstream_url {
+            return url.clone();
+        }
         format!(
             "https://q.{}.amazonaws.com/generateAssistantResponse",
             self.token_manager.config().region
@@ -69,21 +115,27 @@ impl KiroProvider {
     ///
     /// # Arguments
     /// * `ctx` - API 调用上下文，包含凭据和 token
-    fn build_headers(&self, ctx: &CallContext) -> anyhow::Result<HeaderMap> {
+    /// * `url` - 请求 URL（IAM 凭据签名需要）
+    /// * `body` - 请求体（IAM 凭据签名需要）
+    fn build_headers(&self, ctx: &CallContext, url: &str, body: &str) -> anyhow::Result<HeaderMap> {
         let config = self.token_manager.config();
 
         let machine_id = machine_id::generate_from_credentials(&ctx.credentials)
             .ok_or_else(|| anyhow::anyhow!("无法生成 machine_id，请检查凭证配置"))?;
 
-        let kiro_version = &config.kiro_version;
-        let os_name = &config.system_version;
-        let node_version = &config.node_version;
+        let kiro_version = ctx.credentials.effective_kiro_version(config);
+        let os_name = ctx.credentials.effective_system_version(config);
+        let node_version = ctx.credentials.effective_node_version(config);
+        let sdk_version = &config.aws_sdk_version;
 
-        let x_amz_user_agent = format!("aws-sdk-js/1.0.27 KiroIDE-{}-{}", kiro_version, machine_id);
+        let x_amz_user_agent = format!(
+            "aws-sdk-js/{} KiroIDE-{}-{}",
+            sdk_version, kiro_version, machine_id
+        );
 
         let user_agent = format!(
-            "aws-sdk-js/1.0.27 ua/2.1 os/{} lang/js md/nodejs#{} api/codewhispererstreaming#1.0.27 m/E KiroIDE-{}-{}",
-            os_name, node_version, kiro_version, machine_id
+            "aws-sdk-js/{} ua/2.1 os/{} lang/js md/nodejs#{} api/codewhispererstreaming#{} m/E KiroIDE-{}-{}",
+            sdk_version, os_name, node_version, sdk_version, kiro_version, machine_id
         );
 
         let mut headers = HeaderMap::new();
@@ -105,17 +157,25 @@ impl KiroProvider {
         headers.insert(HOST, HeaderValue::from_str(&self.base_domain()).unwrap());
         headers.insert(
             "amz-sdk-invocation-id",
-            HeaderValue::from_str(&Uuid::new_v4().to_string()).unwrap(),
+            HeaderValue::from_str(&request_id::invocation_id()).unwrap(),
         );
         headers.insert(
             "amz-sdk-request",
             HeaderValue::from_static("attempt=1; max=3"),
         );
-        headers.insert(
-            AUTHORIZATION,
-            HeaderValue::from_str(&format!("Bearer {}", ctx.token)).unwrap(),
-        );
         headers.insert(CONNECTION, HeaderValue::from_static("close"));
+        // 凭据的自定义请求头（如企业出口网关令牌），同名时覆盖上面的默认值
+        headers.extend(ctx.credentials.extra_header_map());
+        // Bearer Token 或 SigV4 签名（签名覆盖上面的请求头，需最后设置）
+        sigv4::authorize(
+            &ctx.credentials,
+            &ctx.token,
+            "POST",
+            url,
+            &mut headers,
+            body.as_bytes(),
+            &config.region,
+        )?;
 
         Ok(headers)
     }
@@ -128,11 +188,21 @@ impl KiroProvider {
     ///
     /// # Arguments
     /// * `request_body` - JSON 格式的请求体字符串
+    /// * `model_id` - Kiro 模型 ID，用于跳过不允许该模型的凭据
+    /// * `priority` - 请求优先级类别，决定能否使用保留的优先级分段
+    /// * `session` - 粘性会话键，同一会话固定使用同一凭据（None 表示不启用）
     ///
     /// # Returns
-    /// 返回原始的 HTTP Response，不做解析
-    pub async fn call_api(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
-        self.call_api_with_retry(request_body, false).await
+    /// 返回原始的 HTTP Response（不做解析）及实际使用的凭据 ID
+    pub async fn call_api(
+        &self,
+        request_body: &str,
+        model_id: &str,
+        priority: RequestPriority,
+        session: Option<&str>,
+    ) -> anyhow::Result<ApiResponse> {
+        self.call_api_with_retry(request_body, model_id, priority, session, false)
+            .await
     }
 
     /// 发送流式 API 请求
@@ -143,48 +213,95 @@ impl KiroProvider {
     ///
     /// # Arguments
     /// * `request_body` - JSON 格式的请求体字符串
+    /// * `model_id` - Kiro 模型 ID，用于跳过不允许该模型的凭据
+    /// * `priority` - 请求优先级类别，决定能否使用保留的优先级分段
+    /// * `session` - 粘性会话键，同一会话固定使用同一凭据（None 表示不启用）
     ///
     /// # Returns
-    /// 返回原始的 HTTP Response，调用方负责处理流式数据
-    pub async fn call_api_stream(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
-        self.call_api_with_retry(request_body, true).await
+    /// 返回原始的 HTTP Response（调用方负责处理流式数据）及实际使用的凭据 ID
+    pub async fn call_api_stream(
+        &self,
+        request_body: &str,
+        model_id: &str,
+        priority: RequestPriority,
+        session: Option<&str>,
+    ) -> anyhow::Result<ApiResponse> {
+        self.call_api_with_retry(request_body, model_id, priority, session, true)
+            .await
     }
 
     /// 内部方法：带重试逻辑的 API 调用
     ///
-    /// 重试策略：
-    /// - 每个凭据最多重试 MAX_RETRIES_PER_CREDENTIAL 次
-    /// - 总重试次数 = min(凭据数量 × 每凭据重试次数, MAX_TOTAL_RETRIES)
-    /// - 硬上限 9 次，避免无限重试
+    /// 开启 staging 故障注入时，先附加延迟，被选中失败的请求直接返回 [`InjectedFailure`](crate::kiro::failure_injection::InjectedFailure)
+    ///
+    /// 重试策略（见 [`RetryPolicy`]）：
+    /// - 总尝试次数 = min(凭据数量 × retryMaxAttempts, MAX_TOTAL_RETRIES)
+    /// - 失败后先切换凭据；网络错误与可重试状态码再次落到同一凭据时才指数退避，
+    ///   其余错误状态不退避
+    /// - 400 不重试，硬上限 9 次，避免无限重试
+    /// - 401 且 Token 本地未过期时使 Token 失效并强制刷新重试（每个凭据一次），不计为凭据失败
     async fn call_api_with_retry(
         &self,
         request_body: &str,
+        model_id: &str,
+        priority: RequestPriority,
+        session: Option<&str>,
         is_stream: bool,
-    ) -> anyhow::Result<reqwest::Response> {
-        let total_credentials = self.token_manager.total_count();
-        let max_retries = (total_credentials * MAX_RETRIES_PER_CREDENTIAL).min(MAX_TOTAL_RETRIES);
-        let mut last_error: Option<anyhow::Error> = None;
+    ) -> anyhow::Result<ApiResponse> {
+        // 预发布环境：注入的失败直接返回，不做故障转移
+        if let Some(injector) = &self.failure_injector {
+            injector.apply().await?;
+        }
 
-        for attempt in 0..max_retries {
-            // 获取调用上下文（绑定 index、credentials、token）
-            let ctx = match self.token_manager.acquire_context().await {
-                Ok(c) => c,
+        let total_credentials = self.token_manager.blocking(|tm| tm.total_count()).await;
+        let policy = RetryPolicy::from_config(self.token_manager.config());
+        let max_retries = (total_credentials * policy.max_attempts).min(MAX_TOTAL_RETRIES);
+        let mut last_error: Option<anyhow::Error> = None;
+        // 本次请求中因 401 已使 Token 失效的凭据（每个凭据只强制刷新一次，不占用重试次数）
+        let mut invalidated: Vec<u64> = Vec::new();
+        // 上次可重试失败的凭据与退避时间（切换到其他凭据时无需等待）
+        let mut backoff: Option<(u64, Duration)> = None;
+        let mut next_attempt = 0;
+
+        while next_attempt < max_retries + invalidated.len() {
+            let attempt = next_attempt;
+            next_attempt += 1;
+
+            // 获取凭据租约（绑定 id、credentials、token）
+            let lease = match self
+                .token_manager
+                .lease(Some(model_id), priority, session)
+                .await
+            {
+                Ok(l) => l,
+                // 凭据获取已耗尽时间预算或尝试过所有凭据，重试无意义
+                Err(e) if e.is::<AcquireError>() => return Err(e),
                 Err(e) => {
                     last_error = Some(e);
                     continue;
                 }
             };
 
+            if let Some((id, delay)) = backoff.take()
+                && id == lease.id()
+            {
+                tokio::time::sleep(delay).await;
+            }
+
+            request_id::record_credential(lease.id());
             let url = self.base_url();
-            let headers = match self.build_headers(&ctx) {
+            let headers = match self.build_headers(lease.context(), &url, request_body) {
                 Ok(h) => h,
                 Err(e) => {
+                    // 请求未发出，不影响凭据状态
+                    lease.release();
                     last_error = Some(e);
                     continue;
                 }
             };
 
             // 发送请求
+            let started = std::time::Instant::now();
             let response = match self
                 .client
                 .post(&url)
@@ -201,32 +318,68 @@ impl KiroProvider {
                         max_retries,
                         e
                     );
-                    // 网络错误，报告失败并重试（使用绑定的 id）
-                    if !self.token_manager.report_failure(ctx.id) {
+                    // 网络错误，报告失败后重试（仍使用同一凭据时退避）
+                    let credential_id = lease.id();
+                    if !lease.fail().await {
                         return Err(e.into());
                     }
                     last_error = Some(e.into());
+                    backoff = Some((credential_id, policy.backoff(attempt)));
                     continue;
                 }
             };
 
             let status = response.status();
 
-            // 成功响应
+            // 成功响应：租约随响应返回，响应体读取结束后再结算
             if status.is_success() {
-                self.token_manager.report_success(ctx.id);
-                return Ok(response);
+                let credential_id = lease.id();
+                self.token_manager
+                    .record_latency(credential_id, started.elapsed());
+                return Ok(ApiResponse {
+                    credential_id,
+                    response,
+                    lease,
+                });
             }
 
             // 400 Bad Request - 不算凭据错误，直接返回
             if status.as_u16() == 400 {
+                lease.release();
                 let body = response.text().await.unwrap_or_default();
                 let api_type = if is_stream { "流式" } else { "非流式" };
                 anyhow::bail!("{} API 请求失败: {} {}", api_type, status, body);
             }
 
-            // 其他错误 - 记录失败并可能重试（使用绑定的 id）
+            // 其他错误 - 记录失败并可能重试
             let body = response.text().await.unwrap_or_default();
+
+            // 401 且本地认为 Token 未过期：多为时钟偏差，使 Token 失效后强制刷新重试，不计为失败
+            let lease = if status == StatusCode::UNAUTHORIZED && !invalidated.contains(&lease.id())
+            {
+                let credential_id = lease.id();
+                match lease.invalidate_token().await {
+                    Ok(()) => {
+                        tracing::warn!(
+                            "凭据 #{} 的 Token 未过期但被上游拒绝（401），已使其失效并强制刷新后重试: {}",
+                            credential_id,
+                            body
+                        );
+                        invalidated.push(credential_id);
+                        last_error = Some(anyhow::anyhow!(
+                            "{} API 请求失败: {} {}",
+                            if is_stream { "流式" } else { "非流式" },
+                            status,
+                            body
+                        ));
+                        continue;
+                    }
+                    Err(lease) => lease,
+                }
+            } else {
+                lease
+            };
+
             tracing::warn!(
                 "API 请求失败（尝试 {}/{}）: {} {}",
                 attempt + 1,
@@ -235,7 +388,8 @@ impl KiroProvider {
                 body
             );
 
-            let has_available = self.token_manager.report_failure(ctx.id);
+            let credential_id = lease.id();
+            let has_available = lease.fail().await;
             if !has_available {
                 let api_type = if is_stream { "流式" } else { "非流式" };
                 anyhow::bail!(
@@ -252,6 +406,11 @@ impl KiroProvider {
                 status,
                 body
             ));
+
+            // 限流、服务端错误等可重试状态再次使用同一凭据时退避，凭据错误直接切换
+            if policy.is_retryable(status) {
+                backoff = Some((credential_id, policy.backoff(attempt)));
+            }
         }
 
         // 所有重试都失败
@@ -273,13 +432,11 @@ mod tests {
     use crate::kiro::model::credentials::KiroCredentials;
     use crate::kiro::token_manager::CallContext;
     use crate::model::config::Config;
+    use reqwest::header::AUTHORIZATION;
 
     fn create_test_provider(config: Config, credentials: KiroCredentials) -> KiroProvider {
-        let dir = tempfile::tempdir().unwrap();
-        let db_path = dir.path().join("test.db");
-        let db = Database::open(&db_path).unwrap();
+        let db = Database::open_in_memory().unwrap();
         db.insert_credential(&credentials).unwrap();
-        std::mem::forget(dir); // 保持 tempdir 存活
         let tm = MultiTokenManager::new(config, db, None).unwrap();
         KiroProvider::new(Arc::new(tm))
     }
@@ -287,8 +444,10 @@ mod tests {
     #[test]
     fn test_base_url() {
         let config = Config::default();
-        let mut credentials = KiroCredentials::default();
-        credentials.refresh_token = Some("test_token".to_string());
+        let credentials = KiroCredentials {
+            refresh_token: Some("test_token".to_string()),
+            ..Default::default()
+        };
         let provider = create_test_provider(config, credentials);
         assert!(provider.base_url().contains("amazonaws.com"));
         assert!(provider.base_url().contains("generateAssistantResponse"));
@@ -296,23 +455,31 @@ mod tests {
 
     #[test]
     fn test_base_domain() {
-        let mut config = Config::default();
-        config.region = "us-east-1".to_string();
-        let mut credentials = KiroCredentials::default();
-        credentials.refresh_token = Some("test_token".to_string());
+        let config = Config {
+            region: "us-east-1".to_string(),
+            ..Default::default()
+        };
+        let credentials = KiroCredentials {
+            refresh_token: Some("test_token".to_string()),
+            ..Default::default()
+        };
         let provider = create_test_provider(config, credentials);
         assert_eq!(provider.base_domain(), "q.us-east-1.amazonaws.com");
     }
 
     #[test]
     fn test_build_headers() {
-        let mut config = Config::default();
-        config.region = "us-east-1".to_string();
-        config.kiro_version = "0.8.0".to_string();
+        let config = Config {
+            region: "us-east-1".to_string(),
+            kiro_version: "0.8.0".to_string(),
+            ..Default::default()
+        };
 
-        let mut credentials = KiroCredentials::default();
-        credentials.profile_arn = Some("arn:aws:sso::123456789:profile/test".to_string());
-        credentials.refresh_token = Some("a".repeat(150));
+        let credentials = KiroCredentials {
+            profile_arn: Some("arn:aws:sso::123456789:profile/test".to_string()),
+            refresh_token: Some("a".repeat(150)),
+            ..Default::default()
+        };
 
         let provider = create_test_provider(config, credentials.clone());
         let ctx = CallContext {
@@ -320,7 +487,9 @@ mod tests {
             credentials,
             token: "test_token".to_string(),
         };
-        let headers = provider.build_headers(&ctx).unwrap();
+        let headers = provider
+            .build_headers(&ctx, &provider.base_url(), "{}")
+            .unwrap();
 
         assert_eq!(headers.get(CONTENT_TYPE).unwrap(), "application/json");
         assert_eq!(headers.get("x-amzn-codewhisperer-optout").unwrap(), "true");
@@ -334,5 +503,65 @@ mod tests {
                 .starts_with("Bearer ")
         );
         assert_eq!(headers.get(CONNECTION).unwrap(), "close");
+        assert!(headers.get("x-egress-token").is_none());
+    }
+
+    #[test]
+    fn test_build_headers_merges_extra_headers() {
+        let credentials = KiroCredentials {
+            refresh_token: Some("a".repeat(150)),
+            extra_headers: Some(
+                [
+                    ("x-egress-token", "corp-token"),
+                    ("connection", "keep-alive"),
+                ]
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            ),
+            ..Default::default()
+        };
+
+        let provider = create_test_provider(Config::default(), credentials.clone());
+        let ctx = CallContext {
+            id: 1,
+            credentials,
+            token: "test_token".to_string(),
+        };
+        let headers = provider
+            .build_headers(&ctx, &provider.base_url(), "{}")
+            .unwrap();
+
+        assert_eq!(headers.get("x-egress-token").unwrap(), "corp-token");
+        assert_eq!(headers.get(CONNECTION).unwrap(), "keep-alive");
+        assert_eq!(headers.get(AUTHORIZATION).unwrap(), "Bearer test_token");
+    }
+
+    #[test]
+    fn test_build_headers_signs_iam_credentials() {
+        let credentials = KiroCredentials {
+            auth_method: Some("iam".to_string()),
+            access_key_id: Some("AKIDEXAMPLE".to_string()),
+            secret_access_key: Some("secret".to_string()),
+            machine_id: Some("b3981d12-4d61-418c-9b77-461db82a7cc4".to_string()),
+            ..Default::default()
+        };
+
+        let provider = create_test_provider(Config::default(), credentials.clone());
+        let ctx = CallContext {
+            id: 1,
+            credentials,
+            token: String::new(),
+        };
+        let headers = provider
+            .build_headers(&ctx, &provider.base_url(), "{}")
+            .unwrap();
+
+        let authorization = headers.get(AUTHORIZATION).unwrap().to_str().unwrap();
+        assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+        assert!(
+            authorization.contains("SignedHeaders=content-type;host;x-amz-date;x-amz-user-agent,")
+        );
+        assert!(headers.contains_key("x-amz-date"));
     }
 }
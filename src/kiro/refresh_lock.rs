@@ -0,0 +1,244 @@
+//! Token 刷新锁
+//!
+//! 同一时间只允许一个 Token 刷新操作。刷新请求挂起时所有需要刷新的请求都会被阻塞，
+//! 因此这里记录持有者（凭据、开始时间）与等待数量，并支持强制释放：
+//! 强制释放会取消持有者正在执行的刷新，使其返回错误并立即释放锁
+
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::{Mutex as TokioMutex, Notify};
+use tokio::time::Instant as TokioInstant;
+
+/// 刷新锁状态
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshLockStatus {
+    /// 是否有刷新正在进行
+    pub refreshing: bool,
+    /// 正在刷新的凭据 ID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_id: Option<u64>,
+    /// 刷新开始时间（RFC3339）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<String>,
+    /// 已持有锁的时长（毫秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub held_ms: Option<u64>,
+    /// 等待获取锁的请求数
+    pub waiting: u64,
+}
+
+/// 当前持有者
+struct Holder {
+    generation: u64,
+    credential_id: u64,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    cancel: Arc<Notify>,
+}
+
+/// Token 刷新锁
+#[derive(Default)]
+pub struct RefreshLock {
+    mutex: TokioMutex<()>,
+    holder: Mutex<Option<Holder>>,
+    waiting: AtomicU64,
+    generation: AtomicU64,
+}
+
+/// 等待计数守卫（等待被取消时同样减一）
+struct WaitingGuard<'a>(&'a AtomicU64);
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 持有者清理守卫（仅清理自己登记的持有者）
+struct HolderGuard<'a> {
+    lock: &'a RefreshLock,
+    generation: u64,
+}
+
+impl Drop for HolderGuard<'_> {
+    fn drop(&mut self) {
+        let mut holder = self.lock.holder.lock();
+        if holder
+            .as_ref()
+            .is_some_and(|h| h.generation == self.generation)
+        {
+            *holder = None;
+        }
+    }
+}
+
+impl RefreshLock {
+    /// 创建刷新锁
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 持有锁执行刷新操作
+    ///
+    /// 被强制释放时 `f` 会被丢弃，返回错误
+    pub async fn run<T, F>(&self, credential_id: u64, f: F) -> anyhow::Result<T>
+    where
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        self.run_until(credential_id, None, f).await
+    }
+
+    /// 持有锁执行刷新操作，最多等待锁到 `deadline`
+    ///
+    /// 截止时间仅约束等待锁的时间；已开始的刷新不会被中断，
+    /// 避免上游已轮换 refreshToken 而新 Token 尚未持久化
+    pub async fn run_until<T, F>(
+        &self,
+        credential_id: u64,
+        deadline: Option<TokioInstant>,
+        f: F,
+    ) -> anyhow::Result<T>
+    where
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        let _guard = {
+            self.waiting.fetch_add(1, Ordering::Relaxed);
+            let _waiting = WaitingGuard(&self.waiting);
+            match deadline {
+                Some(deadline) => {
+                    match tokio::time::timeout_at(deadline, self.mutex.lock()).await {
+                        Ok(guard) => guard,
+                        Err(_) => {
+                            let holder = self.holder.lock().as_ref().map(|h| h.credential_id);
+                            match holder {
+                                Some(holder) => anyhow::bail!(
+                                    "等待 Token 刷新锁超时（凭据 #{} 正在刷新）",
+                                    holder
+                                ),
+                                None => anyhow::bail!("等待 Token 刷新锁超时"),
+                            }
+                        }
+                    }
+                }
+                None => self.mutex.lock().await,
+            }
+        };
+
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let cancel = Arc::new(Notify::new());
+        *self.holder.lock() = Some(Holder {
+            generation,
+            credential_id,
+            started_at: Utc::now(),
+            started: Instant::now(),
+            cancel: cancel.clone(),
+        });
+        let _holder = HolderGuard {
+            lock: self,
+            generation,
+        };
+
+        tokio::select! {
+            result = f => result,
+            _ = cancel.notified() => {
+                anyhow::bail!("凭据 #{} 的 Token 刷新已被强制释放", credential_id)
+            }
+        }
+    }
+
+    /// 获取锁状态
+    pub fn status(&self) -> RefreshLockStatus {
+        let holder = self.holder.lock();
+        RefreshLockStatus {
+            refreshing: holder.is_some(),
+            credential_id: holder.as_ref().map(|h| h.credential_id),
+            started_at: holder.as_ref().map(|h| h.started_at.to_rfc3339()),
+            held_ms: holder
+                .as_ref()
+                .map(|h| h.started.elapsed().as_millis() as u64),
+            waiting: self.waiting.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 强制释放锁，返回被取消刷新的凭据 ID（没有进行中的刷新时返回 None）
+    pub fn force_release(&self) -> Option<u64> {
+        let holder = self.holder.lock();
+        let holder = holder.as_ref()?;
+        holder.cancel.notify_one();
+        Some(holder.credential_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::pending;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_force_release_unblocks_waiters() {
+        let lock = Arc::new(RefreshLock::new());
+        assert!(!lock.status().refreshing);
+        assert_eq!(lock.force_release(), None);
+
+        let stuck = {
+            let lock = lock.clone();
+            tokio::spawn(async move { lock.run(7, pending::<anyhow::Result<()>>()).await })
+        };
+        let waiter = {
+            let lock = lock.clone();
+            tokio::spawn(async move { lock.run(8, async { Ok(42) }).await })
+        };
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let status = lock.status();
+        assert!(status.refreshing);
+        assert_eq!(status.credential_id, Some(7));
+        assert!(status.started_at.is_some());
+        assert_eq!(status.waiting, 1);
+
+        assert_eq!(lock.force_release(), Some(7));
+        let err = stuck.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("强制释放"));
+        assert_eq!(waiter.await.unwrap().unwrap(), 42);
+
+        let status = lock.status();
+        assert!(!status.refreshing);
+        assert_eq!(status.waiting, 0);
+    }
+
+    #[tokio::test]
+    async fn test_run_until_deadline() {
+        let lock = Arc::new(RefreshLock::new());
+        let stuck = {
+            let lock = lock.clone();
+            tokio::spawn(async move { lock.run(7, pending::<anyhow::Result<()>>()).await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let deadline = TokioInstant::now() + Duration::from_millis(50);
+        let err = lock
+            .run_until(8, Some(deadline), async { Ok(()) })
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("凭据 #7 正在刷新"));
+        assert_eq!(lock.status().waiting, 0);
+
+        lock.force_release();
+        assert!(stuck.await.unwrap().is_err());
+        let deadline = TokioInstant::now() + Duration::from_millis(50);
+        assert_eq!(
+            lock.run_until(8, Some(deadline), async { Ok(1) })
+                .await
+                .unwrap(),
+            1
+        );
+    }
+}
@@ -0,0 +1,271 @@
+//! 凭据热备同步
+//!
+//! 主实例通过 Admin API 导出完整的凭据快照（含运行时状态），
+//! 热备实例定期拉取快照并整体替换本地凭据表。热备期间实例不对外提供服务，
+//! 也不会刷新 Token（避免轮换主实例正在使用的 refreshToken），
+//! 主实例故障时通过 Admin API 手动提升即可接管，无需重新导入账号
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::http_client::build_client;
+use crate::kiro::db::Database;
+use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::token_manager::MultiTokenManager;
+use crate::model::config::Config;
+
+/// 主实例导出快照的路径（相对 Admin API 路径，主实例与热备实例的 `adminPath` 需一致）
+const SNAPSHOT_PATH: &str = "/replication/snapshot";
+
+/// 是否处于热备模式（仅内存，重启后按配置决定）
+static STANDBY: AtomicBool = AtomicBool::new(false);
+
+/// 最近一次同步状态
+static SYNC_STATUS: RwLock<SyncStatus> = RwLock::new(SyncStatus {
+    last_sync_at: None,
+    last_error: None,
+});
+
+/// 同步状态
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStatus {
+    /// 最近一次成功同步时间
+    pub last_sync_at: Option<DateTime<Utc>>,
+    /// 最近一次同步错误（成功后清空）
+    pub last_error: Option<String>,
+}
+
+/// 同步的凭据（包含 JSON 配置中不导出的运行时状态）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplicatedCredential {
+    #[serde(flatten)]
+    pub credentials: KiroCredentials,
+    pub disabled: bool,
+    pub failure_count: u32,
+    pub subscription_title: Option<String>,
+    pub current_usage: f64,
+    pub usage_limit: f64,
+    pub next_reset_at: Option<f64>,
+    pub balance_updated_at: Option<String>,
+    pub email: Option<String>,
+}
+
+impl From<KiroCredentials> for ReplicatedCredential {
+    fn from(credentials: KiroCredentials) -> Self {
+        Self {
+            disabled: credentials.disabled,
+            failure_count: credentials.failure_count,
+            subscription_title: credentials.subscription_title.clone(),
+            current_usage: credentials.current_usage,
+            usage_limit: credentials.usage_limit,
+            next_reset_at: credentials.next_reset_at,
+            balance_updated_at: credentials.balance_updated_at.clone(),
+            email: credentials.email.clone(),
+            credentials,
+        }
+    }
+}
+
+impl From<ReplicatedCredential> for KiroCredentials {
+    fn from(replicated: ReplicatedCredential) -> Self {
+        Self {
+            disabled: replicated.disabled,
+            failure_count: replicated.failure_count,
+            subscription_title: replicated.subscription_title,
+            current_usage: replicated.current_usage,
+            usage_limit: replicated.usage_limit,
+            next_reset_at: replicated.next_reset_at,
+            balance_updated_at: replicated.balance_updated_at,
+            email: replicated.email,
+            ..replicated.credentials
+        }
+    }
+}
+
+/// 凭据快照
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplicationSnapshot {
+    /// 快照生成时间
+    pub generated_at: DateTime<Utc>,
+    /// 全部凭据
+    pub credentials: Vec<ReplicatedCredential>,
+}
+
+/// 是否处于热备模式
+pub fn is_standby() -> bool {
+    STANDBY.load(Ordering::Relaxed)
+}
+
+/// 将热备实例提升为主实例，返回调用前是否处于热备模式
+///
+/// 提升后同步任务会在下一轮退出
+pub fn promote() -> bool {
+    STANDBY.swap(false, Ordering::Relaxed)
+}
+
+/// 获取最近一次同步状态
+pub fn sync_status() -> SyncStatus {
+    SYNC_STATUS.read().clone()
+}
+
+/// 从数据库导出凭据快照
+pub fn build_snapshot(db: &Database) -> anyhow::Result<ReplicationSnapshot> {
+    Ok(ReplicationSnapshot {
+        generated_at: Utc::now(),
+        credentials: db
+            .load_credentials()?
+            .into_iter()
+            .map(ReplicatedCredential::from)
+            .collect(),
+    })
+}
+
+/// 启动热备同步后台任务
+///
+/// 进入热备模式，按间隔从主实例拉取快照；快照内容未变化时跳过写库
+pub fn spawn_follower(config: &Config, token_manager: Arc<MultiTokenManager>) {
+    let Some(leader_url) = config.replication_leader_url.clone() else {
+        return;
+    };
+    let url = format!(
+        "{}{}{}",
+        leader_url.trim_end_matches('/'),
+        config.admin_path(),
+        SNAPSHOT_PATH
+    );
+    let api_key = config
+        .replication_leader_api_key
+        .clone()
+        .unwrap_or_default();
+    let interval_secs = config.replication_interval_secs.max(1);
+
+    STANDBY.store(true, Ordering::Relaxed);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(interval_secs));
+        let mut last_digest = None;
+        loop {
+            ticker.tick().await;
+            if !is_standby() {
+                tracing::info!("实例已提升为主实例，停止热备同步");
+                break;
+            }
+
+            let result = match fetch_snapshot(&url, &api_key).await {
+                Ok(snapshot) => {
+                    let previous = last_digest.take();
+                    let (result, digest) = token_manager
+                        .blocking(move |tm| {
+                            let mut digest = previous;
+                            (apply_snapshot(tm, snapshot, &mut digest), digest)
+                        })
+                        .await;
+                    last_digest = digest;
+                    result
+                }
+                Err(e) => Err(e),
+            };
+            let mut status = SYNC_STATUS.write();
+            match result {
+                Ok(()) => {
+                    status.last_sync_at = Some(Utc::now());
+                    status.last_error = None;
+                }
+                Err(e) => {
+                    tracing::warn!("热备同步失败: {}", e);
+                    status.last_error = Some(e.to_string());
+                }
+            }
+        }
+    });
+}
+
+/// 从主实例拉取快照
+async fn fetch_snapshot(url: &str, api_key: &str) -> anyhow::Result<ReplicationSnapshot> {
+    let client = build_client(None, 30)?;
+    let response = client.get(url).header("x-api-key", api_key).send().await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("主实例返回错误状态: {}", status);
+    }
+
+    Ok(response.json().await?)
+}
+
+/// 应用快照到本地数据库
+fn apply_snapshot(
+    token_manager: &MultiTokenManager,
+    snapshot: ReplicationSnapshot,
+    last_digest: &mut Option<String>,
+) -> anyhow::Result<()> {
+    let digest = hex::encode(Sha256::digest(serde_json::to_vec(&snapshot.credentials)?));
+    if last_digest.as_deref() == Some(digest.as_str()) {
+        tracing::debug!("热备快照无变化，跳过同步");
+        return Ok(());
+    }
+
+    // 已提升的实例不再覆盖本地数据（提升可能发生在拉取过程中）
+    if !is_standby() {
+        return Ok(());
+    }
+
+    let credentials: Vec<KiroCredentials> = snapshot
+        .credentials
+        .into_iter()
+        .map(KiroCredentials::from)
+        .collect();
+    let removed = token_manager.database().replace_credentials(&credentials)?;
+    token_manager.select_highest_priority();
+    *last_digest = Some(digest);
+
+    tracing::info!(
+        "热备同步完成: {} 个凭据（删除 {} 个，快照时间 {}）",
+        credentials.len(),
+        removed,
+        snapshot.generated_at
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replicated_credential_roundtrip() {
+        let credentials = KiroCredentials {
+            id: Some(3),
+            refresh_token: Some("refresh".to_string()),
+            disabled: true,
+            failure_count: 2,
+            current_usage: 12.5,
+            email: Some("user@example.com".to_string()),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_value(ReplicatedCredential::from(credentials)).unwrap();
+        assert_eq!(json["id"], 3);
+        assert_eq!(json["refreshToken"], "refresh");
+        assert_eq!(json["disabled"], true);
+        assert_eq!(json["email"], "user@example.com");
+
+        let restored: KiroCredentials = serde_json::from_value::<ReplicatedCredential>(json)
+            .unwrap()
+            .into();
+        assert_eq!(restored.id, Some(3));
+        assert!(restored.disabled);
+        assert_eq!(restored.failure_count, 2);
+        assert_eq!(restored.current_usage, 12.5);
+        assert_eq!(restored.email, Some("user@example.com".to_string()));
+    }
+}
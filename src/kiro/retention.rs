@@ -0,0 +1,80 @@
+//! 数据库记录清理
+//!
+//! 请求日志与用量记录随请求持续增长，后台定期删除超过保留期或条数上限的记录。
+//! 多实例共享数据库时只由持有租约的实例清理
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::kiro::db::Database;
+use crate::kiro::lease::Lease;
+
+/// 清理任务租约名称
+const PRUNER_LEASE: &str = "retention";
+
+/// 清理间隔
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// 记录保留设置
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionSettings {
+    /// 请求日志保留天数（0 表示不按时间清理）
+    pub request_log_days: u64,
+    /// 请求日志最多保留的条数（0 表示不限制）
+    pub request_log_max_rows: usize,
+    /// 用量记录保留天数（0 表示不清理）
+    pub usage_log_days: u64,
+}
+
+impl RetentionSettings {
+    /// 是否有需要清理的记录
+    pub fn is_enabled(&self) -> bool {
+        self.request_log_days > 0 || self.request_log_max_rows > 0 || self.usage_log_days > 0
+    }
+}
+
+/// 启动记录清理任务（每小时执行一次）
+pub fn spawn_pruner(db: Arc<Database>, settings: RetentionSettings) {
+    let lease = Lease::spawn(db.clone(), PRUNER_LEASE);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(PRUNE_INTERVAL);
+        // 首个 tick 立即完成，跳过以等待租约获取
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if lease.is_held() {
+                prune(&db, settings).await;
+            }
+        }
+    });
+}
+
+/// 按保留设置删除过期记录
+async fn prune(db: &Arc<Database>, settings: RetentionSettings) {
+    if settings.request_log_days > 0 {
+        let cutoff = Utc::now() - chrono::Duration::days(settings.request_log_days as i64);
+        match db.call(move |db| db.prune_request_logs(cutoff)).await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!("已清理 {} 条过期请求日志", count),
+            Err(e) => tracing::warn!("清理请求日志失败: {}", e),
+        }
+    }
+    if settings.request_log_max_rows > 0 {
+        let max_rows = settings.request_log_max_rows;
+        match db.call(move |db| db.trim_request_logs(max_rows)).await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!("请求日志超出 {} 条上限，已清理 {} 条", max_rows, count),
+            Err(e) => tracing::warn!("清理请求日志失败: {}", e),
+        }
+    }
+    if settings.usage_log_days > 0 {
+        let cutoff = Utc::now() - chrono::Duration::days(settings.usage_log_days as i64);
+        match db.call(move |db| db.prune_usage_log(cutoff)).await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!("已清理 {} 条过期用量记录", count),
+            Err(e) => tracing::warn!("清理用量记录失败: {}", e),
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! 上游请求重试策略
+//!
+//! Token 刷新、额度查询与对话请求共用同一套重试策略（最大尝试次数、指数退避、可重试状态码），
+//! 由配置文件统一调整，而不是各模块各自决定是否重试。Token 刷新不可重放，只重试请求发出前的
+//! 连接错误（见 [`RetryPolicy::send_unsent`]）
+
+use std::time::Duration;
+
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+use crate::model::config::Config;
+
+/// 上游请求重试策略
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// 最大尝试次数（含首次请求，至少为 1）
+    pub max_attempts: usize,
+    /// 退避基数：第 n 次重试前等待 base × 2^(n-1)
+    pub backoff_base: Duration,
+    /// 单次退避上限
+    pub backoff_max: Duration,
+    /// 可重试的 HTTP 状态码
+    pub retryable_status: Vec<u16>,
+}
+
+impl RetryPolicy {
+    /// 从配置创建重试策略
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_attempts: config.retry_max_attempts.max(1),
+            backoff_base: Duration::from_millis(config.retry_backoff_base_ms),
+            backoff_max: Duration::from_millis(config.retry_backoff_max_ms),
+            retryable_status: config.retry_status_codes.clone(),
+        }
+    }
+
+    /// 状态码是否可重试
+    pub fn is_retryable(&self, status: StatusCode) -> bool {
+        self.retryable_status.contains(&status.as_u16())
+    }
+
+    /// 第 `attempt` 次请求（从 0 开始）失败后、下一次请求前的退避时间
+    pub fn backoff(&self, attempt: usize) -> Duration {
+        let factor = 1u32 << attempt.min(16);
+        self.backoff_base
+            .saturating_mul(factor)
+            .min(self.backoff_max)
+    }
+
+    /// 按策略发送请求
+    ///
+    /// 网络错误与可重试状态码会在退避后重新构建请求并重试；其余响应（含非可重试的错误状态）
+    /// 直接返回，由调用方解析。重试耗尽时返回最后一次的响应或错误
+    pub async fn send(
+        &self,
+        label: &str,
+        build: impl Fn() -> RequestBuilder,
+    ) -> anyhow::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let last = attempt + 1 >= self.max_attempts;
+            match build().send().await {
+                Ok(response) if last || !self.is_retryable(response.status()) => {
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    tracing::warn!(
+                        "{}失败（尝试 {}/{}）: {}，{}ms 后重试",
+                        label,
+                        attempt + 1,
+                        self.max_attempts,
+                        response.status(),
+                        self.backoff(attempt).as_millis()
+                    );
+                }
+                Err(e) if last => return Err(e.into()),
+                Err(e) => {
+                    tracing::warn!(
+                        "{}发送失败（尝试 {}/{}）: {}，{}ms 后重试",
+                        label,
+                        attempt + 1,
+                        self.max_attempts,
+                        e,
+                        self.backoff(attempt).as_millis()
+                    );
+                }
+            }
+            tokio::time::sleep(self.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    /// 按策略发送不可重放的请求（如 Token 刷新）
+    ///
+    /// 请求一旦发出，上游可能已经处理（如已轮换 refreshToken），重放会使凭据永久失效，
+    /// 因此只重试请求发出前的连接错误；任何响应与其余错误直接返回
+    pub async fn send_unsent(
+        &self,
+        label: &str,
+        build: impl Fn() -> RequestBuilder,
+    ) -> anyhow::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            match build().send().await {
+                Ok(response) => return Ok(response),
+                Err(e) if e.is_connect() && attempt + 1 < self.max_attempts => {
+                    tracing::warn!(
+                        "{}连接失败（尝试 {}/{}）: {}，{}ms 后重试",
+                        label,
+                        attempt + 1,
+                        self.max_attempts,
+                        e,
+                        self.backoff(attempt).as_millis()
+                    );
+                }
+                Err(e) => return Err(e.into()),
+            }
+            tokio::time::sleep(self.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn policy(max_attempts: usize) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            backoff_base: Duration::from_millis(1),
+            backoff_max: Duration::from_millis(4),
+            retryable_status: vec![429, 503],
+        }
+    }
+
+    #[test]
+    fn test_backoff_is_exponential_and_capped() {
+        let policy = policy(5);
+        assert_eq!(policy.backoff(0), Duration::from_millis(1));
+        assert_eq!(policy.backoff(1), Duration::from_millis(2));
+        assert_eq!(policy.backoff(2), Duration::from_millis(4));
+        assert_eq!(policy.backoff(10), Duration::from_millis(4));
+        assert_eq!(policy.backoff(usize::MAX), Duration::from_millis(4));
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = Config {
+            retry_max_attempts: 0,
+            ..Config::default()
+        };
+        let policy = RetryPolicy::from_config(&config);
+        assert_eq!(policy.max_attempts, 1);
+        assert!(policy.is_retryable(StatusCode::SERVICE_UNAVAILABLE));
+        // 限流默认不退避重试，由对话请求直接切换凭据
+        assert!(!policy.is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!policy.is_retryable(StatusCode::UNAUTHORIZED));
+    }
+
+    /// 启动返回固定状态码序列的本地服务，返回地址与请求计数
+    async fn serve_statuses(statuses: Vec<u16>) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = axum::Router::new().route(
+            "/",
+            axum::routing::get(move || {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                let status = statuses[n.min(statuses.len() - 1)];
+                async move { StatusCode::from_u16(status).unwrap() }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (format!("http://{}/", addr), hits)
+    }
+
+    #[tokio::test]
+    async fn test_send_retries_retryable_status() {
+        let (url, hits) = serve_statuses(vec![503, 429, 200]).await;
+        let client = reqwest::Client::new();
+        let response = policy(3)
+            .send("测试请求", || client.get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        // 重试耗尽时返回最后一次响应
+        let (url, hits) = serve_statuses(vec![503]).await;
+        let response = policy(2)
+            .send("测试请求", || client.get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // 不可重试的状态码直接返回
+        let (url, hits) = serve_statuses(vec![401, 200]).await;
+        let response = policy(3)
+            .send("测试请求", || client.get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_unsent_only_retries_connect_errors() {
+        let client = reqwest::Client::new();
+
+        // 请求已发出：可重试状态码也不重放
+        let (url, hits) = serve_statuses(vec![503, 200]).await;
+        let response = policy(3)
+            .send_unsent("测试请求", || client.get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // 连接失败（请求未发出）时重试，耗尽后返回错误
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+        let attempts = AtomicUsize::new(0);
+        let err = policy(3)
+            .send_unsent("测试请求", || {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                client.get(&url)
+            })
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<reqwest::Error>().unwrap().is_connect());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+}
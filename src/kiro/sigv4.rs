@@ -0,0 +1,299 @@
+//! AWS Signature Version 4 请求签名
+//!
+//! `authMethod` 为 `iam` 的凭据直接使用 IAM 访问密钥调用 CodeWhisperer，
+//! 请求不携带 Bearer Token，而是按 SigV4 规范签名：签名覆盖 `host`、`content-type`
+//! 与全部 `x-amz-*` 请求头，签名结果写入 `Authorization` 请求头
+
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue};
+use ring::hmac;
+use sha2::{Digest, Sha256};
+
+use crate::kiro::model::credentials::KiroCredentials;
+
+/// CodeWhisperer 的签名服务名
+pub const SERVICE: &str = "codewhisperer";
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// 签名参数（IAM 访问密钥与签名范围）
+#[derive(Debug, Clone, Copy)]
+pub struct SigningParams<'a> {
+    pub access_key_id: &'a str,
+    pub secret_access_key: &'a str,
+    /// 临时凭据（STS）的会话令牌
+    pub session_token: Option<&'a str>,
+    pub region: &'a str,
+    pub service: &'a str,
+}
+
+/// 为请求签名，写入 `x-amz-date`、`x-amz-security-token`（如有）与 `Authorization` 请求头
+///
+/// `headers` 需已包含 `host` 请求头；签名之后不应再修改参与签名的请求头
+pub fn sign(
+    method: &str,
+    url: &Url,
+    headers: &mut HeaderMap,
+    body: &[u8],
+    params: &SigningParams<'_>,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let SigningParams {
+        access_key_id,
+        secret_access_key,
+        session_token,
+        region,
+        service,
+    } = *params;
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = &amz_date[..8];
+    headers.insert("x-amz-date", HeaderValue::from_str(&amz_date)?);
+    if let Some(token) = session_token {
+        headers.insert("x-amz-security-token", HeaderValue::from_str(token)?);
+    }
+    // 重复签名时移除旧签名
+    headers.remove(AUTHORIZATION);
+
+    let mut signed: Vec<(String, String)> = headers
+        .iter()
+        .filter(|(name, _)| {
+            let name = name.as_str();
+            name == "host" || name == "content-type" || name.starts_with("x-amz-")
+        })
+        .map(|(name, value)| {
+            let value = value.to_str().unwrap_or_default();
+            (
+                name.as_str().to_string(),
+                value.split_whitespace().collect::<Vec<_>>().join(" "),
+            )
+        })
+        .collect();
+    if !signed.iter().any(|(name, _)| name == "host") {
+        anyhow::bail!("SigV4 签名需要 host 请求头");
+    }
+    signed.sort();
+    let canonical_headers: String = signed
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = signed
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        canonical_uri(url),
+        canonical_query(url),
+        canonical_headers,
+        signed_headers,
+        hex::encode(Sha256::digest(body))
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let key = [date, region, service, "aws4_request"].iter().fold(
+        format!("AWS4{}", secret_access_key).into_bytes(),
+        |key, part| hmac_sha256(&key, part.as_bytes()),
+    );
+    let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+
+    let authorization = format!(
+        "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+        ALGORITHM, access_key_id, scope, signed_headers, signature
+    );
+    headers.insert(AUTHORIZATION, HeaderValue::from_str(&authorization)?);
+    Ok(())
+}
+
+/// 为上游请求设置认证信息
+///
+/// IAM 凭据按 SigV4 签名（覆盖已有的 Authorization），其他凭据使用 Bearer Token。
+/// 需在设置完其他请求头之后调用
+pub fn authorize(
+    credentials: &KiroCredentials,
+    token: &str,
+    method: &str,
+    url: &str,
+    headers: &mut HeaderMap,
+    body: &[u8],
+    region: &str,
+) -> anyhow::Result<()> {
+    if !credentials.is_iam() {
+        // 自定义请求头中已配置 Authorization 时保留
+        if !headers.contains_key(AUTHORIZATION) {
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", token))?,
+            );
+        }
+        return Ok(());
+    }
+
+    let (Some(access_key_id), Some(secret_access_key)) = (
+        credentials.access_key_id.as_deref(),
+        credentials.secret_access_key.as_deref(),
+    ) else {
+        anyhow::bail!("IAM 凭据缺少 accessKeyId 或 secretAccessKey");
+    };
+    let params = SigningParams {
+        access_key_id,
+        secret_access_key,
+        session_token: credentials.session_token.as_deref(),
+        region,
+        service: SERVICE,
+    };
+    sign(
+        method,
+        &Url::parse(url)?,
+        headers,
+        body,
+        &params,
+        Utc::now(),
+    )
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    hmac::sign(&key, data).as_ref().to_vec()
+}
+
+/// 规范 URI（Url 已对路径做过百分号编码，CodeWhisperer 的路径不含需要二次编码的字符）
+fn canonical_uri(url: &Url) -> &str {
+    match url.path() {
+        "" => "/",
+        path => path,
+    }
+}
+
+/// 规范查询字符串：参数按 SigV4 规则重新编码后按名称、值排序
+fn canonical_query(url: &Url) -> String {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| (uri_encode(&k), uri_encode(&v)))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// SigV4 的 URI 编码（仅保留非保留字符 A-Z a-z 0-9 - _ . ~）
+fn uri_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use reqwest::header::HOST;
+
+    const PARAMS: SigningParams<'static> = SigningParams {
+        access_key_id: "AKIDEXAMPLE",
+        secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+        session_token: None,
+        region: "us-east-1",
+        service: "service",
+    };
+
+    fn sign_example(url: &str) -> String {
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, HeaderValue::from_static("example.amazonaws.com"));
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        sign(
+            "GET",
+            &Url::parse(url).unwrap(),
+            &mut headers,
+            b"",
+            &PARAMS,
+            now,
+        )
+        .unwrap();
+        assert_eq!(headers["x-amz-date"], "20150830T123600Z");
+        headers[AUTHORIZATION].to_str().unwrap().to_string()
+    }
+
+    /// AWS SigV4 测试套件 get-vanilla
+    #[test]
+    fn test_sign_get_vanilla() {
+        assert_eq!(
+            sign_example("https://example.amazonaws.com/"),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    /// AWS SigV4 测试套件 get-vanilla-query-order-key-case
+    #[test]
+    fn test_sign_sorts_query() {
+        assert_eq!(
+            sign_example("https://example.amazonaws.com/?Param2=value2&Param1=value1"),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500"
+        );
+    }
+
+    #[test]
+    fn test_authorize_by_auth_method() {
+        let url = "https://q.us-east-1.amazonaws.com/generateAssistantResponse";
+        let new_headers = || {
+            let mut headers = HeaderMap::new();
+            headers.insert(HOST, HeaderValue::from_static("q.us-east-1.amazonaws.com"));
+            headers.insert("connection", HeaderValue::from_static("close"));
+            headers
+        };
+
+        let mut headers = new_headers();
+        let social = KiroCredentials::default();
+        authorize(
+            &social,
+            "token",
+            "POST",
+            url,
+            &mut headers,
+            b"{}",
+            "us-east-1",
+        )
+        .unwrap();
+        assert_eq!(headers[AUTHORIZATION], "Bearer token");
+
+        let mut iam = KiroCredentials {
+            auth_method: Some("IAM".to_string()),
+            access_key_id: Some("AKIDEXAMPLE".to_string()),
+            ..Default::default()
+        };
+        let mut headers = new_headers();
+        assert!(authorize(&iam, "", "POST", url, &mut headers, b"{}", "us-east-1").is_err());
+
+        iam.secret_access_key = Some("secret".to_string());
+        iam.session_token = Some("session".to_string());
+        authorize(&iam, "", "POST", url, &mut headers, b"{}", "us-east-1").unwrap();
+        assert_eq!(headers["x-amz-security-token"], "session");
+        let authorization = headers[AUTHORIZATION].to_str().unwrap();
+        assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+        assert!(authorization.contains("/us-east-1/codewhisperer/aws4_request"));
+        assert!(authorization.contains("SignedHeaders=host;x-amz-date;x-amz-security-token,"));
+    }
+}
@@ -0,0 +1,88 @@
+//! 统计摘要内存快照
+//!
+//! 聚合 SQL 需要扫描请求日志，高频抓取时会与请求路径争用数据库连接。
+//! 这里在后台维护一份统计摘要：检测到数据库写入后按最小间隔刷新，
+//! 无写入时也会定期刷新以滚动"最近一小时"与"今日"窗口；查询直接读取内存快照
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use parking_lot::RwLock;
+
+use crate::kiro::db::Database;
+use crate::kiro::model::stats::StatsSummary;
+
+/// "最近"统计窗口（小时）
+const RECENT_WINDOW_HOURS: i64 = 1;
+
+/// 无写入时的最长刷新间隔
+const MAX_SNAPSHOT_AGE: Duration = Duration::from_secs(60);
+
+/// 检测写入的轮询间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 当前快照
+static SNAPSHOT: RwLock<Option<StatsSummary>> = RwLock::new(None);
+
+/// 获取当前快照（刷新任务尚未完成首次刷新时为 None）
+pub fn current() -> Option<StatsSummary> {
+    SNAPSHOT.read().clone()
+}
+
+/// 立即刷新快照
+pub fn refresh(db: &Database) -> anyhow::Result<StatsSummary> {
+    let summary = compute(db, None)?;
+    *SNAPSHOT.write() = Some(summary.clone());
+    Ok(summary)
+}
+
+/// 按当前时间窗口聚合统计摘要（不更新快照）
+///
+/// 快照只覆盖全部请求，按请求标签统计时直接调用本函数
+pub fn compute(db: &Database, tag: Option<&str>) -> anyhow::Result<StatsSummary> {
+    let now = Utc::now();
+    let since = now - chrono::Duration::hours(RECENT_WINDOW_HOURS);
+    let today = now
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .expect("零点总是有效")
+        .and_utc();
+    db.compute_stats(since, today, tag)
+}
+
+/// 启动后台刷新任务
+///
+/// 数据库有写入时，距上次刷新至少 `min_interval` 后刷新；
+/// 无写入时每 `MAX_SNAPSHOT_AGE` 刷新一次
+pub fn spawn_refresher(db: Arc<Database>, min_interval: Duration) {
+    tokio::spawn(async move {
+        let mut last_changes = None;
+        let mut last_refresh: Option<Instant> = None;
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            ticker.tick().await;
+
+            let changes = db.call(|db| db.total_changes()).await;
+            let age = last_refresh.map(|t| t.elapsed());
+            let due = match age {
+                None => true,
+                Some(age) => {
+                    (last_changes != Some(changes) && age >= min_interval)
+                        || age >= MAX_SNAPSHOT_AGE
+                }
+            };
+            if !due {
+                continue;
+            }
+
+            match db.call(refresh).await {
+                Ok(_) => tracing::debug!("统计快照已刷新"),
+                Err(e) => tracing::warn!("刷新统计快照失败: {}", e),
+            }
+            // 刷新本身不写入，记录刷新前的计数即可
+            last_changes = Some(changes);
+            last_refresh = Some(Instant::now());
+        }
+    });
+}
@@ -1,25 +1,37 @@
 //! Token 管理模块
 //!
-//! 负责 Token 过期检测和刷新，支持 Social 和 IdC 认证方式
+//! 负责 Token 过期检测和刷新，支持 Social 和 IdC 认证方式（IAM 凭据无需刷新，请求改用 SigV4 签名）
 //! 支持单凭据 (TokenManager) 和多凭据 (MultiTokenManager) 管理
 
 use anyhow::bail;
 use chrono::{DateTime, Duration, Utc};
 use parking_lot::Mutex;
+use reqwest::header::{CONNECTION, HOST, HeaderMap, HeaderValue, USER_AGENT};
 use serde::Serialize;
-use tokio::sync::Mutex as TokioMutex;
+use sha2::{Digest, Sha256};
 
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use tokio::time::Instant as TokioInstant;
 
 use crate::http_client::{ProxyConfig, build_client};
-use crate::kiro::db::Database;
+use crate::kiro::alert::{AlertEvent, AlertSender};
+use crate::kiro::circuit_breaker::{CircuitBreaker, CircuitBreakerSettings, CircuitState};
+use crate::kiro::credential_events::{CredentialEvent, CredentialEvents};
+use crate::kiro::db::{self, CredentialUpdate, Database};
+use crate::kiro::latency::{LatencyStatus, LatencyTracker};
 use crate::kiro::machine_id;
 use crate::kiro::model::credentials::KiroCredentials;
 use crate::kiro::model::token_refresh::{
     IdcRefreshRequest, IdcRefreshResponse, RefreshRequest, RefreshResponse,
 };
 use crate::kiro::model::usage_limits::UsageLimitsResponse;
-use crate::model::config::Config;
+use crate::kiro::refresh_lock::{RefreshLock, RefreshLockStatus};
+use crate::kiro::replication;
+use crate::kiro::retry::RetryPolicy;
+use crate::kiro::sigv4;
+use crate::model::config::{Config, PriorityBand};
 
 /// Token 管理器
 ///
@@ -66,10 +78,7 @@ impl TokenManager {
             }
         }
 
-        self.credentials
-            .access_token
-            .clone()
-            .ok_or_else(|| anyhow::anyhow!("没有可用的 accessToken"))
+        access_token_of(&self.credentials)
     }
 
     /// 获取使用额度信息
@@ -94,13 +103,42 @@ pub(crate) fn is_token_expiring_within(
 }
 
 /// 检查 Token 是否已过期（提前 5 分钟判断）
+///
+/// IAM 凭据没有 Token，仅在配置了过期时间（STS 临时凭据）且已过期时视为过期
 pub(crate) fn is_token_expired(credentials: &KiroCredentials) -> bool {
+    if credentials.is_iam() {
+        return is_token_expiring_within(credentials, 0).unwrap_or(false);
+    }
     is_token_expiring_within(credentials, 5).unwrap_or(true)
 }
 
-/// 检查 Token 是否即将过期（10分钟内）
+/// 检查 Token 是否即将过期（10分钟内，IAM 凭据无法刷新，不做提前判断）
 pub(crate) fn is_token_expiring_soon(credentials: &KiroCredentials) -> bool {
-    is_token_expiring_within(credentials, 10).unwrap_or(false)
+    !credentials.is_iam() && is_token_expiring_within(credentials, 10).unwrap_or(false)
+}
+
+/// 请求使用的访问 Token（IAM 凭据改用 SigV4 签名，返回空字符串）
+pub(crate) fn access_token_of(credentials: &KiroCredentials) -> anyhow::Result<String> {
+    if credentials.is_iam() {
+        return Ok(String::new());
+    }
+    credentials
+        .access_token
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("没有可用的 accessToken"))
+}
+
+/// 验证 IAM 凭据的访问密钥是否齐全
+pub(crate) fn validate_iam_keys(credentials: &KiroCredentials) -> anyhow::Result<()> {
+    for (name, value) in [
+        ("accessKeyId", &credentials.access_key_id),
+        ("secretAccessKey", &credentials.secret_access_key),
+    ] {
+        if value.as_deref().is_none_or(|v| v.trim().is_empty()) {
+            bail!("IAM 凭据需要提供 {}", name);
+        }
+    }
+    Ok(())
 }
 
 /// 验证 refreshToken 的基本有效性
@@ -132,6 +170,16 @@ pub async fn refresh_token(
     config: &Config,
     proxy: Option<&ProxyConfig>,
 ) -> anyhow::Result<KiroCredentials> {
+    // 热备实例不刷新 Token，避免轮换主实例正在使用的 refreshToken
+    if replication::is_standby() {
+        bail!("实例处于热备模式，不刷新 Token");
+    }
+
+    // IAM 访问密钥没有刷新机制，STS 临时凭据过期后需要重新写入
+    if credentials.is_iam() {
+        bail!("IAM 凭据已过期且无法自动刷新，请更新 accessKeyId/secretAccessKey/sessionToken");
+    }
+
     validate_refresh_token(credentials)?;
 
     // 根据 auth_method 选择刷新方式
@@ -158,26 +206,30 @@ async fn refresh_social_token(
     let refresh_domain = format!("prod.{}.auth.desktop.kiro.dev", region);
     let machine_id = machine_id::generate_from_credentials(credentials)
         .ok_or_else(|| anyhow::anyhow!("无法生成 machineId"))?;
-    let kiro_version = &config.kiro_version;
+    let kiro_version = credentials.effective_kiro_version(config);
 
     let client = build_client(proxy, 60)?;
     let body = RefreshRequest {
         refresh_token: refresh_token.to_string(),
     };
-
-    let response = client
-        .post(&refresh_url)
-        .header("Accept", "application/json, text/plain, */*")
-        .header("Content-Type", "application/json")
-        .header(
-            "User-Agent",
-            format!("KiroIDE-{}-{}", kiro_version, machine_id),
-        )
-        .header("Accept-Encoding", "gzip, compress, deflate, br")
-        .header("host", &refresh_domain)
-        .header("Connection", "close")
-        .json(&body)
-        .send()
+    let extra_headers = credentials.extra_header_map();
+
+    let response = RetryPolicy::from_config(config)
+        .send_unsent("Social Token 刷新", || {
+            client
+                .post(&refresh_url)
+                .header("Accept", "application/json, text/plain, */*")
+                .header("Content-Type", "application/json")
+                .header(
+                    "User-Agent",
+                    format!("KiroIDE-{}-{}", kiro_version, machine_id),
+                )
+                .header("Accept-Encoding", "gzip, compress, deflate, br")
+                .header("host", &refresh_domain)
+                .header("Connection", "close")
+                .headers(extra_headers.clone())
+                .json(&body)
+        })
         .await?;
 
     let status = response.status();
@@ -214,9 +266,6 @@ async fn refresh_social_token(
     Ok(new_credentials)
 }
 
-/// IdC Token 刷新所需的 x-amz-user-agent header
-const IDC_AMZ_USER_AGENT: &str = "aws-sdk-js/3.738.0 ua/2.1 os/other lang/js md/browser#unknown_unknown api/sso-oidc#3.738.0 m/E KiroIDE";
-
 /// 刷新 IdC Token (AWS SSO OIDC)
 async fn refresh_idc_token(
     credentials: &KiroCredentials,
@@ -235,7 +284,8 @@ async fn refresh_idc_token(
         .as_ref()
         .ok_or_else(|| anyhow::anyhow!("IdC 刷新需要 clientSecret"))?;
 
-    let region = &config.region;
+    // 设备授权登录时指定了区域的凭据按该区域刷新
+    let region = credentials.region.as_deref().unwrap_or(&config.region);
     let refresh_url = format!("https://oidc.{}.amazonaws.com/token", region);
 
     let client = build_client(proxy, 60)?;
@@ -245,20 +295,24 @@ async fn refresh_idc_token(
         refresh_token: refresh_token.to_string(),
         grant_type: "refresh_token".to_string(),
     };
-
-    let response = client
-        .post(&refresh_url)
-        .header("Content-Type", "application/json")
-        .header("Host", format!("oidc.{}.amazonaws.com", region))
-        .header("Connection", "keep-alive")
-        .header("x-amz-user-agent", IDC_AMZ_USER_AGENT)
-        .header("Accept", "*/*")
-        .header("Accept-Language", "*")
-        .header("sec-fetch-mode", "cors")
-        .header("User-Agent", "node")
-        .header("Accept-Encoding", "br, gzip, deflate")
-        .json(&body)
-        .send()
+    let extra_headers = credentials.extra_header_map();
+
+    let response = RetryPolicy::from_config(config)
+        .send_unsent("IdC Token 刷新", || {
+            client
+                .post(&refresh_url)
+                .header("Content-Type", "application/json")
+                .header("Host", format!("oidc.{}.amazonaws.com", region))
+                .header("Connection", "keep-alive")
+                .header("x-amz-user-agent", &config.idc_amz_user_agent)
+                .header("Accept", "*/*")
+                .header("Accept-Language", "*")
+                .header("sec-fetch-mode", "cors")
+                .header("User-Agent", "node")
+                .header("Accept-Encoding", "br, gzip, deflate")
+                .headers(extra_headers.clone())
+                .json(&body)
+        })
         .await?;
 
     let status = response.status();
@@ -291,9 +345,6 @@ async fn refresh_idc_token(
     Ok(new_credentials)
 }
 
-/// getUsageLimits API 所需的 x-amz-user-agent header 前缀
-const USAGE_LIMITS_AMZ_USER_AGENT_PREFIX: &str = "aws-sdk-js/1.0.0";
-
 /// 获取使用额度信息
 pub async fn get_usage_limits(
     credentials: &KiroCredentials,
@@ -307,7 +358,10 @@ pub async fn get_usage_limits(
     let host = format!("q.{}.amazonaws.com", region);
     let machine_id = machine_id::generate_from_credentials(credentials)
         .ok_or_else(|| anyhow::anyhow!("无法生成 machineId"))?;
-    let kiro_version = &config.kiro_version;
+    let kiro_version = credentials.effective_kiro_version(config);
+    let os_name = credentials.effective_system_version(config);
+    let node_version = credentials.effective_node_version(config);
+    let sdk_version = &config.runtime_sdk_version;
 
     // 构建 URL
     let mut url = format!(
@@ -322,27 +376,50 @@ pub async fn get_usage_limits(
 
     // 构建 User-Agent headers
     let user_agent = format!(
-        "aws-sdk-js/1.0.0 ua/2.1 os/darwin#24.6.0 lang/js md/nodejs#22.21.1 \
-         api/codewhispererruntime#1.0.0 m/N,E KiroIDE-{}-{}",
-        kiro_version, machine_id
+        "aws-sdk-js/{} ua/2.1 os/{} lang/js md/nodejs#{} \
+         api/codewhispererruntime#{} m/N,E KiroIDE-{}-{}",
+        sdk_version, os_name, node_version, sdk_version, kiro_version, machine_id
     );
     let amz_user_agent = format!(
-        "{} KiroIDE-{}-{}",
-        USAGE_LIMITS_AMZ_USER_AGENT_PREFIX, kiro_version, machine_id
+        "aws-sdk-js/{} KiroIDE-{}-{}",
+        sdk_version, kiro_version, machine_id
     );
 
-    let client = build_client(proxy, 60)?;
+    if credentials.is_iam() {
+        validate_iam_keys(credentials)?;
+    }
 
-    let response = client
-        .get(&url)
-        .header("x-amz-user-agent", &amz_user_agent)
-        .header("User-Agent", &user_agent)
-        .header("host", &host)
-        .header("amz-sdk-invocation-id", uuid::Uuid::new_v4().to_string())
-        .header("amz-sdk-request", "attempt=1; max=1")
-        .header("Authorization", format!("Bearer {}", token))
-        .header("Connection", "close")
-        .send()
+    let client = build_client(proxy, 60)?;
+    let mut base_headers = HeaderMap::new();
+    base_headers.insert("x-amz-user-agent", HeaderValue::from_str(&amz_user_agent)?);
+    base_headers.insert(USER_AGENT, HeaderValue::from_str(&user_agent)?);
+    base_headers.insert(HOST, HeaderValue::from_str(&host)?);
+    base_headers.insert(CONNECTION, HeaderValue::from_static("close"));
+    base_headers.extend(credentials.extra_header_map());
+
+    let policy = RetryPolicy::from_config(config);
+    let attempt = AtomicUsize::new(0);
+    let response = policy
+        .send("获取使用额度", || {
+            let attempt = attempt.fetch_add(1, Ordering::Relaxed) + 1;
+            let mut headers = base_headers.clone();
+            headers.insert(
+                "amz-sdk-invocation-id",
+                HeaderValue::from_str(&uuid::Uuid::new_v4().to_string()).unwrap(),
+            );
+            headers.insert(
+                "amz-sdk-request",
+                HeaderValue::from_str(&format!("attempt={}; max={}", attempt, policy.max_attempts))
+                    .unwrap(),
+            );
+            // 每次尝试重新签名（SigV4 签名包含请求时间）
+            if let Err(e) =
+                sigv4::authorize(credentials, token, "GET", &url, &mut headers, b"", region)
+            {
+                tracing::warn!("设置获取使用额度请求的认证信息失败: {}", e);
+            }
+            client.get(&url).headers(headers)
+        })
         .await?;
 
     let status = response.status();
@@ -382,6 +459,10 @@ pub struct CredentialEntrySnapshot {
     pub disabled: bool,
     /// 连续失败次数
     pub failure_count: u32,
+    /// 熔断器状态
+    pub circuit_state: CircuitState,
+    /// 熔断结束时间（RFC3339，未熔断时为 None）
+    pub circuit_open_until: Option<String>,
     /// 认证方式
     pub auth_method: Option<String>,
     /// 是否有 Profile ARN
@@ -392,6 +473,26 @@ pub struct CredentialEntrySnapshot {
     pub machine_id: Option<String>,
     /// 账号邮箱
     pub email: Option<String>,
+    /// Kiro IDE 版本覆盖
+    pub kiro_version: Option<String>,
+    /// 系统版本覆盖
+    pub system_version: Option<String>,
+    /// Node.js 版本覆盖
+    pub node_version: Option<String>,
+    /// 允许使用的模型
+    pub allowed_models: Option<Vec<String>>,
+    /// 自定义上游请求头名称
+    pub extra_header_names: Vec<String>,
+    /// 订阅类型（缓存）
+    pub subscription_title: Option<String>,
+    /// 当前使用量（缓存）
+    pub current_usage: f64,
+    /// 使用限额（缓存，0 表示尚未查询）
+    pub usage_limit: f64,
+    /// 下次重置时间（缓存，Unix 时间戳）
+    pub next_reset_at: Option<f64>,
+    /// 余额缓存更新时间（RFC3339）
+    pub balance_updated_at: Option<String>,
 }
 
 /// 凭据管理器状态快照
@@ -419,18 +520,39 @@ pub struct MultiTokenManager {
     config: Config,
     proxy: Option<ProxyConfig>,
     /// 当前活动凭据 ID（仅内存，重启后按优先级重新选择）
-    current_id: Mutex<u64>,
+    ///
+    /// 切换时使用 compare-and-swap：只有仍为观察到的旧值时才写入，
+    /// 避免并发失败时后到的切换覆盖先到的结果
+    current_id: AtomicU64,
     /// Token 刷新锁，确保同一时间只有一个刷新操作
-    refresh_lock: TokioMutex<()>,
+    refresh_lock: RefreshLock,
+    /// 凭据延迟跟踪（延迟过高时临时降级）
+    latency: LatencyTracker,
+    /// 凭据熔断器（连续失败时禁用，熔断期过后半开探测）
+    breaker: CircuitBreaker,
+    /// 因剩余额度不足被跳过的凭据及其额度重置时间（Unix 时间戳，仅内存）
+    quota_skipped: Mutex<HashMap<u64, f64>>,
+    /// 保留凭据 ID 缓存（计算时间, ID 列表），避免每次选择凭据都读取全部凭据
+    reserved_cache: Mutex<Option<(std::time::Instant, Arc<Vec<u64>>)>>,
     /// SQLite 数据库连接（唯一数据源）
     db: Arc<Database>,
+    /// 告警事件发送句柄（未配置邮件告警时为 None）
+    alerts: Option<AlertSender>,
+    /// 凭据状态变更事件广播（Admin WebSocket 订阅）
+    events: CredentialEvents,
 }
 
-/// 每个凭据最大 API 调用失败次数
-const MAX_FAILURES_PER_CREDENTIAL: u32 = 3;
-
-/// 禁用凭据自动恢复冷却时间（秒）
-const DISABLED_COOLDOWN_SECONDS: i64 = 300; // 5 分钟
+/// 请求优先级类别
+///
+/// 决定选择凭据时能否使用尚未启用的优先级分段（见 `priorityBands`）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RequestPriority {
+    /// 普通请求：遵守优先级分段的保留规则
+    #[default]
+    Standard,
+    /// 延迟敏感请求：当前凭据降级、额度不足或不支持模型时，可直接使用保留分段的凭据
+    Priority,
+}
 
 /// API 调用上下文
 ///
@@ -442,10 +564,205 @@ pub struct CallContext {
     pub id: u64,
     /// 凭据信息（用于构建请求头）
     pub credentials: KiroCredentials,
-    /// 访问 Token
+    /// 访问 Token（IAM 凭据为空，请求改用 SigV4 签名）
     pub token: String,
 }
 
+/// 凭据获取失败
+///
+/// 时间预算耗尽或所有凭据均已尝试时返回，记录每个已尝试凭据的失败原因，
+/// 由调用方转换为 503 响应
+#[derive(Debug)]
+pub struct AcquireError {
+    /// 是否因时间预算耗尽
+    pub timed_out: bool,
+    /// 已耗时
+    pub elapsed: std::time::Duration,
+    /// 凭据总数
+    pub total: usize,
+    /// 已尝试的凭据 ID 及失败原因
+    pub failures: Vec<(u64, String)>,
+}
+
+impl std::fmt::Display for AcquireError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.timed_out {
+            write!(f, "获取凭据超时（{:.1}s）", self.elapsed.as_secs_f64())?;
+        } else {
+            write!(f, "所有凭据均无法获取有效 Token")?;
+        }
+        write!(f, "，已尝试 {}/{} 个凭据", self.failures.len(), self.total)?;
+        for (i, (id, reason)) in self.failures.iter().enumerate() {
+            let sep = if i == 0 { "：" } else { "；" };
+            write!(f, "{}#{} {}", sep, id, reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AcquireError {}
+
+/// 凭据租约
+///
+/// 绑定一次请求使用的凭据，请求结束时结算调用结果（上游返回成功时，
+/// 租约随响应返回，在响应体读取结束后结算，见 [`ApiResponse::into_body`](crate::kiro::provider::ApiResponse::into_body)）：
+/// - `succeed`: 报告成功（重置失败计数）
+/// - `fail`: 报告失败（累计失败计数，达到阈值时禁用并切换凭据）
+/// - `release`: 不影响凭据状态（如客户端请求错误）
+/// - `invalidate_token`: 不计失败，但使 accessToken 失效（上游 401）
+///
+/// 未结算即被丢弃（新增代码路径遗漏结算、请求被取消等）时，
+/// 按 `leaseFailureOnDrop` 配置决定是否计为失败（默认不计）
+pub struct CredentialLease {
+    manager: Arc<MultiTokenManager>,
+    ctx: CallContext,
+    settled: bool,
+}
+
+impl CredentialLease {
+    /// 调用上下文
+    pub fn context(&self) -> &CallContext {
+        &self.ctx
+    }
+
+    /// 凭据 ID
+    pub fn id(&self) -> u64 {
+        self.ctx.id
+    }
+
+    /// 报告调用成功
+    pub async fn succeed(mut self) {
+        self.settled = true;
+        let id = self.ctx.id;
+        self.manager.blocking(move |tm| tm.report_success(id)).await;
+    }
+
+    /// 报告调用失败，返回是否还有可用凭据
+    pub async fn fail(mut self) -> bool {
+        self.settled = true;
+        let id = self.ctx.id;
+        self.manager.blocking(move |tm| tm.report_failure(id)).await
+    }
+
+    /// 释放租约，不影响凭据状态
+    pub fn release(mut self) {
+        self.settled = true;
+    }
+
+    /// 上游拒绝了本地认为未过期的 Token（通常是时钟偏差）：使其失效以便下次强制刷新，不计为失败
+    ///
+    /// Token 本地已判定过期、IAM 凭据（没有可失效的 Token）或写入数据库失败时返回租约，
+    /// 由调用方按失败结算
+    pub async fn invalidate_token(mut self) -> Result<(), Self> {
+        if self.ctx.credentials.is_iam() || is_token_expired(&self.ctx.credentials) {
+            return Err(self);
+        }
+        let id = self.ctx.id;
+        let token = self.ctx.token.clone();
+        // Token 已被其他请求刷新时不做修改，下次直接使用新 Token
+        let result = self
+            .manager
+            .db
+            .call(move |db| db.invalidate_access_token(id, &token))
+            .await;
+        if let Err(e) = result {
+            tracing::warn!("使凭据 #{} 的 accessToken 失效失败: {}", id, e);
+            return Err(self);
+        }
+        self.settled = true;
+        Ok(())
+    }
+}
+
+impl Drop for CredentialLease {
+    fn drop(&mut self) {
+        if self.settled || !self.manager.config.lease_failure_on_drop {
+            return;
+        }
+        let id = self.ctx.id;
+        tracing::debug!("凭据 #{} 的租约未结算即被丢弃，计为调用失败", id);
+        let manager = self.manager.clone();
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn_blocking(move || manager.report_failure(id));
+        } else {
+            manager.report_failure(id);
+        }
+    }
+}
+
+/// 从凭据列表中选择允许指定模型的凭据（优先级最高、未禁用、未被排除）
+fn pick_for_model(
+    credentials: Vec<KiroCredentials>,
+    model_id: &str,
+    excluded: &[u64],
+) -> Option<KiroCredentials> {
+    credentials
+        .into_iter()
+        .filter(|c| !c.disabled && c.allows_model(model_id))
+        .filter(|c| c.id.is_some_and(|id| !excluded.contains(&id)))
+        .min_by_key(|c| (c.priority, c.id))
+}
+
+/// 按会话键选择凭据（rendezvous 哈希：取会话键与凭据 ID 哈希值最大的凭据）
+///
+/// 候选凭据增减时，只有原本映射到该凭据的会话会改变，其余会话保持不变；
+/// 哈希与进程无关，多实例部署时同一会话映射到同一凭据
+fn pick_sticky<'a>(
+    credentials: &'a [KiroCredentials],
+    session: &str,
+) -> Option<&'a KiroCredentials> {
+    credentials
+        .iter()
+        .filter_map(|c| c.id.map(|id| (c, sticky_score(session, id))))
+        .max_by_key(|(_, score)| *score)
+        .map(|(c, _)| c)
+}
+
+fn sticky_score(session: &str, id: u64) -> u64 {
+    let digest = Sha256::new()
+        .chain_update(session.as_bytes())
+        .chain_update(id.to_be_bytes())
+        .finalize();
+    u64::from_be_bytes(digest[..8].try_into().unwrap())
+}
+
+/// 上游未返回额度重置时间的凭据因额度不足被跳过后，重新查询余额并按优先级选择的间隔（秒）
+pub const QUOTA_RECHECK_INTERVAL_SECS: i64 = 600;
+
+/// 保留凭据 ID 的缓存时长（余额刷新改变利用率后，最长经过该时长生效）
+const RESERVED_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// 计算保留的凭据 ID：优先级更高（数值小于分段下限）的凭据平均利用率未达到分段启用阈值时，
+/// 分段内的凭据保留不用。没有更高优先级的凭据时分段始终启用
+fn reserved_ids(
+    bands: &[PriorityBand],
+    credentials: &[KiroCredentials],
+    now: DateTime<Utc>,
+) -> Vec<u64> {
+    let mut reserved = Vec::new();
+    for band in bands.iter().filter(|b| b.activation_threshold > 0.0) {
+        let above: Vec<f64> = credentials
+            .iter()
+            .filter(|c| c.priority < band.min_priority)
+            .map(|c| c.utilization(now))
+            .collect();
+        if above.is_empty() {
+            continue;
+        }
+        let utilization = above.iter().sum::<f64>() / above.len() as f64;
+        if utilization >= band.activation_threshold {
+            continue;
+        }
+        reserved.extend(
+            credentials
+                .iter()
+                .filter(|c| (band.min_priority..=band.max_priority).contains(&c.priority))
+                .filter_map(|c| c.id),
+        );
+    }
+    reserved
+}
+
 impl MultiTokenManager {
     /// 创建多凭据 Token 管理器
     ///
@@ -467,14 +784,72 @@ impl MultiTokenManager {
             .unwrap_or(0);
 
         Ok(Self {
+            latency: LatencyTracker::new(config.latency_demotion_threshold_ms),
+            breaker: CircuitBreaker::new(&config),
             config,
             proxy,
-            current_id: Mutex::new(initial_id),
-            refresh_lock: TokioMutex::new(()),
+            current_id: AtomicU64::new(initial_id),
+            refresh_lock: RefreshLock::new(),
+            quota_skipped: Mutex::new(HashMap::new()),
+            reserved_cache: Mutex::new(None),
             db,
+            alerts: None,
+            events: CredentialEvents::default(),
         })
     }
 
+    /// 设置告警事件发送句柄
+    pub fn with_alerts(mut self, alerts: Option<AlertSender>) -> Self {
+        self.alerts = alerts;
+        self
+    }
+
+    /// 凭据状态变更事件广播
+    pub fn events(&self) -> &CredentialEvents {
+        &self.events
+    }
+
+    /// 发布凭据的最新禁用状态与失败计数（没有订阅者时不查询数据库）
+    fn publish_status(&self, id: u64) {
+        // 禁用状态影响分段利用率
+        self.invalidate_reserved_ids();
+        if !self.events.has_subscribers() {
+            return;
+        }
+        if let Ok(Some(cred)) = self.db.get_credential(id) {
+            self.publish_credential_status(&cred);
+        }
+    }
+
+    /// 按已读取的凭据推送状态变更事件（不访问数据库，可在异步上下文中调用）
+    fn publish_credential_status(&self, cred: &KiroCredentials) {
+        self.invalidate_reserved_ids();
+        if let Some(id) = cred.id {
+            self.events.publish(CredentialEvent::StatusChanged {
+                id,
+                disabled: cred.disabled,
+                failure_count: cred.failure_count,
+            });
+        }
+    }
+
+    /// 提交告警事件（未配置告警时忽略）
+    fn alert(&self, event: AlertEvent) {
+        if let Some(alerts) = &self.alerts {
+            alerts.notify(event);
+        }
+    }
+
+    /// 在阻塞线程池中调用同步方法（供异步上下文使用，同步方法内部会访问 SQLite）
+    pub async fn blocking<T, F>(self: &Arc<Self>, f: F) -> T
+    where
+        F: FnOnce(&MultiTokenManager) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let manager = self.clone();
+        db::blocking(move || f(&manager)).await
+    }
+
     /// 获取数据库引用
     pub fn database(&self) -> &Arc<Database> {
         &self.db
@@ -490,9 +865,29 @@ impl MultiTokenManager {
         &self.proxy
     }
 
+    /// 读取当前活动凭据 ID
+    pub fn current(&self) -> u64 {
+        self.current_id.load(Ordering::Acquire)
+    }
+
+    /// 仅当当前凭据仍为 `observed` 时切换到 `new_id`
+    ///
+    /// 返回是否切换成功；失败说明其他请求已完成切换，应以其结果为准
+    fn compare_and_switch(&self, observed: u64, new_id: u64) -> bool {
+        let switched = self
+            .current_id
+            .compare_exchange(observed, new_id, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok();
+        if switched && observed != new_id {
+            self.events
+                .publish(CredentialEvent::CurrentChanged { id: new_id });
+        }
+        switched
+    }
+
     /// 获取当前活动凭据的克隆
     pub fn credentials(&self) -> KiroCredentials {
-        let current_id = *self.current_id.lock();
+        let current_id = self.current();
         self.db
             .get_credential(current_id)
             .ok()
@@ -500,14 +895,20 @@ impl MultiTokenManager {
             .unwrap_or_default()
     }
 
-    /// 获取凭据总数
+    /// 获取凭据总数（查询失败时记录日志并返回 0）
     pub fn total_count(&self) -> usize {
-        self.db.count_credentials().unwrap_or(0)
+        self.db.count_credentials().unwrap_or_else(|e| {
+            tracing::warn!("查询凭据总数失败: {}", e);
+            0
+        })
     }
 
-    /// 获取可用凭据数量
+    /// 获取可用凭据数量（查询失败时记录日志并返回 0）
     pub fn available_count(&self) -> usize {
-        self.db.count_available().unwrap_or(0)
+        self.db.count_available().unwrap_or_else(|e| {
+            tracing::warn!("查询可用凭据数量失败: {}", e);
+            0
+        })
     }
 
     /// 获取 API 调用上下文
@@ -518,104 +919,582 @@ impl MultiTokenManager {
     /// 如果 Token 过期或即将过期，会自动刷新
     /// Token 刷新失败时会尝试下一个可用凭据（不计入失败次数）
     ///
-    /// 会自动恢复冷却期已过的禁用凭据
+    /// 熔断期已过的禁用凭据会作为半开探测优先使用（同一凭据同时只有一个探测请求）
+    ///
+    /// 整个过程受 `credentialAcquireTimeoutSecs` 时间预算约束，预算耗尽或所有凭据均失败时
+    /// 返回 `AcquireError`
     pub async fn acquire_context(&self) -> anyhow::Result<CallContext> {
-        // 尝试恢复冷却期已过的禁用凭据
-        if let Err(e) = self.db.try_recover_disabled(DISABLED_COOLDOWN_SECONDS) {
-            tracing::warn!("尝试恢复禁用凭据失败: {}", e);
+        self.acquire_context_for_model(None, RequestPriority::Standard, None)
+            .await
+    }
+
+    /// 获取指定模型的 API 调用上下文
+    ///
+    /// 当前凭据不允许 `model_id` 时，仅为本次请求选用优先级最高的允许该模型的凭据，
+    /// 不改变当前凭据；`priority` 为 `Priority` 时不受优先级分段保留的限制
+    ///
+    /// 指定 `session`（粘性会话键）时，优先使用该会话固定映射的凭据，同样不改变当前凭据
+    pub async fn acquire_context_for_model(
+        &self,
+        model_id: Option<&str>,
+        priority: RequestPriority,
+        session: Option<&str>,
+    ) -> anyhow::Result<CallContext> {
+        let started = std::time::Instant::now();
+        let deadline = (self.config.credential_acquire_timeout_secs > 0).then(|| {
+            TokioInstant::now()
+                + std::time::Duration::from_secs(self.config.credential_acquire_timeout_secs)
+        });
+
+        // 熔断期已过的凭据进入半开状态，本次请求作为探测请求
+        if let Some(ctx) = self.try_half_open_probe(model_id, deadline).await {
+            return Ok(ctx);
+        }
+        self.recover_quota_skipped().await;
+        self.recover_latency_demoted().await;
+
+        // 粘性会话：同一会话固定使用同一凭据（不可用时按常规流程选择）
+        if let Some(session) = session
+            && let Some(ctx) = self
+                .try_sticky_session(session, model_id, priority, deadline)
+                .await
+        {
+            return Ok(ctx);
         }
 
-        let total = self.total_count();
-        let mut tried_count = 0;
+        let total = self.db.call(|db| db.count_credentials()).await?;
+        // 本次请求中 Token 刷新失败的凭据及原因
+        let mut failures: Vec<(u64, String)> = Vec::new();
+        // 本次请求中 Token 刷新失败的模型专用凭据
+        let mut excluded: Vec<u64> = Vec::new();
 
         loop {
-            if tried_count >= total {
-                anyhow::bail!(
-                    "所有凭据均无法获取有效 Token（可用: {}/{}）",
-                    self.available_count(),
-                    total
-                );
+            let timed_out = deadline.is_some_and(|d| TokioInstant::now() >= d);
+            if timed_out || failures.len() >= total {
+                return Err(AcquireError {
+                    timed_out,
+                    elapsed: started.elapsed(),
+                    total,
+                    failures,
+                }
+                .into());
             }
 
-            let (id, credentials) = {
-                let current_id = *self.current_id.lock();
+            let observed = self.current();
 
-                // 尝试获取当前凭据
-                if let Some(cred) = self.db.get_credential(current_id)? {
-                    if !cred.disabled {
-                        (current_id, cred)
+            // 保留分段中的凭据：普通请求不使用；高优先级请求可以使用，
+            // 但只为本次请求单独选择，不将全局当前凭据切换过去（避免普通请求随之落入保留分段）
+            let reserved_band = self.reserved_ids().await?;
+            let reserved = match priority {
+                RequestPriority::Standard => reserved_band.clone(),
+                RequestPriority::Priority => Arc::default(),
+            };
+            // 本次请求使用的凭据是否为全局当前凭据
+            let mut is_current = true;
+
+            // 尝试获取当前凭据
+            let (mut id, mut credentials) =
+                match self.db.call(move |db| db.get_credential(observed)).await? {
+                    Some(cred) if !cred.disabled => (observed, cred),
+                    _ => {
+                        // 当前凭据不存在或已禁用，选择优先级最高的可用凭据
+                        let best = self
+                            .db
+                            .call(|db| db.get_highest_priority_available())
+                            .await?;
+                        let Some(cred) = best else {
+                            let available = self.count_available_nonblocking().await?;
+                            anyhow::bail!("所有凭据均已禁用（{}/{}）", available, total);
+                        };
+                        let new_id = cred.id.unwrap();
+                        if priority == RequestPriority::Priority && reserved_band.contains(&new_id)
+                        {
+                            is_current = false;
+                        } else if !self.compare_and_switch(observed, new_id) {
+                            // 其他请求已切换凭据，重新读取当前凭据
+                            continue;
+                        }
+                        (new_id, cred)
+                    }
+                };
+
+            // 当前凭据所在的优先级分段尚未启用时，切换到优先级最高的未保留凭据
+            // （跳过本次请求中刷新失败的凭据；没有则继续使用）。高优先级请求不保留任何凭据
+            if reserved.contains(&id) {
+                // 候选凭据需同时满足未降级、额度充足（与其他切换分支一致）
+                let demoted = self.latency.demoted_ids();
+                let now = Utc::now();
+                let all = self.db.call(|db| db.load_credentials()).await?;
+                let fallback = all
+                    .into_iter()
+                    .filter(|c| !c.disabled && !self.is_quota_exhausted(c, now))
+                    .filter(|c| {
+                        c.id.is_some_and(|cid| {
+                            !reserved.contains(&cid)
+                                && !demoted.contains(&cid)
+                                && !failures.iter().any(|(f, _)| *f == cid)
+                        })
+                    })
+                    .min_by_key(|c| (c.priority, c.id));
+                if let Some(cred) = fallback {
+                    let new_id = cred.id.unwrap();
+                    if self.compare_and_switch(id, new_id) {
+                        tracing::info!(
+                            "凭据 #{} 所在的优先级分段未启用，切换到凭据 #{}",
+                            id,
+                            new_id
+                        );
+                    }
+                    continue;
+                }
+            }
+
+            // 当前凭据因延迟过高被降级时，切换到优先级最高的未降级凭据
+            // （跳过本次请求中刷新失败的凭据；没有则继续使用）
+            if is_current && self.latency.is_demoted(id) {
+                let demoted = self.latency.demoted_ids();
+                let now = Utc::now();
+                let all = self.db.call(|db| db.load_credentials()).await?;
+                let fallback = all
+                    .into_iter()
+                    .filter(|c| !c.disabled && !self.is_quota_exhausted(c, now))
+                    .filter(|c| {
+                        c.id.is_some_and(|cid| {
+                            !reserved.contains(&cid)
+                                && !demoted.contains(&cid)
+                                && !failures.iter().any(|(f, _)| *f == cid)
+                        })
+                    })
+                    .min_by_key(|c| (c.priority, c.id));
+                if let Some(cred) = fallback {
+                    let new_id = cred.id.unwrap();
+                    if reserved_band.contains(&new_id) {
+                        (id, credentials, is_current) = (new_id, cred, false);
                     } else {
-                        // 当前凭据已禁用，选择优先级最高的可用凭据
-                        if let Some(cred) = self.db.get_highest_priority_available()? {
-                            let new_id = cred.id.unwrap();
-                            *self.current_id.lock() = new_id;
-                            (new_id, cred)
-                        } else {
-                            anyhow::bail!(
-                                "所有凭据均已禁用（{}/{}）",
-                                self.available_count(),
-                                total
-                            );
+                        if self.compare_and_switch(id, new_id) {
+                            tracing::info!("凭据 #{} 延迟降级中，切换到凭据 #{}", id, new_id);
                         }
+                        continue;
                     }
-                } else {
-                    // 当前凭据不存在，选择优先级最高的可用凭据
-                    if let Some(cred) = self.db.get_highest_priority_available()? {
-                        let new_id = cred.id.unwrap();
-                        *self.current_id.lock() = new_id;
-                        (new_id, cred)
+                }
+            }
+
+            // 当前凭据剩余额度不足时，切换到优先级最高的额度充足凭据
+            // （优先未降级的凭据；没有则继续使用，额度缓存可能已过期）
+            let now = Utc::now();
+            if is_current && self.is_quota_exhausted(&credentials, now) {
+                let demoted = self.latency.demoted_ids();
+                let all = self.db.call(|db| db.load_credentials()).await?;
+                let candidates: Vec<_> = all
+                    .into_iter()
+                    .filter(|c| !c.disabled && !self.is_quota_exhausted(c, now))
+                    .filter(|c| c.id.is_some_and(|cid| !reserved.contains(&cid)))
+                    .collect();
+                let fallback = candidates
+                    .iter()
+                    .filter(|c| c.id.is_some_and(|cid| !demoted.contains(&cid)))
+                    .min_by_key(|c| (c.priority, c.id))
+                    .or_else(|| candidates.iter().min_by_key(|c| (c.priority, c.id)));
+                if let Some(cred) = fallback {
+                    let new_id = cred.id.unwrap();
+                    // 上游未返回额度重置时间时，到期后重新按优先级选择（余额由后台任务重新查询）
+                    let reset_at = credentials
+                        .next_reset_at
+                        .unwrap_or((now.timestamp() + QUOTA_RECHECK_INTERVAL_SECS) as f64);
+                    self.quota_skipped.lock().insert(id, reset_at);
+                    if reserved_band.contains(&new_id) {
+                        (id, credentials, is_current) = (new_id, cred.clone(), false);
                     } else {
-                        anyhow::bail!("所有凭据均已禁用（{}/{}）", self.available_count(), total);
+                        if self.compare_and_switch(id, new_id) {
+                            tracing::info!("凭据 #{} 剩余额度不足，切换到凭据 #{}", id, new_id);
+                        }
+                        continue;
                     }
                 }
+            }
+
+            // 当前凭据不允许请求的模型时，为本次请求单独选择凭据
+            // （优先未保留、未降级、额度充足的凭据）
+            let (id, credentials, is_current) = match model_id {
+                Some(model) if !credentials.allows_model(model) => {
+                    let all = self.db.call(|db| db.load_credentials()).await?;
+                    let mut sufficient = excluded.clone();
+                    sufficient.extend(reserved.iter());
+                    sufficient.extend(
+                        all.iter()
+                            .filter(|c| self.is_quota_exhausted(c, now))
+                            .filter_map(|c| c.id),
+                    );
+                    let mut avoided = sufficient.clone();
+                    avoided.extend(self.latency.demoted_ids());
+                    let Some(cred) = pick_for_model(all.clone(), model, &avoided)
+                        .or_else(|| pick_for_model(all.clone(), model, &sufficient))
+                        .or_else(|| pick_for_model(all, model, &excluded))
+                    else {
+                        anyhow::bail!("没有支持模型 {} 的可用凭据", model);
+                    };
+                    (cred.id.unwrap(), cred, false)
+                }
+                _ => (id, credentials, is_current),
             };
 
             // 尝试获取/刷新 Token
-            match self.try_ensure_token(id, &credentials).await {
+            match self.try_ensure_token(id, &credentials, deadline).await {
                 Ok(ctx) => {
                     return Ok(ctx);
                 }
+                Err(e) if !is_current => {
+                    tracing::warn!("凭据 #{} Token 刷新失败，尝试下一个凭据: {}", id, e);
+                    excluded.push(id);
+                    failures.push((id, e.to_string()));
+                }
                 Err(e) => {
                     tracing::warn!("凭据 #{} Token 刷新失败，尝试下一个凭据: {}", id, e);
 
                     // Token 刷新失败，切换到下一个优先级的凭据（不计入失败次数）
-                    self.switch_to_next_by_priority();
-                    tried_count += 1;
+                    self.switch_to_next_by_priority(id).await;
+                    failures.push((id, e.to_string()));
                 }
             }
         }
     }
 
-    /// 切换到下一个优先级最高的可用凭据（内部方法）
-    fn switch_to_next_by_priority(&self) {
-        let current_id = *self.current_id.lock();
+    /// 为熔断期已过的凭据发起半开探测（没有可探测的凭据时返回 None）
+    ///
+    /// 探测请求的调用结果决定熔断器关闭（重新启用）还是重新打开，见 `report_success` / `report_failure`
+    async fn try_half_open_probe(
+        &self,
+        model_id: Option<&str>,
+        deadline: Option<TokioInstant>,
+    ) -> Option<CallContext> {
+        // 按最短的熔断持续时间查询，再按各凭据认证方式的持续时间过滤
+        let open_duration = self.breaker.min_open_duration();
+        let (candidates, disabled_since) = match self
+            .db
+            .call(move |db| anyhow::Ok((db.list_cooled_down(open_duration)?, db.disabled_since()?)))
+            .await
+        {
+            Ok(result) => result,
+            Err(e) => {
+                tracing::warn!("查询熔断期已过的凭据失败: {}", e);
+                return None;
+            }
+        };
 
-        // 选择优先级最高的未禁用凭据（排除当前凭据）
-        if let Ok(Some(cred)) = self.db.get_next_available(current_id) {
+        let now = Utc::now();
+        for cred in candidates {
+            let id = cred.id?;
+            let (state, _) = self.breaker.state(
+                id,
+                cred.auth_method.as_deref(),
+                disabled_since.get(&id).copied(),
+                now,
+            );
+            if state == CircuitState::Open
+                || model_id.is_some_and(|model| !cred.allows_model(model))
+                || !self.breaker.try_begin_probe(id, std::time::Instant::now())
+            {
+                continue;
+            }
+            self.publish_credential_status(&cred);
+            tracing::info!("凭据 #{} 熔断期已过，发送半开探测请求", id);
+            match self.try_ensure_token(id, &cred, deadline).await {
+                Ok(ctx) => return Some(ctx),
+                Err(e) => {
+                    tracing::warn!("凭据 #{} 半开探测 Token 刷新失败: {}", id, e);
+                    // 异步上下文中不能直接调用同步的 reopen_after_probe（会阻塞运行时线程）
+                    self.breaker.end_probe(id);
+                    if let Err(e) = self.db.call(move |db| db.set_disabled(id, true)).await {
+                        tracing::warn!("重新禁用凭据 #{} 失败: {}", id, e);
+                    }
+                    tracing::warn!(
+                        "凭据 #{} 半开探测失败，重新熔断 {} 秒",
+                        id,
+                        self.breaker
+                            .open_duration(cred.auth_method.as_deref())
+                            .as_secs()
+                    );
+                    self.publish_credential_status(&cred);
+                }
+            }
+        }
+        None
+    }
+
+    /// 使用粘性会话映射的凭据（没有可用凭据或 Token 刷新失败时返回 None）
+    ///
+    /// 候选凭据为未禁用、允许该模型、未保留、未降级且额度充足的凭据；
+    /// 映射的凭据变为不可用期间，会话临时映射到其余候选凭据中的一个
+    async fn try_sticky_session(
+        &self,
+        session: &str,
+        model_id: Option<&str>,
+        priority: RequestPriority,
+        deadline: Option<TokioInstant>,
+    ) -> Option<CallContext> {
+        let reserved = match priority {
+            RequestPriority::Standard => self.reserved_ids().await.ok()?,
+            RequestPriority::Priority => Arc::default(),
+        };
+        let demoted = self.latency.demoted_ids();
+        let now = Utc::now();
+        let all = match self.db.call(|db| db.load_credentials()).await {
+            Ok(all) => all,
+            Err(e) => {
+                tracing::warn!("读取凭据失败，粘性会话按常规流程选择凭据: {}", e);
+                return None;
+            }
+        };
+        let candidates: Vec<_> = all
+            .into_iter()
+            .filter(|c| !c.disabled && !self.is_quota_exhausted(c, now))
+            .filter(|c| model_id.is_none_or(|model| c.allows_model(model)))
+            .filter(|c| {
+                c.id.is_some_and(|id| !reserved.contains(&id) && !demoted.contains(&id))
+            })
+            .collect();
+        let cred = pick_sticky(&candidates, session)?;
+        let id = cred.id?;
+        match self.try_ensure_token(id, cred, deadline).await {
+            Ok(ctx) => Some(ctx),
+            Err(e) => {
+                tracing::warn!(
+                    "粘性会话凭据 #{} Token 刷新失败，按常规流程选择凭据: {}",
+                    id,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// 凭据的熔断持续时间（取决于认证方式）
+    fn open_duration(&self, id: u64) -> std::time::Duration {
+        let auth_method = self
+            .db
+            .get_credential(id)
+            .ok()
+            .flatten()
+            .and_then(|c| c.auth_method);
+        self.breaker.open_duration(auth_method.as_deref())
+    }
+
+    /// 当前熔断参数
+    pub fn circuit_breaker_settings(&self) -> CircuitBreakerSettings {
+        self.breaker.settings()
+    }
+
+    /// 运行时修改熔断参数（仅作用于当前实例，不写回配置文件）
+    pub fn update_circuit_breaker(
+        &self,
+        settings: CircuitBreakerSettings,
+    ) -> CircuitBreakerSettings {
+        let settings = self.breaker.update(settings);
+        tracing::info!(
+            "熔断参数已更新：阈值 {} 次，窗口 {} 秒，熔断 {} 秒，按认证方式 {:?}",
+            settings.failure_threshold,
+            settings.window_secs,
+            settings.open_secs,
+            settings.open_secs_by_auth_method
+        );
+        let ids: Vec<u64> = self
+            .db
+            .load_credentials()
+            .unwrap_or_default()
+            .iter()
+            .filter(|c| c.disabled)
+            .filter_map(|c| c.id)
+            .collect();
+        for id in ids {
+            self.publish_status(id);
+        }
+        settings
+    }
+
+    /// 探测失败，重新打开熔断器（重新计算熔断期）
+    fn reopen_after_probe(&self, id: u64) {
+        self.breaker.end_probe(id);
+        if let Err(e) = self.db.set_disabled(id, true) {
+            tracing::warn!("重新禁用凭据 #{} 失败: {}", id, e);
+        }
+        tracing::warn!(
+            "凭据 #{} 半开探测失败，重新熔断 {} 秒",
+            id,
+            self.open_duration(id).as_secs()
+        );
+        self.publish_status(id);
+    }
+
+    /// 当前保留（所在优先级分段尚未启用）的凭据 ID
+    ///
+    /// 结果缓存 `RESERVED_CACHE_TTL`：本实例修改优先级、禁用状态、余额或增删凭据时立即失效，
+    /// 其他实例的修改在缓存过期后生效
+    async fn reserved_ids(&self) -> anyhow::Result<Arc<Vec<u64>>> {
+        if self.config.priority_bands.is_empty() {
+            return Ok(Arc::default());
+        }
+        if let Some((computed_at, ids)) = &*self.reserved_cache.lock()
+            && computed_at.elapsed() < RESERVED_CACHE_TTL
+        {
+            return Ok(ids.clone());
+        }
+        let all = self.db.call(|db| db.load_credentials()).await?;
+        let ids = Arc::new(reserved_ids(&self.config.priority_bands, &all, Utc::now()));
+        *self.reserved_cache.lock() = Some((std::time::Instant::now(), ids.clone()));
+        Ok(ids)
+    }
+
+    /// 凭据提供的服务等级（所在优先级分段尚未启用时为 Priority，用于回显 `service_tier`）
+    pub async fn served_priority(&self, id: u64) -> RequestPriority {
+        match self.reserved_ids().await {
+            Ok(reserved) if reserved.contains(&id) => RequestPriority::Priority,
+            _ => RequestPriority::Standard,
+        }
+    }
+
+    /// 使保留凭据 ID 缓存失效（凭据优先级、禁用状态、余额或数量变化后调用）
+    pub fn invalidate_reserved_ids(&self) {
+        *self.reserved_cache.lock() = None;
+    }
+
+    /// 凭据缓存的剩余额度是否低于 `quotaSkipThreshold`（未配置阈值时始终为 false）
+    fn is_quota_exhausted(&self, credentials: &KiroCredentials, now: DateTime<Utc>) -> bool {
+        self.config
+            .quota_skip_threshold()
+            .is_some_and(|threshold| credentials.is_quota_exhausted(threshold, now))
+    }
+
+    /// 重新查询因额度不足被跳过、且缺少额度重置时间的凭据余额
+    ///
+    /// 这类凭据无法按重置时间恢复，由后台任务定期调用（见 [`QUOTA_RECHECK_INTERVAL_SECS`]）
+    pub async fn recheck_quota_skipped(&self) {
+        let skipped: Vec<u64> = self.quota_skipped.lock().keys().copied().collect();
+        for id in skipped {
+            let credentials = match self.db.call(move |db| db.get_credential(id)).await {
+                Ok(Some(credentials)) => credentials,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::warn!("读取凭据 #{} 失败: {}", id, e);
+                    continue;
+                }
+            };
+            if credentials.next_reset_at.is_some() {
+                continue;
+            }
+            if let Err(e) = self.refresh_balance(id).await {
+                tracing::warn!("重新查询凭据 #{} 余额失败: {}", id, e);
+            }
+        }
+    }
+
+    /// 被跳过的凭据额度重置时间已过时，重新按优先级选择当前凭据
+    async fn recover_quota_skipped(&self) {
+        let now = Utc::now().timestamp() as f64;
+        let recovered: Vec<u64> = {
+            let mut skipped = self.quota_skipped.lock();
+            let ids: Vec<u64> = skipped
+                .iter()
+                .filter(|(_, reset_at)| **reset_at <= now)
+                .map(|(id, _)| *id)
+                .collect();
+            for id in &ids {
+                skipped.remove(id);
+            }
+            ids
+        };
+        if recovered.is_empty() {
+            return;
+        }
+
+        tracing::info!("凭据 {:?} 额度已重置，恢复按优先级选择", recovered);
+        let current_id = self.current();
+        if let Ok(Some(best)) = self.db.call(|db| db.get_highest_priority_available()).await {
+            let best_id = best.id.unwrap();
+            if best_id != current_id && self.compare_and_switch(current_id, best_id) {
+                tracing::info!("额度重置后切换凭据: #{} -> #{}", current_id, best_id);
+            }
+        }
+    }
+
+    /// 凭据延迟降级到期时，重新按优先级选择当前凭据（切回降级前使用的凭据）
+    async fn recover_latency_demoted(&self) {
+        let recovered = self.latency.take_recovered();
+        if recovered.is_empty() {
+            return;
+        }
+
+        let current_id = self.current();
+        if let Ok(Some(best)) = self.db.call(|db| db.get_highest_priority_available()).await {
+            let best_id = best.id.unwrap();
+            if best_id != current_id && self.compare_and_switch(current_id, best_id) {
+                tracing::info!(
+                    "凭据 {:?} 延迟降级到期后切换凭据: #{} -> #{}",
+                    recovered,
+                    current_id,
+                    best_id
+                );
+            }
+        }
+    }
+
+    /// 获取指定模型的凭据租约
+    ///
+    /// 与 `acquire_context_for_model` 相同，但返回的租约会在请求结束时自动结算调用结果
+    pub async fn lease(
+        self: &Arc<Self>,
+        model_id: Option<&str>,
+        priority: RequestPriority,
+        session: Option<&str>,
+    ) -> anyhow::Result<CredentialLease> {
+        let ctx = self
+            .acquire_context_for_model(model_id, priority, session)
+            .await?;
+        Ok(CredentialLease {
+            manager: self.clone(),
+            ctx,
+            settled: false,
+        })
+    }
+
+    /// 从失败的凭据切换到下一个优先级最高的可用凭据（内部方法）
+    ///
+    /// 如果当前凭据已不是 `failed_id`（其他请求已完成切换），则不做任何改动
+    async fn switch_to_next_by_priority(&self, failed_id: u64) {
+        // 选择优先级最高的未禁用凭据（排除失败的凭据）
+        let next = self
+            .db
+            .call(move |db| db.get_next_available(failed_id))
+            .await;
+        if let Ok(Some(cred)) = next {
             let new_id = cred.id.unwrap();
-            *self.current_id.lock() = new_id;
-            tracing::info!("已切换到凭据 #{}（优先级 {}）", new_id, cred.priority);
+            if self.compare_and_switch(failed_id, new_id) {
+                tracing::info!("已切换到凭据 #{}（优先级 {}）", new_id, cred.priority);
+            }
         }
     }
 
+    /// 获取可用凭据数量（异步上下文使用）
+    async fn count_available_nonblocking(&self) -> anyhow::Result<usize> {
+        self.db.call(|db| db.count_available()).await
+    }
+
     /// 选择优先级最高的未禁用凭据作为当前凭据（内部方法）
     ///
     /// 与 `switch_to_next_by_priority` 不同，此方法不排除当前凭据，
     /// 纯粹按优先级选择，用于优先级变更后立即生效
-    fn select_highest_priority(&self) {
-        let current_id = *self.current_id.lock();
+    pub fn select_highest_priority(&self) {
+        let current_id = self.current();
 
         // 选择优先级最高的未禁用凭据（不排除当前凭据）
         if let Ok(Some(best)) = self.db.get_highest_priority_available() {
             let best_id = best.id.unwrap();
-            if best_id != current_id {
+            if best_id != current_id && self.compare_and_switch(current_id, best_id) {
                 tracing::info!(
                     "优先级变更后切换凭据: #{} -> #{}（优先级 {}）",
                     current_id,
                     best_id,
                     best.priority
                 );
-                *self.current_id.lock() = best_id;
             }
         }
     }
@@ -627,51 +1506,57 @@ impl MultiTokenManager {
     /// # Arguments
     /// * `id` - 凭据 ID，用于更新正确的条目
     /// * `credentials` - 凭据信息
+    /// * `deadline` - 等待刷新锁的截止时间
     async fn try_ensure_token(
         &self,
         id: u64,
         credentials: &KiroCredentials,
+        deadline: Option<TokioInstant>,
     ) -> anyhow::Result<CallContext> {
         // 第一次检查（无锁）：快速判断是否需要刷新
         let needs_refresh = is_token_expired(credentials) || is_token_expiring_soon(credentials);
 
         let creds = if needs_refresh {
-            // 获取刷新锁，确保同一时间只有一个刷新操作
-            let _guard = self.refresh_lock.lock().await;
-
-            // 第二次检查：获取锁后重新读取凭据，因为其他请求可能已经完成刷新
-            let current_creds = self
-                .db
-                .get_credential(id)?
-                .ok_or_else(|| anyhow::anyhow!("凭据 #{} 不存在", id))?;
+            // 持有刷新锁执行，确保同一时间只有一个刷新操作
+            self.refresh_lock
+                .run_until(id, deadline, async {
+                    // 第二次检查：获取锁后重新读取凭据，因为其他请求可能已经完成刷新
+                    let current_creds = self
+                        .db
+                        .call(move |db| db.get_credential(id))
+                        .await?
+                        .ok_or_else(|| anyhow::anyhow!("凭据 #{} 不存在", id))?;
+
+                    if !is_token_expired(&current_creds) && !is_token_expiring_soon(&current_creds)
+                    {
+                        // 其他请求已经完成刷新，直接使用新凭据
+                        tracing::debug!("Token 已被其他请求刷新，跳过刷新");
+                        return Ok(current_creds);
+                    }
+                    if let Some(creds) = self.without_refresh(&current_creds).await? {
+                        return Ok(creds);
+                    }
 
-            if is_token_expired(&current_creds) || is_token_expiring_soon(&current_creds) {
-                // 确实需要刷新
-                let new_creds =
-                    refresh_token(&current_creds, &self.config, self.proxy.as_ref()).await?;
+                    // 确实需要刷新
+                    let new_creds =
+                        refresh_token(&current_creds, &self.config, self.proxy.as_ref()).await?;
 
-                if is_token_expired(&new_creds) {
-                    anyhow::bail!("刷新后的 Token 仍然无效或已过期");
-                }
+                    if is_token_expired(&new_creds) {
+                        anyhow::bail!("刷新后的 Token 仍然无效或已过期");
+                    }
 
-                // 回写凭据到数据库
-                self.db.update_credential(&new_creds)?;
-                tracing::debug!("已持久化凭据 #{} 到数据库", id);
+                    // 回写凭据到数据库
+                    let new_creds = self.persist_refreshed(&current_creds, new_creds).await?;
+                    tracing::debug!("已持久化凭据 #{} 到数据库", id);
 
-                new_creds
-            } else {
-                // 其他请求已经完成刷新，直接使用新凭据
-                tracing::debug!("Token 已被其他请求刷新，跳过刷新");
-                current_creds
-            }
+                    Ok(new_creds)
+                })
+                .await?
         } else {
             credentials.clone()
         };
 
-        let token = creds
-            .access_token
-            .clone()
-            .ok_or_else(|| anyhow::anyhow!("没有可用的 accessToken"))?;
+        let token = access_token_of(&creds)?;
 
         Ok(CallContext {
             id,
@@ -680,58 +1565,161 @@ impl MultiTokenManager {
         })
     }
 
+    /// 只读共享凭据不刷新：accessToken 未过期时继续使用，已过期时报错
+    ///
+    /// 刷新会轮换 refreshToken，使共享库及其他实例中的副本失效，因此共享凭据的 Token
+    /// 由共享库的维护方负责刷新；返回 None 表示不是共享凭据，可以正常刷新
+    async fn without_refresh(
+        &self,
+        credentials: &KiroCredentials,
+    ) -> anyhow::Result<Option<KiroCredentials>> {
+        let Some(id) = credentials.id else {
+            return Ok(None);
+        };
+        if !self.db.call(move |db| db.is_read_only(id)).await {
+            return Ok(None);
+        }
+        if is_token_expired(credentials) {
+            bail!(
+                "凭据 #{} 来自只读的共享凭据库，accessToken 已过期，需由共享库的维护方刷新",
+                id
+            );
+        }
+        Ok(Some(credentials.clone()))
+    }
+
+    /// 持久化刷新后的凭据，处理多实例间的 refreshToken 轮换竞争
+    ///
+    /// 以刷新前的 refreshToken 作为乐观锁写入；写入失败说明其他实例已先完成刷新，
+    /// 此时放弃本次刷新结果，改用数据库中其他实例写入的 Token
+    async fn persist_refreshed(
+        &self,
+        previous: &KiroCredentials,
+        refreshed: KiroCredentials,
+    ) -> anyhow::Result<KiroCredentials> {
+        let id = refreshed.id.ok_or_else(|| anyhow::anyhow!("凭据缺少 ID"))?;
+        let previous_token = previous.refresh_token.clone().unwrap_or_default();
+        let saved = refreshed.clone();
+        let written = self
+            .db
+            .call(move |db| db.save_refreshed_token(&saved, &previous_token))
+            .await?;
+        if written {
+            return Ok(refreshed);
+        }
+
+        let stored = self
+            .db
+            .call(move |db| db.get_credential(id))
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("凭据 #{} 不存在", id))?;
+        if stored.access_token.is_none() || is_token_expired(&stored) {
+            bail!(
+                "凭据 #{} 的 refreshToken 已被其他实例轮换，但数据库中没有有效的 Token",
+                id
+            );
+        }
+        tracing::warn!(
+            "凭据 #{} 的 refreshToken 已被其他实例轮换，放弃本次刷新结果并使用数据库中的 Token",
+            id
+        );
+        Ok(stored)
+    }
+
     /// 报告指定凭据 API 调用成功
     ///
-    /// 重置该凭据的失败计数（持久化到数据库）
+    /// 重置该凭据的失败计数（持久化到数据库）。同步访问凭据存储，异步上下文中需经由
+    /// [`CredentialLease::succeed`] 或 [`Self::blocking`] 调用
     ///
     /// # Arguments
     /// * `id` - 凭据 ID（来自 CallContext）
     pub fn report_success(&self, id: u64) {
-        if let Err(e) = self.db.reset_failure_count(id) {
-            tracing::warn!("重置凭据 #{} 失败计数失败: {}", id, e);
-        } else {
-            tracing::debug!("凭据 #{} API 调用成功", id);
+        self.breaker.record_success(id);
+        if self.breaker.end_probe(id) {
+            // 半开探测成功，关闭熔断器
+            match self.db.reset_and_enable(id) {
+                Ok(_) => tracing::info!("凭据 #{} 半开探测成功，已恢复", id),
+                Err(e) => tracing::warn!("恢复凭据 #{} 失败: {}", id, e),
+            }
+            self.publish_status(id);
+            return;
+        }
+        match self.db.reset_failure_count(id) {
+            Ok(reset) => {
+                tracing::debug!("凭据 #{} API 调用成功", id);
+                if reset {
+                    self.publish_status(id);
+                }
+            }
+            Err(e) => tracing::warn!("重置凭据 #{} 失败计数失败: {}", id, e),
         }
     }
 
     /// 报告指定凭据 API 调用失败
     ///
-    /// 增加失败计数，达到阈值时禁用凭据并切换到优先级最高的可用凭据
-    /// 返回是否还有可用凭据可以重试
+    /// 增加失败计数，达到熔断阈值时禁用凭据并切换到优先级最高的可用凭据；
+    /// 半开探测请求失败时直接重新熔断。返回是否还有可用凭据可以重试。同步访问凭据存储，
+    /// 异步上下文中需经由 [`CredentialLease::fail`] 或 [`Self::blocking`] 调用
     ///
     /// # Arguments
     /// * `id` - 凭据 ID（来自 CallContext）
     pub fn report_failure(&self, id: u64) -> bool {
+        if self.breaker.end_probe(id) {
+            self.reopen_after_probe(id);
+            return self.available_count() > 0;
+        }
+
         // 增加失败计数
-        let failure_count = match self.db.increment_failure_count(id) {
+        let consecutive = match self.db.increment_failure_count(id) {
             Ok(count) => count,
             Err(e) => {
                 tracing::warn!("增加凭据 #{} 失败计数失败: {}", id, e);
                 return self.available_count() > 0;
             }
         };
+        let failure_count = self
+            .breaker
+            .record_failure(id, consecutive, std::time::Instant::now());
 
         tracing::warn!(
             "凭据 #{} API 调用失败（{}/{}）",
             id,
             failure_count,
-            MAX_FAILURES_PER_CREDENTIAL
+            self.breaker.failure_threshold()
         );
 
-        if failure_count >= MAX_FAILURES_PER_CREDENTIAL {
-            // 禁用凭据
+        if !self.breaker.should_trip(failure_count) {
+            self.publish_status(id);
+        } else {
+            // 打开熔断器（禁用凭据）
             if let Err(e) = self.db.set_disabled(id, true) {
                 tracing::warn!("禁用凭据 #{} 失败: {}", id, e);
             }
-            tracing::error!("凭据 #{} 已连续失败 {} 次，已被禁用", id, failure_count);
-
-            // 切换到优先级最高的可用凭据
+            self.breaker.on_open(id);
+            tracing::error!(
+                "凭据 #{} 失败 {} 次，已熔断 {} 秒",
+                id,
+                failure_count,
+                self.open_duration(id).as_secs()
+            );
+            self.publish_status(id);
+            self.alert(AlertEvent::CredentialDisabled {
+                id,
+                failures: failure_count,
+            });
+
+            // 切换到优先级最高的可用凭据（仅当该凭据仍是当前凭据时，
+            // 避免过期的失败报告覆盖其他请求或 Admin 已完成的切换）
             if let Ok(Some(next)) = self.db.get_highest_priority_available() {
                 let next_id = next.id.unwrap();
-                *self.current_id.lock() = next_id;
-                tracing::info!("已切换到凭据 #{}（优先级 {}）", next_id, next.priority);
+                if self.compare_and_switch(id, next_id) {
+                    tracing::info!("已切换到凭据 #{}（优先级 {}）", next_id, next.priority);
+                }
             } else {
                 tracing::error!("所有凭据均已禁用！");
+                self.alert(AlertEvent::AllCredentialsDisabled {
+                    total: self.total_count(),
+                });
                 return false;
             }
         }
@@ -744,13 +1732,15 @@ impl MultiTokenManager {
     ///
     /// 返回是否成功切换
     pub fn switch_to_next(&self) -> bool {
-        let current_id = *self.current_id.lock();
+        let current_id = self.current();
 
         // 选择优先级最高的未禁用凭据（排除当前凭据）
         if let Ok(Some(next)) = self.db.get_next_available(current_id) {
             let next_id = next.id.unwrap();
-            *self.current_id.lock() = next_id;
-            tracing::info!("已切换到凭据 #{}（优先级 {}）", next_id, next.priority);
+            // CAS 失败说明其他请求已切换到别的可用凭据，同样视为成功
+            if self.compare_and_switch(current_id, next_id) {
+                tracing::info!("已切换到凭据 #{}（优先级 {}）", next_id, next.priority);
+            }
             true
         } else {
             // 没有其他可用凭据，检查当前凭据是否可用
@@ -783,22 +1773,50 @@ impl MultiTokenManager {
     /// 获取管理器状态快照（用于 Admin API）
     pub fn snapshot(&self) -> ManagerSnapshot {
         let credentials = self.db.load_credentials().unwrap_or_default();
-        let current_id = *self.current_id.lock();
+        let disabled_since = self.db.disabled_since().unwrap_or_default();
+        let current_id = self.current();
         let available = credentials.iter().filter(|c| !c.disabled).count();
+        let now = Utc::now();
 
         ManagerSnapshot {
             entries: credentials
                 .iter()
-                .map(|c| CredentialEntrySnapshot {
-                    id: c.id.unwrap_or(0),
-                    priority: c.priority,
-                    disabled: c.disabled,
-                    failure_count: c.failure_count,
-                    auth_method: c.auth_method.clone(),
-                    has_profile_arn: c.profile_arn.is_some(),
-                    expires_at: c.expires_at.clone(),
-                    machine_id: c.machine_id.clone(),
-                    email: c.email.clone(),
+                .map(|c| {
+                    let id = c.id.unwrap_or(0);
+                    let (circuit_state, open_until) = self.breaker.state(
+                        id,
+                        c.auth_method.as_deref(),
+                        disabled_since.get(&id).copied(),
+                        now,
+                    );
+                    CredentialEntrySnapshot {
+                        id,
+                        priority: c.priority,
+                        disabled: c.disabled,
+                        failure_count: c.failure_count,
+                        circuit_state,
+                        circuit_open_until: open_until.map(|t| t.to_rfc3339()),
+                        auth_method: c.auth_method.clone(),
+                        has_profile_arn: c.profile_arn.is_some(),
+                        expires_at: c.expires_at.clone(),
+                        machine_id: c.machine_id.clone(),
+                        email: c.email.clone(),
+                        kiro_version: c.kiro_version.clone(),
+                        system_version: c.system_version.clone(),
+                        node_version: c.node_version.clone(),
+                        allowed_models: c.allowed_models.clone(),
+                        extra_header_names: c
+                            .extra_headers
+                            .iter()
+                            .flatten()
+                            .map(|(name, _)| name.clone())
+                            .collect(),
+                        subscription_title: c.subscription_title.clone(),
+                        current_usage: c.current_usage,
+                        usage_limit: c.usage_limit,
+                        next_reset_at: c.next_reset_at,
+                        balance_updated_at: c.balance_updated_at.clone(),
+                    }
                 })
                 .collect(),
             current_id,
@@ -811,12 +1829,26 @@ impl MultiTokenManager {
     ///
     /// 持久化到数据库
     pub fn set_disabled(&self, id: u64, disabled: bool) -> anyhow::Result<()> {
+        self.breaker.reset(id);
         if !disabled {
             // 启用时重置失败计数
             self.db.reset_and_enable(id)?;
         } else {
             self.db.set_disabled(id, true)?;
         }
+        self.publish_status(id);
+        Ok(())
+    }
+
+    /// 禁用凭据并标记为排空中（Admin API）
+    ///
+    /// 与 [`set_disabled`](Self::set_disabled) 不同，排空中的凭据不会在熔断期后被半开探测恢复
+    pub fn set_draining(&self, id: u64) -> anyhow::Result<()> {
+        if !self.db.set_draining(id)? {
+            anyhow::bail!("凭据 #{} 不存在", id);
+        }
+        self.breaker.reset(id);
+        self.publish_status(id);
         Ok(())
     }
 
@@ -826,30 +1858,146 @@ impl MultiTokenManager {
     pub fn set_priority(&self, id: u64, priority: u32) -> anyhow::Result<()> {
         // 持久化更改到数据库
         self.db.set_priority(id, priority)?;
+        self.invalidate_reserved_ids();
         // 立即按新优先级重新选择当前凭据
         self.select_highest_priority();
         Ok(())
     }
 
+    /// 批量更新凭据的禁用状态与优先级（Admin API）
+    ///
+    /// 在单个事务中持久化，任一凭据不存在时不做任何修改并返回该凭据 ID。
+    /// 修改优先级后立即按新优先级重新选择当前凭据
+    pub fn bulk_update(&self, updates: &[CredentialUpdate]) -> anyhow::Result<Option<u64>> {
+        let current_id = self.current();
+        if let Some(missing) = self.db.bulk_update_credentials(updates)? {
+            return Ok(Some(missing));
+        }
+        self.invalidate_reserved_ids();
+
+        for update in updates.iter().filter(|u| u.disabled.is_some()) {
+            self.breaker.reset(update.id);
+            self.publish_status(update.id);
+        }
+        if updates.iter().any(|u| u.priority.is_some()) {
+            self.select_highest_priority();
+        } else if updates
+            .iter()
+            .any(|u| u.id == current_id && u.disabled == Some(true))
+        {
+            let _ = self.switch_to_next();
+        }
+        Ok(None)
+    }
+
     /// 重置凭据失败计数并重新启用（Admin API）
     ///
     /// 持久化到数据库
     pub fn reset_and_enable(&self, id: u64) -> anyhow::Result<()> {
         self.db.reset_and_enable(id)?;
+        self.breaker.reset(id);
+        self.publish_status(id);
+        Ok(())
+    }
+
+    /// 设置凭据的客户端版本覆盖（Admin API）
+    ///
+    /// 持久化到数据库，下次请求时立即生效
+    pub fn set_version_overrides(
+        &self,
+        id: u64,
+        kiro_version: Option<&str>,
+        system_version: Option<&str>,
+        node_version: Option<&str>,
+    ) -> anyhow::Result<()> {
+        if !self
+            .db
+            .set_version_overrides(id, kiro_version, system_version, node_version)?
+        {
+            anyhow::bail!("凭据 #{} 不存在", id);
+        }
+        Ok(())
+    }
+
+    /// 设置凭据的设备指纹（Admin API）
+    ///
+    /// 持久化到数据库，后续请求立即使用新的指纹
+    pub fn set_machine_id(&self, id: u64, machine_id: &str) -> anyhow::Result<()> {
+        if !self.db.set_machine_id(id, Some(machine_id))? {
+            anyhow::bail!("凭据 #{} 不存在", id);
+        }
+        tracing::info!("凭据 #{} 设备指纹已更新", id);
         Ok(())
     }
 
+    /// 设置凭据允许使用的模型（Admin API）
+    ///
+    /// 持久化到数据库，下次选择凭据时立即生效
+    pub fn set_allowed_models(
+        &self,
+        id: u64,
+        allowed_models: Option<Vec<String>>,
+    ) -> anyhow::Result<()> {
+        if !self.db.set_allowed_models(id, &allowed_models)? {
+            anyhow::bail!("凭据 #{} 不存在", id);
+        }
+        Ok(())
+    }
+
+    /// 设置凭据的自定义上游请求头（Admin API）
+    ///
+    /// 持久化到数据库，后续对话与 Token 刷新请求立即使用
+    pub fn set_extra_headers(
+        &self,
+        id: u64,
+        extra_headers: Option<BTreeMap<String, String>>,
+    ) -> anyhow::Result<()> {
+        if !self.db.set_extra_headers(id, &extra_headers)? {
+            anyhow::bail!("凭据 #{} 不存在", id);
+        }
+        tracing::info!("凭据 #{} 自定义请求头已更新", id);
+        Ok(())
+    }
+
+    /// 记录凭据的上游延迟（请求发出到收到响应头）
+    pub fn record_latency(&self, id: u64, latency: std::time::Duration) {
+        self.latency.record(id, latency);
+    }
+
+    /// 获取凭据的延迟状态
+    pub fn latency_status(&self, id: u64) -> LatencyStatus {
+        self.latency.status(id)
+    }
+
+    /// 获取 Token 刷新锁状态（Admin API）
+    pub fn refresh_lock_status(&self) -> RefreshLockStatus {
+        self.refresh_lock.status()
+    }
+
+    /// 强制释放 Token 刷新锁（Admin API）
+    ///
+    /// 取消进行中的刷新并返回其凭据 ID，没有进行中的刷新时返回 None
+    pub fn force_release_refresh_lock(&self) -> Option<u64> {
+        let released = self.refresh_lock.force_release();
+        if let Some(id) = released {
+            tracing::warn!("已强制释放凭据 #{} 的 Token 刷新锁", id);
+        }
+        released
+    }
+
     /// 添加新凭据（Admin API）
     ///
     /// 写入数据库，返回新凭据的 ID
     pub fn add_credential(&self, cred: KiroCredentials) -> anyhow::Result<u64> {
         // 写入数据库
         let id = self.db.insert_credential(&cred)?;
+        self.invalidate_reserved_ids();
 
         // 如果这是第一个凭据，设置为当前凭据
         if self.total_count() == 1 {
-            *self.current_id.lock() = id;
+            self.current_id.store(id, Ordering::Release);
         }
+        self.events.publish(CredentialEvent::CredentialsChanged);
 
         tracing::info!("已添加新凭据 #{}", id);
         Ok(id)
@@ -859,7 +2007,7 @@ impl MultiTokenManager {
     ///
     /// 从数据库中删除，如果删除的是当前凭据会自动切换
     pub fn delete_credential(&self, id: u64) -> anyhow::Result<bool> {
-        let current_id = *self.current_id.lock();
+        let current_id = self.current();
         let need_switch = id == current_id;
 
         // 从数据库删除
@@ -867,55 +2015,98 @@ impl MultiTokenManager {
         if !deleted {
             return Ok(false);
         }
+        self.invalidate_reserved_ids();
 
         // 如果删除的是当前凭据，切换到下一个
         if need_switch {
             self.select_highest_priority();
         }
 
+        self.latency.remove(id);
+        self.breaker.reset(id);
+        self.events.publish(CredentialEvent::CredentialsChanged);
         tracing::info!("已删除凭据 #{}", id);
         Ok(true)
     }
 
+    /// 查询上游余额并更新数据库缓存（写入失败不影响返回结果）
+    pub async fn refresh_balance(&self, id: u64) -> anyhow::Result<UsageLimitsResponse> {
+        let usage = self.get_usage_limits_for(id).await?;
+
+        let subscription_title = usage.subscription_title().map(|s| s.to_string());
+        let current_usage = usage.current_usage();
+        let usage_limit = usage.usage_limit();
+        let next_reset_at = usage.next_date_reset;
+        let result = self
+            .db
+            .call(move |db| {
+                db.update_balance(
+                    id,
+                    subscription_title.as_deref(),
+                    current_usage,
+                    usage_limit,
+                    next_reset_at,
+                )
+            })
+            .await;
+        match result {
+            Ok(_) => {
+                self.invalidate_reserved_ids();
+                self.events.publish(CredentialEvent::BalanceUpdated {
+                    id,
+                    current_usage,
+                    usage_limit,
+                })
+            }
+            Err(e) => tracing::warn!("更新余额到数据库失败（不影响本次请求）: {}", e),
+        }
+
+        Ok(usage)
+    }
+
     /// 获取指定凭据的使用额度（Admin API）
     pub async fn get_usage_limits_for(&self, id: u64) -> anyhow::Result<UsageLimitsResponse> {
         let credentials = self
             .db
-            .get_credential(id)?
+            .call(move |db| db.get_credential(id))
+            .await?
             .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
 
         // 检查是否需要刷新 token
         let needs_refresh = is_token_expired(&credentials) || is_token_expiring_soon(&credentials);
 
         let (token, final_creds) = if needs_refresh {
-            let _guard = self.refresh_lock.lock().await;
-            let current_creds = self
-                .db
-                .get_credential(id)?
-                .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
-
-            if is_token_expired(&current_creds) || is_token_expiring_soon(&current_creds) {
-                let new_creds =
-                    refresh_token(&current_creds, &self.config, self.proxy.as_ref()).await?;
-                // 持久化到数据库
-                self.db.update_credential(&new_creds)?;
-                let token = new_creds
-                    .access_token
-                    .clone()
-                    .ok_or_else(|| anyhow::anyhow!("刷新后无 access_token"))?;
-                (token, new_creds)
-            } else {
-                let token = current_creds
-                    .access_token
-                    .clone()
-                    .ok_or_else(|| anyhow::anyhow!("凭据无 access_token"))?;
-                (token, current_creds)
-            }
+            self.refresh_lock
+                .run(id, async {
+                    let current_creds = self
+                        .db
+                        .call(move |db| db.get_credential(id))
+                        .await?
+                        .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
+
+                    if !is_token_expired(&current_creds) && !is_token_expiring_soon(&current_creds)
+                    {
+                        let token = access_token_of(&current_creds)?;
+                        return Ok((token, current_creds));
+                    }
+                    if let Some(creds) = self.without_refresh(&current_creds).await? {
+                        let token = access_token_of(&creds)?;
+                        return Ok((token, creds));
+                    }
+
+                    let new_creds =
+                        refresh_token(&current_creds, &self.config, self.proxy.as_ref()).await?;
+                    // 持久化到数据库
+                    let new_creds = self.persist_refreshed(&current_creds, new_creds).await?;
+                    let token = new_creds
+                        .access_token
+                        .clone()
+                        .ok_or_else(|| anyhow::anyhow!("刷新后无 access_token"))?;
+                    Ok((token, new_creds))
+                })
+                .await?
         } else {
-            let token = credentials
-                .access_token
-                .clone()
-                .ok_or_else(|| anyhow::anyhow!("凭据无 access_token"))?;
+            let token = access_token_of(&credentials)?;
             (token, credentials)
         };
 
@@ -924,7 +2115,12 @@ impl MultiTokenManager {
 
         // 如果 API 返回了邮箱，更新到数据库
         if let Some(email) = response.email() {
-            if let Err(e) = self.db.update_email(id, Some(email)) {
+            let saved = email.to_string();
+            let result = self
+                .db
+                .call(move |db| db.update_email(id, Some(&saved)))
+                .await;
+            if let Err(e) = result {
                 tracing::warn!("更新凭据 #{} 邮箱失败: {}", id, e);
             } else {
                 tracing::debug!("已更新凭据 #{} 邮箱: {}", id, email);
@@ -949,8 +2145,10 @@ mod tests {
 
     #[test]
     fn test_is_token_expired_with_expired_token() {
-        let mut credentials = KiroCredentials::default();
-        credentials.expires_at = Some("2020-01-01T00:00:00Z".to_string());
+        let credentials = KiroCredentials {
+            expires_at: Some("2020-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
         assert!(is_token_expired(&credentials));
     }
 
@@ -1001,8 +2199,10 @@ mod tests {
 
     #[test]
     fn test_validate_refresh_token_valid() {
-        let mut credentials = KiroCredentials::default();
-        credentials.refresh_token = Some("a".repeat(150));
+        let credentials = KiroCredentials {
+            refresh_token: Some("a".repeat(150)),
+            ..Default::default()
+        };
         let result = validate_refresh_token(&credentials);
         assert!(result.is_ok());
     }
@@ -1010,26 +2210,26 @@ mod tests {
     // MultiTokenManager 测试
 
     fn setup_test_db(credentials: Vec<KiroCredentials>) -> Arc<Database> {
-        let dir = tempfile::tempdir().unwrap();
-        let db_path = dir.path().join("test.db");
-        let db = Database::open(&db_path).unwrap();
+        let db = Database::open_in_memory().unwrap();
         for cred in credentials {
             db.insert_credential(&cred).unwrap();
         }
-        // 保持 tempdir 存活
-        std::mem::forget(dir);
         db
     }
 
     #[test]
     fn test_multi_token_manager_new() {
         let config = Config::default();
-        let mut cred1 = KiroCredentials::default();
-        cred1.refresh_token = Some("token1".to_string());
-        cred1.priority = 0;
-        let mut cred2 = KiroCredentials::default();
-        cred2.refresh_token = Some("token2".to_string());
-        cred2.priority = 1;
+        let cred1 = KiroCredentials {
+            refresh_token: Some("token1".to_string()),
+            priority: 0,
+            ..Default::default()
+        };
+        let cred2 = KiroCredentials {
+            refresh_token: Some("token2".to_string()),
+            priority: 1,
+            ..Default::default()
+        };
 
         let db = setup_test_db(vec![cred1, cred2]);
         let manager = MultiTokenManager::new(config, db, None).unwrap();
@@ -1050,10 +2250,14 @@ mod tests {
     #[test]
     fn test_multi_token_manager_report_failure() {
         let config = Config::default();
-        let mut cred1 = KiroCredentials::default();
-        cred1.refresh_token = Some("token1".to_string());
-        let mut cred2 = KiroCredentials::default();
-        cred2.refresh_token = Some("token2".to_string());
+        let cred1 = KiroCredentials {
+            refresh_token: Some("token1".to_string()),
+            ..Default::default()
+        };
+        let cred2 = KiroCredentials {
+            refresh_token: Some("token2".to_string()),
+            ..Default::default()
+        };
 
         let db = setup_test_db(vec![cred1, cred2]);
         let manager = MultiTokenManager::new(config, db, None).unwrap();
@@ -1075,11 +2279,55 @@ mod tests {
         assert_eq!(manager.available_count(), 0);
     }
 
+    #[test]
+    fn test_report_failure_publishes_events() {
+        let creds = (1..=2)
+            .map(|i| KiroCredentials {
+                refresh_token: Some(format!("token{}", i)),
+                ..Default::default()
+            })
+            .collect();
+        let db = setup_test_db(creds);
+        let manager = MultiTokenManager::new(Config::default(), db, None).unwrap();
+        let mut events = manager.events().subscribe();
+
+        for _ in 0..manager.breaker.failure_threshold() {
+            manager.report_failure(1);
+        }
+        // 成功时失败计数本来为 0，不发布事件
+        manager.report_success(2);
+
+        let received: Vec<_> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        assert_eq!(
+            received,
+            vec![
+                CredentialEvent::StatusChanged {
+                    id: 1,
+                    disabled: false,
+                    failure_count: 1
+                },
+                CredentialEvent::StatusChanged {
+                    id: 1,
+                    disabled: false,
+                    failure_count: 2
+                },
+                CredentialEvent::StatusChanged {
+                    id: 1,
+                    disabled: true,
+                    failure_count: 3
+                },
+                CredentialEvent::CurrentChanged { id: 2 },
+            ]
+        );
+    }
+
     #[test]
     fn test_multi_token_manager_report_success() {
         let config = Config::default();
-        let mut cred = KiroCredentials::default();
-        cred.refresh_token = Some("token".to_string());
+        let cred = KiroCredentials {
+            refresh_token: Some("token".to_string()),
+            ..Default::default()
+        };
 
         let db = setup_test_db(vec![cred]);
         let manager = MultiTokenManager::new(config, db, None).unwrap();
@@ -1100,12 +2348,16 @@ mod tests {
     #[test]
     fn test_multi_token_manager_switch_to_next() {
         let config = Config::default();
-        let mut cred1 = KiroCredentials::default();
-        cred1.refresh_token = Some("token1".to_string());
-        cred1.priority = 0;
-        let mut cred2 = KiroCredentials::default();
-        cred2.refresh_token = Some("token2".to_string());
-        cred2.priority = 1;
+        let cred1 = KiroCredentials {
+            refresh_token: Some("token1".to_string()),
+            priority: 0,
+            ..Default::default()
+        };
+        let cred2 = KiroCredentials {
+            refresh_token: Some("token2".to_string()),
+            priority: 1,
+            ..Default::default()
+        };
 
         let db = setup_test_db(vec![cred1, cred2]);
         let manager = MultiTokenManager::new(config, db, None).unwrap();
@@ -1123,4 +2375,627 @@ mod tests {
             Some("token2".to_string())
         );
     }
+
+    fn prioritized(priorities: &[u32]) -> Vec<KiroCredentials> {
+        priorities
+            .iter()
+            .map(|&priority| KiroCredentials {
+                refresh_token: Some(format!("token{}", priority)),
+                access_token: Some(format!("access{}", priority)),
+                expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+                priority,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_half_open_probe() {
+        let db = setup_test_db(prioritized(&[0, 1]));
+        let config = Config {
+            circuit_breaker_open_secs: 0,
+            ..Default::default()
+        };
+        let manager = MultiTokenManager::new(config, db.clone(), None).unwrap();
+        for _ in 0..manager.breaker.failure_threshold() {
+            manager.report_failure(1);
+        }
+        assert_eq!(manager.available_count(), 1);
+
+        // 熔断期已过：下一个请求作为探测请求使用 #1，探测进行中时其他请求不再探测
+        assert_eq!(manager.acquire_context().await.unwrap().id, 1);
+        assert_eq!(manager.acquire_context().await.unwrap().id, 2);
+        assert_eq!(
+            manager.snapshot().entries[0].circuit_state,
+            CircuitState::HalfOpen
+        );
+
+        // 探测失败：重新熔断，仍然禁用
+        assert!(manager.report_failure(1));
+        assert_eq!(manager.available_count(), 1);
+
+        // 再次探测成功：关闭熔断器并重新启用
+        assert_eq!(manager.acquire_context().await.unwrap().id, 1);
+        manager.report_success(1);
+        assert_eq!(manager.available_count(), 2);
+        let entry = &manager.snapshot().entries[0];
+        assert_eq!(entry.circuit_state, CircuitState::Closed);
+        assert_eq!(entry.failure_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_context_times_out_with_failures() {
+        let mut credentials = prioritized(&[0, 1]);
+        for cred in &mut credentials {
+            cred.expires_at = Some((Utc::now() - Duration::hours(1)).to_rfc3339());
+        }
+        let db = setup_test_db(credentials);
+        let config = Config {
+            credential_acquire_timeout_secs: 1,
+            ..Config::default()
+        };
+        let manager = Arc::new(MultiTokenManager::new(config, db, None).unwrap());
+
+        // 模拟卡住的刷新：其他请求始终无法获取刷新锁
+        let stuck = {
+            let manager = manager.clone();
+            tokio::spawn(async move {
+                manager
+                    .refresh_lock
+                    .run(2, std::future::pending::<anyhow::Result<()>>())
+                    .await
+            })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+        let Err(err) = manager.acquire_context().await else {
+            panic!("时间预算耗尽时应返回错误");
+        };
+        let err = err.downcast::<AcquireError>().unwrap();
+        assert!(err.timed_out);
+        assert_eq!(err.total, 2);
+        assert_eq!(err.failures.len(), 1);
+        assert_eq!(err.failures[0].0, 1);
+        assert!(
+            err.to_string()
+                .contains("已尝试 1/2 个凭据：#1 等待 Token 刷新锁超时")
+        );
+
+        manager.force_release_refresh_lock();
+        assert!(stuck.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shared_credentials_are_not_refreshed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shared.db");
+        let source = Database::open(&path).unwrap();
+        let mut credentials = prioritized(&[0, 1]);
+        credentials[0].expires_at = Some((Utc::now() - Duration::hours(1)).to_rfc3339());
+        for cred in &credentials {
+            source.insert_credential(cred).unwrap();
+        }
+        let db = Database::open_with_shared_credentials(
+            None,
+            None,
+            vec![crate::kiro::db::SharedCredentials::open(&path, 0).unwrap()],
+        )
+        .unwrap();
+        let manager = MultiTokenManager::new(Config::default(), db.clone(), None).unwrap();
+
+        // 过期的共享凭据不发起刷新，直接切换到下一个凭据
+        let ctx = manager.acquire_context().await.unwrap();
+        assert_eq!(ctx.id, 1_000_000_002);
+        assert_eq!(ctx.token, "access1");
+        let expired = db.get_credential(1_000_000_001).unwrap().unwrap();
+        assert_eq!(expired.refresh_token.as_deref(), Some("token0"));
+        assert_eq!(expired.access_token.as_deref(), Some("access0"));
+    }
+
+    #[tokio::test]
+    async fn test_persist_refreshed_yields_to_other_instance() {
+        let db = setup_test_db(prioritized(&[0]));
+        let manager = MultiTokenManager::new(Config::default(), db.clone(), None).unwrap();
+        let before = db.get_credential(1).unwrap().unwrap();
+
+        // 其他实例先完成刷新并轮换了 refreshToken
+        let mut other = before.clone();
+        other.refresh_token = Some("rotated-by-other".to_string());
+        other.access_token = Some("access-by-other".to_string());
+        assert!(db.save_refreshed_token(&other, "token0").unwrap());
+
+        let mut mine = before.clone();
+        mine.refresh_token = Some("rotated-by-me".to_string());
+        mine.access_token = Some("access-by-me".to_string());
+        let result = manager.persist_refreshed(&before, mine).await.unwrap();
+        assert_eq!(result.access_token.as_deref(), Some("access-by-other"));
+        let stored = db.get_credential(1).unwrap().unwrap();
+        assert_eq!(stored.refresh_token.as_deref(), Some("rotated-by-other"));
+    }
+
+    #[tokio::test]
+    async fn test_acquire_context_avoids_demoted_credential() {
+        let db = setup_test_db(prioritized(&[0, 1]));
+        let config = Config {
+            latency_demotion_threshold_ms: 100,
+            ..Config::default()
+        };
+        let manager = MultiTokenManager::new(config, db, None).unwrap();
+        for _ in 0..10 {
+            manager.record_latency(1, std::time::Duration::from_secs(2));
+        }
+        assert!(manager.latency_status(1).demoted_until.is_some());
+
+        let ctx = manager.acquire_context().await.unwrap();
+        assert_eq!(ctx.id, 2);
+        assert_eq!(manager.current(), 2);
+
+        // 降级到期后切回优先级更高的凭据（流量切走后不会再有新样本）
+        manager.latency.expire_demotion(1);
+        let ctx = manager.acquire_context().await.unwrap();
+        assert_eq!(ctx.id, 1);
+        assert_eq!(manager.current(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_context_skips_exhausted_quota() {
+        let db = setup_test_db(prioritized(&[0, 1]));
+        let reset_at = (Utc::now().timestamp() + 3600) as f64;
+        db.update_balance(1, None, 95.0, 100.0, Some(reset_at))
+            .unwrap();
+        let config = Config {
+            quota_skip_threshold: 10.0,
+            ..Config::default()
+        };
+        let manager = MultiTokenManager::new(config, db.clone(), None).unwrap();
+
+        let ctx = manager.acquire_context().await.unwrap();
+        assert_eq!(ctx.id, 2);
+        assert_eq!(manager.current(), 2);
+
+        // 额度重置后恢复按优先级选择
+        let past = (Utc::now().timestamp() - 1) as f64;
+        db.update_balance(1, None, 95.0, 100.0, Some(past)).unwrap();
+        manager.quota_skipped.lock().insert(1, past);
+        let ctx = manager.acquire_context().await.unwrap();
+        assert_eq!(ctx.id, 1);
+        assert!(manager.quota_skipped.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_quota_without_reset_time_is_rechecked() {
+        let db = setup_test_db(prioritized(&[0, 1]));
+        db.update_balance(1, None, 95.0, 100.0, None).unwrap();
+        let config = Config {
+            quota_skip_threshold: 10.0,
+            ..Config::default()
+        };
+        let manager = MultiTokenManager::new(config, db.clone(), None).unwrap();
+
+        let ctx = manager.acquire_context().await.unwrap();
+        assert_eq!(ctx.id, 2);
+        // 缺少重置时间时改为定期重新检查，而不是永久跳过
+        let recheck_at = manager.quota_skipped.lock()[&1];
+        let expected = (Utc::now().timestamp() + QUOTA_RECHECK_INTERVAL_SECS) as f64;
+        assert!((recheck_at - expected).abs() <= 5.0);
+
+        // 余额重新查询后额度充足，到期即恢复按优先级选择
+        db.update_balance(1, None, 0.0, 100.0, None).unwrap();
+        manager
+            .quota_skipped
+            .lock()
+            .insert(1, (Utc::now().timestamp() - 1) as f64);
+        let ctx = manager.acquire_context().await.unwrap();
+        assert_eq!(ctx.id, 1);
+        assert!(manager.quota_skipped.lock().is_empty());
+    }
+
+    fn overflow_band() -> PriorityBand {
+        PriorityBand {
+            name: Some("overflow".to_string()),
+            min_priority: 10,
+            max_priority: 19,
+            activation_threshold: 0.8,
+        }
+    }
+
+    #[test]
+    fn test_reserved_ids_by_utilization() {
+        let now = Utc::now();
+        let mut credentials = prioritized(&[0, 1, 10]);
+        for (i, cred) in credentials.iter_mut().enumerate() {
+            cred.id = Some(i as u64 + 1);
+            cred.usage_limit = 100.0;
+        }
+        let bands = [overflow_band()];
+
+        credentials[0].current_usage = 90.0;
+        credentials[1].current_usage = 50.0;
+        assert_eq!(reserved_ids(&bands, &credentials, now), vec![3]);
+
+        // 平均利用率达到阈值后启用溢出分段；禁用的凭据视为用满
+        credentials[1].disabled = true;
+        assert!(reserved_ids(&bands, &credentials, now).is_empty());
+
+        // 启用阈值为 0 的分段始终启用
+        let always = PriorityBand {
+            activation_threshold: 0.0,
+            ..overflow_band()
+        };
+        credentials[1].disabled = false;
+        assert!(reserved_ids(&[always], &credentials, now).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_acquire_context_reserves_overflow_band() {
+        let db = setup_test_db(prioritized(&[0, 10]));
+        let config = Config {
+            priority_bands: vec![overflow_band()],
+            ..Config::default()
+        };
+        let manager = MultiTokenManager::new(config, db.clone(), None).unwrap();
+
+        // 主分段全部禁用时启用溢出分段
+        manager.set_disabled(1, true).unwrap();
+        assert_eq!(manager.acquire_context().await.unwrap().id, 2);
+
+        // 主分段恢复且利用率低于阈值后，溢出分段重新保留
+        manager.set_disabled(1, false).unwrap();
+        db.update_balance(1, None, 50.0, 100.0, None).unwrap();
+        assert_eq!(manager.acquire_context().await.unwrap().id, 1);
+        assert_eq!(manager.current(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reserved_band_fallback_skips_unusable_credentials() {
+        let db = setup_test_db(prioritized(&[0, 1, 2, 10]));
+        let config = Config {
+            priority_bands: vec![PriorityBand {
+                min_priority: 2,
+                max_priority: 19,
+                ..overflow_band()
+            }],
+            quota_skip_threshold: 10.0,
+            latency_demotion_threshold_ms: 100,
+            ..Config::default()
+        };
+        let manager = MultiTokenManager::new(config, db.clone(), None).unwrap();
+        // #1 额度不足、#2 延迟降级，#3 与 #4 所在分段保留
+        db.update_balance(1, None, 95.0, 100.0, None).unwrap();
+        db.update_balance(2, None, 10.0, 100.0, None).unwrap();
+        for _ in 0..10 {
+            manager.record_latency(2, std::time::Duration::from_secs(2));
+        }
+        manager.invalidate_reserved_ids();
+        assert_eq!(*manager.reserved_ids().await.unwrap(), vec![3, 4]);
+
+        // 当前凭据为保留凭据时，不切换到额度不足或降级的凭据
+        manager.current_id.store(3, Ordering::Release);
+        let ctx = manager
+            .acquire_context_for_model(None, RequestPriority::Standard, None)
+            .await
+            .unwrap();
+        assert_eq!(ctx.id, 3);
+
+        // 缓存在修改优先级后立即失效
+        manager.set_priority(4, 1).unwrap();
+        assert_eq!(*manager.reserved_ids().await.unwrap(), vec![3]);
+    }
+
+    #[tokio::test]
+    async fn test_priority_request_uses_reserved_band() {
+        let db = setup_test_db(prioritized(&[0, 10]));
+        let config = Config {
+            priority_bands: vec![overflow_band()],
+            latency_demotion_threshold_ms: 100,
+            ..Config::default()
+        };
+        let manager = MultiTokenManager::new(config, db, None).unwrap();
+        for _ in 0..10 {
+            manager.record_latency(1, std::time::Duration::from_secs(2));
+        }
+
+        // 主分段利用率未达到阈值：普通请求继续使用降级的凭据，高优先级请求改用保留凭据
+        let ctx = manager
+            .acquire_context_for_model(None, RequestPriority::Standard, None)
+            .await
+            .unwrap();
+        assert_eq!(ctx.id, 1);
+        let ctx = manager
+            .acquire_context_for_model(None, RequestPriority::Priority, None)
+            .await
+            .unwrap();
+        assert_eq!(ctx.id, 2);
+        assert_eq!(manager.served_priority(2).await, RequestPriority::Priority);
+
+        // 高优先级请求不把当前凭据切换到保留分段，后续普通请求仍使用主分段
+        assert_eq!(manager.current(), 1);
+        let ctx = manager
+            .acquire_context_for_model(None, RequestPriority::Standard, None)
+            .await
+            .unwrap();
+        assert_eq!(ctx.id, 1);
+        assert_eq!(manager.served_priority(1).await, RequestPriority::Standard);
+    }
+
+    #[tokio::test]
+    async fn test_demoted_fallback_skips_failed_refresh() {
+        let mut credentials = prioritized(&[0, 10]);
+        credentials[1].expires_at = Some((Utc::now() - Duration::hours(1)).to_rfc3339());
+        let db = setup_test_db(credentials);
+        let config = Config {
+            priority_bands: vec![overflow_band()],
+            latency_demotion_threshold_ms: 100,
+            ..Config::default()
+        };
+        let manager = MultiTokenManager::new(config, db, None).unwrap();
+        for _ in 0..10 {
+            manager.record_latency(1, std::time::Duration::from_secs(2));
+        }
+
+        // 保留凭据 #2 刷新失败（refreshToken 已截断）后不再重复选择，继续使用降级的 #1
+        let ctx = manager
+            .acquire_context_for_model(None, RequestPriority::Priority, None)
+            .await
+            .unwrap();
+        assert_eq!(ctx.id, 1);
+    }
+
+    #[tokio::test]
+    async fn test_racing_switches_do_not_skip_credentials() {
+        let db = setup_test_db(prioritized(&[0, 1, 2]));
+        let manager = MultiTokenManager::new(Config::default(), db, None).unwrap();
+
+        // 两个请求都观察到 #1 刷新失败：第一个切换到 #2，第二个发现已被切换，不再继续跳过 #2
+        manager.switch_to_next_by_priority(1).await;
+        manager.switch_to_next_by_priority(1).await;
+        assert_eq!(manager.current(), 2);
+    }
+
+    #[test]
+    fn test_stale_failure_report_keeps_current() {
+        let db = setup_test_db(prioritized(&[0, 1, 2]));
+        let manager = MultiTokenManager::new(Config::default(), db, None).unwrap();
+
+        // Admin 手动切换到 #2
+        assert!(manager.switch_to_next());
+        assert_eq!(manager.current(), 2);
+
+        // 仍在使用 #3 的旧请求连续失败导致其被禁用，不应把当前凭据改回 #1
+        for _ in 0..manager.breaker.failure_threshold() {
+            assert!(manager.report_failure(3));
+        }
+        assert_eq!(manager.available_count(), 2);
+        assert_eq!(manager.current(), 2);
+
+        // 当前凭据自身被禁用时正常切换
+        for _ in 0..manager.breaker.failure_threshold() {
+            manager.report_failure(2);
+        }
+        assert_eq!(manager.current(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_context_after_disable_race() {
+        let db = setup_test_db(prioritized(&[0, 1]));
+        let manager = MultiTokenManager::new(Config::default(), db.clone(), None).unwrap();
+
+        // 模拟 #1 在其他请求中被禁用，但切换尚未发生
+        db.set_disabled(1, true).unwrap();
+        assert_eq!(manager.current(), 1);
+
+        let ctx = manager.acquire_context().await.unwrap();
+        assert_eq!(ctx.id, 2);
+        assert_eq!(manager.current(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_context_respects_allowed_models() {
+        let mut credentials = prioritized(&[0, 1, 2]);
+        credentials[0].allowed_models = Some(vec!["claude-haiku-*".to_string()]);
+        credentials[2].allowed_models = Some(vec!["claude-opus-4.5".to_string()]);
+        let db = setup_test_db(credentials);
+        let manager = MultiTokenManager::new(Config::default(), db.clone(), None).unwrap();
+
+        let ctx = manager
+            .acquire_context_for_model(Some("claude-haiku-4.5"), RequestPriority::Standard, None)
+            .await
+            .unwrap();
+        assert_eq!(ctx.id, 1);
+
+        // 当前凭据不允许该模型时，仅为本次请求选择其他凭据，不切换当前凭据
+        let ctx = manager
+            .acquire_context_for_model(Some("claude-sonnet-4.5"), RequestPriority::Standard, None)
+            .await
+            .unwrap();
+        assert_eq!(ctx.id, 2);
+        assert_eq!(manager.current(), 1);
+
+        db.set_disabled(2, true).unwrap();
+        let Err(err) = manager
+            .acquire_context_for_model(Some("claude-sonnet-4.5"), RequestPriority::Standard, None)
+            .await
+        else {
+            panic!("没有允许该模型的可用凭据时应返回错误");
+        };
+        assert!(err.to_string().contains("claude-sonnet-4.5"));
+    }
+
+    #[tokio::test]
+    async fn test_acquire_context_sticky_session() {
+        async fn sticky(manager: &MultiTokenManager, session: &str) -> u64 {
+            manager
+                .acquire_context_for_model(None, RequestPriority::Standard, Some(session))
+                .await
+                .unwrap()
+                .id
+        }
+
+        let db = setup_test_db(prioritized(&[0, 1, 2, 3]));
+        let manager = MultiTokenManager::new(Config::default(), db.clone(), None).unwrap();
+
+        // 同一会话固定映射到同一凭据，不同会话分散到多个凭据，且不改变当前凭据
+        let mut mapped = BTreeMap::new();
+        for i in 0..32 {
+            let session = format!("user-{}", i);
+            let id = sticky(&manager, &session).await;
+            assert_eq!(sticky(&manager, &session).await, id);
+            mapped.insert(session, id);
+        }
+        let mut used: Vec<u64> = mapped.values().copied().collect();
+        used.sort();
+        used.dedup();
+        assert!(used.len() > 1);
+        assert_eq!(manager.current(), 1);
+
+        // 映射的凭据禁用后只有该凭据上的会话改变，重新启用后恢复
+        let (moved, &id) = mapped.iter().next().unwrap();
+        db.set_disabled(id, true).unwrap();
+        assert_ne!(sticky(&manager, moved).await, id);
+        for (session, &other) in mapped.iter().filter(|(_, other)| **other != id) {
+            assert_eq!(sticky(&manager, session).await, other);
+        }
+        db.set_disabled(id, false).unwrap();
+        assert_eq!(sticky(&manager, moved).await, id);
+    }
+
+    #[tokio::test]
+    async fn test_lease_settlement() {
+        let db = setup_test_db(prioritized(&[0, 1]));
+        let manager =
+            Arc::new(MultiTokenManager::new(Config::default(), db.clone(), None).unwrap());
+        let failures = |id: u64| db.get_credential(id).unwrap().unwrap().failure_count;
+
+        assert!(
+            manager
+                .lease(None, RequestPriority::Standard, None)
+                .await
+                .unwrap()
+                .fail()
+                .await
+        );
+        assert_eq!(failures(1), 1);
+
+        manager
+            .lease(None, RequestPriority::Standard, None)
+            .await
+            .unwrap()
+            .release();
+        assert_eq!(failures(1), 1);
+
+        // 默认未结算即丢弃只释放租约
+        drop(
+            manager
+                .lease(None, RequestPriority::Standard, None)
+                .await
+                .unwrap(),
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(failures(1), 1);
+
+        manager
+            .lease(None, RequestPriority::Standard, None)
+            .await
+            .unwrap()
+            .succeed()
+            .await;
+        assert_eq!(failures(1), 0);
+
+        // 开启后未结算即丢弃计为失败
+        let config = Config {
+            lease_failure_on_drop: true,
+            ..Default::default()
+        };
+        let manager = Arc::new(MultiTokenManager::new(config, db.clone(), None).unwrap());
+        drop(
+            manager
+                .lease(None, RequestPriority::Standard, None)
+                .await
+                .unwrap(),
+        );
+        for _ in 0..50 {
+            if failures(1) == 1 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(failures(1), 1);
+    }
+
+    #[tokio::test]
+    async fn test_lease_invalidate_token() {
+        let db = setup_test_db(prioritized(&[0, 1]));
+        let manager =
+            Arc::new(MultiTokenManager::new(Config::default(), db.clone(), None).unwrap());
+
+        let lease = manager
+            .lease(None, RequestPriority::Standard, None)
+            .await
+            .unwrap();
+        assert_eq!(lease.context().token, "access0");
+        assert!(lease.invalidate_token().await.is_ok());
+        let credentials = db.get_credential(1).unwrap().unwrap();
+        assert_eq!(credentials.failure_count, 0);
+        assert!(is_token_expired(&credentials));
+
+        // Token 已被刷新时不覆盖新 Token 的过期时间
+        let mut stale = prioritized(&[1]).remove(0);
+        stale.id = Some(2);
+        let lease = CredentialLease {
+            manager: manager.clone(),
+            ctx: CallContext {
+                id: 2,
+                credentials: stale,
+                token: "outdated".to_string(),
+            },
+            settled: false,
+        };
+        assert!(lease.invalidate_token().await.is_ok());
+        assert!(!is_token_expired(&db.get_credential(2).unwrap().unwrap()));
+
+        // 本地已判定过期的 Token 交还租约，由调用方计为失败
+        let mut expired = prioritized(&[1]).remove(0);
+        expired.expires_at = Some((Utc::now() - Duration::hours(1)).to_rfc3339());
+        let lease = CredentialLease {
+            manager: manager.clone(),
+            ctx: CallContext {
+                id: 2,
+                credentials: expired,
+                token: "access1".to_string(),
+            },
+            settled: false,
+        };
+        let lease = lease.invalidate_token().await.unwrap_err();
+        lease.release();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_acquire_and_report_failure() {
+        let db = setup_test_db(prioritized(&[0, 1, 2, 3]));
+        let manager =
+            Arc::new(MultiTokenManager::new(Config::default(), db.clone(), None).unwrap());
+
+        let tasks: Vec<_> = (0..9)
+            .map(|_| {
+                let manager = manager.clone();
+                tokio::spawn(async move {
+                    let ctx = manager.acquire_context().await.unwrap();
+                    manager.report_failure(ctx.id)
+                })
+            })
+            .collect();
+        for task in tasks {
+            assert!(task.await.unwrap());
+        }
+
+        // 每次失败都被记录，且当前凭据始终指向可用凭据
+        let credentials = db.load_credentials().unwrap();
+        let failures: u32 = credentials.iter().map(|c| c.failure_count).sum();
+        assert_eq!(failures, 9);
+        assert!(manager.available_count() >= 1);
+        assert!(!manager.credentials().disabled);
+
+        let ctx = manager.acquire_context().await.unwrap();
+        assert_eq!(ctx.id, manager.current());
+    }
 }
@@ -0,0 +1,370 @@
+//! 对话记录存储
+//!
+//! 开启 `transcriptStore` 后，每个消息请求的提示词与生成内容（流式响应为实际发送给客户端的内容）
+//! 写入 SQLite `transcripts` 表或按天滚动的 JSONL 文件，用于排查客户端集成问题。
+//! 写入前对所有字符串截断（`transcriptMaxChars`）并脱敏：替换配置的敏感词，
+//! 可选遮蔽常见密钥格式（API Key、访问令牌等）
+
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde_json::Value;
+
+use crate::kiro::db::Database;
+use crate::kiro::model::transcript::{Transcript, TranscriptFilter};
+use crate::model::config::Config;
+
+/// 脱敏替换文本
+const REDACTED: &str = "[REDACTED]";
+
+/// 常见密钥前缀（其后紧跟至少 `MIN_SECRET_TAIL` 个字符时整体遮蔽，保留前缀便于辨认类型）
+const SECRET_PREFIXES: &[&str] = &[
+    "sk-ant-",
+    "sk-",
+    "AKIA",
+    "ASIA",
+    "ghp_",
+    "gho_",
+    "ghs_",
+    "github_pat_",
+    "xoxb-",
+    "xoxp-",
+    "AIza",
+    "eyJ",
+];
+
+/// 密钥前缀之后的最少字符数（避免误伤普通单词）
+const MIN_SECRET_TAIL: usize = 16;
+
+/// JSONL 文件名前缀（文件名为 `transcripts-YYYY-MM-DD.jsonl`）
+const JSONL_PREFIX: &str = "transcripts-";
+
+/// 写入目标
+enum Sink {
+    /// SQLite `transcripts` 表（超出最大条数时删除最早的记录）
+    Database {
+        database: Arc<Database>,
+        max_records: usize,
+    },
+    /// JSONL 文件目录（串行追加写入）
+    Jsonl { dir: PathBuf, lock: Mutex<()> },
+}
+
+/// 对话记录存储
+pub struct TranscriptStore {
+    sink: Sink,
+    /// 单个字符串字段的最大字符数（0 表示不截断）
+    max_chars: usize,
+    redact_secrets: bool,
+    redact_terms: Vec<String>,
+}
+
+impl TranscriptStore {
+    /// 按配置创建对话记录存储（未配置 `transcriptStore` 时返回 None）
+    pub fn from_config(config: &Config, database: Arc<Database>) -> anyhow::Result<Option<Self>> {
+        let sink = match config.transcript_store.as_deref() {
+            None => return Ok(None),
+            Some("sqlite") => Sink::Database {
+                database,
+                max_records: config.transcript_max_records,
+            },
+            Some("jsonl") => Sink::Jsonl {
+                dir: PathBuf::from(&config.transcript_dir),
+                lock: Mutex::new(()),
+            },
+            Some(other) => anyhow::bail!("transcriptStore 无效: {}（可选 sqlite、jsonl）", other),
+        };
+        Ok(Some(Self {
+            sink,
+            max_chars: config.transcript_max_chars,
+            redact_secrets: config.transcript_redact_secrets,
+            redact_terms: config
+                .transcript_redact_terms
+                .iter()
+                .filter(|t| !t.is_empty())
+                .cloned()
+                .collect(),
+        }))
+    }
+
+    /// 对 JSON 中的所有字符串截断并脱敏
+    pub fn sanitize(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => *s = self.sanitize_text(s),
+            Value::Array(items) => items.iter_mut().for_each(|v| self.sanitize(v)),
+            Value::Object(map) => map.values_mut().for_each(|v| self.sanitize(v)),
+            _ => {}
+        }
+    }
+
+    fn sanitize_text(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for term in &self.redact_terms {
+            if text.contains(term.as_str()) {
+                text = text.replace(term.as_str(), REDACTED);
+            }
+        }
+        if self.redact_secrets {
+            text = redact_secrets(&text);
+        }
+        truncate(&text, self.max_chars)
+    }
+
+    /// 写入一条对话记录（在阻塞线程池中执行）
+    pub async fn write(self: &Arc<Self>, transcript: Transcript) {
+        let store = self.clone();
+        let result = tokio::task::spawn_blocking(move || match &store.sink {
+            Sink::Database {
+                database,
+                max_records,
+            } => database.insert_transcript(&transcript, *max_records),
+            Sink::Jsonl { dir, lock } => {
+                let _guard = lock.lock();
+                append_jsonl(dir, &transcript)
+            }
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("写入对话记录失败: {}", e),
+            Err(e) => tracing::warn!("写入对话记录任务异常: {}", e),
+        }
+    }
+}
+
+/// 查询对话记录（按时间倒序），返回 (匹配总数, 当前页记录)；未启用时返回 None
+pub fn list(
+    config: &Config,
+    database: &Database,
+    filter: &TranscriptFilter,
+) -> anyhow::Result<Option<(usize, Vec<Transcript>)>> {
+    match config.transcript_store.as_deref() {
+        Some("sqlite") => database.list_transcripts(filter).map(Some),
+        Some("jsonl") => list_jsonl(Path::new(&config.transcript_dir), filter).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// 追加写入当天的 JSONL 文件
+fn append_jsonl(dir: &Path, transcript: &Transcript) -> anyhow::Result<()> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!(
+        "{}{}.jsonl",
+        JSONL_PREFIX,
+        transcript.created_at.format("%Y-%m-%d")
+    ));
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut line = serde_json::to_vec(transcript)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    Ok(())
+}
+
+/// 从 JSONL 文件读取对话记录（从最新的文件开始，跳过无法解析的行）
+fn list_jsonl(dir: &Path, filter: &TranscriptFilter) -> anyhow::Result<(usize, Vec<Transcript>)> {
+    let mut files: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with(JSONL_PREFIX) && n.ends_with(".jsonl"))
+            })
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    // 文件名按日期命名，倒序即为从新到旧
+    files.sort_by(|a, b| b.cmp(a));
+
+    let mut total = 0;
+    let mut page = Vec::new();
+    for path in files {
+        let file = fs::File::open(&path)?;
+        let mut transcripts: Vec<Transcript> = BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .filter(|t| filter.matches(t))
+            .collect();
+        transcripts.reverse();
+        for transcript in transcripts {
+            if total >= filter.offset && page.len() < filter.limit {
+                page.push(transcript);
+            }
+            total += 1;
+        }
+    }
+    Ok((total, page))
+}
+
+/// 按字符数截断，并注明省略的字符数
+fn truncate(text: &str, max_chars: usize) -> String {
+    if max_chars == 0 {
+        return text.to_string();
+    }
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => {
+            let omitted = text[end..].chars().count();
+            format!("{}…[truncated {} chars]", &text[..end], omitted)
+        }
+        None => text.to_string(),
+    }
+}
+
+/// 遮蔽以常见密钥前缀开头的片段（片段由字母、数字与 `-_.+/=` 组成）
+fn redact_secrets(text: &str) -> String {
+    let is_token_char =
+        |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '+' | '/' | '=');
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(is_token_char) {
+        result.push_str(&rest[..start]);
+        let token_rest = &rest[start..];
+        let end = token_rest
+            .find(|c: char| !is_token_char(c))
+            .unwrap_or(token_rest.len());
+        let token = &token_rest[..end];
+        match SECRET_PREFIXES
+            .iter()
+            .find(|p| token.starts_with(**p) && token.len() >= p.len() + MIN_SECRET_TAIL)
+        {
+            Some(prefix) => {
+                result.push_str(prefix);
+                result.push_str(REDACTED);
+            }
+            None => result.push_str(token),
+        }
+        rest = &token_rest[end..];
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(store: &str, dir: &Path) -> Config {
+        Config {
+            transcript_store: Some(store.to_string()),
+            transcript_dir: dir.to_string_lossy().to_string(),
+            transcript_max_chars: 20,
+            transcript_redact_terms: vec!["hunter2".to_string()],
+            ..Config::default()
+        }
+    }
+
+    fn transcript(model: &str, request_id: &str) -> Transcript {
+        Transcript {
+            id: None,
+            created_at: chrono::Utc::now(),
+            request_id: Some(request_id.to_string()),
+            model: model.to_string(),
+            credential_id: Some(1),
+            client_key: None,
+            stream: true,
+            status: 200,
+            latency_ms: 10,
+            request: json!({"messages": [{"role": "user", "content": "hi"}]}),
+            response: json!([{"type": "text", "text": "hello"}]),
+            stop_reason: Some("end_turn".to_string()),
+            input_tokens: Some(3),
+            output_tokens: Some(1),
+        }
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = TranscriptStore::from_config(
+            &config("sqlite", dir.path()),
+            Database::open_in_memory().unwrap(),
+        )
+        .unwrap()
+        .unwrap();
+
+        let mut value = json!({
+            "a": "password hunter2",
+            "b": ["sk-ant-REDACTED"],
+            "c": "x".repeat(25),
+            "d": 42,
+        });
+        store.sanitize(&mut value);
+        assert_eq!(value["a"], "password [REDACTED]");
+        assert_eq!(value["b"][0], "sk-ant-[REDACTED]");
+        assert_eq!(
+            value["c"],
+            format!("{}…[truncated 5 chars]", "x".repeat(20))
+        );
+        assert_eq!(value["d"], 42);
+
+        // 不满足最小长度的片段不遮蔽
+        assert_eq!(redact_secrets("ask-me sk-short"), "ask-me sk-short");
+        assert!(
+            TranscriptStore::from_config(
+                &config("s3", dir.path()),
+                Database::open_in_memory().unwrap()
+            )
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_and_list() {
+        let dir = tempfile::tempdir().unwrap();
+        for kind in ["sqlite", "jsonl"] {
+            let config = Config {
+                transcript_max_records: 2,
+                ..config(kind, &dir.path().join(kind))
+            };
+            let database = Database::open_in_memory().unwrap();
+            let store = Arc::new(
+                TranscriptStore::from_config(&config, database.clone())
+                    .unwrap()
+                    .unwrap(),
+            );
+            store.write(transcript("claude-sonnet-4", "req-1")).await;
+            store.write(transcript("claude-opus-4", "req-2")).await;
+            store.write(transcript("claude-sonnet-4", "req-3")).await;
+
+            let all = TranscriptFilter {
+                limit: 10,
+                ..Default::default()
+            };
+            let (total, page) = list(&config, &database, &all).unwrap().unwrap();
+            let ids: Vec<_> = page.iter().map(|t| t.request_id.as_deref()).collect();
+            if kind == "sqlite" {
+                // 超出最大条数的最早记录被删除
+                assert_eq!(total, 2);
+                assert_eq!(ids, vec![Some("req-3"), Some("req-2")]);
+            } else {
+                assert_eq!(total, 3);
+                assert_eq!(ids, vec![Some("req-3"), Some("req-2"), Some("req-1")]);
+            }
+
+            let by_model = TranscriptFilter {
+                model: Some("claude-opus-4".to_string()),
+                limit: 10,
+                ..Default::default()
+            };
+            let (total, page) = list(&config, &database, &by_model).unwrap().unwrap();
+            assert_eq!(total, 1, "{}", kind);
+            assert_eq!(page[0].response[0]["text"], "hello");
+        }
+
+        assert!(
+            list(
+                &Config::default(),
+                &Database::open_in_memory().unwrap(),
+                &TranscriptFilter::default()
+            )
+            .unwrap()
+            .is_none()
+        );
+    }
+}
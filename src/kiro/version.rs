@@ -0,0 +1,129 @@
+//! Kiro IDE 版本自动检测
+//!
+//! 定期从 Kiro 更新元数据接口获取最新版本号，
+//! 用于请求头中的 KiroIDE 版本特征，避免因客户端版本过旧被上游拒绝
+
+use std::time::Duration;
+
+use parking_lot::RwLock;
+
+use crate::http_client::{ProxyConfig, build_client};
+use crate::model::config::Config;
+
+/// 自动检测到的最新版本（仅内存，重启后重新检测）
+static DETECTED_VERSION: RwLock<Option<String>> = RwLock::new(None);
+
+/// 获取自动检测到的 Kiro 版本
+pub fn detected_version() -> Option<String> {
+    DETECTED_VERSION.read().clone()
+}
+
+/// 启动版本自动检测后台任务
+///
+/// 仅当检测到的版本高于当前生效版本时才会更新，不会降级
+pub fn spawn_auto_update(config: &Config, proxy: Option<ProxyConfig>) {
+    let url = config.kiro_version_check_url.clone();
+    let baseline = config.kiro_version.clone();
+    let interval_secs = config.kiro_version_check_interval_secs.max(60);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            ticker.tick().await;
+            match fetch_latest_version(&url, proxy.as_ref()).await {
+                Ok(latest) => {
+                    let current = detected_version().unwrap_or_else(|| baseline.clone());
+                    if is_newer_version(&latest, &current) {
+                        tracing::info!("检测到新的 Kiro 版本: {} -> {}", current, latest);
+                        *DETECTED_VERSION.write() = Some(latest);
+                    } else {
+                        tracing::debug!("Kiro 版本已是最新: {}", current);
+                    }
+                }
+                Err(e) => tracing::warn!("检测 Kiro 最新版本失败: {}", e),
+            }
+        }
+    });
+}
+
+/// 从更新元数据接口获取最新版本号
+async fn fetch_latest_version(url: &str, proxy: Option<&ProxyConfig>) -> anyhow::Result<String> {
+    let client = build_client(proxy, 30)?;
+    let response = client.get(url).send().await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("版本元数据接口返回错误状态: {}", status);
+    }
+
+    let metadata: serde_json::Value = response.json().await?;
+    parse_version_from_metadata(&metadata)
+        .ok_or_else(|| anyhow::anyhow!("版本元数据中未找到有效的版本号"))
+}
+
+/// 从元数据 JSON 中提取版本号
+///
+/// 依次尝试 `currentRelease`、`version` 以及 `releases[0].version`
+fn parse_version_from_metadata(metadata: &serde_json::Value) -> Option<String> {
+    let candidate = metadata
+        .get("currentRelease")
+        .or_else(|| metadata.get("version"))
+        .or_else(|| {
+            metadata
+                .get("releases")
+                .and_then(|r| r.get(0))
+                .and_then(|r| r.get("version"))
+        })
+        .and_then(|v| v.as_str())?
+        .trim();
+
+    parse_version_parts(candidate).map(|_| candidate.to_string())
+}
+
+/// 将版本号解析为数字段（如 "0.8.140" -> [0, 8, 140]）
+fn parse_version_parts(version: &str) -> Option<Vec<u64>> {
+    let parts: Option<Vec<u64>> = version.split('.').map(|p| p.parse().ok()).collect();
+    parts.filter(|p| !p.is_empty())
+}
+
+/// 判断 `candidate` 是否比 `current` 更新
+fn is_newer_version(candidate: &str, current: &str) -> bool {
+    match (parse_version_parts(candidate), parse_version_parts(current)) {
+        (Some(a), Some(b)) => a > b,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_version_from_metadata() {
+        assert_eq!(
+            parse_version_from_metadata(&json!({"currentRelease": "0.8.140"})),
+            Some("0.8.140".to_string())
+        );
+        assert_eq!(
+            parse_version_from_metadata(&json!({"releases": [{"version": "0.9.2"}]})),
+            Some("0.9.2".to_string())
+        );
+        assert_eq!(
+            parse_version_from_metadata(&json!({"version": "latest"})),
+            None
+        );
+        assert_eq!(parse_version_from_metadata(&json!({})), None);
+    }
+
+    #[test]
+    fn test_is_newer_version() {
+        assert!(is_newer_version("0.8.140", "0.8.0"));
+        assert!(is_newer_version("0.10.0", "0.9.9"));
+        assert!(is_newer_version("1.0", "0.99.1"));
+        assert!(!is_newer_version("0.8.0", "0.8.0"));
+        assert!(!is_newer_version("0.7.9", "0.8.0"));
+        assert!(!is_newer_version("invalid", "0.8.0"));
+    }
+}
@@ -0,0 +1,24 @@
+//! kiro-rs：Anthropic Claude API 兼容的 Kiro 代理
+//!
+//! 二进制入口见 `main.rs`。嵌入到自有 axum 服务时使用 [`build_app`] 构建路由，
+//! 需要直接访问凭据管理器时使用 [`App`]；启用 `client` feature 时，
+//! [`admin::client`] 模块提供 Admin API 的类型化客户端
+
+pub mod admin;
+pub mod anthropic;
+pub mod app;
+pub mod bench;
+pub mod common;
+pub mod http_client;
+pub mod kiro;
+pub mod model;
+pub mod status;
+pub mod token;
+pub mod tokenizer;
+pub mod web;
+
+pub use app::{App, build_app, open_database};
+pub use kiro::db::Database;
+pub use kiro::provider::KiroProvider;
+pub use kiro::token_manager::{CallContext, CredentialLease, MultiTokenManager};
+pub use model::config::Config;
@@ -1,20 +1,9 @@
-mod admin;
-mod anthropic;
-mod common;
-mod http_client;
-mod kiro;
-mod model;
-pub mod token;
-mod web;
-
-use std::sync::Arc;
+//! kiro-rs 命令行入口：解析参数、初始化日志并启动服务（应用组装见 `kiro_rs::app`）
 
+use anyhow::Context;
 use clap::Parser;
-use kiro::db::Database;
-use kiro::provider::KiroProvider;
-use kiro::token_manager::MultiTokenManager;
-use model::arg::Args;
-use model::config::Config;
+use kiro_rs::model::arg::{Args, BenchArgs, Command};
+use kiro_rs::{App, Config, bench, common, kiro};
 
 #[tokio::main]
 async fn main() {
@@ -29,124 +18,95 @@ async fn main() {
         )
         .init();
 
+    // 安装 panic hook（记录 panic ID 与调用栈）
+    common::panic::install_hook();
+
     // 加载配置
     let config_path = args
         .config
         .unwrap_or_else(|| Config::default_config_path().to_string());
+
+    if let Some(Command::Bench(bench_args)) = args.command {
+        run_bench(&config_path, bench_args).await;
+        return;
+    }
     let config = Config::load(&config_path).unwrap_or_else(|e| {
         tracing::error!("加载配置失败: {}", e);
         std::process::exit(1);
     });
 
-    // 打开 SQLite 数据库
-    let db = Database::open(&config.database_path).unwrap_or_else(|e| {
-        tracing::error!("打开数据库失败: {}", e);
+    if let Err(e) = run(config, args.credentials.as_deref()).await {
+        tracing::error!("{:#}", e);
         std::process::exit(1);
-    });
-    tracing::info!("数据库已打开: {}", config.database_path);
-
-    // 获取 API Key
-    let api_key = config.api_key.clone().unwrap_or_else(|| {
-        tracing::error!("配置文件中未设置 apiKey");
-        std::process::exit(1);
-    });
-
-    // 构建代理配置
-    let proxy_config = config.proxy_url.as_ref().map(|url| {
-        let mut proxy = http_client::ProxyConfig::new(url);
-        if let (Some(username), Some(password)) = (&config.proxy_username, &config.proxy_password) {
-            proxy = proxy.with_auth(username, password);
-        }
-        proxy
-    });
+    }
+}
 
-    if proxy_config.is_some() {
-        tracing::info!("已配置 HTTP 代理: {}", config.proxy_url.as_ref().unwrap());
+/// 打开数据库、迁移 `--credentials` 指定的旧版凭据文件，组装应用并启动服务
+async fn run(config: Config, credentials_path: Option<&str>) -> anyhow::Result<()> {
+    let db = kiro_rs::open_database(&config)?;
+    if let Some(path) = credentials_path {
+        let credentials = kiro::legacy::load_credentials_file(std::path::Path::new(path))
+            .context("加载旧版凭据文件失败")?;
+        kiro::legacy::import(&db, &credentials, &format!("凭据文件 {} ", path))
+            .context("迁移旧版凭据失败")?;
     }
+    App::with_database(config, db)?.serve().await
+}
 
-    // 创建 MultiTokenManager 和 KiroProvider
-    let token_manager = MultiTokenManager::new(config.clone(), db.clone(), proxy_config.clone())
-        .unwrap_or_else(|e| {
-            tracing::error!("创建 Token 管理器失败: {}", e);
+/// 执行 `bench` 子命令（未指定地址或 Key 时从配置文件读取，`--mock` 时压测进程内的 mock 上游）
+async fn run_bench(config_path: &str, args: BenchArgs) {
+    if args.mock {
+        let delay = std::time::Duration::from_millis(args.mock_delay_ms);
+        let target = bench::spawn_mock(delay).await.unwrap_or_else(|e| {
+            tracing::error!("启动 mock 上游失败: {}", e);
             std::process::exit(1);
         });
-
-    let credentials_count = token_manager.total_count();
-    if credentials_count == 0 {
-        tracing::warn!("数据库中没有凭据，请通过 Admin API 添加凭据后使用");
-    } else {
-        tracing::info!("已加载 {} 个凭据", credentials_count);
+        if let Err(e) = bench::run(target, args).await {
+            tracing::error!("压测失败: {}", e);
+            std::process::exit(1);
+        }
+        return;
     }
 
-    // 获取第一个凭据用于日志显示
-    let first_credentials = token_manager.credentials();
-
-    let token_manager = Arc::new(token_manager);
-    let kiro_provider = KiroProvider::with_proxy(token_manager.clone(), proxy_config.clone());
-
-    // 初始化 count_tokens 配置
-    token::init_config(token::CountTokensConfig {
-        api_url: config.count_tokens_api_url.clone(),
-        api_key: config.count_tokens_api_key.clone(),
-        auth_type: config.count_tokens_auth_type.clone(),
-        proxy: proxy_config,
-    });
-
-    // 构建 Anthropic API 路由（从第一个凭据获取 profile_arn）
-    let anthropic_app = anthropic::create_router_with_provider(
-        &api_key,
-        Some(kiro_provider),
-        first_credentials.profile_arn.clone(),
-    );
-
-    // 构建 Admin API 路由（如果配置了非空的 admin_api_key）
-    // 安全检查：空字符串被视为未配置，防止空 key 绕过认证
-    let admin_key_valid = config
-        .admin_api_key
-        .as_ref()
-        .map(|k| !k.trim().is_empty())
-        .unwrap_or(false);
-
-    let app = if let Some(admin_key) = &config.admin_api_key {
-        if admin_key.trim().is_empty() {
-            tracing::warn!("admin_api_key 配置为空，Admin API 未启用");
-            anthropic_app
-        } else {
-            let admin_service = admin::AdminService::new(token_manager.clone());
-            let admin_state = admin::AdminState::new(admin_key, admin_service);
-            let admin_app = admin::create_admin_router(admin_state);
-
-            tracing::info!("Admin API 已启用");
-            anthropic_app.nest("/api/admin", admin_app)
-        }
-    } else {
-        anthropic_app
+    let config = match (&args.url, &args.api_key) {
+        (Some(_), Some(_)) => None,
+        _ => Some(Config::load(config_path).unwrap_or_else(|e| {
+            tracing::error!("加载配置失败（可通过 --url/--api-key 直接指定）: {}", e);
+            std::process::exit(1);
+        })),
     };
 
-    // 添加前端静态文件服务（作为 fallback，避免覆盖 API 路由）
-    let web_router = web::create_web_router();
-    let app = app.fallback_service(web_router);
+    let base_url = args.url.clone().unwrap_or_else(|| {
+        let config = config.as_ref().unwrap();
+        // 监听所有地址时通过本机回环访问
+        let host = match config.host.as_str() {
+            "0.0.0.0" | "::" => "127.0.0.1",
+            host => host,
+        };
+        // 配置了 TLS 证书时服务只接受 HTTPS
+        let scheme = match config.tls_paths() {
+            Ok(Some(_)) => "https",
+            _ => "http",
+        };
+        format!(
+            "{}://{}:{}{}",
+            scheme,
+            host,
+            config.port,
+            config.base_path()
+        )
+    });
+    let api_key = args
+        .api_key
+        .clone()
+        .or_else(|| config.as_ref().and_then(|c| c.api_key.clone()))
+        .unwrap_or_else(|| {
+            tracing::error!("未指定 --api-key，且配置文件中未设置 apiKey");
+            std::process::exit(1);
+        });
 
-    // 启动服务器
-    let addr = format!("{}:{}", config.host, config.port);
-    tracing::info!("启动 Anthropic API 端点: {}", addr);
-    tracing::info!("API Key: {}***", &api_key[..(api_key.len() / 2)]);
-    tracing::info!("可用 API:");
-    tracing::info!("  GET  /v1/models");
-    tracing::info!("  POST /v1/messages");
-    tracing::info!("  POST /v1/messages/count_tokens");
-    if admin_key_valid {
-        tracing::info!("Admin API:");
-        tracing::info!("  GET  /api/admin/credentials");
-        tracing::info!("  POST /api/admin/credentials/:id/disabled");
-        tracing::info!("  POST /api/admin/credentials/:id/priority");
-        tracing::info!("  POST /api/admin/credentials/:id/reset");
-        tracing::info!("  GET  /api/admin/credentials/:id/balance");
-        tracing::info!("  POST /api/admin/credentials");
-        tracing::info!("  DELETE /api/admin/credentials/:id");
+    if let Err(e) = bench::run(bench::BenchTarget { base_url, api_key }, args).await {
+        tracing::error!("压测失败: {}", e);
+        std::process::exit(1);
     }
-    tracing::info!("Web UI: http://{}", addr);
-
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
 }
@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 /// Anthropic <-> Kiro API 客户端
 #[derive(Parser, Debug)]
@@ -8,7 +8,66 @@ pub struct Args {
     #[arg(short, long)]
     pub config: Option<String>,
 
-    /// 凭证文件路径
+    /// 旧版凭证文件路径（单个对象或数组，启动时迁移到数据库）
     #[arg(long)]
     pub credentials: Option<String>,
+
+    /// 子命令（省略时启动服务）
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// 子命令
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 向运行中的实例发送合成负载，报告延迟、首字延迟与吞吐
+    Bench(BenchArgs),
+}
+
+/// 压测参数
+#[derive(clap::Args, Debug)]
+pub struct BenchArgs {
+    /// 目标地址（默认使用配置文件中的 host/port）
+    #[arg(long)]
+    pub url: Option<String>,
+
+    /// 客户端 API Key（默认使用配置文件中的 apiKey）
+    #[arg(long)]
+    pub api_key: Option<String>,
+
+    /// 请求总数
+    #[arg(short = 'n', long, default_value_t = 100)]
+    pub requests: usize,
+
+    /// 并发数
+    #[arg(short = 'j', long, default_value_t = 10)]
+    pub concurrency: usize,
+
+    /// 模型
+    #[arg(long, default_value = "claude-sonnet-4-5-20250929")]
+    pub model: String,
+
+    /// 最大输出 tokens
+    #[arg(long, default_value_t = 64)]
+    pub max_tokens: i32,
+
+    /// 用户消息内容
+    #[arg(long, default_value = "Reply with a short greeting.")]
+    pub prompt: String,
+
+    /// 使用非流式请求
+    #[arg(long)]
+    pub no_stream: bool,
+
+    /// 单个请求超时（秒）
+    #[arg(long, default_value_t = 300)]
+    pub timeout_secs: u64,
+
+    /// 压测进程内的 mock 上游（经过真实的代理路径，不连接运行中的实例，不消耗上游额度）
+    #[arg(long, conflicts_with_all = ["url", "api_key"])]
+    pub mock: bool,
+
+    /// mock 上游相邻事件的间隔（毫秒）
+    #[arg(long, default_value_t = 20)]
+    pub mock_delay_ms: u64,
 }
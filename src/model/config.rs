@@ -1,6 +1,56 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
 use std::path::Path;
+use std::time::Duration;
+
+use crate::kiro::model::credentials::KiroCredentials;
+
+/// 模型弃用配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelDeprecation {
+    /// 后继模型（下线日期之后请求会被改写为该模型）
+    #[serde(default)]
+    pub successor: Option<String>,
+    /// 下线日期（RFC3339）；未配置时仅提示，不改写
+    #[serde(default)]
+    pub sunset_at: Option<DateTime<Utc>>,
+    /// 附加说明（写入 Warning 响应头）
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// 按客户端 API Key 覆盖的令牌桶限额（省略的字段使用全局默认值，0 表示不限制）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyRateLimit {
+    /// 每分钟请求数
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+    /// 每分钟 tokens（输入 + 输出）
+    #[serde(default)]
+    pub tokens_per_minute: Option<u64>,
+}
+
+/// 凭据优先级分段
+///
+/// 分段内的凭据平时保留不用，仅当优先级更高（数值更小）的凭据平均利用率达到启用阈值时才参与选择
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PriorityBand {
+    /// 分段名称（仅用于日志）
+    #[serde(default)]
+    pub name: Option<String>,
+    /// 最小优先级（包含）
+    pub min_priority: u32,
+    /// 最大优先级（包含）
+    pub max_priority: u32,
+    /// 启用阈值（0~1）：更高优先级凭据的平均利用率达到该值时启用本分段；`0` 表示始终启用
+    #[serde(default)]
+    pub activation_threshold: f64,
+}
 
 /// KNA 应用配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,15 +68,255 @@ pub struct Config {
     #[serde(default = "default_kiro_version")]
     pub kiro_version: String,
 
+    /// 是否自动检测并使用最新的 Kiro IDE 版本
+    #[serde(default)]
+    pub kiro_version_auto_update: bool,
+
+    /// Kiro 版本元数据接口地址
+    #[serde(default = "default_kiro_version_check_url")]
+    pub kiro_version_check_url: String,
+
+    /// Kiro 版本检测间隔（秒）
+    #[serde(default = "default_kiro_version_check_interval_secs")]
+    pub kiro_version_check_interval_secs: u64,
+
     #[serde(default)]
     pub api_key: Option<String>,
 
+    /// 每个客户端 API Key 的最大并发请求数（0 表示不限制）
+    #[serde(default)]
+    pub max_concurrent_requests_per_key: usize,
+
+    /// 流式输出节流：每秒最多发送的输出 tokens（0 表示不节流）
+    #[serde(default)]
+    pub output_tokens_per_second: u32,
+
+    /// 按客户端 API Key 覆盖输出节流速率（Key -> 每秒 tokens，0 表示该 Key 不节流）
+    #[serde(default)]
+    pub output_tokens_per_second_by_key: HashMap<String, u32>,
+
+    /// 每个 API Key 每分钟最多请求数（令牌桶，0 表示不限制）
+    #[serde(default)]
+    pub requests_per_minute_per_key: u32,
+
+    /// 每个 API Key 每分钟最多 tokens（输入 + 输出，令牌桶，0 表示不限制）
+    #[serde(default)]
+    pub tokens_per_minute_per_key: u64,
+
+    /// 按客户端 API Key 覆盖令牌桶限额
+    #[serde(default)]
+    pub rate_limits_by_key: HashMap<String, KeyRateLimit>,
+
+    /// 上游连接最大存活时间（秒），超时后强制关闭（0 表示不限制）
+    #[serde(default)]
+    pub upstream_max_lifetime_secs: u64,
+
+    /// 单次请求最大输出字节数（文本与工具参数），超出后以 output_limit_exceeded 结束（0 表示不限制）
+    #[serde(default)]
+    pub max_output_bytes: usize,
+
+    /// 单次请求最大输出 tokens（估算值），超出后以 output_limit_exceeded 结束（0 表示不限制）
+    #[serde(default)]
+    pub max_output_tokens: u32,
+
+    /// 流式响应空闲超时（秒）：上游超过该时间未产生输出时结束响应并返回已生成的内容（0 表示不限制）
+    #[serde(default = "default_stream_idle_timeout_secs")]
+    pub stream_idle_timeout_secs: u64,
+
+    /// 流式响应空闲超时结束时使用的 stop_reason
+    #[serde(default = "default_stream_idle_stop_reason")]
+    pub stream_idle_stop_reason: String,
+
+    /// 上游响应未收到结束事件（meteringEvent / contextUsageEvent）时是否视为截断
+    ///
+    /// 上游并不保证总是发送这些事件，默认只将未结束的工具调用与读取错误视为截断
+    #[serde(default)]
+    pub truncation_require_terminal_event: bool,
+
+    /// 是否合并连续的同角色消息（上游要求 user/assistant 严格交替）
+    #[serde(default = "default_normalize_messages")]
+    pub normalize_messages: bool,
+
+    /// 统计快照检测到写入后的最小刷新间隔（秒）
+    #[serde(default = "default_stats_refresh_interval_secs")]
+    pub stats_refresh_interval_secs: u64,
+
+    /// 请求日志批量写入的最大条数（达到后立即写入）
+    #[serde(default = "default_request_log_batch_size")]
+    pub request_log_batch_size: usize,
+
+    /// 请求日志批量写入的最长等待时间（毫秒）
+    #[serde(default = "default_request_log_flush_interval_ms")]
+    pub request_log_flush_interval_ms: u64,
+
+    /// 请求日志（`request_logs`）保留天数，后台每小时删除更早的日志（0 表示不按时间清理）
+    #[serde(default = "default_request_log_retention_days")]
+    pub request_log_retention_days: u64,
+
+    /// 请求日志最多保留的条数，后台每小时删除超出的最早日志（0 表示不限制）
+    #[serde(default)]
+    pub request_log_max_rows: usize,
+
+    /// 对话记录存储："sqlite"（`transcripts` 表）或 "jsonl"（`transcriptDir` 下按天滚动的文件），
+    /// 未配置时不记录提示词与生成内容
+    #[serde(default)]
+    pub transcript_store: Option<String>,
+
+    /// 对话记录 JSONL 文件目录
+    #[serde(default = "default_transcript_dir")]
+    pub transcript_dir: String,
+
+    /// 对话记录中单个字符串字段的最大字符数，超出部分截断（0 表示不截断）
+    #[serde(default = "default_transcript_max_chars")]
+    pub transcript_max_chars: usize,
+
+    /// SQLite 对话记录最多保留的条数，超出时删除最早的记录（0 表示不限制）
+    #[serde(default = "default_transcript_max_records")]
+    pub transcript_max_records: usize,
+
+    /// 是否遮蔽对话记录中常见格式的密钥（API Key、GitHub Token、AWS 访问密钥等）
+    #[serde(default = "default_transcript_redact_secrets")]
+    pub transcript_redact_secrets: bool,
+
+    /// 对话记录中需要替换为 `[REDACTED]` 的敏感词（区分大小写）
+    #[serde(default)]
+    pub transcript_redact_terms: Vec<String>,
+
+    /// 凭据租约未显式结算即被丢弃时是否计为调用失败
+    ///
+    /// 客户端断开、请求被取消也会丢弃租约，默认只释放不计失败
+    #[serde(default)]
+    pub lease_failure_on_drop: bool,
+
+    /// 凭据 p95 上游延迟降级阈值（毫秒），持续超过时临时降低其选择优先级，`0` 表示不降级
+    #[serde(default)]
+    pub latency_demotion_threshold_ms: u64,
+
+    /// 熔断阈值：凭据失败次数达到该值时熔断（禁用）
+    #[serde(default = "default_circuit_breaker_failure_threshold")]
+    pub circuit_breaker_failure_threshold: u32,
+
+    /// 熔断失败计数窗口（秒），只统计窗口内的失败；`0` 表示统计连续失败（成功时清零）
+    #[serde(default)]
+    pub circuit_breaker_window_secs: u64,
+
+    /// 熔断持续时间（秒），之后发送一次半开探测请求，成功则恢复凭据
+    #[serde(default = "default_circuit_breaker_open_secs")]
+    pub circuit_breaker_open_secs: u64,
+
+    /// 按认证方式覆盖的熔断持续时间（秒），键为 `social` / `idc` / `iam`
+    #[serde(default)]
+    pub circuit_breaker_open_secs_by_auth_method: HashMap<String, u64>,
+
+    /// 凭据健康检查间隔（分钟）：定期调用 getUsageLimits 探测全部凭据并记录结果（0 表示不启用）
+    #[serde(default)]
+    pub health_check_interval_mins: u64,
+
+    /// 凭据余额后台刷新间隔（分钟）：定期查询全部启用凭据的余额并写入缓存，供额度跳过与利用率
+    /// 分段使用（0 表示不启用，默认不启用，可通过 Admin API 按需发起刷新任务）
+    #[serde(default)]
+    pub balance_refresh_interval_mins: u64,
+
+    /// 用量记录（`usage_log`）保留天数，后台每小时删除更早的记录（0 表示不清理）
+    #[serde(default = "default_usage_log_retention_days")]
+    pub usage_log_retention_days: u64,
+
+    /// 模型弃用配置（键为客户端请求的模型名，精确匹配）
+    #[serde(default)]
+    pub model_deprecations: HashMap<String, ModelDeprecation>,
+
+    /// 凭据优先级分段（用于保留备用账号，未落在任何分段的凭据始终可用）
+    #[serde(default)]
+    pub priority_bands: Vec<PriorityBand>,
+
+    /// 额度跳过阈值：缓存的剩余额度（usageLimit - currentUsage）低于该值的凭据在选择时被跳过，
+    /// 直到 `nextResetAt` 到期；`0` 表示不跳过
+    #[serde(default)]
+    pub quota_skip_threshold: f64,
+
+    /// 上游请求（对话、Token 刷新、额度查询）每次的最大尝试次数（含首次请求）
+    #[serde(default = "default_retry_max_attempts")]
+    pub retry_max_attempts: usize,
+
+    /// 重试指数退避基数（毫秒）
+    #[serde(default = "default_retry_backoff_base_ms")]
+    pub retry_backoff_base_ms: u64,
+
+    /// 单次重试退避上限（毫秒）
+    #[serde(default = "default_retry_backoff_max_ms")]
+    pub retry_backoff_max_ms: u64,
+
+    /// 可重试的上游 HTTP 状态码（默认不含 429：对话请求被限流时直接切换凭据，不退避）
+    #[serde(default = "default_retry_status_codes")]
+    pub retry_status_codes: Vec<u16>,
+
+    /// 预发布模式：按 `failureInjection*` 配置注入上游延迟与失败（切勿在生产环境开启）
+    #[serde(default)]
+    pub staging: bool,
+
+    /// 故障注入：每 N 个上游请求失败一次（0 表示不注入失败）
+    #[serde(default)]
+    pub failure_injection_every_nth: u64,
+
+    /// 故障注入返回的状态码（依次轮换）
+    #[serde(default = "default_failure_injection_status_codes")]
+    pub failure_injection_status_codes: Vec<u16>,
+
+    /// 故障注入：每个上游请求附加的延迟（毫秒）
+    #[serde(default)]
+    pub failure_injection_latency_ms: u64,
+
+    /// 响应标注方式（可选）：`metadata` 添加 `kiro_annotation` 字段，`suffix` 在文本末尾追加后缀
+    /// 用于多环境部署时追溯输出来自哪个代理实例与凭据池
+    #[serde(default)]
+    pub response_annotation: Option<String>,
+
+    /// 响应标注中的代理实例名称（可选，默认为本实例 ID）
+    #[serde(default)]
+    pub annotation_instance: Option<String>,
+
+    /// 响应标注中的凭据池名称
+    #[serde(default = "default_annotation_pool")]
+    pub annotation_pool: String,
+
+    /// `suffix` 标注的后缀模板，支持 `{instance}` 与 `{pool}` 占位符
+    #[serde(default = "default_annotation_suffix")]
+    pub annotation_suffix: String,
+
+    /// 添加凭据时是否先执行一次真实的 Token 刷新校验凭据（失败则拒绝添加，默认不校验）
+    #[serde(default)]
+    pub validate_credential_on_add: bool,
+
+    /// 获取凭据的时间预算（秒，含禁用恢复、Token 刷新及故障切换），`0` 表示不限制
+    #[serde(default = "default_credential_acquire_timeout_secs")]
+    pub credential_acquire_timeout_secs: u64,
+
+    /// 粘性会话：按会话键（`metadata.user_id` 或 `stickySessionHeader` 请求头）将同一会话固定到同一凭据
+    #[serde(default)]
+    pub sticky_sessions: bool,
+
+    /// 粘性会话键请求头（优先于 `metadata.user_id`），未配置时仅使用 `metadata.user_id`
+    #[serde(default)]
+    pub sticky_session_header: Option<String>,
+
     #[serde(default = "default_system_version")]
     pub system_version: String,
 
     #[serde(default = "default_node_version")]
     pub node_version: String,
 
+    /// codewhispererstreaming 接口使用的 aws-sdk-js 版本
+    #[serde(default = "default_aws_sdk_version")]
+    pub aws_sdk_version: String,
+
+    /// codewhispererruntime 接口（getUsageLimits）使用的 aws-sdk-js 版本
+    #[serde(default = "default_runtime_sdk_version")]
+    pub runtime_sdk_version: String,
+
+    /// IdC Token 刷新时发送的 x-amz-user-agent
+    #[serde(default = "default_idc_amz_user_agent")]
+    pub idc_amz_user_agent: String,
+
     /// 外部 count_tokens API 地址（可选）
     #[serde(default)]
     pub count_tokens_api_url: Option<String>,
@@ -39,6 +329,33 @@ pub struct Config {
     #[serde(default = "default_count_tokens_auth_type")]
     pub count_tokens_auth_type: String,
 
+    /// 外部 count_tokens API 超时（毫秒），超时或失败时返回本地估算值
+    #[serde(default = "default_count_tokens_timeout_ms")]
+    pub count_tokens_timeout_ms: u64,
+
+    /// 每分钟最多调用外部 count_tokens API 的次数（0 表示不限制）
+    /// 超出后使用本地估算，避免 token 计数耗尽外部 API 的额度
+    #[serde(default)]
+    pub count_tokens_budget_per_minute: u32,
+
+    /// embeddings 上游地址（可选，如 `https://api.openai.com/v1/embeddings`）
+    /// 配置后启用 `POST /v1/embeddings` 透传
+    #[serde(default)]
+    pub embeddings_api_url: Option<String>,
+
+    /// embeddings 上游 API Key（可选，以 `Authorization: Bearer` 发送）
+    #[serde(default)]
+    pub embeddings_api_key: Option<String>,
+
+    /// embeddings 上游请求超时（秒）
+    #[serde(default = "default_embeddings_timeout_secs")]
+    pub embeddings_timeout_secs: u64,
+
+    /// 是否下载 `url` 来源的图片并转为 base64 发送（上游只接受 base64 图片）
+    /// 默认关闭：开启后服务端会请求客户端提供的任意 URL
+    #[serde(default)]
+    pub image_url_fetch: bool,
+
     /// HTTP 代理地址（可选）
     /// 支持格式: http://host:port, https://host:port, socks5://host:port
     #[serde(default)]
@@ -52,13 +369,159 @@ pub struct Config {
     #[serde(default)]
     pub proxy_password: Option<String>,
 
+    /// 上游域名静态解析覆盖（域名 -> IP），用于内网或分离 DNS 环境
+    #[serde(default)]
+    pub dns_overrides: HashMap<String, String>,
+
+    /// DNS-over-HTTPS 服务地址（可选，JSON 接口，如 `https://1.1.1.1/dns-query`）
+    /// 配置后上游域名通过 DoH 解析，失败时回退到系统 DNS
+    #[serde(default)]
+    pub doh_url: Option<String>,
+
+    /// DoH 查询超时（毫秒）
+    #[serde(default = "default_doh_timeout_ms")]
+    pub doh_timeout_ms: u64,
+
     /// Admin API 密钥（可选，启用 Admin API 功能）
     #[serde(default)]
     pub admin_api_key: Option<String>,
 
+    /// 热备模式：主实例地址（配置后本实例作为热备，定期同步主实例的凭据）
+    #[serde(default)]
+    pub replication_leader_url: Option<String>,
+
+    /// 热备模式：主实例的 Admin API 密钥
+    #[serde(default)]
+    pub replication_leader_api_key: Option<String>,
+
+    /// 热备模式：同步间隔（秒）
+    #[serde(default = "default_replication_interval_secs")]
+    pub replication_interval_secs: u64,
+
+    /// 告警邮件 SMTP 服务器地址（可选，配置后启用邮件告警）
+    #[serde(default)]
+    pub alert_smtp_host: Option<String>,
+
+    /// 告警邮件 SMTP 端口
+    #[serde(default = "default_alert_smtp_port")]
+    pub alert_smtp_port: u16,
+
+    /// 告警邮件 SMTP 加密方式："tls"（隐式 TLS）、"starttls" 或 "none"
+    #[serde(default = "default_alert_smtp_security")]
+    pub alert_smtp_security: String,
+
+    /// 告警邮件 SMTP 认证用户名（可选）
+    #[serde(default)]
+    pub alert_smtp_username: Option<String>,
+
+    /// 告警邮件 SMTP 认证密码（可选）
+    #[serde(default)]
+    pub alert_smtp_password: Option<String>,
+
+    /// 告警邮件发件人地址
+    #[serde(default)]
+    pub alert_email_from: Option<String>,
+
+    /// 告警邮件收件人地址
+    #[serde(default)]
+    pub alert_email_to: Vec<String>,
+
+    /// 需要发送告警邮件的事件类型
+    #[serde(default = "default_alert_email_events")]
+    pub alert_email_events: Vec<String>,
+
+    /// 同一告警事件的最小发送间隔（秒）
+    #[serde(default = "default_alert_email_cooldown_secs")]
+    pub alert_email_cooldown_secs: u64,
+
+    /// 单个告警通知的最大投递次数，耗尽后进入死信状态
+    #[serde(default = "default_alert_max_attempts")]
+    pub alert_max_attempts: u32,
+
     /// SQLite 数据库路径（用于存储凭据）
     #[serde(default = "default_database_path")]
     pub database_path: String,
+
+    /// 使用内存数据库（不持久化，重启后凭据与日志丢失；用于 CI 冒烟测试等场景）
+    #[serde(default)]
+    pub database_in_memory: bool,
+
+    /// PostgreSQL 连接串（需 `postgres` feature）
+    ///
+    /// 配置后凭据、故障状态与后台任务租约存入 PostgreSQL，多个实例可共享同一凭据池；
+    /// 请求日志等其余数据仍保存在本地 SQLite
+    #[serde(default)]
+    pub database_url: Option<String>,
+
+    /// 只读共享凭据库（SQLite 文件路径）
+    ///
+    /// 选择凭据时与本地凭据合并，共享库中的凭据 ID 加上偏移以免冲突；从不写入共享库
+    #[serde(default)]
+    pub shared_database_paths: Vec<String>,
+
+    /// 旧版内联凭据数组（引入 SQLite 之前的格式，启动时自动迁移到数据库）
+    #[serde(default, rename = "credentials", skip_serializing_if = "Vec::is_empty")]
+    pub legacy_credentials: Vec<KiroCredentials>,
+
+    /// 是否启用内置 Web UI（禁用后非 API 路径返回 404）
+    #[serde(default = "default_web_ui_enabled")]
+    pub web_ui_enabled: bool,
+
+    /// 外部 Web UI 目录（可选，配置后替代嵌入的前端资源）
+    #[serde(default)]
+    pub web_ui_dir: Option<String>,
+
+    /// 服务挂载的路径前缀（如 `/kiro`），所有路由与 Web UI 资源地址都会加上该前缀
+    #[serde(default)]
+    pub base_path: Option<String>,
+
+    /// Admin API 路径（默认 `/api/admin`，位于 `basePath` 之下；Web UI 会自动使用该路径）
+    #[serde(default)]
+    pub admin_path: Option<String>,
+
+    /// Admin API 独立监听端口（配置后 Admin API 与 Web UI 仅在该端口提供，公共端口不再暴露）
+    #[serde(default)]
+    pub admin_port: Option<u16>,
+
+    /// Admin API 独立监听地址（仅在配置 `adminPort` 时生效，默认与 `host` 相同）
+    #[serde(default)]
+    pub admin_host: Option<String>,
+
+    /// HTTPS 证书文件路径（PEM，可包含中间证书链；与 `tlsKeyPath` 同时配置后以 HTTPS 提供服务）
+    #[serde(default)]
+    pub tls_cert_path: Option<String>,
+
+    /// HTTPS 私钥文件路径（PEM，支持 PKCS#8 / PKCS#1 / SEC1）
+    #[serde(default)]
+    pub tls_key_path: Option<String>,
+
+    /// HTTP 重定向端口（启用 HTTPS 时在该端口监听明文 HTTP，并将请求重定向到 HTTPS）
+    #[serde(default)]
+    pub tls_redirect_port: Option<u16>,
+
+    /// 无 JS 状态页（/status）访问模式："auth"（需要 Admin API Key）、"public"（隐藏账号信息）或 "off"
+    #[serde(default = "default_status_page")]
+    pub status_page: String,
+
+    /// 是否为 Web UI 响应添加安全响应头（CSP、X-Frame-Options 等）
+    #[serde(default = "default_web_security_headers")]
+    pub web_security_headers: bool,
+
+    /// Web UI 的 Content-Security-Policy（空字符串表示不下发 CSP）
+    #[serde(default = "default_web_content_security_policy")]
+    pub web_content_security_policy: String,
+
+    /// HTTP 访问日志格式："common"、"combined" 或 "json"（未配置时不输出访问日志）
+    #[serde(default)]
+    pub access_log_format: Option<String>,
+
+    /// HTTP 访问日志写入目标："stdout"（默认）、"stderr" 或文件路径（追加写入）
+    #[serde(default)]
+    pub access_log_path: Option<String>,
+
+    /// 配置文件中显式设置的字段（camelCase，加载时记录，用于区分配置来源）
+    #[serde(skip)]
+    pub file_keys: BTreeSet<String>,
 }
 
 fn default_host() -> String {
@@ -77,6 +540,14 @@ fn default_kiro_version() -> String {
     "0.8.0".to_string()
 }
 
+fn default_kiro_version_check_url() -> String {
+    "https://prod.download.desktop.kiro.dev/stable/metadata-linux-x64-stable.json".to_string()
+}
+
+fn default_kiro_version_check_interval_secs() -> u64 {
+    6 * 60 * 60
+}
+
 fn default_system_version() -> String {
     const SYSTEM_VERSIONS: &[&str] = &["darwin#24.6.0", "win32#10.0.22631"];
     SYSTEM_VERSIONS[fastrand::usize(..SYSTEM_VERSIONS.len())].to_string()
@@ -86,14 +557,175 @@ fn default_node_version() -> String {
     "22.21.1".to_string()
 }
 
+fn default_aws_sdk_version() -> String {
+    "1.0.27".to_string()
+}
+
+fn default_runtime_sdk_version() -> String {
+    "1.0.0".to_string()
+}
+
+fn default_idc_amz_user_agent() -> String {
+    "aws-sdk-js/3.738.0 ua/2.1 os/other lang/js md/browser#unknown_unknown api/sso-oidc#3.738.0 m/E KiroIDE".to_string()
+}
+
+fn default_count_tokens_timeout_ms() -> u64 {
+    2000
+}
+
 fn default_count_tokens_auth_type() -> String {
     "x-api-key".to_string()
 }
 
+fn default_embeddings_timeout_secs() -> u64 {
+    60
+}
+
+fn default_doh_timeout_ms() -> u64 {
+    3000
+}
+
+fn default_replication_interval_secs() -> u64 {
+    30
+}
+
+fn default_circuit_breaker_failure_threshold() -> u32 {
+    3
+}
+
+fn default_circuit_breaker_open_secs() -> u64 {
+    300
+}
+
+fn default_alert_smtp_port() -> u16 {
+    465
+}
+
+fn default_alert_smtp_security() -> String {
+    "tls".to_string()
+}
+
+fn default_alert_email_events() -> Vec<String> {
+    vec![
+        "credential_disabled".to_string(),
+        "all_credentials_disabled".to_string(),
+    ]
+}
+
+fn default_alert_email_cooldown_secs() -> u64 {
+    600
+}
+
+fn default_alert_max_attempts() -> u32 {
+    8
+}
+
+fn default_stream_idle_timeout_secs() -> u64 {
+    120
+}
+
+fn default_stream_idle_stop_reason() -> String {
+    "max_tokens".to_string()
+}
+
+fn default_normalize_messages() -> bool {
+    true
+}
+
+fn default_stats_refresh_interval_secs() -> u64 {
+    5
+}
+
+fn default_usage_log_retention_days() -> u64 {
+    90
+}
+
+fn default_request_log_retention_days() -> u64 {
+    30
+}
+
+fn default_request_log_batch_size() -> usize {
+    100
+}
+
+fn default_transcript_dir() -> String {
+    "transcripts".to_string()
+}
+
+fn default_transcript_max_chars() -> usize {
+    16 * 1024
+}
+
+fn default_transcript_max_records() -> usize {
+    1000
+}
+
+fn default_transcript_redact_secrets() -> bool {
+    true
+}
+
+fn default_request_log_flush_interval_ms() -> u64 {
+    1000
+}
+
+fn default_retry_max_attempts() -> usize {
+    3
+}
+
+fn default_retry_backoff_base_ms() -> u64 {
+    200
+}
+
+fn default_retry_backoff_max_ms() -> u64 {
+    5000
+}
+
+fn default_retry_status_codes() -> Vec<u16> {
+    vec![500, 502, 503, 504]
+}
+
+fn default_annotation_pool() -> String {
+    "default".to_string()
+}
+
+fn default_annotation_suffix() -> String {
+    "\n\n[served by {instance} / {pool}]".to_string()
+}
+
+fn default_failure_injection_status_codes() -> Vec<u16> {
+    vec![503]
+}
+
+fn default_credential_acquire_timeout_secs() -> u64 {
+    30
+}
+
+/// 默认 Admin API 路径
+pub const DEFAULT_ADMIN_PATH: &str = "/api/admin";
+
 fn default_database_path() -> String {
     "./kiro.db".to_string()
 }
 
+fn default_web_ui_enabled() -> bool {
+    true
+}
+
+fn default_status_page() -> String {
+    "auth".to_string()
+}
+
+fn default_web_security_headers() -> bool {
+    true
+}
+
+fn default_web_content_security_policy() -> String {
+    "default-src 'self'; script-src 'self'; style-src 'self' 'unsafe-inline' https://fonts.googleapis.com; \
+     font-src 'self' data: https://fonts.gstatic.com; img-src 'self' data:; connect-src 'self'; \
+     object-src 'none'; frame-ancestors 'none'; base-uri 'self'; form-action 'self'"
+        .to_string()
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -101,17 +733,116 @@ impl Default for Config {
             port: default_port(),
             region: default_region(),
             kiro_version: default_kiro_version(),
+            kiro_version_auto_update: false,
+            kiro_version_check_url: default_kiro_version_check_url(),
+            kiro_version_check_interval_secs: default_kiro_version_check_interval_secs(),
             api_key: None,
+            max_concurrent_requests_per_key: 0,
+            output_tokens_per_second: 0,
+            output_tokens_per_second_by_key: HashMap::new(),
+            requests_per_minute_per_key: 0,
+            tokens_per_minute_per_key: 0,
+            rate_limits_by_key: HashMap::new(),
+            upstream_max_lifetime_secs: 0,
+            max_output_bytes: 0,
+            max_output_tokens: 0,
+            stream_idle_timeout_secs: default_stream_idle_timeout_secs(),
+            stream_idle_stop_reason: default_stream_idle_stop_reason(),
+            truncation_require_terminal_event: false,
+            normalize_messages: default_normalize_messages(),
+            stats_refresh_interval_secs: default_stats_refresh_interval_secs(),
+            request_log_batch_size: default_request_log_batch_size(),
+            request_log_flush_interval_ms: default_request_log_flush_interval_ms(),
+            request_log_retention_days: default_request_log_retention_days(),
+            request_log_max_rows: 0,
+            transcript_store: None,
+            transcript_dir: default_transcript_dir(),
+            transcript_max_chars: default_transcript_max_chars(),
+            transcript_max_records: default_transcript_max_records(),
+            transcript_redact_secrets: default_transcript_redact_secrets(),
+            transcript_redact_terms: Vec::new(),
+            lease_failure_on_drop: false,
+            latency_demotion_threshold_ms: 0,
+            circuit_breaker_failure_threshold: default_circuit_breaker_failure_threshold(),
+            circuit_breaker_window_secs: 0,
+            circuit_breaker_open_secs: default_circuit_breaker_open_secs(),
+            circuit_breaker_open_secs_by_auth_method: HashMap::new(),
+            health_check_interval_mins: 0,
+            balance_refresh_interval_mins: 0,
+            usage_log_retention_days: default_usage_log_retention_days(),
+            quota_skip_threshold: 0.0,
+            model_deprecations: HashMap::new(),
+            priority_bands: Vec::new(),
+            retry_max_attempts: default_retry_max_attempts(),
+            retry_backoff_base_ms: default_retry_backoff_base_ms(),
+            retry_backoff_max_ms: default_retry_backoff_max_ms(),
+            retry_status_codes: default_retry_status_codes(),
+            staging: false,
+            failure_injection_every_nth: 0,
+            failure_injection_status_codes: default_failure_injection_status_codes(),
+            failure_injection_latency_ms: 0,
+            response_annotation: None,
+            annotation_instance: None,
+            annotation_pool: default_annotation_pool(),
+            annotation_suffix: default_annotation_suffix(),
+            validate_credential_on_add: false,
+            credential_acquire_timeout_secs: default_credential_acquire_timeout_secs(),
+            sticky_sessions: false,
+            sticky_session_header: None,
             system_version: default_system_version(),
             node_version: default_node_version(),
+            aws_sdk_version: default_aws_sdk_version(),
+            runtime_sdk_version: default_runtime_sdk_version(),
+            idc_amz_user_agent: default_idc_amz_user_agent(),
             count_tokens_api_url: None,
             count_tokens_api_key: None,
             count_tokens_auth_type: default_count_tokens_auth_type(),
+            count_tokens_timeout_ms: default_count_tokens_timeout_ms(),
+            count_tokens_budget_per_minute: 0,
+            embeddings_api_url: None,
+            embeddings_api_key: None,
+            embeddings_timeout_secs: default_embeddings_timeout_secs(),
+            image_url_fetch: false,
             proxy_url: None,
             proxy_username: None,
             proxy_password: None,
+            dns_overrides: HashMap::new(),
+            doh_url: None,
+            doh_timeout_ms: default_doh_timeout_ms(),
             admin_api_key: None,
+            replication_leader_url: None,
+            replication_leader_api_key: None,
+            replication_interval_secs: default_replication_interval_secs(),
+            alert_smtp_host: None,
+            alert_smtp_port: default_alert_smtp_port(),
+            alert_smtp_security: default_alert_smtp_security(),
+            alert_smtp_username: None,
+            alert_smtp_password: None,
+            alert_email_from: None,
+            alert_email_to: Vec::new(),
+            alert_email_events: default_alert_email_events(),
+            alert_email_cooldown_secs: default_alert_email_cooldown_secs(),
+            alert_max_attempts: default_alert_max_attempts(),
             database_path: default_database_path(),
+            database_in_memory: false,
+            database_url: None,
+            shared_database_paths: Vec::new(),
+            legacy_credentials: Vec::new(),
+            web_ui_enabled: default_web_ui_enabled(),
+            web_ui_dir: None,
+            base_path: None,
+            admin_path: None,
+            admin_port: None,
+            admin_host: None,
+            tls_cert_path: None,
+            tls_key_path: None,
+            tls_redirect_port: None,
+            status_page: default_status_page(),
+            web_security_headers: default_web_security_headers(),
+            web_content_security_policy: default_web_content_security_policy(),
+            access_log_format: None,
+            access_log_path: None,
+            file_keys: BTreeSet::new(),
         }
     }
 }
@@ -131,7 +862,85 @@ impl Config {
         }
 
         let content = fs::read_to_string(path)?;
-        let config: Config = serde_json::from_str(&content)?;
+        let value: serde_json::Value = serde_json::from_str(&content)?;
+        let file_keys = value
+            .as_object()
+            .map(|obj| obj.keys().cloned().collect())
+            .unwrap_or_default();
+        let mut config: Config = serde_json::from_value(value)?;
+        config.file_keys = file_keys;
         Ok(config)
     }
+
+    /// 获取指定客户端 Key 的输出节流速率（None 表示不节流）
+    pub fn output_tokens_per_second_for(&self, api_key: Option<&str>) -> Option<u32> {
+        let rate = api_key
+            .and_then(|key| self.output_tokens_per_second_by_key.get(key))
+            .copied()
+            .unwrap_or(self.output_tokens_per_second);
+        (rate > 0).then_some(rate)
+    }
+
+    /// 规范化的路径前缀：以 `/` 开头、不以 `/` 结尾，未配置时为空字符串
+    pub fn base_path(&self) -> String {
+        let trimmed = self
+            .base_path
+            .as_deref()
+            .unwrap_or("")
+            .trim()
+            .trim_matches('/');
+        if trimmed.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", trimmed)
+        }
+    }
+
+    /// 规范化的 Admin API 路径：以 `/` 开头、不以 `/` 结尾，未配置时为 [`DEFAULT_ADMIN_PATH`]
+    pub fn admin_path(&self) -> String {
+        let trimmed = self
+            .admin_path
+            .as_deref()
+            .unwrap_or("")
+            .trim()
+            .trim_matches('/');
+        if trimmed.is_empty() {
+            DEFAULT_ADMIN_PATH.to_string()
+        } else {
+            format!("/{}", trimmed)
+        }
+    }
+
+    /// Admin API 独立监听地址（未配置 `adminPort` 时为 None，与公共端口共用）
+    pub fn admin_addr(&self) -> Option<String> {
+        let port = self.admin_port?;
+        let host = self.admin_host.as_deref().unwrap_or(&self.host);
+        Some(format!("{}:{}", host, port))
+    }
+
+    /// HTTPS 证书与私钥路径（均未配置时为 None，以 HTTP 提供服务；只配置其中一个时返回错误）
+    pub fn tls_paths(&self) -> anyhow::Result<Option<(&str, &str)>> {
+        match (&self.tls_cert_path, &self.tls_key_path) {
+            (Some(cert), Some(key)) => Ok(Some((cert, key))),
+            (None, None) => Ok(None),
+            _ => anyhow::bail!("tlsCertPath 与 tlsKeyPath 需同时配置"),
+        }
+    }
+
+    /// 额度跳过阈值（None 表示不跳过）
+    pub fn quota_skip_threshold(&self) -> Option<f64> {
+        (self.quota_skip_threshold > 0.0).then_some(self.quota_skip_threshold)
+    }
+
+    /// 上游连接最大存活时间（None 表示不限制）
+    pub fn upstream_max_lifetime(&self) -> Option<Duration> {
+        (self.upstream_max_lifetime_secs > 0)
+            .then(|| Duration::from_secs(self.upstream_max_lifetime_secs))
+    }
+
+    /// 流式响应空闲超时（None 表示不限制）
+    pub fn stream_idle_timeout(&self) -> Option<Duration> {
+        (self.stream_idle_timeout_secs > 0)
+            .then(|| Duration::from_secs(self.stream_idle_timeout_secs))
+    }
 }
@@ -0,0 +1,396 @@
+//! 无 JS 的服务端渲染状态页（`GET /status`）
+//!
+//! 展示凭据池健康状况、各凭据剩余额度（使用缓存的余额，不请求上游）和版本信息，
+//! 便于在手机上快速查看而无需加载完整的 Web UI。
+//!
+//! 访问模式（`statusPage` 配置）：
+//! - `auth`：需要 Admin API Key（浏览器 Basic 认证的密码，或 `x-api-key` / `Authorization: Bearer`）
+//! - `public`：无需认证，但隐藏邮箱、订阅等账号信息（认证后显示完整信息）
+//! - `off`：不提供状态页
+
+use std::fmt::Write;
+use std::sync::Arc;
+
+use axum::{
+    Router,
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
+use base64::Engine;
+
+use crate::common::auth;
+use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::replication;
+use crate::kiro::token_manager::MultiTokenManager;
+use crate::kiro::version;
+
+/// 状态页自动刷新间隔（秒）
+const REFRESH_INTERVAL_SECS: u64 = 30;
+
+#[derive(Clone)]
+struct StatusState {
+    token_manager: Arc<MultiTokenManager>,
+    /// Admin API Key（None 表示未配置，此时无法认证）
+    admin_key: Option<Arc<str>>,
+    /// 未认证时是否可访问（隐藏账号信息）
+    public: bool,
+}
+
+/// 创建状态页路由，`statusPage` 为 `off`（或 `auth` 但未配置 Admin API Key）时返回 None
+///
+/// `accept_admin_key` 为 false 时不接受 Admin API Key 认证（用于 Admin API 隔离到独立端口后的
+/// 公共端口，避免在公共端口上校验 Admin Key），此时仅 `public` 模式可用且始终隐藏账号信息
+pub fn create_status_router(
+    token_manager: Arc<MultiTokenManager>,
+    accept_admin_key: bool,
+) -> Option<Router> {
+    let config = token_manager.config();
+    let admin_key = config
+        .admin_api_key
+        .as_deref()
+        .map(str::trim)
+        .filter(|key| accept_admin_key && !key.is_empty())
+        .map(Arc::from);
+    let public = match config.status_page.as_str() {
+        "off" => return None,
+        "public" => true,
+        "auth" => false,
+        other => {
+            tracing::warn!("未知的 statusPage 配置: {}，状态页未启用", other);
+            return None;
+        }
+    };
+    if !public && admin_key.is_none() {
+        return None;
+    }
+
+    let state = StatusState {
+        token_manager,
+        admin_key,
+        public,
+    };
+    Some(
+        Router::new()
+            .route("/status", get(status_page))
+            .with_state(state),
+    )
+}
+
+/// 请求是否携带有效的 Admin API Key（Basic 认证密码、x-api-key 或 Bearer）
+fn is_authorized(headers: &HeaderMap, admin_key: &str) -> bool {
+    let basic_password = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Basic "))
+        .and_then(|encoded| {
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded.trim())
+                .ok()
+        })
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .and_then(|credentials| {
+            credentials
+                .split_once(':')
+                .map(|(_, password)| password.to_string())
+        });
+
+    basic_password
+        .or_else(|| auth::extract_api_key_from_headers(headers))
+        .is_some_and(|key| auth::constant_time_eq(&key, admin_key))
+}
+
+/// GET /status
+async fn status_page(State(state): State<StatusState>, headers: HeaderMap) -> Response {
+    let authorized = state
+        .admin_key
+        .as_deref()
+        .is_some_and(|key| is_authorized(&headers, key));
+
+    if !authorized && !state.public {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Basic realm=\"kiro-rs status\"")],
+            "Unauthorized",
+        )
+            .into_response();
+    }
+
+    let page = state
+        .token_manager
+        .blocking(move |tm| {
+            let credentials = tm.database().load_credentials().unwrap_or_default();
+            let rows = credentials
+                .iter()
+                .map(|c| {
+                    let id = c.id.unwrap_or(0);
+                    CredentialRow {
+                        id,
+                        priority: c.priority,
+                        disabled: c.disabled,
+                        demoted: tm.latency_status(id).demoted_until.is_some(),
+                        current: id == tm.current(),
+                        balance: Balance::from_credentials(c),
+                        email: c.email.clone(),
+                        subscription: c.subscription_title.clone(),
+                    }
+                })
+                .collect();
+            StatusPage {
+                rows,
+                kiro_version: version::detected_version()
+                    .unwrap_or_else(|| tm.config().kiro_version.clone()),
+                standby: replication::is_standby(),
+                redacted: !authorized,
+            }
+        })
+        .await;
+
+    ([(header::CACHE_CONTROL, "no-store")], Html(page.render())).into_response()
+}
+
+/// 缓存的额度信息
+struct Balance {
+    current_usage: f64,
+    usage_limit: f64,
+    updated_at: Option<String>,
+}
+
+impl Balance {
+    fn from_credentials(credentials: &KiroCredentials) -> Option<Self> {
+        (credentials.usage_limit > 0.0).then(|| Self {
+            current_usage: credentials.current_usage,
+            usage_limit: credentials.usage_limit,
+            updated_at: credentials.balance_updated_at.clone(),
+        })
+    }
+
+    /// 剩余额度百分比（0-100）
+    fn remaining_percentage(&self) -> f64 {
+        ((self.usage_limit - self.current_usage) / self.usage_limit * 100.0).clamp(0.0, 100.0)
+    }
+}
+
+struct CredentialRow {
+    id: u64,
+    priority: u32,
+    disabled: bool,
+    demoted: bool,
+    current: bool,
+    balance: Option<Balance>,
+    email: Option<String>,
+    subscription: Option<String>,
+}
+
+struct StatusPage {
+    rows: Vec<CredentialRow>,
+    kiro_version: String,
+    standby: bool,
+    /// 是否隐藏账号信息（未认证的公开访问）
+    redacted: bool,
+}
+
+impl StatusPage {
+    fn render(&self) -> String {
+        let available = self.rows.iter().filter(|r| !r.disabled).count();
+        let (health, health_class) = if self.standby {
+            ("热备", "warn")
+        } else if available > 0 {
+            ("正常", "ok")
+        } else {
+            ("不可用", "bad")
+        };
+
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            r#"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta http-equiv="refresh" content="{refresh}">
+<title>kiro-rs 状态</title>
+<style>
+body{{font-family:-apple-system,system-ui,sans-serif;margin:16px;color:#222;max-width:720px}}
+h1{{font-size:1.3em}}
+.ok{{color:#1a7f37}}.warn{{color:#9a6700}}.bad{{color:#cf222e}}
+table{{border-collapse:collapse;width:100%}}
+td,th{{padding:6px 4px;border-bottom:1px solid #ddd;text-align:left;font-size:.9em;vertical-align:top}}
+.bar{{background:#eee;border-radius:4px;height:10px;min-width:80px}}
+.bar div{{height:10px;border-radius:4px;background:#1a7f37}}
+.bar .low{{background:#cf222e}}
+.muted{{color:#777;font-size:.8em}}
+</style>
+</head>
+<body>
+<h1>kiro-rs 状态</h1>
+<p>凭据池：<strong class="{health_class}">{health}</strong>（可用 {available} / 共 {total}）</p>
+<p class="muted">kiro-rs {crate_version} · Kiro {kiro_version} · 每 {refresh} 秒自动刷新</p>
+<table>
+<tr><th>凭据</th><th>状态</th><th>剩余额度</th></tr>
+"#,
+            refresh = REFRESH_INTERVAL_SECS,
+            health_class = health_class,
+            health = health,
+            available = available,
+            total = self.rows.len(),
+            crate_version = env!("CARGO_PKG_VERSION"),
+            kiro_version = escape_html(&self.kiro_version),
+        );
+
+        for row in &self.rows {
+            let (status, class) = if row.disabled {
+                ("禁用", "bad")
+            } else if row.demoted {
+                ("降级", "warn")
+            } else {
+                ("可用", "ok")
+            };
+
+            let mut label = format!("#{}", row.id);
+            if row.current {
+                label.push_str(" ★");
+            }
+            let mut detail = format!("优先级 {}", row.priority);
+            if !self.redacted {
+                for value in [&row.email, &row.subscription].into_iter().flatten() {
+                    detail.push_str(" · ");
+                    detail.push_str(&escape_html(value));
+                }
+            }
+
+            let quota = match &row.balance {
+                Some(balance) => {
+                    let remaining = balance.remaining_percentage();
+                    let amount = if self.redacted {
+                        format!("{:.0}%", remaining)
+                    } else {
+                        format!(
+                            "{:.0}% · {:.1} / {:.1}",
+                            remaining,
+                            balance.usage_limit - balance.current_usage,
+                            balance.usage_limit
+                        )
+                    };
+                    let updated = balance
+                        .updated_at
+                        .as_deref()
+                        .map(|at| {
+                            format!(
+                                "<br><span class=\"muted\">更新于 {}</span>",
+                                escape_html(at)
+                            )
+                        })
+                        .unwrap_or_default();
+                    format!(
+                        r#"<div class="bar"><div class="{low}" style="width:{width:.0}%"></div></div><span class="muted">{amount}</span>{updated}"#,
+                        low = if remaining < 10.0 { "low" } else { "" },
+                        width = remaining,
+                        amount = amount,
+                        updated = updated,
+                    )
+                }
+                None => r#"<span class="muted">未查询</span>"#.to_string(),
+            };
+
+            let _ = writeln!(
+                html,
+                "<tr><td>{label}<br><span class=\"muted\">{detail}</span></td><td class=\"{class}\">{status}</td><td>{quota}</td></tr>",
+            );
+        }
+
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+}
+
+/// 转义 HTML 特殊字符
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(redacted: bool) -> StatusPage {
+        StatusPage {
+            rows: vec![
+                CredentialRow {
+                    id: 1,
+                    priority: 0,
+                    disabled: false,
+                    demoted: false,
+                    current: true,
+                    balance: Some(Balance {
+                        current_usage: 25.0,
+                        usage_limit: 100.0,
+                        updated_at: None,
+                    }),
+                    email: Some("<alice@example.com>".to_string()),
+                    subscription: Some("KIRO PRO".to_string()),
+                },
+                CredentialRow {
+                    id: 2,
+                    priority: 1,
+                    disabled: true,
+                    demoted: false,
+                    current: false,
+                    balance: None,
+                    email: None,
+                    subscription: None,
+                },
+            ],
+            kiro_version: "0.8.0".to_string(),
+            standby: false,
+            redacted,
+        }
+    }
+
+    #[test]
+    fn test_render_redacts_account_details() {
+        let full = page(false).render();
+        assert!(full.contains("可用 1 / 共 2"));
+        assert!(full.contains("&lt;alice@example.com&gt;"));
+        assert!(full.contains("75% · 75.0 / 100.0"));
+        assert!(full.contains("width:75%"));
+
+        let redacted = page(true).render();
+        assert!(!redacted.contains("alice"));
+        assert!(!redacted.contains("KIRO PRO"));
+        assert!(!redacted.contains("75.0 / 100.0"));
+        assert!(redacted.contains("75%"));
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_authorized(&headers, "secret"));
+
+        // 浏览器 Basic 认证：用户名任意，密码为 Admin API Key
+        let encoded = base64::engine::general_purpose::STANDARD.encode("admin:secret");
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Basic {}", encoded).parse().unwrap(),
+        );
+        assert!(is_authorized(&headers, "secret"));
+        assert!(!is_authorized(&headers, "other"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", "secret".parse().unwrap());
+        assert!(is_authorized(&headers, "secret"));
+    }
+}
@@ -2,19 +2,18 @@
 //!
 //! 提供文本 token 数量计算功能。
 //!
-//! # 计算规则
-//! - 非西文字符：每个计 4.5 个字符单位
-//! - 西文字符：每个计 1 个字符单位
-//! - 4 个字符单位 = 1 token（四舍五入）
-
-use crate::anthropic::types::{
-    CountTokensRequest, CountTokensResponse, Message, SystemMessage, Tool,
-};
+//! 配置了外部 count_tokens API 时优先调用远程 API；未配置或调用失败时使用
+//! 内置的本地分词器（见 `tokenizer` 模块）估算，无需联网
+
+use crate::anthropic::types::{CountTokensRequest, CountTokensResponse, Tool};
 use crate::http_client::{ProxyConfig, build_client};
+use base64::Engine;
+use parking_lot::Mutex;
 use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
 /// Count Tokens API 配置
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct CountTokensConfig {
     /// 外部 count_tokens API 地址
     pub api_url: Option<String>,
@@ -22,10 +21,28 @@ pub struct CountTokensConfig {
     pub api_key: Option<String>,
     /// count_tokens API 认证类型（"x-api-key" 或 "bearer"）
     pub auth_type: String,
+    /// 外部 API 超时
+    pub timeout: Duration,
+    /// 每分钟最多调用外部 API 的次数（0 表示不限制），超出后使用本地估算
+    pub budget_per_minute: u32,
     /// 代理配置
     pub proxy: Option<ProxyConfig>,
 }
 
+impl Default for CountTokensConfig {
+    /// 与配置文件的默认值一致（`countTokensAuthType`、`countTokensTimeoutMs`）
+    fn default() -> Self {
+        Self {
+            api_url: None,
+            api_key: None,
+            auth_type: "x-api-key".to_string(),
+            timeout: Duration::from_millis(2000),
+            budget_per_minute: 0,
+            proxy: None,
+        }
+    }
+}
+
 /// 全局配置存储
 static COUNT_TOKENS_CONFIG: OnceLock<CountTokensConfig> = OnceLock::new();
 
@@ -36,121 +53,177 @@ pub fn init_config(config: CountTokensConfig) {
     let _ = COUNT_TOKENS_CONFIG.set(config);
 }
 
+/// 外部 API 失败后跳过调用、直接使用本地估算的时长
+const REMOTE_BACKOFF: Duration = Duration::from_secs(30);
+
+/// 外部 API 的调用状态（失败退避与调用预算）
+struct RemoteState {
+    /// 外部 API 最近一次失败后的恢复时间
+    down_until: Mutex<Option<Instant>>,
+    /// 外部 API 调用预算窗口：(窗口开始时间, 窗口内已调用次数)
+    budget: Mutex<Option<(Instant, u32)>>,
+}
+
+/// 全局外部 API 调用状态
+static REMOTE_STATE: RemoteState = RemoteState::new();
+
 /// 获取配置
 fn get_config() -> Option<&'static CountTokensConfig> {
     COUNT_TOKENS_CONFIG.get()
 }
 
-/// 判断字符是否为非西文字符
-///
-/// 西文字符包括：
-/// - ASCII 字符 (U+0000..U+007F)
-/// - 拉丁字母扩展 (U+0080..U+024F)
-/// - 拉丁字母扩展附加 (U+1E00..U+1EFF)
-///
-/// 返回 true 表示该字符是非西文字符（如中文、日文、韩文、阿拉伯文等）
-fn is_non_western_char(c: char) -> bool {
-    !matches!(c,
-        // 基本 ASCII
-        '\u{0000}'..='\u{007F}' |
-        // 拉丁字母扩展-A (Latin Extended-A)
-        '\u{0080}'..='\u{00FF}' |
-        // 拉丁字母扩展-B (Latin Extended-B)
-        '\u{0100}'..='\u{024F}' |
-        // 拉丁字母扩展附加 (Latin Extended Additional)
-        '\u{1E00}'..='\u{1EFF}' |
-        // 拉丁字母扩展-C/D/E
-        '\u{2C60}'..='\u{2C7F}' |
-        '\u{A720}'..='\u{A7FF}' |
-        '\u{AB30}'..='\u{AB6F}'
-    )
-}
-
-/// 计算文本的 token 数量
-///
-/// # 计算规则
-/// - 非西文字符：每个计 4.5 个字符单位
-/// - 西文字符：每个计 1 个字符单位
-/// - 4 个字符单位 = 1 token（四舍五入）
-/// ```
+/// 计算文本的 token 数量（本地分词器估算）
 pub fn count_tokens(text: &str) -> u64 {
-    // println!("text: {}", text);
+    crate::tokenizer::count(text)
+}
 
-    let char_units: f64 = text
-        .chars()
-        .map(|c| if is_non_western_char(c) { 4.0 } else { 1.0 })
-        .sum();
+/// 单张图片的估算 token 数
+///
+/// 无法在不解码的情况下获取图片尺寸，按 Anthropic 对约 1.15MP 图片的上限估算
+const IMAGE_TOKENS: u64 = 1600;
 
-    let tokens = char_units / 4.0;
+/// 启用工具时上游注入的工具调用系统提示开销
+const TOOL_USE_SYSTEM_PROMPT_TOKENS_AUTO: u64 = 346;
+/// tool_choice 为 any/tool 时的工具调用系统提示开销
+const TOOL_USE_SYSTEM_PROMPT_TOKENS_FORCED: u64 = 313;
 
-    // println!("tokens: {}, acc_tokens: {}", tokens, acc_token);
-    (if tokens < 100.0 {
-        tokens * 1.5
-    } else if tokens < 200.0 {
-        tokens * 1.3
-    } else if tokens < 300.0 {
-        tokens * 1.25
-    } else if tokens < 800.0 {
-        tokens * 1.2
-    } else {
-        tokens * 1.0
-    } as u64)
+/// 输入 token 计数结果
+pub(crate) struct TokenCount {
+    /// token 数
+    pub tokens: u64,
+    /// 外部 API 已配置但不可用，结果为本地估算值
+    pub estimated: bool,
 }
 
 /// 估算请求的输入 tokens
 ///
 /// 优先调用远程 API，失败时回退到本地计算
-pub(crate) fn count_all_tokens(
-    model: String,
-    system: Option<Vec<SystemMessage>>,
-    messages: Vec<Message>,
-    tools: Option<Vec<Tool>>,
-) -> u64 {
+pub(crate) fn count_all_tokens(request: CountTokensRequest) -> u64 {
+    count_input_tokens(request).tokens
+}
+
+/// 计算请求的输入 tokens，并标记是否为降级后的本地估算
+///
+/// 远程 API 超时（`countTokensTimeoutMs`）或失败时回退到本地计算，
+/// 并在 `REMOTE_BACKOFF` 内跳过远程调用，避免每个请求都等待超时
+pub(crate) fn count_input_tokens(request: CountTokensRequest) -> TokenCount {
+    match get_config() {
+        Some(config) => count_input_tokens_with(config, &REMOTE_STATE, &request),
+        None => TokenCount {
+            tokens: count_all_tokens_local(&request),
+            estimated: false,
+        },
+    }
+}
+
+/// 按指定配置与调用状态计算请求的输入 tokens
+fn count_input_tokens_with(
+    config: &CountTokensConfig,
+    state: &RemoteState,
+    request: &CountTokensRequest,
+) -> TokenCount {
     // 检查是否配置了远程 API
-    if let Some(config) = get_config()
-        && let Some(api_url) = &config.api_url
-    {
-        // 尝试调用远程 API
-        let result = tokio::task::block_in_place(|| {
-            tokio::runtime::Handle::current().block_on(call_remote_count_tokens(
-                api_url, config, model, &system, &messages, &tools,
-            ))
-        });
-
-        match result {
-            Ok(tokens) => {
-                tracing::debug!("远程 count_tokens API 返回: {}", tokens);
-                return tokens;
+    let Some(api_url) = &config.api_url else {
+        return TokenCount {
+            tokens: count_all_tokens_local(request),
+            estimated: false,
+        };
+    };
+
+    if state.backing_off() || !state.take_budget(config.budget_per_minute, Instant::now()) {
+        return TokenCount {
+            tokens: count_all_tokens_local(request),
+            estimated: true,
+        };
+    }
+
+    // 尝试调用远程 API
+    let result = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(async {
+            tokio::time::timeout(
+                config.timeout,
+                call_remote_count_tokens(api_url, config, request),
+            )
+            .await
+            .unwrap_or_else(|_| Err(format!("超时（{:?}）", config.timeout).into()))
+        })
+    });
+
+    match result {
+        Ok(tokens) => {
+            tracing::debug!("远程 count_tokens API 返回: {}", tokens);
+            TokenCount {
+                tokens,
+                estimated: false,
             }
-            Err(e) => {
-                tracing::warn!("远程 count_tokens API 调用失败，回退到本地计算: {}", e);
+        }
+        Err(e) => {
+            tracing::warn!("远程 count_tokens API 调用失败，回退到本地计算: {}", e);
+            *state.down_until.lock() = Some(Instant::now() + REMOTE_BACKOFF);
+            TokenCount {
+                tokens: count_all_tokens_local(request),
+                estimated: true,
             }
         }
     }
+}
 
-    // 本地计算
-    count_all_tokens_local(system, messages, tools)
+impl RemoteState {
+    const fn new() -> Self {
+        Self {
+            down_until: Mutex::new(None),
+            budget: Mutex::new(None),
+        }
+    }
+
+    /// 远程 API 是否处于失败退避期
+    fn backing_off(&self) -> bool {
+        let mut down_until = self.down_until.lock();
+        match *down_until {
+            Some(until) if Instant::now() < until => true,
+            Some(_) => {
+                *down_until = None;
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// 占用一次外部 API 调用预算（每分钟固定窗口），预算用尽时返回 false
+    fn take_budget(&self, budget_per_minute: u32, now: Instant) -> bool {
+        if budget_per_minute == 0 {
+            return true;
+        }
+        let mut budget = self.budget.lock();
+        let (started, used) = match *budget {
+            Some((started, used)) if now.duration_since(started) < Duration::from_secs(60) => {
+                (started, used)
+            }
+            _ => (now, 0),
+        };
+        if used >= budget_per_minute {
+            if used == budget_per_minute {
+                tracing::warn!(
+                    "外部 count_tokens API 本分钟调用次数已达上限 {}，改用本地估算",
+                    budget_per_minute
+                );
+                *budget = Some((started, used + 1));
+            }
+            return false;
+        }
+        *budget = Some((started, used + 1));
+        true
+    }
 }
 
 /// 调用远程 count_tokens API
 async fn call_remote_count_tokens(
     api_url: &str,
     config: &CountTokensConfig,
-    model: String,
-    system: &Option<Vec<SystemMessage>>,
-    messages: &[Message],
-    tools: &Option<Vec<Tool>>,
+    request: &CountTokensRequest,
 ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
     let client = build_client(config.proxy.as_ref(), 300)?;
 
-    // 构建请求体
-    let request = CountTokensRequest {
-        model, // 模型名称用于 token 计算
-        messages: messages.to_vec(),
-        system: system.clone(),
-        tools: tools.clone(),
-    };
-
     // 构建请求
     let mut req_builder = client.post(api_url);
 
@@ -166,7 +239,7 @@ async fn call_remote_count_tokens(
     // 发送请求
     let response = req_builder
         .header("Content-Type", "application/json")
-        .json(&request)
+        .json(request)
         .send()
         .await?;
 
@@ -179,35 +252,23 @@ async fn call_remote_count_tokens(
 }
 
 /// 本地计算请求的输入 tokens
-fn count_all_tokens_local(
-    system: Option<Vec<SystemMessage>>,
-    messages: Vec<Message>,
-    tools: Option<Vec<Tool>>,
-) -> u64 {
+fn count_all_tokens_local(request: &CountTokensRequest) -> u64 {
     let mut total = 0;
 
     // 系统消息
-    if let Some(ref system) = system {
+    if let Some(ref system) = request.system {
         for msg in system {
             total += count_tokens(&msg.text);
         }
     }
 
     // 用户消息
-    for msg in &messages {
-        if let serde_json::Value::String(s) = &msg.content {
-            total += count_tokens(s);
-        } else if let serde_json::Value::Array(arr) = &msg.content {
-            for item in arr {
-                if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
-                    total += count_tokens(text);
-                }
-            }
-        }
+    for msg in &request.messages {
+        total += count_content_tokens(&msg.content);
     }
 
     // 工具定义
-    if let Some(ref tools) = tools {
+    if let Some(ref tools) = request.tools {
         for tool in tools {
             total += count_tokens(&tool.name);
             total += count_tokens(&tool.description);
@@ -216,9 +277,102 @@ fn count_all_tokens_local(
         }
     }
 
+    total += count_tool_choice_tokens(request.tools.as_deref(), request.tool_choice.as_ref());
+
     total.max(1)
 }
 
+/// 计算消息内容（字符串或内容块数组）的 tokens
+fn count_content_tokens(content: &serde_json::Value) -> u64 {
+    match content {
+        serde_json::Value::String(s) => count_tokens(s),
+        serde_json::Value::Array(blocks) => blocks.iter().map(count_block_tokens).sum(),
+        _ => 0,
+    }
+}
+
+/// 计算单个内容块的 tokens
+fn count_block_tokens(block: &serde_json::Value) -> u64 {
+    let block_type = block.get("type").and_then(|v| v.as_str()).unwrap_or("");
+    match block_type {
+        "image" => IMAGE_TOKENS,
+        "tool_use" => {
+            let name = block.get("name").and_then(|v| v.as_str()).unwrap_or("");
+            let input = block
+                .get("input")
+                .map(|v| serde_json::to_string(v).unwrap_or_default())
+                .unwrap_or_default();
+            count_tokens(name) + count_tokens(&input)
+        }
+        "tool_result" => block.get("content").map(count_content_tokens).unwrap_or(0),
+        // 文档内容（仅纯文本与 base64 编码的 text/* 来源会被转发给上游）与搜索结果
+        "document" => match block.pointer("/source/type").and_then(|v| v.as_str()) {
+            Some("text") => block
+                .pointer("/source/data")
+                .and_then(|v| v.as_str())
+                .map(count_tokens)
+                .unwrap_or(0),
+            Some("content") => block
+                .pointer("/source/content")
+                .map(count_content_tokens)
+                .unwrap_or(0),
+            // base64 编码的 text/* 文档解码后转发
+            Some("base64")
+                if block
+                    .pointer("/source/media_type")
+                    .and_then(|v| v.as_str())
+                    .is_some_and(|media_type| media_type.starts_with("text/")) =>
+            {
+                block
+                    .pointer("/source/data")
+                    .and_then(|v| v.as_str())
+                    .and_then(|data| base64::engine::general_purpose::STANDARD.decode(data).ok())
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                    .map(|text| count_tokens(&text))
+                    .unwrap_or(0)
+            }
+            _ => 0,
+        },
+        "search_result" => block.get("content").map(count_content_tokens).unwrap_or(0),
+        "thinking" => block
+            .get("thinking")
+            .and_then(|v| v.as_str())
+            .map(count_tokens)
+            .unwrap_or(0),
+        _ => block
+            .get("text")
+            .and_then(|v| v.as_str())
+            .map(count_tokens)
+            .unwrap_or(0),
+    }
+}
+
+/// 计算工具调用相关的额外开销（工具系统提示 + tool_choice）
+fn count_tool_choice_tokens(
+    tools: Option<&[Tool]>,
+    tool_choice: Option<&serde_json::Value>,
+) -> u64 {
+    if tools.is_none_or(|t| t.is_empty()) {
+        return 0;
+    }
+
+    let choice_type = tool_choice
+        .and_then(|c| c.get("type"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("auto");
+
+    match choice_type {
+        "any" | "tool" => {
+            let name = tool_choice
+                .and_then(|c| c.get("name"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            TOOL_USE_SYSTEM_PROMPT_TOKENS_FORCED + count_tokens(name)
+        }
+        _ => TOOL_USE_SYSTEM_PROMPT_TOKENS_AUTO,
+    }
+}
+
 /// 估算输出 tokens
 pub(crate) fn estimate_output_tokens(content: &[serde_json::Value]) -> i32 {
     let mut total = 0;
@@ -238,3 +392,266 @@ pub(crate) fn estimate_output_tokens(content: &[serde_json::Value]) -> i32 {
 
     total.max(1)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anthropic::types::{Message, SystemMessage};
+    use serde_json::json;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn request(messages: Vec<Message>) -> CountTokensRequest {
+        CountTokensRequest {
+            model: "claude-sonnet-4".to_string(),
+            messages,
+            system: None,
+            tools: None,
+            tool_choice: None,
+            prompt_template: None,
+        }
+    }
+
+    fn user(content: serde_json::Value) -> Message {
+        Message {
+            role: "user".to_string(),
+            content,
+        }
+    }
+
+    #[test]
+    fn test_take_remote_budget() {
+        let state = RemoteState::new();
+        let start = Instant::now();
+        assert!(state.take_budget(0, start));
+        assert!(state.take_budget(2, start));
+        assert!(state.take_budget(2, start + Duration::from_secs(10)));
+        assert!(!state.take_budget(2, start + Duration::from_secs(20)));
+        assert!(!state.take_budget(2, start + Duration::from_secs(30)));
+        // 新窗口重新计数
+        assert!(state.take_budget(2, start + Duration::from_secs(61)));
+    }
+
+    /// 启动模拟的 count_tokens API：`/ok` 返回固定值，`/slow` 超过超时时间才返回，
+    /// `/error` 返回 500；返回基础地址与调用计数
+    async fn mock_count_tokens_api() -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = |hits: Arc<AtomicUsize>| {
+            move || {
+                hits.fetch_add(1, Ordering::SeqCst);
+            }
+        };
+        let (ok, slow, error) = (
+            counter(hits.clone()),
+            counter(hits.clone()),
+            counter(hits.clone()),
+        );
+        let app = axum::Router::new()
+            .route(
+                "/ok",
+                axum::routing::post(move || async move {
+                    ok();
+                    axum::Json(json!({"input_tokens": 4242}))
+                }),
+            )
+            .route(
+                "/slow",
+                axum::routing::post(move || async move {
+                    slow();
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    axum::Json(json!({"input_tokens": 4242}))
+                }),
+            )
+            .route(
+                "/error",
+                axum::routing::post(move || async move {
+                    error();
+                    axum::http::StatusCode::INTERNAL_SERVER_ERROR
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (base, hits)
+    }
+
+    fn remote_config(url: String) -> CountTokensConfig {
+        CountTokensConfig {
+            api_url: Some(url),
+            timeout: Duration::from_millis(200),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_config_has_real_timeout() {
+        let config = CountTokensConfig::default();
+        assert_eq!(config.timeout, Duration::from_millis(2000));
+        assert_eq!(config.auth_type, "x-api-key");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_remote_count_is_not_estimated() {
+        let (base, hits) = mock_count_tokens_api().await;
+        let state = RemoteState::new();
+        let req = request(vec![user(json!("hi"))]);
+
+        let count = count_input_tokens_with(&remote_config(format!("{base}/ok")), &state, &req);
+        assert_eq!(count.tokens, 4242);
+        assert!(!count.estimated);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // 未配置外部 API 时本地计算不是降级估算
+        let local = count_input_tokens_with(&CountTokensConfig::default(), &state, &req);
+        assert_eq!(local.tokens, count_all_tokens_local(&req));
+        assert!(!local.estimated);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_remote_timeout_falls_back_and_backs_off() {
+        let (base, hits) = mock_count_tokens_api().await;
+        let state = RemoteState::new();
+        let req = request(vec![user(json!("hi"))]);
+        let config = remote_config(format!("{base}/slow"));
+
+        let started = Instant::now();
+        let count = count_input_tokens_with(&config, &state, &req);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(count.tokens, count_all_tokens_local(&req));
+        assert!(count.estimated);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // 退避期内不再调用外部 API
+        let count = count_input_tokens_with(&config, &state, &req);
+        assert!(count.estimated);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // 退避结束后恢复调用
+        *state.down_until.lock() = Some(Instant::now());
+        let count = count_input_tokens_with(&remote_config(format!("{base}/ok")), &state, &req);
+        assert_eq!(count.tokens, 4242);
+        assert!(!count.estimated);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_remote_error_falls_back_to_estimate() {
+        let (base, hits) = mock_count_tokens_api().await;
+        let state = RemoteState::new();
+        let req = request(vec![user(json!("hi"))]);
+
+        let count = count_input_tokens_with(&remote_config(format!("{base}/error")), &state, &req);
+        assert_eq!(count.tokens, count_all_tokens_local(&req));
+        assert!(count.estimated);
+        assert!(state.backing_off());
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_count_includes_images() {
+        let text_only = request(vec![user(json!([{"type": "text", "text": "describe"}]))]);
+        let with_image = request(vec![user(json!([
+            {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "AAAA"}},
+            {"type": "text", "text": "describe"}
+        ]))]);
+
+        assert_eq!(
+            count_all_tokens_local(&with_image),
+            count_all_tokens_local(&text_only) + IMAGE_TOKENS
+        );
+    }
+
+    #[test]
+    fn test_count_includes_tool_blocks() {
+        let base = count_all_tokens_local(&request(vec![user(json!("hi"))]));
+        let with_tool_blocks = count_all_tokens_local(&request(vec![
+            user(json!("hi")),
+            Message {
+                role: "assistant".to_string(),
+                content: json!([{"type": "tool_use", "id": "t1", "name": "read_file", "input": {"path": "/tmp/a.txt"}}]),
+            },
+            user(
+                json!([{"type": "tool_result", "tool_use_id": "t1", "content": [{"type": "text", "text": "file contents here"}]}]),
+            ),
+        ]));
+
+        assert!(with_tool_blocks > base);
+    }
+
+    #[test]
+    fn test_count_includes_text_documents() {
+        let base = count_all_tokens_local(&request(vec![user(
+            json!([{"type": "text", "text": "hi"}]),
+        )]));
+        let with_document = count_all_tokens_local(&request(vec![user(json!([
+            {"type": "document", "source": {"type": "text", "media_type": "text/plain", "data": "The grass is green."}, "citations": {"enabled": true}},
+            {"type": "text", "text": "hi"}
+        ]))]));
+        // PDF 不会转发给上游，不计入
+        let with_pdf = count_all_tokens_local(&request(vec![user(json!([
+            {"type": "document", "source": {"type": "base64", "media_type": "application/pdf", "data": "JVBERi0xLjQ="}},
+            {"type": "text", "text": "hi"}
+        ]))]));
+
+        // base64 编码的纯文本文档解码后计入（"The grass is green."）
+        let with_encoded = count_all_tokens_local(&request(vec![user(json!([
+            {"type": "document", "source": {"type": "base64", "media_type": "text/plain", "data": "VGhlIGdyYXNzIGlzIGdyZWVuLg=="}},
+            {"type": "text", "text": "hi"}
+        ]))]));
+
+        assert!(with_document > base);
+        assert_eq!(with_pdf, base);
+        assert_eq!(with_encoded, with_document);
+    }
+
+    #[test]
+    fn test_count_includes_system_and_tool_choice() {
+        let mut req = request(vec![user(json!("hi"))]);
+        let base = count_all_tokens_local(&req);
+
+        req.system = Some(vec![
+            SystemMessage {
+                text: "You are a helpful assistant.".to_string(),
+            },
+            SystemMessage {
+                text: "Answer briefly.".to_string(),
+            },
+        ]);
+        let with_system = count_all_tokens_local(&req);
+        assert!(with_system > base);
+
+        req.tools = Some(vec![Tool {
+            name: "get_weather".to_string(),
+            description: "Get the weather".to_string(),
+            input_schema: Default::default(),
+        }]);
+        let with_tools = count_all_tokens_local(&req);
+        assert!(with_tools >= with_system + TOOL_USE_SYSTEM_PROMPT_TOKENS_AUTO);
+
+        req.tool_choice = Some(json!({"type": "tool", "name": "get_weather"}));
+        let forced = count_all_tokens_local(&req);
+        assert_ne!(forced, with_tools);
+    }
+
+    #[test]
+    fn test_tool_choice_ignored_without_tools() {
+        assert_eq!(
+            count_tool_choice_tokens(None, Some(&json!({"type": "any"}))),
+            0
+        );
+    }
+
+    #[test]
+    fn test_deserialize_string_system() {
+        let req: CountTokensRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "messages": [],
+            "system": "You are a helpful assistant."
+        }))
+        .unwrap();
+
+        let system = req.system.unwrap();
+        assert_eq!(system.len(), 1);
+        assert_eq!(system[0].text, "You are a helpful assistant.");
+    }
+}
@@ -0,0 +1,231 @@
+//! 本地分词器
+//!
+//! 在未配置外部 count_tokens API 时估算文本的 token 数，无需联网。
+//!
+//! 按 tiktoken（cl100k）的预分词规则把文本切分为片段：缩写后缀、带前导空格的字母串、
+//! 最多 3 位的数字串、带前导空格的标点串、换行与空白串；BPE 合并后每个片段通常为
+//! 1 个 token，较长的片段再按各类字符的平均 token 长度估算，不需要内置完整词表。
+//! 结果与上游的实际计数存在偏差，仅作为估算值使用
+
+/// 拉丁字母串的平均 token 长度（字符）
+const LATIN_CHARS_PER_TOKEN: usize = 6;
+
+/// 其他拼音文字（西里尔、希腊、阿拉伯字母等）的平均 token 长度（字符）
+const ALPHABETIC_CHARS_PER_TOKEN: usize = 3;
+
+/// ASCII 标点串的平均 token 长度（字符）
+const PUNCTUATION_CHARS_PER_TOKEN: usize = 3;
+
+/// 缩进等空白串的平均 token 长度（字符）
+const WHITESPACE_CHARS_PER_TOKEN: usize = 8;
+
+/// Claude 分词器对同一文本的计数通常比 cl100k 多约 10%，按此比例放大（百分比）
+const CLAUDE_RATIO_PERCENT: u64 = 110;
+
+/// 字符类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    /// 换行符
+    Newline,
+    /// 其他空白
+    Space,
+    /// 字母（含 CJK 文字）
+    Letter,
+    /// 数字
+    Digit,
+    /// 标点、符号、emoji 等
+    Other,
+}
+
+fn classify(c: char) -> CharClass {
+    if c == '\n' || c == '\r' {
+        CharClass::Newline
+    } else if c.is_whitespace() {
+        CharClass::Space
+    } else if c.is_alphabetic() {
+        CharClass::Letter
+    } else if c.is_numeric() {
+        CharClass::Digit
+    } else {
+        CharClass::Other
+    }
+}
+
+/// 是否为按字计 token 的表意/音节文字（汉字、假名、谚文）
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}' |
+        '\u{3400}'..='\u{4DBF}' |
+        '\u{4E00}'..='\u{9FFF}' |
+        '\u{AC00}'..='\u{D7AF}' |
+        '\u{F900}'..='\u{FAFF}' |
+        '\u{20000}'..='\u{2FFFF}'
+    )
+}
+
+/// 缩写后缀（`'s`、`'re` 等，不区分大小写）
+const CONTRACTIONS: &[&str] = &["s", "t", "re", "ve", "m", "ll", "d"];
+
+/// 估算文本的 token 数（未按 Claude 分词器放大）
+fn count_cl100k(text: &str) -> u64 {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        // 缩写后缀：'s 't 're 've 'm 'll 'd
+        if c == '\''
+            && let Some(len) = contraction_len(&chars[i + 1..])
+        {
+            tokens += 1;
+            i += 1 + len;
+            continue;
+        }
+
+        match classify(c) {
+            CharClass::Newline | CharClass::Space => {
+                let end = run_end(&chars, i, |c| {
+                    matches!(classify(c), CharClass::Newline | CharClass::Space)
+                });
+                // 换行及其之前的空白为一个片段，之后的缩进单独计算
+                if let Some(last_newline) = chars[i..end]
+                    .iter()
+                    .rposition(|c| classify(*c) == CharClass::Newline)
+                {
+                    tokens += 1;
+                    i += last_newline + 1;
+                }
+                // 最后一个空格与后面的字母串或标点串合并为一个片段
+                let joins_next = end < chars.len()
+                    && matches!(classify(chars[end]), CharClass::Letter | CharClass::Other);
+                let standalone = (end - i).saturating_sub(joins_next as usize);
+                tokens += standalone.div_ceil(WHITESPACE_CHARS_PER_TOKEN) as u64;
+                i = end;
+            }
+            CharClass::Letter => {
+                let end = run_end(&chars, i, |c| classify(c) == CharClass::Letter);
+                tokens += count_letters(&chars[i..end]);
+                i = end;
+            }
+            CharClass::Digit => {
+                let end = run_end(&chars, i, |c| classify(c) == CharClass::Digit);
+                // 数字每 3 位一个片段
+                tokens += (end - i).div_ceil(3) as u64;
+                i = end;
+            }
+            CharClass::Other => {
+                let end = run_end(&chars, i, |c| c != '\'' && classify(c) == CharClass::Other);
+                let end = end.max(i + 1);
+                // 单个标点作为字母串前缀时与字母串合并（如 `.method`、`_name`）
+                if end == i + 1
+                    && chars
+                        .get(end)
+                        .is_some_and(|c| classify(*c) == CharClass::Letter)
+                {
+                    i = end;
+                    continue;
+                }
+                tokens += count_symbols(&chars[i..end]);
+                i = end;
+            }
+        }
+    }
+
+    tokens
+}
+
+/// 匹配缩写后缀，返回后缀长度（不含撇号）
+fn contraction_len(rest: &[char]) -> Option<usize> {
+    CONTRACTIONS.iter().find_map(|suffix| {
+        let len = suffix.chars().count();
+        let matches = rest.len() >= len
+            && rest[..len]
+                .iter()
+                .zip(suffix.chars())
+                .all(|(a, b)| a.to_ascii_lowercase() == b);
+        // 后面紧跟字母时不是缩写（如 `'test'`）
+        let standalone = rest
+            .get(len)
+            .is_none_or(|c| classify(*c) != CharClass::Letter);
+        (matches && standalone).then_some(len)
+    })
+}
+
+/// 从 `start` 开始满足条件的最长连续字符的结束位置
+fn run_end(chars: &[char], start: usize, pred: impl Fn(char) -> bool) -> usize {
+    chars[start..]
+        .iter()
+        .position(|c| !pred(*c))
+        .map_or(chars.len(), |offset| start + offset)
+}
+
+/// 字母串的 token 数：CJK 按字计，拉丁字母与其他拼音文字按平均长度计
+fn count_letters(letters: &[char]) -> u64 {
+    let cjk = letters.iter().filter(|c| is_cjk(**c)).count();
+    let latin = letters.iter().filter(|c| c.is_ascii()).count();
+    let other = letters.len() - cjk - latin;
+    (cjk + latin.div_ceil(LATIN_CHARS_PER_TOKEN) + other.div_ceil(ALPHABETIC_CHARS_PER_TOKEN))
+        as u64
+}
+
+/// 标点串的 token 数：ASCII 标点按平均长度计，其他符号（emoji 等）每个计 1 个
+fn count_symbols(symbols: &[char]) -> u64 {
+    let ascii = symbols.iter().filter(|c| c.is_ascii()).count();
+    (ascii.div_ceil(PUNCTUATION_CHARS_PER_TOKEN) + symbols.len() - ascii) as u64
+}
+
+/// 估算文本的 token 数
+pub fn count(text: &str) -> u64 {
+    (count_cl100k(text) * CLAUDE_RATIO_PERCENT).div_ceil(100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_common_words_are_single_tokens() {
+        // Hello | , | world | !
+        assert_eq!(count_cl100k("Hello, world!"), 4);
+        // The | quick | brown | fox
+        assert_eq!(count_cl100k("The quick brown fox"), 4);
+        // I | 'm | here
+        assert_eq!(count_cl100k("I'm here"), 3);
+    }
+
+    #[test]
+    fn test_long_words_and_numbers_split() {
+        // internationalization（20 个字母）
+        assert_eq!(count_cl100k("internationalization"), 4);
+        // 123 | 456 | 7
+        assert_eq!(count_cl100k("1234567"), 3);
+    }
+
+    #[test]
+    fn test_cjk_counts_per_character() {
+        assert_eq!(count_cl100k("你好世界"), 4);
+        assert_eq!(count_cl100k("こんにちは"), 5);
+    }
+
+    #[test]
+    fn test_whitespace_and_code() {
+        assert_eq!(count_cl100k(""), 0);
+        assert_eq!(count_cl100k("\n\n"), 1);
+        // fn | main | () | {}
+        assert_eq!(count_cl100k("fn main() {}"), 4);
+        // a | \n | 3 个空格 | b（缩进的最后一个空格并入 b）
+        assert_eq!(count_cl100k("a\n    b"), 4);
+        assert_eq!(count_cl100k("a\nb"), 3);
+    }
+
+    #[test]
+    fn test_count_applies_claude_ratio() {
+        assert_eq!(count(""), 0);
+        assert_eq!(count("Hello, world!"), 5);
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(20);
+        let base = count_cl100k(&text);
+        assert_eq!(count(&text), (base * 110).div_ceil(100));
+    }
+}
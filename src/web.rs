@@ -1,71 +1,466 @@
 //! 前端静态文件服务模块
 //!
-//! 使用 rust-embed 在编译时将前端资源嵌入二进制文件
+//! 使用 rust-embed 在编译时将前端资源嵌入二进制文件，
+//! 也可通过 `webUiDir` 配置改为从外部目录读取（用于自定义构建的前端）
+
+use std::path::{Component, PathBuf};
+use std::sync::Arc;
 
 use axum::{
     Router,
     body::Body,
-    extract::Path,
-    http::{StatusCode, header},
-    response::{IntoResponse, Response},
+    extract::{Path, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware,
+    response::Response,
     routing::get,
 };
 use rust_embed::Embed;
+use sha2::{Digest, Sha256};
+
+use crate::kiro::db::blocking;
+use crate::model::config::{Config, DEFAULT_ADMIN_PATH};
 
 /// 嵌入 web/dist 目录下的所有前端静态文件
 #[derive(Embed)]
 #[folder = "web/dist"]
 struct Assets;
 
+/// 前端资源来源
+#[derive(Clone)]
+enum AssetSource {
+    /// 编译时嵌入的资源
+    Embedded,
+    /// 外部目录
+    Directory(Arc<PathBuf>),
+}
+
+/// 前端路由状态
+#[derive(Clone)]
+struct WebState {
+    /// 资源来源
+    source: AssetSource,
+    /// 路径前缀（见 `Config::base_path`）
+    base_path: Arc<str>,
+    /// 注入 index.html 的 Admin API 路径（见 `Config::admin_path`，None 表示不注入）
+    admin_path: Option<Arc<str>>,
+}
+
+/// 读取到的资源文件
+struct Asset {
+    /// 文件内容
+    data: Vec<u8>,
+    /// 基于内容哈希的 ETag（带引号）
+    etag: String,
+}
+
+impl AssetSource {
+    /// 读取资源文件内容
+    fn get(&self, path: &str) -> Option<Asset> {
+        match self {
+            Self::Embedded => Assets::get(path).map(|f| Asset {
+                etag: format!("\"{}\"", hex::encode(f.metadata.sha256_hash())),
+                data: f.data.into_owned(),
+            }),
+            Self::Directory(root) => {
+                // 仅允许普通路径段，防止目录穿越
+                let relative = std::path::Path::new(path);
+                if !relative
+                    .components()
+                    .all(|c| matches!(c, Component::Normal(_)))
+                {
+                    return None;
+                }
+                let full_path = root.join(relative);
+                if !full_path.is_file() {
+                    return None;
+                }
+                let data = std::fs::read(full_path).ok()?;
+                Some(Asset {
+                    etag: format!("\"{}\"", hex::encode(Sha256::digest(&data))),
+                    data,
+                })
+            }
+        }
+    }
+}
+
 /// 处理静态文件请求
-async fn serve_static(Path(path): Path<String>) -> impl IntoResponse {
-    serve_file(&path)
+async fn serve_static(
+    State(state): State<WebState>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    serve(state, path, headers).await
 }
 
 /// 处理根路径请求，返回 index.html
-async fn serve_index() -> impl IntoResponse {
-    serve_file("index.html")
-}
-
-/// 从嵌入的资源中获取文件
-fn serve_file(path: &str) -> Response {
-    match Assets::get(path) {
-        Some(content) => {
-            // 根据文件扩展名猜测 MIME 类型
-            let mime = mime_guess::from_path(path)
-                .first_or_octet_stream()
-                .to_string();
-
-            Response::builder()
-                .status(StatusCode::OK)
-                .header(header::CONTENT_TYPE, mime)
-                .header(header::CACHE_CONTROL, "public, max-age=31536000") // 1 年缓存
-                .body(Body::from(content.data.into_owned()))
-                .unwrap()
-        }
-        None => {
-            // 对于 SPA，非静态资源路径返回 index.html
-            if !path.contains('.') && let Some(index) = Assets::get("index.html") {
-                let mime = "text/html; charset=utf-8";
-                return Response::builder()
-                    .status(StatusCode::OK)
-                    .header(header::CONTENT_TYPE, mime)
-                    .header(header::CACHE_CONTROL, "no-cache") // index.html 不缓存
-                    .body(Body::from(index.data.into_owned()))
-                    .unwrap();
-            }
+async fn serve_index(State(state): State<WebState>, headers: HeaderMap) -> Response {
+    serve(state, "index.html".to_string(), headers).await
+}
+
+/// 返回资源文件（从外部目录读取时在阻塞线程池中读取，避免阻塞异步运行时）
+async fn serve(state: WebState, path: String, headers: HeaderMap) -> Response {
+    let from_disk = matches!(state.source, AssetSource::Directory(_));
+    let respond = move || {
+        serve_file(
+            &state.source,
+            &state.base_path,
+            state.admin_path.as_deref(),
+            &path,
+            &headers,
+        )
+    };
+    if from_disk {
+        blocking(respond).await
+    } else {
+        respond()
+    }
+}
+
+/// 从资源来源中获取文件
+///
+/// 配置了路径前缀时，index.html 中的绝对资源地址会加上前缀
+fn serve_file(
+    source: &AssetSource,
+    base_path: &str,
+    admin_path: Option<&str>,
+    path: &str,
+    headers: &HeaderMap,
+) -> Response {
+    if path == "index.html"
+        && let Some(index) = index_asset(source, base_path, admin_path)
+    {
+        let mime = "text/html; charset=utf-8".to_string();
+        return asset_response(index, mime, cache_control_for(path), headers);
+    }
+
+    if let Some(asset) = source.get(path) {
+        // 根据文件扩展名猜测 MIME 类型
+        let mime = mime_guess::from_path(path)
+            .first_or_octet_stream()
+            .to_string();
+        return asset_response(asset, mime, cache_control_for(path), headers);
+    }
+
+    // 对于 SPA，非静态资源路径返回 index.html
+    if !path.contains('.')
+        && let Some(index) = index_asset(source, base_path, admin_path)
+    {
+        let mime = "text/html; charset=utf-8".to_string();
+        return asset_response(index, mime, cache_control_for("index.html"), headers);
+    }
+
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from("404 Not Found"))
+        .unwrap()
+}
+
+/// 读取 index.html，按路径前缀与 Admin API 路径改写
+fn index_asset(source: &AssetSource, base_path: &str, admin_path: Option<&str>) -> Option<Asset> {
+    let asset = source.get("index.html")?;
+    if base_path.is_empty() && admin_path.is_none_or(|path| path == DEFAULT_ADMIN_PATH) {
+        return Some(asset);
+    }
+    let html = rewrite_index(&String::from_utf8_lossy(&asset.data), base_path, admin_path);
+    Some(Asset {
+        etag: format!("\"{}\"", hex::encode(Sha256::digest(html.as_bytes()))),
+        data: html.into_bytes(),
+    })
+}
 
-            Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("404 Not Found"))
-                .unwrap()
+/// 为 index.html 中的绝对地址（`src="/..."`、`href="/..."`）与相对地址（`src="./..."`，
+/// 前端以 `base: './'` 构建时的产物）加上路径前缀，
+/// 并通过 `<meta name="kiro-base-path">`、`<meta name="kiro-admin-path">` 告知前端 API 地址
+/// （未指定 Admin API 路径时不注入后者，前端使用默认路径或用户设置的路径）
+fn rewrite_index(html: &str, base_path: &str, admin_path: Option<&str>) -> String {
+    let mut html = html.to_string();
+    for (attr, prefix) in [("src", "/"), ("href", "/"), ("src", "./"), ("href", "./")] {
+        let from = format!("{}=\"{}", attr, prefix);
+        let to = format!("{}=\"{}/", attr, base_path);
+        // 跳过协议相对地址（`//host/...`）
+        let mut result = String::with_capacity(html.len());
+        let mut rest = html.as_str();
+        while let Some(pos) = rest.find(&from) {
+            result.push_str(&rest[..pos]);
+            let after = &rest[pos + from.len()..];
+            if after.starts_with('/') {
+                result.push_str(&from);
+            } else {
+                result.push_str(&to);
+            }
+            rest = after;
         }
+        result.push_str(rest);
+        html = result;
+    }
+
+    let mut meta = format!("<meta name=\"kiro-base-path\" content=\"{}\">", base_path);
+    if let Some(admin_path) = admin_path {
+        meta.push_str(&format!(
+            "<meta name=\"kiro-admin-path\" content=\"{}\">",
+            admin_path
+        ));
+    }
+    match html.find("<head>") {
+        Some(pos) => html.insert_str(pos + "<head>".len(), &meta),
+        None => html.insert_str(0, &meta),
+    }
+    html
+}
+
+/// 根据路径选择缓存策略
+///
+/// Vite 构建产物 `assets/` 下的文件名带内容哈希，可长期缓存；
+/// 其他文件（index.html、图标等）路径固定，每次都需通过 ETag 重新验证
+fn cache_control_for(path: &str) -> &'static str {
+    if path.starts_with("assets/") {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    }
+}
+
+/// 构建资源响应，`If-None-Match` 命中时返回 304
+fn asset_response(
+    asset: Asset,
+    mime: String,
+    cache_control: &'static str,
+    headers: &HeaderMap,
+) -> Response {
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| etag_matches(v, &asset.etag));
+
+    let builder = Response::builder()
+        .header(header::ETAG, &asset.etag)
+        .header(header::CACHE_CONTROL, cache_control);
+
+    if not_modified {
+        return builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    builder
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, mime)
+        .body(Body::from(asset.data))
+        .unwrap()
+}
+
+/// 判断 `If-None-Match` 是否与 ETag 匹配（支持 `*`、多个值以及弱校验前缀 `W/`）
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+/// 为响应添加安全响应头
+///
+/// CSP 为空字符串时不下发 Content-Security-Policy
+fn apply_security_headers(response: &mut Response, csp: Option<&HeaderValue>) {
+    let headers = response.headers_mut();
+    if let Some(csp) = csp {
+        headers.insert(header::CONTENT_SECURITY_POLICY, csp.clone());
     }
+    headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    headers.insert(
+        header::REFERRER_POLICY,
+        HeaderValue::from_static("no-referrer"),
+    );
+    headers.insert(
+        "permissions-policy",
+        HeaderValue::from_static("camera=(), microphone=(), geolocation=()"),
+    );
 }
 
 /// 创建前端静态文件路由
-pub fn create_web_router() -> Router {
-    Router::new()
+///
+/// - `webUiDir` 配置后从外部目录读取前端资源，否则使用嵌入的资源
+/// - `webSecurityHeaders` 启用时为所有前端响应添加 CSP 等安全响应头
+/// - `basePath` / `adminPath` 配置后改写 index.html 中的资源地址与 API 地址；
+///   `expose_admin_path` 为 false 时（公共端口）不注入 Admin API 路径，避免泄露自定义的 `adminPath`
+pub fn create_web_router(config: &Config, expose_admin_path: bool) -> Router {
+    let source = match &config.web_ui_dir {
+        Some(dir) => AssetSource::Directory(Arc::new(PathBuf::from(dir))),
+        None => AssetSource::Embedded,
+    };
+    let state = WebState {
+        source,
+        base_path: config.base_path().into(),
+        admin_path: expose_admin_path.then(|| config.admin_path().into()),
+    };
+
+    let router = Router::new()
         .route("/", get(serve_index))
         .route("/{*path}", get(serve_static))
+        .with_state(state);
+
+    if !config.web_security_headers {
+        return router;
+    }
+
+    let csp = match config.web_content_security_policy.trim() {
+        "" => None,
+        value => match HeaderValue::from_str(value) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                tracing::warn!("webContentSecurityPolicy 无效，已忽略: {}", e);
+                None
+            }
+        },
+    };
+
+    router.layer(middleware::map_response(move |mut response: Response| {
+        let csp = csp.clone();
+        async move {
+            apply_security_headers(&mut response, csp.as_ref());
+            response
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_source_rejects_traversal() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("dist");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("index.html"), "<html></html>").unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+
+        let source = AssetSource::Directory(Arc::new(root));
+        assert_eq!(source.get("index.html").unwrap().data, b"<html></html>");
+        assert!(source.get("../secret.txt").is_none());
+        assert!(source.get("/etc/passwd").is_none());
+        assert!(source.get("missing.js").is_none());
+    }
+
+    #[test]
+    fn test_etag_revalidation() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.html"), "<html></html>").unwrap();
+        let source = AssetSource::Directory(Arc::new(dir.path().to_path_buf()));
+
+        let response = serve_file(&source, "", None, "index.html", &HeaderMap::new());
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-cache");
+        let etag = response.headers()[header::ETAG].clone();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag.clone());
+        let response = serve_file(&source, "", None, "index.html", &headers);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag);
+
+        // 内容变化后 ETag 失效
+        std::fs::write(dir.path().join("index.html"), "<html>v2</html>").unwrap();
+        let response = serve_file(&source, "", None, "index.html", &headers);
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_base_path_rewrites_index() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("index.html"),
+            r#"<html><head><script src="/assets/app.js"></script><link href="//cdn.example.com/x.css"></head></html>"#,
+        )
+        .unwrap();
+        let source = AssetSource::Directory(Arc::new(dir.path().to_path_buf()));
+
+        let plain = serve_file(&source, "", None, "index.html", &HeaderMap::new());
+        let prefixed = serve_file(&source, "/kiro", None, "credentials", &HeaderMap::new());
+        assert_ne!(
+            plain.headers()[header::ETAG],
+            prefixed.headers()[header::ETAG]
+        );
+
+        let html = rewrite_index(
+            r#"<html><head><script src="/assets/app.js"></script><link href="./assets/app.css"><link href="//cdn.example.com/x.css"></head></html>"#,
+            "/kiro",
+            None,
+        );
+        assert!(html.contains(r#"<head><meta name="kiro-base-path" content="/kiro">"#));
+        assert!(html.contains(r#"src="/kiro/assets/app.js""#));
+        assert!(html.contains(r#"href="/kiro/assets/app.css""#));
+        assert!(html.contains(r#"href="//cdn.example.com/x.css""#));
+    }
+
+    #[test]
+    fn test_admin_path_injected_into_index() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.html"), "<html><head></head></html>").unwrap();
+        let source = AssetSource::Directory(Arc::new(dir.path().to_path_buf()));
+
+        let response = serve_file(
+            &source,
+            "",
+            Some("/ops-7f3a"),
+            "index.html",
+            &HeaderMap::new(),
+        );
+        let etag = response.headers()[header::ETAG].clone();
+        let html =
+            String::from_utf8(index_asset(&source, "", Some("/ops-7f3a")).unwrap().data).unwrap();
+        assert!(html.contains(r#"<meta name="kiro-admin-path" content="/ops-7f3a">"#));
+
+        // 公共端口不注入 Admin API 路径
+        let html = rewrite_index("<html><head></head></html>", "/kiro", None);
+        assert!(!html.contains("kiro-admin-path"));
+
+        // 默认路径不改写
+        let plain = serve_file(
+            &source,
+            "",
+            Some(DEFAULT_ADMIN_PATH),
+            "index.html",
+            &HeaderMap::new(),
+        );
+        assert_ne!(plain.headers()[header::ETAG], etag);
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        assert!(etag_matches("\"abc\"", "\"abc\""));
+        assert!(etag_matches("W/\"abc\"", "\"abc\""));
+        assert!(etag_matches("\"x\", \"abc\"", "\"abc\""));
+        assert!(etag_matches("*", "\"abc\""));
+        assert!(!etag_matches("\"x\"", "\"abc\""));
+    }
+
+    #[test]
+    fn test_apply_security_headers() {
+        let csp = HeaderValue::from_static("default-src 'self'");
+        let mut response = Response::new(Body::empty());
+        apply_security_headers(&mut response, Some(&csp));
+
+        let headers = response.headers();
+        assert_eq!(
+            headers[header::CONTENT_SECURITY_POLICY],
+            "default-src 'self'"
+        );
+        assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+
+        let mut response = Response::new(Body::empty());
+        apply_security_headers(&mut response, None);
+        assert!(
+            !response
+                .headers()
+                .contains_key(header::CONTENT_SECURITY_POLICY)
+        );
+        assert_eq!(response.headers()[header::X_FRAME_OPTIONS], "DENY");
+    }
 }
@@ -1,16 +1,32 @@
-import { getStoredPassword } from '@/components/PasswordSettingModal'
+import { getStoredAdminPath, getStoredPassword } from '@/components/PasswordSettingModal'
 import type {
   CredentialsResponse,
   AddCredentialRequest,
   AddCredentialResponse,
   SetDisabledRequest,
   SetPriorityRequest,
+  BulkCredentialUpdate,
   BalanceResponse,
+  BalanceRefreshJob,
+  RefreshBalancesRequest,
+  Recommendation,
+  RecommendationsResponse,
+  StatsSummary,
   SuccessResponse,
   ErrorResponse,
+  CredentialEvent,
 } from '@/types/credential'
 
-const API_BASE = '/api/admin'
+// 服务挂载在路径前缀下或自定义了 Admin API 路径时，后端会在 index.html 中注入对应路径
+// （公共端口不注入 Admin API 路径，此时使用设置中填写的路径）
+const BASE_PATH =
+  document.querySelector('meta[name="kiro-base-path"]')?.getAttribute('content') ?? ''
+const INJECTED_ADMIN_PATH =
+  document.querySelector('meta[name="kiro-admin-path"]')?.getAttribute('content') ?? null
+
+function apiBase(): string {
+  return `${BASE_PATH}${INJECTED_ADMIN_PATH ?? getStoredAdminPath() ?? '/api/admin'}`
+}
 
 class ApiError extends Error {
   type: string
@@ -33,7 +49,7 @@ async function request<T>(
     throw new ApiError('authentication_error', '请先设置 API Key', 401)
   }
 
-  const response = await fetch(`${API_BASE}${path}`, {
+  const response = await fetch(`${apiBase()}${path}`, {
     ...options,
     headers: {
       'Content-Type': 'application/json',
@@ -98,6 +114,16 @@ export async function setCredentialPriority(
   })
 }
 
+/** 批量设置禁用状态与优先级（单个事务，用于拖拽排序与批量禁用） */
+export async function bulkUpdateCredentials(
+  updates: BulkCredentialUpdate[]
+): Promise<SuccessResponse> {
+  return request<SuccessResponse>('/credentials/bulk', {
+    method: 'POST',
+    body: JSON.stringify(updates),
+  })
+}
+
 /** 重置失败计数 */
 export async function resetCredentialFailure(
   id: number
@@ -114,4 +140,89 @@ export async function getCredentialBalance(
   return request<BalanceResponse>(`/credentials/${id}/balance`)
 }
 
+/** 批量刷新余额（后台任务） */
+export async function refreshBalances(
+  data: RefreshBalancesRequest = {}
+): Promise<BalanceRefreshJob> {
+  return request<BalanceRefreshJob>('/credentials/refresh-balances', {
+    method: 'POST',
+    body: JSON.stringify(data),
+  })
+}
+
+/** 获取批量刷新余额任务进度 */
+export async function getBalanceRefreshJob(
+  id: number
+): Promise<BalanceRefreshJob> {
+  return request<BalanceRefreshJob>(`/balance-refresh-jobs/${id}`)
+}
+
+/** 获取凭据池调整建议 */
+export async function getRecommendations(days?: number): Promise<RecommendationsResponse> {
+  const query = days ? `?days=${days}` : ''
+  return request<RecommendationsResponse>(`/recommendations${query}`)
+}
+
+/** 获取统计摘要 */
+export async function getStats(): Promise<StatsSummary> {
+  return request<StatsSummary>('/stats')
+}
+
+/** 应用调整建议（执行建议附带的 API 调用） */
+export async function applyRecommendation(
+  recommendation: Recommendation
+): Promise<unknown> {
+  const { method, path, body } = recommendation.action
+  return request<unknown>(path, {
+    method,
+    body: JSON.stringify(body),
+  })
+}
+
+/**
+ * 订阅凭据状态变更（WebSocket），返回取消订阅函数
+ *
+ * 浏览器无法为 WebSocket 设置请求头，API Key 通过子协议传递；连接断开后自动重连
+ */
+export function subscribeCredentialEvents(
+  onEvent: (event: CredentialEvent) => void
+): () => void {
+  let socket: WebSocket | null = null
+  let retryTimer: ReturnType<typeof setTimeout> | undefined
+  let closed = false
+
+  const connect = () => {
+    const apiKey = getStoredPassword()
+    if (!apiKey || closed) return
+
+    const encodedKey = btoa(String.fromCharCode(...new TextEncoder().encode(apiKey)))
+      .replace(/\+/g, '-')
+      .replace(/\//g, '_')
+      .replace(/=+$/, '')
+    const url = new URL(`${apiBase()}/ws`, window.location.href)
+    url.protocol = url.protocol === 'https:' ? 'wss:' : 'ws:'
+
+    socket = new WebSocket(url, ['kiro-admin', `kiro-admin-key.${encodedKey}`])
+    socket.onmessage = (message) => {
+      onEvent(JSON.parse(message.data) as CredentialEvent)
+    }
+    socket.onclose = () => {
+      if (!closed) {
+        // 重连后可能错过了事件，按列表变化处理
+        retryTimer = setTimeout(() => {
+          connect()
+          onEvent({ type: 'credentials_changed' })
+        }, 5000)
+      }
+    }
+  }
+
+  connect()
+  return () => {
+    closed = true
+    clearTimeout(retryTimer)
+    socket?.close()
+  }
+}
+
 export { ApiError }
@@ -10,8 +10,15 @@ import {
   Upload,
   CheckCircle,
   Wallet,
+  Lightbulb,
 } from 'lucide-react'
-import type { Credential, BalanceResponse, AddCredentialRequest } from '@/types/credential'
+import type {
+  Credential,
+  BalanceResponse,
+  AddCredentialRequest,
+  CredentialEvent,
+  Recommendation,
+} from '@/types/credential'
 import {
   getCredentials,
   addCredential,
@@ -19,6 +26,11 @@ import {
   setCredentialDisabled,
   setCredentialPriority,
   getCredentialBalance,
+  refreshBalances,
+  getBalanceRefreshJob,
+  subscribeCredentialEvents,
+  getRecommendations,
+  applyRecommendation,
   ApiError,
 } from '@/api/credentials'
 import { DeleteConfirmModal } from './DeleteConfirmModal'
@@ -30,7 +42,6 @@ import { ImportModal } from './ImportModal'
 export function Dashboard() {
   const [credentials, setCredentials] = useState<Credential[]>([])
   const [total, setTotal] = useState(0)
-  const [available, setAvailable] = useState(0)
   const [loading, setLoading] = useState(true)
   const [error, setError] = useState<string | null>(null)
 
@@ -47,6 +58,9 @@ export function Dashboard() {
 
   const [showPasswordWarning, setShowPasswordWarning] = useState(false)
   const [actionLoading, setActionLoading] = useState<number | null>(null)
+  const [balancesRefreshing, setBalancesRefreshing] = useState(false)
+  const [recommendations, setRecommendations] = useState<Recommendation[]>([])
+  const [applyingRecommendation, setApplyingRecommendation] = useState<string | null>(null)
 
   const fetchCredentials = useCallback(async () => {
     const apiKey = getStoredPassword()
@@ -64,7 +78,10 @@ export function Dashboard() {
       const response = await getCredentials()
       setCredentials(response.credentials)
       setTotal(response.total)
-      setAvailable(response.available)
+      // 调整建议仅作提示，获取失败（如受限 Token 无权限）时不显示
+      getRecommendations()
+        .then((r) => setRecommendations(r.recommendations))
+        .catch(() => setRecommendations([]))
     } catch (e) {
       if (e instanceof ApiError) {
         setError(e.message)
@@ -80,6 +97,95 @@ export function Dashboard() {
     fetchCredentials()
   }, [fetchCredentials])
 
+  // 通过 WebSocket 实时更新账号状态，无需轮询
+  useEffect(() => {
+    if (showPasswordWarning) return
+
+    const applyEvent = (event: CredentialEvent) => {
+      switch (event.type) {
+        case 'status_changed':
+          setCredentials((prev) =>
+            prev.map((c) =>
+              c.id === event.id
+                ? { ...c, disabled: event.disabled, failureCount: event.failureCount }
+                : c
+            )
+          )
+          break
+        case 'current_changed':
+          setCredentials((prev) => prev.map((c) => ({ ...c, isCurrent: c.id === event.id })))
+          break
+        case 'balance_updated':
+          setCredentials((prev) =>
+            prev.map((c) =>
+              c.id === event.id
+                ? {
+                    ...c,
+                    currentUsage: event.currentUsage,
+                    usageLimit: event.usageLimit,
+                    remaining: Math.max(event.usageLimit - event.currentUsage, 0),
+                    usagePercentage:
+                      event.usageLimit > 0
+                        ? Math.min((event.currentUsage / event.usageLimit) * 100, 100)
+                        : 0,
+                  }
+                : c
+            )
+          )
+          break
+        case 'credentials_changed':
+          fetchCredentials()
+          break
+      }
+    }
+
+    return subscribeCredentialEvents(applyEvent)
+  }, [showPasswordWarning, fetchCredentials])
+
+  const available = credentials.filter((c) => !c.disabled).length
+
+  const handleRefreshBalances = async () => {
+    setBalancesRefreshing(true)
+    try {
+      let job = await refreshBalances()
+      while (job.state === 'running') {
+        await new Promise((resolve) => setTimeout(resolve, 1000))
+        job = await getBalanceRefreshJob(job.id)
+      }
+      if (job.failed > 0) {
+        alert(`${job.failed} 个账号余额刷新失败`)
+      }
+      fetchCredentials()
+    } catch (e) {
+      if (e instanceof ApiError) {
+        alert(e.message)
+      }
+    } finally {
+      setBalancesRefreshing(false)
+    }
+  }
+
+  const handleApplyRecommendation = async (recommendation: Recommendation) => {
+    const key = `${recommendation.kind}-${recommendation.credentialId}`
+    setApplyingRecommendation(key)
+    try {
+      await applyRecommendation(recommendation)
+      fetchCredentials()
+    } catch (e) {
+      if (e instanceof ApiError) {
+        alert(e.message)
+      }
+    } finally {
+      setApplyingRecommendation(null)
+    }
+  }
+
+  const recommendationLabels: Record<Recommendation['kind'], string> = {
+    raise_priority: '提升优先级',
+    disable: '禁用',
+    rotate_machine_id: '重新生成机器码',
+  }
+
   const handleDelete = (credential: Credential) => {
     setDeletingCredential(credential)
     setIsDeleteModalOpen(true)
@@ -222,6 +328,14 @@ export function Dashboard() {
           >
             <RefreshCw className={`w-4 h-4 ${loading ? 'animate-spin' : ''}`} />
           </button>
+          <button
+            onClick={handleRefreshBalances}
+            className="btn-ghost text-muted-foreground hover:text-foreground"
+            title="刷新所有账号余额"
+            disabled={balancesRefreshing}
+          >
+            <Wallet className={`w-4 h-4 ${balancesRefreshing ? 'animate-pulse' : ''}`} />
+          </button>
           <button
             onClick={() => setIsPasswordModalOpen(true)}
             className="btn-secondary"
@@ -293,6 +407,41 @@ export function Dashboard() {
         </div>
       </div>
 
+      {recommendations.length > 0 && (
+        <div className="card overflow-hidden mb-8 animate-slide-up">
+          <div className="p-4 md:p-6 border-b border-border flex items-center gap-2">
+            <Lightbulb className="w-4 h-4 text-amber-500" />
+            <h2 className="font-semibold text-lg">调整建议</h2>
+          </div>
+          <ul className="divide-y divide-border">
+            {recommendations.map((recommendation) => {
+              const key = `${recommendation.kind}-${recommendation.credentialId}`
+              const credential = credentials.find((c) => c.id === recommendation.credentialId)
+              return (
+                <li key={key} className="px-4 md:px-6 py-3 flex items-center justify-between gap-4">
+                  <div className="min-w-0">
+                    <p className="text-sm font-medium">
+                      {recommendationLabels[recommendation.kind]}
+                      <span className="ml-2 font-mono text-muted-foreground">
+                        {credential?.email || `#${recommendation.credentialId}`}
+                      </span>
+                    </p>
+                    <p className="text-xs text-muted-foreground truncate">{recommendation.reason}</p>
+                  </div>
+                  <button
+                    onClick={() => handleApplyRecommendation(recommendation)}
+                    className="btn-secondary flex-shrink-0"
+                    disabled={applyingRecommendation === key}
+                  >
+                    应用
+                  </button>
+                </li>
+              )
+            })}
+          </ul>
+        </div>
+      )}
+
       <div className="card overflow-hidden animate-slide-up" style={{ animationDelay: '0.1s' }}>
         <div className="p-4 md:p-6 border-b border-border flex items-center justify-between">
           <h2 className="font-semibold text-lg">账号列表</h2>
@@ -361,10 +510,18 @@ export function Dashboard() {
                           onClick={() => handleToggleDisabled(credential)}
                           disabled={actionLoading === credential.id}
                           className="inline-flex items-center gap-1.5 transition-opacity hover:opacity-80"
-                          title={credential.disabled ? '点击启用' : '点击禁用'}
+                          title={
+                            credential.disabled
+                              ? credential.circuitOpenUntil
+                                ? `熔断至 ${new Date(credential.circuitOpenUntil).toLocaleString()}，点击启用`
+                                : '点击启用'
+                              : '点击禁用'
+                          }
                         >
                           {credential.disabled ? (
-                            <span className="badge-default">禁用</span>
+                            <span className="badge-default">
+                              {credential.circuitState === 'half_open' ? '探测中' : '禁用'}
+                            </span>
                           ) : (
                             <span className="badge-success">启用</span>
                           )}
@@ -19,12 +19,15 @@ export function ImportModal({ isOpen, onClose, onImport }: ImportModalProps) {
     const items: ImportCredential[] = Array.isArray(json) ? json : [json]
 
     return items
-      .filter((item) => item.refreshToken)
+      .filter((item) => item.refreshToken || item.accessKeyId)
       .map((item) => ({
         refreshToken: item.refreshToken,
         authMethod: item.authMethod,
         clientId: item.clientId,
         clientSecret: item.clientSecret,
+        accessKeyId: item.accessKeyId,
+        secretAccessKey: item.secretAccessKey,
+        sessionToken: item.sessionToken,
         machineId: item.machineId,
         priority: item.priority ?? 0,
       }))
@@ -49,7 +52,7 @@ export function ImportModal({ isOpen, onClose, onImport }: ImportModalProps) {
     }
 
     if (credentials.length === 0) {
-      setError('没有找到有效的账号（需要包含 refreshToken 字段）')
+      setError('没有找到有效的账号（需要包含 refreshToken 字段，IAM 凭据需要 accessKeyId 字段）')
       return
     }
 
@@ -123,7 +126,7 @@ export function ImportModal({ isOpen, onClose, onImport }: ImportModalProps) {
               disabled={importing}
             />
             <p className="text-xs text-muted-foreground mt-2">
-              支持 credentials.json 格式，必须包含 refreshToken 字段。其他字段（accessToken、expiresAt 等）会被忽略。
+              支持 credentials.json 格式，必须包含 refreshToken 字段（authMethod 为 iam 的凭据改为 accessKeyId/secretAccessKey）。其他字段（accessToken、expiresAt 等）会被忽略。
             </p>
           </div>
 
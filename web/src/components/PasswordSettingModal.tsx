@@ -2,6 +2,7 @@ import { useState, useEffect, type FormEvent } from 'react'
 import { X, Eye, EyeOff, Key } from 'lucide-react'
 
 const STORAGE_KEY = 'kiro_admin_password'
+const ADMIN_PATH_STORAGE_KEY = 'kiro_admin_path'
 
 interface PasswordSettingModalProps {
   isOpen: boolean
@@ -17,8 +18,22 @@ export function setStoredPassword(password: string): void {
   localStorage.setItem(STORAGE_KEY, password)
 }
 
+// 自定义了 adminPath 且 Web UI 由公共端口提供时，后端不会注入 Admin API 路径，需手动填写
+export function getStoredAdminPath(): string | null {
+  return localStorage.getItem(ADMIN_PATH_STORAGE_KEY)
+}
+
+export function setStoredAdminPath(path: string): void {
+  if (path) {
+    localStorage.setItem(ADMIN_PATH_STORAGE_KEY, path)
+  } else {
+    localStorage.removeItem(ADMIN_PATH_STORAGE_KEY)
+  }
+}
+
 export function PasswordSettingModal({ isOpen, onClose, onSave }: PasswordSettingModalProps) {
   const [password, setPassword] = useState('')
+  const [adminPath, setAdminPath] = useState('')
   const [showPassword, setShowPassword] = useState(false)
   const [error, setError] = useState('')
 
@@ -27,6 +42,7 @@ export function PasswordSettingModal({ isOpen, onClose, onSave }: PasswordSettin
   useEffect(() => {
     if (isOpen) {
       setPassword('')
+      setAdminPath(getStoredAdminPath() ?? '')
       setError('')
     }
   }, [isOpen])
@@ -45,7 +61,14 @@ export function PasswordSettingModal({ isOpen, onClose, onSave }: PasswordSettin
       return
     }
 
+    const path = adminPath.trim().replace(/\/+$/, '')
+    if (path && !path.startsWith('/')) {
+      setError('Admin API 路径需以 / 开头')
+      return
+    }
+
     setStoredPassword(password)
+    setStoredAdminPath(path)
     onSave()
     onClose()
   }
@@ -101,6 +124,20 @@ export function PasswordSettingModal({ isOpen, onClose, onSave }: PasswordSettin
             </div>
           </div>
 
+          <div>
+            <label htmlFor="admin-path" className="block text-sm font-medium mb-2">
+              Admin API 路径（可选）
+            </label>
+            <input
+              id="admin-path"
+              type="text"
+              value={adminPath}
+              onChange={(e) => setAdminPath(e.target.value)}
+              placeholder="/api/admin"
+              className="input"
+            />
+          </div>
+
           {error && (
             <p className="text-sm text-destructive animate-fade-in">{error}</p>
           )}
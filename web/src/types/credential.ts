@@ -6,7 +6,12 @@ export interface Credential {
   priority: number
   disabled: boolean
   failureCount: number
+  circuitState: 'closed' | 'open' | 'half_open'
+  circuitOpenUntil: string | null
   isCurrent: boolean
+  inFlight: number
+  latencyP95Ms: number | null
+  latencyDemotedUntil: string | null
   expiresAt: string | null
   authMethod: string | null
   hasProfileArn: boolean
@@ -17,8 +22,12 @@ export interface Credential {
   remaining: number
   usagePercentage: number
   nextResetAt: number | null
+  balanceUpdatedAt: string | null
   machineId: string | null
   email: string | null
+  kiroVersion: string | null
+  systemVersion: string | null
+  nodeVersion: string | null
 }
 
 /** 账号列表响应 */
@@ -31,12 +40,18 @@ export interface CredentialsResponse {
 
 /** 添加账号请求 */
 export interface AddCredentialRequest {
-  refreshToken: string
+  refreshToken?: string // authMethod 为 iam 时不需要
   authMethod?: string
   clientId?: string
   clientSecret?: string
+  accessKeyId?: string // IAM 凭据
+  secretAccessKey?: string
+  sessionToken?: string
   machineId?: string // UUID v4 格式，36 字符
   priority?: number
+  kiroVersion?: string
+  systemVersion?: string
+  nodeVersion?: string
 }
 
 /** 添加账号响应 */
@@ -49,6 +64,88 @@ export interface AddCredentialResponse {
 /** 设置禁用状态请求 */
 export interface SetDisabledRequest {
   disabled: boolean
+  drain?: boolean
+  timeoutSecs?: number
+}
+
+/** 排空任务 */
+export interface DrainJob {
+  id: number
+  credentialId: number
+  action: 'disable' | 'delete'
+  state: 'draining' | 'completed' | 'timedOut' | 'failed'
+  inFlight: number
+  startedAt: string
+  finishedAt?: string
+  error?: string
+}
+
+/** 批量刷新余额请求 */
+export interface RefreshBalancesRequest {
+  ids?: number[]
+  disabled?: boolean
+  concurrency?: number
+}
+
+/** 批量刷新余额任务 */
+export interface BalanceRefreshJob {
+  id: number
+  state: 'running' | 'completed'
+  total: number
+  succeeded: number
+  failed: number
+  failures: { credentialId: number; error: string }[]
+  startedAt: string
+  finishedAt?: string
+}
+
+/** 凭据池调整建议 */
+export interface Recommendation {
+  kind: 'raise_priority' | 'disable' | 'rotate_machine_id'
+  credentialId: number
+  reason: string
+  /** 一键应用需要执行的 Admin API 调用（路径相对 Admin API） */
+  action: {
+    method: string
+    path: string
+    body: unknown
+  }
+}
+
+/** 调整建议响应 */
+export interface RecommendationsResponse {
+  generatedAt: string
+  windowDays: number
+  recommendations: Recommendation[]
+}
+
+/** 一组请求的用量汇总 */
+export interface UsageTotals {
+  requests: number
+  errors: number
+  inputTokens: number
+  outputTokens: number
+  avgLatencyMs: number
+}
+
+/** 统计摘要（GET /stats） */
+export interface StatsSummary {
+  refreshedAt: string
+  credentialsTotal: number
+  credentialsAvailable: number
+  requestsTotal: number
+  requestsLastHour: number
+  errorsLastHour: number
+  avgLatencyMsLastHour: number
+  modelsLastHour: { model: string; requests: number; errors: number }[]
+  /** 今日（UTC 零点起）统计 */
+  today: UsageTotals & {
+    since: string
+    successRate: number
+    models: (UsageTotals & { model: string })[]
+    credentials: (UsageTotals & { credentialId: number | null; share: number })[]
+    hourly: { hour: string; requests: number; errors: number; outputTokens: number }[]
+  }
 }
 
 /** 设置优先级请求 */
@@ -56,6 +153,13 @@ export interface SetPriorityRequest {
   priority: number
 }
 
+/** 批量更新凭据请求项（省略的字段保持不变） */
+export interface BulkCredentialUpdate {
+  id: number
+  disabled?: boolean
+  priority?: number
+}
+
 /** 余额响应 */
 export interface BalanceResponse {
   id: number
@@ -67,6 +171,13 @@ export interface BalanceResponse {
   nextResetAt: number | null
 }
 
+/** 凭据状态变更事件（WebSocket 推送） */
+export type CredentialEvent =
+  | { type: 'status_changed'; id: number; disabled: boolean; failureCount: number }
+  | { type: 'current_changed'; id: number }
+  | { type: 'balance_updated'; id: number; currentUsage: number; usageLimit: number }
+  | { type: 'credentials_changed' }
+
 /** 通用成功响应 */
 export interface SuccessResponse {
   success: boolean
@@ -85,11 +196,14 @@ export interface ErrorResponse {
 export interface ImportCredential {
   id?: number
   accessToken?: string
-  refreshToken: string
+  refreshToken?: string
   expiresAt?: string
   authMethod?: string
   clientId?: string
   clientSecret?: string
+  accessKeyId?: string
+  secretAccessKey?: string
+  sessionToken?: string
   machineId?: string
   profileArn?: string
   priority?: number
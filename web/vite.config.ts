@@ -5,6 +5,8 @@ import path from 'path'
 
 // https://vite.dev/config/
 export default defineConfig({
+  // 相对资源地址，部署在路径前缀或自定义 Admin 路径下时无需重新构建
+  base: './',
   plugins: [react(), tailwindcss()],
   resolve: {
     alias: {